@@ -36,6 +36,20 @@ export interface SubsetResponse {
   download_url: string;
 }
 
+export interface BatchItem<T> {
+  index: number;
+  /** What the single endpoint would have answered. */
+  status: number;
+  result?: T;
+  error?: string;
+}
+
+export interface BatchResponse<T> {
+  succeeded: number;
+  failed: number;
+  items: BatchItem<T>[];
+}
+
 export interface FontCatalogEntry {
   id: string;
   family: string;
@@ -45,6 +59,38 @@ export interface FontCatalogEntry {
   glyph_count: number;
   unicode_ranges: string[];
   license: string;
+  /** Generated files are only served through signed, expiring URLs. */
+  private?: boolean;
+}
+
+export interface SignUrlRequest {
+  /** A `download_url` of the font. */
+  url: string;
+  /** Lifetime in seconds. */
+  expires_in?: number;
+}
+
+export interface SignedUrl {
+  url: string;
+  /** Unix seconds. */
+  expires_at: number;
+}
+
+export interface CatalogQuery {
+  family?: string;
+  license?: string;
+  format?: string;
+  max_size_kb?: number;
+  page?: number;
+  per_page?: number;
+}
+
+export interface CatalogPage {
+  items: FontCatalogEntry[];
+  total: number;
+  page: number;
+  per_page: number;
+  pages: number;
 }
 
 export interface AnalyzeRequest {
@@ -56,14 +102,36 @@ export interface AnalyzeResponse {
   glyph_count: number;
   format: string;
   size_kb: number;
+  tables: string[];
   unicode_ranges: string[];
   has_variable_axes: boolean;
+  variation_axes: { tag: string; name?: string; min: number; default: number; max: number; hidden?: boolean }[];
+  named_instances?: { name: string; postscript_name?: string; coordinates: Record<string, number> }[];
+  axis_values?: {
+    name: string;
+    location: Record<string, number>;
+    range?: [number, number];
+    linked_value?: number;
+    elidable?: boolean;
+  }[];
+  has_math_table: boolean;
   color_palettes: number;
   opentype_features: string[];
 }
 
 // ── FontClient ─────────────────────────────────────────────────────────────
 
+export interface GraphQLRequest {
+  query: string;
+  variables?: Record<string, unknown>;
+  operationName?: string;
+}
+
+export interface GraphQLResponse<T> {
+  data?: T;
+  errors?: { message: string }[];
+}
+
 export class FontClient {
   private readonly baseUrl: string;
 
@@ -112,9 +180,52 @@ export class FontClient {
     );
   }
 
-  /** List all fonts available in the CDN catalog. */
-  catalog(): Promise<FontCatalogEntry[]> {
-    return this.get<FontCatalogEntry[]>("/api/v1/font/catalog");
+  /** Compress several fonts at once; items fail independently. */
+  compressBatch(
+    reqs: CompressRequest[],
+  ): Promise<BatchResponse<CompressResponse>> {
+    return this.post<CompressRequest[], BatchResponse<CompressResponse>>(
+      "/api/v1/font/compress/batch",
+      reqs,
+    );
+  }
+
+  /** Generate several subsets at once; items fail independently. */
+  subsetBatch(reqs: SubsetRequest[]): Promise<BatchResponse<SubsetResponse>> {
+    return this.post<SubsetRequest[], BatchResponse<SubsetResponse>>(
+      "/api/v1/font/subset/batch",
+      reqs,
+    );
+  }
+
+  /** List fonts in the CDN catalog, a filtered page at a time. */
+  catalog(query: CatalogQuery = {}): Promise<CatalogPage> {
+    const params = new URLSearchParams();
+    for (const [key, value] of Object.entries(query)) {
+      if (value !== undefined) params.set(key, String(value));
+    }
+    const qs = params.toString();
+    return this.get<CatalogPage>(`/api/v1/font/catalog${qs ? `?${qs}` : ""}`);
+  }
+
+  /** Query the catalog with GraphQL; the schema is at GET /api/v1/graphql. */
+  async graphql<T>(
+    query: string,
+    variables?: Record<string, unknown>,
+  ): Promise<T> {
+    const res = await this.post<GraphQLRequest, GraphQLResponse<T>>(
+      "/api/v1/graphql",
+      { query, variables },
+    );
+    return res.data as T;
+  }
+
+  /** Mint a signed, expiring URL for a generated file of a private font. */
+  signUrl(id: string, req: SignUrlRequest): Promise<SignedUrl> {
+    return this.post<SignUrlRequest, SignedUrl>(
+      `/api/v1/font/${encodeURIComponent(id)}/sign-url`,
+      req,
+    );
   }
 
   /** Analyze a font: glyph count, format, size, OpenType features. */
@@ -0,0 +1,263 @@
+//! Gateway configuration: environment defaults, an optional TOML file named by
+//! `GATEWAY_CONFIG`, and the diffing used when the file is reloaded at runtime.
+
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+fn env(k: &str, d: &str) -> String {
+    std::env::var(k).unwrap_or_else(|_| d.into())
+}
+
+fn env_num<T: std::str::FromStr>(k: &str, d: T) -> T {
+    std::env::var(k).ok().and_then(|v| v.parse().ok()).unwrap_or(d)
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct RateLimit {
+    pub burst: f64,
+    pub per_hour: f64,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self { burst: env_num("RATE_LIMIT_BURST", 100_000.0), per_hour: env_num("RATE_LIMIT_PER_HOUR", 100_000.0) }
+    }
+}
+
+/// Per-tenant (JWT `sub`) settings that override the global ones.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct TenantOverride {
+    pub rate_limit: Option<RateLimit>,
+    pub cors_origins: Option<Vec<String>>,
+}
+
+/// Alternate upstream (e.g. an engine running a new encoder) that receives
+/// requests carrying `header`, plus a sticky `percent` of other callers.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Canary {
+    pub upstream: String,
+    #[serde(default = "default_canary_header")]
+    pub header: String,
+    #[serde(default)]
+    pub percent: f64,
+}
+
+fn default_canary_header() -> String {
+    "x-canary".into()
+}
+
+impl Canary {
+    fn from_env() -> Option<Self> {
+        let upstream = std::env::var("CANARY_URL").ok().filter(|u| !u.is_empty())?;
+        Some(Self { upstream, header: env("CANARY_HEADER", "x-canary"), percent: env_num("CANARY_PERCENT", 0.0) })
+    }
+
+    /// An explicit header wins (`0`/`false` opt out); otherwise callers are
+    /// bucketed by a stable hash so each one stays on the same lane.
+    pub fn selects(&self, headers: &HeaderMap, caller: &str) -> bool {
+        if let Some(v) = headers.get(self.header.as_str()) {
+            return !matches!(v.to_str(), Ok("0") | Ok("false"));
+        }
+        if self.percent <= 0.0 { return false; }
+        let mut h = DefaultHasher::new();
+        caller.hash(&mut h);
+        ((h.finish() % 10_000) as f64) < self.percent * 100.0
+    }
+}
+
+/// Public demo mode: callers without credentials are admitted as `tenant`,
+/// rate-limited per client address, and kept to small JSON requests outside
+/// the admin API (no font uploads, nothing deleted). Needs `GATEWAY_SECRET`
+/// so the engine believes the tenant.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct Sandbox {
+    pub tenant: String,
+    pub rate_limit: RateLimit,
+    pub max_body_bytes: u64,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            tenant: env("SANDBOX_TENANT", "sandbox"),
+            rate_limit: RateLimit {
+                burst: env_num("SANDBOX_RATE_LIMIT_BURST", 20.0),
+                per_hour: env_num("SANDBOX_RATE_LIMIT_PER_HOUR", 200.0),
+            },
+            max_body_bytes: env_num("SANDBOX_MAX_BODY_BYTES", 64 * 1024),
+        }
+    }
+}
+
+impl Sandbox {
+    fn from_env() -> Option<Self> {
+        matches!(env("SANDBOX_MODE", "off").as_str(), "on" | "true" | "1").then(Self::default)
+    }
+
+    /// Why a sandbox request is refused, if it is.
+    pub fn refuses(&self, method: &axum::http::Method, path: &str, headers: &HeaderMap) -> Option<String> {
+        if path.starts_with("/api/v1/admin") {
+            return Some("the admin API is not available in the sandbox".into());
+        }
+        match *method {
+            axum::http::Method::GET | axum::http::Method::HEAD => return None,
+            axum::http::Method::DELETE => return Some("nothing can be deleted from the sandbox".into()),
+            _ => {}
+        }
+        let json = headers
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|t| t.starts_with("application/json"));
+        // The body's length is checked as it is read, in the auth middleware.
+        (!json).then(|| "font uploads are not available in the sandbox; send JSON requests".into())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct GatewayConfig {
+    // Restart required.
+    pub listen_addr: String,
+    pub core_url: String,
+    pub jwt_secret: String,
+    // Reloadable.
+    pub cors_origins: Vec<String>,
+    pub rate_limit: RateLimit,
+    pub stale_cache_max_age_secs: u64,
+    pub tenants: BTreeMap<String, TenantOverride>,
+    pub canary: Option<Canary>,
+    pub sandbox: Option<Sandbox>,
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        Self {
+            listen_addr: env("GATEWAY_ADDR", "0.0.0.0:8080"),
+            core_url: env("CORE_ENGINE_URL", "http://core-engine:8081"),
+            jwt_secret: env("JWT_SECRET", "dev-secret-change-me"),
+            cors_origins: env("CORS_ORIGINS", "*").split(',').map(|o| o.trim().to_string()).filter(|o| !o.is_empty()).collect(),
+            rate_limit: RateLimit::default(),
+            stale_cache_max_age_secs: env_num("STALE_CACHE_MAX_AGE_SECS", 600),
+            tenants: BTreeMap::new(),
+            canary: Canary::from_env(),
+            sandbox: Sandbox::from_env(),
+        }
+    }
+}
+
+impl GatewayConfig {
+    /// Loads the file named by `GATEWAY_CONFIG` (if any) over the env defaults.
+    pub fn load() -> Result<Self, String> {
+        match std::env::var("GATEWAY_CONFIG") {
+            Ok(path) => {
+                let raw = std::fs::read_to_string(&path).map_err(|e| format!("{path}: {e}"))?;
+                toml::from_str(&raw).map_err(|e| format!("{path}: {e}"))
+            }
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Problems that would make the gateway misbehave, as actionable messages.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.listen_addr.parse::<std::net::SocketAddr>().is_err() {
+            errors.push(format!("listen_addr {:?} is not a socket address like 0.0.0.0:8080", self.listen_addr));
+        }
+        let upstreams = std::iter::once(("core_url", &self.core_url))
+            .chain(self.canary.as_ref().map(|c| ("canary.upstream", &c.upstream)));
+        for (name, url) in upstreams {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                errors.push(format!("{name} {url:?} must be an http(s) URL"));
+            }
+        }
+        if self.jwt_secret == "dev-secret-change-me" {
+            errors.push("jwt_secret is still the development default; set JWT_SECRET".into());
+        }
+        if self.cors_origins.is_empty() {
+            errors.push("cors_origins is empty; browsers will be refused (use \"*\" to allow all)".into());
+        }
+        let limits = std::iter::once(("rate_limit".to_string(), &self.rate_limit))
+            .chain(self.tenants.iter().filter_map(|(id, t)| t.rate_limit.as_ref().map(|r| (format!("tenants.{id}.rate_limit"), r))))
+            .chain(self.sandbox.as_ref().map(|s| ("sandbox.rate_limit".to_string(), &s.rate_limit)));
+        for (name, r) in limits {
+            if !(r.burst > 0.0 && r.per_hour > 0.0) {
+                errors.push(format!("{name}: burst and per_hour must be positive"));
+            }
+        }
+        if let Some(c) = &self.canary {
+            if !(0.0..=100.0).contains(&c.percent) {
+                errors.push(format!("canary.percent {} must be between 0 and 100", c.percent));
+            }
+            if axum::http::HeaderName::from_bytes(c.header.as_bytes()).is_err() {
+                errors.push(format!("canary.header {:?} is not a valid header name", c.header));
+            }
+        }
+        if let Some(s) = &self.sandbox {
+            if s.tenant.is_empty() || axum::http::HeaderValue::from_str(&s.tenant).is_err() {
+                errors.push(format!("sandbox.tenant {:?} must be a non-empty header-safe name", s.tenant));
+            }
+            if self.tenants.contains_key(&s.tenant) {
+                errors.push(format!("sandbox.tenant {:?} collides with a configured tenant", s.tenant));
+            }
+            if std::env::var("GATEWAY_SECRET").map_or(true, |v| v.is_empty()) {
+                errors.push("sandbox needs GATEWAY_SECRET, shared with the engine; no sandbox callers are admitted".into());
+            }
+        }
+        errors
+    }
+
+    pub fn rate_limit_for(&self, sub: &str) -> &RateLimit {
+        self.tenants.get(sub).and_then(|t| t.rate_limit.as_ref()).unwrap_or(&self.rate_limit)
+    }
+
+    /// Whether `tenant` (JWT `sub`) may be called from `origin`: its own
+    /// `cors_origins` when it has them, the global list otherwise.
+    pub fn allows_origin(&self, tenant: &str, origin: &str) -> bool {
+        let list = self.tenants.get(tenant).and_then(|t| t.cors_origins.as_ref()).unwrap_or(&self.cors_origins);
+        list.iter().any(|o| o == "*" || o == origin)
+    }
+
+    /// Whether any tenant may be called from `origin`. Preflights carry no
+    /// credentials, so this is all the CORS layer can check; the owning
+    /// tenant's list is enforced once the caller is authenticated.
+    pub fn admits_origin(&self, origin: &str) -> bool {
+        let tenant_lists = self.tenants.values().filter_map(|t| t.cors_origins.as_ref());
+        std::iter::once(&self.cors_origins)
+            .chain(tenant_lists)
+            .flatten()
+            .any(|o| o == "*" || o == origin)
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReloadReport {
+    /// Settings whose new value is now in effect.
+    pub applied: Vec<String>,
+    /// Settings that changed on disk but only take effect after a restart.
+    pub requires_restart: Vec<String>,
+}
+
+/// Compares two configs setting by setting.
+pub fn diff(old: &GatewayConfig, new: &GatewayConfig) -> ReloadReport {
+    let mut r = ReloadReport::default();
+    let mut restart = |name: &str, changed: bool| if changed { r.requires_restart.push(name.into()) };
+    restart("listen_addr", old.listen_addr != new.listen_addr);
+    restart("core_url", old.core_url != new.core_url);
+    restart("jwt_secret", old.jwt_secret != new.jwt_secret);
+    let mut applied = |name: &str, changed: bool| if changed { r.applied.push(name.into()) };
+    applied("cors_origins", old.cors_origins != new.cors_origins);
+    applied("rate_limit", old.rate_limit != new.rate_limit);
+    applied("stale_cache_max_age_secs", old.stale_cache_max_age_secs != new.stale_cache_max_age_secs);
+    applied("canary", old.canary != new.canary);
+    applied("sandbox", old.sandbox != new.sandbox);
+    for id in old.tenants.keys().chain(new.tenants.keys().filter(|k| !old.tenants.contains_key(*k))) {
+        applied(&format!("tenants.{id}"), old.tenants.get(id) != new.tenants.get(id));
+    }
+    r
+}
@@ -1,24 +1,41 @@
+mod config;
+mod resilience;
+#[cfg(unix)]
+mod systemd;
+
 use axum::{
-    body::Body,
-    extract::{Request, State},
-    http::{HeaderMap, StatusCode},
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
     middleware::{self, Next},
     response::{Json, Response},
-    routing::{any, get},
+    routing::{any, get, post},
     Router,
 };
+use config::{GatewayConfig, ReloadReport};
 use dashmap::DashMap;
+use resilience::{CircuitBreaker, RetryPolicy, StaleCache};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use std::time::Instant;
-use tower_http::cors::{Any, CorsLayer};
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 
 struct AppState {
     core_url: String,
     jwt_secret: String,
+    admin_token: Option<String>,
+    /// Sent as `X-Font-Gateway` so the engine believes the tenant header.
+    gateway_secret: Option<HeaderValue>,
+    config: RwLock<Arc<GatewayConfig>>,
     rate_limiters: DashMap<String, TokenBucket>,
     start_time: Instant,
+    http: reqwest::Client,
+    retry: RetryPolicy,
+    breaker: CircuitBreaker,
+    canary_breaker: CircuitBreaker,
+    stale: StaleCache,
 }
 
 struct TokenBucket {
@@ -42,7 +59,7 @@ impl TokenBucket {
 }
 
 #[derive(Serialize)]
-struct Health { status: String, version: String, uptime_secs: u64 }
+struct Health { status: String, version: String, uptime_secs: u64, upstream_circuit: String }
 
 #[derive(Serialize)]
 struct Err { error: String, #[serde(skip_serializing_if = "Option::is_none")] details: Option<String> }
@@ -53,6 +70,66 @@ struct LicenseInfo { license: String, source_code: String, notice: String }
 #[derive(Deserialize, Serialize, Clone)]
 struct Claims { sub: String, email: Option<String>, role: Option<String>, exp: usize }
 
+impl AppState {
+    fn config(&self) -> Arc<GatewayConfig> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Re-reads `GATEWAY_CONFIG` and swaps in the reloadable settings. Settings
+    /// that need a restart keep their running values and are only reported.
+    /// A file that fails [`GatewayConfig::validate`] with problems the running
+    /// configuration does not already have is rejected, keeping the old one.
+    fn reload(&self) -> Result<ReloadReport, String> {
+        let mut new = GatewayConfig::load()?;
+        let mut cur = self.config.write().unwrap();
+        let report = config::diff(&cur, &new);
+        new.listen_addr.clone_from(&cur.listen_addr);
+        new.core_url.clone_from(&cur.core_url);
+        new.jwt_secret.clone_from(&cur.jwt_secret);
+        let known = cur.validate();
+        let errors: Vec<String> = new.validate().into_iter().filter(|e| !known.contains(e)).collect();
+        if !errors.is_empty() {
+            return Err(errors.join("; "));
+        }
+        if report.applied.iter().any(|k| k == "rate_limit" || k == "sandbox" || k.starts_with("tenants.")) {
+            self.rate_limiters.clear();
+        }
+        *cur = Arc::new(new);
+        tracing::info!(applied = ?report.applied, requires_restart = ?report.requires_restart, "configuration reloaded");
+        Ok(report)
+    }
+}
+
+/// `--check-config`: parse and validate the configuration (and with
+/// `--check-upstream`, reach the engines) without serving; nonzero on error.
+async fn check_config(with_upstream: bool) -> i32 {
+    let cfg = match GatewayConfig::load() {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return 1;
+        }
+    };
+    let mut errors = cfg.validate();
+    if with_upstream {
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(5)).build().expect("failed to build HTTP client");
+        for url in std::iter::once(&cfg.core_url).chain(cfg.canary.as_ref().map(|c| &c.upstream)) {
+            match http.get(format!("{url}/health")).send().await {
+                Ok(r) if r.status().is_success() => {}
+                Ok(r) => errors.push(format!("upstream {url}/health returned {}", r.status())),
+                Err(e) => errors.push(format!("upstream {url} unreachable: {e}")),
+            }
+        }
+    }
+    if errors.is_empty() {
+        println!("configuration OK");
+        return 0;
+    }
+    for e in &errors { eprintln!("error: {e}"); }
+    eprintln!("{} configuration error(s)", errors.len());
+    1
+}
+
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt()
@@ -62,30 +139,100 @@ async fn main() {
         )
         .init();
     let env = |k: &str, d: &str| std::env::var(k).unwrap_or_else(|_| d.into());
+    if std::env::args().any(|a| a == "--check-config") {
+        std::process::exit(check_config(std::env::args().any(|a| a == "--check-upstream")).await);
+    }
+    let cfg = GatewayConfig::load().expect("invalid gateway configuration");
+    let addr = cfg.listen_addr.clone();
     let state = Arc::new(AppState {
-        core_url: env("CORE_ENGINE_URL", "http://core-engine:8081"),
-        jwt_secret: env("JWT_SECRET", "dev-secret-change-me"),
+        core_url: cfg.core_url.clone(),
+        jwt_secret: cfg.jwt_secret.clone(),
+        admin_token: std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+        gateway_secret: std::env::var("GATEWAY_SECRET").ok().filter(|t| !t.is_empty())
+            .map(|t| HeaderValue::from_str(&t).expect("GATEWAY_SECRET must be a valid header value")),
+        config: RwLock::new(Arc::new(cfg)),
         rate_limiters: DashMap::new(),
         start_time: Instant::now(),
+        http: reqwest::Client::builder()
+            .timeout(Duration::from_secs(env("UPSTREAM_TIMEOUT_SECS", "30").parse().unwrap_or(30)))
+            .build()
+            .expect("failed to build HTTP client"),
+        retry: RetryPolicy::from_env(),
+        breaker: CircuitBreaker::from_env(),
+        canary_breaker: CircuitBreaker::from_env(),
+        stale: StaleCache::from_env(),
     });
-    let cors = CorsLayer::new().allow_origin(Any).allow_methods(Any).allow_headers(Any);
+    #[cfg(unix)]
+    tokio::spawn(reload_on_sighup(state.clone()));
+    let cors_state = state.clone();
+    let cors = CorsLayer::new()
+        .allow_origin(AllowOrigin::predicate(move |origin, _| {
+            origin.to_str().is_ok_and(|o| cors_state.config().admits_origin(o))
+        }))
+        .allow_methods(Any)
+        .allow_headers(Any);
     let public = Router::new()
         .route("/health", get(health))
-        .route("/license", get(license_handler));
+        .route("/license", get(license_handler))
+        .route("/admin/reload", post(reload_handler))
+        // Compressed font artifacts: content-addressed and public, like any CDN object.
+        .route("/cdn/fonts/*p", get(proxy_core));
     let api = Router::new()
-        .route("/api/v1/{*p}", any(proxy_core))
-        .layer(middleware::from_fn_with_state(state.clone(), auth_mw))
-        .layer(middleware::from_fn_with_state(state.clone(), rate_mw));
+        .route("/api/v1/*p", any(proxy_core))
+        .layer(middleware::from_fn_with_state(state.clone(), origin_mw))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_mw))
+        .layer(middleware::from_fn_with_state(state.clone(), auth_mw));
     let app = Router::new()
         .merge(public)
         .merge(api)
         .layer(cors)
         .layer(TraceLayer::new_for_http())
         .with_state(state);
-    let addr = std::env::var("GATEWAY_ADDR").unwrap_or_else(|_| "0.0.0.0:8080".into());
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    tracing::info!("API Gateway on {addr}");
-    axum::serve(listener, app).await.unwrap();
+    #[cfg(unix)]
+    let activated = systemd::activated_listener();
+    #[cfg(not(unix))]
+    let activated: Option<std::net::TcpListener> = None;
+    let listener = match activated {
+        Some(l) => tokio::net::TcpListener::from_std(l).unwrap(),
+        None => tokio::net::TcpListener::bind(&addr).await.unwrap(),
+    };
+    tracing::info!("API Gateway on {}", listener.local_addr().map_or(addr, |a| a.to_string()));
+    #[cfg(unix)]
+    systemd::notify_ready("serving");
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
+}
+
+#[cfg(unix)]
+async fn reload_on_sighup(s: Arc<AppState>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let Ok(mut hup) = signal(SignalKind::hangup()) else { return };
+    while hup.recv().await.is_some() {
+        if let Err(e) = s.reload() { tracing::error!("configuration reload failed, keeping the running one: {e}"); }
+    }
+}
+
+/// Compares every byte rather than stopping at the first difference, so the
+/// time taken does not tell a caller how much of the token it guessed.
+fn same_token(want: &str, got: &str) -> bool {
+    let (want, got) = (want.as_bytes(), got.as_bytes());
+    let diff = (0..want.len().max(got.len())).fold(want.len() ^ got.len(), |diff, i| {
+        diff | usize::from(want.get(i).unwrap_or(&0) ^ got.get(i).unwrap_or(&0))
+    });
+    std::hint::black_box(diff) == 0
+}
+
+async fn reload_handler(
+    State(s): State<Arc<AppState>>, headers: HeaderMap,
+) -> Result<Json<ReloadReport>, (StatusCode, Json<Err>)> {
+    let token = headers.get("X-Admin-Token").and_then(|h| h.to_str().ok());
+    match (&s.admin_token, token) {
+        (Some(want), Some(got)) if same_token(want, got) => {}
+        _ => return Err((StatusCode::FORBIDDEN, Json(Err { error: "Admin token required".into(), details: None }))),
+    }
+    s.reload().map(Json).map_err(|e| {
+        tracing::error!("configuration reload failed, keeping the running one: {e}");
+        (StatusCode::UNPROCESSABLE_ENTITY, Json(Err { error: "Invalid configuration".into(), details: Some(e) }))
+    })
 }
 
 async fn health(State(s): State<Arc<AppState>>) -> Json<Health> {
@@ -93,6 +240,7 @@ async fn health(State(s): State<Arc<AppState>>) -> Json<Health> {
         status: "ok".into(),
         version: env!("CARGO_PKG_VERSION").into(),
         uptime_secs: s.start_time.elapsed().as_secs(),
+        upstream_circuit: s.breaker.state_name().into(),
     })
 }
 
@@ -129,45 +277,160 @@ async fn auth_mw(
         req.extensions_mut().insert(Claims { sub: "api-key-user".into(), email: None, role: Some("api".into()), exp: usize::MAX });
         return Ok(next.run(req).await);
     }
+    // Without the shared secret the engine would not know these callers are the sandbox, so none are admitted.
+    if let Some(sandbox) = s.config().sandbox.clone().filter(|_| s.gateway_secret.is_some()) {
+        if let Some(reason) = sandbox.refuses(req.method(), req.uri().path(), req.headers()) {
+            return Err((StatusCode::FORBIDDEN, Json(Err { error: "Not available in sandbox".into(), details: Some(reason) })));
+        }
+        // Counted as read, whatever Content-Length claims; sandbox bodies are small JSON.
+        let (parts, body) = req.into_parts();
+        let body = axum::body::to_bytes(body, sandbox.max_body_bytes as usize).await.map_err(|_| {
+            let details = format!("sandbox request bodies are limited to {} bytes", sandbox.max_body_bytes);
+            (StatusCode::PAYLOAD_TOO_LARGE, Json(Err { error: "Not available in sandbox".into(), details: Some(details) }))
+        })?;
+        let mut req = Request::from_parts(parts, Body::from(body));
+        req.extensions_mut().insert(Claims { sub: sandbox.tenant, email: None, role: Some("sandbox".into()), exp: usize::MAX });
+        let mut resp = next.run(req).await;
+        resp.headers_mut().insert("x-font-sandbox", HeaderValue::from_static("1"));
+        return Ok(resp);
+    }
     Err((StatusCode::UNAUTHORIZED, Json(Err { error: "Auth required".into(), details: Some("Provide Bearer token or X-API-Key".into()) })))
 }
 
 async fn rate_mw(
     State(s): State<Arc<AppState>>, req: Request, next: Next,
 ) -> Result<Response, (StatusCode, Json<Err>)> {
-    let uid = req.extensions().get::<Claims>().map(|c| c.sub.clone()).unwrap_or_else(|| "anon".into());
+    let claims = req.extensions().get::<Claims>();
+    let uid = claims.map(|c| c.sub.clone()).unwrap_or_else(|| "anon".into());
+    let cfg = s.config();
+    // Sandbox callers share one tenant, so each client address gets its own bucket.
+    let sandbox = cfg.sandbox.as_ref().filter(|_| claims.is_some_and(|c| c.role.as_deref() == Some("sandbox")));
+    let (uid, lim) = match sandbox {
+        Some(sb) => {
+            let peer = req.extensions().get::<ConnectInfo<SocketAddr>>().map(|c| c.0.ip().to_string()).unwrap_or_default();
+            (format!("{uid}@{peer}"), &sb.rate_limit)
+        }
+        None => (uid.clone(), cfg.rate_limit_for(&uid)),
+    };
     let ok = {
-        let mut e = s.rate_limiters.entry(uid).or_insert_with(|| TokenBucket::new(100000.0, 100000.0 / 3600.0));
+        let mut e = s.rate_limiters.entry(uid).or_insert_with(|| TokenBucket::new(lim.burst, lim.per_hour / 3600.0));
         e.try_consume()
     };
     if !ok { return Err((StatusCode::TOO_MANY_REQUESTS, Json(Err { error: "Rate limit exceeded".into(), details: None }))); }
     Ok(next.run(req).await)
 }
 
-async fn forward(url: &str, req: Request) -> Result<Response, (StatusCode, Json<Err>)> {
-    let client = reqwest::Client::new();
+/// Refuses browser calls from origins the authenticated tenant does not
+/// allow, which the CORS layer could only check against every tenant's list.
+async fn origin_mw(
+    State(s): State<Arc<AppState>>, req: Request, next: Next,
+) -> Result<Response, (StatusCode, Json<Err>)> {
+    let origin = req.headers().get(header::ORIGIN).and_then(|v| v.to_str().ok());
+    let tenant = req.extensions().get::<Claims>().map_or("", |c| c.sub.as_str());
+    if let Some(origin) = origin.filter(|o| !s.config().allows_origin(tenant, o)) {
+        let details = format!("origin {origin} is not allowed for this tenant");
+        return Err((StatusCode::FORBIDDEN, Json(Err { error: "Origin not allowed".into(), details: Some(details) })));
+    }
+    Ok(next.run(req).await)
+}
+
+/// Event streams end with the job they follow; this only bounds one that never does.
+const EVENT_STREAM_TIMEOUT: Duration = Duration::from_secs(24 * 3600);
+
+/// Forwards `req` to the upstream, retrying idempotent requests on transport
+/// errors and 502/503/504 with jittered backoff. While the upstream is failing
+/// (or the breaker is open), GETs fall back to the last good response.
+/// Non-idempotent bodies (font uploads) are never retried, so they are
+/// streamed straight through instead of being buffered here. So are
+/// Server-Sent Events responses (job progress), which last as long as the job.
+async fn forward(s: &AppState, url: &str, breaker: &CircuitBreaker, req: Request) -> Result<Response, (StatusCode, Json<Err>)> {
     let path = req.uri().path().to_owned();
     let q = req.uri().query().map(|q| format!("?{q}")).unwrap_or_default();
     let method = req.method().clone();
     let hdrs = req.headers().clone();
-    let body = axum::body::to_bytes(req.into_body(), 20 * 1024 * 1024).await
-        .map_err(|e| (StatusCode::BAD_REQUEST, Json(Err { error: "Body read fail".into(), details: Some(e.to_string()) })))?;
-    let mut r = client.request(method, format!("{url}{path}{q}"));
-    for (k, v) in hdrs.iter() { if k != "host" { r = r.header(k, v); } }
-    let resp = r.body(body).send().await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, Json(Err { error: "Upstream unavailable".into(), details: Some(e.to_string()) })))?;
-    let st = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
-    let rh = resp.headers().clone();
-    let rb = resp.bytes().await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, Json(Err { error: "Read fail".into(), details: Some(e.to_string()) })))?;
+    // The engine compresses per Accept-Encoding and answers Range with part of a file, so a stale copy is
+    // only valid for the same headers.
+    let encoding = hdrs.get(header::ACCEPT_ENCODING).and_then(|v| v.to_str().ok()).unwrap_or_default().to_owned();
+    let range = hdrs.get(header::RANGE).and_then(|v| v.to_str().ok()).unwrap_or_default().to_owned();
+    // Public routes (CDN artifacts) answer everyone alike, so they share one entry per URL.
+    let owner = req.extensions().get::<Claims>().map_or_else(|| "public".to_owned(), |c| format!("user {}", c.sub));
+    let stale_key = (method == Method::GET).then(|| format!("{owner} {path}{q} {encoding} {range}"));
+    let events = hdrs.get(header::ACCEPT).and_then(|v| v.to_str().ok()).is_some_and(|a| a.contains("text/event-stream"));
+    let idempotent = matches!(method, Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS);
+    let (body, mut streamed) = if idempotent {
+        let b = axum::body::to_bytes(req.into_body(), 20 * 1024 * 1024).await
+            .map_err(|e| (StatusCode::BAD_REQUEST, Json(Err { error: "Body read fail".into(), details: Some(e.to_string()) })))?;
+        (b, None)
+    } else {
+        (Bytes::new(), Some(reqwest::Body::wrap_stream(req.into_body().into_data_stream())))
+    };
+    let stale_age = Duration::from_secs(s.config().stale_cache_max_age_secs);
+    let mut attempt = 0;
+    let failure = loop {
+        if !breaker.allow() { break "circuit breaker open".to_string(); }
+        let mut r = s.http.request(method.clone(), format!("{url}{path}{q}"));
+        for (k, v) in hdrs.iter() { if k != "host" { r = r.header(k, v); } }
+        if events { r = r.timeout(EVENT_STREAM_TIMEOUT); }
+        let rb = streamed.take().unwrap_or_else(|| body.clone().into());
+        let err = match r.body(rb).send().await {
+            Ok(resp) if matches!(resp.status().as_u16(), 502..=504) => format!("upstream returned {}", resp.status()),
+            Ok(resp) => {
+                breaker.on_success();
+                let st = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                let rh = resp.headers().clone();
+                if rh.get(header::CONTENT_TYPE).is_some_and(|v| v.as_bytes().starts_with(b"text/event-stream")) {
+                    return build_response(st, &rh, Body::from_stream(resp.bytes_stream()), false);
+                }
+                let rb = resp.bytes().await
+                    .map_err(|e| (StatusCode::BAD_GATEWAY, Json(Err { error: "Read fail".into(), details: Some(e.to_string()) })))?;
+                if let Some(k) = stale_key.filter(|_| st.is_success()) { s.stale.store(k, st, &rh, &rb, stale_age); }
+                return build_response(st, &rh, rb, false);
+            }
+            Err(e) => e.to_string(),
+        };
+        breaker.on_failure();
+        if !idempotent || attempt >= s.retry.max_retries { break err; }
+        tokio::time::sleep(s.retry.backoff(attempt)).await;
+        attempt += 1;
+    };
+    if let Some(hit) = stale_key.as_deref().and_then(|k| s.stale.get(k, stale_age)) {
+        tracing::warn!(path = %path, reason = %failure, "serving stale response");
+        return build_response(hit.status, &hit.headers, hit.body, true);
+    }
+    Err((StatusCode::BAD_GATEWAY, Json(Err { error: "Upstream unavailable".into(), details: Some(failure) })))
+}
+
+fn build_response(st: StatusCode, rh: &HeaderMap, rb: impl Into<Body>, stale: bool) -> Result<Response, (StatusCode, Json<Err>)> {
     let mut b = Response::builder().status(st);
     for (k, v) in rh.iter() { b = b.header(k, v); }
-    b.body(Body::from(rb))
+    if stale { b = b.header("Warning", "110 - \"Response is Stale\"").header("X-Cache", "STALE"); }
+    b.body(rb.into())
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(Err { error: "Build fail".into(), details: Some(e.to_string()) })))
 }
 
 async fn proxy_core(
-    State(s): State<Arc<AppState>>, req: Request,
+    State(s): State<Arc<AppState>>, mut req: Request,
 ) -> Result<Response, (StatusCode, Json<Err>)> {
-    forward(&s.core_url, req).await
+    let cfg = s.config();
+    let caller = req.extensions().get::<Claims>().map(|c| c.sub.clone()).unwrap_or_default();
+    let (url, breaker, lane) = match cfg.canary.as_ref().filter(|c| c.selects(req.headers(), &caller)) {
+        Some(c) => (c.upstream.as_str(), &s.canary_breaker, "canary"),
+        None => (s.core_url.as_str(), &s.breaker, "primary"),
+    };
+    // Tag the lane on both legs so engine logs and clients can attribute results.
+    let lane = HeaderValue::from_static(lane);
+    req.headers_mut().insert("x-font-lane", lane.clone());
+    // The engine scopes catalogs and flags by tenant; never trust a client-sent value, and vouch for ours.
+    req.headers_mut().remove("x-font-tenant");
+    req.headers_mut().remove("x-font-gateway");
+    if let Some(v) = Some(caller.as_str()).filter(|c| !c.is_empty()).and_then(|c| HeaderValue::from_str(c).ok()) {
+        req.headers_mut().insert("x-font-tenant", v);
+    }
+    if let Some(secret) = &s.gateway_secret {
+        req.headers_mut().insert("x-font-gateway", secret.clone());
+    }
+    tracing::debug!(lane = ?lane, caller = %caller, path = %req.uri().path(), "routing request");
+    let mut resp = forward(&s, url, breaker, req).await?;
+    resp.headers_mut().insert("x-font-lane", lane);
+    Ok(resp)
 }
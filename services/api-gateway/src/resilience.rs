@@ -0,0 +1,152 @@
+//! Resilience primitives for calls to downstream dependencies: retries with
+//! full-jitter backoff, a consecutive-failure circuit breaker, and a bounded
+//! stale-response cache used as a fallback while the upstream is unhealthy.
+
+use axum::body::Bytes;
+use axum::http::{HeaderMap, StatusCode};
+use dashmap::DashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+fn env_u64(k: &str, d: u64) -> u64 {
+    std::env::var(k).ok().and_then(|v| v.parse().ok()).unwrap_or(d)
+}
+
+// ── Retry ──────────────────────────────────────────────────────────────────
+
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            max_retries: env_u64("UPSTREAM_RETRIES", 2) as u32,
+            base_delay: Duration::from_millis(env_u64("UPSTREAM_RETRY_BASE_MS", 50)),
+            max_delay: Duration::from_millis(env_u64("UPSTREAM_RETRY_MAX_MS", 1_000)),
+        }
+    }
+
+    /// Full-jitter exponential backoff for the zero-based retry `attempt`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let cap = self.base_delay.saturating_mul(1 << attempt.min(16)).min(self.max_delay);
+        let roll = RandomState::new().build_hasher().finish() % 1_000;
+        cap.mul_f64(roll as f64 / 1_000.0)
+    }
+}
+
+// ── Circuit breaker ────────────────────────────────────────────────────────
+
+enum BreakerState {
+    Closed { failures: u32 },
+    Open { since: Instant },
+    HalfOpen,
+}
+
+/// Opens after `threshold` consecutive failures, then lets a single probe
+/// through once `cooldown` has elapsed.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn from_env() -> Self {
+        Self {
+            threshold: env_u64("UPSTREAM_BREAKER_THRESHOLD", 5).max(1) as u32,
+            cooldown: Duration::from_secs(env_u64("UPSTREAM_BREAKER_COOLDOWN_SECS", 30)),
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+        }
+    }
+
+    pub fn allow(&self) -> bool {
+        let mut st = self.state.lock().unwrap();
+        match *st {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { since } if since.elapsed() >= self.cooldown => {
+                *st = BreakerState::HalfOpen;
+                true
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen => false,
+        }
+    }
+
+    pub fn on_success(&self) {
+        *self.state.lock().unwrap() = BreakerState::Closed { failures: 0 };
+    }
+
+    pub fn on_failure(&self) {
+        let mut st = self.state.lock().unwrap();
+        *st = match *st {
+            BreakerState::Closed { failures } if failures + 1 < self.threshold => {
+                BreakerState::Closed { failures: failures + 1 }
+            }
+            _ => BreakerState::Open { since: Instant::now() },
+        };
+    }
+
+    pub fn state_name(&self) -> &'static str {
+        match *self.state.lock().unwrap() {
+            BreakerState::Closed { .. } => "closed",
+            BreakerState::Open { .. } => "open",
+            BreakerState::HalfOpen => "half-open",
+        }
+    }
+}
+
+// ── Stale cache ────────────────────────────────────────────────────────────
+
+#[derive(Clone)]
+pub struct StaleEntry {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    stored_at: Instant,
+}
+
+/// Last known-good GET responses, served when the upstream is failing.
+pub struct StaleCache {
+    entries: DashMap<String, StaleEntry>,
+    max_entries: usize,
+    max_body: usize,
+}
+
+impl StaleCache {
+    pub fn from_env() -> Self {
+        Self {
+            entries: DashMap::new(),
+            max_entries: env_u64("STALE_CACHE_MAX_ENTRIES", 1_024) as usize,
+            max_body: env_u64("STALE_CACHE_MAX_BODY_BYTES", 1024 * 1024) as usize,
+        }
+    }
+
+    pub fn store(&self, key: String, status: StatusCode, headers: &HeaderMap, body: &Bytes, max_age: Duration) {
+        if body.len() > self.max_body {
+            return;
+        }
+        if self.entries.len() >= self.max_entries && !self.entries.contains_key(&key) {
+            self.entries.retain(|_, e| e.stored_at.elapsed() < max_age);
+            if self.entries.len() >= self.max_entries {
+                return;
+            }
+        }
+        self.entries.insert(key, StaleEntry {
+            status,
+            headers: headers.clone(),
+            body: body.clone(),
+            stored_at: Instant::now(),
+        });
+    }
+
+    pub fn get(&self, key: &str, max_age: Duration) -> Option<StaleEntry> {
+        self.entries
+            .get(key)
+            .filter(|e| e.stored_at.elapsed() < max_age)
+            .map(|e| e.clone())
+    }
+}
@@ -0,0 +1,6 @@
+-- Persistent font catalog owned by the core engine
+create table if not exists catalog_entries (
+    id text primary key,
+    entry jsonb not null,
+    updated_at timestamptz not null default now()
+);
@@ -0,0 +1,6 @@
+-- Catalog entries awaiting promotion to production
+create table if not exists catalog_staging (
+    id text primary key,
+    entry jsonb not null,
+    staged_at timestamptz not null default now()
+);
@@ -0,0 +1,9 @@
+-- Named, versioned subset specifications shared within a tenant
+create table if not exists subset_profiles (
+    tenant text not null,
+    name text not null,
+    version integer not null,
+    profile jsonb not null,
+    saved_at timestamptz not null default now(),
+    primary key (tenant, name, version)
+);
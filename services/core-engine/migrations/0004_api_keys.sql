@@ -0,0 +1,6 @@
+-- API keys (stored by SHA-256 only) and their scopes
+create table if not exists api_keys (
+    id text primary key,
+    key jsonb not null,
+    created_at timestamptz not null default now()
+);
@@ -0,0 +1,11 @@
+-- Per-font daily usage counters (see src/analytics.rs)
+create table if not exists font_usage (
+    font text not null,
+    day date not null,
+    downloads bigint not null default 0,
+    bytes_served bigint not null default 0,
+    compressions bigint not null default 0,
+    subsets bigint not null default 0,
+    bytes_saved bigint not null default 0,
+    primary key (font, day)
+);
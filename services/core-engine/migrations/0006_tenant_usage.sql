@@ -0,0 +1,14 @@
+-- Per-tenant monthly usage and quotas (see src/quotas.rs)
+create table if not exists tenant_usage (
+    tenant text not null,
+    month text not null,
+    downloads bigint not null default 0,
+    bandwidth_bytes bigint not null default 0,
+    operations bigint not null default 0,
+    primary key (tenant, month)
+);
+
+create table if not exists tenant_quotas (
+    tenant text primary key,
+    quota jsonb not null
+);
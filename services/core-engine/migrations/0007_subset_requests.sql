@@ -0,0 +1,8 @@
+-- Subset requests per request digest and day, for warming (see src/warmup.rs)
+create table if not exists subset_requests (
+    key text not null,
+    day date not null,
+    request jsonb not null,
+    requests bigint not null default 0,
+    primary key (key, day)
+);
@@ -0,0 +1,24 @@
+-- Administrative and mutating operations (see src/audit.rs); append-only
+create table if not exists audit_log (
+    id bigserial primary key,
+    at timestamptz not null default now(),
+    actor text not null,
+    tenant text not null default '',
+    action text not null,
+    target text not null,
+    request_id text not null default '',
+    before jsonb,
+    after jsonb
+);
+
+create index if not exists audit_log_action on audit_log (action, id);
+
+create or replace function audit_log_append_only() returns trigger language plpgsql as $$
+begin
+    raise exception 'audit_log is append-only';
+end
+$$;
+
+drop trigger if exists audit_log_append_only on audit_log;
+create trigger audit_log_append_only before update or delete or truncate on audit_log
+    for each statement execute function audit_log_append_only();
@@ -0,0 +1,25 @@
+-- Background jobs shared by every replica (src/queue.rs with JOB_QUEUE=postgres)
+create table if not exists jobs (
+    id text primary key,
+    seq bigserial not null,
+    kind text not null,
+    tenant text not null default '',
+    status text not null,
+    request jsonb not null,
+    headers jsonb not null default '{}',
+    created_at timestamptz not null default now(),
+    started_at timestamptz,
+    finished_at timestamptz,
+    attempts integer not null default 0,
+    lease_owner text,
+    lease_expires_at timestamptz,
+    progress jsonb,
+    result jsonb,
+    download_url text,
+    error_status integer,
+    error text,
+    callback jsonb
+);
+
+create index if not exists jobs_status on jobs (status, seq);
+create index if not exists jobs_finished on jobs (finished_at) where finished_at is not null;
@@ -0,0 +1,10 @@
+-- Per-font daily requests by output format and by charset (see src/analytics.rs)
+create table if not exists usage_breakdown (
+    font text not null,
+    day date not null,
+    dimension text not null,
+    value text not null,
+    requests bigint not null default 0,
+    bytes bigint not null default 0,
+    primary key (font, day, dimension, value)
+);
@@ -0,0 +1,2 @@
+-- How far an interrupted job got, so its next run resumes there (src/queue.rs)
+alter table jobs add column if not exists checkpoint jsonb;
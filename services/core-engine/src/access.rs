@@ -0,0 +1,104 @@
+//! Access log and request IDs.
+//!
+//! Every request gets an `X-Request-Id` — the caller's when it sent a usable
+//! one, otherwise a fresh UUID — which is passed on to handlers, echoed on
+//! the response and added to error bodies (`request_id` in JSON errors, a
+//! `(request <id>)` suffix on plain-text ones) so a failure can be quoted in
+//! a support ticket. Each request is then written to stdout as one JSON line
+//! with method, path, status, latency, client and response bytes;
+//! `ACCESS_LOG=off` turns that off.
+
+use axum::{
+    body::{self, Body},
+    extract::{ConnectInfo, Request},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::{json, Value};
+use std::{io::Write, net::SocketAddr, sync::OnceLock, time::Instant};
+
+use crate::telemetry;
+
+pub const HEADER: &str = "x-request-id";
+
+/// Error bodies larger than this are passed through untouched.
+const MAX_ERROR_BODY: u64 = 64 * 1024;
+
+fn enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| !std::env::var("ACCESS_LOG").is_ok_and(|v| v == "off" || v == "false" || v == "0"))
+}
+
+/// The caller's ID when it is short, printable ASCII; otherwise a new one.
+fn request_id(request: &Request) -> HeaderValue {
+    request
+        .headers()
+        .get(HEADER)
+        .filter(|v| !v.is_empty() && v.len() <= 128 && v.as_bytes().iter().all(u8::is_ascii_graphic))
+        .cloned()
+        .unwrap_or_else(|| HeaderValue::from_str(&uuid::Uuid::new_v4().to_string()).expect("uuid is a header value"))
+}
+
+pub async fn log(mut request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let id = request_id(&request);
+    request.headers_mut().insert(HEADER, id.clone());
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let client = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|c| c.0.ip().to_string());
+    let forwarded = request.headers().get("x-forwarded-for").and_then(|v| v.to_str().ok()).map(str::to_string);
+
+    let mut response = next.run(request).await;
+    let id = id.to_str().unwrap_or_default().to_string();
+    if response.status().is_client_error() || response.status().is_server_error() {
+        response = tag_error(response, &id).await;
+    }
+    response.headers_mut().insert(HEADER, HeaderValue::from_str(&id).expect("validated request id"));
+
+    if enabled() {
+        let entry = json!({
+            "request_id": id,
+            "method": method,
+            "path": path,
+            "status": response.status().as_u16(),
+            "latency_ms": start.elapsed().as_micros() as f64 / 1000.0,
+            "client": client,
+            "forwarded_for": forwarded,
+            "bytes": telemetry::content_length(&response),
+        });
+        let mut out = std::io::stdout().lock();
+        let _ = writeln!(out, "{entry}");
+    }
+    response
+}
+
+/// Adds the request ID to a buffered plain-text or JSON-object error body.
+async fn tag_error(response: Response, id: &str) -> Response {
+    let content_type = response.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("");
+    let json = content_type.starts_with("application/json");
+    let text = content_type.starts_with("text/plain");
+    let buffered = telemetry::content_length(&response).is_some_and(|n| n <= MAX_ERROR_BODY);
+    if !(json || text) || !buffered {
+        return response;
+    }
+    let (mut parts, original) = response.into_parts();
+    let Ok(bytes) = body::to_bytes(original, MAX_ERROR_BODY as usize).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+    let tagged = if json {
+        match serde_json::from_slice::<Value>(&bytes) {
+            Ok(Value::Object(mut object)) => {
+                object.insert("request_id".to_string(), Value::String(id.to_string()));
+                Some(Value::Object(object).to_string().into_bytes())
+            }
+            _ => None,
+        }
+    } else {
+        let message = String::from_utf8_lossy(&bytes);
+        Some(format!("{} (request {id})", message.trim_end()).into_bytes())
+    };
+    let bytes = tagged.unwrap_or_else(|| bytes.to_vec());
+    parts.headers.insert(header::CONTENT_LENGTH, HeaderValue::from(bytes.len()));
+    Response::from_parts(parts, Body::from(bytes))
+}
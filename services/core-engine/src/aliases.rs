@@ -0,0 +1,180 @@
+//! Catalog aliases: other names for a family or entry.
+//!
+//! `PUT /api/v1/admin/aliases/NotoSansJP` with `{"target": "noto-sans-jp"}`
+//! makes a name stand for a family, or with a catalog ID as `target` for
+//! that entry (legacy IDs after a rename). Names are compared as slugs, so
+//! `NotoSansJP` and `notosansjp` are one alias. Family CSS (see
+//! [`crate::stylesheet`]) and `/cdn/` URLs under an alias serve the
+//! canonical entry as if it had been asked for; in CDN paths the alias is
+//! replaced as the directory and at the start of the file name
+//! (`/cdn/fonts/old-id/v2/old-id.woff2` is `/cdn/fonts/new-id/v2/new-id.woff2`).
+//! With `"redirect": 308` (or `301`) CDN URLs under the alias are answered
+//! with a redirect to the canonical path instead, for files that moved for
+//! good. An alias cannot take the name of a catalog ID or family, and an
+//! entry registered later under its name wins over it. Aliases are kept in
+//! the `aliases` storage namespace (`ARTIFACT_DIR/.aliases` with local
+//! storage). `GET /api/v1/admin/aliases` lists them and `DELETE` removes
+//! one.
+
+use axum::{
+    extract::{Path, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use font_api::Alias;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+use tracing::{info, warn};
+
+use crate::{
+    artifacts, audit,
+    extract::ApiJson,
+    storage::{self, FontStorage},
+    AppState, FontCatalogEntry,
+};
+
+const FILE: &str = "aliases.json";
+const REDIRECTS: &[u16] = &[301, 308];
+
+pub struct Aliases {
+    /// By slug.
+    names: RwLock<BTreeMap<String, Alias>>,
+    storage: Arc<dyn FontStorage>,
+}
+
+/// Whether `slug` is a catalog ID or family.
+fn in_catalog(catalog: &[FontCatalogEntry], slug: &str) -> bool {
+    catalog.iter().any(|e| e.id == slug || artifacts::slug(&e.family) == slug)
+}
+
+impl Aliases {
+    pub async fn load(storage: Arc<dyn FontStorage>) -> Self {
+        let names = match storage.get(FILE).await {
+            Ok(Some(raw)) => serde_json::from_slice(&raw).unwrap_or_else(|e| {
+                warn!("{}: unreadable aliases: {e}", storage.location());
+                BTreeMap::new()
+            }),
+            Ok(None) => BTreeMap::new(),
+            Err(e) => {
+                warn!("{}: cannot read aliases: {e}", storage.location());
+                BTreeMap::new()
+            }
+        };
+        Self { names: RwLock::new(names), storage }
+    }
+
+    /// The alias `name` is, unless a catalog entry took its name.
+    fn get(&self, catalog: &[FontCatalogEntry], name: &str) -> Option<Alias> {
+        let slug = artifacts::slug(name);
+        if in_catalog(catalog, &slug) {
+            return None;
+        }
+        self.names.read().unwrap().get(&slug).cloned()
+    }
+
+    /// The family `name` stands for: an alias's target family, else `name`.
+    pub fn family(&self, catalog: &[FontCatalogEntry], name: &str) -> String {
+        let Some(alias) = self.get(catalog, name) else { return name.to_string() };
+        catalog.iter().find(|e| e.id == alias.target).map_or(alias.target, |e| e.family.clone())
+    }
+
+    /// The canonical `slug` and `file` of a CDN path under an alias, with
+    /// the alias's redirect status.
+    pub fn cdn(&self, catalog: &[FontCatalogEntry], slug: &str, file: &str) -> Option<(String, String, Option<u16>)> {
+        let alias = self.get(catalog, slug)?;
+        let file = match file.strip_prefix(slug) {
+            Some(rest) if rest.starts_with(['.', '-']) => format!("{}{rest}", alias.target),
+            _ => file.to_string(),
+        };
+        Some((alias.target, file, alias.redirect))
+    }
+
+    async fn save(&self) -> Result<(), (StatusCode, String)> {
+        let raw = serde_json::to_vec_pretty(&*self.names.read().unwrap())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        self.storage.put(FILE, raw).await.map_err(storage::error("storing aliases"))
+    }
+}
+
+/// `/cdn/` middleware: redirects requests under a redirecting alias to the
+/// canonical path, query included. Serving aliases is left to the handlers.
+pub async fn redirect(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let mut segments: Vec<String> = request.uri().path().split('/').map(String::from).collect();
+    // `/cdn/fonts/<slug>/...` or `/cdn/<tenant>/fonts/<slug>/...`.
+    let at = if segments.get(2).is_some_and(|s| s == "fonts") { 3 } else { 4 };
+    let moved = match (segments.get(at), segments.last()) {
+        (Some(slug), Some(file)) if at < segments.len() - 1 => {
+            state.aliases.cdn(&state.catalog.read().unwrap(), slug, file)
+        }
+        _ => None,
+    };
+    let Some((slug, file, Some(status))) = moved else { return next.run(request).await };
+    segments[at] = slug;
+    *segments.last_mut().expect("the path has a file") = file;
+    let location = match request.uri().query() {
+        Some(query) => format!("{}?{query}", segments.join("/")),
+        None => segments.join("/"),
+    };
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::PERMANENT_REDIRECT);
+    (status, [(header::LOCATION, location)]).into_response()
+}
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<BTreeMap<String, Alias>>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    Ok(Json(state.aliases.names.read().unwrap().clone()))
+}
+
+pub async fn update(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    ApiJson(mut alias): ApiJson<Alias>,
+) -> Result<Json<Alias>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let slug = artifacts::slug(&name);
+    if slug.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, format!("'{name}' is not a usable alias")));
+    }
+    if let Some(status) = alias.redirect.filter(|s| !REDIRECTS.contains(s)) {
+        return Err((StatusCode::BAD_REQUEST, format!("redirect {status} must be 301 or 308")));
+    }
+    alias.target = artifacts::slug(&alias.target);
+    {
+        let catalog = state.catalog.read().unwrap();
+        if in_catalog(&catalog, &slug) {
+            return Err((StatusCode::CONFLICT, format!("'{slug}' is a catalog ID or family, not an alias")));
+        }
+        if !in_catalog(&catalog, &alias.target) {
+            let message = format!("target '{}' is not a catalog ID or family", alias.target);
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, message));
+        }
+    }
+    let before = state.aliases.names.write().unwrap().insert(slug.clone(), alias.clone());
+    state.aliases.save().await?;
+    audit::record(&state, &headers, "aliases.update", &slug, json!(before), json!(alias)).await;
+    info!(alias = %slug, target = %alias.target, redirect = ?alias.redirect, "alias set");
+    Ok(Json(alias))
+}
+
+pub async fn delete(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let slug = artifacts::slug(&name);
+    let Some(before) = state.aliases.names.write().unwrap().remove(&slug) else {
+        return Err((StatusCode::NOT_FOUND, format!("no alias '{slug}'")));
+    };
+    state.aliases.save().await?;
+    audit::record(&state, &headers, "aliases.delete", &slug, json!(before), Value::Null).await;
+    info!(alias = %slug, "alias removed");
+    Ok(StatusCode::NO_CONTENT)
+}
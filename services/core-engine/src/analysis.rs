@@ -0,0 +1,207 @@
+//! What analysis reads from a font binary.
+//!
+//! Everything comes from the file's own tables via `ttf-parser`: glyph count
+//! (`maxp`), the table directory, Unicode coverage (`cmap`) and variation
+//! sequences (`cmap` format 14), variation axes
+//! and named instances (`fvar`) with the style names of axis values
+//! (`STAT`), the `GSUB`/`GPOS` feature tags; palettes and `COLR` come from
+//! [`crate::color`], bitmap strikes from [`crate::bitmap`].
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use ttf_parser::{stat::AxisValueSubtable, Face, GlyphId, Tag};
+
+use crate::{
+    bitmap::{self, StrikeSummary},
+    cmap::{CharMap, VariationSummary},
+    color::{Colr, Cpal, Palette},
+    fvar::Fvar,
+    name::NameTable,
+    sfnt::Font,
+    unicode,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Axis {
+    pub tag: String,
+    /// The axis name, such as `Weight`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub min: f32,
+    pub default: f32,
+    pub max: f32,
+    /// Flagged to be left out of user interfaces.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+}
+
+/// An `fvar` named instance: a subfamily name and its axis values.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NamedInstance {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub postscript_name: Option<String>,
+    pub coordinates: BTreeMap<String, f32>,
+}
+
+/// A `STAT` axis value: the style name of a value, a range or (format 4) a
+/// combination of axes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AxisValue {
+    pub name: String,
+    /// Axis tag to value; a range's nominal value.
+    pub location: BTreeMap<String, f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<[f32; 2]>,
+    /// The style this one links to, such as Bold for Regular.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linked_value: Option<f32>,
+    /// Left out of composed names, as Regular usually is.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub elidable: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Facts {
+    pub glyph_count: usize,
+    pub tables: Vec<String>,
+    pub unicode_ranges: Vec<String>,
+    pub variation_sequences: Option<VariationSummary>,
+    pub axes: Vec<Axis>,
+    pub instances: Vec<NamedInstance>,
+    pub axis_values: Vec<AxisValue>,
+    pub math: bool,
+    pub palettes: Vec<Palette>,
+    pub colr: Option<Colr>,
+    /// Color glyph formats present: `COLRv0`/`COLRv1`, `SVG`, `sbix`,
+    /// `CBDT`.
+    pub color_formats: Vec<String>,
+    pub strikes: Vec<StrikeSummary>,
+    pub features: Vec<String>,
+}
+
+/// Readable glyph names for reports: the character a glyph maps to, else
+/// its glyph name, else `gid<n>`.
+pub struct GlyphLabels<'a> {
+    face: &'a Face<'a>,
+    characters: BTreeMap<u16, char>,
+}
+
+impl<'a> GlyphLabels<'a> {
+    pub fn new(face: &'a Face<'a>) -> Self {
+        let mut characters = BTreeMap::new();
+        for subtable in face.tables().cmap.iter().flat_map(|c| c.subtables).filter(|s| s.is_unicode()) {
+            subtable.codepoints(|cp| {
+                if let Some((glyph, c)) = char::from_u32(cp).and_then(|c| face.glyph_index(c).map(|g| (g.0, c))) {
+                    characters.entry(glyph).or_insert(c);
+                }
+            });
+        }
+        Self { face, characters }
+    }
+
+    pub fn get(&self, glyph: u16) -> String {
+        match self.characters.get(&glyph) {
+            Some(c) if !c.is_control() && !c.is_whitespace() => c.to_string(),
+            _ => self.face.glyph_name(GlyphId(glyph)).map_or_else(|| format!("gid{glyph}"), str::to_string),
+        }
+    }
+}
+
+pub fn facts(data: &[u8]) -> Result<Facts, String> {
+    let face = Face::parse(data, 0).map_err(|e| format!("not a parseable font: {e}"))?;
+    let tables = face.raw_face().table_records.into_iter().map(|r| r.tag.to_string()).collect();
+    let raw = |tag: &[u8; 4]| face.raw_face().table(Tag::from_bytes(tag));
+
+    let mut code_points = Vec::new();
+    for subtable in face.tables().cmap.iter().flat_map(|c| c.subtables).filter(|s| s.is_unicode()) {
+        subtable.codepoints(|cp| code_points.push(cp..=cp));
+    }
+    let unicode_ranges = unicode::merge(code_points).iter().map(unicode::format_range).collect();
+
+    let names = raw(b"name").and_then(|t| NameTable::parse(t).ok());
+    let name = |id: u16| names.as_ref().and_then(|n| n.get(id));
+    let axes = face
+        .variation_axes()
+        .into_iter()
+        .map(|a| Axis {
+            tag: a.tag.to_string(),
+            name: name(a.name_id),
+            min: a.min_value,
+            default: a.def_value,
+            max: a.max_value,
+            hidden: a.hidden,
+        })
+        .collect();
+    let instances = raw(b"fvar").and_then(|t| Fvar::parse(t).ok()).map_or_else(Vec::new, |fvar| {
+        let tags: Vec<&str> = fvar.axes.iter().map(|a| a.tag.as_str()).collect();
+        fvar.instances
+            .iter()
+            .map(|i| NamedInstance {
+                name: name(i.subfamily_name_id).unwrap_or_else(|| format!("name {}", i.subfamily_name_id)),
+                postscript_name: i.postscript_name_id.and_then(name),
+                coordinates: tags.iter().map(|t| t.to_string()).zip(i.coordinates.iter().map(|&c| c as f32)).collect(),
+            })
+            .collect()
+    });
+    let axis_values = face.tables().stat.map_or_else(Vec::new, |stat| {
+        let tag = |index: u16| stat.axes.get(index).map(|a| a.tag.to_string());
+        stat.subtables()
+            .filter_map(|subtable| {
+                let single = |index, value: f32| tag(index).map(|t| BTreeMap::from([(t, value)]));
+                let (location, range, linked_value) = match subtable {
+                    AxisValueSubtable::Format1(v) => (single(v.axis_index, v.value.0)?, None, None),
+                    AxisValueSubtable::Format2(v) => (
+                        single(v.axis_index, v.nominal_value.0)?,
+                        Some([v.range_min_value.0, v.range_max_value.0]),
+                        None,
+                    ),
+                    AxisValueSubtable::Format3(v) => {
+                        (single(v.axis_index, v.value.0)?, None, Some(v.linked_value.0))
+                    }
+                    AxisValueSubtable::Format4(v) => {
+                        let location = v.values.into_iter().filter_map(|a| Some((tag(a.axis_index)?, a.value.0)));
+                        (location.collect(), None, None)
+                    }
+                };
+                let name = name(subtable.name_id()).unwrap_or_else(|| format!("name {}", subtable.name_id()));
+                Some(AxisValue { name, location, range, linked_value, elidable: subtable.is_elidable() })
+            })
+            .collect()
+    });
+
+    let mut features: Vec<String> = Vec::new();
+    for layout in [face.tables().gsub, face.tables().gpos].into_iter().flatten() {
+        for feature in layout.features {
+            let tag = feature.tag.to_string();
+            if !features.contains(&tag) {
+                features.push(tag);
+            }
+        }
+    }
+
+    let colr = raw(b"COLR").and_then(Colr::parse);
+    let mut color_formats: Vec<String> = colr.iter().map(|c| format!("COLRv{}", c.version)).collect();
+    for tag in [b"SVG ", b"sbix", b"CBDT"] {
+        if raw(tag).is_some() {
+            color_formats.push(String::from_utf8_lossy(tag).trim_end().to_string());
+        }
+    }
+    let strikes = Font::parse(data).map(|font| bitmap::summary(&font)).unwrap_or_default();
+
+    Ok(Facts {
+        glyph_count: face.number_of_glyphs() as usize,
+        tables,
+        unicode_ranges,
+        variation_sequences: raw(b"cmap").and_then(|t| CharMap::parse(t).ok()).and_then(|c| c.variation_summary()),
+        axes,
+        instances,
+        axis_values,
+        math: face.tables().math.is_some(),
+        palettes: raw(b"CPAL").and_then(|t| Cpal::parse(t).ok()).map_or_else(Vec::new, |c| c.palettes()),
+        colr,
+        color_formats,
+        strikes,
+        features,
+    })
+}
@@ -0,0 +1,531 @@
+//! Per-font usage analytics.
+//!
+//! Downloads and bytes served from `/cdn/fonts/`, and compress and subset
+//! requests with the bytes their output saves against the source font, are
+//! counted per font (its URL slug) per UTC day. Counts build up in memory
+//! and are added to the `font_usage` table every `ANALYTICS_FLUSH_SECS`
+//! (default 60) when `DATABASE_URL` is set; without a database they last as
+//! long as the process. Reports combine both.
+//!
+//! Subset requests for shared catalog fonts are also counted per distinct
+//! request and day in `subset_requests`; the most requested are generated
+//! ahead of time (see [`crate::warmup`]).
+//!
+//! Each font's requests are also broken down per day by output format
+//! (downloads, compress and subset output) and by charset (subset requests:
+//! the preset, else the `unicode_range` asked for, else `custom`), in
+//! `usage_breakdown`. `GET /api/v1/font/catalog/:id/stats` reports a
+//! catalog entry's totals and top formats and charsets over one or more
+//! windows of days.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::SubsetRequest;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    ops::AddAssign,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::warn;
+
+use crate::{artifacts, db, storage, AppState};
+
+const MAX_DAYS: u64 = 366;
+/// Distinct subset requests kept between flushes; others wait for room.
+const MAX_PENDING_REQUESTS: usize = 10_000;
+/// Distinct breakdown values kept between flushes; new ones past this are
+/// not counted.
+const MAX_PENDING_BREAKDOWN: usize = 10_000;
+/// Longest `unicode_range` kept as a charset name.
+const MAX_CHARSET_CHARS: usize = 100;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct Usage {
+    pub downloads: u64,
+    pub bytes_served: u64,
+    pub compressions: u64,
+    pub subsets: u64,
+    /// Source size minus output size, summed over compress and subset
+    /// requests.
+    pub bytes_saved: u64,
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.downloads += other.downloads;
+        self.bytes_served += other.bytes_served;
+        self.compressions += other.compressions;
+        self.subsets += other.subsets;
+        self.bytes_saved += other.bytes_saved;
+    }
+}
+
+/// Requests and bytes (served, or generated) for one breakdown value.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct Tally {
+    pub requests: u64,
+    pub bytes: u64,
+}
+
+impl AddAssign for Tally {
+    fn add_assign(&mut self, other: Self) {
+        self.requests += other.requests;
+        self.bytes += other.bytes;
+    }
+}
+
+/// Font, day, dimension (`format` or `charset`) and value.
+pub type Breakdown = (String, String, String, String);
+
+/// The charset a subset request is counted under.
+pub fn charset(request: &SubsetRequest) -> String {
+    match &request.preset {
+        Some(preset) => preset.clone(),
+        None if request.characters.is_empty() && !request.unicode_range.trim().is_empty() => {
+            let ranges: Vec<&str> = request.unicode_range.split(',').map(str::trim).collect();
+            ranges.join(",").chars().take(MAX_CHARSET_CHARS).collect()
+        }
+        None => "custom".to_string(),
+    }
+}
+
+/// `YYYY-MM-DD`, `days_ago` days before today (UTC).
+fn day(days_ago: u64) -> String {
+    let today = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) / 86_400;
+    let (year, month, day) = storage::civil_date(today.saturating_sub(days_ago));
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// A subset request and how often it was made.
+pub type Requested = (SubsetRequest, u64);
+
+/// Counters keyed by font and day that have not reached the database yet.
+#[derive(Default)]
+pub struct Analytics {
+    pending: Mutex<BTreeMap<(String, String), Usage>>,
+    /// Subset requests by digest and day, with their count.
+    requests: Mutex<BTreeMap<(String, String), Requested>>,
+    breakdown: Mutex<BTreeMap<Breakdown, Tally>>,
+}
+
+impl Analytics {
+    fn add(&self, font: &str, usage: Usage) {
+        let key = (artifacts::slug(font), day(0));
+        *self.pending.lock().unwrap().entry(key).or_default() += usage;
+    }
+
+    fn tally(&self, font: &str, dimension: &str, value: &str, bytes: u64) {
+        let key = (artifacts::slug(font), day(0), dimension.to_string(), value.to_string());
+        let mut breakdown = self.breakdown.lock().unwrap();
+        if let Some(tally) = breakdown.get_mut(&key) {
+            *tally += Tally { requests: 1, bytes };
+        } else if breakdown.len() < MAX_PENDING_BREAKDOWN {
+            breakdown.insert(key, Tally { requests: 1, bytes });
+        }
+    }
+
+    pub fn record_download(&self, slug: &str, format: &str, bytes: u64) {
+        self.add(slug, Usage { downloads: 1, bytes_served: bytes, ..Usage::default() });
+        self.tally(slug, "format", format, bytes);
+    }
+
+    pub fn record_compress(&self, font: &str, format: &str, original: usize, output: usize) {
+        let bytes_saved = original.saturating_sub(output) as u64;
+        self.add(font, Usage { compressions: 1, bytes_saved, ..Usage::default() });
+        self.tally(font, "format", format, output as u64);
+    }
+
+    pub fn record_subset(&self, font: &str, format: &str, charset: &str, original: usize, output: usize) {
+        let bytes_saved = original.saturating_sub(output) as u64;
+        self.add(font, Usage { subsets: 1, bytes_saved, ..Usage::default() });
+        self.tally(font, "format", format, output as u64);
+        self.tally(font, "charset", charset, output as u64);
+    }
+
+    /// Counts a subset request, keyed by its JSON so counts carry over
+    /// purges and new versions of the font.
+    pub fn record_subset_request(&self, request: &SubsetRequest) {
+        let key = format!("{:x}", Sha256::digest(serde_json::to_vec(request).unwrap_or_default()));
+        let mut requests = self.requests.lock().unwrap();
+        let day = (key, day(0));
+        if let Some((_, count)) = requests.get_mut(&day) {
+            *count += 1;
+        } else if requests.len() < MAX_PENDING_REQUESTS {
+            requests.insert(day, (request.clone(), 1));
+        }
+    }
+
+    /// The `limit` subset requests made most often over the last `days`
+    /// days, most requested first.
+    pub async fn popular_subsets(
+        &self,
+        pool: Option<&sqlx::PgPool>,
+        days: u64,
+        limit: usize,
+    ) -> Result<Vec<Requested>, sqlx::Error> {
+        let since = day(days.saturating_sub(1));
+        let mut totals: BTreeMap<String, Requested> = match pool {
+            Some(pool) => db::popular_subsets(pool, &since)
+                .await?
+                .into_iter()
+                .map(|(key, request, count)| (key, (request, count)))
+                .collect(),
+            None => BTreeMap::new(),
+        };
+        let mut requests = self.requests.lock().unwrap();
+        // Without a database nothing else drops old days.
+        requests.retain(|(_, d), _| pool.is_some() || *d >= since);
+        for ((key, d), (request, count)) in requests.iter() {
+            if *d >= since {
+                totals.entry(key.clone()).or_insert_with(|| (request.clone(), 0)).1 += count;
+            }
+        }
+        let mut popular: Vec<_> = totals.into_iter().collect();
+        popular.sort_by(|(a, (_, x)), (b, (_, y))| y.cmp(x).then_with(|| a.cmp(b)));
+        Ok(popular.into_iter().take(limit).map(|(_, entry)| entry).collect())
+    }
+
+    /// Moves pending counters into the database; on failure they are kept
+    /// for the next attempt.
+    async fn flush(&self, pool: &sqlx::PgPool) {
+        let rows: Vec<_> = std::mem::take(&mut *self.pending.lock().unwrap()).into_iter().collect();
+        if !rows.is_empty() {
+            if let Err(e) = db::add_font_usage(pool, &rows).await {
+                warn!(rows = rows.len(), "analytics flush failed: {e}");
+                let mut pending = self.pending.lock().unwrap();
+                for (key, usage) in rows {
+                    *pending.entry(key).or_default() += usage;
+                }
+            }
+        }
+        let requests: Vec<_> = std::mem::take(&mut *self.requests.lock().unwrap()).into_iter().collect();
+        if !requests.is_empty() {
+            if let Err(e) = db::add_subset_requests(pool, &requests).await {
+                warn!(rows = requests.len(), "subset request flush failed: {e}");
+                let mut pending = self.requests.lock().unwrap();
+                for (key, (request, count)) in requests {
+                    pending.entry(key).or_insert((request, 0)).1 += count;
+                }
+            }
+        }
+        let breakdown: Vec<_> = std::mem::take(&mut *self.breakdown.lock().unwrap()).into_iter().collect();
+        if !breakdown.is_empty() {
+            if let Err(e) = db::add_usage_breakdown(pool, &breakdown).await {
+                warn!(rows = breakdown.len(), "usage breakdown flush failed: {e}");
+                let mut pending = self.breakdown.lock().unwrap();
+                for (key, tally) in breakdown {
+                    *pending.entry(key).or_default() += tally;
+                }
+            }
+        }
+    }
+
+    /// Usage since `since` (inclusive), by font and day.
+    async fn rows(
+        &self,
+        pool: Option<&sqlx::PgPool>,
+        font: Option<&str>,
+        since: &str,
+    ) -> Result<BTreeMap<(String, String), Usage>, (StatusCode, String)> {
+        let mut rows = match pool {
+            Some(pool) => db::font_usage(pool, font, since)
+                .await
+                .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("reading analytics failed: {e}")))?
+                .into_iter()
+                .collect(),
+            None => BTreeMap::new(),
+        };
+        for ((f, d), usage) in self.pending.lock().unwrap().iter() {
+            if font.is_none_or(|font| font == f) && d.as_str() >= since {
+                *rows.entry((f.clone(), d.clone())).or_default() += *usage;
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Breakdowns of `fonts` since `since` (inclusive).
+    async fn breakdown(
+        &self,
+        pool: Option<&sqlx::PgPool>,
+        fonts: &[String],
+        since: &str,
+    ) -> Result<Vec<(Breakdown, Tally)>, (StatusCode, String)> {
+        let mut rows = match pool {
+            Some(pool) => db::usage_breakdown(pool, fonts, since)
+                .await
+                .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("reading analytics failed: {e}")))?,
+            None => Vec::new(),
+        };
+        let mut breakdown = self.breakdown.lock().unwrap();
+        // Without a database nothing else drops old days.
+        breakdown.retain(|(_, d, _, _), _| pool.is_some() || d.as_str() >= day(MAX_DAYS).as_str());
+        rows.extend(
+            breakdown
+                .iter()
+                .filter(|((f, d, _, _), _)| fonts.contains(f) && d.as_str() >= since)
+                .map(|(key, tally)| (key.clone(), *tally)),
+        );
+        Ok(rows)
+    }
+}
+
+/// Flushes to the database until the process exits.
+pub async fn run(state: Arc<AppState>) {
+    let Some(pool) = state.db.clone() else { return };
+    let secs = std::env::var("ANALYTICS_FLUSH_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(60u64);
+    let mut tick = tokio::time::interval(Duration::from_secs(secs.max(1)));
+    tick.tick().await;
+    loop {
+        tick.tick().await;
+        state.analytics.flush(&pool).await;
+    }
+}
+
+fn default_days() -> u64 {
+    30
+}
+
+fn default_limit() -> usize {
+    10
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FontQuery {
+    #[serde(default = "default_days")]
+    days: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DailyUsage {
+    day: String,
+    #[serde(flatten)]
+    usage: Usage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FontReport {
+    font: String,
+    days: u64,
+    total: Usage,
+    /// Days with any activity, oldest first.
+    daily: Vec<DailyUsage>,
+}
+
+fn window(days: u64) -> Result<String, (StatusCode, String)> {
+    if !(1..=MAX_DAYS).contains(&days) {
+        return Err((StatusCode::BAD_REQUEST, format!("days must be between 1 and {MAX_DAYS}")));
+    }
+    Ok(day(days - 1))
+}
+
+/// Daily usage of one font over the last `days` days.
+pub async fn font(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<FontQuery>,
+) -> Result<Json<FontReport>, (StatusCode, String)> {
+    let since = window(query.days)?;
+    let font = artifacts::slug(&id);
+    let rows = state.analytics.rows(state.db.as_ref(), Some(&font), &since).await?;
+    let mut total = Usage::default();
+    let daily = rows
+        .into_iter()
+        .map(|((_, day), usage)| {
+            total += usage;
+            DailyUsage { day, usage }
+        })
+        .collect();
+    Ok(Json(FontReport { font, days: query.days, total, daily }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TopQuery {
+    #[serde(default = "default_days")]
+    days: u64,
+    #[serde(default = "default_limit")]
+    limit: usize,
+    /// Counter to rank by; `downloads` when absent.
+    by: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RankedFont {
+    font: String,
+    #[serde(flatten)]
+    usage: Usage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopReport {
+    by: String,
+    days: u64,
+    fonts: Vec<RankedFont>,
+}
+
+/// The `limit` fonts with the highest `by` counter over the last `days` days.
+pub async fn top(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<TopQuery>,
+) -> Result<Json<TopReport>, (StatusCode, String)> {
+    let since = window(query.days)?;
+    let by = query.by.unwrap_or_else(|| "downloads".to_string());
+    let key: fn(&Usage) -> u64 = match by.as_str() {
+        "downloads" => |u| u.downloads,
+        "bytes_served" => |u| u.bytes_served,
+        "compressions" => |u| u.compressions,
+        "subsets" => |u| u.subsets,
+        "bytes_saved" => |u| u.bytes_saved,
+        other => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("cannot rank by '{other}' (downloads, bytes_served, compressions, subsets, bytes_saved)"),
+            ))
+        }
+    };
+    let mut totals: BTreeMap<String, Usage> = BTreeMap::new();
+    for ((font, _), usage) in state.analytics.rows(state.db.as_ref(), None, &since).await? {
+        *totals.entry(font).or_default() += usage;
+    }
+    let mut fonts: Vec<RankedFont> = totals.into_iter().map(|(font, usage)| RankedFont { font, usage }).collect();
+    fonts.sort_by(|a, b| key(&b.usage).cmp(&key(&a.usage)).then_with(|| a.font.cmp(&b.font)));
+    fonts.truncate(query.limit.clamp(1, 100));
+    Ok(Json(TopReport { by, days: query.days, fonts }))
+}
+
+fn default_windows() -> String {
+    "1,7,30".to_string()
+}
+
+fn default_top() -> usize {
+    5
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    /// Comma-separated window lengths in days.
+    #[serde(default = "default_windows")]
+    days: String,
+    /// Formats and charsets listed per window.
+    #[serde(default = "default_top")]
+    limit: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Ranked {
+    name: String,
+    requests: u64,
+    bytes: u64,
+    average_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WindowStats {
+    days: u64,
+    since: String,
+    downloads: u64,
+    bytes_served: u64,
+    average_download_bytes: u64,
+    compressions: u64,
+    subsets: u64,
+    /// Most requested output formats, most requested first.
+    formats: Vec<Ranked>,
+    /// Most requested subset charsets, most requested first.
+    charsets: Vec<Ranked>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CatalogStats {
+    id: String,
+    windows: Vec<WindowStats>,
+}
+
+fn average(bytes: u64, requests: u64) -> u64 {
+    bytes.checked_div(requests).unwrap_or(0)
+}
+
+/// The `limit` values of `dimension` with the most requests.
+fn ranked(rows: &[(Breakdown, Tally)], dimension: &str, since: &str, limit: usize) -> Vec<Ranked> {
+    let mut totals: BTreeMap<&str, Tally> = BTreeMap::new();
+    for ((_, day, d, value), tally) in rows {
+        if d == dimension && day.as_str() >= since {
+            *totals.entry(value).or_default() += *tally;
+        }
+    }
+    let mut ranked: Vec<Ranked> = totals
+        .into_iter()
+        .map(|(name, t)| Ranked {
+            name: name.to_string(),
+            requests: t.requests,
+            bytes: t.bytes,
+            average_bytes: average(t.bytes, t.requests),
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.requests.cmp(&a.requests).then_with(|| a.name.cmp(&b.name)));
+    ranked.truncate(limit);
+    ranked
+}
+
+/// How a catalog entry was used over each window of `days`: its downloads,
+/// generated files and top formats and charsets, counted under its id and
+/// its family.
+pub async fn stats(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<CatalogStats>, (StatusCode, String)> {
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &id)?;
+    let family = state.catalog.read().unwrap().iter().find(|e| e.id == id).map(|e| e.family.clone());
+    let family = family.ok_or_else(|| (StatusCode::NOT_FOUND, format!("no catalog entry '{id}'")))?;
+    let mut windows = query
+        .days
+        .split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(|d| d.parse::<u64>().map_err(|_| (StatusCode::BAD_REQUEST, format!("days: '{d}' is not a number"))))
+        .collect::<Result<Vec<_>, _>>()?;
+    windows.sort_unstable();
+    windows.dedup();
+    let longest = *windows.last().ok_or_else(|| (StatusCode::BAD_REQUEST, "days lists no window".to_string()))?;
+    let oldest = window(longest)?;
+    window(windows[0])?;
+
+    let mut fonts = vec![artifacts::slug(&id), artifacts::slug(&family)];
+    fonts.dedup();
+    let mut usage = Vec::new();
+    for font in &fonts {
+        usage.extend(state.analytics.rows(state.db.as_ref(), Some(font), &oldest).await?);
+    }
+    let breakdown = state.analytics.breakdown(state.db.as_ref(), &fonts, &oldest).await?;
+    let limit = query.limit.clamp(1, 50);
+    let windows = windows
+        .into_iter()
+        .map(|days| {
+            let since = day(days - 1);
+            let mut total = Usage::default();
+            for ((_, d), u) in &usage {
+                if *d >= since {
+                    total += *u;
+                }
+            }
+            WindowStats {
+                days,
+                downloads: total.downloads,
+                bytes_served: total.bytes_served,
+                average_download_bytes: average(total.bytes_served, total.downloads),
+                compressions: total.compressions,
+                subsets: total.subsets,
+                formats: ranked(&breakdown, "format", &since, limit),
+                charsets: ranked(&breakdown, "charset", &since, limit),
+                since,
+            }
+        })
+        .collect();
+    Ok(Json(CatalogStats { id, windows }))
+}
@@ -0,0 +1,615 @@
+//! Generated font files.
+//!
+//! Compression, subset and slice output is kept in an [`ArtifactStore`]
+//! (see [`storage`]; `ARTIFACT_DIR` on local disk) as
+//! `<slug>/<slug>-<digest>.<ext>`. The digest covers the source font's
+//! SHA-256, the transform and the engine version, and files are written
+//! once, so every replica, before and after a restart, finds a repeated
+//! request's output under the same immutable URL. A record of the outcome
+//! is kept next to it under `.meta/`, so such requests are answered without
+//! regenerating anything. Files are served from `/cdn/fonts/:slug/:file`
+//! (`/cdn/:tenant/fonts/:slug/:file` for a tenant's fonts, see [`tenants`]),
+//! streamed with the `immutable` cache policy (see
+//! [`crate::cache_control`]) for edges to pull from; `<file>.auto` picks the
+//! client's best stored format (see [`crate::negotiation`]). Revalidation
+//! with `If-None-Match` or `If-Modified-Since` gets `304 Not Modified`, and
+//! a single `Range`
+//! (honoured when an `If-Range` names the current ETag) gets
+//! `206 Partial Content`. Files of private fonts need a signed URL (see
+//! [`signing`]) and are only cached privately until it expires.
+//!
+//! Every file's SHA-256 is stored next to its record and sent with it as
+//! `Digest: sha-256=<base64>` and `x-content-sha256: <hex>`; stylesheet
+//! preloads carry it as an `integrity` value (see [`integrity`]) for
+//! Subresource Integrity. Files stored before digests were kept get theirs
+//! on first download.
+//!
+//! Keys found missing are remembered for `NEGATIVE_CACHE_SECS` (30 by
+//! default; `0` disables it), at most `NEGATIVE_CACHE_ENTRIES` of them
+//! (10000), and answered `404` without asking storage again, so scans of
+//! made-up paths do not reach the backend. A file stored in the meantime by
+//! this replica is found at once; one stored by another replica within the
+//! TTL. Hits and misses are exported as the `cdn_missing` cache's metrics.
+//!
+//! A purge (see [`crate::purge`]) deletes files and raises the font's purge
+//! generation, which joins the digest, so regenerated files get new URLs
+//! that no edge has cached.
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::Response,
+};
+use font_api::UploadedFont;
+use base64::Engine;
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::warn;
+
+use crate::{
+    cache_control::Policy, compress, duplicates, gc, negotiation, signing, spool, storage, telemetry, tenants,
+    usage_events, AppState,
+};
+
+/// Part of every address, so an engine whose encoders changed does not
+/// reuse files an older one wrote.
+const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Purge generations by slug, `""` for everything.
+const PURGES_KEY: &str = ".meta/purges.json";
+
+pub fn artifact_dir() -> PathBuf {
+    std::env::var("ARTIFACT_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| spool::spool_dir().join("artifacts"))
+}
+
+/// A font or family name as a URL path segment.
+pub fn slug(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .split('-')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Where the SHA-256 of the file at `key` is kept.
+fn digest_key(key: &str) -> String {
+    format!(".meta/{key}.sha256")
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// A hex SHA-256 in base64, as `Digest` and SRI carry it.
+fn sha256_base64(hex: &str) -> Option<String> {
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()
+        .filter(|b| b.len() == 32)?;
+    Some(base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+/// `sha256-<base64>`, the Subresource Integrity value for a hex SHA-256.
+pub fn integrity(hex: &str) -> Option<String> {
+    sha256_base64(hex).map(|b| format!("sha256-{b}"))
+}
+
+/// The key of the file a `/cdn/fonts/` or `/cdn/:tenant/fonts/` URL (on any
+/// host, with any query) points at.
+pub fn key_of(url: &str) -> Option<&str> {
+    let path = url.split(['?', '#']).next()?;
+    let (_, rest) = path.split_once("/cdn/")?;
+    let key = match rest.strip_prefix("fonts/") {
+        Some(key) => key,
+        None => rest.split_once('/')?.1.strip_prefix("fonts/")?,
+    };
+    key.split('/').all(valid_segment).then_some(key)
+}
+
+fn valid_segment(s: &str) -> bool {
+    !s.is_empty() && !s.starts_with('.') && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+fn env_u64(k: &str, d: u64) -> u64 {
+    std::env::var(k).ok().and_then(|v| v.parse().ok()).unwrap_or(d)
+}
+
+/// Keys storage did not have, with when they were looked up.
+struct MissingKeys {
+    ttl: Duration,
+    capacity: usize,
+    keys: Mutex<HashMap<String, Instant>>,
+}
+
+impl MissingKeys {
+    fn from_env() -> Self {
+        Self {
+            ttl: Duration::from_secs(env_u64("NEGATIVE_CACHE_SECS", 30)),
+            capacity: env_u64("NEGATIVE_CACHE_ENTRIES", 10_000) as usize,
+            keys: Mutex::default(),
+        }
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        let mut keys = self.keys.lock().unwrap();
+        match keys.get(key) {
+            Some(at) if at.elapsed() < self.ttl => true,
+            Some(_) => {
+                keys.remove(key);
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&self, key: &str) {
+        if self.ttl.is_zero() || self.capacity == 0 {
+            return;
+        }
+        let mut keys = self.keys.lock().unwrap();
+        if keys.len() >= self.capacity {
+            keys.retain(|_, at| at.elapsed() < self.ttl);
+        }
+        // Still full: the newest scan matters more than older ones.
+        if keys.len() >= self.capacity {
+            keys.clear();
+        }
+        keys.insert(key.to_string(), Instant::now());
+        telemetry::record_cache_size("cdn_missing", keys.keys().map(String::len).sum(), keys.len());
+    }
+
+    fn remove(&self, key: &str) {
+        self.keys.lock().unwrap().remove(key);
+    }
+}
+
+/// Where a generated font lives.
+pub struct Address {
+    key: String,
+    /// See [`gc::CLASSES`].
+    class: &'static str,
+}
+
+impl Address {
+    /// See [`ArtifactStore::address`].
+    fn new(slug: &str, stem: &str, source: &str, transform: &serde_json::Value, format: &str, generation: u64) -> Self {
+        let class = gc::CLASSES.iter().find(|c| transform.get(c).is_some()).map_or(gc::OTHER, |c| c);
+        let mut hasher = Sha256::new();
+        for part in [ENGINE_VERSION, source, &transform.to_string(), format] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        // Unpurged fonts keep the addresses they always had.
+        if generation > 0 {
+            hasher.update(generation.to_string().as_bytes());
+        }
+        let digest = format!("{:x}", hasher.finalize());
+        Self { key: format!("{slug}/{stem}-{}.{format}", &digest[..16]), class }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The CDN path.
+    pub fn path(&self) -> String {
+        format!("/cdn/fonts/{}", self.key)
+    }
+
+    /// `valid_segment` rejects the leading dot, so records are never served.
+    fn record_key(&self) -> String {
+        format!(".meta/{}.json", self.key)
+    }
+}
+
+/// Content-addressed, write-once storage for generated fonts.
+pub struct ArtifactStore {
+    storage: Arc<dyn storage::FontStorage>,
+    /// SHA-256 of catalog binaries by path, kept with the size and
+    /// modification time it was computed for.
+    sources: Mutex<HashMap<PathBuf, (u64, SystemTime, String)>>,
+    purges: RwLock<BTreeMap<String, u64>>,
+    /// SHA-256 of stored files by key; files never change under a key.
+    digests: Mutex<HashMap<String, String>>,
+    missing: MissingKeys,
+    access: gc::Access,
+}
+
+impl ArtifactStore {
+    /// Where artifacts are stored.
+    pub fn location(&self) -> String {
+        self.storage.location()
+    }
+
+    pub async fn load(storage: Arc<dyn storage::FontStorage>) -> Self {
+        let store = Self {
+            storage,
+            sources: Mutex::default(),
+            purges: RwLock::default(),
+            digests: Mutex::default(),
+            missing: MissingKeys::from_env(),
+            access: gc::Access::default(),
+        };
+        if let Err(e) = store.reload_purges().await {
+            warn!("{}: cannot read purge generations: {e}", store.storage.location());
+        }
+        store
+    }
+
+    /// Picks up purges made by other replicas.
+    pub async fn reload_purges(&self) -> Result<(), String> {
+        let Some(raw) = self.storage.get(PURGES_KEY).await? else { return Ok(()) };
+        let purges = serde_json::from_slice(&raw).map_err(|e| e.to_string())?;
+        *self.purges.write().unwrap() = purges;
+        Ok(())
+    }
+
+    /// The purge generation of `slug`; 0 when it was never purged.
+    pub fn generation(&self, slug: &str) -> u64 {
+        let purges = self.purges.read().unwrap();
+        purges.get("").copied().max(purges.get(slug).copied()).unwrap_or(0)
+    }
+
+    /// Raises the generation of `slugs` (of everything when empty) to a new
+    /// one, the purge time in Unix seconds, and stores it.
+    pub async fn bump(&self, slugs: &[String]) -> Result<u64, String> {
+        self.reload_purges().await?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let (generation, raw) = {
+            let mut purges = self.purges.write().unwrap();
+            let generation = purges.values().max().map_or(now, |&latest| now.max(latest + 1));
+            if slugs.is_empty() {
+                purges.insert(String::new(), generation);
+            }
+            for slug in slugs {
+                purges.insert(slug.clone(), generation);
+            }
+            (generation, serde_json::to_vec(&*purges).map_err(|e| e.to_string())?)
+        };
+        self.storage.put(PURGES_KEY, raw).await?;
+        Ok(generation)
+    }
+
+    /// Where `format` output of `transform` applied to the font whose
+    /// SHA-256 is `source` is kept; `name` only makes the URL readable.
+    pub fn address(&self, name: &str, source: &str, transform: &serde_json::Value, format: &str) -> Address {
+        self.named_address(name, None, source, transform, format)
+    }
+
+    /// [`Self::address`] with the file named after `output` rather than the
+    /// font, still under the font's directory.
+    pub fn named_address(
+        &self,
+        name: &str,
+        output: Option<&str>,
+        source: &str,
+        transform: &serde_json::Value,
+        format: &str,
+    ) -> Address {
+        let slug = match slug(name) {
+            s if s.is_empty() => "font".to_string(),
+            s => s,
+        };
+        let stem = output.map(self::slug).filter(|s| !s.is_empty()).unwrap_or_else(|| slug.clone());
+        Address::new(&slug, &stem, source, transform, format, self.generation(&slug))
+    }
+
+    /// `url` past edge copies made before `slug`'s last purge.
+    pub fn bust(&self, slug: &str, url: String) -> String {
+        match self.generation(slug) {
+            0 => url,
+            generation if url.contains('?') => format!("{url}&v={generation}"),
+            generation => format!("{url}?v={generation}"),
+        }
+    }
+
+    /// Deletes the file at `key`, its record and its digest.
+    pub async fn delete(&self, key: &str) -> Result<(), String> {
+        self.storage.delete(&format!(".meta/{key}.json")).await?;
+        self.storage.delete(&digest_key(key)).await?;
+        self.digests.lock().unwrap().remove(key);
+        self.storage.delete(key).await
+    }
+
+    /// Stores `data` at `key` with its digest, for files outside the
+    /// content-addressed scheme (version pins).
+    pub async fn put_file(&self, key: &str, data: Vec<u8>) -> Result<(), String> {
+        let digest = sha256_hex(&data);
+        self.storage.put(key, data).await?;
+        self.storage.put(&digest_key(key), digest.clone().into_bytes()).await?;
+        self.digests.lock().unwrap().insert(key.to_string(), digest);
+        self.missing.remove(key);
+        Ok(())
+    }
+
+    /// Hex SHA-256 of the file at `key`, `None` when there is no such file.
+    /// A missing digest is computed and stored.
+    pub async fn digest(&self, key: &str) -> Result<Option<String>, String> {
+        if let Some(digest) = self.digests.lock().unwrap().get(key) {
+            return Ok(Some(digest.clone()));
+        }
+        let digest = match self.storage.get(&digest_key(key)).await? {
+            Some(raw) => String::from_utf8_lossy(&raw).trim().to_string(),
+            None => {
+                let Some(data) = self.storage.get(key).await? else { return Ok(None) };
+                let digest = sha256_hex(&data);
+                self.storage.put(&digest_key(key), digest.clone().into_bytes()).await?;
+                digest
+            }
+        };
+        self.digests.lock().unwrap().insert(key.to_string(), digest.clone());
+        Ok(Some(digest))
+    }
+
+    pub fn storage(&self) -> &dyn storage::FontStorage {
+        &*self.storage
+    }
+
+    pub fn access(&self) -> &gc::Access {
+        &self.access
+    }
+
+    /// SHA-256 of the upload, or of the catalog binary `font_name` refers
+    /// to; catalog binaries are only rehashed when they change on disk.
+    pub fn source(&self, state: &AppState, font_name: &str, upload: Option<&UploadedFont>) -> Result<String, String> {
+        if let Some(upload) = upload {
+            return Ok(upload.sha256.clone());
+        }
+        let path = duplicates::catalog_path(state, font_name)?;
+        let meta = std::fs::metadata(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let modified = meta.modified().unwrap_or(UNIX_EPOCH);
+        if let Some((_, _, digest)) =
+            self.sources.lock().unwrap().get(&path).filter(|(len, at, _)| *len == meta.len() && *at == modified)
+        {
+            return Ok(digest.clone());
+        }
+        let data = std::fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let digest = format!("{:x}", Sha256::digest(&data));
+        self.sources.lock().unwrap().insert(path, (meta.len(), modified, digest.clone()));
+        Ok(digest)
+    }
+
+    /// The record stored with the file at `address`, if it was generated
+    /// before.
+    pub async fn lookup<R: DeserializeOwned>(&self, address: &Address) -> Result<Option<R>, (StatusCode, String)> {
+        let Some(raw) = self.storage.get(&address.record_key()).await.map_err(storage::error("reading artifact"))?
+        else {
+            return Ok(None);
+        };
+        match serde_json::from_slice(&raw) {
+            Ok(record) => {
+                self.access.touch(&address.key, Some(address.class), None);
+                Ok(Some(record))
+            }
+            Err(e) => {
+                warn!(key = %address.key, error = %e, "unreadable artifact record; regenerating");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Stores `data`, its digest and its record unless they are already
+    /// there. The record goes last, so a record always means the file is
+    /// complete.
+    pub async fn put<R: Serialize>(&self, address: &Address, data: &[u8], record: &R) -> Result<(), (StatusCode, String)> {
+        let error = || storage::error("storing artifact");
+        self.access.touch(&address.key, Some(address.class), Some(data.len() as u64));
+        if self.storage.exists(&address.record_key()).await.map_err(error())? {
+            return Ok(());
+        }
+        if !self.storage.exists(&address.key).await.map_err(error())? {
+            self.storage.put(&address.key, data.to_vec()).await.map_err(error())?;
+        }
+        let digest = sha256_hex(data);
+        self.storage.put(&digest_key(&address.key), digest.clone().into_bytes()).await.map_err(error())?;
+        self.digests.lock().unwrap().insert(address.key.clone(), digest);
+        self.missing.remove(&address.key);
+        let raw = serde_json::to_vec(record).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        self.storage.put(&address.record_key(), raw).await.map_err(error())
+    }
+
+    /// The file at `address`, which must have been stored.
+    pub async fn read(&self, address: &Address) -> Result<Vec<u8>, (StatusCode, String)> {
+        self.storage
+            .get(&address.key)
+            .await
+            .map_err(storage::error("reading artifact"))?
+            .ok_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, format!("artifact '{}' is missing", address.key)))
+    }
+
+    pub async fn exists(&self, key: &str) -> Result<bool, String> {
+        self.storage.exists(key).await
+    }
+
+    pub async fn open(&self, key: &str) -> Result<Option<storage::Object>, String> {
+        self.storage.open(key).await
+    }
+
+    pub async fn open_range(&self, key: &str, range: storage::ByteRange) -> Result<Option<storage::Object>, String> {
+        self.storage.open_range(key, range).await
+    }
+
+    /// Keys of the files generated under `/cdn/fonts/<slug>/`.
+    pub async fn list(&self, slug: &str) -> Result<Vec<String>, String> {
+        if !valid_segment(slug) {
+            return Ok(Vec::new());
+        }
+        self.storage.list(slug).await
+    }
+}
+
+/// Streams an artifact of a shared font.
+pub async fn serve(
+    State(state): State<Arc<AppState>>,
+    Path((slug, file)): Path<(String, String)>,
+    Query(signature): Query<signing::Signature>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    serve_file(&state, "", &slug, &file, &signature, &headers).await
+}
+
+/// `/cdn/:tenant/fonts/:slug/:file`: streams an artifact of a tenant's font.
+pub async fn serve_tenant(
+    State(state): State<Arc<AppState>>,
+    Path((tenant, slug, file)): Path<(String, String, String)>,
+    Query(signature): Query<signing::Signature>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    serve_file(&state, &tenant, &slug, &file, &signature, &headers).await
+}
+
+async fn serve_file(
+    state: &AppState,
+    tenant: &str,
+    slug: &str,
+    file: &str,
+    signature: &signing::Signature,
+    headers: &HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    // Paths under an alias serve the canonical entry (see [`crate::aliases`]).
+    let canonical = state.aliases.cdn(&state.catalog.read().unwrap(), slug, file);
+    let (slug, file) = canonical.as_ref().map_or((slug, file), |(slug, file, _)| (slug.as_str(), file.as_str()));
+    let not_found = || (StatusCode::NOT_FOUND, format!("no font at {}/{slug}/{file}", tenants::prefix(tenant)));
+    if !valid_segment(slug) || !valid_segment(file) {
+        return Err(not_found());
+    }
+    let Some(base) = file.strip_suffix(".auto") else {
+        return stream(state, tenant, slug, &format!("{slug}/{file}"), signature, headers).await;
+    };
+    for format in negotiation::formats(headers) {
+        let key = format!("{slug}/{base}.{format}");
+        if state.artifacts.exists(&key).await.map_err(storage::error("reading artifact"))? {
+            let mut response = stream(state, tenant, slug, &key, signature, headers).await?;
+            response.headers_mut().append(header::VARY, HeaderValue::from_static(negotiation::VARY));
+            return Ok(response);
+        }
+    }
+    Err(not_found())
+}
+
+/// Streams the stored file `key`, which is under `<slug>/`, when `tenant`
+/// (empty for `/cdn/fonts/`) owns the font (see [`tenants`]). Files are
+/// written once under their key, so the rest of the key doubles as a strong
+/// `ETag` and a matching `If-None-Match` is answered without reading
+/// storage. `If-Modified-Since` is compared with the stored object's
+/// `Last-Modified` and only consulted without `If-None-Match`.
+pub async fn stream(
+    state: &AppState,
+    tenant: &str,
+    slug: &str,
+    key: &str,
+    signature: &signing::Signature,
+    headers: &HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let path = format!("{}/{key}", tenants::prefix(tenant));
+    let not_found = || (StatusCode::NOT_FOUND, format!("no font at {path}"));
+    let file = key.strip_prefix(slug).and_then(|rest| rest.strip_prefix('/')).ok_or_else(not_found)?;
+    if tenants::owner(state, slug) != tenant {
+        return Err(not_found());
+    }
+    // Shared fonts are billed to the tenant asking for them.
+    let billed = if tenant.is_empty() { tenants::of(headers) } else { tenant };
+    state.quotas.ensure_bandwidth(billed)?;
+    let cache_control = if signing::is_private(state, slug) {
+        let valid_for = state.signer.verify(&path, signature)?;
+        HeaderValue::from_str(&format!("private, max-age={valid_for}")).expect("a valid header value")
+    } else {
+        state.cache_policies.for_font(&state.catalog.read().unwrap(), slug, Policy::Immutable)
+    };
+    let etag = format!("\"{file}\"");
+    let cached = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').map(|t| t.trim().trim_start_matches("W/")).any(|t| t == "*" || t == etag));
+    let format = file.rsplit_once('.').map_or("", |(_, ext)| ext);
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, compress::media_type(format))
+        .header(header::CACHE_CONTROL, cache_control)
+        .header(header::ETAG, &etag)
+        .header(header::ACCEPT_RANGES, "bytes");
+    let usage =
+        |bytes, cache| usage_events::Usage { kind: "download", tenant: billed, font: slug, format, bytes, cache };
+    if cached {
+        state.artifacts.access.touch(key, None, None);
+        state.usage_events.record(headers, usage(0, "revalidated"));
+        return Ok(response.status(StatusCode::NOT_MODIFIED).body(Body::empty()).unwrap());
+    }
+    if state.artifacts.missing.contains(key) {
+        telemetry::record_cache("cdn_missing", true);
+        return Err(not_found());
+    }
+    let Some(digest) = state.artifacts.digest(key).await.map_err(storage::error("reading artifact"))? else {
+        telemetry::record_cache("cdn_missing", false);
+        state.artifacts.missing.insert(key);
+        return Err(not_found());
+    };
+    if let Some(b) = sha256_base64(&digest) {
+        response = response.header("digest", format!("sha-256={b}"));
+    }
+    response = response.header("x-content-sha256", digest);
+    // A stale `If-Range` (only the ETag is compared) gets the whole file.
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| headers.get(header::IF_RANGE).is_none_or(|v| v.to_str().is_ok_and(|v| v.trim() == etag)))
+        .and_then(storage::ByteRange::parse);
+    let object = match range {
+        Some(range) => state.artifacts.open_range(key, range).await,
+        None => state.artifacts.open(key).await,
+    };
+    let object = object.map_err(storage::error("reading artifact"))?.ok_or_else(not_found)?;
+    if let Some(modified) = object.modified {
+        response = response.header(header::LAST_MODIFIED, httpdate::fmt_http_date(modified));
+        let since = headers
+            .get(header::IF_MODIFIED_SINCE)
+            .filter(|_| !headers.contains_key(header::IF_NONE_MATCH))
+            .and_then(|v| httpdate::parse_http_date(v.to_str().ok()?).ok());
+        // HTTP dates have whole seconds.
+        let modified_secs = modified.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        if since.is_some_and(|s| s.duration_since(UNIX_EPOCH).is_ok_and(|s| modified_secs <= s.as_secs())) {
+            state.usage_events.record(headers, usage(0, "revalidated"));
+            return Ok(response.status(StatusCode::NOT_MODIFIED).body(Body::empty()).unwrap());
+        }
+    }
+    state.artifacts.access.touch(key, None, object.length);
+    let length = object.length.unwrap_or(0);
+    let sent = match (range, object.range) {
+        (Some(_), Some((first, last))) => {
+            response = response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_RANGE, format!("bytes {first}-{last}/{length}"))
+                .header(header::CONTENT_LENGTH, last - first + 1);
+            last - first + 1
+        }
+        (Some(_), None) => {
+            let response = response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{length}"));
+            return Ok(response.body(Body::empty()).unwrap());
+        }
+        (None, _) => {
+            if let Some(length) = object.length {
+                response = response.header(header::CONTENT_LENGTH, length);
+            }
+            length
+        }
+    };
+    state.analytics.record_download(slug, format, sent);
+    state.usage_events.record(headers, usage(sent, "hit"));
+    state.quotas.record_download(billed, sent);
+    Ok(response.body(object.body).unwrap())
+}
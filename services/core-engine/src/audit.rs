@@ -0,0 +1,150 @@
+//! Audit log of administrative and mutating operations.
+//!
+//! Uploads, catalog changes (versions, staging and restores included), API
+//! keys, cache purges, tenant quotas, feature flags, CORS and resource hint
+//! settings, saved subset profiles and every delete are recorded with who
+//! did it, when, under which request ID, and the affected record before and
+//! after. With a database the log is the `audit_log` table, which refuses
+//! updates and deletes; without one the latest `AUDIT_MEMORY` (default
+//! 10000) events are kept in memory.
+//!
+//! `GET /api/v1/admin/audit` lists events newest first, filtered by
+//! `action` (a prefix, e.g. `catalog.`), `actor`, `tenant`, `target` and
+//! `since` / `until` (Unix seconds), `limit` at a time (default 100, at most
+//! 1000); `before=<id>` pages back. The actor is `admin` for requests with
+//! the admin token, `key:<id>` for API keys and `anonymous` otherwise; the
+//! tenant is the request's `X-Font-Tenant`.
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::AuditEvent;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{error, info};
+
+use crate::{access, db, AppState};
+
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct Filter {
+    pub action: Option<String>,
+    pub actor: Option<String>,
+    pub tenant: Option<String>,
+    pub target: Option<String>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    /// Only events older than this one.
+    pub before: Option<i64>,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+fn default_limit() -> usize {
+    100
+}
+
+impl Filter {
+    fn matches(&self, event: &AuditEvent) -> bool {
+        let at = event.at_unix as i64;
+        self.action.as_deref().is_none_or(|a| event.action.starts_with(a))
+            && self.actor.as_deref().is_none_or(|a| event.actor == a)
+            && self.tenant.as_deref().is_none_or(|t| event.tenant == t)
+            && self.target.as_deref().is_none_or(|t| event.target == t)
+            && self.since.is_none_or(|s| at >= s)
+            && self.until.is_none_or(|u| at < u)
+            && self.before.is_none_or(|b| event.id < b)
+    }
+}
+
+/// Events kept without a database.
+pub struct AuditLog {
+    recent: Mutex<VecDeque<AuditEvent>>,
+    capacity: usize,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        let capacity = std::env::var("AUDIT_MEMORY").ok().and_then(|v| v.parse().ok()).unwrap_or(10_000);
+        Self { recent: Mutex::default(), capacity }
+    }
+}
+
+impl AuditLog {
+    fn keep(&self, mut event: AuditEvent) {
+        let mut recent = self.recent.lock().unwrap();
+        event.id = recent.back().map_or(1, |e| e.id + 1);
+        recent.push_back(event);
+        while recent.len() > self.capacity {
+            recent.pop_front();
+        }
+    }
+}
+
+fn actor(state: &AppState, headers: &HeaderMap) -> String {
+    if state.require_admin(headers).is_ok() {
+        return "admin".to_string();
+    }
+    match state.keys.key_id(headers) {
+        Some(id) => format!("key:{id}"),
+        None => "anonymous".to_string(),
+    }
+}
+
+fn header(headers: &HeaderMap, name: &str) -> String {
+    headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or_default().to_string()
+}
+
+/// Records `action` on `target`; `Value::Null` for a side that does not
+/// exist (before a creation, after a delete).
+pub async fn record(state: &AppState, headers: &HeaderMap, action: &str, target: &str, before: Value, after: Value) {
+    let event = AuditEvent {
+        id: 0,
+        at_unix: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        actor: actor(state, headers),
+        tenant: header(headers, "x-font-tenant"),
+        action: action.to_string(),
+        target: target.to_string(),
+        request_id: header(headers, access::HEADER),
+        before: Some(before).filter(|v| !v.is_null()),
+        after: Some(after).filter(|v| !v.is_null()),
+    };
+    info!(action, target, actor = %event.actor, "audit");
+    match &state.db {
+        Some(pool) => {
+            if let Err(e) = db::add_audit_event(pool, &event).await {
+                // The whole event, so it can be restored from the logs.
+                let event = serde_json::to_string(&event).unwrap_or_default();
+                error!(event = %event, "audit log write failed: {e}");
+            }
+        }
+        None => state.audit.keep(event),
+    }
+}
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(mut filter): Query<Filter>,
+) -> Result<Json<Vec<AuditEvent>>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    filter.limit = filter.limit.clamp(1, MAX_LIMIT);
+    let events = match &state.db {
+        Some(pool) => db::audit_events(pool, &filter)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("audit log read failed: {e}")))?,
+        None => {
+            let recent = state.audit.recent.lock().unwrap();
+            recent.iter().rev().filter(|e| filter.matches(e)).take(filter.limit).cloned().collect()
+        }
+    };
+    Ok(Json(events))
+}
@@ -0,0 +1,231 @@
+//! API keys with scopes.
+//!
+//! With `API_AUTH=required` every API route needs a key, sent as
+//! `Authorization: Bearer <key>` or `X-API-Key: <key>`. Each key carries
+//! scopes: `read` (GET routes such as the catalog), `upload` (uploading and
+//! deleting uploads) and `process` (every other write: compress, subset,
+//! analyze, ...). A key may be bound to a tenant, which then replaces any
+//! `X-Font-Tenant` the caller sent and scopes the catalog it sees (see
+//! [`crate::tenants`]). Otherwise `X-Font-Tenant` is only believed from the
+//! gateway, which proves itself with `X-Font-Gateway: <GATEWAY_SECRET>`;
+//! without that secret configured and sent, the header is dropped.
+//!
+//! What browsers fetch without custom headers stays public: `/cdn/fonts/`,
+//! the stylesheets, slim fonts and previews, plus health and metrics. Admin routes and
+//! catalog edits keep their own `X-Admin-Token` check, and keys are managed
+//! through them (`/api/v1/admin/keys`). Only a SHA-256 of each key is stored, persisted
+//! when a database is configured; the key itself is shown once at creation.
+
+use axum::{
+    extract::{MatchedPath, Path, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{Json, Response},
+};
+use font_api::{ApiKey, CreatedKey, NewKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::info;
+
+use crate::{audit, db, extract::ApiJson, signing, tenants, AppState};
+
+pub const SCOPES: &[&str] = &["read", "upload", "process"];
+
+/// Carries `GATEWAY_SECRET` on requests the gateway forwards.
+const GATEWAY_HEADER: &str = "x-font-gateway";
+
+/// Routes open without a key: fetched by browsers or probes.
+const PUBLIC: &[&str] = &[
+    "/health",
+    "/readyz",
+    "/healthz/live",
+    "/healthz/ready",
+    "/metrics",
+    "/cdn/fonts/:slug/:file",
+    "/cdn/fonts/:slug/:version/:file",
+    "/cdn/:tenant/fonts/:slug/:file",
+    "/cdn/:tenant/fonts/:slug/:version/:file",
+    "/api/v1/font/css",
+    "/api/v1/font/css/:family",
+    "/api/v1/font/slim",
+    "/api/v1/font/:id/preview.svg",
+    "/api/v1/openapi.json",
+    "/api/v1/docs",
+];
+
+/// ID of the key a request was authenticated with, as a request extension.
+#[derive(Debug, Clone)]
+pub struct KeyId(pub String);
+
+pub struct ApiKeys {
+    required: bool,
+    /// Shared with the gateway, which alone may name the caller's tenant.
+    gateway_secret: Option<String>,
+    /// By key hash.
+    keys: RwLock<BTreeMap<String, ApiKey>>,
+}
+
+fn hash(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+impl ApiKeys {
+    pub fn load(keys: Vec<ApiKey>) -> Self {
+        Self {
+            required: std::env::var("API_AUTH").is_ok_and(|v| v == "required"),
+            gateway_secret: std::env::var("GATEWAY_SECRET").ok().filter(|s| !s.is_empty()),
+            keys: RwLock::new(keys.into_iter().map(|k| (k.sha256.clone(), k)).collect()),
+        }
+    }
+
+    /// ID of the known key a request carries, if any.
+    pub fn key_id(&self, headers: &HeaderMap) -> Option<String> {
+        let secret = presented(headers)?;
+        self.keys.read().unwrap().get(&hash(secret)).map(|k| k.id.clone())
+    }
+}
+
+/// The key sent with a request, if any.
+fn presented(headers: &HeaderMap) -> Option<&str> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    bearer.or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok())).map(str::trim)
+}
+
+/// The scope a request needs.
+fn scope_for(method: &Method, route: &str) -> &'static str {
+    // Queries sent as POST bodies, but reads all the same.
+    let query = route == crate::grpc::CATALOG || route == "/api/v1/graphql";
+    if method == Method::GET || method == Method::HEAD || query {
+        "read"
+    } else if route == "/api/v1/font/upload" || route.starts_with("/api/v1/font/uploads/") {
+        "upload"
+    } else {
+        "process"
+    }
+}
+
+pub async fn authenticate(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, (StatusCode, String)> {
+    let from_gateway = match (&state.keys.gateway_secret, request.headers().get(GATEWAY_HEADER)) {
+        (Some(want), Some(got)) => got.to_str().is_ok_and(|got| signing::same_secret(want, got)),
+        _ => false,
+    };
+    let headers = request.headers_mut();
+    headers.remove(GATEWAY_HEADER);
+    if !from_gateway {
+        headers.remove("x-font-tenant");
+    }
+    let route = request.extensions().get::<MatchedPath>().map(|p| p.as_str().to_string());
+    let Some(route) = route.filter(|_| state.keys.required) else {
+        return Ok(next.run(request).await);
+    };
+    let admin = route.starts_with("/api/v1/admin/") || route == "/api/v1/font/catalog/:id";
+    if PUBLIC.contains(&route.as_str()) || admin {
+        return Ok(next.run(request).await);
+    }
+    let secret = presented(request.headers()).ok_or_else(|| {
+        (StatusCode::UNAUTHORIZED, "API key required; send Authorization: Bearer <key> or X-API-Key".to_string())
+    })?;
+    let key = state
+        .keys
+        .keys
+        .read()
+        .unwrap()
+        .get(&hash(secret))
+        .cloned()
+        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "invalid API key".to_string()))?;
+    let scope = scope_for(request.method(), &route);
+    if !key.scopes.iter().any(|s| s == scope) {
+        return Err((StatusCode::FORBIDDEN, format!("API key '{}' lacks the '{scope}' scope", key.name)));
+    }
+    let headers = request.headers_mut();
+    headers.remove("x-font-tenant");
+    let tenant = Some(key.tenant.as_str()).filter(|t| !t.is_empty()).and_then(|t| HeaderValue::from_str(t).ok());
+    if let Some(tenant) = tenant {
+        headers.insert("x-font-tenant", tenant);
+    }
+    request.extensions_mut().insert(KeyId(key.id));
+    Ok(next.run(request).await)
+}
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<ApiKey>>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let mut keys: Vec<ApiKey> = state.keys.keys.read().unwrap().values().cloned().collect();
+    keys.sort_by_key(|k| k.created_at_unix);
+    Ok(Json(keys))
+}
+
+pub async fn create(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(new): ApiJson<NewKey>,
+) -> Result<(StatusCode, Json<CreatedKey>), (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    if new.name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "name is required".to_string()));
+    }
+    if new.scopes.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, format!("scopes must list at least one of: {}", SCOPES.join(", "))));
+    }
+    if let Some(s) = new.scopes.iter().find(|s| !SCOPES.contains(&s.as_str())) {
+        return Err((StatusCode::BAD_REQUEST, format!("unknown scope '{s}'; valid: {}", SCOPES.join(", "))));
+    }
+    // Tenants name CDN paths, so they are valid header values too.
+    tenants::validate(&new.tenant).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let secret = format!("afk_{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+    let mut scopes = new.scopes;
+    scopes.sort();
+    scopes.dedup();
+    let key = ApiKey {
+        id: uuid::Uuid::new_v4().simple().to_string()[..12].to_string(),
+        name: new.name.trim().to_string(),
+        tenant: new.tenant,
+        scopes,
+        prefix: secret[..12].to_string(),
+        sha256: hash(&secret),
+        created_at_unix: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+    };
+    if let Some(pool) = &state.db {
+        db::save_api_key(pool, &key)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("API key persist failed: {e}")))?;
+    }
+    state.keys.keys.write().unwrap().insert(key.sha256.clone(), key.clone());
+    audit::record(&state, &headers, "key.create", &key.id, Value::Null, json!(key)).await;
+    info!(id = %key.id, name = %key.name, scopes = ?key.scopes, "API key created");
+    Ok((StatusCode::CREATED, Json(CreatedKey { secret, key })))
+}
+
+pub async fn revoke(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let Some(key) = state.keys.keys.read().unwrap().values().find(|k| k.id == id).cloned() else {
+        return Err((StatusCode::NOT_FOUND, format!("no API key '{id}'")));
+    };
+    if let Some(pool) = &state.db {
+        db::delete_api_key(pool, &id)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("API key delete failed: {e}")))?;
+    }
+    state.keys.keys.write().unwrap().retain(|_, k| k.id != id);
+    audit::record(&state, &headers, "key.revoke", &id, json!(key), Value::Null).await;
+    info!(id = %id, "API key revoked");
+    Ok(StatusCode::NO_CONTENT)
+}
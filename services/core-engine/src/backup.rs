@@ -0,0 +1,119 @@
+//! Catalog backup and restore.
+//!
+//! A snapshot is a self-describing JSON document holding the catalog plus a
+//! SHA-256 over its canonical serialization. Restore refuses any snapshot
+//! whose digest does not match, so a truncated or hand-edited archive can
+//! never half-apply on a fresh instance.
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::Snapshot;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::info;
+
+use crate::{audit, db, extract::ApiJson, AppState, FontCatalogEntry};
+
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize)]
+pub struct RestoreParams {
+    #[serde(default)]
+    verify_only: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RestoreResponse {
+    verified: bool,
+    restored: bool,
+    entries: usize,
+    catalog_sha256: String,
+}
+
+fn catalog_digest(entries: &[FontCatalogEntry]) -> String {
+    let canonical = serde_json::to_vec(entries).expect("catalog entries always serialize");
+    format!("{:x}", Sha256::digest(&canonical))
+}
+
+pub async fn export(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Snapshot>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+
+    let catalog = state.catalog.read().unwrap().clone();
+    let catalog_sha256 = catalog_digest(&catalog);
+    info!(entries = catalog.len(), sha256 = %catalog_sha256, "catalog snapshot exported");
+
+    Ok(Json(Snapshot {
+        format_version: FORMAT_VERSION,
+        engine_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at_unix: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        catalog,
+        catalog_sha256,
+    }))
+}
+
+pub async fn restore(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<RestoreParams>,
+    ApiJson(snapshot): ApiJson<Snapshot>,
+) -> Result<Json<RestoreResponse>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+
+    if snapshot.format_version != FORMAT_VERSION {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "unsupported snapshot format_version {}; expected {FORMAT_VERSION}",
+                snapshot.format_version
+            ),
+        ));
+    }
+    let actual = catalog_digest(&snapshot.catalog);
+    if actual != snapshot.catalog_sha256 {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "catalog digest mismatch: snapshot says {}, content hashes to {actual}",
+                snapshot.catalog_sha256
+            ),
+        ));
+    }
+
+    let entries = snapshot.catalog.len();
+    if !params.verify_only {
+        if let Some(pool) = &state.db {
+            db::replace_catalog(pool, &snapshot.catalog)
+                .await
+                .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("catalog persist failed: {e}")))?;
+        }
+        // Catalogs are summarized by digest rather than copied whole into the log.
+        let summary = |catalog: &[FontCatalogEntry]| {
+            let ids: Vec<&str> = catalog.iter().map(|e| e.id.as_str()).collect();
+            json!({ "entries": catalog.len(), "catalog_sha256": catalog_digest(catalog), "ids": ids })
+        };
+        let after = summary(&snapshot.catalog);
+        let before = summary(&std::mem::replace(&mut *state.catalog.write().unwrap(), snapshot.catalog));
+        audit::record(&state, &headers, "catalog.restore", "catalog", before, after).await;
+        info!(entries, sha256 = %actual, "catalog restored from snapshot");
+    }
+
+    Ok(Json(RestoreResponse {
+        verified: true,
+        restored: !params.verify_only,
+        entries,
+        catalog_sha256: actual,
+    }))
+}
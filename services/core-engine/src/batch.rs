@@ -0,0 +1,73 @@
+//! Batch compress and subset.
+//!
+//! `POST /api/v1/font/compress/batch` and `/api/v1/font/subset/batch` take a
+//! JSON array of the bodies the single endpoints accept (up to
+//! [`MAX_ITEMS`]), e.g. every weight of a family in two formats, and run
+//! them through those endpoints `BATCH_CONCURRENCY` at a time. One failing
+//! item does not fail the batch: the answer is `200` with each item's
+//! status and either its response or its error, in request order.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{BatchItem, BatchResponse};
+use futures_util::{stream, StreamExt};
+use std::{future::Future, sync::Arc};
+use tracing::info;
+
+use crate::{extract::ApiJson, AppState, CompressRequest, CompressResponse, SubsetRequest, SubsetResponse};
+
+pub const MAX_ITEMS: usize = 64;
+
+pub fn concurrency() -> usize {
+    std::env::var("BATCH_CONCURRENCY").ok().and_then(|v| v.parse().ok()).unwrap_or(4).max(1)
+}
+
+async fn run<R, T, F, Fut>(
+    operation: &'static str,
+    requests: Vec<R>,
+    work: F,
+) -> Result<Json<BatchResponse<T>>, (StatusCode, String)>
+where
+    F: Fn(R) -> Fut,
+    Fut: Future<Output = Result<Json<T>, (StatusCode, String)>>,
+{
+    if requests.is_empty() || requests.len() > MAX_ITEMS {
+        return Err((StatusCode::BAD_REQUEST, format!("a batch holds 1-{MAX_ITEMS} requests")));
+    }
+    let items: Vec<BatchItem<T>> = stream::iter(requests.into_iter().enumerate())
+        .map(|(index, req)| {
+            let item = work(req);
+            async move {
+                match item.await {
+                    Ok(Json(result)) => BatchItem { index, status: 200, result: Some(result), error: None },
+                    Err((status, error)) => BatchItem { index, status: status.as_u16(), result: None, error: Some(error) },
+                }
+            }
+        })
+        .buffered(concurrency())
+        .collect()
+        .await;
+    let succeeded = items.iter().filter(|i| i.result.is_some()).count();
+    let failed = items.len() - succeeded;
+    info!(operation, items = items.len(), succeeded, failed, "batch finished");
+    Ok(Json(BatchResponse { succeeded, failed, items }))
+}
+
+pub async fn compress(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(requests): ApiJson<Vec<CompressRequest>>,
+) -> Result<Json<BatchResponse<CompressResponse>>, (StatusCode, String)> {
+    run("compress", requests, |req| crate::compress(State(Arc::clone(&state)), headers.clone(), ApiJson(req))).await
+}
+
+pub async fn subset(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(requests): ApiJson<Vec<SubsetRequest>>,
+) -> Result<Json<BatchResponse<SubsetResponse>>, (StatusCode, String)> {
+    run("subset", requests, |req| crate::subset(State(Arc::clone(&state)), headers.clone(), ApiJson(req))).await
+}
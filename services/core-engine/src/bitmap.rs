@@ -0,0 +1,457 @@
+//! Embedded color bitmaps: Apple `sbix` and Google `CBLC`/`CBDT` strikes.
+//!
+//! Only PNG payloads are extracted (sbix graphic type `png `, CBDT image
+//! formats 17-19), which is what color emoji fonts ship. [`prune`] rewrites
+//! the tables keeping only retained glyphs and strikes, since bitmaps
+//! dominate the size of emoji fonts.
+
+use axum::{
+    body::Body,
+    extract::Query,
+    http::{header, HeaderName, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeSet;
+use tracing::info;
+
+use crate::{
+    cmap::CharMap,
+    render,
+    sfnt::{be_u16, be_u32, Font},
+    spool,
+};
+
+/// Strike (bitmap size) available in the font.
+#[derive(Debug, Clone, Copy)]
+pub struct Strike {
+    pub ppem: u16,
+    index: usize,
+}
+
+pub enum Bitmaps<'a> {
+    Sbix { sbix: &'a [u8], num_glyphs: usize },
+    Cbdt { cblc: &'a [u8], cbdt: &'a [u8] },
+}
+
+impl<'a> Bitmaps<'a> {
+    pub fn from_font(font: &'a Font) -> Option<Self> {
+        if let Some(sbix) = font.table(b"sbix") {
+            let num_glyphs = font.table(b"maxp").and_then(|m| be_u16(m, 4))? as usize;
+            return Some(Self::Sbix { sbix, num_glyphs });
+        }
+        Some(Self::Cbdt { cblc: font.table(b"CBLC")?, cbdt: font.table(b"CBDT")? })
+    }
+
+    pub fn strikes(&self) -> Vec<Strike> {
+        match self {
+            Self::Sbix { sbix, .. } => {
+                let count = be_u32(sbix, 4).unwrap_or(0) as usize;
+                (0..count)
+                    .filter_map(|index| {
+                        let at = be_u32(sbix, 8 + 4 * index)? as usize;
+                        Some(Strike { ppem: be_u16(sbix, at)?, index })
+                    })
+                    .collect()
+            }
+            Self::Cbdt { cblc, .. } => {
+                let count = be_u32(cblc, 4).unwrap_or(0) as usize;
+                (0..count)
+                    .filter_map(|index| {
+                        let ppem = *cblc.get(8 + 48 * index + 44)?;
+                        Some(Strike { ppem: ppem as u16, index })
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Smallest strike at least `size` pixels, else the largest one.
+    pub fn best_strike(&self, size: u16) -> Option<Strike> {
+        let strikes = self.strikes();
+        strikes
+            .iter()
+            .filter(|s| s.ppem >= size)
+            .min_by_key(|s| s.ppem)
+            .or_else(|| strikes.iter().max_by_key(|s| s.ppem))
+            .copied()
+    }
+
+    /// Number of glyphs with a bitmap in `strike`.
+    pub fn glyphs(&self, strike: Strike) -> usize {
+        match *self {
+            Self::Sbix { sbix, num_glyphs } => {
+                let Some(base) = be_u32(sbix, 8 + 4 * strike.index) else { return 0 };
+                let offset = |g: usize| be_u32(sbix, base as usize + 4 + 4 * g);
+                (0..num_glyphs).filter(|&g| matches!((offset(g), offset(g + 1)), (Some(a), Some(b)) if b > a)).count()
+            }
+            Self::Cbdt { cblc, .. } => {
+                let size = 8 + 48 * strike.index;
+                let (start, end) = (be_u16(cblc, size + 40).unwrap_or(0), be_u16(cblc, size + 42).unwrap_or(0));
+                (start..=end).filter(|&g| cbdt_location(cblc, strike.index, g).is_some()).count()
+            }
+        }
+    }
+
+    /// PNG bytes for `glyph` in `strike`, if it has one.
+    pub fn png(&self, strike: Strike, glyph: u16) -> Option<&'a [u8]> {
+        match *self {
+            Self::Sbix { sbix, num_glyphs } => {
+                let glyph = glyph as usize;
+                if glyph >= num_glyphs {
+                    return None;
+                }
+                let base = be_u32(sbix, 8 + 4 * strike.index)? as usize;
+                let start = base + be_u32(sbix, base + 4 + 4 * glyph)? as usize;
+                let end = base + be_u32(sbix, base + 8 + 4 * glyph)? as usize;
+                // originOffsetX, originOffsetY, graphicType, data
+                let record = sbix.get(start..end)?;
+                (record.get(4..8)? == b"png ").then(|| &record[8..])
+            }
+            Self::Cbdt { cblc, cbdt } => {
+                let loc = cbdt_location(cblc, strike.index, glyph)?;
+                let image = cbdt.get(loc.offset..loc.offset + loc.len)?;
+                let png_at = match loc.image_format {
+                    17 => 5,
+                    18 => 8,
+                    19 => 0,
+                    _ => return None,
+                };
+                let data_len = be_u32(image, png_at)? as usize;
+                image.get(png_at + 4..png_at + 4 + data_len)
+            }
+        }
+    }
+}
+
+struct CbdtGlyph {
+    image_format: u16,
+    offset: usize,
+    len: usize,
+    /// bigGlyphMetrics from index formats 2 and 5, which image format 19
+    /// relies on.
+    metrics: Option<[u8; 8]>,
+}
+
+/// Finds `glyph` in the index subtables of CBLC size `size_index`.
+fn cbdt_location(cblc: &[u8], size_index: usize, glyph: u16) -> Option<CbdtGlyph> {
+    let size = 8 + 48 * size_index;
+    let array = be_u32(cblc, size)? as usize;
+    let subtables = be_u32(cblc, size + 8)? as usize;
+    for i in 0..subtables {
+        let at = array + 8 * i;
+        let (first, last) = (be_u16(cblc, at)?, be_u16(cblc, at + 2)?);
+        if !(first..=last).contains(&glyph) {
+            continue;
+        }
+        let sub = array + be_u32(cblc, at + 4)? as usize;
+        let index_format = be_u16(cblc, sub)?;
+        let image_format = be_u16(cblc, sub + 2)?;
+        let image_data = be_u32(cblc, sub + 4)? as usize;
+        let n = (glyph - first) as usize;
+        let mut metrics = None;
+        let (start, end) = match index_format {
+            1 => (be_u32(cblc, sub + 8 + 4 * n)? as usize, be_u32(cblc, sub + 12 + 4 * n)? as usize),
+            2 => {
+                let image_size = be_u32(cblc, sub + 8)? as usize;
+                metrics = cblc.get(sub + 12..sub + 20)?.try_into().ok();
+                (n * image_size, (n + 1) * image_size)
+            }
+            3 => (be_u16(cblc, sub + 8 + 2 * n)? as usize, be_u16(cblc, sub + 10 + 2 * n)? as usize),
+            4 => {
+                let count = be_u32(cblc, sub + 8)? as usize;
+                (0..count).find_map(|k| {
+                    let pair = sub + 12 + 4 * k;
+                    (be_u16(cblc, pair)? == glyph)
+                        .then(|| Some((be_u16(cblc, pair + 2)? as usize, be_u16(cblc, pair + 6)? as usize)))
+                        .flatten()
+                })?
+            }
+            5 => {
+                let image_size = be_u32(cblc, sub + 8)? as usize;
+                metrics = cblc.get(sub + 12..sub + 20)?.try_into().ok();
+                let count = be_u32(cblc, sub + 20)? as usize;
+                let k = (0..count).find(|&k| be_u16(cblc, sub + 24 + 2 * k) == Some(glyph))?;
+                (k * image_size, (k + 1) * image_size)
+            }
+            _ => return None,
+        };
+        return (end > start).then_some(CbdtGlyph {
+            image_format,
+            offset: image_data + start,
+            len: end - start,
+            metrics,
+        });
+    }
+    None
+}
+
+/// A strike as analyze reports it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StrikeSummary {
+    /// `sbix` or `CBDT`.
+    #[serde(deserialize_with = "strike_table")]
+    pub table: crate::isolation::Known,
+    pub ppem: u16,
+    pub glyphs: usize,
+}
+
+fn strike_table<'de, D: Deserializer<'de>>(deserializer: D) -> Result<&'static str, D::Error> {
+    crate::isolation::known_name(deserializer, &["sbix", "CBDT"])
+}
+
+/// The font's bitmap strikes, smallest first.
+pub fn summary(font: &Font) -> Vec<StrikeSummary> {
+    let Some(bitmaps) = Bitmaps::from_font(font) else { return Vec::new() };
+    let table = match bitmaps {
+        Bitmaps::Sbix { .. } => "sbix",
+        Bitmaps::Cbdt { .. } => "CBDT",
+    };
+    let mut strikes: Vec<StrikeSummary> = bitmaps
+        .strikes()
+        .into_iter()
+        .map(|strike| StrikeSummary { table, ppem: strike.ppem, glyphs: bitmaps.glyphs(strike) })
+        .collect();
+    strikes.sort_by_key(|s| s.ppem);
+    strikes
+}
+
+// ── Pruning ────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    pub strikes_kept: Vec<u16>,
+    pub strikes_dropped: Vec<u16>,
+    pub bitmaps_kept: usize,
+    pub bitmaps_dropped: usize,
+}
+
+/// Drops bitmaps for glyphs outside `keep` and whole strikes whose ppem is
+/// in `drop_ppem`. Glyph IDs are unchanged.
+pub fn prune(font: &mut Font, keep: &BTreeSet<u16>, drop_ppem: &[u16]) -> Result<PruneReport, String> {
+    let mut report = PruneReport::default();
+    let rebuilt = match Bitmaps::from_font(font).ok_or("font has no color bitmaps (sbix or CBDT)")? {
+        Bitmaps::Sbix { sbix, num_glyphs } => {
+            vec![(*b"sbix", prune_sbix(sbix, num_glyphs, keep, drop_ppem, &mut report)?)]
+        }
+        Bitmaps::Cbdt { cblc, cbdt } => {
+            let (cblc, cbdt) = prune_cbdt(cblc, cbdt, keep, drop_ppem, &mut report)?;
+            vec![(*b"CBLC", cblc), (*b"CBDT", cbdt)]
+        }
+    };
+    for (tag, data) in rebuilt {
+        font.set_table(tag, data);
+    }
+    Ok(report)
+}
+
+fn prune_sbix(
+    sbix: &[u8],
+    num_glyphs: usize,
+    keep: &BTreeSet<u16>,
+    drop_ppem: &[u16],
+    report: &mut PruneReport,
+) -> Result<Vec<u8>, String> {
+    let bitmaps = Bitmaps::Sbix { sbix, num_glyphs };
+    let record = |base: usize, glyph: usize| -> Option<&[u8]> {
+        let start = base + be_u32(sbix, base + 4 + 4 * glyph)? as usize;
+        let end = base + be_u32(sbix, base + 8 + 4 * glyph)? as usize;
+        sbix.get(start..end)
+    };
+    let mut strikes = Vec::new();
+    for strike in bitmaps.strikes() {
+        let base = be_u32(sbix, 8 + 4 * strike.index).ok_or("sbix truncated")? as usize;
+        let present = (0..num_glyphs).filter(|&g| record(base, g).is_some_and(|r| !r.is_empty())).count();
+        if drop_ppem.contains(&strike.ppem) {
+            report.strikes_dropped.push(strike.ppem);
+            report.bitmaps_dropped += present;
+            continue;
+        }
+        let header_len = 4 + 4 * (num_glyphs + 1);
+        let mut out = sbix.get(base..base + 4).ok_or("sbix truncated")?.to_vec();
+        let mut data = Vec::new();
+        let mut offsets = Vec::with_capacity(num_glyphs + 1);
+        for g in 0..num_glyphs {
+            offsets.push((header_len + data.len()) as u32);
+            let Some(mut rec) = record(base, g).filter(|r| !r.is_empty()) else { continue };
+            if !keep.contains(&(g as u16)) {
+                report.bitmaps_dropped += 1;
+                continue;
+            }
+            // A `dupe` points at another glyph that may not be retained;
+            // inline the target instead.
+            if rec.get(4..8) == Some(b"dupe") {
+                let target = be_u16(rec, 8).ok_or("sbix dupe record truncated")? as usize;
+                rec = record(base, target).filter(|r| r.get(4..8) != Some(b"dupe")).ok_or("sbix dupe target missing")?;
+            }
+            data.extend_from_slice(rec);
+            report.bitmaps_kept += 1;
+        }
+        offsets.push((header_len + data.len()) as u32);
+        out.extend(offsets.iter().flat_map(|o| o.to_be_bytes()));
+        out.extend_from_slice(&data);
+        strikes.push(out);
+        report.strikes_kept.push(strike.ppem);
+    }
+    let mut out = sbix.get(..4).ok_or("sbix truncated")?.to_vec();
+    out.extend_from_slice(&(strikes.len() as u32).to_be_bytes());
+    let mut offset = 8 + 4 * strikes.len();
+    for s in &strikes {
+        out.extend_from_slice(&(offset as u32).to_be_bytes());
+        offset += s.len();
+    }
+    strikes.iter().for_each(|s| out.extend_from_slice(s));
+    Ok(out)
+}
+
+fn prune_cbdt(
+    cblc: &[u8],
+    cbdt: &[u8],
+    keep: &BTreeSet<u16>,
+    drop_ppem: &[u16],
+    report: &mut PruneReport,
+) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let num_sizes = be_u32(cblc, 4).ok_or("CBLC truncated")? as usize;
+    let mut new_cbdt = cbdt.get(..4).ok_or("CBDT truncated")?.to_vec();
+    let mut sizes: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+
+    for index in 0..num_sizes {
+        let size_at = 8 + 48 * index;
+        let record = cblc.get(size_at..size_at + 48).ok_or("CBLC truncated")?;
+        let ppem = record[44] as u16;
+        let (start, end) = (be_u16(record, 40).unwrap_or(0), be_u16(record, 42).unwrap_or(0));
+        let glyphs: Vec<(u16, CbdtGlyph)> = (start..=end)
+            .filter_map(|g| cbdt_location(cblc, index, g).map(|loc| (g, loc)))
+            .collect();
+        let retained: Vec<&(u16, CbdtGlyph)> = glyphs.iter().filter(|(g, _)| keep.contains(g)).collect();
+        if drop_ppem.contains(&ppem) || retained.is_empty() {
+            report.strikes_dropped.push(ppem);
+            report.bitmaps_dropped += glyphs.len();
+            continue;
+        }
+        report.bitmaps_dropped += glyphs.len() - retained.len();
+        report.bitmaps_kept += retained.len();
+
+        // Images, normalised so the metrics travel with the image: format
+        // 19 (metrics in the index) becomes format 18.
+        let mut images: Vec<(u16, u16, Vec<u8>)> = Vec::with_capacity(retained.len());
+        for (g, loc) in retained {
+            let bytes = cbdt.get(loc.offset..loc.offset + loc.len).ok_or("CBDT truncated")?;
+            let (format, data) = match (loc.image_format, loc.metrics) {
+                (19, Some(m)) => (18, [m.as_slice(), bytes].concat()),
+                (19, None) => return Err(format!("glyph {g}: image format 19 without index metrics")),
+                (f, _) => (f, bytes.to_vec()),
+            };
+            images.push((*g, format, data));
+        }
+
+        // One index format 1 subtable per run of consecutive glyphs sharing
+        // an image format.
+        let mut runs: Vec<&[(u16, u16, Vec<u8>)]> = Vec::new();
+        let mut run_start = 0;
+        for i in 1..=images.len() {
+            if i == images.len() || images[i].0 != images[i - 1].0 + 1 || images[i].1 != images[i - 1].1 {
+                runs.push(&images[run_start..i]);
+                run_start = i;
+            }
+        }
+        let mut array = Vec::new();
+        let mut subtables = Vec::new();
+        for run in &runs {
+            let offset_to_subtable = 8 * runs.len() + subtables.len();
+            array.extend_from_slice(&run[0].0.to_be_bytes());
+            array.extend_from_slice(&run[run.len() - 1].0.to_be_bytes());
+            array.extend_from_slice(&(offset_to_subtable as u32).to_be_bytes());
+            subtables.extend_from_slice(&1u16.to_be_bytes());
+            subtables.extend_from_slice(&run[0].1.to_be_bytes());
+            subtables.extend_from_slice(&(new_cbdt.len() as u32).to_be_bytes());
+            let mut rel = 0u32;
+            for (_, _, data) in run.iter() {
+                subtables.extend_from_slice(&rel.to_be_bytes());
+                new_cbdt.extend_from_slice(data);
+                rel += data.len() as u32;
+            }
+            subtables.extend_from_slice(&rel.to_be_bytes());
+            // Keep subtables 4-byte aligned.
+            subtables.resize(subtables.len().next_multiple_of(4), 0);
+        }
+        array.extend_from_slice(&subtables);
+
+        let mut record = record.to_vec();
+        record[4..8].copy_from_slice(&(array.len() as u32).to_be_bytes());
+        record[8..12].copy_from_slice(&(runs.len() as u32).to_be_bytes());
+        record[40..42].copy_from_slice(&images[0].0.to_be_bytes());
+        record[42..44].copy_from_slice(&images[images.len() - 1].0.to_be_bytes());
+        sizes.push((record, array));
+        report.strikes_kept.push(ppem);
+    }
+
+    let mut new_cblc = cblc.get(..4).ok_or("CBLC truncated")?.to_vec();
+    new_cblc.extend_from_slice(&(sizes.len() as u32).to_be_bytes());
+    let mut array_offset = 8 + 48 * sizes.len();
+    for (record, array) in &mut sizes {
+        record[0..4].copy_from_slice(&(array_offset as u32).to_be_bytes());
+        new_cblc.extend_from_slice(record);
+        array_offset += array.len();
+    }
+    sizes.iter().for_each(|(_, array)| new_cblc.extend_from_slice(array));
+    Ok((new_cblc, new_cbdt))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PruneQuery {
+    /// Characters whose bitmaps to keep.
+    text: String,
+    /// Comma-separated strike sizes (ppem) to remove entirely.
+    #[serde(default)]
+    drop_strikes: String,
+}
+
+/// Returns the font with sbix/CBDT bitmaps limited to the glyphs of `text`
+/// and the remaining strikes; counts are reported in `X-Bitmaps-*` headers.
+pub async fn prune_handler(
+    Query(query): Query<PruneQuery>,
+    Query(verify): Query<render::VerifyQuery>,
+    body: Body,
+) -> Result<Response, (StatusCode, String)> {
+    let drop_ppem = query
+        .drop_strikes
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<u16>().map_err(|_| (StatusCode::BAD_REQUEST, format!("invalid strike size '{s}'"))))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (data, flavor) = spool::receive_font(body).await?;
+    let unprocessable = |e: String| (StatusCode::UNPROCESSABLE_ENTITY, e);
+    let mut font = Font::parse(&data).map_err(unprocessable)?;
+    let cmap = CharMap::parse(font.table(b"cmap").ok_or("font has no cmap table".to_string()).map_err(unprocessable)?)
+        .map_err(unprocessable)?;
+    // .notdef always stays.
+    let keep: BTreeSet<u16> = std::iter::once(0).chain(query.text.chars().filter_map(|c| cmap.glyph(c))).collect();
+    let report = prune(&mut font, &keep, &drop_ppem).map_err(unprocessable)?;
+    let out = font.to_bytes();
+    let checked = render::verify(&verify, &data, &out, Some(&query.text))?;
+
+    info!(
+        kept = report.bitmaps_kept,
+        dropped = report.bitmaps_dropped,
+        strikes = ?report.strikes_kept,
+        before = data.len(),
+        after = out.len(),
+        "bitmap strikes pruned"
+    );
+    let list = |v: &[u16]| v.iter().map(u16::to_string).collect::<Vec<_>>().join(",");
+    Ok((
+        checked,
+        [
+            (header::CONTENT_TYPE, flavor.media_type().to_string()),
+            (HeaderName::from_static("x-bitmaps-kept"), report.bitmaps_kept.to_string()),
+            (HeaderName::from_static("x-bitmaps-dropped"), report.bitmaps_dropped.to_string()),
+            (HeaderName::from_static("x-strikes-kept"), list(&report.strikes_kept)),
+            (HeaderName::from_static("x-strikes-dropped"), list(&report.strikes_dropped)),
+        ],
+        out,
+    )
+        .into_response())
+}
@@ -0,0 +1,978 @@
+//! Brotli (RFC 7932) encoder for WOFF2.
+//!
+//! Greedy LZ77 over hash chains, one set of prefix codes per meta-block and
+//! no context modelling or static dictionary: a fraction of what the
+//! reference encoder does at its top levels, but a standard stream any
+//! Brotli decoder reads. `level` (0-11) sets how hard the match finder
+//! searches; 0 stores the data uncompressed.
+
+const WINDOW_BITS: u32 = 22;
+const MAX_DISTANCE: usize = (1 << WINDOW_BITS) - 16;
+const META_BLOCK_BYTES: usize = 1 << 14;
+const MIN_MATCH: usize = 4;
+const MAX_MATCH: usize = 1 << 16;
+const HASH_BITS: u32 = 17;
+const FAR_SHORT_MATCH: usize = 1 << 12;
+const LAZY_LEVEL: u8 = 4;
+
+const LITERAL_ALPHABET: usize = 256;
+const COMMAND_ALPHABET: usize = 704;
+/// 16 short codes plus 48 distance prefixes (no postfix or direct codes).
+const DISTANCE_ALPHABET: usize = 64;
+const MAX_CODE_LENGTH: u8 = 15;
+const MAX_CODE_LENGTH_CODE_LENGTH: u8 = 5;
+const CODE_LENGTH_CODE_ORDER: [usize; 18] = [1, 2, 3, 4, 0, 5, 17, 6, 16, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+/// (extra bits, base) of the 24 insert length codes.
+const INSERT_CODES: [(u32, u32); 24] = [
+    (0, 0), (0, 1), (0, 2), (0, 3), (0, 4), (0, 5), (1, 6), (1, 8),
+    (2, 10), (2, 14), (3, 18), (3, 26), (4, 34), (4, 50), (5, 66), (5, 98),
+    (6, 130), (7, 194), (8, 322), (9, 578), (10, 1090), (12, 2114), (14, 6210), (24, 22594),
+];
+
+/// (extra bits, base) of the 24 copy length codes.
+const COPY_CODES: [(u32, u32); 24] = [
+    (0, 2), (0, 3), (0, 4), (0, 5), (0, 6), (0, 7), (0, 8), (0, 9),
+    (1, 10), (1, 12), (2, 14), (2, 18), (3, 22), (3, 30), (4, 38), (4, 54),
+    (5, 70), (5, 102), (6, 134), (7, 198), (8, 326), (9, 582), (10, 1094), (24, 2118),
+];
+
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self { out: Vec::new(), acc: 0, bits: 0 }
+    }
+
+    /// Writes the low `n` bits of `value`, least significant first.
+    fn put(&mut self, n: u32, value: u64) {
+        debug_assert!(n <= 32);
+        self.acc |= (value & ((1u64 << n) - 1)) << self.bits;
+        self.bits += n;
+        while self.bits >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn align(&mut self) {
+        if self.bits > 0 {
+            self.put(8 - self.bits, 0);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.align();
+        self.out
+    }
+}
+
+/// Code lengths (at most `limit`) for `freqs`; unused symbols get 0.
+fn code_lengths(freqs: &[u32], limit: u8) -> Vec<u8> {
+    let mut freqs: Vec<u64> = freqs.iter().map(|&f| f as u64).collect();
+    loop {
+        let lengths = huffman(&freqs);
+        if lengths.iter().all(|&l| l <= limit) {
+            return lengths;
+        }
+        // Flatten the distribution until the tree is shallow enough.
+        for f in freqs.iter_mut().filter(|f| **f > 0) {
+            *f = (*f >> 1) | 1;
+        }
+    }
+}
+
+fn huffman(freqs: &[u64]) -> Vec<u8> {
+    use std::{cmp::Reverse, collections::BinaryHeap};
+    let mut lengths = vec![0u8; freqs.len()];
+    let used: Vec<usize> = (0..freqs.len()).filter(|&s| freqs[s] > 0).collect();
+    if used.len() < 2 {
+        for &s in &used {
+            lengths[s] = 1;
+        }
+        return lengths;
+    }
+    // Nodes: leaves first, then internal nodes; `parent` links give depths.
+    let mut parent = vec![usize::MAX; 2 * used.len() - 1];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = used.iter().enumerate().map(|(i, &s)| Reverse((freqs[s], i))).collect();
+    let mut next = used.len();
+    while heap.len() > 1 {
+        let Reverse((fa, a)) = heap.pop().unwrap();
+        let Reverse((fb, b)) = heap.pop().unwrap();
+        parent[a] = next;
+        parent[b] = next;
+        heap.push(Reverse((fa + fb, next)));
+        next += 1;
+    }
+    for (i, &s) in used.iter().enumerate() {
+        let (mut depth, mut node) = (0u8, i);
+        while parent[node] != usize::MAX {
+            node = parent[node];
+            depth = depth.saturating_add(1);
+        }
+        lengths[s] = depth;
+    }
+    lengths
+}
+
+/// Canonical codes for `lengths`, bit-reversed for the LSB-first stream.
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let max = lengths.iter().copied().max().unwrap_or(0) as usize;
+    let mut count = vec![0u16; max + 1];
+    for &l in lengths.iter().filter(|&&l| l > 0) {
+        count[l as usize] += 1;
+    }
+    let mut next = vec![0u16; max + 2];
+    let mut code = 0u16;
+    for bits in 1..=max {
+        code = (code + count[bits - 1]) << 1;
+        next[bits] = code;
+    }
+    lengths
+        .iter()
+        .map(|&l| {
+            if l == 0 {
+                return 0;
+            }
+            let c = next[l as usize];
+            next[l as usize] += 1;
+            c.reverse_bits() >> (16 - l as u32)
+        })
+        .collect()
+}
+
+/// A prefix code as written to and used from the stream.
+struct PrefixCode {
+    lengths: Vec<u8>,
+    codes: Vec<u16>,
+}
+
+impl PrefixCode {
+    /// Builds the code for `freqs` and writes its description.
+    fn write(w: &mut BitWriter, freqs: &[u32]) -> Self {
+        let alphabet_bits = usize::BITS - (freqs.len() - 1).leading_zeros();
+        let used: Vec<usize> = (0..freqs.len()).filter(|&s| freqs[s] > 0).collect();
+        if used.len() <= 1 {
+            // Simple prefix code with one symbol: it takes no bits at all.
+            w.put(2, 1);
+            w.put(2, 0);
+            w.put(alphabet_bits, used.first().copied().unwrap_or(0) as u64);
+            return Self { lengths: vec![0; freqs.len()], codes: vec![0; freqs.len()] };
+        }
+        let lengths = code_lengths(freqs, MAX_CODE_LENGTH);
+        let last = lengths.iter().rposition(|&l| l > 0).unwrap();
+
+        let mut cl_freqs = [0u32; 18];
+        for &l in &lengths[..=last] {
+            cl_freqs[l as usize] += 1;
+        }
+        let cl_lengths = code_lengths(&cl_freqs, MAX_CODE_LENGTH_CODE_LENGTH);
+        let cl_codes = canonical_codes(&cl_lengths);
+        let single = cl_lengths.iter().filter(|&&l| l > 0).count() == 1;
+
+        w.put(2, 0); // HSKIP
+        let mut space = 32i32;
+        for &symbol in &CODE_LENGTH_CODE_ORDER {
+            let l = cl_lengths[symbol];
+            // Static code for code length code lengths 0-5.
+            let (n, v) = [(2, 0), (4, 7), (3, 3), (2, 2), (2, 1), (4, 15)][l as usize];
+            w.put(n, v);
+            if l > 0 {
+                space -= 32 >> l;
+                if space <= 0 && !single {
+                    break;
+                }
+            }
+        }
+        for &l in &lengths[..=last] {
+            if !single {
+                w.put(cl_lengths[l as usize] as u32, cl_codes[l as usize] as u64);
+            }
+        }
+        let codes = canonical_codes(&lengths);
+        Self { lengths, codes }
+    }
+
+    fn put(&self, w: &mut BitWriter, symbol: usize) {
+        w.put(self.lengths[symbol] as u32, self.codes[symbol] as u64);
+    }
+}
+
+struct Command {
+    insert: usize,
+    copy: usize,
+    distance: usize,
+    /// `distance` is the previous command's, coded as distance code 0.
+    repeat: bool,
+}
+
+fn length_code(table: &[(u32, u32); 24], len: usize) -> usize {
+    table.iter().rposition(|&(_, base)| base as usize <= len).unwrap()
+}
+
+impl Command {
+    fn codes(&self) -> (usize, usize) {
+        // A trailing insert-only command still carries a (never used) copy length.
+        (length_code(&INSERT_CODES, self.insert), length_code(&COPY_CODES, self.copy.max(2)))
+    }
+
+    /// Whether the command symbol itself says "reuse the last distance".
+    fn implicit_distance(&self) -> bool {
+        let (ins, copy) = self.codes();
+        self.repeat && ins < 8 && copy < 16
+    }
+
+    fn symbol(&self) -> usize {
+        let (ins, copy) = self.codes();
+        let base = match (ins >> 3, copy >> 3) {
+            _ if self.implicit_distance() => (copy >> 3) * 64,
+            (0, 0) => 128,
+            (0, 1) => 192,
+            (1, 0) => 256,
+            (1, 1) => 320,
+            (0, 2) => 384,
+            (2, 0) => 448,
+            (1, 2) => 512,
+            (2, 1) => 576,
+            _ => 640,
+        };
+        base + ((ins & 7) << 3) + (copy & 7)
+    }
+
+    /// Distance code and extra bits (count, value), when one is coded.
+    fn distance_code(&self) -> Option<(usize, u32, u64)> {
+        if self.copy == 0 || self.implicit_distance() {
+            return None;
+        }
+        if self.repeat {
+            return Some((0, 0, 0));
+        }
+        let d = self.distance + 3;
+        let nbits = usize::BITS - 1 - d.leading_zeros() - 1;
+        let prefix = (d >> nbits) & 1;
+        Some((16 + 2 * (nbits as usize - 1) + prefix, nbits, (d - ((2 + prefix) << nbits)) as u64))
+    }
+}
+
+struct MatchFinder {
+    head: Vec<u32>,
+    prev: Vec<u32>,
+    depth: usize,
+}
+
+impl MatchFinder {
+    fn new(len: usize, depth: usize) -> Self {
+        Self { head: vec![u32::MAX; 1 << HASH_BITS], prev: vec![u32::MAX; len], depth }
+    }
+
+    fn hash(data: &[u8], at: usize) -> usize {
+        let v = u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+        (v.wrapping_mul(0x1E35_A7BD) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, data: &[u8], at: usize) {
+        if at + MIN_MATCH <= data.len() {
+            let h = Self::hash(data, at);
+            self.prev[at] = self.head[h];
+            self.head[h] = at as u32;
+        }
+    }
+
+    /// Longest match for `at` within `end`, as (length, distance); ties go
+    /// to `last`, the cheapest distance to code.
+    fn find(&self, data: &[u8], at: usize, end: usize, last: usize) -> Option<(usize, usize)> {
+        if at + MIN_MATCH > end {
+            return None;
+        }
+        let limit = (end - at).min(MAX_MATCH);
+        let matching = |from: usize| data[from..].iter().zip(&data[at..at + limit]).take_while(|(a, b)| a == b).count();
+        let mut best = (last <= at)
+            .then(|| matching(at - last))
+            .filter(|&len| len >= MIN_MATCH)
+            .map(|len| (len, last));
+        let mut candidate = self.head[Self::hash(data, at)];
+        for _ in 0..self.depth {
+            if candidate == u32::MAX {
+                break;
+            }
+            let c = candidate as usize;
+            if at - c > MAX_DISTANCE {
+                break;
+            }
+            let len = matching(c);
+            // A short match far back costs more bits than its literals.
+            let worth = len > MIN_MATCH || (len == MIN_MATCH && at - c <= FAR_SHORT_MATCH);
+            if worth && best.is_none_or(|(l, _)| len > l) {
+                best = Some((len, at - c));
+                if len == limit {
+                    break;
+                }
+            }
+            candidate = self.prev[c];
+        }
+        best
+    }
+}
+
+fn write_header(w: &mut BitWriter, len: usize, uncompressed: bool) {
+    w.put(1, 0); // ISLAST
+    let nibbles = match len - 1 {
+        n if n < 1 << 16 => 4,
+        n if n < 1 << 20 => 5,
+        _ => 6,
+    };
+    w.put(2, nibbles - 4);
+    w.put(4 * nibbles as u32, (len - 1) as u64);
+    w.put(1, uncompressed as u64);
+}
+
+fn write_meta_block(w: &mut BitWriter, data: &[u8], start: usize, commands: &[Command]) {
+    let mut literal_freqs = vec![0u32; LITERAL_ALPHABET];
+    let mut command_freqs = vec![0u32; COMMAND_ALPHABET];
+    let mut distance_freqs = vec![0u32; DISTANCE_ALPHABET];
+    let mut at = start;
+    for c in commands {
+        command_freqs[c.symbol()] += 1;
+        for &b in &data[at..at + c.insert] {
+            literal_freqs[b as usize] += 1;
+        }
+        if let Some((code, _, _)) = c.distance_code() {
+            distance_freqs[code] += 1;
+        }
+        at += c.insert + c.copy;
+    }
+
+    write_header(w, at - start, false);
+    w.put(1, 0); // NBLTYPESL = 1
+    w.put(1, 0); // NBLTYPESI = 1
+    w.put(1, 0); // NBLTYPESD = 1
+    w.put(2, 0); // NPOSTFIX
+    w.put(4, 0); // NDIRECT
+    w.put(2, 0); // literal context mode
+    w.put(1, 0); // NTREESL = 1
+    w.put(1, 0); // NTREESD = 1
+    let literals = PrefixCode::write(w, &literal_freqs);
+    let symbols = PrefixCode::write(w, &command_freqs);
+    let distances = PrefixCode::write(w, &distance_freqs);
+
+    let mut at = start;
+    for c in commands {
+        let (ins, copy) = c.codes();
+        symbols.put(w, c.symbol());
+        w.put(INSERT_CODES[ins].0, (c.insert - INSERT_CODES[ins].1 as usize) as u64);
+        w.put(COPY_CODES[copy].0, (c.copy.max(2) - COPY_CODES[copy].1 as usize) as u64);
+        for &b in &data[at..at + c.insert] {
+            literals.put(w, b as usize);
+        }
+        if let Some((code, nbits, extra)) = c.distance_code() {
+            distances.put(w, code);
+            w.put(nbits, extra);
+        }
+        at += c.insert + c.copy;
+    }
+}
+
+/// Compresses `data` at `level` (0-11).
+pub fn compress(data: &[u8], level: u8) -> Vec<u8> {
+    let mut w = BitWriter::new();
+    // WBITS 22: a set bit, then 22 - 17 in three bits.
+    w.put(1, 1);
+    w.put(3, (WINDOW_BITS - 17) as u64);
+
+    if level == 0 {
+        for chunk in data.chunks(META_BLOCK_BYTES) {
+            write_header(&mut w, chunk.len(), true);
+            w.align();
+            w.out.extend_from_slice(chunk);
+        }
+    } else {
+        let mut finder = MatchFinder::new(data.len(), 1 << (level.min(11) - 1));
+        // Last entry of the initial distance ring buffer (16, 15, 11, 4).
+        let mut last_distance = 4;
+        let mut start = 0;
+        while start < data.len() {
+            let end = (start + META_BLOCK_BYTES).min(data.len());
+            let mut commands = Vec::new();
+            let (mut at, mut literal_start) = (start, start);
+            while at < end {
+                let found = finder.find(data, at, end, last_distance);
+                // Lazy matching: a longer match one byte on wins over this one.
+                if let (Some((len, _)), true) = (found, level >= LAZY_LEVEL) {
+                    if finder.find(data, at + 1, end, last_distance).is_some_and(|(next, _)| next > len) {
+                        finder.insert(data, at);
+                        at += 1;
+                        continue;
+                    }
+                }
+                match found {
+                    Some((len, distance)) => {
+                        let repeat = distance == last_distance;
+                        commands.push(Command { insert: at - literal_start, copy: len, distance, repeat });
+                        last_distance = distance;
+                        for p in at..at + len {
+                            finder.insert(data, p);
+                        }
+                        at += len;
+                        literal_start = at;
+                    }
+                    None => {
+                        finder.insert(data, at);
+                        at += 1;
+                    }
+                }
+            }
+            if literal_start < end {
+                commands.push(Command { insert: end - literal_start, copy: 0, distance: 0, repeat: false });
+            }
+            write_meta_block(&mut w, data, start, &commands);
+            start = end;
+        }
+    }
+    // Final empty meta-block: ISLAST, ISLASTEMPTY.
+    w.put(2, 3);
+    w.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Brotli decoder written from RFC 7932 and sharing nothing with the
+    /// encoder above. It reads every stream the format allows except
+    /// static dictionary references and multi-tree UTF-8 literal contexts,
+    /// neither of which the encoder emits.
+    mod reference {
+        struct Bits<'a> {
+            data: &'a [u8],
+            at: usize,
+        }
+
+        impl Bits<'_> {
+            fn bit(&mut self) -> Result<u32, String> {
+                let byte = self.data.get(self.at / 8).ok_or("stream ends early")?;
+                let bit = (byte >> (self.at % 8)) & 1;
+                self.at += 1;
+                Ok(bit as u32)
+            }
+
+            fn read(&mut self, n: u32) -> Result<u32, String> {
+                (0..n).try_fold(0, |value, i| Ok(value | self.bit()? << i))
+            }
+
+            /// Skips to the next byte; the skipped bits must be zero.
+            fn align(&mut self) -> Result<(), String> {
+                while !self.at.is_multiple_of(8) {
+                    if self.bit()? != 0 {
+                        return Err("non-zero padding".into());
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        /// A canonical prefix code, decoded a bit at a time.
+        struct Code {
+            count: [u16; 16],
+            symbols: Vec<u16>,
+        }
+
+        impl Code {
+            fn from_lengths(lengths: &[u8]) -> Result<Self, String> {
+                let mut count = [0u16; 16];
+                for &l in lengths {
+                    count[l as usize] += 1;
+                }
+                count[0] = 0;
+                let mut symbols = Vec::new();
+                for len in 1..16 {
+                    symbols.extend((0..lengths.len()).filter(|&s| lengths[s] == len).map(|s| s as u16));
+                }
+                if symbols.is_empty() {
+                    return Err("empty prefix code".into());
+                }
+                Ok(Self { count, symbols })
+            }
+
+            fn single(symbol: u16) -> Self {
+                Self { count: [0; 16], symbols: vec![symbol] }
+            }
+
+            fn decode(&self, bits: &mut Bits) -> Result<usize, String> {
+                if self.count.iter().all(|&c| c == 0) {
+                    return Ok(self.symbols[0] as usize);
+                }
+                let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+                for len in 1..16 {
+                    code |= bits.bit()? as i32;
+                    let count = self.count[len] as i32;
+                    if code - count < first {
+                        return Ok(self.symbols[(index + code - first) as usize] as usize);
+                    }
+                    index += count;
+                    first = (first + count) << 1;
+                    code <<= 1;
+                }
+                Err("invalid prefix code".into())
+            }
+        }
+
+        fn alphabet_bits(size: usize) -> u32 {
+            usize::BITS - (size - 1).leading_zeros()
+        }
+
+        fn read_code(bits: &mut Bits, size: usize) -> Result<Code, String> {
+            let hskip = bits.read(2)?;
+            if hskip == 1 {
+                let nsym = bits.read(2)? as usize + 1;
+                let mut symbols = Vec::with_capacity(nsym);
+                for _ in 0..nsym {
+                    let symbol = bits.read(alphabet_bits(size))? as usize;
+                    if symbol >= size || symbols.contains(&symbol) {
+                        return Err("bad simple prefix code".into());
+                    }
+                    symbols.push(symbol);
+                }
+                let shape: &[u8] = match nsym {
+                    1 => return Ok(Code::single(symbols[0] as u16)),
+                    2 => &[1, 1],
+                    3 => &[1, 2, 2],
+                    _ if bits.bit()? == 0 => &[2, 2, 2, 2],
+                    _ => &[1, 2, 3, 3],
+                };
+                // Equal lengths take codes in symbol order.
+                let mut lengths = vec![0u8; size];
+                let mut order: Vec<(u8, usize)> = shape.iter().copied().zip(symbols).collect();
+                order.sort();
+                for (len, symbol) in order {
+                    lengths[symbol] = len;
+                }
+                return Code::from_lengths(&lengths);
+            }
+
+            const ORDER: [usize; 18] = [1, 2, 3, 4, 0, 5, 17, 6, 16, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+            let mut cl_lengths = [0u8; 18];
+            let (mut space, mut used) = (32i32, 0);
+            for &symbol in &ORDER[hskip as usize..] {
+                // The fixed code for code length code lengths.
+                let len = match bits.read(2)? {
+                    0 => 0,
+                    1 => 4,
+                    2 => 3,
+                    _ if bits.bit()? == 0 => 2,
+                    _ if bits.bit()? == 0 => 1,
+                    _ => 5,
+                };
+                cl_lengths[symbol] = len;
+                if len > 0 {
+                    space -= 32 >> len;
+                    used += 1;
+                    if space <= 0 {
+                        break;
+                    }
+                }
+            }
+            if used != 1 && space != 0 {
+                return Err("code length code is incomplete".into());
+            }
+            let cl_code = match used {
+                1 => Code::single(cl_lengths.iter().position(|&l| l > 0).unwrap() as u16),
+                _ => Code::from_lengths(&cl_lengths)?,
+            };
+
+            let mut lengths = vec![0u8; size];
+            let (mut symbol, mut space) = (0, 32768i32);
+            let (mut previous, mut repeat, mut repeat_len) = (8u8, 0usize, 0u8);
+            while symbol < size && space > 0 {
+                let code = cl_code.decode(bits)?;
+                if code < 16 {
+                    lengths[symbol] = code as u8;
+                    symbol += 1;
+                    repeat = 0;
+                    if code > 0 {
+                        previous = code as u8;
+                        space -= 32768 >> code;
+                    }
+                    continue;
+                }
+                let (extra, len) = if code == 16 { (2, previous) } else { (3, 0) };
+                if repeat_len != len {
+                    repeat = 0;
+                    repeat_len = len;
+                }
+                let before = repeat;
+                if repeat > 0 {
+                    repeat = (repeat - 2) << extra;
+                }
+                repeat += bits.read(extra)? as usize + 3;
+                let run = repeat - before;
+                if symbol + run > size {
+                    return Err("code length run overflows the alphabet".into());
+                }
+                for _ in 0..run {
+                    lengths[symbol] = len;
+                    symbol += 1;
+                    if len > 0 {
+                        space -= 32768 >> len;
+                    }
+                }
+            }
+            if space != 0 {
+                return Err("prefix code is incomplete".into());
+            }
+            Code::from_lengths(&lengths)
+        }
+
+        /// NBLTYPES, NTREES and similar 1-256 counts.
+        fn read_count(bits: &mut Bits) -> Result<usize, String> {
+            if bits.bit()? == 0 {
+                return Ok(1);
+            }
+            let n = bits.read(3)?;
+            Ok((1 << n) + bits.read(n)? as usize + 1)
+        }
+
+        /// (base, extra bits) for `extras`, the first starting at `base`.
+        fn ranges(mut base: u32, extras: &[u32]) -> Vec<(u32, u32)> {
+            extras
+                .iter()
+                .map(|&extra| {
+                    let range = (base, extra);
+                    base += 1 << extra;
+                    range
+                })
+                .collect()
+        }
+
+        struct Blocks {
+            types: usize,
+            type_code: Option<Code>,
+            count_code: Option<Code>,
+            current: usize,
+            ring: [usize; 2],
+            left: u32,
+        }
+
+        impl Blocks {
+            fn read(bits: &mut Bits) -> Result<Self, String> {
+                let types = read_count(bits)?;
+                let mut blocks =
+                    Self { types, type_code: None, count_code: None, current: 0, ring: [1, 0], left: 1 << 24 };
+                if types >= 2 {
+                    blocks.type_code = Some(read_code(bits, types + 2)?);
+                    blocks.count_code = Some(read_code(bits, 26)?);
+                    blocks.left = blocks.count(bits)?;
+                }
+                Ok(blocks)
+            }
+
+            fn count(&self, bits: &mut Bits) -> Result<u32, String> {
+                let extras = [2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 6, 6, 7, 8, 9, 10, 11, 12, 13, 24];
+                let (base, extra) = ranges(1, &extras)[self.count_code.as_ref().unwrap().decode(bits)?];
+                Ok(base + bits.read(extra)?)
+            }
+
+            /// The block type for the next symbol.
+            fn next(&mut self, bits: &mut Bits) -> Result<usize, String> {
+                if self.left == 0 {
+                    let code = self.type_code.as_ref().ok_or("block count overrun")?.decode(bits)?;
+                    let block_type = match code {
+                        0 => self.ring[0],
+                        1 => self.ring[1] + 1,
+                        n => n - 2,
+                    } % self.types;
+                    self.ring = [self.ring[1], block_type];
+                    self.current = block_type;
+                    self.left = self.count(bits)?;
+                }
+                self.left -= 1;
+                Ok(self.current)
+            }
+        }
+
+        fn read_context_map(bits: &mut Bits, size: usize, trees: usize) -> Result<Vec<usize>, String> {
+            if trees < 2 {
+                return Ok(vec![0; size]);
+            }
+            let rle_max = if bits.bit()? == 1 { bits.read(4)? as usize + 1 } else { 0 };
+            let code = read_code(bits, trees + rle_max)?;
+            let mut map = Vec::with_capacity(size);
+            while map.len() < size {
+                match code.decode(bits)? {
+                    0 => map.push(0),
+                    n if n <= rle_max => {
+                        let run = (1 << n) + bits.read(n as u32)? as usize;
+                        map.extend(std::iter::repeat_n(0, run));
+                    }
+                    n => map.push(n - rle_max),
+                }
+            }
+            if map.len() > size {
+                return Err("context map run overflows".into());
+            }
+            if bits.bit()? == 1 {
+                // Inverse move-to-front.
+                let mut mtf: Vec<usize> = (0..256).collect();
+                for value in &mut map {
+                    let index = *value;
+                    *value = mtf.remove(index);
+                    mtf.insert(0, *value);
+                }
+            }
+            if map.iter().any(|&t| t >= trees) {
+                return Err("context map names a missing tree".into());
+            }
+            Ok(map)
+        }
+
+        /// Signed-mode context of a byte.
+        fn signed(byte: u8) -> usize {
+            match byte {
+                0 => 0,
+                1..=15 => 1,
+                16..=63 => 2,
+                64..=127 => 3,
+                128..=191 => 4,
+                192..=239 => 5,
+                240..=254 => 6,
+                255 => 7,
+            }
+        }
+
+        pub fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+            let insert_codes =
+                ranges(0, &[0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 7, 8, 9, 10, 12, 14, 24]);
+            let copy_codes = ranges(2, &[0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 7, 8, 9, 10, 24]);
+            let bits = &mut Bits { data, at: 0 };
+            let window_bits = match bits.bit()? {
+                0 => 16,
+                _ => match bits.read(3)? {
+                    0 => match bits.read(3)? {
+                        0 => 17,
+                        1 => return Err("large window streams are not standard Brotli".into()),
+                        n => 8 + n,
+                    },
+                    n => 17 + n,
+                },
+            };
+            let max_distance = (1usize << window_bits) - 16;
+            let mut out: Vec<u8> = Vec::new();
+            let mut distances = [16usize, 15, 11, 4];
+            loop {
+                let last = bits.bit()? == 1;
+                if last && bits.bit()? == 1 {
+                    break;
+                }
+                let nibbles = match bits.read(2)? {
+                    3 => {
+                        if bits.bit()? != 0 {
+                            return Err("reserved bit set".into());
+                        }
+                        let skip_bytes = bits.read(2)?;
+                        let skip = if skip_bytes == 0 { 0 } else { bits.read(8 * skip_bytes)? as usize + 1 };
+                        bits.align()?;
+                        bits.at += 8 * skip;
+                        if last {
+                            break;
+                        }
+                        continue;
+                    }
+                    n => n + 4,
+                };
+                let len = bits.read(4 * nibbles)? as usize + 1;
+                let end = out.len() + len;
+                if !last && bits.bit()? == 1 {
+                    bits.align()?;
+                    let start = bits.at / 8;
+                    out.extend_from_slice(data.get(start..start + len).ok_or("stored block ends early")?);
+                    bits.at += 8 * len;
+                    continue;
+                }
+
+                let mut literal_blocks = Blocks::read(bits)?;
+                let mut command_blocks = Blocks::read(bits)?;
+                let mut distance_blocks = Blocks::read(bits)?;
+                let postfix = bits.read(2)?;
+                let direct = (bits.read(4)? << postfix) as usize;
+                let modes: Vec<u32> = (0..literal_blocks.types).map(|_| bits.read(2)).collect::<Result<_, _>>()?;
+                let literal_trees = read_count(bits)?;
+                let literal_map = read_context_map(bits, 64 * literal_blocks.types, literal_trees)?;
+                let distance_trees = read_count(bits)?;
+                let distance_map = read_context_map(bits, 4 * distance_blocks.types, distance_trees)?;
+                let literal_codes: Vec<Code> =
+                    (0..literal_trees).map(|_| read_code(bits, 256)).collect::<Result<_, _>>()?;
+                let command_codes: Vec<Code> =
+                    (0..command_blocks.types).map(|_| read_code(bits, 704)).collect::<Result<_, _>>()?;
+                let distance_alphabet = 16 + direct + (48 << postfix);
+                let distance_codes: Vec<Code> =
+                    (0..distance_trees).map(|_| read_code(bits, distance_alphabet)).collect::<Result<_, _>>()?;
+
+                while out.len() < end {
+                    let command = command_codes[command_blocks.next(bits)?].decode(bits)?;
+                    let (insert_base, copy_base) =
+                        [(0, 0), (0, 8), (0, 0), (0, 8), (8, 0), (8, 8), (0, 16), (16, 0), (8, 16), (16, 8), (16, 16)]
+                            [command >> 6];
+                    let (insert, extra) = insert_codes[insert_base + (command >> 3 & 7)];
+                    let insert = (insert + bits.read(extra)?) as usize;
+                    let (copy, extra) = copy_codes[copy_base + (command & 7)];
+                    let copy = (copy + bits.read(extra)?) as usize;
+
+                    for _ in 0..insert {
+                        let block = literal_blocks.next(bits)?;
+                        let p1 = out.last().copied().unwrap_or(0);
+                        let p2 = out.len().checked_sub(2).map_or(0, |i| out[i]);
+                        let context = match modes[block] {
+                            0 => (p1 & 0x3f) as usize,
+                            1 => (p1 >> 2) as usize,
+                            3 => signed(p1) << 3 | signed(p2),
+                            _ if literal_trees == 1 => 0,
+                            _ => return Err("multi-tree UTF-8 literal contexts are not supported".into()),
+                        };
+                        out.push(literal_codes[literal_map[64 * block + context]].decode(bits)? as u8);
+                    }
+                    if out.len() > end {
+                        return Err("literals overrun the meta-block".into());
+                    }
+                    if out.len() == end {
+                        break;
+                    }
+
+                    let code = if command < 128 {
+                        0
+                    } else {
+                        let block = distance_blocks.next(bits)?;
+                        let context = copy.min(5).saturating_sub(2);
+                        distance_codes[distance_map[4 * block + context]].decode(bits)?
+                    };
+                    let distance = match code {
+                        0..=3 => distances[3 - code],
+                        4..=15 => {
+                            let base = distances[3 - (code - 4) / 6];
+                            let delta = [-1, 1, -2, 2, -3, 3][(code - 4) % 6];
+                            usize::try_from(base as i64 + delta).map_err(|_| "negative distance")?
+                        }
+                        _ if code < 16 + direct => code - 15,
+                        _ => {
+                            let code = code - 16 - direct;
+                            let nbits = 1 + (code >> (postfix + 1)) as u32;
+                            let high = code >> postfix;
+                            let low = code & ((1 << postfix) - 1);
+                            let offset = ((2 + (high & 1)) << nbits) - 4;
+                            ((offset + bits.read(nbits)? as usize) << postfix) + low + direct + 1
+                        }
+                    };
+                    if distance == 0 || distance > out.len().min(max_distance) {
+                        return Err(format!("distance {distance} reaches into the static dictionary"));
+                    }
+                    if code != 0 {
+                        distances = [distances[1], distances[2], distances[3], distance];
+                    }
+                    if out.len() + copy > end {
+                        return Err("copy overruns the meta-block".into());
+                    }
+                    for _ in 0..copy {
+                        out.push(out[out.len() - distance]);
+                    }
+                }
+                if last {
+                    break;
+                }
+            }
+            bits.align()?;
+            if bits.at / 8 != data.len() {
+                return Err("data after the last meta-block".into());
+            }
+            Ok(out)
+        }
+    }
+
+    /// Deterministic noise.
+    fn noise(len: usize, mut seed: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect()
+    }
+
+    /// Font-like data: repeated records, text, runs, noise and a match of
+    /// every length from 4 to 300, over several meta-blocks.
+    fn sample() -> Vec<u8> {
+        let mut data = Vec::new();
+        for len in 4..=300 {
+            let chunk = noise(len + 8, len as u32);
+            data.extend_from_slice(&chunk);
+            data.extend_from_slice(&chunk[..len]);
+        }
+        for i in 0..2000u32 {
+            data.extend_from_slice(&[0, 1, (i >> 8) as u8, i as u8, 0, 0, 0x40, (i % 7) as u8]);
+        }
+        data.extend(b"The quick brown fox jumps over the lazy dog. ".repeat(300));
+        data.extend(std::iter::repeat_n(0, 5000));
+        data.extend(noise(3000, 0x2545_F491));
+        data.extend_from_within(..10_000);
+        data
+    }
+
+    #[test]
+    fn reference_decoder_reads_reference_streams() {
+        // empty.compressed and x.compressed from the reference test data.
+        assert_eq!(reference::decompress(&[0x06]).unwrap(), b"");
+        assert_eq!(reference::decompress(&[0x0b, 0x00, 0x80, 0x58, 0x03]).unwrap(), b"X");
+    }
+
+    #[test]
+    fn round_trips_at_every_level() {
+        let data = sample();
+        for level in 0..=11 {
+            let compressed = compress(&data, level);
+            assert_eq!(reference::decompress(&compressed).unwrap(), data, "level {level}");
+            if level > 0 {
+                assert!(compressed.len() < data.len() / 2, "level {level}: {} bytes", compressed.len());
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_empty_input() {
+        for level in [0, 1, 11] {
+            assert_eq!(reference::decompress(&compress(&[], level)).unwrap(), b"", "level {level}");
+        }
+    }
+
+    #[test]
+    fn round_trips_incompressible_input() {
+        let data = noise(100_000, 0x9E37_79B9);
+        for level in [0, 1, 5, 11] {
+            let compressed = compress(&data, level);
+            assert_eq!(reference::decompress(&compressed).unwrap(), data, "level {level}");
+            assert!(compressed.len() < data.len() + data.len() / 50, "level {level}: {} bytes", compressed.len());
+        }
+    }
+
+    #[test]
+    fn round_trips_input_over_16_mib() {
+        // A 1 MiB block at shifting offsets: matches lie about 1 MiB back,
+        // and the same copy of it recurs only beyond the 4 MiB window.
+        let block = noise(1 << 20, 0x1234_5678);
+        let mut data = Vec::with_capacity((17 << 20) + 1000);
+        for i in 0..17 {
+            data.extend_from_slice(&block[(i % 5) * 1000..]);
+            if i % 3 == 0 {
+                data.extend(noise(1000, i as u32 + 1));
+            }
+        }
+        assert!(data.len() > 16 << 20);
+        for level in [0, 1] {
+            let compressed = compress(&data, level);
+            assert_eq!(reference::decompress(&compressed).unwrap(), data, "level {level}");
+        }
+    }
+}
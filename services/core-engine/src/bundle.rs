@@ -0,0 +1,186 @@
+//! `GET /api/v1/font/:family/bundle.zip`: a family's download kit.
+//!
+//! Every weight and style of the family, in each of `?formats=` (default
+//! `woff2,woff,ttf`; `ttf` and `otf` both mean the face's own outlines),
+//! goes under `<family>/fonts/`, next to `<family>.css` declaring them by
+//! relative URL and `LICENSE.txt` with each face's license, copyright and the
+//! license text and URL its `name` table carries. `?charset=latin` (a
+//! [`unicode::preset`] name) subsets every face first, and the stylesheet
+//! gets matching `unicode-range`s. Faces are encoded at their catalog
+//! default quality, 100 otherwise.
+//!
+//! Files of private families are only served through signed URLs, so they
+//! have no bundle.
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+};
+use serde::Deserialize;
+use std::{collections::BTreeSet, ops::RangeInclusive, sync::Arc};
+use tracing::info;
+
+use crate::{
+    artifacts, cancel, compress, duplicates, licensing, sfnt::Font, stylesheet, subset, unicode, zip, AppState,
+    FontCatalogEntry,
+};
+
+const FORMATS: &[&str] = &["woff2", "woff", "ttf", "otf"];
+const DEFAULT_FORMATS: &str = "woff2,woff,ttf";
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BundleQuery {
+    formats: Option<String>,
+    /// Unicode preset to subset every face to.
+    charset: Option<String>,
+}
+
+fn bad_request(message: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message)
+}
+
+/// `formats=` in the caller's order, `ttf` and `otf` merged as `sfnt`.
+fn parse_formats(spec: &str) -> Result<Vec<&'static str>, (StatusCode, String)> {
+    let mut formats = Vec::new();
+    for f in spec.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        let format = match FORMATS.iter().find(|v| v.eq_ignore_ascii_case(f)) {
+            Some(&("ttf" | "otf")) => "sfnt",
+            Some(format) => format,
+            None => return Err(bad_request(format!("format '{f}' must be one of: {}", FORMATS.join(", ")))),
+        };
+        if !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+    if formats.is_empty() {
+        return Err(bad_request("formats must name at least one format".to_string()));
+    }
+    Ok(formats)
+}
+
+/// One face's part of the license file.
+fn license_text(entry: &FontCatalogEntry, font: &Font) -> String {
+    let embedded = licensing::read(font);
+    let mut text = format!("License: {}\n", entry.license);
+    for line in [&embedded.copyright, &embedded.url, &embedded.description].into_iter().flatten() {
+        text.push('\n');
+        text.push_str(line.trim());
+        text.push('\n');
+    }
+    text
+}
+
+/// Faces sharing license text are listed together under it.
+fn license_file(family: &str, licenses: Vec<(String, String)>) -> String {
+    let mut groups: Vec<(Vec<String>, String)> = Vec::new();
+    for (face, text) in licenses {
+        match groups.iter_mut().find(|(_, t)| *t == text) {
+            Some((faces, _)) => faces.push(face),
+            None => groups.push((vec![face], text)),
+        }
+    }
+    let sections: Vec<String> =
+        groups.into_iter().map(|(faces, text)| format!("{}\n{text}", faces.join(", "))).collect();
+    format!("{family}\n\n{}", sections.join("\n----------------------------------------\n\n"))
+}
+
+pub async fn bundle(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(family): Path<String>,
+    Query(query): Query<BundleQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    state.flags.ensure("compress", &headers)?;
+    let formats = parse_formats(query.formats.as_deref().unwrap_or(DEFAULT_FORMATS))?;
+    let charset: Option<Vec<RangeInclusive<u32>>> = match &query.charset {
+        Some(name) => Some(unicode::preset(name).ok_or_else(|| {
+            bad_request(format!("unknown charset '{name}'; valid: {}", unicode::preset_names()))
+        })?),
+        None => None,
+    };
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &family)?;
+    let slug = family.to_lowercase().replace(' ', "-");
+    let mut members: Vec<FontCatalogEntry> = state
+        .sandbox
+        .visible(&headers, state.catalog.read().unwrap().clone())
+        .into_iter()
+        .filter(|e| e.family.to_lowercase().replace(' ', "-") == slug)
+        .collect();
+    if members.is_empty() {
+        return Err((StatusCode::NOT_FOUND, format!("no catalog family '{family}'")));
+    }
+    if members.iter().any(|e| e.private) {
+        return Err((StatusCode::FORBIDDEN, format!("'{family}' is private; its files need signed URLs")));
+    }
+    members.sort_by(|a, b| a.id.cmp(&b.id));
+    let dir = duplicates::catalog_font_dir()
+        .ok_or_else(|| (StatusCode::UNPROCESSABLE_ENTITY, "CATALOG_FONT_DIR is not configured".to_string()))?;
+    let mut faces = Vec::with_capacity(members.len());
+    for entry in members {
+        let path = duplicates::font_path(&dir, &entry.id).ok_or_else(|| {
+            (StatusCode::UNPROCESSABLE_ENTITY, format!("no binary for '{}' in CATALOG_FONT_DIR", entry.id))
+        })?;
+        let quality = state.defaults_for(&entry.id).quality.unwrap_or(100);
+        faces.push((entry, path, quality));
+    }
+    state.quotas.charge_operation(&headers)?;
+
+    let name = artifacts::slug(&faces[0].0.family);
+    let (job_name, job_family) = (name.clone(), faces[0].0.family.clone());
+    let (files, zipped) = cancel::run(&state.jobs, "bundle", move |token| {
+        let wanted: Option<BTreeSet<u32>> = charset.as_ref().map(|r| r.iter().cloned().flatten().collect());
+        let (mut files, mut css, mut licenses) = (Vec::new(), Vec::new(), Vec::new());
+        for (entry, path, quality) in &faces {
+            token.check()?;
+            let data = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()));
+            let mut font = match data.and_then(|data| Font::parse(&data)) {
+                Ok(font) => font,
+                Err(e) => return Ok(Err(e)),
+            };
+            licenses.push((format!("{} {}", entry.family, entry.variant), license_text(entry, &font)));
+            let mut range = None;
+            if let (Some(wanted), Some(ranges)) = (&wanted, &charset) {
+                if let Err(e) = subset::subset(&mut font, wanted, true, false) {
+                    return Ok(Err(format!("{}: {e}", entry.id)));
+                }
+                let covered = unicode::parse_ranges(&entry.unicode_ranges);
+                let ranges = if covered.is_empty() { ranges.clone() } else { unicode::intersect(&covered, ranges) };
+                range = Some(unicode::css_unicode_range(&ranges));
+            }
+            let mut urls = Vec::new();
+            for &format in &formats {
+                let format = match format {
+                    "sfnt" if font.table(b"CFF ").is_some() || font.table(b"CFF2").is_some() => "otf",
+                    "sfnt" => "ttf",
+                    format => format,
+                };
+                let encoded = match compress::encode(&font, format, *quality) {
+                    Ok(encoded) => encoded,
+                    Err(e) => return Ok(Err(format!("{}: {e}", entry.id))),
+                };
+                let url = format!("fonts/{}.{format}", entry.id);
+                files.push((format!("{job_name}/{url}"), encoded));
+                urls.push(url);
+            }
+            css.push(stylesheet::kit_face(entry, &urls, range.as_deref()));
+        }
+        let credits: BTreeSet<&str> = faces.iter().filter_map(|(e, ..)| e.attribution.as_deref()).collect();
+        let comments: String =
+            credits.iter().map(|text| format!("/* {job_family}: {} */\n", text.trim().replace("*/", "* /"))).collect();
+        files.push((format!("{job_name}/{job_name}.css"), format!("{comments}{}", css.join("\n")).into_bytes()));
+        files.push((format!("{job_name}/LICENSE.txt"), license_file(&job_family, licenses).into_bytes()));
+        Ok(zip::write(&files).map(|zipped| (files.len(), zipped)))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    info!(family = %family, files, bytes = zipped.len(), charset = ?query.charset, "family bundle");
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{name}.zip\""))
+        .header(header::CONTENT_LENGTH, zipped.len())
+        .body(Body::from(zipped))
+        .unwrap())
+}
@@ -0,0 +1,129 @@
+//! Recently generated subsets, kept in memory.
+//!
+//! Identical subset requests (same font binary, characters, format and
+//! options) are answered from [`SubsetCache`] instead of being subset again.
+//! Entries are keyed by their artifact address (see
+//! [`crate::artifacts::Address`]), which covers the source font's hash, so a
+//! replaced binary is never answered from stale output. They are evicted
+//! least recently used first once their output exceeds `SUBSET_CACHE_BYTES`
+//! (64 MiB by default; `0` disables the cache). Hits, misses and the cache's
+//! size are exported as metrics.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use crate::{subset, telemetry};
+
+const DEFAULT_BUDGET: usize = 64 << 20;
+
+/// What a subset request reports, also stored with the artifact.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SubsetRecord {
+    pub original_bytes: usize,
+    pub output_bytes: usize,
+    pub report: subset::Report,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_features: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shaping: Option<font_api::ShapingReport>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pdf: Option<font_api::PdfSubset>,
+}
+
+/// A subset as the handler produced it.
+pub struct Generated {
+    pub record: SubsetRecord,
+    pub encoded: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, (Arc<Generated>, u64)>,
+    /// Last use → key, oldest first.
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: usize,
+}
+
+impl Lru {
+    fn touch(&mut self, key: &str) -> Option<Arc<Generated>> {
+        self.tick += 1;
+        let (value, used) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        *used = self.tick;
+        self.order.insert(self.tick, key.to_string());
+        Some(Arc::clone(value))
+    }
+}
+
+pub struct SubsetCache {
+    budget: usize,
+    lru: Mutex<Lru>,
+}
+
+impl SubsetCache {
+    pub fn from_env() -> Self {
+        let budget = std::env::var("SUBSET_CACHE_BYTES").ok().and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_BUDGET);
+        Self { budget, lru: Mutex::default() }
+    }
+
+    /// The byte budget, and the bytes and entries held.
+    pub fn usage(&self) -> (usize, usize, usize) {
+        let lru = self.lru.lock().unwrap();
+        (self.budget, lru.bytes, lru.entries.len())
+    }
+
+    /// Fails once a panic while holding the lock has left the cache unusable.
+    pub fn check(&self) -> Result<(), String> {
+        self.lru.lock().map(drop).map_err(|_| "cache lock poisoned".to_string())
+    }
+
+    pub fn get(&self, key: &str) -> Option<Arc<Generated>> {
+        if self.budget == 0 {
+            return None;
+        }
+        let hit = self.lru.lock().unwrap().touch(key);
+        telemetry::record_cache("subset", hit.is_some());
+        hit
+    }
+
+    pub fn insert(&self, key: String, value: Arc<Generated>) {
+        let size = value.encoded.len();
+        if size > self.budget {
+            return;
+        }
+        let mut lru = self.lru.lock().unwrap();
+        if lru.touch(&key).is_some() {
+            return;
+        }
+        while lru.bytes + size > self.budget {
+            let Some((_, oldest)) = lru.order.pop_first() else { break };
+            if let Some((evicted, _)) = lru.entries.remove(&oldest) {
+                lru.bytes -= evicted.encoded.len();
+            }
+        }
+        let tick = lru.tick;
+        lru.order.insert(tick, key.clone());
+        lru.entries.insert(key, (value, tick));
+        lru.bytes += size;
+        telemetry::record_cache_size("subset", lru.bytes, lru.entries.len());
+    }
+
+    /// Drops the entries whose key `purged` accepts; returns how many.
+    pub fn evict(&self, purged: impl Fn(&str) -> bool) -> usize {
+        let mut lru = self.lru.lock().unwrap();
+        let keys: Vec<(String, u64)> =
+            lru.entries.iter().filter(|(key, _)| purged(key)).map(|(key, (_, used))| (key.clone(), *used)).collect();
+        for (key, used) in &keys {
+            lru.order.remove(used);
+            if let Some((evicted, _)) = lru.entries.remove(key) {
+                lru.bytes -= evicted.encoded.len();
+            }
+        }
+        telemetry::record_cache_size("subset", lru.bytes, lru.entries.len());
+        keys.len()
+    }
+}
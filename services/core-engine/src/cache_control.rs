@@ -0,0 +1,132 @@
+//! `Cache-Control` policies.
+//!
+//! Responses get the policy of what they are rather than a header chosen by
+//! each handler:
+//!
+//! | Policy | Responses | Default |
+//! |---|---|---|
+//! | `immutable` | files under `/cdn/` (content-addressed or version-pinned) | `public, max-age=31536000, immutable` |
+//! | `css` | stylesheets | `public, max-age=3600, stale-while-revalidate=86400` |
+//! | `catalog` | catalog listings, which depend on the caller | `private, max-age=60, stale-while-revalidate=600` |
+//! | `generated` | fonts and previews made per request (`slim`, `preview.svg`) | `public, max-age=86400` |
+//!
+//! Each is set with `CACHE_CONTROL_<POLICY>` or `[cache_control]` in the
+//! configuration file (see [`crate::config`]). A catalog entry's own
+//! `cache_control` replaces the `immutable` and `css` policies for its files
+//! and its family's stylesheet; files of private fonts are still cached
+//! privately until their signature expires (see [`crate::signing`]).
+
+use axum::http::HeaderValue;
+use tracing::warn;
+
+use crate::{artifacts, FontCatalogEntry};
+
+/// Directives that take a number of seconds.
+const TIMED: &[&str] = &["max-age", "s-maxage", "stale-while-revalidate", "stale-if-error"];
+const FLAGS: &[&str] =
+    &["public", "private", "no-cache", "no-store", "no-transform", "must-revalidate", "proxy-revalidate", "immutable"];
+
+#[derive(Debug, Clone, Copy)]
+pub enum Policy {
+    Immutable,
+    Css,
+    Catalog,
+    Generated,
+}
+
+impl Policy {
+    fn env(self) -> &'static str {
+        match self {
+            Self::Immutable => "CACHE_CONTROL_IMMUTABLE",
+            Self::Css => "CACHE_CONTROL_CSS",
+            Self::Catalog => "CACHE_CONTROL_CATALOG",
+            Self::Generated => "CACHE_CONTROL_GENERATED",
+        }
+    }
+
+    fn default_value(self) -> &'static str {
+        match self {
+            Self::Immutable => "public, max-age=31536000, immutable",
+            Self::Css => "public, max-age=3600, stale-while-revalidate=86400",
+            Self::Catalog => "private, max-age=60, stale-while-revalidate=600",
+            Self::Generated => "public, max-age=86400",
+        }
+    }
+}
+
+/// Checks a `Cache-Control` value: known directives, seconds where they
+/// take them.
+pub fn validate(value: &str) -> Result<(), String> {
+    let directives: Vec<&str> = value.split(',').map(str::trim).collect();
+    if directives.iter().any(|d| d.is_empty()) {
+        return Err(format!("'{value}' must be comma-separated directives like public, max-age=3600"));
+    }
+    for directive in directives {
+        let lower = directive.to_ascii_lowercase();
+        let valid = match lower.split_once('=') {
+            Some((name, secs)) => TIMED.contains(&name) && !secs.is_empty() && secs.bytes().all(|b| b.is_ascii_digit()),
+            None => FLAGS.contains(&lower.as_str()),
+        };
+        if !valid {
+            return Err(format!(
+                "unknown Cache-Control directive '{directive}'; use {}, or {}=<seconds>",
+                FLAGS.join(", "),
+                TIMED.join("/")
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub struct CachePolicies {
+    immutable: HeaderValue,
+    css: HeaderValue,
+    catalog: HeaderValue,
+    generated: HeaderValue,
+}
+
+impl CachePolicies {
+    /// Invalid values were already reported by [`crate::config`]; they fall
+    /// back to the default here.
+    pub fn from_env() -> Self {
+        let load = |policy: Policy| {
+            let value = std::env::var(policy.env()).ok().filter(|v| !v.trim().is_empty());
+            let value = value.filter(|v| match validate(v) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("{}: {e}; using the default", policy.env());
+                    false
+                }
+            });
+            value
+                .and_then(|v| HeaderValue::from_str(v.trim()).ok())
+                .unwrap_or_else(|| HeaderValue::from_static(policy.default_value()))
+        };
+        Self {
+            immutable: load(Policy::Immutable),
+            css: load(Policy::Css),
+            catalog: load(Policy::Catalog),
+            generated: load(Policy::Generated),
+        }
+    }
+
+    pub fn get(&self, policy: Policy) -> HeaderValue {
+        match policy {
+            Policy::Immutable => &self.immutable,
+            Policy::Css => &self.css,
+            Policy::Catalog => &self.catalog,
+            Policy::Generated => &self.generated,
+        }
+        .clone()
+    }
+
+    /// `policy` for the font at `slug` (an entry ID or family), or the
+    /// override of the first entry it names that has one.
+    pub fn for_font(&self, catalog: &[FontCatalogEntry], slug: &str, policy: Policy) -> HeaderValue {
+        catalog
+            .iter()
+            .filter(|e| artifacts::slug(&e.id) == slug || artifacts::slug(&e.family) == slug)
+            .find_map(|e| e.cache_control.as_deref().and_then(|v| HeaderValue::from_str(v).ok()))
+            .unwrap_or_else(|| self.get(policy))
+    }
+}
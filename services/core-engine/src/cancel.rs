@@ -0,0 +1,209 @@
+//! Cancellation for CPU-bound processing jobs.
+//!
+//! Axum drops a handler's future when the client disconnects, but work moved
+//! onto the blocking pool keeps running regardless. [`run`] ties the two
+//! together: the job gets a [`CancelToken`] that trips when the waiting
+//! request is dropped or exceeds `PROCESSING_TIMEOUT_SECS`, and the job
+//! checks it between steps so abandoned subset/compress work stops early.
+//! Queued jobs (see [`crate::queue`]) run under a longer limit set with
+//! [`with_timeout`], and follow the job's steps through
+//! [`CancelToken::report`] when run under [`with_progress`]. Long jobs that
+//! run in several steps save a [`checkpoint`] after each under
+//! [`with_checkpoint`], and a job started again after a restart picks up
+//! from the one it [`resumed`].
+
+use axum::http::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tracing::{info, warn};
+
+/// Receives a job's step and how far along it is, in percent.
+pub type Progress = Arc<dyn Fn(&'static str, u8) + Send + Sync>;
+
+/// Keeps a job's checkpoint, so a later run can resume from it.
+pub type Save = Arc<dyn Fn(Value) + Send + Sync>;
+
+/// The checkpoint a job resumes from, and where it saves new ones.
+#[derive(Clone)]
+struct Checkpoint {
+    resumed: Option<Value>,
+    save: Save,
+}
+
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    progress: Option<Progress>,
+}
+
+/// Returned by a job that noticed its token tripped.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl CancelToken {
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// A token that is never cancelled and reports steps to `progress`, for
+    /// work done outside a job (see [`crate::isolation`]).
+    pub fn reporting(progress: Progress) -> Self {
+        Self { cancelled: Arc::default(), progress: Some(progress) }
+    }
+
+    /// Reports that the job finished `step`, `percent` of the way through.
+    pub fn report(&self, step: &'static str, percent: u8) {
+        if let Some(progress) = &self.progress {
+            progress(step, percent);
+        }
+    }
+}
+
+/// Counters for processing jobs, reported on `/health`.
+#[derive(Default)]
+pub struct JobStats {
+    running: AtomicU64,
+    completed: AtomicU64,
+    timed_out: AtomicU64,
+    cancelled_while_running: AtomicU64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobStatsSnapshot {
+    running: u64,
+    completed: u64,
+    timed_out: u64,
+    cancelled_while_running: u64,
+}
+
+impl JobStats {
+    pub fn snapshot(&self) -> JobStatsSnapshot {
+        JobStatsSnapshot {
+            running: self.running.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            timed_out: self.timed_out.load(Ordering::Relaxed),
+            cancelled_while_running: self.cancelled_while_running.load(Ordering::Relaxed),
+        }
+    }
+}
+
+tokio::task_local! {
+    static TIMEOUT: Duration;
+    static PROGRESS: Progress;
+    static CHECKPOINT: Checkpoint;
+}
+
+/// Runs `work` with `timeout` in place of `PROCESSING_TIMEOUT_SECS` for the
+/// jobs it starts.
+pub async fn with_timeout<F: Future>(timeout: Duration, work: F) -> F::Output {
+    TIMEOUT.scope(timeout, work).await
+}
+
+/// Runs `work` with the jobs it starts reporting their steps to `progress`.
+pub async fn with_progress<F: Future>(progress: Progress, work: F) -> F::Output {
+    PROGRESS.scope(progress, work).await
+}
+
+/// Runs `work` resuming from `resumed` and saving checkpoints with `save`.
+pub async fn with_checkpoint<F: Future>(resumed: Option<Value>, save: Save, work: F) -> F::Output {
+    CHECKPOINT.scope(Checkpoint { resumed, save }, work).await
+}
+
+/// The checkpoint an earlier run of this job saved last, if any.
+pub fn resumed() -> Option<Value> {
+    CHECKPOINT.try_with(|c| c.resumed.clone()).ok().flatten()
+}
+
+/// Reports a step of work done elsewhere as [`CancelToken::report`] would.
+pub fn report(step: &'static str, percent: u8) {
+    let _ = PROGRESS.try_with(|progress| progress(step, percent));
+}
+
+/// Saves how far the job got; a no-op outside [`with_checkpoint`].
+pub fn checkpoint(value: Value) {
+    let _ = CHECKPOINT.try_with(|c| (c.save)(value));
+}
+
+/// How long the jobs started now may run.
+pub fn limit() -> Duration {
+    TIMEOUT.try_with(|t| *t).unwrap_or_else(|_| processing_timeout())
+}
+
+pub fn processing_timeout() -> Duration {
+    let secs = std::env::var("PROCESSING_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30);
+    Duration::from_secs(secs)
+}
+
+/// Trips the token if the awaiting future is dropped before the job ends.
+struct DropGuard {
+    token: CancelToken,
+    stats: Arc<JobStats>,
+    job: &'static str,
+    armed: bool,
+}
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        if self.armed {
+            self.token.cancel();
+            self.stats.cancelled_while_running.fetch_add(1, Ordering::Relaxed);
+            info!(job = self.job, "client went away; cancelling job");
+        }
+    }
+}
+
+/// Runs `work` on the blocking pool, cancelling it when the request is
+/// abandoned or times out.
+pub async fn run<T, F>(stats: &Arc<JobStats>, job: &'static str, work: F) -> Result<T, (StatusCode, String)>
+where
+    T: Send + 'static,
+    F: FnOnce(&CancelToken) -> Result<T, Cancelled> + Send + 'static,
+{
+    let token = CancelToken { cancelled: Arc::default(), progress: PROGRESS.try_with(Arc::clone).ok() };
+    let job_token = token.clone();
+    let job_stats = Arc::clone(stats);
+    let mut guard = DropGuard { token: token.clone(), stats: Arc::clone(stats), job, armed: true };
+
+    stats.running.fetch_add(1, Ordering::Relaxed);
+    let handle = tokio::task::spawn_blocking(move || {
+        let out = work(&job_token);
+        job_stats.running.fetch_sub(1, Ordering::Relaxed);
+        out
+    });
+    let outcome = tokio::time::timeout(limit(), handle).await;
+    guard.armed = false;
+
+    match outcome {
+        Ok(Ok(Ok(value))) => {
+            stats.completed.fetch_add(1, Ordering::Relaxed);
+            Ok(value)
+        }
+        Ok(Ok(Err(Cancelled))) => Err((StatusCode::SERVICE_UNAVAILABLE, format!("{job} was cancelled"))),
+        Ok(Err(e)) => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("{job} failed: {e}"))),
+        Err(_) => {
+            token.cancel();
+            stats.timed_out.fetch_add(1, Ordering::Relaxed);
+            warn!(job, "processing timed out; cancelling job");
+            Err((StatusCode::SERVICE_UNAVAILABLE, format!("{job} timed out")))
+        }
+    }
+}
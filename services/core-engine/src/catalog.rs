@@ -0,0 +1,273 @@
+//! The catalog API.
+//!
+//! `GET /api/v1/font/catalog` lists entries a page at a time, optionally
+//! filtered by family, license, format or size, cached per the `catalog`
+//! policy (see [`cache_control`]). `POST /api/v1/font/catalog/:id`
+//! registers a family, `PUT` replaces an entry (licenses, unicode ranges,
+//! defaults, ...) and `DELETE` retires it; all need `X-Admin-Token`. Unlike
+//! [`crate::staging`], changes go live at once. Entries are validated before
+//! they are stored: the binary `<id>.ttf`/`<id>.otf` must exist in
+//! `CATALOG_FONT_DIR` and pass [`validation`], unicode ranges and a
+//! `cache_control` override must parse, and a family or PostScript name
+//! already used by another entry is a `409` unless `?resolution=` says how
+//! to proceed (see [`collision`]). An entry with a `tenant` is only visible
+//! to that tenant (see [`tenants`]).
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json},
+};
+use font_api::CatalogPage;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::info;
+
+use crate::{
+    audit,
+    cache_control::{self, Policy},
+    collision::{self, ResolutionParams},
+    db, duplicates,
+    extract::ApiJson,
+    samples, staging, tenants, unicode, validation, AppState, FontCatalogEntry,
+};
+
+const FORMATS: &[&str] = &["ttf", "otf", "woff", "woff2"];
+
+const MAX_PER_PAGE: usize = 200;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ListQuery {
+    channel: Option<String>,
+    /// Case-insensitive substring of the family name.
+    family: Option<String>,
+    /// Exact license identifier, e.g. `OFL-1.1`.
+    license: Option<String>,
+    format: Option<String>,
+    max_size_kb: Option<f64>,
+    #[serde(default = "default_page")]
+    page: usize,
+    #[serde(default = "default_per_page")]
+    per_page: usize,
+}
+
+fn default_page() -> usize {
+    1
+}
+
+fn default_per_page() -> usize {
+    50
+}
+
+impl ListQuery {
+    /// A query for the live catalog with these filters, for callers outside
+    /// the REST API (see [`crate::grpc`]).
+    pub fn filters(
+        family: Option<String>,
+        license: Option<String>,
+        format: Option<String>,
+        max_size_kb: Option<f64>,
+    ) -> Self {
+        Self { family, license, format, max_size_kb, ..Self::default() }
+    }
+
+    fn matches(&self, entry: &FontCatalogEntry) -> bool {
+        let family = self.family.as_deref().map(str::to_lowercase);
+        family.is_none_or(|f| entry.family.to_lowercase().contains(&f))
+            && self.license.as_deref().is_none_or(|l| entry.license.eq_ignore_ascii_case(l))
+            && self.format.as_deref().is_none_or(|f| entry.formats.iter().any(|e| e.eq_ignore_ascii_case(f)))
+            && self.max_size_kb.is_none_or(|max| entry.size_kb <= max)
+    }
+}
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<ListQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    if query.page == 0 {
+        return Err(bad_request("page starts at 1".to_string()));
+    }
+    if !(1..=MAX_PER_PAGE).contains(&query.per_page) {
+        return Err(bad_request(format!("per_page must be 1-{MAX_PER_PAGE}")));
+    }
+    let matching = matching(&state, &headers, &query);
+    let total = matching.len();
+    let items = matching.into_iter().skip((query.page - 1) * query.per_page).take(query.per_page).collect();
+    let page = CatalogPage {
+        items: samples::annotate(items),
+        total,
+        page: query.page,
+        per_page: query.per_page,
+        pages: total.div_ceil(query.per_page),
+    };
+    Ok(([(header::CACHE_CONTROL, state.cache_policies.get(Policy::Catalog))], Json(page)))
+}
+
+/// Entries the caller may see that match `query`, in catalog order.
+pub fn matching(state: &AppState, headers: &HeaderMap, query: &ListQuery) -> Vec<FontCatalogEntry> {
+    let entries = if staging::is_preview(headers, query.channel.as_deref()) {
+        staging::overlay(state)
+    } else {
+        state.catalog.read().unwrap().clone()
+    };
+    state.sandbox.visible(headers, entries).into_iter().filter(|e| query.matches(e)).collect()
+}
+
+fn bad_request(message: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message)
+}
+
+/// Checks the entry's fields and that its binary is in font storage.
+pub fn validate(id: &str, entry: &FontCatalogEntry) -> Result<(), (StatusCode, String)> {
+    if entry.id != id {
+        return Err(bad_request(format!("entry id '{}' does not match path id '{id}'", entry.id)));
+    }
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        return Err(bad_request(format!("id '{id}' must be lowercase letters, digits and '-'")));
+    }
+    for (field, value) in [("family", &entry.family), ("variant", &entry.variant), ("license", &entry.license)] {
+        if value.trim().is_empty() {
+            return Err(bad_request(format!("{field} is required")));
+        }
+    }
+    if entry.formats.is_empty() {
+        return Err(bad_request(format!("formats must list at least one of: {}", FORMATS.join(", "))));
+    }
+    if let Some(f) = entry.formats.iter().find(|f| !FORMATS.contains(&f.as_str())) {
+        return Err(bad_request(format!("unknown format '{f}'; valid: {}", FORMATS.join(", "))));
+    }
+    if let Some(r) = entry.unicode_ranges.iter().find(|r| unicode::parse_range(r).is_none()) {
+        return Err(bad_request(format!("unicode range '{r}' should look like U+0000-00FF")));
+    }
+    if let Some(defaults) = &entry.defaults {
+        crate::validate_defaults(defaults).map_err(bad_request)?;
+    }
+    tenants::validate(&entry.tenant).map_err(bad_request)?;
+    if let Some(value) = &entry.cache_control {
+        cache_control::validate(value).map_err(|e| bad_request(format!("cache_control: {e}")))?;
+    }
+    let dir = duplicates::catalog_font_dir().ok_or_else(|| {
+        (StatusCode::UNPROCESSABLE_ENTITY, format!("CATALOG_FONT_DIR is not configured, so '{id}' has no binary"))
+    })?;
+    let path = duplicates::font_path(&dir, id).ok_or_else(|| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("no binary for '{id}' in CATALOG_FONT_DIR; add {id}.ttf or {id}.otf first"),
+        )
+    })?;
+    let data = std::fs::read(&path)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{}: {e}", path.display())))?;
+    let problems = validation::validate(&data).problems();
+    if !problems.is_empty() {
+        let message = format!("{} fails validation: {}", path.display(), problems.join("; "));
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, message));
+    }
+    Ok(())
+}
+
+/// The version of entry `id`, 1 for a new one. Entries written through the
+/// API keep it: only [`crate::versions`] adds versions.
+pub fn current_version(state: &AppState, id: &str) -> u32 {
+    state.catalog.read().unwrap().iter().find(|e| e.id == id).map_or(1, |e| e.version)
+}
+
+pub async fn persist(state: &AppState, entry: &FontCatalogEntry) -> Result<(), (StatusCode, String)> {
+    if let Some(pool) = &state.db {
+        db::save_entry(pool, entry)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("catalog persist failed: {e}")))?;
+    }
+    Ok(())
+}
+
+pub async fn create(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(params): Query<ResolutionParams>,
+    ApiJson(entry): ApiJson<FontCatalogEntry>,
+) -> Result<(StatusCode, Json<FontCatalogEntry>), (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    validate(&id, &entry)?;
+    if state.catalog.read().unwrap().iter().any(|e| e.id == id) {
+        return Err((StatusCode::CONFLICT, format!("'{id}' is already in the catalog; PUT to update it")));
+    }
+    let mut entry = collision::resolve(entry, &state.catalog.read().unwrap(), &params)?;
+    entry.version = current_version(&state, &entry.id);
+    // new_version registers the binary as a replacement of the colliding entry.
+    if entry.id != id {
+        validate(&entry.id, &entry)?;
+    }
+    persist(&state, &entry).await?;
+    let before = {
+        let mut catalog = state.catalog.write().unwrap();
+        match catalog.iter_mut().find(|e| e.id == entry.id) {
+            Some(existing) => Some(std::mem::replace(existing, entry.clone())),
+            None => {
+                catalog.push(entry.clone());
+                None
+            }
+        }
+    };
+    audit::record(&state, &headers, "catalog.create", &entry.id, json!(before), json!(entry)).await;
+    info!(id = %entry.id, family = %entry.family, "catalog entry registered");
+    Ok((StatusCode::CREATED, Json(entry)))
+}
+
+pub async fn update(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(params): Query<ResolutionParams>,
+    ApiJson(entry): ApiJson<FontCatalogEntry>,
+) -> Result<Json<FontCatalogEntry>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    if !state.catalog.read().unwrap().iter().any(|e| e.id == id) {
+        return Err((StatusCode::NOT_FOUND, format!("no catalog entry '{id}'; POST to register it")));
+    }
+    validate(&id, &entry)?;
+    // Renames must not take another entry's name.
+    let mut entry = collision::resolve(entry, &state.catalog.read().unwrap(), &params)?;
+    entry.version = current_version(&state, &id);
+    if entry.id != id {
+        return Err((StatusCode::CONFLICT, format!("new_version would replace '{}'; retire '{id}' instead", entry.id)));
+    }
+    persist(&state, &entry).await?;
+    let before = state
+        .catalog
+        .write()
+        .unwrap()
+        .iter_mut()
+        .find(|e| e.id == id)
+        .map(|existing| std::mem::replace(existing, entry.clone()));
+    audit::record(&state, &headers, "catalog.update", &id, json!(before), json!(entry)).await;
+    info!(id = %id, "catalog entry updated");
+    Ok(Json(entry))
+}
+
+pub async fn retire(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    if !state.catalog.read().unwrap().iter().any(|e| e.id == id) {
+        return Err((StatusCode::NOT_FOUND, format!("no catalog entry '{id}'")));
+    }
+    if let Some(pool) = &state.db {
+        db::delete_entry(pool, &id)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("catalog persist failed: {e}")))?;
+    }
+    let before = {
+        let mut catalog = state.catalog.write().unwrap();
+        let before = catalog.iter().find(|e| e.id == id).cloned();
+        catalog.retain(|e| e.id != id);
+        before
+    };
+    audit::record(&state, &headers, "catalog.retire", &id, json!(before), Value::Null).await;
+    info!(id = %id, "catalog entry retired");
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,283 @@
+//! Signed catalog snapshots for promoting a catalog between environments.
+//!
+//! `GET /api/v1/admin/catalog/export` writes the catalog (`?channel=staging`
+//! with staged entries layered on top) sorted by ID, each entry with the
+//! SHA-256 of its binary, as JSON or as NDJSON (`?format=ndjson`: the
+//! [`SyncManifest`] line, then one entry per line). The manifest carries a
+//! SHA-256 over the entries and an HMAC over that keyed with
+//! `CATALOG_SYNC_SECRET`, which both environments share; without it there is
+//! no export or import.
+//!
+//! `POST /api/v1/admin/catalog/import` takes either form back (NDJSON with
+//! `Content-Type: application/x-ndjson`) and applies it all or nothing. The
+//! signature and digest must match, every entry must pass the checks of
+//! [`catalog::validate`], and every binary in `CATALOG_FONT_DIR` must hash
+//! to the digest it was exported with, so production serves exactly the
+//! fonts staging was tested with. `If-Match` with the `catalog_sha256` an
+//! export of this instance's catalog would have makes the import fail with
+//! `412` when the catalog changed since. The report lists entries added,
+//! updated and unchanged; entries missing from the snapshot are kept unless
+//! `?prune=true`. `?dry_run=true` reports without applying.
+
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use font_api::{SyncEntry, SyncManifest, SyncSnapshot};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::info;
+
+use crate::{audit, catalog, db, duplicates, signing, staging, AppState, FontCatalogEntry};
+
+const FORMAT_VERSION: u32 = 1;
+const NDJSON: &str = "application/x-ndjson";
+
+fn secret() -> Result<String, (StatusCode, String)> {
+    std::env::var("CATALOG_SYNC_SECRET")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| (StatusCode::SERVICE_UNAVAILABLE, "catalog sync needs CATALOG_SYNC_SECRET".to_string()))
+}
+
+fn entries_digest(entries: &[SyncEntry]) -> String {
+    let canonical = serde_json::to_vec(entries).expect("catalog entries always serialize");
+    format!("{:x}", Sha256::digest(&canonical))
+}
+
+fn mac(secret: &str, manifest: &SyncManifest) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    let signed = format!(
+        "{}.{}.{}.{}",
+        manifest.format_version, manifest.created_at_unix, manifest.entry_count, manifest.catalog_sha256
+    );
+    mac.update(signed.as_bytes());
+    mac
+}
+
+/// SHA-256 of the binary for `id` in `CATALOG_FONT_DIR`, whether or not
+/// this catalog has the entry yet.
+fn binary_digest(id: &str) -> Result<String, String> {
+    let dir = duplicates::catalog_font_dir().ok_or("CATALOG_FONT_DIR is not configured")?;
+    let path = duplicates::font_path(&dir, id).ok_or("no binary in CATALOG_FONT_DIR")?;
+    let data = std::fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(format!("{:x}", Sha256::digest(&data)))
+}
+
+/// `entries` sorted by ID, with their binaries' digests.
+fn sync_entries(mut entries: Vec<FontCatalogEntry>) -> Vec<SyncEntry> {
+    entries.sort_by(|a, b| a.id.cmp(&b.id));
+    entries
+        .into_iter()
+        .map(|entry| {
+            let binary_sha256 = binary_digest(&entry.id).ok();
+            SyncEntry { entry, binary_sha256 }
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    format: Option<String>,
+    channel: Option<String>,
+}
+
+pub async fn export(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let secret = secret()?;
+    let format = query.format.as_deref().unwrap_or("json");
+    if !matches!(format, "json" | "ndjson") {
+        return Err((StatusCode::BAD_REQUEST, format!("unknown format '{format}'; valid: json, ndjson")));
+    }
+    let catalog = if staging::is_preview(&HeaderMap::new(), query.channel.as_deref()) {
+        staging::overlay(&state)
+    } else {
+        state.catalog.read().unwrap().clone()
+    };
+    let entries = tokio::task::spawn_blocking(move || sync_entries(catalog))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("hashing catalog binaries: {e}")))?;
+    let mut manifest = SyncManifest {
+        format_version: FORMAT_VERSION,
+        engine_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at_unix: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        entry_count: entries.len(),
+        catalog_sha256: entries_digest(&entries),
+        signature: String::new(),
+    };
+    let digest: String = mac(&secret, &manifest).finalize().into_bytes().iter().map(|b| format!("{b:02x}")).collect();
+    manifest.signature = format!("sha256={digest}");
+    info!(entries = entries.len(), sha256 = %manifest.catalog_sha256, format, "catalog sync snapshot exported");
+
+    if format == "json" {
+        return Ok(Json(SyncSnapshot { manifest, entries }).into_response());
+    }
+    let mut body = serde_json::to_string(&manifest).expect("manifests always serialize");
+    for entry in &entries {
+        body.push('\n');
+        body.push_str(&serde_json::to_string(entry).expect("catalog entries always serialize"));
+    }
+    body.push('\n');
+    Ok(([(header::CONTENT_TYPE, NDJSON)], body).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    prune: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    dry_run: bool,
+    applied: bool,
+    catalog_sha256: String,
+    added: Vec<String>,
+    updated: Vec<String>,
+    unchanged: usize,
+    /// Entries only this catalog has: removed with `prune`, kept otherwise.
+    removed: Vec<String>,
+    kept: Vec<String>,
+}
+
+fn parse(headers: &HeaderMap, body: &[u8]) -> Result<SyncSnapshot, String> {
+    let ndjson = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|t| t.starts_with(NDJSON));
+    if !ndjson {
+        return serde_json::from_slice(body).map_err(|e| format!("invalid snapshot: {e}"));
+    }
+    let text = std::str::from_utf8(body).map_err(|_| "snapshot is not UTF-8".to_string())?;
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let (_, first) = lines.next().ok_or("empty snapshot")?;
+    let manifest = serde_json::from_str(first).map_err(|e| format!("line 1: invalid manifest: {e}"))?;
+    let entries = lines
+        .map(|(i, line)| serde_json::from_str(line).map_err(|e| format!("line {}: invalid entry: {e}", i + 1)))
+        .collect::<Result<_, _>>()?;
+    Ok(SyncSnapshot { manifest, entries })
+}
+
+/// Why the snapshot cannot be applied here, one problem per entry.
+fn check(entries: &[SyncEntry]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for SyncEntry { entry, binary_sha256 } in entries {
+        if let Err((_, e)) = catalog::validate(&entry.id, entry) {
+            problems.push(format!("{}: {e}", entry.id));
+            continue;
+        }
+        let Some(expected) = binary_sha256 else { continue };
+        match binary_digest(&entry.id) {
+            Ok(actual) if &actual == expected => {}
+            Ok(actual) => problems.push(format!("{}: binary hashes to {actual}, snapshot has {expected}", entry.id)),
+            Err(e) => problems.push(format!("{}: {e}", entry.id)),
+        }
+    }
+    problems
+}
+
+pub async fn import(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<ImportQuery>,
+    body: Bytes,
+) -> Result<Json<ImportReport>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let secret = secret()?;
+    let snapshot = parse(&headers, &body).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let manifest = &snapshot.manifest;
+    if manifest.format_version != FORMAT_VERSION {
+        let message = format!("unsupported format_version {}; expected {FORMAT_VERSION}", manifest.format_version);
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, message));
+    }
+    let signature = manifest.signature.strip_prefix("sha256=").and_then(signing::hex_decode).unwrap_or_default();
+    if mac(&secret, manifest).verify_slice(&signature).is_err() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "snapshot signature does not match".to_string()));
+    }
+    let actual = entries_digest(&snapshot.entries);
+    if manifest.entry_count != snapshot.entries.len() || actual != manifest.catalog_sha256 {
+        let message = format!(
+            "snapshot content does not match its manifest: {} entries hashing to {actual}, manifest says {} and {}",
+            snapshot.entries.len(),
+            manifest.entry_count,
+            manifest.catalog_sha256
+        );
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, message));
+    }
+
+    let current = state.catalog.read().unwrap().clone();
+    let (current, problems, snapshot) = tokio::task::spawn_blocking(move || {
+        let current = sync_entries(current);
+        let problems = check(&snapshot.entries);
+        (current, problems, snapshot)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("checking catalog binaries: {e}")))?;
+    if let Some(expected) = headers.get(header::IF_MATCH).and_then(|v| v.to_str().ok()) {
+        let now = entries_digest(&current);
+        if expected.trim().trim_matches('"') != now {
+            return Err((StatusCode::PRECONDITION_FAILED, format!("the catalog changed; it now hashes to {now}")));
+        }
+    }
+    if !problems.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, format!("snapshot cannot be applied: {}", problems.join("; "))));
+    }
+
+    let (mut added, mut updated, mut unchanged) = (Vec::new(), Vec::new(), 0);
+    for SyncEntry { entry, .. } in &snapshot.entries {
+        match current.iter().find(|c| c.entry.id == entry.id) {
+            None => added.push(entry.id.clone()),
+            Some(c) if json!(c.entry) != json!(entry) => updated.push(entry.id.clone()),
+            Some(_) => unchanged += 1,
+        }
+    }
+    let extra: Vec<String> = current
+        .iter()
+        .filter(|c| !snapshot.entries.iter().any(|e| e.entry.id == c.entry.id))
+        .map(|c| c.entry.id.clone())
+        .collect();
+    let (removed, kept) = if query.prune { (extra, Vec::new()) } else { (Vec::new(), extra) };
+    let catalog_sha256 = snapshot.manifest.catalog_sha256.clone();
+    let mut imported: Vec<FontCatalogEntry> = snapshot.entries.into_iter().map(|e| e.entry).collect();
+    imported.extend(current.into_iter().map(|c| c.entry).filter(|e| kept.contains(&e.id)));
+    imported.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let changed = !(added.is_empty() && updated.is_empty() && removed.is_empty());
+    let applied = !query.dry_run && changed;
+    if applied {
+        if let Some(pool) = &state.db {
+            db::replace_catalog(pool, &imported)
+                .await
+                .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("catalog persist failed: {e}")))?;
+        }
+        *state.catalog.write().unwrap() = imported;
+        let before = json!({ "updated": updated, "removed": removed });
+        let after = json!({ "catalog_sha256": catalog_sha256, "added": added, "updated": updated });
+        audit::record(&state, &headers, "catalog.import", "catalog", before, after).await;
+        info!(added = added.len(), updated = updated.len(), removed = removed.len(), "catalog sync snapshot imported");
+    }
+    Ok(Json(ImportReport {
+        dry_run: query.dry_run,
+        applied,
+        catalog_sha256,
+        added,
+        updated,
+        unchanged,
+        removed,
+        kept,
+    }))
+}
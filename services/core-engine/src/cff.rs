@@ -0,0 +1,615 @@
+//! Compact Font Format (`CFF ` table, version 1) outlines.
+//!
+//! [`retain`] rewrites the table for a subset: charstrings of dropped glyphs
+//! become a bare `endchar` and subroutines no retained charstring calls
+//! become a bare `return`, so glyph IDs and subroutine numbers are
+//! unchanged. Everything the Top DICT points at is laid out again with
+//! fixed-width offsets. CFF2 (variable) outlines are not supported.
+
+use std::collections::BTreeSet;
+
+use crate::sfnt::be_u16;
+
+const OP_CHARSET: u16 = 15;
+const OP_ENCODING: u16 = 16;
+const OP_CHARSTRINGS: u16 = 17;
+const OP_PRIVATE: u16 = 18;
+const OP_SUBRS: u16 = 19;
+/// Two-byte operators are `12 x`, stored as `1200 + x`.
+const OP_FD_ARRAY: u16 = 1236;
+const OP_FD_SELECT: u16 = 1237;
+
+const ENDCHAR: u8 = 14;
+const RETURN: u8 = 11;
+/// Type 2 charstrings nest subroutine calls at most this deep.
+const MAX_CALL_DEPTH: u8 = 10;
+
+/// An INDEX: its items and the offset just past it.
+struct Index<'a> {
+    items: Vec<&'a [u8]>,
+    end: usize,
+}
+
+fn offset(data: &[u8], at: usize, size: usize) -> Option<usize> {
+    let bytes = data.get(at..at + size)?;
+    Some(bytes.iter().fold(0, |v, &b| v << 8 | b as usize))
+}
+
+fn index(data: &[u8], at: usize) -> Option<Index<'_>> {
+    let count = be_u16(data, at)? as usize;
+    if count == 0 {
+        return Some(Index { items: Vec::new(), end: at + 2 });
+    }
+    let size = *data.get(at + 2)? as usize;
+    if !(1..=4).contains(&size) {
+        return None;
+    }
+    // Offsets are 1-based from the byte before the object data.
+    let base = at + 3 + (count + 1) * size - 1;
+    let offsets = (0..=count).map(|i| offset(data, at + 3 + i * size, size)).collect::<Option<Vec<_>>>()?;
+    let items = offsets.windows(2).map(|w| data.get(base + w[0]..base + w[1])).collect::<Option<Vec<_>>>()?;
+    Some(Index { items, end: base + offsets[count] })
+}
+
+fn write_index<T: AsRef<[u8]>>(items: &[T]) -> Vec<u8> {
+    let mut out = (items.len() as u16).to_be_bytes().to_vec();
+    if items.is_empty() {
+        return out;
+    }
+    let total = items.iter().map(|i| i.as_ref().len()).sum::<usize>() + 1;
+    let size = match total {
+        0..=0xFF => 1,
+        0x100..=0xFFFF => 2,
+        0x1_0000..=0xFF_FFFF => 3,
+        _ => 4,
+    };
+    out.push(size as u8);
+    let mut at = 1;
+    out.extend_from_slice(&1u32.to_be_bytes()[4 - size..]);
+    for item in items {
+        at += item.as_ref().len();
+        out.extend_from_slice(&(at as u32).to_be_bytes()[4 - size..]);
+    }
+    items.iter().for_each(|i| out.extend_from_slice(i.as_ref()));
+    out
+}
+
+/// One DICT entry: the operands as encoded, and their integer values
+/// (reals read as 0; no offset operator takes one).
+struct Entry {
+    op: u16,
+    operands: Vec<u8>,
+    values: Vec<i32>,
+}
+
+fn parse_dict(data: &[u8]) -> Option<Vec<Entry>> {
+    let (mut entries, mut start, mut values, mut i) = (Vec::new(), 0, Vec::new(), 0);
+    while i < data.len() {
+        let b = data[i];
+        match b {
+            0..=21 => {
+                let (op, len) = if b == 12 { (1200 + *data.get(i + 1)? as u16, 2) } else { (b as u16, 1) };
+                entries.push(Entry { op, operands: data[start..i].to_vec(), values: std::mem::take(&mut values) });
+                i += len;
+                start = i;
+                continue;
+            }
+            28 => {
+                values.push(be_u16(data, i + 1)? as i16 as i32);
+                i += 3;
+            }
+            29 => {
+                values.push(i32::from_be_bytes(data.get(i + 1..i + 5)?.try_into().ok()?));
+                i += 5;
+            }
+            30 => {
+                // Real: nibbles up to and including an 0xf terminator.
+                i += 1;
+                while data.get(i)? & 0x0F != 0x0F && data[i] >> 4 != 0x0F {
+                    i += 1;
+                }
+                values.push(0);
+                i += 1;
+            }
+            32..=246 => {
+                values.push(b as i32 - 139);
+                i += 1;
+            }
+            247..=250 => {
+                values.push((b as i32 - 247) * 256 + *data.get(i + 1)? as i32 + 108);
+                i += 2;
+            }
+            251..=254 => {
+                values.push(-(b as i32 - 251) * 256 - *data.get(i + 1)? as i32 - 108);
+                i += 2;
+            }
+            _ => return None,
+        }
+    }
+    Some(entries)
+}
+
+fn get(dict: &[Entry], op: u16) -> Option<&[i32]> {
+    dict.iter().find(|e| e.op == op).map(|e| e.values.as_slice())
+}
+
+/// Re-encodes `dict`, writing the operands of `overrides` as 5-byte
+/// integers so the DICT's length does not depend on their values.
+fn write_dict(dict: &[Entry], overrides: &[(u16, Vec<usize>)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for e in dict {
+        match overrides.iter().find(|(op, _)| *op == e.op) {
+            Some((_, values)) => values.iter().for_each(|&v| {
+                out.push(29);
+                out.extend_from_slice(&(v as i32).to_be_bytes());
+            }),
+            None => out.extend_from_slice(&e.operands),
+        }
+        if e.op >= 1200 {
+            out.extend_from_slice(&[12, (e.op - 1200) as u8]);
+        } else {
+            out.push(e.op as u8);
+        }
+    }
+    out
+}
+
+fn location(dict: &[Entry], op: u16) -> Option<usize> {
+    get(dict, op).and_then(|v| v.first()).map(|&v| v as usize)
+}
+
+fn charset_len(cff: &[u8], at: usize, glyphs: usize) -> Option<usize> {
+    let format = *cff.get(at)?;
+    if format == 0 {
+        return Some(1 + 2 * glyphs.saturating_sub(1));
+    }
+    let (mut covered, mut p) = (0, at + 1);
+    while covered < glyphs.saturating_sub(1) {
+        let left = match format {
+            1 => *cff.get(p + 2)? as usize,
+            2 => be_u16(cff, p + 2)? as usize,
+            _ => return None,
+        };
+        p += if format == 1 { 3 } else { 4 };
+        covered += left + 1;
+    }
+    Some(p - at)
+}
+
+fn encoding_len(cff: &[u8], at: usize) -> Option<usize> {
+    let format = *cff.get(at)?;
+    let count = *cff.get(at + 1)? as usize;
+    let mut len = match format & 0x7F {
+        0 => 2 + count,
+        1 => 2 + 2 * count,
+        _ => return None,
+    };
+    if format & 0x80 != 0 {
+        len += 1 + 3 * *cff.get(at + len)? as usize;
+    }
+    Some(len)
+}
+
+/// FDSelect: each glyph's font DICT, and the table's length.
+fn fd_select(cff: &[u8], at: usize, glyphs: usize) -> Option<(Vec<u8>, usize)> {
+    match *cff.get(at)? {
+        0 => Some((cff.get(at + 1..at + 1 + glyphs)?.to_vec(), 1 + glyphs)),
+        3 => {
+            let ranges = be_u16(cff, at + 1)? as usize;
+            let mut fds = vec![0; glyphs];
+            for r in 0..ranges {
+                let rec = at + 3 + 3 * r;
+                let (first, fd, next) = (be_u16(cff, rec)? as usize, *cff.get(rec + 2)?, be_u16(cff, rec + 3)? as usize);
+                fds.iter_mut().take(next).skip(first).for_each(|f| *f = fd);
+            }
+            Some((fds, 3 + 3 * ranges + 2))
+        }
+        _ => None,
+    }
+}
+
+/// A Private DICT and its local subroutines.
+struct Private<'a> {
+    dict: Vec<Entry>,
+    subrs: Vec<&'a [u8]>,
+}
+
+fn private<'a>(cff: &'a [u8], dict: &[Entry]) -> Option<Private<'a>> {
+    let Some(&[size, at]) = get(dict, OP_PRIVATE) else {
+        return Some(Private { dict: Vec::new(), subrs: Vec::new() });
+    };
+    let (size, at) = (size as usize, at as usize);
+    let dict = parse_dict(cff.get(at..at + size)?)?;
+    let subrs = match location(&dict, OP_SUBRS) {
+        Some(o) => index(cff, at + o)?.items,
+        None => Vec::new(),
+    };
+    Some(Private { dict, subrs })
+}
+
+/// Serialises a Private DICT with its local subroutines directly after it.
+fn write_private(private: &Private, used: &[bool]) -> Vec<u8> {
+    let has_subrs = get(&private.dict, OP_SUBRS).is_some();
+    let len = write_dict(&private.dict, &[(OP_SUBRS, vec![0])]).len();
+    let mut out = write_dict(&private.dict, &[(OP_SUBRS, vec![len])]);
+    if has_subrs {
+        out.extend(write_index(&kept(&private.subrs, used, &[RETURN])));
+    }
+    out
+}
+
+/// `items` with the unused ones replaced by `stub`.
+fn kept<'a>(items: &[&'a [u8]], used: &[bool], stub: &'static [u8]) -> Vec<&'a [u8]> {
+    items.iter().zip(used).map(|(&item, &u)| if u { item } else { stub }).collect()
+}
+
+fn bias(count: usize) -> i32 {
+    match count {
+        0..1240 => 107,
+        1240..33900 => 1131,
+        _ => 32768,
+    }
+}
+
+/// Walks Type 2 charstrings marking the subroutines they call.
+struct Calls<'a> {
+    global: &'a [&'a [u8]],
+    local: &'a [&'a [u8]],
+    used_global: &'a mut [bool],
+    used_local: &'a mut [bool],
+    stems: usize,
+    stack: Vec<i32>,
+}
+
+impl Calls<'_> {
+    /// Returns `true` once `endchar` is reached.
+    fn walk(&mut self, cs: &[u8], depth: u8) -> Result<bool, String> {
+        if depth > MAX_CALL_DEPTH {
+            return Err("CFF subroutines nest too deeply".to_string());
+        }
+        let truncated = || "CFF charstring truncated".to_string();
+        let mut i = 0;
+        while i < cs.len() {
+            let b = cs[i];
+            match b {
+                28 => {
+                    self.stack.push(be_u16(cs, i + 1).ok_or_else(truncated)? as i16 as i32);
+                    i += 3;
+                }
+                32..=246 => {
+                    self.stack.push(b as i32 - 139);
+                    i += 1;
+                }
+                247..=250 => {
+                    self.stack.push((b as i32 - 247) * 256 + *cs.get(i + 1).ok_or_else(truncated)? as i32 + 108);
+                    i += 2;
+                }
+                251..=254 => {
+                    self.stack.push(-(b as i32 - 251) * 256 - *cs.get(i + 1).ok_or_else(truncated)? as i32 - 108);
+                    i += 2;
+                }
+                255 => {
+                    let fixed = cs.get(i + 1..i + 5).ok_or_else(truncated)?;
+                    self.stack.push(i32::from_be_bytes(fixed.try_into().unwrap()) >> 16);
+                    i += 5;
+                }
+                // hstem, vstem, hstemhm, vstemhm
+                1 | 3 | 18 | 23 => {
+                    self.stems += self.stack.len() / 2;
+                    self.stack.clear();
+                    i += 1;
+                }
+                // hintmask, cntrmask: operands before them are implicit vstems.
+                19 | 20 => {
+                    self.stems += self.stack.len() / 2;
+                    self.stack.clear();
+                    i += 1 + self.stems.div_ceil(8);
+                }
+                10 | 29 => {
+                    let global = b == 29;
+                    let subrs = if global { self.global } else { self.local };
+                    let n = self.stack.pop().ok_or("CFF subroutine call without a number")? + bias(subrs.len());
+                    let subr = usize::try_from(n).ok().and_then(|n| subrs.get(n).map(|s| (n, *s)));
+                    let (n, subr) = subr.ok_or_else(|| format!("CFF charstring calls missing subroutine {n}"))?;
+                    if global {
+                        self.used_global[n] = true;
+                    } else {
+                        self.used_local[n] = true;
+                    }
+                    if self.walk(subr, depth + 1)? {
+                        return Ok(true);
+                    }
+                    i += 1;
+                }
+                RETURN => return Ok(false),
+                ENDCHAR => return Ok(true),
+                12 => {
+                    self.stack.clear();
+                    i += 2;
+                }
+                _ => {
+                    self.stack.clear();
+                    i += 1;
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Rewrites a `CFF ` table keeping only the outlines of `keep` (plus
+/// `.notdef`).
+pub fn retain(cff: &[u8], keep: &BTreeSet<u16>) -> Result<Vec<u8>, String> {
+    let malformed = |what: &str| format!("CFF {what} is malformed");
+    match cff.first() {
+        Some(1) => {}
+        Some(2) => return Err("CFF2 outlines cannot be subset yet".to_string()),
+        _ => return Err(malformed("header")),
+    }
+    let header_len = *cff.get(2).ok_or_else(|| malformed("header"))? as usize;
+    let names = index(cff, header_len).ok_or_else(|| malformed("Name INDEX"))?;
+    let top = index(cff, names.end).ok_or_else(|| malformed("Top DICT INDEX"))?;
+    let strings = index(cff, top.end).ok_or_else(|| malformed("String INDEX"))?;
+    let global = index(cff, strings.end).ok_or_else(|| malformed("Global Subr INDEX"))?;
+    let [top_dict] = top.items[..] else {
+        return Err("CFF font sets with more than one font are not supported".to_string());
+    };
+    let top_dict = parse_dict(top_dict).ok_or_else(|| malformed("Top DICT"))?;
+    let charstrings_at = location(&top_dict, OP_CHARSTRINGS).ok_or("CFF font has no CharStrings")?;
+    let charstrings = index(cff, charstrings_at).ok_or_else(|| malformed("CharStrings INDEX"))?.items;
+    let glyphs = charstrings.len();
+
+    let custom = |op, predefined| location(&top_dict, op).filter(|&at| at > predefined);
+    let charset = match custom(OP_CHARSET, 2) {
+        Some(at) => charset_len(cff, at, glyphs).and_then(|len| cff.get(at..at + len)),
+        None => Some(&[][..]),
+    }
+    .ok_or_else(|| malformed("charset"))?;
+    let encoding = match custom(OP_ENCODING, 1) {
+        Some(at) => encoding_len(cff, at).and_then(|len| cff.get(at..at + len)),
+        None => Some(&[][..]),
+    }
+    .ok_or_else(|| malformed("Encoding"))?;
+
+    // CID-keyed fonts carry one Private DICT per font DICT in the FDArray.
+    let (font_dicts, select, select_bytes) = match location(&top_dict, OP_FD_ARRAY) {
+        Some(at) => {
+            let fd_array = index(cff, at).ok_or_else(|| malformed("FDArray"))?;
+            let dicts = fd_array.items.iter().map(|d| parse_dict(d)).collect::<Option<Vec<_>>>();
+            let select_at = location(&top_dict, OP_FD_SELECT).ok_or("CID-keyed CFF font has no FDSelect")?;
+            let (select, len) = fd_select(cff, select_at, glyphs).ok_or_else(|| malformed("FDSelect"))?;
+            (dicts.ok_or_else(|| malformed("font DICT"))?, select, &cff[select_at..select_at + len])
+        }
+        None => (Vec::new(), vec![0; glyphs], &[][..]),
+    };
+    let privates = if font_dicts.is_empty() {
+        vec![private(cff, &top_dict)]
+    } else {
+        font_dicts.iter().map(|d| private(cff, d)).collect()
+    }
+    .into_iter()
+    .collect::<Option<Vec<_>>>()
+    .ok_or_else(|| malformed("Private DICT"))?;
+
+    let mut used_global = vec![false; global.items.len()];
+    let mut used_local: Vec<Vec<bool>> = privates.iter().map(|p| vec![false; p.subrs.len()]).collect();
+    let mut used_glyphs = vec![false; glyphs];
+    for g in keep.iter().map(|&g| g as usize).chain([0]).filter(|&g| g < glyphs) {
+        used_glyphs[g] = true;
+        let fd = select[g] as usize;
+        let (private, used) = privates.get(fd).zip(used_local.get_mut(fd)).ok_or_else(|| malformed("FDSelect"))?;
+        let mut calls = Calls {
+            global: &global.items,
+            local: &private.subrs,
+            used_global: &mut used_global,
+            used_local: used,
+            stems: 0,
+            stack: Vec::new(),
+        };
+        calls.walk(charstrings[g], 0)?;
+    }
+
+    // Lay out everything with placeholder offsets first: the fixed-width
+    // encoding makes every length independent of the final values.
+    let private_blobs: Vec<Vec<u8>> = privates.iter().zip(&used_local).map(|(p, u)| write_private(p, u)).collect();
+    let top_overrides = |charset_at, encoding_at, select_at, charstrings_at, fd_array_at, private_at| {
+        let mut o = vec![(OP_CHARSTRINGS, vec![charstrings_at])];
+        if !charset.is_empty() {
+            o.push((OP_CHARSET, vec![charset_at]));
+        }
+        if !encoding.is_empty() {
+            o.push((OP_ENCODING, vec![encoding_at]));
+        }
+        if font_dicts.is_empty() {
+            o.push((OP_PRIVATE, vec![private_blobs[0].len() - subrs_len(&privates[0], &used_local[0]), private_at]));
+        } else {
+            o.push((OP_FD_SELECT, vec![select_at]));
+            o.push((OP_FD_ARRAY, vec![fd_array_at]));
+        }
+        o
+    };
+    let font_dicts_at = |privates_at: usize| -> Vec<Vec<u8>> {
+        let mut at = privates_at;
+        font_dicts
+            .iter()
+            .zip(privates.iter().zip(&used_local).zip(&private_blobs))
+            .map(|(d, ((p, u), blob))| {
+                let dict = write_dict(d, &[(OP_PRIVATE, vec![blob.len() - subrs_len(p, u), at])]);
+                at += blob.len();
+                dict
+            })
+            .collect()
+    };
+
+    let global_index = write_index(&kept(&global.items, &used_global, &[RETURN]));
+    let charstrings_index = write_index(&kept(&charstrings, &used_glyphs, &[ENDCHAR]));
+    let top_len = write_dict(&top_dict, &top_overrides(0, 0, 0, 0, 0, 0)).len();
+    let fd_array_len = write_index(&font_dicts_at(0)).len();
+
+    let charset_at = header_len + (names.end - header_len) + write_index(&[vec![0; top_len]]).len()
+        + (strings.end - top.end)
+        + global_index.len();
+    let encoding_at = charset_at + charset.len();
+    let select_at = encoding_at + encoding.len();
+    let charstrings_at = select_at + select_bytes.len();
+    let fd_array_at = charstrings_at + charstrings_index.len();
+    let privates_at = fd_array_at + if font_dicts.is_empty() { 0 } else { fd_array_len };
+
+    let mut out = cff[..names.end].to_vec();
+    let top_dict = write_dict(
+        &top_dict,
+        &top_overrides(charset_at, encoding_at, select_at, charstrings_at, fd_array_at, privates_at),
+    );
+    out.extend(write_index(&[top_dict]));
+    out.extend_from_slice(&cff[top.end..strings.end]);
+    out.extend(global_index);
+    out.extend_from_slice(charset);
+    out.extend_from_slice(encoding);
+    out.extend_from_slice(select_bytes);
+    out.extend(charstrings_index);
+    if !font_dicts.is_empty() {
+        out.extend(write_index(&font_dicts_at(privates_at)));
+    }
+    private_blobs.into_iter().for_each(|b| out.extend(b));
+    Ok(out)
+}
+
+/// Length of the local Subrs INDEX [`write_private`] appends.
+fn subrs_len(private: &Private, used: &[bool]) -> usize {
+    match get(&private.dict, OP_SUBRS) {
+        Some(_) => write_index(&kept(&private.subrs, used, &[RETURN])).len(),
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cmap::CharMap,
+        sfnt::{Font, Table},
+        subset,
+    };
+
+    const SQUARE: &[u8] = &[139, 139, 21, 239, 139, 5, 139, 239, 5, 39, 139, 5, ENDCHAR];
+    const LOCAL: &[u8] = &[239, 139, 5, 139, 239, 5, RETURN];
+    const GLOBAL: &[u8] = &[189, 139, 5, 139, 189, 5, RETURN];
+
+    /// `value` as a five-byte DICT integer.
+    fn int(value: usize) -> Vec<u8> {
+        [&[29][..], &(value as i32).to_be_bytes()].concat()
+    }
+
+    /// Four glyphs: `.notdef`, a square, and one each drawn by a local and
+    /// a global subroutine.
+    fn sample() -> Vec<u8> {
+        let names = write_index(&[b"Test"]);
+        let top_len = write_index(&[[0; 17]]).len();
+        let subrs = write_index(&[GLOBAL]);
+        let charstrings_at = 4 + names.len() + top_len + 2 + subrs.len();
+        let local = [139, 139, 21, 32, 10, ENDCHAR];
+        let global = [139, 139, 21, 32, 29, ENDCHAR];
+        let charstrings = write_index(&[&[ENDCHAR][..], SQUARE, &local, &global]);
+        let private = [int(6), vec![OP_SUBRS as u8]].concat();
+        let private_at = charstrings_at + charstrings.len();
+        let top = [int(charstrings_at), vec![17], int(private.len()), int(private_at), vec![18]].concat();
+        [vec![1, 0, 4, 1], names, write_index(&[top]), vec![0, 0], subrs, charstrings, private, write_index(&[LOCAL])]
+            .concat()
+    }
+
+    type Items = Vec<Vec<u8>>;
+
+    /// The global subroutines, charstrings and local subroutines of `cff`.
+    fn parts(cff: &[u8]) -> (Items, Items, Items) {
+        let owned = |items: Vec<&[u8]>| items.into_iter().map(<[u8]>::to_vec).collect();
+        let names = index(cff, cff[2] as usize).unwrap();
+        let top = index(cff, names.end).unwrap();
+        let strings = index(cff, top.end).unwrap();
+        let global = index(cff, strings.end).unwrap();
+        let dict = parse_dict(top.items[0]).unwrap();
+        let charstrings = index(cff, location(&dict, OP_CHARSTRINGS).unwrap()).unwrap();
+        (owned(global.items), owned(charstrings.items), owned(private(cff, &dict).unwrap().subrs))
+    }
+
+    #[test]
+    fn retain_stubs_dropped_glyphs_and_subroutines() {
+        let cff = sample();
+        let (_, charstrings, _) = parts(&cff);
+
+        let local_only = retain(&cff, &BTreeSet::from([2])).unwrap();
+        let (global, kept, local) = parts(&local_only);
+        assert_eq!(kept.len(), 4);
+        assert_eq!(kept, [charstrings[0].clone(), vec![ENDCHAR], charstrings[2].clone(), vec![ENDCHAR]]);
+        assert_eq!(global, [[RETURN]]);
+        assert_eq!(local, [LOCAL]);
+        assert_eq!(retain(&local_only, &BTreeSet::from([2])).unwrap(), local_only);
+
+        let (global, kept, local) = parts(&retain(&cff, &BTreeSet::from([1, 3])).unwrap());
+        assert_eq!(kept, [charstrings[0].clone(), SQUARE.to_vec(), vec![ENDCHAR], charstrings[3].clone()]);
+        assert_eq!(global, [GLOBAL]);
+        assert_eq!(local, [[RETURN]]);
+    }
+
+    #[test]
+    fn rejects_missing_subroutines_and_cff2() {
+        let mut cff = sample();
+        let at = cff.windows(3).position(|w| w == [32, 29, ENDCHAR]).unwrap();
+        cff[at] = 33;
+        assert!(retain(&cff, &BTreeSet::from([3])).unwrap_err().contains("missing subroutine"));
+        assert!(retain(&[2, 0, 5, 0, 0], &BTreeSet::new()).unwrap_err().contains("CFF2"));
+    }
+
+    struct Segments(usize);
+
+    impl ttf_parser::OutlineBuilder for Segments {
+        fn move_to(&mut self, _: f32, _: f32) {}
+        fn line_to(&mut self, _: f32, _: f32) {
+            self.0 += 1;
+        }
+        fn quad_to(&mut self, _: f32, _: f32, _: f32, _: f32) {
+            self.0 += 1;
+        }
+        fn curve_to(&mut self, _: f32, _: f32, _: f32, _: f32, _: f32, _: f32) {
+            self.0 += 1;
+        }
+        fn close(&mut self) {}
+    }
+
+    #[test]
+    fn subset_font_reparses() {
+        let mut head = vec![0; 54];
+        head[..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        head[12..16].copy_from_slice(&0x5F0F_3CF5u32.to_be_bytes());
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        let mut hhea = vec![0; 36];
+        hhea[..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        hhea[34..36].copy_from_slice(&4u16.to_be_bytes());
+        let cmap = CharMap::new([(0x61, 1), (0x62, 2), (0x63, 3)].into());
+        let tables = [
+            (*b"CFF ", sample()),
+            (*b"cmap", cmap.to_bytes()),
+            (*b"head", head),
+            (*b"hhea", hhea),
+            (*b"hmtx", [0x01, 0xF4, 0, 0].repeat(4)),
+            (*b"maxp", vec![0, 0, 0x50, 0, 0, 4]),
+        ];
+        let mut font = Font::new(0x4F54_544F, tables.into_iter().map(|(tag, data)| Table { tag, data }).collect());
+        let report = subset::subset(&mut font, &BTreeSet::from([0x62, 0x63]), true, false).unwrap();
+        assert_eq!((report.characters, report.glyphs, report.total_glyphs), (2, 3, 4));
+
+        let bytes = font.to_bytes();
+        let face = ttf_parser::Face::parse(&bytes, 0).unwrap();
+        assert_eq!(face.number_of_glyphs(), 4);
+        assert_eq!(face.glyph_index('a'), None);
+        assert_eq!(face.glyph_index('b'), Some(ttf_parser::GlyphId(2)));
+        let segments = |g| {
+            let mut segments = Segments(0);
+            face.outline_glyph(ttf_parser::GlyphId(g), &mut segments).map(|_| segments.0)
+        };
+        assert_eq!(segments(1), None);
+        assert_eq!(segments(2), Some(2));
+        assert_eq!(segments(3), Some(2));
+        assert_eq!(face.glyph_hor_advance(ttf_parser::GlyphId(1)), Some(0));
+        assert_eq!(face.glyph_hor_advance(ttf_parser::GlyphId(2)), Some(500));
+    }
+}
@@ -0,0 +1,225 @@
+//! `--check-config`: validate the configuration file and environment without
+//! starting the server.
+//!
+//! Every setting is parsed the same way the engine would, but unparseable
+//! values are reported instead of silently falling back to defaults. With
+//! `--check-storage` the database, spool directories, font storage and
+//! scanner are also contacted. Exits nonzero when anything is wrong so CI/CD can gate a
+//! rollout on it.
+
+use std::{path::Path, time::Duration};
+
+use crate::{
+    artifacts, config, db, duplicates, flags, gc, quarantine, queue, shedding, slices, spool, storage, tls, uploads,
+    usage_events,
+};
+
+fn var(k: &str) -> Option<String> {
+    std::env::var(k).ok().filter(|v| !v.is_empty())
+}
+
+struct Checker {
+    errors: Vec<String>,
+}
+
+impl Checker {
+    fn error(&mut self, msg: String) {
+        self.errors.push(msg);
+    }
+
+    fn number(&mut self, k: &str) {
+        if let Some(v) = var(k) {
+            if v.parse::<u64>().is_err() {
+                self.error(format!("{k}={v:?} is not a non-negative integer"));
+            }
+        }
+    }
+
+    fn boolean(&mut self, k: &str) {
+        if let Some(v) = var(k) {
+            if !matches!(v.as_str(), "true" | "false" | "1" | "0") {
+                self.error(format!("{k}={v:?} must be true/false/1/0"));
+            }
+        }
+    }
+}
+
+fn check_env(c: &mut Checker) {
+    // Addresses, storage, cache sizes, CORS, rate limits, workers and TLS, from
+    // the configuration file and the environment.
+    match config::EngineConfig::load() {
+        Ok(config) => config.export(),
+        Err(errors) => errors.into_iter().for_each(|e| c.error(e)),
+    }
+    if let Some((cert, key)) = tls::paths() {
+        if let Err(e) = tls::check(&cert, &key) {
+            c.error(format!("TLS: {e}"));
+        }
+    }
+    if let Some(mode) = var("API_AUTH") {
+        if !matches!(mode.as_str(), "off" | "required") {
+            c.error(format!("API_AUTH={mode:?} must be off or required"));
+        }
+    }
+    if let Some(mode) = var("PARSE_ISOLATION") {
+        if !matches!(mode.as_str(), "process" | "off") {
+            c.error(format!("PARSE_ISOLATION={mode:?} must be process or off"));
+        }
+    }
+    if let Some(spec) = var("SCANNER") {
+        let addr = spec
+            .strip_prefix("clamd://")
+            .or_else(|| spec.strip_prefix("icap://").map(|r| r.split('/').next().unwrap_or(r)));
+        match addr {
+            Some(a) if a.trim_end_matches('/').rsplit_once(':').is_some_and(|(_, p)| p.parse::<u16>().is_ok()) => {}
+            Some(_) => c.error(format!("SCANNER={spec:?} needs a host:port")),
+            None => c.error(format!("SCANNER={spec:?} must be clamd://host:port or icap://host:port/service")),
+        }
+    }
+    for item in var("FEATURE_FLAGS").unwrap_or_default().split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        match item.split_once('=') {
+            Some((name, _)) if !flags::CAPABILITIES.contains(&name) => c.error(format!(
+                "FEATURE_FLAGS: unknown capability '{name}' (known: {})",
+                flags::CAPABILITIES.join(", ")
+            )),
+            Some((_, "on" | "off" | "true" | "false" | "1" | "0")) => {}
+            _ => c.error(format!("FEATURE_FLAGS: '{item}' should look like subset=off")),
+        }
+    }
+    if let Err(e) = gc::parse_ttls(&var("ARTIFACT_TTL_SECS").unwrap_or_default()) {
+        c.error(e);
+    }
+    if let Err(e) = slices::parse_profiles(&var("SLICING_PROFILES").unwrap_or_default()) {
+        c.error(e);
+    }
+    if let Err(e) = shedding::parse_limits(&var("CONCURRENCY_LIMITS").unwrap_or_default()) {
+        c.error(e);
+    }
+    for url in var("EDGE_ENDPOINTS").unwrap_or_default().split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            c.error(format!("EDGE_ENDPOINTS: '{url}' must be an http(s) URL"));
+        }
+    }
+    if let Some(path) = var("EDGE_PROBE_PATH") {
+        if !path.starts_with('/') {
+            c.error(format!("EDGE_PROBE_PATH={path:?} must start with '/'"));
+        }
+    }
+    if let Some(spec) = var("USAGE_EVENTS") {
+        if let Err(e) = usage_events::Sink::parse(&spec) {
+            c.error(e);
+        }
+    }
+    if let Err(e) = storage::from_env("uploads", uploads::upload_dir()) {
+        c.error(e);
+    }
+    for k in [
+        "EDGE_PROBE_INTERVAL_SECS",
+        "EDGE_PROBE_TIMEOUT_SECS",
+        "EDGE_MAX_AGE_SECS",
+        "JOB_RETENTION_SECS",
+        "JOB_LEASE_SECS",
+        "JOB_MAX_ATTEMPTS",
+        "JOB_POLL_MS",
+        "USAGE_EVENTS_BUFFER",
+        "WEBHOOK_MAX_ATTEMPTS",
+        "WEBHOOK_TIMEOUT_SECS",
+        "SIGNED_URL_TTL_SECS",
+        "PARSE_WORKER_MEMORY_MB",
+        "CONCURRENCY_LIMIT",
+        "CONCURRENCY_QUEUE",
+        "CONCURRENCY_WAIT_SECS",
+        "GC_INTERVAL_SECS",
+    ] {
+        c.number(k);
+    }
+    for k in ["STRICT_JSON", "EDGE_FAILOVER"] {
+        c.boolean(k);
+    }
+}
+
+async fn check_storage(c: &mut Checker) {
+    if let Some(url) = var("DATABASE_URL") {
+        match tokio::time::timeout(Duration::from_secs(10), db::connect(&url)).await {
+            Ok(Ok(pool)) => match db::schema_version(&pool).await {
+                Ok(v) if v < db::latest_migration() => {
+                    println!("note: database schema {v:?} is behind this build ({:?}); it will be migrated on start", db::latest_migration());
+                }
+                Ok(_) => {}
+                // Fresh database: the migrations table does not exist yet.
+                Err(_) => println!("note: database has no applied migrations yet"),
+            },
+            Ok(Err(e)) => c.error(format!("DATABASE_URL: cannot connect: {e}")),
+            Err(_) => c.error("DATABASE_URL: connection timed out".to_string()),
+        }
+    }
+    let dirs = [
+        ("SPOOL_DIR", spool::spool_dir()),
+        ("QUARANTINE_DIR", quarantine::quarantine_dir()),
+        ("JOB_DIR", queue::job_dir()),
+    ];
+    for (k, dir) in dirs {
+        if let Err(e) = writable(&dir).await {
+            c.error(format!("{k} {}: not writable: {e}", dir.display()));
+        }
+    }
+    for (namespace, dir) in [("uploads", uploads::upload_dir()), ("artifacts", artifacts::artifact_dir())] {
+        // Configuration errors were already reported by check_env.
+        let Ok(store) = storage::from_env(namespace, dir) else { continue };
+        if let Err(e) = round_trip(&*store).await {
+            c.error(format!("{}: not writable: {e}", store.location()));
+        }
+    }
+    if let Some(dir) = duplicates::catalog_font_dir() {
+        if !dir.is_dir() {
+            c.error(format!("CATALOG_FONT_DIR {}: not a directory", dir.display()));
+        }
+    }
+    if let Some(spec) = var("SCANNER") {
+        let addr = spec
+            .trim_start_matches("clamd://")
+            .trim_start_matches("icap://")
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        match tokio::time::timeout(Duration::from_secs(5), tokio::net::TcpStream::connect(&addr)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => c.error(format!("SCANNER: cannot reach {addr}: {e}")),
+            Err(_) => c.error(format!("SCANNER: connecting to {addr} timed out")),
+        }
+    }
+}
+
+async fn writable(dir: &Path) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+    let probe = dir.join(format!(".check-{}", uuid::Uuid::new_v4()));
+    tokio::fs::write(&probe, b"").await?;
+    tokio::fs::remove_file(&probe).await
+}
+
+async fn round_trip(store: &dyn storage::FontStorage) -> Result<(), String> {
+    let key = format!(".check-{}", uuid::Uuid::new_v4());
+    store.put(&key, Vec::new()).await?;
+    store.get(&key).await?.ok_or("object written but not readable")?;
+    store.delete(&key).await
+}
+
+/// Runs the checks, prints the outcome and returns the process exit code.
+pub async fn run(with_storage: bool) -> i32 {
+    let mut c = Checker { errors: Vec::new() };
+    check_env(&mut c);
+    if with_storage {
+        check_storage(&mut c).await;
+    }
+    if c.errors.is_empty() {
+        println!("configuration OK{}", if with_storage { " (storage reachable)" } else { "" });
+        0
+    } else {
+        for e in &c.errors {
+            eprintln!("error: {e}");
+        }
+        eprintln!("{} configuration error(s)", c.errors.len());
+        1
+    }
+}
@@ -0,0 +1,162 @@
+//! CJK width-class analysis.
+//!
+//! CJK fonts are designed on a full-width (1 em) grid; proportional kana or
+//! punctuation, and the `palt`/`halt` features that make full-width glyphs
+//! proportional or half-width at layout time, decide whether a layout
+//! engine should enable proportional punctuation. This reports advance
+//! widths per character class from `hmtx` and which width features exist.
+
+use axum::{body::Body, http::StatusCode, response::Json};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tracing::info;
+
+use crate::{
+    cmap::CharMap,
+    layout,
+    sfnt::{be_u16, Font},
+    spool,
+};
+
+/// Character classes reported, with their code point ranges.
+const CLASSES: &[(&str, &[(u32, u32)])] = &[
+    ("kana", &[(0x3041, 0x309F), (0x30A0, 0x30FF), (0x31F0, 0x31FF)]),
+    ("ideographs", &[(0x3400, 0x4DBF), (0x4E00, 0x9FFF), (0xF900, 0xFAFF)]),
+    ("punctuation", &[(0x3000, 0x303F), (0xFF01, 0xFF0F), (0xFF1A, 0xFF20), (0xFF3B, 0xFF40), (0xFF5B, 0xFF60)]),
+    ("hangul", &[(0xAC00, 0xD7AF)]),
+    ("halfwidth_forms", &[(0xFF61, 0xFFDC)]),
+];
+
+/// Width-related GSUB/GPOS features.
+const WIDTH_FEATURES: &[&str] = &["palt", "halt", "vpal", "vhal", "pwid", "fwid", "hwid", "twid", "qwid", "chws", "vchw"];
+
+/// Advance widths from `hmtx`; glyphs past numberOfHMetrics repeat the last.
+struct Advances<'a> {
+    hmtx: &'a [u8],
+    long_metrics: usize,
+}
+
+impl<'a> Advances<'a> {
+    fn read(font: &'a Font) -> Result<Self, String> {
+        let hhea = font.table(b"hhea").ok_or("font has no hhea table")?;
+        let hmtx = font.table(b"hmtx").ok_or("font has no hmtx table")?;
+        let long_metrics = be_u16(hhea, 34).ok_or("hhea table truncated")? as usize;
+        if long_metrics == 0 || hmtx.len() < 4 * long_metrics {
+            return Err("hmtx table truncated".to_string());
+        }
+        Ok(Self { hmtx, long_metrics })
+    }
+
+    fn advance(&self, glyph: u16) -> u16 {
+        let index = (glyph as usize).min(self.long_metrics - 1);
+        be_u16(self.hmtx, 4 * index).unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClassWidths {
+    glyphs: usize,
+    full_width: usize,
+    half_width: usize,
+    proportional: usize,
+    full_width_ratio: f64,
+    /// Advance widths in em → number of characters, most common first.
+    advances_em: Vec<(f64, usize)>,
+    min_advance_em: f64,
+    max_advance_em: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CjkReport {
+    units_per_em: u16,
+    classes: BTreeMap<&'static str, ClassWidths>,
+    /// Full-width share across every reported class except halfwidth forms.
+    full_width_ratio: f64,
+    features: BTreeMap<&'static str, bool>,
+    /// Punctuation is full-width and `palt` can tighten it.
+    proportional_punctuation_available: bool,
+}
+
+fn em(units: u16, upem: u16) -> f64 {
+    (units as f64 / upem as f64 * 1000.0).round() / 1000.0
+}
+
+fn class_widths(widths: &[u16], upem: u16) -> ClassWidths {
+    let mut histogram: BTreeMap<u16, usize> = BTreeMap::new();
+    for &w in widths {
+        *histogram.entry(w).or_default() += 1;
+    }
+    let count = |w: u16| histogram.get(&w).copied().unwrap_or(0);
+    let (full, half) = (count(upem), count(upem / 2));
+    let mut advances_em: Vec<(f64, usize)> = histogram.iter().map(|(&w, &n)| (em(w, upem), n)).collect();
+    advances_em.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+    advances_em.truncate(8);
+    ClassWidths {
+        glyphs: widths.len(),
+        full_width: full,
+        half_width: half,
+        proportional: widths.len() - full - half,
+        full_width_ratio: if widths.is_empty() { 0.0 } else { full as f64 / widths.len() as f64 },
+        advances_em,
+        min_advance_em: widths.iter().min().map_or(0.0, |&w| em(w, upem)),
+        max_advance_em: widths.iter().max().map_or(0.0, |&w| em(w, upem)),
+    }
+}
+
+/// Width classes and width features of a raw CJK font.
+pub async fn report(body: Body) -> Result<Json<CjkReport>, (StatusCode, String)> {
+    let (data, _) = spool::receive_font(body).await?;
+    let unprocessable = |e: String| (StatusCode::UNPROCESSABLE_ENTITY, e);
+    let font = Font::parse(&data).map_err(unprocessable)?;
+    let upem = font
+        .table(b"head")
+        .and_then(|h| be_u16(h, 18))
+        .filter(|&u| u > 0)
+        .ok_or_else(|| unprocessable("font has no usable head table".into()))?;
+    let cmap = CharMap::parse(font.table(b"cmap").ok_or_else(|| unprocessable("font has no cmap table".into()))?)
+        .map_err(unprocessable)?;
+    let advances = Advances::read(&font).map_err(unprocessable)?;
+
+    let mut classes = BTreeMap::new();
+    let (mut full, mut total) = (0, 0);
+    for &(class, ranges) in CLASSES {
+        let widths: Vec<u16> = ranges
+            .iter()
+            .flat_map(|&(start, end)| start..=end)
+            .filter_map(char::from_u32)
+            .filter_map(|c| cmap.glyph(c))
+            .map(|g| advances.advance(g))
+            .collect();
+        if widths.is_empty() {
+            continue;
+        }
+        let widths = class_widths(&widths, upem);
+        if class != "halfwidth_forms" {
+            full += widths.full_width;
+            total += widths.glyphs;
+        }
+        classes.insert(class, widths);
+    }
+    if total == 0 {
+        return Err(unprocessable("font maps no kana, ideographs, CJK punctuation or hangul".into()));
+    }
+
+    let tags: Vec<[u8; 4]> = [b"GSUB", b"GPOS"]
+        .into_iter()
+        .filter_map(|t| font.table(t))
+        .filter_map(layout::feature_tags)
+        .flatten()
+        .collect();
+    let features: BTreeMap<&'static str, bool> =
+        WIDTH_FEATURES.iter().map(|&f| (f, tags.iter().any(|t| t == f.as_bytes()))).collect();
+    let punctuation_full = classes.get("punctuation").is_some_and(|p| p.full_width_ratio >= 0.9);
+
+    info!(classes = classes.len(), glyphs = total, palt = features["palt"], "cjk width analysis");
+    Ok(Json(CjkReport {
+        units_per_em: upem,
+        full_width_ratio: full as f64 / total as f64,
+        proportional_punctuation_available: punctuation_full && features["palt"],
+        classes,
+        features,
+    }))
+}
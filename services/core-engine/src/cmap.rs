@@ -0,0 +1,351 @@
+//! Character-to-glyph mapping from the `cmap` table.
+//!
+//! Reads the best Unicode subtable: format 12 (full repertoire) when
+//! present, otherwise format 4 (BMP). Writes Windows Unicode subtables: format
+//! 4, plus format 12 when supplementary-plane characters are mapped.
+//!
+//! Unicode variation sequences (a base character and a variation selector,
+//! such as the Ideographic Variation Sequences picking a kanji's glyph
+//! variant) come from the format 14 subtable and are written back to one
+//! when any survive [`CharMap::retain`].
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::RangeInclusive;
+
+use crate::sfnt::{be_u16, be_u32};
+
+/// Code point → glyph ID for every mapped character.
+pub struct CharMap {
+    map: BTreeMap<u32, u16>,
+    /// Variation selector → its sequences.
+    variations: BTreeMap<u32, Sequences>,
+}
+
+/// The sequences of one variation selector.
+#[derive(Debug, Default, Clone)]
+struct Sequences {
+    /// Bases drawn with their default (`cmap`) glyph.
+    defaults: BTreeSet<u32>,
+    /// Bases drawn with another glyph.
+    glyphs: BTreeMap<u32, u16>,
+}
+
+/// What a font's variation sequences cover.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct VariationSummary {
+    /// Selectors with sequences, as `U+E0100`.
+    pub selectors: Vec<String>,
+    pub sequences: usize,
+    /// Sequences drawn with a glyph other than the base character's.
+    pub distinct_glyphs: usize,
+}
+
+fn be_u24(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 3).map(|b| u32::from_be_bytes([0, b[0], b[1], b[2]]))
+}
+
+impl CharMap {
+    /// A mapping without variation sequences.
+    pub fn new(map: BTreeMap<u32, u16>) -> Self {
+        Self { map, variations: BTreeMap::new() }
+    }
+
+    pub fn parse(cmap: &[u8]) -> Result<Self, String> {
+        let truncated = || "cmap table truncated".to_string();
+        let count = be_u16(cmap, 2).ok_or_else(truncated)? as usize;
+        let mut best: Option<(u8, usize)> = None;
+        let mut variations = BTreeMap::new();
+        for i in 0..count {
+            let at = 4 + 8 * i;
+            let platform = be_u16(cmap, at).ok_or_else(truncated)?;
+            let encoding = be_u16(cmap, at + 2).ok_or_else(truncated)?;
+            let offset = be_u32(cmap, at + 4).ok_or_else(truncated)? as usize;
+            let format = be_u16(cmap, offset).ok_or_else(truncated)?;
+            if (platform, encoding, format) == (0, 5, 14) {
+                // A broken format 14 costs the sequences, not the mapping.
+                variations = parse_format14(&cmap[offset..]).unwrap_or_default();
+                continue;
+            }
+            let rank = match (platform, encoding, format) {
+                (3, 10, 12) | (0, 4 | 6, 12) => 0,
+                (3, 1, 4) | (0, 3, 4) => 1,
+                (0, _, 4) => 2,
+                _ => continue,
+            };
+            if best.is_none_or(|(r, _)| rank < r) {
+                best = Some((rank, offset));
+            }
+        }
+        let (_, offset) = best.ok_or("font has no Unicode cmap subtable")?;
+        let sub = &cmap[offset..];
+        let map = match be_u16(sub, 0) {
+            Some(12) => parse_format12(sub),
+            _ => parse_format4(sub),
+        }
+        .ok_or_else(truncated)?;
+        Ok(Self { map, variations })
+    }
+
+    pub fn glyph(&self, c: char) -> Option<u16> {
+        self.map.get(&(c as u32)).copied().filter(|&g| g != 0)
+    }
+
+    /// The mapping restricted to `code_points`, with the variation
+    /// sequences whose selector and base are both among them.
+    pub fn retain(&self, code_points: &BTreeSet<u32>) -> Self {
+        let map: BTreeMap<u32, u16> =
+            self.map.iter().filter(|(cp, &g)| g != 0 && code_points.contains(cp)).map(|(&c, &g)| (c, g)).collect();
+        let variations = self
+            .variations
+            .iter()
+            .filter(|(selector, _)| code_points.contains(selector))
+            .map(|(&selector, sequences)| {
+                let glyphs = sequences.glyphs.iter().filter(|(b, _)| code_points.contains(b));
+                let kept = Sequences {
+                    defaults: sequences.defaults.iter().copied().filter(|b| map.contains_key(b)).collect(),
+                    glyphs: glyphs.map(|(&b, &g)| (b, g)).collect(),
+                };
+                (selector, kept)
+            })
+            .filter(|(_, kept)| !kept.defaults.is_empty() || !kept.glyphs.is_empty())
+            .collect();
+        Self { map, variations }
+    }
+
+    pub fn glyphs(&self) -> impl Iterator<Item = u16> + '_ {
+        self.map.values().copied().filter(|&g| g != 0)
+    }
+
+    /// Glyphs only variation sequences reach.
+    pub fn variation_glyphs(&self) -> impl Iterator<Item = u16> + '_ {
+        self.variations.values().flat_map(|s| s.glyphs.values().copied())
+    }
+
+    /// Variation selectors with sequences.
+    pub fn selectors(&self) -> impl Iterator<Item = u32> + '_ {
+        self.variations.keys().copied()
+    }
+
+    /// `None` without a format 14 subtable.
+    pub fn variation_summary(&self) -> Option<VariationSummary> {
+        if self.variations.is_empty() {
+            return None;
+        }
+        let sequences = self.variations.values().map(|s| s.defaults.len() + s.glyphs.len()).sum();
+        Some(VariationSummary {
+            selectors: self.variations.keys().map(|&s| format!("U+{s:04X}")).collect(),
+            sequences,
+            distinct_glyphs: self.variations.values().map(|s| s.glyphs.len()).sum(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let bmp: Vec<(u32, u16)> = self.map.range(..=0xFFFF).map(|(&c, &g)| (c, g)).collect();
+        let full = self.map.keys().next_back().is_some_and(|&c| c > 0xFFFF);
+        let mut subtables = Vec::new();
+        if !self.variations.is_empty() {
+            subtables.push(((0u16, 5u16), format14(&self.variations)));
+        }
+        subtables.push(((3, 1), format4(&bmp)));
+        if full {
+            subtables.push(((3, 10), format12(&self.map)));
+        }
+        let mut out = Vec::new();
+        out.extend_from_slice(&0u16.to_be_bytes());
+        out.extend_from_slice(&(subtables.len() as u16).to_be_bytes());
+        let mut offset = 4 + 8 * subtables.len();
+        for ((platform, encoding), data) in &subtables {
+            out.extend_from_slice(&platform.to_be_bytes());
+            out.extend_from_slice(&encoding.to_be_bytes());
+            out.extend_from_slice(&(offset as u32).to_be_bytes());
+            offset += data.len();
+        }
+        for (_, data) in subtables {
+            out.extend(data);
+        }
+        out
+    }
+
+    /// Mapped code points as sorted ranges of consecutive values. Only
+    /// truly adjacent code points merge, so every code point in a range
+    /// has a glyph.
+    pub fn ranges(&self) -> Vec<RangeInclusive<u32>> {
+        let mut ranges: Vec<RangeInclusive<u32>> = Vec::new();
+        for (&cp, _) in self.map.iter().filter(|(_, &g)| g != 0) {
+            match ranges.last_mut() {
+                Some(last) if *last.end() + 1 == cp => *last = *last.start()..=cp,
+                _ => ranges.push(cp..=cp),
+            }
+        }
+        ranges
+    }
+}
+
+fn parse_format4(sub: &[u8]) -> Option<BTreeMap<u32, u16>> {
+    let seg_count = be_u16(sub, 6)? as usize / 2;
+    let ends = 14;
+    let starts = ends + 2 * seg_count + 2;
+    let deltas = starts + 2 * seg_count;
+    let range_offsets = deltas + 2 * seg_count;
+    let mut map = BTreeMap::new();
+    for s in 0..seg_count {
+        let end = be_u16(sub, ends + 2 * s)?;
+        let start = be_u16(sub, starts + 2 * s)?;
+        let delta = be_u16(sub, deltas + 2 * s)?;
+        let ro_at = range_offsets + 2 * s;
+        let range_offset = be_u16(sub, ro_at)? as usize;
+        if start > end || start == 0xFFFF {
+            continue;
+        }
+        for c in start..=end {
+            let glyph = if range_offset == 0 {
+                c.wrapping_add(delta)
+            } else {
+                let at = ro_at + range_offset + 2 * (c - start) as usize;
+                match be_u16(sub, at)? {
+                    0 => 0,
+                    g => g.wrapping_add(delta),
+                }
+            };
+            map.insert(c as u32, glyph);
+        }
+    }
+    Some(map)
+}
+
+fn parse_format12(sub: &[u8]) -> Option<BTreeMap<u32, u16>> {
+    let groups = be_u32(sub, 12)? as usize;
+    let mut map = BTreeMap::new();
+    for i in 0..groups {
+        let at = 16 + 12 * i;
+        let (start, end, first) = (be_u32(sub, at)?, be_u32(sub, at + 4)?, be_u32(sub, at + 8)?);
+        if start > end || end > 0x10FFFF {
+            return None;
+        }
+        for (n, cp) in (start..=end).enumerate() {
+            map.insert(cp, (first as usize + n) as u16);
+        }
+    }
+    Some(map)
+}
+
+fn parse_format14(sub: &[u8]) -> Option<BTreeMap<u32, Sequences>> {
+    let records = be_u32(sub, 6)? as usize;
+    let mut variations = BTreeMap::new();
+    for i in 0..records {
+        let at = 10 + 11 * i;
+        let (selector, defaults, glyphs) = (be_u24(sub, at)?, be_u32(sub, at + 3)?, be_u32(sub, at + 7)?);
+        let mut sequences = Sequences::default();
+        if defaults != 0 {
+            let ranges = be_u32(sub, defaults as usize)? as usize;
+            for r in 0..ranges {
+                let at = defaults as usize + 4 + 4 * r;
+                let (start, additional) = (be_u24(sub, at)?, *sub.get(at + 3)? as u32);
+                sequences.defaults.extend(start..=start + additional);
+            }
+        }
+        if glyphs != 0 {
+            let mappings = be_u32(sub, glyphs as usize)? as usize;
+            for m in 0..mappings {
+                let at = glyphs as usize + 4 + 5 * m;
+                sequences.glyphs.insert(be_u24(sub, at)?, be_u16(sub, at + 3)?);
+            }
+        }
+        variations.insert(selector, sequences);
+    }
+    Some(variations)
+}
+
+fn format14(variations: &BTreeMap<u32, Sequences>) -> Vec<u8> {
+    let mut records = Vec::new();
+    let mut tables = Vec::new();
+    let mut offset = 10 + 11 * variations.len();
+    for (&selector, sequences) in variations {
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
+        for &base in &sequences.defaults {
+            match ranges.last_mut() {
+                Some((start, additional)) if *start + *additional + 1 == base && *additional < 255 => *additional += 1,
+                _ => ranges.push((base, 0)),
+            }
+        }
+        let default_offset = if ranges.is_empty() { 0 } else { offset };
+        if !ranges.is_empty() {
+            tables.extend_from_slice(&(ranges.len() as u32).to_be_bytes());
+            for (start, additional) in &ranges {
+                tables.extend_from_slice(&start.to_be_bytes()[1..]);
+                tables.push(*additional as u8);
+            }
+            offset += 4 + 4 * ranges.len();
+        }
+        let glyphs_offset = if sequences.glyphs.is_empty() { 0 } else { offset };
+        if !sequences.glyphs.is_empty() {
+            tables.extend_from_slice(&(sequences.glyphs.len() as u32).to_be_bytes());
+            for (base, glyph) in &sequences.glyphs {
+                tables.extend_from_slice(&base.to_be_bytes()[1..]);
+                tables.extend_from_slice(&glyph.to_be_bytes());
+            }
+            offset += 4 + 5 * sequences.glyphs.len();
+        }
+        records.extend_from_slice(&selector.to_be_bytes()[1..]);
+        records.extend_from_slice(&(default_offset as u32).to_be_bytes());
+        records.extend_from_slice(&(glyphs_offset as u32).to_be_bytes());
+    }
+    let mut out = Vec::with_capacity(offset);
+    out.extend_from_slice(&14u16.to_be_bytes());
+    out.extend_from_slice(&(offset as u32).to_be_bytes());
+    out.extend_from_slice(&(variations.len() as u32).to_be_bytes());
+    out.extend(records);
+    out.extend(tables);
+    out
+}
+
+/// Runs of consecutive code points mapped to consecutive glyphs, as
+/// (first code point, last code point, first glyph).
+fn runs(map: impl Iterator<Item = (u32, u16)>) -> Vec<(u32, u32, u16)> {
+    let mut runs: Vec<(u32, u32, u16)> = Vec::new();
+    for (c, g) in map {
+        match runs.last_mut() {
+            Some((first, last, glyph)) if *last + 1 == c && (*glyph as u32 + c - *first) == g as u32 => *last = c,
+            _ => runs.push((c, c, g)),
+        }
+    }
+    runs
+}
+
+fn format4(map: &[(u32, u16)]) -> Vec<u8> {
+    let mut segments: Vec<(u16, u16, u16)> = runs(map.iter().copied().filter(|&(c, _)| c < 0xFFFF))
+        .into_iter()
+        .map(|(first, last, glyph)| (first as u16, last as u16, glyph.wrapping_sub(first as u16)))
+        .collect();
+    segments.push((0xFFFF, 0xFFFF, 1));
+    let n = segments.len() as u16;
+    let selector = 15 - n.leading_zeros() as u16;
+    let search_range = 2 * (1u16 << selector);
+    let length = 16 + 8 * segments.len();
+    let mut out = Vec::with_capacity(length);
+    for v in [4, length as u16, 0, 2 * n, search_range, selector, 2 * n - search_range] {
+        out.extend_from_slice(&v.to_be_bytes());
+    }
+    segments.iter().for_each(|s| out.extend_from_slice(&s.1.to_be_bytes()));
+    out.extend_from_slice(&0u16.to_be_bytes());
+    segments.iter().for_each(|s| out.extend_from_slice(&s.0.to_be_bytes()));
+    segments.iter().for_each(|s| out.extend_from_slice(&s.2.to_be_bytes()));
+    segments.iter().for_each(|_| out.extend_from_slice(&0u16.to_be_bytes()));
+    out
+}
+
+fn format12(map: &BTreeMap<u32, u16>) -> Vec<u8> {
+    let groups = runs(map.iter().map(|(&c, &g)| (c, g)));
+    let mut out = Vec::with_capacity(16 + 12 * groups.len());
+    out.extend_from_slice(&12u16.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+    for v in [16 + 12 * groups.len() as u32, 0, groups.len() as u32] {
+        out.extend_from_slice(&v.to_be_bytes());
+    }
+    for (first, last, glyph) in groups {
+        for v in [first, last, glyph as u32] {
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+    }
+    out
+}
@@ -0,0 +1,240 @@
+//! TrueType collections (`.ttc`).
+//!
+//! A collection upload holds several fonts that share tables. `GET
+//! /api/v1/font/uploads/:id/faces` lists them; `POST .../faces/:index`
+//! extracts one as a standalone TTF/OTF upload, which compress, subset and
+//! analyze then take as `font_id`; `POST .../faces` (admin) registers faces
+//! as catalog entries of their own, writing `<id>.ttf`/`<id>.otf` to
+//! `CATALOG_FONT_DIR` with the id derived from the face's full name. Either
+//! every requested face is registered or none is.
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{CollectionFace, CollectionFaces, FontCatalogEntry, RegisterFacesRequest, UploadedFont};
+use serde_json::{json, Value};
+use std::{path::PathBuf, sync::Arc};
+use tracing::info;
+
+use crate::{
+    analysis, artifacts, audit, cancel, catalog, collision, duplicates,
+    extract::ApiJson,
+    name::NameTable,
+    sfnt::{self, be_u16, Flavor, Font},
+    tenants, uploads, validation, AppState,
+};
+
+const FAMILY: u16 = 1;
+const SUBFAMILY: u16 = 2;
+const FULL_NAME: u16 = 4;
+const POSTSCRIPT_NAME: u16 = 6;
+const TYPOGRAPHIC_FAMILY: u16 = 16;
+const TYPOGRAPHIC_SUBFAMILY: u16 = 17;
+
+/// The faces of a collection as standalone fonts.
+fn faces(data: &[u8]) -> Result<Vec<Font>, String> {
+    let count = sfnt::collection_len(data).ok_or("not a font collection")?;
+    (0..count).map(|index| Font::parse_face(data, index)).collect()
+}
+
+fn ext(font: &Font) -> &'static str {
+    if font.sfnt_version() == u32::from_be_bytes(*b"OTTO") {
+        "otf"
+    } else {
+        "ttf"
+    }
+}
+
+fn describe(index: usize, font: &Font) -> CollectionFace {
+    let names = font.table(b"name").and_then(|t| NameTable::parse(t).ok());
+    let name = |ids: &[u16]| names.as_ref().and_then(|n| ids.iter().find_map(|&id| n.get(id)));
+    CollectionFace {
+        index,
+        family: name(&[TYPOGRAPHIC_FAMILY, FAMILY]),
+        subfamily: name(&[TYPOGRAPHIC_SUBFAMILY, SUBFAMILY]),
+        full_name: name(&[FULL_NAME]),
+        postscript_name: name(&[POSTSCRIPT_NAME]),
+        format: ext(font).to_string(),
+        glyph_count: font.table(b"maxp").and_then(|maxp| be_u16(maxp, 4)).unwrap_or_default() as usize,
+        tables: font.tables.iter().map(|t| String::from_utf8_lossy(&t.tag).into_owned()).collect(),
+        size_kb: font.to_bytes().len() as f64 / 1024.0,
+    }
+}
+
+/// The caller's upload `id` and its bytes, which must be a collection.
+async fn collection(
+    state: &AppState,
+    headers: &HeaderMap,
+    id: &str,
+) -> Result<(UploadedFont, Vec<u8>), (StatusCode, String)> {
+    let upload = state.uploads.get(headers, id).await?;
+    if upload.flavor != Flavor::Ttc {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, format!("'{id}' is {:?}, not a font collection", upload.flavor)));
+    }
+    let data = state.uploads.read(&upload).await?;
+    Ok((upload, data))
+}
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<CollectionFaces>, (StatusCode, String)> {
+    state.flags.ensure("analyze", &headers)?;
+    let (upload, data) = collection(&state, &headers, &id).await?;
+    let faces = cancel::run(&state.jobs, "collection", move |token| {
+        token.check()?;
+        Ok(faces(&data).map(|fonts| fonts.iter().enumerate().map(|(i, font)| describe(i, font)).collect()))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    Ok(Json(CollectionFaces { id: upload.id, faces }))
+}
+
+/// Stores face `index` as an upload of its own.
+pub async fn extract(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path((id, index)): Path<(String, usize)>,
+) -> Result<(StatusCode, Json<UploadedFont>), (StatusCode, String)> {
+    let (upload, data) = collection(&state, &headers, &id).await?;
+    let (face, flavor, details) = cancel::run(&state.jobs, "collection", move |token| {
+        let font = match Font::parse_face(&data, index) {
+            Ok(font) => font,
+            Err(e) => return Ok(Err(e)),
+        };
+        token.check()?;
+        let flavor = if ext(&font) == "otf" { Flavor::Otf } else { Flavor::Ttf };
+        let face = font.to_bytes();
+        let problems = validation::validate(&face).problems();
+        if !problems.is_empty() {
+            return Ok(Err(format!("face {index} fails validation: {}", problems.join("; "))));
+        }
+        let details = uploads::Details::read(&face);
+        Ok(Ok((face, flavor, details)))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let filename = upload.filename.as_deref().map(|name| {
+        let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+        format!("{stem}-{index}.{}", if flavor == Flavor::Otf { "otf" } else { "ttf" })
+    });
+    let (status, font) = state.uploads.store(&headers, &face, None, flavor, filename, details).await?;
+    if status == StatusCode::CREATED {
+        audit::record(&state, &headers, "upload.create", &font.id, Value::Null, json!(font)).await;
+    }
+    info!(collection = %upload.id, index, id = %font.id, "collection face extracted");
+    Ok((status, Json(font)))
+}
+
+/// Registers faces of the collection as catalog entries.
+pub async fn register(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ApiJson(req): ApiJson<RegisterFacesRequest>,
+) -> Result<(StatusCode, Json<Vec<FontCatalogEntry>>), (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    if req.license.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "license is required".to_string()));
+    }
+    let dir = duplicates::catalog_font_dir()
+        .ok_or_else(|| (StatusCode::UNPROCESSABLE_ENTITY, "CATALOG_FONT_DIR is not configured".to_string()))?;
+    let (upload, data) = collection(&state, &headers, &id).await?;
+
+    // Faces belong to the tenant that uploaded the collection.
+    tenants::validate(&upload.tenant).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let job_tenant = upload.tenant.clone();
+    let job_req = req.clone();
+    let prepared = cancel::run(&state.jobs, "collection", move |token| {
+        let fonts = match faces(&data) {
+            Ok(fonts) => fonts,
+            Err(e) => return Ok(Err(e)),
+        };
+        let wanted: Vec<usize> =
+            if job_req.faces.is_empty() { (0..fonts.len()).collect() } else { job_req.faces.clone() };
+        let mut prepared = Vec::new();
+        for index in wanted {
+            token.check()?;
+            let Some(font) = fonts.get(index) else {
+                return Ok(Err(format!("face {index} does not exist; the collection has {}", fonts.len())));
+            };
+            let face = describe(index, font);
+            let bytes = font.to_bytes();
+            let facts = match analysis::facts(&bytes) {
+                Ok(facts) => facts,
+                Err(e) => return Ok(Err(format!("face {index}: {e}"))),
+            };
+            let (Some(family), Some(variant)) = (face.family, face.subfamily) else {
+                return Ok(Err(format!("face {index} has no family or subfamily name")));
+            };
+            let entry = FontCatalogEntry {
+                id: artifacts::slug(&face.full_name.unwrap_or_else(|| format!("{family} {variant}"))),
+                family,
+                variant,
+                formats: vec!["woff2".to_string(), "woff".to_string(), face.format.clone()],
+                size_kb: face.size_kb,
+                glyph_count: facts.glyph_count,
+                unicode_ranges: facts.unicode_ranges,
+                license: job_req.license.clone(),
+                foundry: job_req.foundry.clone(),
+                postscript_name: face.postscript_name,
+                defaults: None,
+                private: false,
+                version: 1,
+                attribution: None,
+                tenant: job_tenant.clone(),
+                cache_control: None,
+            };
+            prepared.push((entry, face.format, bytes));
+        }
+        Ok(Ok(prepared))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    // Every entry is checked against the catalog and the others before
+    // anything is written.
+    let mut entries: Vec<FontCatalogEntry> = Vec::new();
+    for (entry, _, _) in &prepared {
+        let taken = |e: &FontCatalogEntry| e.id == entry.id;
+        if state.catalog.read().unwrap().iter().any(taken) || entries.iter().any(taken) {
+            return Err((StatusCode::CONFLICT, format!("'{}' is already in the catalog", entry.id)));
+        }
+        let mut existing = state.catalog.read().unwrap().clone();
+        existing.extend(entries.iter().cloned());
+        entries.push(collision::resolve(entry.clone(), &existing, &Default::default())?);
+    }
+    let mut written: Vec<PathBuf> = Vec::new();
+    let stored = async {
+        for (entry, format, bytes) in &prepared {
+            let path = dir.join(format!("{}.{format}", entry.id));
+            tokio::fs::write(&path, bytes)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{}: {e}", path.display())))?;
+            written.push(path);
+            catalog::validate(&entry.id, entry)?;
+        }
+        for entry in &entries {
+            catalog::persist(&state, entry).await?;
+        }
+        Ok(())
+    };
+    if let Err(e) = stored.await {
+        for path in &written {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+        return Err(e);
+    }
+
+    state.catalog.write().unwrap().extend(entries.iter().cloned());
+    for entry in &entries {
+        audit::record(&state, &headers, "catalog.create", &entry.id, Value::Null, json!(entry)).await;
+    }
+    let ids: Vec<&str> = entries.iter().map(|e| e.id.as_str()).collect();
+    info!(collection = %upload.id, ids = ?ids, "collection faces registered");
+    Ok((StatusCode::CREATED, Json(entries)))
+}
@@ -0,0 +1,137 @@
+//! Name collision detection for catalog ingest.
+//!
+//! A new entry collides when its family or PostScript name matches an entry
+//! with a different ID — typically another foundry's font or a separate
+//! release lineage. Ingest is blocked until the caller picks a resolution:
+//! make it a new version of the existing entry, rename it, or force a new
+//! entry alongside.
+
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::FontCatalogEntry;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    /// Replace the colliding entry, keeping its ID.
+    NewVersion,
+    /// Register under `rename_to` instead.
+    Rename,
+    /// Keep both entries despite the shared name.
+    ForceNew,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ResolutionParams {
+    pub resolution: Option<Resolution>,
+    pub rename_to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Collision {
+    pub existing_id: String,
+    pub field: &'static str,
+    pub value: String,
+    pub existing_foundry: Option<String>,
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-' && *c != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Entries in `existing` whose names clash with `candidate`.
+pub fn find<'a>(
+    candidate: &FontCatalogEntry,
+    existing: impl IntoIterator<Item = &'a FontCatalogEntry>,
+) -> Vec<Collision> {
+    let family = normalize(&candidate.family);
+    let variant = normalize(&candidate.variant);
+    let postscript = candidate.postscript_name.as_deref().map(normalize);
+    let mut found = Vec::new();
+    for other in existing.into_iter().filter(|e| e.id != candidate.id) {
+        let same_face = normalize(&other.family) == family && normalize(&other.variant) == variant;
+        let same_ps = postscript.is_some() && other.postscript_name.as_deref().map(normalize) == postscript;
+        if same_face || same_ps {
+            found.push(Collision {
+                existing_id: other.id.clone(),
+                field: if same_ps { "postscript_name" } else { "family" },
+                value: if same_ps {
+                    other.postscript_name.clone().unwrap_or_default()
+                } else {
+                    format!("{} {}", other.family, other.variant)
+                },
+                existing_foundry: other.foundry.clone(),
+            });
+        }
+    }
+    found
+}
+
+/// Applies the caller's resolution to `candidate`, or rejects the ingest with
+/// `409 Conflict` describing the collisions and the available resolutions.
+pub fn resolve(
+    mut candidate: FontCatalogEntry,
+    existing: &[FontCatalogEntry],
+    params: &ResolutionParams,
+) -> Result<FontCatalogEntry, (StatusCode, String)> {
+    let collisions = find(&candidate, existing);
+    if collisions.is_empty() {
+        return Ok(candidate);
+    }
+    match params.resolution {
+        None => Err((StatusCode::CONFLICT, describe(&collisions))),
+        Some(Resolution::ForceNew) => Ok(candidate),
+        Some(Resolution::NewVersion) => {
+            let targets: Vec<&str> = collisions.iter().map(|c| c.existing_id.as_str()).collect();
+            if targets.iter().any(|t| *t != targets[0]) {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!("new_version is ambiguous: collides with {}", targets.join(", ")),
+                ));
+            }
+            candidate.id = targets[0].to_string();
+            Ok(candidate)
+        }
+        Some(Resolution::Rename) => {
+            let Some(name) = params.rename_to.as_deref().map(str::trim).filter(|n| !n.is_empty()) else {
+                return Err((StatusCode::BAD_REQUEST, "resolution=rename requires rename_to".to_string()));
+            };
+            candidate.family = name.to_string();
+            candidate.postscript_name = Some(format!(
+                "{}-{}",
+                name.replace(char::is_whitespace, ""),
+                candidate.variant.replace(char::is_whitespace, "")
+            ));
+            let remaining = find(&candidate, existing);
+            if remaining.is_empty() {
+                Ok(candidate)
+            } else {
+                Err((StatusCode::CONFLICT, describe(&remaining)))
+            }
+        }
+    }
+}
+
+fn describe(collisions: &[Collision]) -> String {
+    let list: Vec<String> = collisions
+        .iter()
+        .map(|c| {
+            format!(
+                "{} '{}' of '{}' (foundry: {})",
+                c.field,
+                c.value,
+                c.existing_id,
+                c.existing_foundry.as_deref().unwrap_or("unknown")
+            )
+        })
+        .collect();
+    format!(
+        "name collision with {}; retry with ?resolution=new_version, \
+         ?resolution=rename&rename_to=<family>, or ?resolution=force_new",
+        list.join("; ")
+    )
+}
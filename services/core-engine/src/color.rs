@@ -0,0 +1,289 @@
+//! Color fonts: the `CPAL` palettes and a summary of `COLR`, as analyze
+//! reports them, and `POST /api/v1/font/recolor`.
+//!
+//! Recoloring overrides entries of one palette (brand colors, say) and bakes
+//! the result into `CPAL`; glyph layers and paints are untouched, so every
+//! shape that used an entry now draws in the new color. Palettes that shared
+//! color records with the overridden one keep their old colors.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{RecolorRequest, RecolorResponse};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+
+use crate::{
+    cancel, compress, duplicates,
+    extract::ApiJson,
+    sfnt::{be_u16, be_u32, Font},
+    signing, AppState,
+};
+
+const LIGHT_BACKGROUND: u32 = 1;
+const DARK_BACKGROUND: u32 = 2;
+
+/// A `CPAL` table; colors are RGBA.
+pub struct Cpal {
+    entries: usize,
+    palettes: Vec<Vec<[u8; 4]>>,
+    /// Version 1 arrays: palette types, palette label name IDs and entry
+    /// label name IDs, each absent when its offset is 0.
+    types: Option<Vec<u32>>,
+    labels: Option<Vec<u16>>,
+    entry_labels: Option<Vec<u16>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Palette {
+    pub index: usize,
+    /// `#RRGGBBAA`, by entry index.
+    pub colors: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub light_background: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dark_background: bool,
+    /// `name` ID of the palette's label.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label_name_id: Option<u16>,
+}
+
+/// What a `COLR` table holds.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Colr {
+    pub version: u16,
+    /// Glyphs with color layers (version 0 records).
+    pub layered_glyphs: usize,
+    pub layers: usize,
+    /// Glyphs with version 1 paint graphs.
+    pub painted_glyphs: usize,
+}
+
+fn hex([r, g, b, a]: [u8; 4]) -> String {
+    format!("#{r:02X}{g:02X}{b:02X}{a:02X}")
+}
+
+/// `#RRGGBB` or `#RRGGBBAA`, the `#` optional.
+fn parse_color(text: &str) -> Option<[u8; 4]> {
+    let digits = text.strip_prefix('#').unwrap_or(text);
+    if !matches!(digits.len(), 6 | 8) || !digits.is_ascii() {
+        return None;
+    }
+    let byte = |i: usize| u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).ok();
+    Some([byte(0)?, byte(1)?, byte(2)?, if digits.len() == 8 { byte(3)? } else { 0xFF }])
+}
+
+impl Cpal {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let truncated = || "CPAL table truncated".to_string();
+        let version = be_u16(data, 0).ok_or_else(truncated)?;
+        let entries = be_u16(data, 2).ok_or_else(truncated)? as usize;
+        let count = be_u16(data, 4).ok_or_else(truncated)? as usize;
+        let records = be_u16(data, 6).ok_or_else(truncated)? as usize;
+        let colors = be_u32(data, 8).ok_or_else(truncated)? as usize;
+        let palettes = (0..count)
+            .map(|i| {
+                let first = be_u16(data, 12 + 2 * i).ok_or_else(truncated)? as usize;
+                if first + entries > records {
+                    return Err(format!("CPAL palette {i} runs past the color records"));
+                }
+                (first..first + entries)
+                    .map(|r| {
+                        let at = colors + 4 * r;
+                        let [b, g, r, a] = data.get(at..at + 4).ok_or_else(truncated)?.try_into().unwrap();
+                        Ok([r, g, b, a])
+                    })
+                    .collect()
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let mut cpal = Self { entries, palettes, types: None, labels: None, entry_labels: None };
+        if version >= 1 {
+            let arrays = 12 + 2 * count;
+            let offset = |i: usize| be_u32(data, arrays + 4 * i).map(|o| o as usize).ok_or_else(truncated);
+            let u16s = |at: usize, n: usize| (0..n).map(|i| be_u16(data, at + 2 * i)).collect::<Option<Vec<_>>>();
+            cpal.types = match offset(0)? {
+                0 => None,
+                at => Some((0..count).map(|i| be_u32(data, at + 4 * i)).collect::<Option<_>>().ok_or_else(truncated)?),
+            };
+            cpal.labels = match offset(1)? {
+                0 => None,
+                at => Some(u16s(at, count).ok_or_else(truncated)?),
+            };
+            cpal.entry_labels = match offset(2)? {
+                0 => None,
+                at => Some(u16s(at, entries).ok_or_else(truncated)?),
+            };
+        }
+        Ok(cpal)
+    }
+
+    pub fn palettes(&self) -> Vec<Palette> {
+        let kind = |i: usize| self.types.as_ref().and_then(|t| t.get(i)).copied().unwrap_or(0);
+        let label = |i: usize| self.labels.as_ref().and_then(|l| l.get(i)).copied().filter(|&id| id != 0xFFFF);
+        self.palettes
+            .iter()
+            .enumerate()
+            .map(|(index, colors)| Palette {
+                index,
+                colors: colors.iter().map(|&c| hex(c)).collect(),
+                light_background: kind(index) & LIGHT_BACKGROUND != 0,
+                dark_background: kind(index) & DARK_BACKGROUND != 0,
+                label_name_id: label(index),
+            })
+            .collect()
+    }
+
+    /// Serialises with a color record run per palette; version 1 when any
+    /// version 1 array is present.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let v1 = self.types.is_some() || self.labels.is_some() || self.entry_labels.is_some();
+        let count = self.palettes.len();
+        let header = 12 + 2 * count + if v1 { 12 } else { 0 };
+        let mut out = Vec::new();
+        out.extend(u16::from(v1).to_be_bytes());
+        out.extend((self.entries as u16).to_be_bytes());
+        out.extend((count as u16).to_be_bytes());
+        out.extend(((count * self.entries) as u16).to_be_bytes());
+        out.extend((header as u32).to_be_bytes());
+        for i in 0..count {
+            out.extend(((i * self.entries) as u16).to_be_bytes());
+        }
+        if v1 {
+            let mut at = header + 4 * count * self.entries;
+            let lens = [
+                self.types.as_ref().map(|t| 4 * t.len()),
+                self.labels.as_ref().map(|l| 2 * l.len()),
+                self.entry_labels.as_ref().map(|l| 2 * l.len()),
+            ];
+            for len in lens {
+                out.extend(len.map_or(0, |_| at as u32).to_be_bytes());
+                at += len.unwrap_or(0);
+            }
+        }
+        for &[r, g, b, a] in self.palettes.iter().flatten() {
+            out.extend([b, g, r, a]);
+        }
+        for kind in self.types.iter().flatten() {
+            out.extend(kind.to_be_bytes());
+        }
+        for id in self.labels.iter().flatten().chain(self.entry_labels.iter().flatten()) {
+            out.extend(id.to_be_bytes());
+        }
+        out
+    }
+}
+
+impl Colr {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let version = be_u16(data, 0)?;
+        let list = if version >= 1 { be_u32(data, 14)? as usize } else { 0 };
+        let painted_glyphs = if list == 0 { 0 } else { be_u32(data, list)? as usize };
+        Some(Self {
+            version,
+            layered_glyphs: be_u16(data, 2)? as usize,
+            layers: be_u16(data, 12)? as usize,
+            painted_glyphs,
+        })
+    }
+}
+
+/// Stored with a recolored font (see [`artifacts::ArtifactStore`]).
+#[derive(Serialize, Deserialize)]
+struct RecolorRecord {
+    output_bytes: usize,
+    colors: Vec<String>,
+}
+
+fn bad_request(message: impl Into<String>) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message.into())
+}
+
+/// Overrides palette `palette` of `font`; returns the palette's colors.
+fn recolor(font: &mut Font, palette: usize, colors: &[(usize, [u8; 4])]) -> Result<Vec<String>, String> {
+    let mut cpal = Cpal::parse(font.table(b"CPAL").ok_or("font has no CPAL table; it is not a color font")?)?;
+    let count = cpal.palettes.len();
+    let entries = cpal.entries;
+    let target = cpal
+        .palettes
+        .get_mut(palette)
+        .ok_or_else(|| format!("palette {palette} does not exist; the font has {count}"))?;
+    for &(entry, color) in colors {
+        *target.get_mut(entry).ok_or_else(|| format!("entry {entry} does not exist; palettes have {entries}"))? = color;
+    }
+    let result = target.iter().map(|&c| hex(c)).collect();
+    font.set_table(*b"CPAL", cpal.to_bytes());
+    Ok(result)
+}
+
+pub async fn recolor_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(mut req): ApiJson<RecolorRequest>,
+) -> Result<Json<RecolorResponse>, (StatusCode, String)> {
+    state.flags.ensure("compress", &headers)?;
+    state.quotas.charge_operation(&headers)?;
+    if !["woff2", "woff", "otf", "ttf"].contains(&req.format.as_str()) {
+        return Err(bad_request(format!("unsupported format '{}'; valid: woff2, woff, otf, ttf", req.format)));
+    }
+    if req.colors.is_empty() {
+        return Err(bad_request("colors must override at least one palette entry"));
+    }
+    let colors = req
+        .colors
+        .iter()
+        .map(|(&entry, text)| {
+            let color = parse_color(text)
+                .ok_or_else(|| bad_request(format!("color '{text}' for entry {entry} should look like #RRGGBB or #RRGGBBAA")))?;
+            Ok((entry, color))
+        })
+        .collect::<Result<Vec<_>, (StatusCode, String)>>()?;
+    let (font_name, upload) = state.uploads.resolve(&headers, req.font_id.as_deref(), &req.font_name).await?;
+    req.font_name = font_name;
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &req.font_name)?;
+
+    let uploaded = match &upload {
+        Some(upload) => Some(state.uploads.read(upload).await?),
+        None => None,
+    };
+    let (job_state, job_req) = (Arc::clone(&state), req.clone());
+    let (palette, encoded) = cancel::run(&state.jobs, "recolor", move |token| {
+        let data = match uploaded {
+            Some(data) => data,
+            None => match duplicates::catalog_binary(&job_state, &job_req.font_name) {
+                Ok(data) => data,
+                Err(e) => return Ok(Err(e)),
+            },
+        };
+        token.check()?;
+        Ok(compress::load(&data).and_then(|mut font| {
+            let palette = recolor(&mut font, job_req.palette, &colors)?;
+            Ok((palette, compress::encode(&font, &job_req.format, 100)?))
+        }))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let source = state
+        .artifacts
+        .source(&state, &req.font_name, upload.as_ref())
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let transform = serde_json::json!({ "recolor": { "palette": req.palette, "colors": palette } });
+    let address = state.artifacts.address(&req.font_name, &source, &transform, &req.format);
+    if state.artifacts.lookup::<RecolorRecord>(&address).await?.is_none() {
+        let record = RecolorRecord { output_bytes: encoded.len(), colors: palette.clone() };
+        state.artifacts.put(&address, &encoded, &record).await?;
+    }
+
+    info!(font = %req.font_name, palette = req.palette, overridden = req.colors.len(), "font recolored");
+    Ok(Json(RecolorResponse {
+        font_name: req.font_name,
+        format: req.format,
+        palette: req.palette,
+        colors: palette,
+        size_kb: encoded.len() as f64 / 1024.0,
+        download_url: signing::download_url(&state, &address.path()),
+    }))
+}
@@ -0,0 +1,140 @@
+//! Real font compression for `/api/v1/font/compress`.
+//!
+//! The source binary (TTF, OTF or WOFF) is parsed, optionally stripped of
+//! TrueType hinting, and re-encoded: WOFF2 through Brotli, WOFF through
+//! zlib, TTF/OTF as a clean sfnt and EOT as EOT-lite. `quality` 0-100 maps
+//! onto the encoder's effort (Brotli 0-11, zlib 0-9); output is lossless at
+//! every quality. `inline` requests get the output back as a `data:` URI
+//! as well, for emails and single-file HTML that cannot fetch a font.
+
+use base64::Engine;
+
+use crate::{
+    glyf::{self, Glyf},
+    licensing,
+    name::NameTable,
+    sfnt::{be_u16, be_u32, sniff, Flavor, Font},
+    woff,
+};
+
+/// Tables that exist only to drive the TrueType hinting interpreter or
+/// cache its results.
+const HINTING_TABLES: [&[u8; 4]; 6] = [b"fpgm", b"prep", b"cvt ", b"hdmx", b"VDMX", b"LTSH"];
+const OTTO: u32 = 0x4F54_544F;
+/// Largest output returned inline; anything bigger belongs on the CDN.
+pub const MAX_INLINE_BYTES: usize = 1024 * 1024;
+
+pub fn brotli_level(quality: u8) -> u8 {
+    ((quality.min(100) as u32 * 11 + 50) / 100) as u8
+}
+
+pub fn zlib_level(quality: u8) -> u32 {
+    (quality.min(100) as u32 * 9 + 50) / 100
+}
+
+/// Parses a TTF, OTF or WOFF binary.
+pub fn load(data: &[u8]) -> Result<Font, String> {
+    match sniff(data) {
+        Some(Flavor::Woff) => woff::decode_woff(data),
+        Some(Flavor::Woff2) => Err("WOFF2 input is already compressed; send the ttf, otf or woff".to_string()),
+        _ => Font::parse(data),
+    }
+}
+
+/// Drops hinting tables and glyph programs.
+pub fn strip_hints(font: &mut Font) -> Result<(), String> {
+    font.tables.retain(|t| !HINTING_TABLES.contains(&&t.tag));
+    if let Some(glyf) = Glyf::parse(font).transpose()? {
+        let glyphs: Vec<Vec<u8>> =
+            (0..glyf.num_glyphs() as u16).map(|g| glyf::strip_instructions(glyf.data(g))).collect();
+        glyf::rebuild(font, &glyphs)?;
+    }
+    Ok(())
+}
+
+/// Encodes `font` as `format` (`woff2`, `woff`, `ttf`, `otf` or `eot`),
+/// unless its license forbids embedding.
+pub fn encode(font: &Font, format: &str, quality: u8) -> Result<Vec<u8>, String> {
+    licensing::check_embedding(font)?;
+    let cff = font.sfnt_version() == OTTO;
+    match (format, cff) {
+        ("ttf", true) => return Err("this font has CFF outlines; ask for otf instead of ttf".to_string()),
+        ("otf", false) => return Err("this font has TrueType outlines; ask for ttf instead of otf".to_string()),
+        _ => {}
+    }
+    let sfnt = font.to_bytes();
+    match format {
+        "woff2" => woff::encode_woff2(&sfnt, brotli_level(quality)),
+        "woff" => woff::encode_woff(&sfnt, zlib_level(quality), woff::metadata_xml(font).as_deref()),
+        "eot" => eot_lite(font, sfnt),
+        _ => Ok(sfnt),
+    }
+}
+
+/// Media type of an encoded `format`.
+pub fn media_type(format: &str) -> &'static str {
+    match format {
+        "woff2" => "font/woff2",
+        "woff" => "font/woff",
+        "otf" => "font/otf",
+        "eot" => "application/vnd.ms-fontobject",
+        _ => "font/ttf",
+    }
+}
+
+/// `data:<media type>;base64,...` for `encoded`, unless it is over
+/// [`MAX_INLINE_BYTES`].
+pub fn data_uri(format: &str, encoded: &[u8]) -> Result<String, String> {
+    if encoded.len() > MAX_INLINE_BYTES {
+        return Err(format!(
+            "output is {} KB, over the {} KB inline limit; use download_url instead",
+            encoded.len() / 1024,
+            MAX_INLINE_BYTES / 1024
+        ));
+    }
+    let data = base64::engine::general_purpose::STANDARD.encode(encoded);
+    Ok(format!("data:{};base64,{data}", media_type(format)))
+}
+
+/// EOT 2.1 header around the unmodified sfnt (no MTX compression or XOR
+/// obfuscation), little-endian throughout.
+fn eot_lite(font: &Font, sfnt: Vec<u8>) -> Result<Vec<u8>, String> {
+    let os2 = font.table(b"OS/2").ok_or("EOT needs an OS/2 table")?;
+    // checkSumAdjustment as serialised, not as it was in the source.
+    let adjustment = Font::parse(&sfnt)?.table(b"head").and_then(|h| be_u32(h, 8)).ok_or("font has no head table")?;
+    let names = font.table(b"name").map(NameTable::parse).transpose()?;
+    let name = |id| names.as_ref().and_then(|n| n.get(id)).unwrap_or_default();
+    let field = |at| be_u32(os2, at).unwrap_or(0);
+
+    let mut out = Vec::new();
+    let u32le = |out: &mut Vec<u8>, v: u32| out.extend_from_slice(&v.to_le_bytes());
+    let u16le = |out: &mut Vec<u8>, v: u16| out.extend_from_slice(&v.to_le_bytes());
+    u32le(&mut out, 0); // EOTSize, patched below
+    u32le(&mut out, sfnt.len() as u32);
+    u32le(&mut out, 0x0002_0001);
+    u32le(&mut out, 0); // flags: no subsetting, compression or obfuscation
+    out.extend_from_slice(os2.get(32..42).ok_or("OS/2 table truncated")?); // PANOSE
+    out.push(1); // DEFAULT_CHARSET
+    out.push((be_u16(os2, 62).unwrap_or(0) & 1) as u8); // fsSelection ITALIC
+    u32le(&mut out, be_u16(os2, 4).unwrap_or(400) as u32);
+    u16le(&mut out, be_u16(os2, 8).unwrap_or(0));
+    u16le(&mut out, 0x504C); // magic
+    for at in [42, 46, 50, 54, 78, 82] {
+        u32le(&mut out, field(at)); // ulUnicodeRange1-4, ulCodePageRange1-2
+    }
+    u32le(&mut out, adjustment);
+    out.extend_from_slice(&[0; 16]); // Reserved1-4
+    for id in [1, 2, 5, 4] {
+        // Family, subfamily, version and full name, each after a padding word.
+        let utf16: Vec<u8> = name(id).encode_utf16().flat_map(u16::to_le_bytes).collect();
+        u16le(&mut out, 0);
+        u16le(&mut out, utf16.len() as u16);
+        out.extend_from_slice(&utf16);
+    }
+    u16le(&mut out, 0);
+    u16le(&mut out, 0); // RootStringSize: usable from any page
+    out.extend_from_slice(&sfnt);
+    let size = out.len() as u32;
+    out[..4].copy_from_slice(&size.to_le_bytes());
+    Ok(out)
+}
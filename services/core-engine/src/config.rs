@@ -0,0 +1,377 @@
+//! Engine configuration file.
+//!
+//! Settings can come from a TOML file — `FONT_CONFIG`, or `config.toml` in
+//! the working directory when that exists — as well as from the environment.
+//! Environment variables win over the file, so a deploy can pin one value
+//! without editing it:
+//!
+//! ```toml
+//! listen_addr = "0.0.0.0:8082"
+//!
+//! [storage]
+//! backend = "s3"
+//!
+//! [cors]
+//! origins = ["https://app.example.com"]
+//!
+//! [rate_limit]
+//! per_minute = 600
+//!
+//! [workers]
+//! job_workers = 4
+//! job_queue = "postgres"
+//!
+//! [cache_control]
+//! css = "public, max-age=600, stale-while-revalidate=3600"
+//!
+//! [tls]
+//! cert_path = "/etc/font-engine/fullchain.pem"
+//! key_path = "/etc/font-engine/privkey.pem"
+//! redirect_addr = "0.0.0.0:80"
+//! ```
+//!
+//! [`EngineConfig::load`] parses and validates everything once at startup;
+//! the merged values are then exported under their environment names, which
+//! is where the rest of the engine reads them. A reload (see
+//! [`crate::reload`]) reads the file again and reports which settings
+//! changed; CORS origins and rate limits take effect at once, the rest on
+//! the next restart.
+
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::SocketAddr,
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+};
+
+use crate::{cache_control, cors};
+
+const DEFAULT_FILE: &str = "config.toml";
+
+/// What [`EngineConfig::export`] left in effect.
+#[derive(Default)]
+struct Exported {
+    /// Variables set from the file rather than by the deployment; a reload
+    /// reads the file for them again.
+    from_file: BTreeSet<&'static str>,
+    values: BTreeMap<&'static str, String>,
+}
+
+static EXPORTED: Mutex<Option<Exported>> = Mutex::new(None);
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// `local` or `s3`.
+    pub backend: Option<String>,
+    pub artifact_dir: Option<PathBuf>,
+    pub upload_dir: Option<PathBuf>,
+    pub spool_dir: Option<PathBuf>,
+    pub quarantine_dir: Option<PathBuf>,
+    pub job_dir: Option<PathBuf>,
+    pub catalog_font_dir: Option<PathBuf>,
+    pub database_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub subset_cache_bytes: Option<u64>,
+    pub max_upload_bytes: Option<u64>,
+    pub max_json_bytes: Option<u64>,
+}
+
+/// `Cache-Control` values by policy (see [`cache_control`]).
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheControlConfig {
+    pub immutable: Option<String>,
+    pub css: Option<String>,
+    pub catalog: Option<String>,
+    pub generated: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    pub origins: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub per_minute: Option<f64>,
+    pub burst: Option<f64>,
+    pub trust_forwarded: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkerConfig {
+    pub job_workers: Option<u64>,
+    /// `memory` or `postgres`.
+    pub job_queue: Option<String>,
+    pub job_queue_limit: Option<u64>,
+    pub job_timeout_secs: Option<u64>,
+    pub batch_concurrency: Option<u64>,
+    pub processing_timeout_secs: Option<u64>,
+    pub catalog_timeout_secs: Option<u64>,
+    pub transform_timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert_path: Option<PathBuf>,
+    pub key_path: Option<PathBuf>,
+    pub redirect_addr: Option<SocketAddr>,
+    pub reload_secs: Option<u64>,
+}
+
+/// Every file setting is optional; unset ones keep the engine's defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    pub listen_addr: Option<SocketAddr>,
+    pub grpc_addr: Option<SocketAddr>,
+    pub storage: StorageConfig,
+    pub cache: CacheConfig,
+    pub cache_control: CacheControlConfig,
+    pub cors: CorsConfig,
+    pub rate_limit: RateLimitConfig,
+    pub workers: WorkerConfig,
+    pub tls: TlsConfig,
+}
+
+/// A file setting and how it is written as an environment variable.
+struct Field<'a, T> {
+    slot: &'a mut Option<T>,
+    parse: fn(&str) -> Option<T>,
+    render: fn(&T) -> String,
+}
+
+trait Slot {
+    fn overlay(&mut self, raw: &str) -> bool;
+    fn rendered(&self) -> Option<String>;
+}
+
+impl<T> Slot for Field<'_, T> {
+    fn overlay(&mut self, raw: &str) -> bool {
+        *self.slot = (self.parse)(raw);
+        self.slot.is_some()
+    }
+
+    fn rendered(&self) -> Option<String> {
+        self.slot.as_ref().map(self.render)
+    }
+}
+
+fn field<'a, T: FromStr + ToString + 'a>(slot: &'a mut Option<T>) -> Box<dyn Slot + 'a> {
+    Box::new(Field { slot, parse: |raw| raw.parse().ok(), render: T::to_string })
+}
+
+fn path(slot: &mut Option<PathBuf>) -> Box<dyn Slot + '_> {
+    Box::new(Field { slot, parse: |raw| Some(PathBuf::from(raw)), render: |p| p.display().to_string() })
+}
+
+/// `true`/`1` or `false`/`0`, like the rest of the engine's switches.
+fn flag(slot: &mut Option<bool>) -> Box<dyn Slot + '_> {
+    let parse = |raw: &str| match raw {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    };
+    Box::new(Field { slot, parse, render: bool::to_string })
+}
+
+/// Comma-separated in the environment.
+fn list(slot: &mut Option<Vec<String>>) -> Box<dyn Slot + '_> {
+    let parse = |raw: &str| Some(raw.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect());
+    Box::new(Field { slot, parse, render: |l: &Vec<String>| l.join(",") })
+}
+
+fn var(k: &str) -> Option<String> {
+    std::env::var(k).ok().filter(|v| !v.is_empty())
+}
+
+impl EngineConfig {
+    /// Every setting with the environment variable that overrides it.
+    fn slots(&mut self) -> Vec<(&'static str, Box<dyn Slot + '_>)> {
+        let Self { listen_addr, grpc_addr, storage, cache, cache_control, cors, rate_limit, workers, tls } = self;
+        vec![
+            ("FONT_ADDR", field(listen_addr)),
+            ("GRPC_ADDR", field(grpc_addr)),
+            ("FONT_STORAGE", field(&mut storage.backend)),
+            ("ARTIFACT_DIR", path(&mut storage.artifact_dir)),
+            ("UPLOAD_DIR", path(&mut storage.upload_dir)),
+            ("SPOOL_DIR", path(&mut storage.spool_dir)),
+            ("QUARANTINE_DIR", path(&mut storage.quarantine_dir)),
+            ("JOB_DIR", path(&mut storage.job_dir)),
+            ("CATALOG_FONT_DIR", path(&mut storage.catalog_font_dir)),
+            ("DATABASE_URL", field(&mut storage.database_url)),
+            ("SUBSET_CACHE_BYTES", field(&mut cache.subset_cache_bytes)),
+            ("MAX_UPLOAD_BYTES", field(&mut cache.max_upload_bytes)),
+            ("MAX_JSON_BYTES", field(&mut cache.max_json_bytes)),
+            ("CACHE_CONTROL_IMMUTABLE", field(&mut cache_control.immutable)),
+            ("CACHE_CONTROL_CSS", field(&mut cache_control.css)),
+            ("CACHE_CONTROL_CATALOG", field(&mut cache_control.catalog)),
+            ("CACHE_CONTROL_GENERATED", field(&mut cache_control.generated)),
+            ("CORS_ORIGINS", list(&mut cors.origins)),
+            ("RATE_LIMIT_PER_MINUTE", field(&mut rate_limit.per_minute)),
+            ("RATE_LIMIT_BURST", field(&mut rate_limit.burst)),
+            ("RATE_LIMIT_TRUST_FORWARDED", flag(&mut rate_limit.trust_forwarded)),
+            ("JOB_WORKERS", field(&mut workers.job_workers)),
+            ("JOB_QUEUE", field(&mut workers.job_queue)),
+            ("JOB_QUEUE_LIMIT", field(&mut workers.job_queue_limit)),
+            ("JOB_TIMEOUT_SECS", field(&mut workers.job_timeout_secs)),
+            ("BATCH_CONCURRENCY", field(&mut workers.batch_concurrency)),
+            ("PROCESSING_TIMEOUT_SECS", field(&mut workers.processing_timeout_secs)),
+            ("CATALOG_TIMEOUT_SECS", field(&mut workers.catalog_timeout_secs)),
+            ("TRANSFORM_TIMEOUT_SECS", field(&mut workers.transform_timeout_secs)),
+            ("TLS_CERT", path(&mut tls.cert_path)),
+            ("TLS_KEY", path(&mut tls.key_path)),
+            ("TLS_REDIRECT_ADDR", field(&mut tls.redirect_addr)),
+            ("TLS_RELOAD_SECS", field(&mut tls.reload_secs)),
+        ]
+    }
+
+    /// The configuration file, if one is in use.
+    pub fn path() -> Option<PathBuf> {
+        match var("FONT_CONFIG") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Some(PathBuf::from(DEFAULT_FILE)).filter(|p| p.is_file()),
+        }
+    }
+
+    /// Reads the file, applies environment overrides and validates the
+    /// result; every problem found is returned.
+    pub fn load() -> Result<Self, Vec<String>> {
+        let mut config = match Self::path() {
+            Some(path) => {
+                let raw = std::fs::read_to_string(&path).map_err(|e| vec![format!("{}: {e}", path.display())])?;
+                toml::from_str(&raw).map_err(|e| vec![format!("{}: {e}", path.display())])?
+            }
+            None => Self::default(),
+        };
+        let mut errors = Vec::new();
+        let from_file = EXPORTED.lock().unwrap().as_ref().map(|e| e.from_file.clone()).unwrap_or_default();
+        for (name, mut slot) in config.slots() {
+            if let Some(raw) = var(name).filter(|_| !from_file.contains(name)) {
+                if !slot.overlay(&raw) {
+                    errors.push(format!("{name}={raw:?} is not a valid value"));
+                }
+            }
+        }
+        errors.extend(config.validate());
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if let Some(backend) = self.storage.backend.as_deref().filter(|b| !matches!(*b, "local" | "s3")) {
+            errors.push(format!("storage.backend {backend:?} must be local or s3"));
+        }
+        if let Some(url) = &self.storage.database_url {
+            if !url.starts_with("postgres://") && !url.starts_with("postgresql://") {
+                errors.push("storage.database_url must start with postgres:// or postgresql://".to_string());
+            }
+        }
+        match self.workers.job_queue.as_deref() {
+            None | Some("memory") => {}
+            Some("postgres") if self.storage.database_url.is_none() => {
+                errors.push("workers.job_queue \"postgres\" needs storage.database_url".to_string())
+            }
+            Some("postgres") => {}
+            Some(other) => errors.push(format!("workers.job_queue {other:?} must be memory or postgres")),
+        }
+        let policies = [
+            ("cache_control.immutable", &self.cache_control.immutable),
+            ("cache_control.css", &self.cache_control.css),
+            ("cache_control.catalog", &self.cache_control.catalog),
+            ("cache_control.generated", &self.cache_control.generated),
+        ];
+        for (name, value) in policies {
+            if let Some(Err(e)) = value.as_deref().map(cache_control::validate) {
+                errors.push(format!("{name}: {e}"));
+            }
+        }
+        if let Some(origins) = &self.cors.origins {
+            if let Err(e) = cors::normalized(origins) {
+                errors.push(format!("cors.origins: {e}"));
+            }
+        }
+        let rates = [
+            ("rate_limit.per_minute", self.rate_limit.per_minute),
+            ("rate_limit.burst", self.rate_limit.burst),
+        ];
+        for (name, value) in rates {
+            if value.is_some_and(|v| v.is_nan() || v <= 0.0) {
+                errors.push(format!("{name} must be positive"));
+            }
+        }
+        let pools = [
+            ("workers.job_workers", self.workers.job_workers),
+            ("workers.batch_concurrency", self.workers.batch_concurrency),
+        ];
+        let timeouts = [
+            ("workers.catalog_timeout_secs", self.workers.catalog_timeout_secs),
+            ("workers.transform_timeout_secs", self.workers.transform_timeout_secs),
+        ];
+        for (name, value) in pools.into_iter().chain(timeouts) {
+            if value == Some(0) {
+                errors.push(format!("{name} must be at least 1"));
+            }
+        }
+        match (&self.tls.cert_path, &self.tls.key_path) {
+            (Some(_), None) => errors.push("tls.cert_path needs tls.key_path".to_string()),
+            (None, Some(_)) => errors.push("tls.key_path needs tls.cert_path".to_string()),
+            (None, None) if self.tls.redirect_addr.is_some() => {
+                errors.push("tls.redirect_addr needs tls.cert_path and tls.key_path".to_string())
+            }
+            _ => {}
+        }
+        errors
+    }
+
+    /// Publishes file settings under their environment names, leaving
+    /// variables that are already set alone. Runs before any other thread
+    /// reads the environment.
+    pub fn export(mut self) {
+        let mut exported = Exported::default();
+        for (name, slot) in self.slots() {
+            let Some(value) = slot.rendered() else { continue };
+            if var(name).is_none() {
+                std::env::set_var(name, &value);
+                exported.from_file.insert(name);
+            }
+            exported.values.insert(name, value);
+        }
+        *EXPORTED.lock().unwrap() = Some(exported);
+    }
+
+    /// Settings whose value differs from the one in effect, by environment
+    /// name; they become the ones in effect. The environment is left alone,
+    /// so settings that cannot change at runtime keep their old value.
+    pub fn changes(&mut self) -> Vec<&'static str> {
+        let mut guard = EXPORTED.lock().unwrap();
+        let exported = guard.get_or_insert_with(Exported::default);
+        let mut changed = Vec::new();
+        for (name, slot) in self.slots() {
+            let value = slot.rendered();
+            if exported.values.get(name) != value.as_ref() {
+                changed.push(name);
+                match value {
+                    Some(value) => exported.values.insert(name, value),
+                    None => exported.values.remove(name),
+                };
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,197 @@
+//! Cross-origin access to fonts and stylesheets.
+//!
+//! Browsers refuse cross-origin fonts without `Access-Control-Allow-Origin`.
+//! [`apply`] wraps the delivery and CSS routes and allows the origins on a
+//! list: `*`, exact origins like `https://app.example.com`, or subdomain
+//! wildcards like `https://*.example.com`. The global list starts from
+//! `CORS_ORIGINS` (default `*`) and, like [`crate::hints`], kits can get
+//! their own list through the admin API, which replaces the global one for
+//! requests carrying their `X-Font-Tenant`. CDN requests have no tenant, so
+//! any kit's origins are allowed there: a font URL may be embedded by any
+//! kit's site.
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{Json, Response},
+};
+use font_api::{OriginSet, OriginUpdate};
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+use tracing::{info, warn};
+
+use crate::{audit, extract::ApiJson, AppState};
+
+const MAX_ORIGINS: usize = 64;
+
+/// How long browsers may cache a preflight answer.
+const PREFLIGHT_MAX_AGE: &str = "86400";
+
+pub struct CorsPolicy {
+    set: RwLock<OriginSet>,
+}
+
+/// `*`, or `scheme://host[:port]` with an optional leading `*.` label,
+/// lowercased; `None` when `entry` is neither.
+fn pattern(entry: &str) -> Option<String> {
+    let entry = entry.trim().trim_end_matches('/');
+    if entry == "*" {
+        return Some(entry.to_string());
+    }
+    let (scheme, host) = entry.split_once("://")?;
+    let host = host.strip_prefix("*.").unwrap_or(host);
+    let valid = matches!(scheme, "http" | "https")
+        && !host.is_empty()
+        && host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'));
+    valid.then(|| entry.to_ascii_lowercase())
+}
+
+fn matches(pattern: &str, origin: &str) -> bool {
+    if pattern == "*" || pattern == origin {
+        return true;
+    }
+    // `https://*.example.com` covers `https://a.example.com` but not the apex.
+    pattern.split_once("://*.").is_some_and(|(scheme, domain)| {
+        origin
+            .strip_prefix(scheme)
+            .and_then(|o| o.strip_prefix("://"))
+            .and_then(|host| host.strip_suffix(domain))
+            .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.'))
+    })
+}
+
+pub fn normalized(origins: &[String]) -> Result<Vec<String>, String> {
+    if origins.len() > MAX_ORIGINS {
+        return Err(format!("at most {MAX_ORIGINS} origins"));
+    }
+    let mut out: Vec<String> = Vec::new();
+    for entry in origins {
+        let p = pattern(entry).ok_or_else(|| {
+            format!("'{entry}' must be *, an origin like https://app.example.com or https://*.example.com")
+        })?;
+        if !out.contains(&p) {
+            out.push(p);
+        }
+    }
+    Ok(out)
+}
+
+/// Parses `CORS_ORIGINS`; shared with `--check-config`.
+pub fn parse_env() -> Result<Vec<String>, String> {
+    match std::env::var("CORS_ORIGINS").ok().filter(|v| !v.trim().is_empty()) {
+        Some(spec) => {
+            let entries: Vec<String> =
+                spec.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect();
+            normalized(&entries).map_err(|e| format!("CORS_ORIGINS: {e}"))
+        }
+        None => Ok(vec!["*".to_string()]),
+    }
+}
+
+impl CorsPolicy {
+    pub fn from_env() -> Self {
+        let global = parse_env().unwrap_or_else(|e| {
+            // Fail closed: a typo must not open fonts to every site.
+            warn!("{e}; allowing no cross-origin requests");
+            Vec::new()
+        });
+        Self { set: RwLock::new(OriginSet { global, tenants: BTreeMap::new() }) }
+    }
+
+    /// Replaces the global list, as a reload of `cors.origins` does; kit
+    /// lists stay.
+    pub fn set_global(&self, origins: Vec<String>) {
+        self.set.write().unwrap().global = origins;
+    }
+
+    /// The `Access-Control-Allow-Origin` value for `origin`, if allowed.
+    fn allow(&self, headers: &HeaderMap, origin: &str) -> Option<HeaderValue> {
+        let tenant = headers.get("x-font-tenant").and_then(|v| v.to_str().ok());
+        let set = self.set.read().unwrap();
+        let origin = origin.to_ascii_lowercase();
+        let lists: Vec<&Vec<String>> = match tenant.and_then(|t| set.tenants.get(t)) {
+            Some(list) => vec![list],
+            None if tenant.is_some() => vec![&set.global],
+            None => std::iter::once(&set.global).chain(set.tenants.values()).collect(),
+        };
+        let matched = lists.into_iter().flatten().find(|p| matches(p, &origin))?;
+        if matched == "*" {
+            Some(HeaderValue::from_static("*"))
+        } else {
+            HeaderValue::from_str(&origin).ok()
+        }
+    }
+}
+
+pub async fn apply(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let origin = request.headers().get(header::ORIGIN).and_then(|v| v.to_str().ok()).map(String::from);
+    let allowed = origin.as_deref().and_then(|o| state.cors.allow(request.headers(), o));
+    let preflight = request.method() == Method::OPTIONS
+        && request.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    let mut response = if preflight {
+        let mut r = Response::builder().status(StatusCode::NO_CONTENT).body(Body::empty()).unwrap();
+        if allowed.is_some() {
+            let h = r.headers_mut();
+            h.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static("GET, HEAD, OPTIONS"));
+            if let Some(requested) = request.headers().get(header::ACCESS_CONTROL_REQUEST_HEADERS) {
+                h.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, requested.clone());
+            }
+            h.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from_static(PREFLIGHT_MAX_AGE));
+        }
+        r
+    } else {
+        next.run(request).await
+    };
+    let headers = response.headers_mut();
+    headers.remove(header::ACCESS_CONTROL_ALLOW_ORIGIN);
+    // Echoed origins make the response differ per origin.
+    if !matches!(&allowed, Some(v) if v == "*") {
+        headers.append(header::VARY, HeaderValue::from_static("origin"));
+    }
+    if let Some(value) = allowed {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, value);
+        headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, HeaderValue::from_static("etag, last-modified"));
+    }
+    response
+}
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<OriginSet>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    Ok(Json(state.cors.set.read().unwrap().clone()))
+}
+
+pub async fn update(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(update): ApiJson<OriginUpdate>,
+) -> Result<Json<OriginSet>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let origins = normalized(&update.origins).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let (before, set) = {
+        let mut set = state.cors.set.write().unwrap();
+        let before = set.clone();
+        match &update.tenant {
+            // An empty list drops the kit override and falls back to global.
+            Some(tenant) if origins.is_empty() => {
+                set.tenants.remove(tenant);
+            }
+            Some(tenant) => {
+                set.tenants.insert(tenant.clone(), origins);
+            }
+            None => set.global = origins,
+        }
+        (before, set.clone())
+    };
+    let target = update.tenant.as_deref().unwrap_or("global");
+    audit::record(&state, &headers, "cors.update", target, json!(before), json!(set)).await;
+    info!(tenant = ?update.tenant, "CORS origins updated");
+    Ok(Json(set))
+}
@@ -0,0 +1,133 @@
+//! `POST /api/v1/font/coverage`: which characters of a text a font renders.
+//!
+//! The font's own `cmap` decides what is covered. Missing characters are
+//! then matched against the `unicode_ranges` other catalog entries declare,
+//! and fallbacks are suggested greedily — each one covering the most of what
+//! is still missing — so CJK or emoji content can be checked before a font
+//! is committed to.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::CoverageRequest;
+use serde::Serialize;
+use std::{collections::BTreeSet, ops::RangeInclusive, sync::Arc};
+
+use crate::{cancel, cmap::CharMap, compress, duplicates, extract::ApiJson, unicode, AppState};
+
+const MAX_FALLBACKS: usize = 5;
+
+#[derive(Debug, Serialize)]
+pub struct Fallback {
+    id: String,
+    family: String,
+    /// Missing characters this font adds on top of earlier suggestions.
+    covers: usize,
+    characters: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CoverageResponse {
+    font_name: String,
+    requested: usize,
+    covered: usize,
+    coverage_percent: f64,
+    missing: String,
+    missing_ranges: Vec<String>,
+    /// Catalog fonts for the gaps; characters no font covers stay in
+    /// `uncovered`.
+    fallbacks: Vec<Fallback>,
+    uncovered: String,
+}
+
+/// Compact ranges of a sorted set of characters.
+fn ranges(chars: &BTreeSet<char>) -> Vec<RangeInclusive<u32>> {
+    unicode::merge(chars.iter().map(|&c| c as u32..=c as u32).collect())
+}
+
+pub async fn coverage(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(mut req): ApiJson<CoverageRequest>,
+) -> Result<Json<CoverageResponse>, (StatusCode, String)> {
+    state.flags.ensure("analyze", &headers)?;
+    let (font_name, upload) = state.uploads.resolve(&headers, req.font_id.as_deref(), &req.font_name).await?;
+    req.font_name = font_name;
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &req.font_name)?;
+
+    let mut wanted: BTreeSet<char> = req.characters.chars().filter(|c| !c.is_control()).collect();
+    if let Some(name) = &req.preset {
+        let preset = unicode::preset(name).ok_or_else(|| {
+            (StatusCode::BAD_REQUEST, format!("unknown preset '{name}'; valid: {}", unicode::preset_names()))
+        })?;
+        wanted.extend(preset.into_iter().flatten().filter_map(char::from_u32));
+    }
+    if wanted.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "characters or preset is required".to_string()));
+    }
+
+    let uploaded = match &upload {
+        Some(upload) => Some(state.uploads.read(upload).await?),
+        None => None,
+    };
+    let (job_state, name) = (Arc::clone(&state), req.font_name.clone());
+    let cmap = cancel::run(&state.jobs, "coverage", move |token| {
+        let data = match uploaded {
+            Some(data) => data,
+            None => match duplicates::catalog_binary(&job_state, &name) {
+                Ok(data) => data,
+                Err(e) => return Ok(Err(e)),
+            },
+        };
+        token.check()?;
+        Ok(compress::load(&data).and_then(|font| {
+            CharMap::parse(font.table(b"cmap").ok_or("font has no cmap table")?)
+        }))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let (covered, mut missing): (BTreeSet<char>, BTreeSet<char>) =
+        wanted.iter().partition(|&&c| cmap.glyph(c).is_some_and(|g| g != 0));
+    let missing_all: String = missing.iter().collect();
+    let missing_ranges = ranges(&missing).iter().map(unicode::format_range).collect();
+
+    let key = req.font_name.to_lowercase();
+    let candidates: Vec<(String, String, Vec<RangeInclusive<u32>>)> = state
+        .catalog
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|e| e.id != key && e.family.to_lowercase() != key)
+        .filter(|e| state.sandbox.ensure_font(&headers, std::slice::from_ref(e), &e.id).is_ok())
+        .map(|e| (e.id.clone(), e.family.clone(), unicode::parse_ranges(&e.unicode_ranges)))
+        .collect();
+    let mut fallbacks = Vec::new();
+    while !missing.is_empty() && fallbacks.len() < MAX_FALLBACKS {
+        let best = candidates
+            .iter()
+            .filter(|(id, ..)| !fallbacks.iter().any(|f: &Fallback| &f.id == id))
+            .map(|(id, family, ranges)| {
+                let adds: BTreeSet<char> = missing.iter().copied().filter(|&c| unicode::covers(ranges, c)).collect();
+                (id, family, adds)
+            })
+            .max_by(|a, b| a.2.len().cmp(&b.2.len()).then_with(|| b.0.cmp(a.0)));
+        let Some((id, family, adds)) = best.filter(|(_, _, adds)| !adds.is_empty()) else { break };
+        missing.retain(|c| !adds.contains(c));
+        let (covers, characters) = (adds.len(), adds.iter().collect());
+        fallbacks.push(Fallback { id: id.clone(), family: family.clone(), covers, characters });
+    }
+
+    Ok(Json(CoverageResponse {
+        font_name: req.font_name,
+        requested: wanted.len(),
+        covered: covered.len(),
+        coverage_percent: (covered.len() as f64 * 1000.0 / wanted.len() as f64).round() / 10.0,
+        missing: missing_all,
+        missing_ranges,
+        fallbacks,
+        uncovered: missing.iter().collect(),
+    }))
+}
@@ -0,0 +1,910 @@
+//! Page-crawl driven subsetting.
+//!
+//! `POST /api/v1/font/subset-from-url` fetches up to [`MAX_PAGES`] pages
+//! (and/or reads `html`) with the stylesheets they link, works out which
+//! `font-family` each piece of visible text is set in, and subsets every
+//! catalog face of each family to exactly the characters set in it: one
+//! call to optimize a site's fonts.
+//!
+//! The cascade is approximated: rules from `<style>` and linked sheets
+//! (inside `@media` and `@supports` too) match on their last compound
+//! selector (tag, classes, ID), the most specific and then the latest wins,
+//! `style` attributes beat both, and elements inherit from their parent.
+//! `font` shorthands count; custom properties, `@import`, `@font-face`
+//! aliases and scripts are not evaluated. Text in `<head>`, `<script>`,
+//! `<noscript>` and `<template>` is not visible. A stack's text goes to its
+//! first family in the catalog; stacks reaching a generic family first, or
+//! naming none in the catalog, are reported as `unmatched`.
+//!
+//! Only http(s) URLs resolving to public addresses are fetched (set
+//! `CRAWL_ALLOW_PRIVATE=true` for internal sites), redirects included, each
+//! document at most `CRAWL_MAX_BYTES` (default 2 MiB) within
+//! `CRAWL_TIMEOUT_SECS` (default 10). The check runs in the client's own
+//! resolver ([`PublicResolver`]), so the addresses checked are the ones
+//! connected to and a host cannot answer differently in between; proxies
+//! from the environment are not used while it is on.
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{FontCatalogEntry, SubsetFromUrlRequest, SubsetRequest, TransformOptions};
+use reqwest::Url;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tracing::info;
+
+use crate::{extract::ApiJson, unicode, AppState, SubsetResponse};
+
+pub const MAX_PAGES: usize = 10;
+const MAX_STYLESHEETS: usize = 16;
+const MAX_REDIRECTS: usize = 5;
+/// Subsets made by one request.
+const MAX_FACES: usize = 32;
+
+/// Elements whose text is never rendered.
+const HIDDEN: &[&str] = &["head", "script", "style", "noscript", "template", "title"];
+/// Elements whose content is not markup.
+const RAW_TEXT: &[&str] = &["script", "style", "textarea", "title", "noscript"];
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr",
+];
+const GENERIC: &[&str] = &[
+    "serif", "sans-serif", "monospace", "cursive", "fantasy", "system-ui", "ui-serif", "ui-sans-serif",
+    "ui-monospace", "ui-rounded", "emoji", "math", "fangsong", "-apple-system", "blinkmacsystemfont",
+];
+
+fn env_u64(k: &str, d: u64) -> u64 {
+    std::env::var(k).ok().and_then(|v| v.parse().ok()).unwrap_or(d)
+}
+
+pub struct Crawler {
+    http: reqwest::Client,
+    max_bytes: usize,
+    allow_private: bool,
+}
+
+/// Resolves hosts for the crawler, failing for any that resolves to a
+/// non-public address.
+pub struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            // Port 0 is replaced with the URL's.
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if let Some(a) = addresses.iter().find(|a| !public(a.ip())) {
+                return Err(format!("{host} resolves to non-public address {}", a.ip()).into());
+            }
+            Ok(Box::new(addresses.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// `e` with its causes, which say why a connection was refused.
+pub fn describe(e: &reqwest::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = std::error::Error::source(e);
+    while let Some(cause) = source {
+        message = format!("{message}: {cause}");
+        source = cause.source();
+    }
+    message
+}
+
+impl Crawler {
+    pub fn from_env() -> Self {
+        let allow_private = matches!(std::env::var("CRAWL_ALLOW_PRIVATE").as_deref(), Ok("true" | "1"));
+        let mut http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(env_u64("CRAWL_TIMEOUT_SECS", 10).max(1)))
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(concat!("font-engine/", env!("CARGO_PKG_VERSION")));
+        if !allow_private {
+            http = http.dns_resolver(Arc::new(PublicResolver)).no_proxy();
+        }
+        Self {
+            http: http.build().expect("failed to build HTTP client"),
+            max_bytes: env_u64("CRAWL_MAX_BYTES", 2 * 1024 * 1024) as usize,
+            allow_private,
+        }
+    }
+
+    /// Host names are checked as they are resolved (see [`PublicResolver`]);
+    /// addresses in the URL never reach a resolver, so they are checked here.
+    fn check(&self, url: &Url) -> Result<(), String> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("{url}: only http and https URLs are fetched"));
+        }
+        let host = url.host_str().ok_or_else(|| format!("{url}: no host"))?;
+        let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() else { return Ok(()) };
+        if !self.allow_private && !public(ip) {
+            return Err(format!("{url}: {ip} is not a public address"));
+        }
+        Ok(())
+    }
+
+    /// The body of `url` as text, following redirects, with the URL it was
+    /// finally read from.
+    async fn fetch(&self, url: &str) -> Result<(Url, String), String> {
+        let mut url = Url::parse(url).map_err(|e| format!("{url}: {e}"))?;
+        for _ in 0..=MAX_REDIRECTS {
+            self.check(&url)?;
+            let mut response = self.http.get(url.clone()).send().await.map_err(|e| format!("{url}: {}", describe(&e)))?;
+            if response.status().is_redirection() {
+                let location = response.headers().get(header::LOCATION).and_then(|v| v.to_str().ok());
+                let location = location.ok_or_else(|| format!("{url}: redirect without a Location"))?;
+                url = url.join(location).map_err(|e| format!("{url}: bad redirect: {e}"))?;
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(format!("{url}: {}", response.status()));
+            }
+            let mut body = Vec::new();
+            while let Some(chunk) = response.chunk().await.map_err(|e| format!("{url}: {e}"))? {
+                body.extend_from_slice(&chunk);
+                if body.len() > self.max_bytes {
+                    return Err(format!("{url}: larger than {} bytes", self.max_bytes));
+                }
+            }
+            return Ok((url, String::from_utf8_lossy(&body).into_owned()));
+        }
+        Err(format!("{url}: more than {MAX_REDIRECTS} redirects"))
+    }
+}
+
+pub fn public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            // 0.0.0.0/8 reaches this host; 100.64.0.0/10 is carrier-grade NAT,
+            // 198.18.0.0/15 benchmarking networks and 240.0.0.0/4 (with the
+            // broadcast address) reserved.
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || a == 0
+                || v4.is_multicast()
+                || a >= 240
+                || (a == 100 && b & 0xC0 == 64)
+                || (a == 198 && b & 0xFE == 18))
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => public(IpAddr::V4(v4)),
+            // 64:ff9b::/96 is NAT64, which reaches the embedded IPv4 address.
+            None if v6.segments()[..6] == [0x64, 0xFF9B, 0, 0, 0, 0] => {
+                let [.., a, b, c, d] = v6.octets();
+                public(IpAddr::V4([a, b, c, d].into()))
+            }
+            None => {
+                let first = v6.segments()[0];
+                !(v6.is_loopback()
+                    || v6.is_unspecified()
+                    || v6.is_multicast()
+                    || first & 0xFE00 == 0xFC00
+                    || first & 0xFFC0 == 0xFE80)
+            }
+        },
+    }
+}
+
+// ── Markup ─────────────────────────────────────────────────────────────────
+
+enum Token<'a> {
+    Open { tag: String, attrs: Vec<(String, String)> },
+    Close(String),
+    Text(&'a str),
+    /// The content of a `<style>` element.
+    Style(&'a str),
+}
+
+impl Token<'_> {
+    fn attr(&self, name: &str) -> Option<&str> {
+        match self {
+            Token::Open { attrs, .. } => attrs.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str()),
+            _ => None,
+        }
+    }
+}
+
+/// Splits `s` at the first byte `stop` accepts.
+fn split_at_byte(s: &str, stop: impl Fn(u8) -> bool) -> (&str, &str) {
+    s.split_at(s.bytes().position(stop).unwrap_or(s.len()))
+}
+
+/// Reads a start tag after its `<`: name, attributes and what follows `>`.
+fn open_tag(s: &str) -> (String, Vec<(String, String)>, &str) {
+    let (name, mut rest) = split_at_byte(s, |b| b.is_ascii_whitespace() || b == b'/' || b == b'>');
+    let mut attrs = Vec::new();
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            rest = after;
+            break;
+        }
+        let (attr, after) = split_at_byte(rest, |b| b.is_ascii_whitespace() || b == b'=' || b == b'>' || b == b'/');
+        let after = after.trim_start();
+        let (value, after) = match after.strip_prefix('=').map(str::trim_start) {
+            Some(quoted) if quoted.starts_with(['"', '\'']) => {
+                let quote = quoted.as_bytes()[0];
+                let (value, after) = split_at_byte(&quoted[1..], |b| b == quote);
+                (value, after.get(1..).unwrap_or_default())
+            }
+            Some(bare) => split_at_byte(bare, |b| b.is_ascii_whitespace() || b == b'>'),
+            None => ("", after),
+        };
+        // A stray `=` or quote: skip a byte so the loop advances.
+        rest = if attr.is_empty() && after.len() == rest.len() { &rest[1..] } else { after };
+        if !attr.is_empty() {
+            attrs.push((attr.to_ascii_lowercase(), decode(value)));
+        }
+    }
+    (name.to_ascii_lowercase(), attrs, rest)
+}
+
+fn tokens(html: &str) -> Vec<Token<'_>> {
+    let mut out = Vec::new();
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(lt) = rest.find('<') else {
+            out.push(Token::Text(rest));
+            break;
+        };
+        if lt > 0 {
+            out.push(Token::Text(&rest[..lt]));
+        }
+        rest = &rest[lt..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+        } else if let Some(close) = rest.strip_prefix("</") {
+            let (name, after) = split_at_byte(close, |b| b == b'>');
+            out.push(Token::Close(name.trim().to_ascii_lowercase()));
+            rest = after.get(1..).unwrap_or_default();
+        } else if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let (tag, attrs, after) = open_tag(&rest[1..]);
+            rest = after;
+            let raw = RAW_TEXT.contains(&tag.as_str());
+            let close = format!("</{tag}");
+            out.push(Token::Open { tag, attrs });
+            if raw {
+                let end = rest.to_ascii_lowercase().find(&close).unwrap_or(rest.len());
+                match close.as_str() {
+                    "</style" => out.push(Token::Style(&rest[..end])),
+                    "</textarea" => out.push(Token::Text(&rest[..end])),
+                    _ => {}
+                }
+                rest = &rest[end..];
+            }
+        } else {
+            out.push(Token::Text("<"));
+            rest = &rest[1..];
+        }
+    }
+    out
+}
+
+/// Replaces character references.
+fn decode(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let end = rest.find(';').filter(|&e| e <= 32);
+        let named = end.and_then(|e| {
+            let name = &rest[1..e];
+            match name.strip_prefix('#') {
+                Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16).ok(),
+                Some(decimal) => decimal.parse().ok(),
+                None => return entity(name),
+            }
+            .and_then(char::from_u32)
+        });
+        match (named, end) {
+            (Some(c), Some(e)) => {
+                out.push(c);
+                rest = &rest[e + 1..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn entity(name: &str) -> Option<char> {
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{A0}',
+        "shy" => '\u{AD}',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "bull" => '•',
+        "middot" => '·',
+        "euro" => '€',
+        "pound" => '£',
+        "yen" => '¥',
+        "times" => '×',
+        "deg" => '°',
+        _ => return None,
+    })
+}
+
+// ── Styles ─────────────────────────────────────────────────────────────────
+
+#[derive(Default)]
+struct Rule {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    specificity: (usize, usize, usize),
+    order: usize,
+    family: String,
+}
+
+impl Rule {
+    fn matches(&self, tag: &str, id: Option<&str>, classes: &[&str]) -> bool {
+        self.tag.as_deref().is_none_or(|t| t == tag)
+            && self.id.as_deref().is_none_or(|i| id == Some(i))
+            && self.classes.iter().all(|c| classes.contains(&c.as_str()))
+    }
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = rest[start + 2..].find("*/").map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Appends the rules of `css` that set a font family.
+fn parse_rules(css: &str, rules: &mut Vec<Rule>) {
+    let css = strip_comments(css);
+    let mut rest = css.as_str();
+    while let Some(open) = rest.find('{') {
+        // Statement at-rules (`@import …;`) end before the selector.
+        let prelude = rest[..open].rsplit(';').next().unwrap_or_default().trim();
+        let mut depth = 0;
+        let close = rest[open..]
+            .char_indices()
+            .find(|&(_, c)| {
+                depth += match c {
+                    '{' => 1,
+                    '}' => -1,
+                    _ => 0,
+                };
+                depth == 0
+            })
+            .map_or(rest.len(), |(i, _)| open + i);
+        let body = &rest[open + 1..close];
+        rest = rest.get(close + 1..).unwrap_or_default();
+        if let Some(at) = prelude.strip_prefix('@') {
+            if ["media", "supports", "layer", "container", "document"].iter().any(|k| at.starts_with(k)) {
+                parse_rules(body, rules);
+            }
+            continue;
+        }
+        let Some(family) = declared_family(body) else { continue };
+        for selector in prelude.split(',') {
+            if let Some(mut rule) = compound(selector) {
+                rule.order = rules.len();
+                rule.family = family.clone();
+                rules.push(rule);
+            }
+        }
+    }
+}
+
+/// The family a declaration block sets, normalized; `None` when it sets
+/// none or one that cannot be resolved here.
+fn declared_family(declarations: &str) -> Option<String> {
+    let mut family = None;
+    for declaration in declarations.split(';') {
+        let Some((property, value)) = declaration.split_once(':') else { continue };
+        let value = value.trim().trim_end_matches("!important").trim();
+        let value = match property.trim().to_ascii_lowercase().as_str() {
+            "font-family" => value,
+            "font" => shorthand_family(value).unwrap_or_default(),
+            _ => continue,
+        };
+        let keyword = ["inherit", "initial", "unset", "revert", "revert-layer"].contains(&value);
+        if !value.is_empty() && !keyword && !value.contains("var(") {
+            family = Some(normalize_stack(value));
+        }
+    }
+    family
+}
+
+/// The family list of a `font` shorthand: what follows the size (and line
+/// height).
+fn shorthand_family(value: &str) -> Option<&str> {
+    const SIZES: &[&str] =
+        &["xx-small", "x-small", "small", "medium", "large", "x-large", "xx-large", "xxx-large", "smaller", "larger"];
+    let mut at = 0;
+    let mut sized = false;
+    for token in value.split_whitespace() {
+        let start = value[at..].find(token)? + at;
+        at = start + token.len();
+        let size = token.split('/').next().unwrap_or_default();
+        if sized && !token.starts_with('/') {
+            return Some(value[start..].trim());
+        }
+        if token.starts_with('/') {
+            continue;
+        }
+        sized = SIZES.contains(&size) || size.starts_with(|c: char| c.is_ascii_digit() || c == '.');
+        // `700` is a weight; a size has a unit.
+        sized &= !size.bytes().all(|b| b.is_ascii_digit());
+    }
+    None
+}
+
+fn normalize_stack(value: &str) -> String {
+    value
+        .split(',')
+        .map(|f| f.trim().trim_matches(['"', '\'']).split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|f| !f.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The rule for the last compound selector of `selector` (descendant and
+/// sibling context is ignored); `None` for pseudo-elements and states.
+fn compound(selector: &str) -> Option<Rule> {
+    let last = selector.rsplit(|c: char| c.is_whitespace() || matches!(c, '>' | '+' | '~')).find(|s| !s.is_empty())?;
+    let mut rule = Rule::default();
+    let mut rest = last;
+    while !rest.is_empty() {
+        let ident_end = |s: &str| s.find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_')).unwrap_or(s.len());
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = ident_end(after);
+            rule.classes.push(after[..end].to_string());
+            rule.specificity.1 += 1;
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('#') {
+            let end = ident_end(after);
+            rule.id = Some(after[..end].to_string());
+            rule.specificity.0 += 1;
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            rule.specificity.1 += 1;
+            rest = after.find(']').map_or("", |end| &after[end + 1..]);
+        } else if let Some(after) = rest.strip_prefix(':') {
+            if after.starts_with(':') {
+                return None;
+            }
+            let end = ident_end(after);
+            match &after[..end] {
+                "root" => {
+                    rule.tag = Some("html".to_string());
+                    rule.specificity.1 += 1;
+                }
+                "before" | "after" | "first-line" | "first-letter" | "hover" | "focus" | "active" | "visited" => {
+                    return None
+                }
+                _ => rule.specificity.1 += 1,
+            }
+            rest = &after[end..];
+            // Skip an argument list, `:not(...)`.
+            if let Some(args) = rest.strip_prefix('(') {
+                rest = args.find(')').map_or("", |end| &args[end + 1..]);
+            }
+        } else if let Some(after) = rest.strip_prefix('*') {
+            rest = after;
+        } else {
+            let end = ident_end(rest);
+            if end == 0 {
+                return None;
+            }
+            rule.tag = Some(rest[..end].to_ascii_lowercase());
+            rule.specificity.2 += 1;
+            rest = &rest[end..];
+        }
+    }
+    Some(rule)
+}
+
+// ── Text ───────────────────────────────────────────────────────────────────
+
+/// Visible characters of a page by the `font-family` stack they are set in
+/// (`""` when the page sets none).
+fn text_by_stack(tokens: &[Token], rules: &[Rule]) -> BTreeMap<String, BTreeSet<char>> {
+    struct Element {
+        tag: String,
+        family: String,
+        hidden: bool,
+    }
+    let mut text: BTreeMap<String, BTreeSet<char>> = BTreeMap::new();
+    let mut add = |family: &str, s: &str| {
+        let mut visible: BTreeSet<char> =
+            decode(s).chars().filter(|&c| !c.is_control() && (c == '\u{A0}' || !c.is_whitespace())).collect();
+        if !visible.is_empty() {
+            text.entry(family.to_string()).or_default().append(&mut visible);
+        }
+    };
+    let mut stack: Vec<Element> = Vec::new();
+    for token in tokens {
+        let (family, hidden) = stack.last().map_or(("", false), |e| (e.family.as_str(), e.hidden));
+        match token {
+            Token::Open { tag, .. } => {
+                let id = token.attr("id");
+                let classes: Vec<&str> = token.attr("class").unwrap_or_default().split_whitespace().collect();
+                let family = token
+                    .attr("style")
+                    .and_then(declared_family)
+                    .or_else(|| {
+                        let matching = rules.iter().filter(|r| r.matches(tag, id, &classes));
+                        matching.max_by_key(|r| (r.specificity, r.order)).map(|r| r.family.clone())
+                    })
+                    .unwrap_or_else(|| family.to_string());
+                let hidden = hidden || HIDDEN.contains(&tag.as_str()) || token.attr("hidden").is_some();
+                if !hidden && tag == "input" && token.attr("type") != Some("hidden") {
+                    for attr in ["placeholder", "value"] {
+                        add(&family, token.attr(attr).unwrap_or_default());
+                    }
+                }
+                if !VOID.contains(&tag.as_str()) {
+                    stack.push(Element { tag: tag.clone(), family, hidden });
+                }
+            }
+            Token::Close(tag) => {
+                if let Some(at) = stack.iter().rposition(|e| &e.tag == tag) {
+                    stack.truncate(at);
+                }
+            }
+            Token::Text(s) if !hidden => add(family, s),
+            Token::Text(_) | Token::Style(_) => {}
+        }
+    }
+    text
+}
+
+/// The catalog family a stack's text is drawn from.
+fn catalog_family<'a>(stack: &str, catalog: &'a [FontCatalogEntry]) -> Option<&'a str> {
+    for name in stack.split(", ") {
+        if GENERIC.contains(&name.to_ascii_lowercase().as_str()) {
+            return None;
+        }
+        let key = name.to_lowercase();
+        if let Some(entry) = catalog.iter().find(|e| e.family.to_lowercase() == key || e.id == key) {
+            return Some(&entry.family);
+        }
+    }
+    None
+}
+
+// ── Endpoint ───────────────────────────────────────────────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct Page {
+    url: String,
+    stylesheets: usize,
+    character_count: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Face {
+    font_name: String,
+    variant: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subset: Option<SubsetResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Family {
+    family: String,
+    character_count: usize,
+    unicode_range: String,
+    faces: Vec<Face>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Unmatched {
+    /// The stack as written; empty for text the page sets no family for.
+    font_family: String,
+    character_count: usize,
+    characters: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubsetFromUrlResponse {
+    pages: Vec<Page>,
+    families: Vec<Family>,
+    unmatched: Vec<Unmatched>,
+}
+
+/// Reads one page: its visible text by stack, and its report.
+async fn read_page(
+    crawler: &Crawler,
+    sheets: &mut BTreeMap<Url, Result<String, String>>,
+    base: Option<Url>,
+    url: String,
+    html: &str,
+) -> (Page, BTreeMap<String, BTreeSet<char>>) {
+    let tokens = tokens(html);
+    let mut page = Page { url, stylesheets: 0, character_count: 0, warnings: Vec::new(), error: None };
+    let mut rules = Vec::new();
+    for token in &tokens {
+        match token {
+            Token::Style(css) => parse_rules(css, &mut rules),
+            Token::Open { tag, .. } if tag == "link" => {
+                let rel = token.attr("rel").unwrap_or_default();
+                let stylesheet = rel.split_whitespace().any(|r| r.eq_ignore_ascii_case("stylesheet"));
+                let Some(href) = token.attr("href").filter(|_| stylesheet) else { continue };
+                let resolved = match &base {
+                    Some(base) => base.join(href),
+                    None => Url::parse(href),
+                };
+                let Ok(resolved) = resolved else {
+                    page.warnings.push(format!("stylesheet {href:?} cannot be resolved"));
+                    continue;
+                };
+                let href = resolved;
+                if page.stylesheets == MAX_STYLESHEETS {
+                    page.warnings.push(format!("only the first {MAX_STYLESHEETS} stylesheets are read"));
+                    break;
+                }
+                page.stylesheets += 1;
+                if !sheets.contains_key(&href) {
+                    let css = crawler.fetch(href.as_str()).await.map(|(_, css)| css);
+                    sheets.insert(href.clone(), css);
+                }
+                match &sheets[&href] {
+                    Ok(css) => parse_rules(css, &mut rules),
+                    Err(e) => page.warnings.push(format!("stylesheet {e}")),
+                }
+            }
+            _ => {}
+        }
+    }
+    let text = text_by_stack(&tokens, &rules);
+    page.character_count = text.values().flatten().collect::<BTreeSet<_>>().len();
+    (page, text)
+}
+
+pub async fn subset_from_url(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(req): ApiJson<SubsetFromUrlRequest>,
+) -> Result<Json<SubsetFromUrlResponse>, (StatusCode, String)> {
+    state.flags.ensure("crawl", &headers)?;
+    state.flags.ensure("subset", &headers)?;
+    let count = req.urls.len() + usize::from(req.html.is_some());
+    if count == 0 || count > MAX_PAGES {
+        return Err((StatusCode::BAD_REQUEST, format!("give 1-{MAX_PAGES} pages as urls and/or html")));
+    }
+
+    let mut sheets = BTreeMap::new();
+    let mut pages = Vec::new();
+    let mut by_stack: BTreeMap<String, BTreeSet<char>> = BTreeMap::new();
+    let mut read = |text: BTreeMap<String, BTreeSet<char>>| {
+        for (stack, mut characters) in text {
+            by_stack.entry(stack).or_default().append(&mut characters);
+        }
+    };
+    if let Some(html) = &req.html {
+        let (page, text) = read_page(&state.crawler, &mut sheets, None, "html".to_string(), html).await;
+        pages.push(page);
+        read(text);
+    }
+    for url in &req.urls {
+        match state.crawler.fetch(url).await {
+            Ok((base, html)) => {
+                let (page, text) = read_page(&state.crawler, &mut sheets, Some(base), url.clone(), &html).await;
+                pages.push(page);
+                read(text);
+            }
+            Err(e) => pages.push(Page {
+                url: url.clone(),
+                stylesheets: 0,
+                character_count: 0,
+                warnings: Vec::new(),
+                error: Some(e),
+            }),
+        }
+    }
+    if by_stack.is_empty() {
+        let errors: Vec<&str> = pages.iter().filter_map(|p| p.error.as_deref()).collect();
+        let message = match errors.is_empty() {
+            true => "no visible text found on the pages".to_string(),
+            false => format!("no visible text found on the pages; {}", errors.join("; ")),
+        };
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, message));
+    }
+
+    let catalog = state.sandbox.visible(&headers, state.catalog.read().unwrap().clone());
+    let mut by_family: BTreeMap<String, BTreeSet<char>> = BTreeMap::new();
+    let mut unmatched = Vec::new();
+    for (stack, mut characters) in by_stack {
+        match catalog_family(&stack, &catalog) {
+            Some(family) => by_family.entry(family.to_string()).or_default().append(&mut characters),
+            None => unmatched.push(Unmatched {
+                font_family: stack,
+                character_count: characters.len(),
+                characters: characters.into_iter().collect(),
+            }),
+        }
+    }
+
+    let mut families = Vec::new();
+    let mut made = 0;
+    for (family, characters) in by_family {
+        let text: String = characters.iter().collect();
+        let mut faces = Vec::new();
+        for entry in catalog.iter().filter(|e| e.family == family) {
+            if made == MAX_FACES {
+                let error = Some(format!("only {MAX_FACES} faces are subset per request"));
+                faces.push(Face { font_name: entry.id.clone(), variant: entry.variant.clone(), subset: None, error });
+                continue;
+            }
+            made += 1;
+            let sub = SubsetRequest {
+                font_name: entry.id.clone(),
+                characters: text.clone(),
+                transform: TransformOptions { format: req.format.clone(), ..Default::default() },
+                ..Default::default()
+            };
+            let (subset, error) = match crate::subset(State(Arc::clone(&state)), headers.clone(), ApiJson(sub)).await {
+                Ok(Json(subset)) => (Some(subset), None),
+                Err((_, e)) => (None, Some(e)),
+            };
+            faces.push(Face { font_name: entry.id.clone(), variant: entry.variant.clone(), subset, error });
+        }
+        let ranges = unicode::merge(characters.iter().map(|&c| c as u32..=c as u32).collect());
+        families.push(Family {
+            family,
+            character_count: characters.len(),
+            unicode_range: unicode::css_unicode_range(&ranges),
+            faces,
+        });
+    }
+
+    info!(
+        pages = pages.len(),
+        families = families.len(),
+        faces = made,
+        unmatched = unmatched.len(),
+        "subsets made from pages"
+    );
+    Ok(Json(SubsetFromUrlResponse { pages, families, unmatched }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_public(ip: &str) -> bool {
+        public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn public_addresses_are_fetched() {
+        for ip in ["93.184.216.34", "8.8.8.8", "100.128.0.1", "2606:4700::1111", "::ffff:93.184.216.34"] {
+            assert!(is_public(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn private_and_local_addresses_are_not() {
+        let blocked = [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "100.64.0.1",
+            "0.0.0.0",
+            "0.1.2.3",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+        ];
+        for ip in blocked {
+            assert!(!is_public(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn multicast_addresses_are_not() {
+        for ip in ["224.0.0.1", "239.255.255.250", "ff02::1", "ff0e::fb", "::ffff:224.0.0.251"] {
+            assert!(!is_public(ip), "{ip}");
+        }
+        assert!(is_public("223.255.255.255"));
+    }
+
+    #[test]
+    fn reserved_addresses_are_not() {
+        for ip in ["240.0.0.1", "250.1.2.3", "255.255.255.254"] {
+            assert!(!is_public(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn benchmarking_addresses_are_not() {
+        for ip in ["198.18.0.1", "198.19.255.255"] {
+            assert!(!is_public(ip), "{ip}");
+        }
+        assert!(is_public("198.17.255.255") && is_public("198.20.0.0"));
+    }
+
+    #[test]
+    fn carrier_grade_nat_addresses_are_not() {
+        for ip in ["100.64.0.0", "100.100.100.200", "100.127.255.255"] {
+            assert!(!is_public(ip), "{ip}");
+        }
+        assert!(is_public("100.63.255.255") && is_public("100.128.0.0"));
+    }
+
+    #[test]
+    fn link_local_metadata_endpoints_are_not() {
+        for ip in ["169.254.169.254", "169.254.0.1", "169.254.255.255", "fe80::a9fe:a9fe"] {
+            assert!(!is_public(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn embedded_ipv4_addresses_are_judged_as_ipv4() {
+        let blocked = ["::ffff:127.0.0.1", "::ffff:169.254.169.254", "::ffff:10.0.0.1", "::ffff:0:0", "64:ff9b::a9fe:a9fe"];
+        for ip in blocked {
+            assert!(!is_public(ip), "{ip}");
+        }
+        assert!(is_public("64:ff9b::808:808"));
+    }
+
+    #[test]
+    fn urls_with_private_addresses_are_refused() {
+        let crawler = Crawler { http: reqwest::Client::new(), max_bytes: 1024, allow_private: false };
+        for url in ["http://127.0.0.1/", "http://[::1]:8080/", "http://169.254.169.254/latest/meta-data/"] {
+            assert!(crawler.check(&Url::parse(url).unwrap()).is_err(), "{url}");
+        }
+        assert!(crawler.check(&Url::parse("ftp://example.com/").unwrap()).is_err());
+        assert!(crawler.check(&Url::parse("https://example.com/").unwrap()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn the_resolver_refuses_hosts_resolving_to_private_addresses() {
+        use reqwest::dns::Resolve;
+        let refused = PublicResolver.resolve("localhost".parse().unwrap()).await;
+        assert!(refused.is_err_and(|e| e.to_string().contains("non-public address")));
+    }
+}
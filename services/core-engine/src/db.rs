@@ -0,0 +1,640 @@
+//! Optional Postgres persistence for the catalog, saved subset profiles, API
+//! keys, usage analytics, tenant quotas, the audit log and the shared job
+//! queue.
+//!
+//! Migrations under `migrations/` are embedded at build time and applied on
+//! startup (or alone with `--migrate-only`). Entries are stored as JSONB so
+//! adding fields to `FontCatalogEntry` does not need a migration.
+
+use font_api::{ApiKey, AuditEvent, SavedProfile, SubsetRequest, TenantQuota};
+use serde_json::Value;
+use sqlx::{migrate::Migrator, postgres::PgPoolOptions, types::Json, PgPool};
+use std::time::Duration;
+
+use crate::{analytics::{Breakdown, Requested, Tally, Usage}, audit::Filter, quotas::Counters, FontCatalogEntry};
+
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// A pool that gives up on a connection after `DB_TIMEOUT_SECS` (default
+/// 5) instead of queueing requests behind an unreachable database; writes
+/// that fail that way stay buffered for the next flush.
+pub async fn connect(url: &str) -> Result<PgPool, sqlx::Error> {
+    let timeout = std::env::var("DB_TIMEOUT_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(5).max(1);
+    PgPoolOptions::new().max_connections(8).acquire_timeout(Duration::from_secs(timeout)).connect(url).await
+}
+
+pub async fn ping(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query("select 1").execute(pool).await.map(drop)
+}
+
+/// Highest successfully applied migration, or `None` on an empty database.
+pub async fn schema_version(pool: &PgPool) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar("select max(version) from _sqlx_migrations where success")
+        .fetch_one(pool)
+        .await
+}
+
+/// Newest migration embedded in this binary.
+pub fn latest_migration() -> Option<i64> {
+    MIGRATOR.iter().map(|m| m.version).max()
+}
+
+pub async fn load_catalog(pool: &PgPool) -> Result<Vec<FontCatalogEntry>, sqlx::Error> {
+    let rows: Vec<Json<FontCatalogEntry>> =
+        sqlx::query_scalar("select entry from catalog_entries order by id")
+            .fetch_all(pool)
+            .await?;
+    Ok(rows.into_iter().map(|Json(e)| e).collect())
+}
+
+/// Atomically replaces the stored catalog with `entries`.
+pub async fn replace_catalog(pool: &PgPool, entries: &[FontCatalogEntry]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("delete from catalog_entries").execute(&mut *tx).await?;
+    for entry in entries {
+        sqlx::query("insert into catalog_entries (id, entry) values ($1, $2)")
+            .bind(&entry.id)
+            .bind(Json(entry))
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
+
+pub async fn save_entry(pool: &PgPool, entry: &FontCatalogEntry) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "insert into catalog_entries (id, entry) values ($1, $2) \
+         on conflict (id) do update set entry = excluded.entry, updated_at = now()",
+    )
+    .bind(&entry.id)
+    .bind(Json(entry))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_entry(pool: &PgPool, id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("delete from catalog_entries where id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn load_staging(pool: &PgPool) -> Result<Vec<FontCatalogEntry>, sqlx::Error> {
+    let rows: Vec<Json<FontCatalogEntry>> =
+        sqlx::query_scalar("select entry from catalog_staging order by id")
+            .fetch_all(pool)
+            .await?;
+    Ok(rows.into_iter().map(|Json(e)| e).collect())
+}
+
+pub async fn stage_entry(pool: &PgPool, entry: &FontCatalogEntry) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "insert into catalog_staging (id, entry) values ($1, $2) \
+         on conflict (id) do update set entry = excluded.entry, staged_at = now()",
+    )
+    .bind(&entry.id)
+    .bind(Json(entry))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn unstage_entry(pool: &PgPool, id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("delete from catalog_staging where id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Moves every staged entry into the production catalog in one transaction.
+pub async fn promote_staging(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "insert into catalog_entries (id, entry) select id, entry from catalog_staging \
+         on conflict (id) do update set entry = excluded.entry, updated_at = now()",
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("delete from catalog_staging").execute(&mut *tx).await?;
+    tx.commit().await
+}
+
+pub async fn load_subset_profiles(pool: &PgPool) -> Result<Vec<SavedProfile>, sqlx::Error> {
+    let rows: Vec<Json<SavedProfile>> =
+        sqlx::query_scalar("select profile from subset_profiles order by tenant, name, version")
+            .fetch_all(pool)
+            .await?;
+    Ok(rows.into_iter().map(|Json(p)| p).collect())
+}
+
+pub async fn save_subset_profile(pool: &PgPool, profile: &SavedProfile) -> Result<(), sqlx::Error> {
+    sqlx::query("insert into subset_profiles (tenant, name, version, profile) values ($1, $2, $3, $4)")
+        .bind(&profile.tenant)
+        .bind(&profile.name)
+        .bind(profile.version as i32)
+        .bind(Json(profile))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Deletes every version of a profile.
+pub async fn delete_subset_profile(pool: &PgPool, tenant: &str, name: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("delete from subset_profiles where tenant = $1 and name = $2")
+        .bind(tenant)
+        .bind(name)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn load_api_keys(pool: &PgPool) -> Result<Vec<ApiKey>, sqlx::Error> {
+    let rows: Vec<Json<ApiKey>> =
+        sqlx::query_scalar("select key from api_keys order by created_at").fetch_all(pool).await?;
+    Ok(rows.into_iter().map(|Json(k)| k).collect())
+}
+
+pub async fn save_api_key(pool: &PgPool, key: &ApiKey) -> Result<(), sqlx::Error> {
+    sqlx::query("insert into api_keys (id, key) values ($1, $2)")
+        .bind(&key.id)
+        .bind(Json(key))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_api_key(pool: &PgPool, id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("delete from api_keys where id = $1").bind(id).execute(pool).await?;
+    Ok(())
+}
+
+/// Adds each row's counters to what is stored for that font and day.
+pub async fn add_font_usage(pool: &PgPool, rows: &[((String, String), Usage)]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for ((font, day), usage) in rows {
+        sqlx::query(
+            "insert into font_usage (font, day, downloads, bytes_served, compressions, subsets, bytes_saved) \
+             values ($1, $2::date, $3, $4, $5, $6, $7) \
+             on conflict (font, day) do update set \
+             downloads = font_usage.downloads + excluded.downloads, \
+             bytes_served = font_usage.bytes_served + excluded.bytes_served, \
+             compressions = font_usage.compressions + excluded.compressions, \
+             subsets = font_usage.subsets + excluded.subsets, \
+             bytes_saved = font_usage.bytes_saved + excluded.bytes_saved",
+        )
+        .bind(font)
+        .bind(day)
+        .bind(usage.downloads as i64)
+        .bind(usage.bytes_served as i64)
+        .bind(usage.compressions as i64)
+        .bind(usage.subsets as i64)
+        .bind(usage.bytes_saved as i64)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// Stored usage from `since` (`YYYY-MM-DD`) on, for one font or all.
+pub async fn font_usage(
+    pool: &PgPool,
+    font: Option<&str>,
+    since: &str,
+) -> Result<Vec<((String, String), Usage)>, sqlx::Error> {
+    let rows: Vec<(String, String, i64, i64, i64, i64, i64)> = sqlx::query_as(
+        "select font, day::text, downloads, bytes_served, compressions, subsets, bytes_saved from font_usage \
+         where day >= $1::date and ($2::text is null or font = $2) order by font, day",
+    )
+    .bind(since)
+    .bind(font)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(font, day, downloads, bytes_served, compressions, subsets, bytes_saved)| {
+            let usage = Usage {
+                downloads: downloads as u64,
+                bytes_served: bytes_served as u64,
+                compressions: compressions as u64,
+                subsets: subsets as u64,
+                bytes_saved: bytes_saved as u64,
+            };
+            ((font, day), usage)
+        })
+        .collect())
+}
+
+/// Adds each row's tally to what is stored for that font, day, dimension
+/// and value.
+pub async fn add_usage_breakdown(pool: &PgPool, rows: &[(Breakdown, Tally)]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for ((font, day, dimension, value), tally) in rows {
+        sqlx::query(
+            "insert into usage_breakdown (font, day, dimension, value, requests, bytes) \
+             values ($1, $2::date, $3, $4, $5, $6) \
+             on conflict (font, day, dimension, value) do update set \
+             requests = usage_breakdown.requests + excluded.requests, \
+             bytes = usage_breakdown.bytes + excluded.bytes",
+        )
+        .bind(font)
+        .bind(day)
+        .bind(dimension)
+        .bind(value)
+        .bind(tally.requests as i64)
+        .bind(tally.bytes as i64)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// Stored breakdowns of `fonts` from `since` (`YYYY-MM-DD`) on.
+pub async fn usage_breakdown(
+    pool: &PgPool,
+    fonts: &[String],
+    since: &str,
+) -> Result<Vec<(Breakdown, Tally)>, sqlx::Error> {
+    let rows: Vec<(String, String, String, String, i64, i64)> = sqlx::query_as(
+        "select font, day::text, dimension, value, requests, bytes from usage_breakdown \
+         where day >= $1::date and font = any($2)",
+    )
+    .bind(since)
+    .bind(fonts)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(font, day, dimension, value, requests, bytes)| {
+            ((font, day, dimension, value), Tally { requests: requests as u64, bytes: bytes as u64 })
+        })
+        .collect())
+}
+
+/// Adds each request's count to what is stored for its digest and day.
+pub async fn add_subset_requests(pool: &PgPool, rows: &[((String, String), Requested)]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for ((key, day), (request, count)) in rows {
+        sqlx::query(
+            "insert into subset_requests (key, day, request, requests) values ($1, $2::date, $3, $4) \
+             on conflict (key, day) do update set requests = subset_requests.requests + excluded.requests",
+        )
+        .bind(key)
+        .bind(day)
+        .bind(Json(request))
+        .bind(*count as i64)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// Stored subset requests from `since` (`YYYY-MM-DD`) on, counted per
+/// digest, with the request each stands for.
+pub async fn popular_subsets(pool: &PgPool, since: &str) -> Result<Vec<(String, SubsetRequest, u64)>, sqlx::Error> {
+    let rows: Vec<(String, Json<SubsetRequest>, i64)> = sqlx::query_as(
+        "select key, (array_agg(request order by day desc))[1], sum(requests)::bigint from subset_requests \
+         where day >= $1::date group by key",
+    )
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|(key, Json(request), count)| (key, request, count as u64)).collect())
+}
+
+/// Adds each row's counters to what is stored for that tenant and month.
+pub async fn add_tenant_usage(pool: &PgPool, rows: &[((String, String), Counters)]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for ((tenant, month), counters) in rows {
+        sqlx::query(
+            "insert into tenant_usage (tenant, month, downloads, bandwidth_bytes, operations) \
+             values ($1, $2, $3, $4, $5) \
+             on conflict (tenant, month) do update set \
+             downloads = tenant_usage.downloads + excluded.downloads, \
+             bandwidth_bytes = tenant_usage.bandwidth_bytes + excluded.bandwidth_bytes, \
+             operations = tenant_usage.operations + excluded.operations",
+        )
+        .bind(tenant)
+        .bind(month)
+        .bind(counters.downloads as i64)
+        .bind(counters.bandwidth_bytes as i64)
+        .bind(counters.operations as i64)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// Stored usage of every tenant in `month` (`YYYY-MM`).
+pub async fn tenant_usage(pool: &PgPool, month: &str) -> Result<Vec<(String, Counters)>, sqlx::Error> {
+    let rows: Vec<(String, i64, i64, i64)> = sqlx::query_as(
+        "select tenant, downloads, bandwidth_bytes, operations from tenant_usage where month = $1 order by tenant",
+    )
+    .bind(month)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(tenant, downloads, bandwidth_bytes, operations)| {
+            let counters = Counters {
+                downloads: downloads as u64,
+                bandwidth_bytes: bandwidth_bytes as u64,
+                operations: operations as u64,
+            };
+            (tenant, counters)
+        })
+        .collect())
+}
+
+pub async fn load_tenant_quotas(pool: &PgPool) -> Result<Vec<(String, TenantQuota)>, sqlx::Error> {
+    let rows: Vec<(String, Json<TenantQuota>)> =
+        sqlx::query_as("select tenant, quota from tenant_quotas order by tenant").fetch_all(pool).await?;
+    Ok(rows.into_iter().map(|(tenant, Json(q))| (tenant, q)).collect())
+}
+
+pub async fn save_tenant_quota(pool: &PgPool, tenant: &str, quota: &TenantQuota) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "insert into tenant_quotas (tenant, quota) values ($1, $2) \
+         on conflict (tenant) do update set quota = excluded.quota",
+    )
+    .bind(tenant)
+    .bind(Json(quota))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_tenant_quota(pool: &PgPool, tenant: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("delete from tenant_quotas where tenant = $1").bind(tenant).execute(pool).await?;
+    Ok(())
+}
+
+/// Appends `event`; the database assigns its ID and time.
+pub async fn add_audit_event(pool: &PgPool, event: &AuditEvent) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "insert into audit_log (actor, tenant, action, target, request_id, before, after) \
+         values ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(&event.actor)
+    .bind(&event.tenant)
+    .bind(&event.action)
+    .bind(&event.target)
+    .bind(&event.request_id)
+    .bind(event.before.as_ref().map(Json))
+    .bind(event.after.as_ref().map(Json))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+type AuditRow = (i64, i64, String, String, String, String, String, Option<Json<Value>>, Option<Json<Value>>);
+
+/// Events matching `filter`, newest first.
+pub async fn audit_events(pool: &PgPool, filter: &Filter) -> Result<Vec<AuditEvent>, sqlx::Error> {
+    let rows: Vec<AuditRow> = sqlx::query_as(
+        "select id, extract(epoch from at)::bigint, actor, tenant, action, target, request_id, before, after \
+         from audit_log where ($1::text is null or starts_with(action, $1)) and ($2::text is null or actor = $2) \
+         and ($3::text is null or tenant = $3) and ($4::text is null or target = $4) \
+         and ($5::bigint is null or at >= to_timestamp($5)) and ($6::bigint is null or at < to_timestamp($6)) \
+         and ($7::bigint is null or id < $7) order by id desc limit $8",
+    )
+    .bind(&filter.action)
+    .bind(&filter.actor)
+    .bind(&filter.tenant)
+    .bind(&filter.target)
+    .bind(filter.since)
+    .bind(filter.until)
+    .bind(filter.before)
+    .bind(filter.limit as i64)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(id, at, actor, tenant, action, target, request_id, before, after)| AuditEvent {
+            id,
+            at_unix: at as u64,
+            actor,
+            tenant,
+            action,
+            target,
+            request_id,
+            before: before.map(|Json(v)| v),
+            after: after.map(|Json(v)| v),
+        })
+        .collect())
+}
+
+/// A job in the shared queue (see [`crate::queue`]); times in Unix seconds.
+#[derive(sqlx::FromRow)]
+pub struct JobRow {
+    pub id: String,
+    pub kind: String,
+    pub tenant: String,
+    pub status: String,
+    pub request: Json<Value>,
+    pub headers: Json<Value>,
+    pub created_at: i64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub attempts: i32,
+    pub progress: Option<Json<Value>>,
+    pub result: Option<Json<Value>>,
+    pub download_url: Option<String>,
+    pub error_status: Option<i32>,
+    pub error: Option<String>,
+    pub callback: Option<Json<Value>>,
+    pub checkpoint: Option<Json<Value>>,
+    /// Queued jobs ahead of this one.
+    pub queue_position: i64,
+}
+
+const JOB_COLUMNS: &str = "id, kind, tenant, status, request, headers, \
+    extract(epoch from created_at)::bigint as created_at, extract(epoch from started_at)::bigint as started_at, \
+    extract(epoch from finished_at)::bigint as finished_at, attempts, progress, result, download_url, error_status, \
+    error, callback, checkpoint, \
+    (select count(*) from jobs q where q.status = 'queued' and q.seq < jobs.seq) as queue_position";
+
+/// A job to queue.
+pub struct NewJob<'a> {
+    pub id: &'a str,
+    pub kind: &'a str,
+    pub tenant: &'a str,
+    pub request: &'a Value,
+    pub headers: &'a Value,
+    pub callback: Option<&'a Value>,
+}
+
+/// Queues a job unless `max_queued` are already waiting; `false` when full.
+pub async fn insert_job(pool: &PgPool, job: &NewJob<'_>, max_queued: usize) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query(
+        "insert into jobs (id, kind, tenant, status, request, headers, callback) \
+         select $1, $2, $3, 'queued', $4, $5, $6 where (select count(*) from jobs where status = 'queued') < $7",
+    )
+    .bind(job.id)
+    .bind(job.kind)
+    .bind(job.tenant)
+    .bind(Json(job.request))
+    .bind(Json(job.headers))
+    .bind(job.callback.map(Json))
+    .bind(max_queued as i64)
+    .execute(pool)
+    .await?;
+    Ok(inserted.rows_affected() == 1)
+}
+
+/// The job `id`, of `tenant` unless that is `None`.
+pub async fn job(pool: &PgPool, id: &str, tenant: Option<&str>) -> Result<Option<JobRow>, sqlx::Error> {
+    sqlx::query_as(&format!("select {JOB_COLUMNS} from jobs where id = $1 and ($2::text is null or tenant = $2)"))
+        .bind(id)
+        .bind(tenant)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Fails running jobs whose lease expired after `max_attempts` tries,
+/// returning their IDs.
+pub async fn abandon_jobs(pool: &PgPool, max_attempts: u32) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "update jobs set status = 'failed', finished_at = now(), lease_owner = null, error_status = 500, \
+         error = 'interrupted ' || attempts || ' time(s); not retried' \
+         where status = 'running' and lease_expires_at < now() and attempts >= $1 returning id",
+    )
+    .bind(max_attempts as i32)
+    .fetch_all(pool)
+    .await
+}
+
+/// Queues again, or fails once they ran `max_attempts` times, the running
+/// jobs leased to `owner` by an earlier run of the same replica. Returns
+/// their IDs and new status.
+pub async fn recover_jobs(pool: &PgPool, owner: &str, max_attempts: u32) -> Result<Vec<(String, String)>, sqlx::Error> {
+    sqlx::query_as(
+        "update jobs set lease_owner = null, lease_expires_at = null, \
+         status = case when attempts < $2 then 'queued' else 'failed' end, \
+         finished_at = case when attempts < $2 then null else now() end, \
+         error_status = case when attempts < $2 then null else 500 end, \
+         error = case when attempts < $2 then null else 'interrupted ' || attempts || ' time(s); not retried' end \
+         where status = 'running' and lease_owner = $1 returning id, status",
+    )
+    .bind(owner)
+    .bind(max_attempts as i32)
+    .fetch_all(pool)
+    .await
+}
+
+/// Leases the oldest queued job, or a running one whose lease expired (its
+/// replica is gone) with tries left, to `owner` for `lease_secs`.
+pub async fn claim_job(
+    pool: &PgPool,
+    owner: &str,
+    lease_secs: u64,
+    max_attempts: u32,
+) -> Result<Option<JobRow>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "update jobs set status = 'running', lease_owner = $1, lease_expires_at = now() + make_interval(secs => $2), \
+         started_at = coalesce(started_at, now()), attempts = attempts + 1 \
+         where id = (select id from jobs where status = 'queued' \
+         or (status = 'running' and lease_expires_at < now() and attempts < $3) \
+         order by seq for update skip locked limit 1) returning {JOB_COLUMNS}"
+    ))
+    .bind(owner)
+    .bind(lease_secs as f64)
+    .bind(max_attempts as i32)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Extends `owner`'s lease on a running job; `false` once the job was
+/// cancelled or taken over.
+pub async fn renew_job(pool: &PgPool, id: &str, owner: &str, lease_secs: u64) -> Result<bool, sqlx::Error> {
+    let renewed = sqlx::query(
+        "update jobs set lease_expires_at = now() + make_interval(secs => $3) \
+         where id = $1 and lease_owner = $2 and status = 'running'",
+    )
+    .bind(id)
+    .bind(owner)
+    .bind(lease_secs as f64)
+    .execute(pool)
+    .await?;
+    Ok(renewed.rows_affected() == 1)
+}
+
+/// Keeps how far `owner`'s run of a job got, for the next run to resume from.
+pub async fn job_checkpoint(pool: &PgPool, id: &str, owner: &str, checkpoint: &Value) -> Result<(), sqlx::Error> {
+    sqlx::query("update jobs set checkpoint = $3 where id = $1 and lease_owner = $2 and status = 'running'")
+        .bind(id)
+        .bind(owner)
+        .bind(Json(checkpoint))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn job_progress(pool: &PgPool, id: &str, owner: &str, progress: &Value) -> Result<(), sqlx::Error> {
+    sqlx::query("update jobs set progress = $3 where id = $1 and lease_owner = $2 and status = 'running'")
+        .bind(id)
+        .bind(owner)
+        .bind(Json(progress))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// How a run ended.
+pub struct JobOutcome<'a> {
+    pub status: &'a str,
+    pub progress: Option<&'a Value>,
+    pub result: Option<&'a Value>,
+    pub download_url: Option<&'a str>,
+    pub error: Option<(u16, &'a str)>,
+}
+
+/// Records the outcome of `owner`'s run; `false` if it no longer held the
+/// job.
+pub async fn finish_job(pool: &PgPool, id: &str, owner: &str, outcome: &JobOutcome<'_>) -> Result<bool, sqlx::Error> {
+    let finished = sqlx::query(
+        "update jobs set status = $3, finished_at = now(), lease_owner = null, lease_expires_at = null, \
+         progress = coalesce($4, progress), result = $5, download_url = $6, error_status = $7, error = $8, \
+         checkpoint = null where id = $1 and lease_owner = $2 and status = 'running'",
+    )
+    .bind(id)
+    .bind(owner)
+    .bind(outcome.status)
+    .bind(outcome.progress.map(Json))
+    .bind(outcome.result.map(Json))
+    .bind(outcome.download_url)
+    .bind(outcome.error.map(|(status, _)| status as i32))
+    .bind(outcome.error.map(|(_, message)| message))
+    .execute(pool)
+    .await?;
+    Ok(finished.rows_affected() == 1)
+}
+
+/// Cancels `tenant`'s job if it is queued or running; `false` otherwise.
+pub async fn cancel_job(pool: &PgPool, id: &str, tenant: &str) -> Result<bool, sqlx::Error> {
+    let cancelled = sqlx::query(
+        "update jobs set status = 'cancelled', finished_at = now(), lease_owner = null, lease_expires_at = null \
+         where id = $1 and tenant = $2 and status in ('queued', 'running')",
+    )
+    .bind(id)
+    .bind(tenant)
+    .execute(pool)
+    .await?;
+    Ok(cancelled.rows_affected() == 1)
+}
+
+pub async fn set_job_callback(pool: &PgPool, id: &str, callback: &Value) -> Result<(), sqlx::Error> {
+    sqlx::query("update jobs set callback = $2 where id = $1").bind(id).bind(Json(callback)).execute(pool).await?;
+    Ok(())
+}
+
+/// Deletes jobs that finished more than `retention_secs` ago.
+pub async fn prune_jobs(pool: &PgPool, retention_secs: u64) -> Result<u64, sqlx::Error> {
+    let pruned = sqlx::query("delete from jobs where finished_at < now() - make_interval(secs => $1)")
+        .bind(retention_secs as f64)
+        .execute(pool)
+        .await?;
+    Ok(pruned.rows_affected())
+}
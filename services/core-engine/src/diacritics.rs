@@ -0,0 +1,188 @@
+//! Diacritic completeness per language.
+//!
+//! A letter renders correctly either from its own precomposed glyph or, the
+//! way shapers fall back, as a base glyph plus combining marks positioned by
+//! GPOS mark-to-base / mark-to-mark anchors. Yoruba `ẹ́` has no precomposed
+//! code point at all, so it always needs the second route. Missing pieces
+//! are reported per letter (which glyph, which anchor) so a foundry or a
+//! subset request can be fixed directly.
+
+use axum::{body::Body, extract::Query, http::StatusCode, response::Json};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{cmap::CharMap, layout::MarkAttachment, sfnt::Font, spool};
+
+struct Language {
+    code: &'static str,
+    name: &'static str,
+    /// Letters needed as-is.
+    letters: &'static str,
+    /// Bases combined with every mark in `marks`.
+    bases: &'static str,
+    marks: &'static [char],
+}
+
+const GRAVE: char = '\u{300}';
+const ACUTE: char = '\u{301}';
+const TILDE: char = '\u{303}';
+const HOOK: char = '\u{309}';
+const DOT_BELOW: char = '\u{323}';
+
+const LANGUAGES: &[Language] = &[
+    Language {
+        code: "vi",
+        name: "Vietnamese",
+        letters: "ăâđêôơưĂÂĐÊÔƠƯ",
+        bases: "aăâeêioôơuưyAĂÂEÊIOÔƠUƯY",
+        marks: &[GRAVE, ACUTE, HOOK, TILDE, DOT_BELOW],
+    },
+    Language {
+        code: "yo",
+        name: "Yoruba",
+        letters: "ẹọṣẸỌṢ",
+        bases: "aeẹioọunmAEẸIOỌUNM",
+        marks: &[GRAVE, ACUTE],
+    },
+    Language { code: "pl", name: "Polish", letters: "ąćęłńóśźżĄĆĘŁŃÓŚŹŻ", bases: "", marks: &[] },
+    Language {
+        code: "cs",
+        name: "Czech",
+        letters: "áčďéěíňóřšťúůýžÁČĎÉĚÍŇÓŘŠŤÚŮÝŽ",
+        bases: "",
+        marks: &[],
+    },
+    Language { code: "ro", name: "Romanian", letters: "ăâîșțĂÂÎȘȚ", bases: "", marks: &[] },
+    Language { code: "tr", name: "Turkish", letters: "çğıİöşüÇĞÖŞÜ", bases: "", marks: &[] },
+    Language { code: "hu", name: "Hungarian", letters: "áéíóöőúüűÁÉÍÓÖŐÚÜŰ", bases: "", marks: &[] },
+];
+
+impl Language {
+    fn find(code: &str) -> Option<&'static Self> {
+        LANGUAGES.iter().find(|l| l.code.eq_ignore_ascii_case(code))
+    }
+
+    /// Required letters in canonical (NFC) form, which may still be
+    /// multi-code-point where Unicode has no precomposed character.
+    fn required(&self) -> Vec<String> {
+        let combined = self
+            .bases
+            .chars()
+            .flat_map(|b| self.marks.iter().map(move |&m| [b, m].iter().collect::<String>().nfc().collect()));
+        self.letters.chars().map(String::from).chain(combined).collect()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Missing {
+    letter: String,
+    code_points: String,
+    reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiacriticsReport {
+    language: &'static str,
+    name: &'static str,
+    required: usize,
+    precomposed: usize,
+    /// Rendered as base + positioned combining marks.
+    composed_with_marks: usize,
+    complete: bool,
+    missing: Vec<Missing>,
+}
+
+fn code_points(s: &str) -> String {
+    s.chars().map(|c| format!("U+{:04X}", c as u32)).collect::<Vec<_>>().join(" ")
+}
+
+/// How `letter` renders with this font, or why it cannot.
+enum Outcome {
+    Precomposed,
+    Composed,
+    Missing(String),
+}
+
+fn check(letter: &str, cmap: &CharMap, marks: Option<&MarkAttachment>) -> Outcome {
+    let single = |s: &str| {
+        let mut chars = s.chars();
+        chars.next().filter(|_| chars.as_str().is_empty())
+    };
+    if single(letter).and_then(|c| cmap.glyph(c)).is_some() {
+        return Outcome::Precomposed;
+    }
+    // Like a shaper: decompose fully, then recompose the longest prefix the
+    // font has a glyph for and position the remaining marks.
+    let nfd: Vec<char> = letter.nfd().collect();
+    let base = (1..=nfd.len()).rev().find_map(|k| {
+        let prefix: String = nfd[..k].iter().collect::<String>().nfc().collect();
+        Some((k, single(&prefix).and_then(|c| cmap.glyph(c))?, prefix))
+    });
+    let Some((k, base_glyph, base)) = base else {
+        return Outcome::Missing(format!("no glyph for base {} ({})", nfd[0], code_points(&nfd[0].to_string())));
+    };
+    let Some(marks) = marks else {
+        return Outcome::Missing(format!("no precomposed glyph and no GPOS to position marks on '{base}'"));
+    };
+    let mut previous = None;
+    for &mark in &nfd[k..] {
+        let Some(glyph) = cmap.glyph(mark) else {
+            return Outcome::Missing(format!("no glyph for combining mark {}", code_points(&mark.to_string())));
+        };
+        let placed = marks.on_base(glyph, base_glyph) || previous.is_some_and(|p| marks.on_mark(glyph, p));
+        if !placed {
+            return Outcome::Missing(format!(
+                "{} has no mark anchor on '{base}'{}",
+                code_points(&mark.to_string()),
+                if previous.is_some() { " or the preceding mark" } else { "" }
+            ));
+        }
+        previous = Some(glyph);
+    }
+    Outcome::Composed
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiacriticsQuery {
+    language: String,
+}
+
+/// Checks a raw font (or subset) for every letter of `?language=`.
+pub async fn report(
+    Query(query): Query<DiacriticsQuery>,
+    body: Body,
+) -> Result<Json<DiacriticsReport>, (StatusCode, String)> {
+    let language = Language::find(&query.language).ok_or_else(|| {
+        let known: Vec<_> = LANGUAGES.iter().map(|l| l.code).collect();
+        (StatusCode::BAD_REQUEST, format!("unknown language '{}'; valid: {}", query.language, known.join(", ")))
+    })?;
+    let (data, _) = spool::receive_font(body).await?;
+    let unprocessable = |e: String| (StatusCode::UNPROCESSABLE_ENTITY, e);
+    let font = Font::parse(&data).map_err(unprocessable)?;
+    let cmap = CharMap::parse(font.table(b"cmap").ok_or_else(|| unprocessable("font has no cmap table".into()))?)
+        .map_err(unprocessable)?;
+    let marks = font.table(b"GPOS").map(MarkAttachment::parse).transpose().map_err(unprocessable)?;
+
+    let required = language.required();
+    let (mut precomposed, mut composed, mut missing) = (0, 0, Vec::new());
+    for letter in &required {
+        match check(letter, &cmap, marks.as_ref()) {
+            Outcome::Precomposed => precomposed += 1,
+            Outcome::Composed => composed += 1,
+            Outcome::Missing(reason) => {
+                missing.push(Missing { code_points: code_points(letter), letter: letter.clone(), reason })
+            }
+        }
+    }
+    info!(language = language.code, required = required.len(), missing = missing.len(), "diacritics checked");
+    Ok(Json(DiacriticsReport {
+        language: language.code,
+        name: language.name,
+        required: required.len(),
+        precomposed,
+        composed_with_marks: composed,
+        complete: missing.is_empty(),
+        missing,
+    }))
+}
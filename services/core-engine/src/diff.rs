@@ -0,0 +1,281 @@
+//! `POST /api/v1/font/diff`: what changes between two versions of a font.
+//!
+//! Meant for reviewing an upgrade before it reaches the CDN. Glyphs are
+//! matched by label (the character they map to, else their glyph name, else
+//! `gid<n>`; see [`crate::analysis::GlyphLabels`]), so renumbered glyphs do
+//! not show up as changes. Reported: glyphs added and removed, advance width
+//! changes, code points gained and lost, tables whose bytes differ with
+//! their sizes, and changed vertical and style metrics. Glyph lists stop at
+//! [`MAX_LISTED`]; the counts beside them are complete.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{DiffRequest, FontSource};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::RangeInclusive,
+    sync::Arc,
+};
+use tracing::info;
+use ttf_parser::{Face, GlyphId};
+
+use crate::{analysis::GlyphLabels, cancel, compress, duplicates, extract::ApiJson, sfnt::Font, unicode, AppState};
+
+pub const MAX_LISTED: usize = 500;
+
+#[derive(Debug, Serialize)]
+pub struct DiffResponse {
+    from: String,
+    to: String,
+    /// The two files are byte-for-byte the same.
+    identical: bool,
+    glyphs: GlyphChanges,
+    coverage: CoverageChanges,
+    /// Tables added, removed or with different bytes.
+    tables: Vec<TableChange>,
+    /// Only the metrics that differ.
+    metrics: Vec<MetricChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GlyphChanges {
+    from_count: usize,
+    to_count: usize,
+    added_count: usize,
+    added: Vec<String>,
+    removed_count: usize,
+    removed: Vec<String>,
+    advance_change_count: usize,
+    advance_changes: Vec<AdvanceChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdvanceChange {
+    glyph: String,
+    from: u16,
+    to: u16,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CoverageChanges {
+    added_count: usize,
+    added: Vec<String>,
+    removed_count: usize,
+    removed: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableChange {
+    tag: String,
+    /// Absent when the table is new.
+    from_bytes: Option<usize>,
+    /// Absent when the table was dropped.
+    to_bytes: Option<usize>,
+    delta: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MetricChange {
+    /// `table.field`, as in the OpenType spec (`OS/2.sTypoAscender`).
+    name: &'static str,
+    from: Option<f64>,
+    to: Option<f64>,
+}
+
+/// Each glyph's advance width by label; the first glyph wins a label.
+fn advances(face: &Face) -> BTreeMap<String, u16> {
+    let labels = GlyphLabels::new(face);
+    let mut out = BTreeMap::new();
+    for glyph in 0..face.number_of_glyphs() {
+        let advance = face.glyph_hor_advance(GlyphId(glyph)).unwrap_or(0);
+        out.entry(labels.get(glyph)).or_insert(advance);
+    }
+    out
+}
+
+fn code_points(face: &Face) -> BTreeSet<u32> {
+    let mut out = BTreeSet::new();
+    for subtable in face.tables().cmap.iter().flat_map(|c| c.subtables).filter(|s| s.is_unicode()) {
+        subtable.codepoints(|cp| {
+            if char::from_u32(cp).and_then(|c| face.glyph_index(c)).is_some() {
+                out.insert(cp);
+            }
+        });
+    }
+    out
+}
+
+fn metrics(face: &Face) -> Vec<(&'static str, Option<f64>)> {
+    let hhea = face.tables().hhea;
+    let os2 = face.tables().os2;
+    let os2_field = |f: fn(&ttf_parser::os2::Table) -> Option<f64>| os2.as_ref().and_then(f);
+    let underline = face.underline_metrics();
+    let bbox = face.global_bounding_box();
+    vec![
+        ("head.unitsPerEm", Some(face.units_per_em() as f64)),
+        ("head.xMin", Some(bbox.x_min as f64)),
+        ("head.yMin", Some(bbox.y_min as f64)),
+        ("head.xMax", Some(bbox.x_max as f64)),
+        ("head.yMax", Some(bbox.y_max as f64)),
+        ("hhea.ascender", Some(hhea.ascender as f64)),
+        ("hhea.descender", Some(hhea.descender as f64)),
+        ("hhea.lineGap", Some(hhea.line_gap as f64)),
+        ("OS/2.sTypoAscender", os2_field(|t| Some(t.typographic_ascender() as f64))),
+        ("OS/2.sTypoDescender", os2_field(|t| Some(t.typographic_descender() as f64))),
+        ("OS/2.sTypoLineGap", os2_field(|t| Some(t.typographic_line_gap() as f64))),
+        ("OS/2.usWinAscent", os2_field(|t| Some(t.windows_ascender() as f64))),
+        ("OS/2.usWinDescent", os2_field(|t| Some(t.windows_descender() as f64))),
+        ("OS/2.sxHeight", os2_field(|t| t.x_height().map(f64::from))),
+        ("OS/2.sCapHeight", os2_field(|t| t.capital_height().map(f64::from))),
+        ("OS/2.usWeightClass", os2_field(|t| Some(t.weight().to_number() as f64))),
+        ("OS/2.usWidthClass", os2_field(|t| Some(t.width().to_number() as f64))),
+        ("post.italicAngle", Some(face.italic_angle() as f64)),
+        ("post.underlinePosition", underline.map(|m| m.position as f64)),
+        ("post.underlineThickness", underline.map(|m| m.thickness as f64)),
+    ]
+}
+
+/// Up to [`MAX_LISTED`] of `items`, with their full count.
+fn listed<T>(items: impl Iterator<Item = T>) -> (usize, Vec<T>) {
+    let mut count = 0;
+    let mut out = Vec::new();
+    for item in items {
+        count += 1;
+        if out.len() < MAX_LISTED {
+            out.push(item);
+        }
+    }
+    (count, out)
+}
+
+fn ranges(code_points: impl Iterator<Item = u32>) -> Vec<String> {
+    let ranges: Vec<RangeInclusive<u32>> = code_points.map(|cp| cp..=cp).collect();
+    unicode::merge(ranges).iter().map(unicode::format_range).collect()
+}
+
+fn tables(font: &Font) -> BTreeMap<[u8; 4], &[u8]> {
+    font.tables.iter().map(|t| (t.tag, t.data.as_slice())).collect()
+}
+
+fn compare(from: &[u8], to: &[u8], names: (String, String)) -> Result<DiffResponse, String> {
+    let identical = from == to;
+    let (from, to) = (compress::load(from)?, compress::load(to)?);
+    let (from_tables, to_tables) = (tables(&from), tables(&to));
+    let tags: BTreeSet<[u8; 4]> = from_tables.keys().chain(to_tables.keys()).copied().collect();
+    let tables = tags
+        .into_iter()
+        .filter(|tag| from_tables.get(tag) != to_tables.get(tag))
+        .map(|tag| {
+            let from_bytes = from_tables.get(&tag).map(|t| t.len());
+            let to_bytes = to_tables.get(&tag).map(|t| t.len());
+            TableChange {
+                tag: String::from_utf8_lossy(&tag).into_owned(),
+                from_bytes,
+                to_bytes,
+                delta: to_bytes.unwrap_or(0) as i64 - from_bytes.unwrap_or(0) as i64,
+            }
+        })
+        .collect();
+
+    let (from, to) = (from.to_bytes(), to.to_bytes());
+    let from_face = Face::parse(&from, 0).map_err(|e| format!("'{}' is not a parseable font: {e}", names.0))?;
+    let to_face = Face::parse(&to, 0).map_err(|e| format!("'{}' is not a parseable font: {e}", names.1))?;
+
+    let (before, after) = (advances(&from_face), advances(&to_face));
+    let (added_count, added) = listed(after.keys().filter(|g| !before.contains_key(*g)).cloned());
+    let (removed_count, removed) = listed(before.keys().filter(|g| !after.contains_key(*g)).cloned());
+    let (advance_change_count, advance_changes) = listed(before.iter().filter_map(|(glyph, &from)| {
+        let to = *after.get(glyph)?;
+        (to != from).then(|| AdvanceChange { glyph: glyph.clone(), from, to })
+    }));
+
+    let (before, after) = (code_points(&from_face), code_points(&to_face));
+    let gained: Vec<u32> = after.difference(&before).copied().collect();
+    let lost: Vec<u32> = before.difference(&after).copied().collect();
+
+    let metrics = metrics(&from_face)
+        .into_iter()
+        .zip(metrics(&to_face))
+        .filter(|((_, from), (_, to))| from != to)
+        .map(|((name, from), (_, to))| MetricChange { name, from, to })
+        .collect();
+
+    Ok(DiffResponse {
+        from: names.0,
+        to: names.1,
+        identical,
+        glyphs: GlyphChanges {
+            from_count: from_face.number_of_glyphs() as usize,
+            to_count: to_face.number_of_glyphs() as usize,
+            added_count,
+            added,
+            removed_count,
+            removed,
+            advance_change_count,
+            advance_changes,
+        },
+        coverage: CoverageChanges {
+            added_count: gained.len(),
+            added: ranges(gained.into_iter()),
+            removed_count: lost.len(),
+            removed: ranges(lost.into_iter()),
+        },
+        tables,
+        metrics,
+    })
+}
+
+/// The catalog name of `source`, with the bytes when it is an upload.
+async fn resolve(
+    state: &Arc<AppState>,
+    headers: &HeaderMap,
+    source: &FontSource,
+) -> Result<(String, Option<Vec<u8>>), (StatusCode, String)> {
+    let (name, upload) = state.uploads.resolve(headers, source.font_id.as_deref(), &source.font_name).await?;
+    state.sandbox.ensure_font(headers, &state.catalog.read().unwrap(), &name)?;
+    let data = match &upload {
+        Some(upload) => Some(state.uploads.read(upload).await?),
+        None => None,
+    };
+    Ok((name, data))
+}
+
+pub async fn diff(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(req): ApiJson<DiffRequest>,
+) -> Result<Json<DiffResponse>, (StatusCode, String)> {
+    state.flags.ensure("analyze", &headers)?;
+    let (from_name, from_upload) = resolve(&state, &headers, &req.from).await?;
+    let (to_name, to_upload) = resolve(&state, &headers, &req.to).await?;
+
+    let (job_state, names) = (Arc::clone(&state), (from_name.clone(), to_name.clone()));
+    let response = cancel::run(&state.jobs, "diff", move |token| {
+        let load = |upload: Option<Vec<u8>>, name: &str| match upload {
+            Some(data) => Ok(data),
+            None => duplicates::catalog_binary(&job_state, name),
+        };
+        let (from, to) = match (load(from_upload, &names.0), load(to_upload, &names.1)) {
+            (Ok(from), Ok(to)) => (from, to),
+            (Err(e), _) | (_, Err(e)) => return Ok(Err(e)),
+        };
+        token.check()?;
+        Ok(compare(&from, &to, names))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    info!(
+        from = %from_name,
+        to = %to_name,
+        glyphs_added = response.glyphs.added_count,
+        glyphs_removed = response.glyphs.removed_count,
+        tables_changed = response.tables.len(),
+        "font diff request"
+    );
+    Ok(Json(response))
+}
@@ -0,0 +1,216 @@
+//! Duplicate outline detection across the catalog.
+//!
+//! Each catalog font's binary (`CATALOG_FONT_DIR/<id>.ttf|otf`) is reduced
+//! to the set of its glyph outline hashes, with coordinates normalized to a
+//! 1000-unit em so a re-scaled fork still matches. Faces whose sets overlap
+//! beyond a threshold are reported: identical sets are re-uploads or
+//! renames, high overlap is a fork with a few glyphs changed.
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    hash::Hasher,
+    path::PathBuf,
+    sync::Arc,
+};
+use tracing::info;
+use ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+use crate::{cancel, AppState};
+
+/// Directory holding the original binary of each catalog entry.
+pub fn catalog_font_dir() -> Option<PathBuf> {
+    std::env::var("CATALOG_FONT_DIR").ok().filter(|d| !d.is_empty()).map(PathBuf::from)
+}
+
+/// The entry's binary, `<id>.ttf` or `<id>.otf`.
+pub fn font_path(dir: &std::path::Path, id: &str) -> Option<PathBuf> {
+    ["ttf", "otf"].iter().map(|ext| dir.join(format!("{id}.{ext}"))).find(|p| p.is_file())
+}
+
+/// Where the binary of the catalog entry `font_name` refers to is.
+pub fn catalog_path(state: &AppState, font_name: &str) -> Result<PathBuf, String> {
+    let key = font_name.to_lowercase();
+    let id = state
+        .catalog
+        .read()
+        .unwrap()
+        .iter()
+        .find(|e| e.id == key || e.family.to_lowercase() == key)
+        .map(|e| e.id.clone())
+        .ok_or_else(|| format!("'{font_name}' is not in the catalog; upload it and pass font_id"))?;
+    let dir = catalog_font_dir().ok_or_else(|| format!("CATALOG_FONT_DIR is not configured, so '{id}' has no binary"))?;
+    font_path(&dir, &id).ok_or_else(|| format!("no binary for '{id}' in CATALOG_FONT_DIR"))
+}
+
+/// The binary of the catalog entry `font_name` refers to.
+pub fn catalog_binary(state: &AppState, font_name: &str) -> Result<Vec<u8>, String> {
+    let path = catalog_path(state, font_name)?;
+    std::fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// [`catalog_binary`] without blocking the runtime.
+pub async fn read_catalog_binary(state: &AppState, font_name: &str) -> Result<Vec<u8>, String> {
+    let path = catalog_path(state, font_name)?;
+    tokio::fs::read(&path).await.map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Hashes outline commands with coordinates in 1/1000 em.
+struct OutlineHasher {
+    scale: f32,
+    hasher: DefaultHasher,
+}
+
+impl OutlineHasher {
+    fn point(&mut self, x: f32, y: f32) {
+        self.hasher.write_i32((x * self.scale).round() as i32);
+        self.hasher.write_i32((y * self.scale).round() as i32);
+    }
+}
+
+impl OutlineBuilder for OutlineHasher {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.hasher.write_u8(b'M');
+        self.point(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.hasher.write_u8(b'L');
+        self.point(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.hasher.write_u8(b'Q');
+        self.point(x1, y1);
+        self.point(x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.hasher.write_u8(b'C');
+        self.point(x1, y1);
+        self.point(x2, y2);
+        self.point(x, y);
+    }
+
+    fn close(&mut self) {
+        self.hasher.write_u8(b'Z');
+    }
+}
+
+/// Outline hashes of every non-empty glyph (TrueType or CFF outlines).
+fn fingerprint(data: &[u8]) -> Result<HashSet<u64>, String> {
+    let face = Face::parse(data, 0).map_err(|e| e.to_string())?;
+    let scale = 1000.0 / face.units_per_em().max(1) as f32;
+    let mut hashes = HashSet::new();
+    for glyph in 0..face.number_of_glyphs() {
+        let mut builder = OutlineHasher { scale, hasher: DefaultHasher::new() };
+        if face.outline_glyph(GlyphId(glyph), &mut builder).is_some() {
+            hashes.insert(builder.hasher.finish());
+        }
+    }
+    if hashes.is_empty() {
+        return Err("font has no outlines".to_string());
+    }
+    Ok(hashes)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DuplicateQuery {
+    /// Minimum share of outlines in common (Jaccard) to report a pair.
+    #[serde(default = "default_threshold")]
+    threshold: f64,
+}
+
+fn default_threshold() -> f64 {
+    0.9
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicatePair {
+    a: String,
+    b: String,
+    /// `identical` (same outline set) or `near-duplicate`.
+    kind: &'static str,
+    similarity: f64,
+    shared_outlines: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Unreadable {
+    id: String,
+    error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateReport {
+    threshold: f64,
+    scanned: usize,
+    /// Catalog entries without a binary in `CATALOG_FONT_DIR`.
+    missing: Vec<String>,
+    unreadable: Vec<Unreadable>,
+    pairs: Vec<DuplicatePair>,
+}
+
+/// Compares every pair of catalog fonts by outline hashes.
+pub async fn scan(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<DuplicateQuery>,
+) -> Result<Json<DuplicateReport>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    if !(0.0..=1.0).contains(&query.threshold) {
+        return Err((StatusCode::BAD_REQUEST, "threshold must be between 0 and 1".to_string()));
+    }
+    let dir = catalog_font_dir()
+        .ok_or((StatusCode::SERVICE_UNAVAILABLE, "CATALOG_FONT_DIR is not configured".to_string()))?;
+    let ids: Vec<String> = state.catalog.read().unwrap().iter().map(|e| e.id.clone()).collect();
+    let threshold = query.threshold;
+
+    let report = cancel::run(&state.jobs, "duplicates", move |token| {
+        let (mut fingerprints, mut missing, mut unreadable) = (Vec::new(), Vec::new(), Vec::new());
+        for id in ids {
+            token.check()?;
+            let Some(path) = font_path(&dir, &id) else {
+                missing.push(id);
+                continue;
+            };
+            match std::fs::read(&path).map_err(|e| e.to_string()).and_then(|data| fingerprint(&data)) {
+                Ok(hashes) => fingerprints.push((id, hashes)),
+                Err(error) => unreadable.push(Unreadable { id, error }),
+            }
+        }
+        let mut pairs = Vec::new();
+        for (i, (a, ha)) in fingerprints.iter().enumerate() {
+            token.check()?;
+            for (b, hb) in &fingerprints[i + 1..] {
+                let shared = ha.intersection(hb).count();
+                let similarity = shared as f64 / (ha.len() + hb.len() - shared) as f64;
+                if similarity >= threshold {
+                    pairs.push(DuplicatePair {
+                        a: a.clone(),
+                        b: b.clone(),
+                        kind: if ha == hb { "identical" } else { "near-duplicate" },
+                        similarity: (similarity * 1000.0).round() / 1000.0,
+                        shared_outlines: shared,
+                    });
+                }
+            }
+        }
+        pairs.sort_by(|x, y| y.similarity.total_cmp(&x.similarity));
+        Ok(DuplicateReport { threshold, scanned: fingerprints.len(), missing, unreadable, pairs })
+    })
+    .await?;
+
+    info!(
+        scanned = report.scanned,
+        pairs = report.pairs.len(),
+        missing = report.missing.len(),
+        "catalog duplicate scan"
+    );
+    Ok(Json(report))
+}
@@ -0,0 +1,147 @@
+//! Health probing of downstream CDN/edge hosts.
+//!
+//! `EDGE_ENDPOINTS` lists the public base URLs fonts are served from (the
+//! first is primary). Each is fetched every `EDGE_PROBE_INTERVAL_SECS` at
+//! `EDGE_PROBE_PATH`; a failed request marks it unhealthy and an `Age` header
+//! above `EDGE_MAX_AGE_SECS` marks it stale. Results appear on `/readyz`.
+//! With `EDGE_FAILOVER=true`, generated download URLs point at the first
+//! healthy host instead of always the primary.
+
+use serde::Serialize;
+use std::{
+    sync::RwLock,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+fn env_u64(k: &str, d: u64) -> u64 {
+    std::env::var(k).ok().and_then(|v| v.parse().ok()).unwrap_or(d)
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EdgeStatus {
+    pub url: String,
+    /// `None` until the first probe completes.
+    pub healthy: Option<bool>,
+    pub stale: bool,
+    pub age_secs: Option<u64>,
+    pub latency_ms: Option<u64>,
+    pub last_checked_unix: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub probes: u64,
+    pub failures: u64,
+}
+
+pub struct EdgeMonitor {
+    edges: Vec<RwLock<EdgeStatus>>,
+    probe_path: String,
+    max_age: u64,
+    interval: Duration,
+    failover: bool,
+    http: reqwest::Client,
+}
+
+impl EdgeMonitor {
+    pub fn from_env() -> Self {
+        let edges = std::env::var("EDGE_ENDPOINTS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|u| u.trim().trim_end_matches('/'))
+            .filter(|u| !u.is_empty())
+            .map(|url| RwLock::new(EdgeStatus { url: url.to_string(), ..Default::default() }))
+            .collect();
+        Self {
+            edges,
+            probe_path: std::env::var("EDGE_PROBE_PATH").unwrap_or_else(|_| "/health".to_string()),
+            max_age: env_u64("EDGE_MAX_AGE_SECS", 3_600),
+            interval: Duration::from_secs(env_u64("EDGE_PROBE_INTERVAL_SECS", 30).max(1)),
+            failover: matches!(std::env::var("EDGE_FAILOVER").as_deref(), Ok("true" | "1")),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(env_u64("EDGE_PROBE_TIMEOUT_SECS", 5)))
+                .build()
+                .expect("failed to build HTTP client"),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        !self.edges.is_empty()
+    }
+
+    pub fn statuses(&self) -> Vec<EdgeStatus> {
+        self.edges.iter().map(|e| e.read().unwrap().clone()).collect()
+    }
+
+    /// Absolute URL for a CDN `path`, or `path` itself when no edges are set.
+    pub fn url_for(&self, path: &str) -> String {
+        let Some(primary) = self.edges.first() else {
+            return path.to_string();
+        };
+        let usable = |e: &EdgeStatus| e.healthy != Some(false) && !e.stale;
+        let base = self
+            .failover
+            .then(|| {
+                self.edges
+                    .iter()
+                    .map(|e| e.read().unwrap())
+                    .find(|e| usable(e))
+                    .map(|e| e.url.clone())
+            })
+            .flatten()
+            // No failover, or everything is down: keep pointing at the primary.
+            .unwrap_or_else(|| primary.read().unwrap().url.clone());
+        format!("{base}{path}")
+    }
+
+    /// Probes every edge forever; spawn once at startup.
+    pub async fn run(self: std::sync::Arc<Self>) {
+        if !self.is_configured() {
+            return;
+        }
+        info!(edges = self.edges.len(), interval_secs = self.interval.as_secs(), "edge probing enabled");
+        let mut tick = tokio::time::interval(self.interval);
+        loop {
+            tick.tick().await;
+            for edge in &self.edges {
+                let url = edge.read().unwrap().url.clone();
+                let started = Instant::now();
+                let result = self.http.get(format!("{url}{}", self.probe_path)).send().await;
+                let latency_ms = started.elapsed().as_millis() as u64;
+
+                let mut st = edge.write().unwrap();
+                let was_healthy = st.healthy;
+                st.probes += 1;
+                st.latency_ms = Some(latency_ms);
+                st.last_checked_unix = SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+                match result {
+                    Ok(resp) if resp.status().is_success() => {
+                        st.healthy = Some(true);
+                        st.error = None;
+                        st.age_secs = resp
+                            .headers()
+                            .get("age")
+                            .and_then(|v| v.to_str().ok())
+                            .and_then(|v| v.trim().parse().ok());
+                        st.stale = st.age_secs.is_some_and(|age| age > self.max_age);
+                    }
+                    Ok(resp) => {
+                        st.healthy = Some(false);
+                        st.failures += 1;
+                        st.error = Some(format!("probe returned {}", resp.status()));
+                    }
+                    Err(e) => {
+                        st.healthy = Some(false);
+                        st.failures += 1;
+                        st.error = Some(e.to_string());
+                    }
+                }
+                if was_healthy != st.healthy {
+                    match st.healthy {
+                        Some(true) => info!(edge = %st.url, "edge healthy"),
+                        _ => warn!(edge = %st.url, error = ?st.error, "edge unhealthy"),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! `Content-Encoding` negotiation for font downloads and stylesheets.
+//!
+//! [`negotiate`] wraps the CDN and CSS routes. TTF, OTF and CSS bodies are
+//! sent Brotli- or gzip-compressed when `Accept-Encoding` allows it (Brotli
+//! wins ties); WOFF and WOFF2 are already compressed and pass through as is.
+//! Eligible responses always get `Vary: Accept-Encoding`, and a compressed
+//! one's `ETag` is weakened and its `Digest` dropped, since the bytes differ
+//! from the stored file.
+//!
+//! Gzip is applied chunk by chunk as the body streams to the client, so
+//! memory stays flat however large the file. Brotli is encoded in one piece
+//! and used only up to 4 MiB; larger bodies go out gzipped, or as they are.
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use flate2::{write::GzEncoder, Compression};
+use futures_util::{stream, StreamExt};
+use std::io::Write;
+use tracing::warn;
+
+use crate::brotli;
+
+const COMPRESSIBLE: &[&str] = &["font/ttf", "font/otf", "text/css"];
+
+/// Below this the encoding overhead outweighs the savings.
+const MIN_BYTES: u64 = 1024;
+/// Brotli compresses bodies in memory; larger ones are gzip-streamed.
+const MAX_BROTLI_BYTES: u64 = 4 << 20;
+
+/// Fast enough per request; generated fonts are immutable, so edges cache
+/// the result.
+const BROTLI_LEVEL: u8 = 5;
+
+#[derive(Clone, Copy)]
+enum Coding {
+    Brotli,
+    Gzip,
+}
+
+impl Coding {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+        }
+    }
+
+}
+
+/// `body` gzip-compressed a chunk at a time, as the client reads it.
+fn gzip(body: Body) -> Body {
+    let encoder = GzEncoder::new(Vec::new(), Compression::default());
+    Body::from_stream(stream::unfold((body.into_data_stream(), Some(encoder)), |(mut chunks, gz)| async move {
+        let mut gz = gz?;
+        loop {
+            let written = match chunks.next().await {
+                Some(Ok(chunk)) => gz.write_all(&chunk),
+                Some(Err(e)) => Err(std::io::Error::other(e)),
+                // Ends the stream on the next poll.
+                None => return Some((gz.finish(), (chunks, None))),
+            };
+            if let Err(e) = written {
+                return Some((Err(e), (chunks, None)));
+            }
+            let out = std::mem::take(gz.get_mut());
+            if !out.is_empty() {
+                return Some((Ok(out), (chunks, Some(gz))));
+            }
+        }
+    }))
+}
+
+/// The coding `Accept-Encoding` rates highest, if any, Brotli left out
+/// unless `brotli`.
+fn preferred(headers: &HeaderMap, brotli: bool) -> Option<Coding> {
+    let accept = headers.get(header::ACCEPT_ENCODING)?.to_str().ok()?;
+    let (mut br, mut gzip, mut wildcard) = (None, None, None);
+    for item in accept.split(',') {
+        let mut parts = item.split(';').map(str::trim);
+        let token = parts.next().unwrap_or_default().to_ascii_lowercase();
+        let q = match parts.find_map(|p| p.strip_prefix("q=")) {
+            Some(v) => v.parse::<f32>().unwrap_or(0.0),
+            None => 1.0,
+        };
+        match token.as_str() {
+            "br" => br = Some(q),
+            "gzip" | "x-gzip" => gzip = Some(q),
+            "*" => wildcard = Some(q),
+            _ => {}
+        }
+    }
+    // `*` covers the codings not listed by name.
+    let br = br.or(wildcard).filter(|_| brotli).unwrap_or(0.0);
+    let gzip = gzip.or(wildcard).unwrap_or(0.0);
+    if br > 0.0 && br >= gzip {
+        Some(Coding::Brotli)
+    } else if gzip > 0.0 {
+        Some(Coding::Gzip)
+    } else {
+        None
+    }
+}
+
+fn compressible(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|t| COMPRESSIBLE.contains(&t.trim()))
+}
+
+pub async fn negotiate(request: Request, next: Next) -> Response {
+    let (coding, without_brotli) = (preferred(request.headers(), true), preferred(request.headers(), false));
+    let mut response = next.run(request).await;
+    if !compressible(&response) {
+        return response;
+    }
+    response.headers_mut().append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    let length = response.body().size_hint().exact().or_else(|| {
+        response.headers().get(header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()?.parse().ok())
+    });
+    let Some(length) = length.filter(|l| *l >= MIN_BYTES) else { return response };
+    let coding = if length > MAX_BROTLI_BYTES { without_brotli } else { coding };
+    let Some(coding) = coding else { return response };
+    if response.status() != StatusCode::OK || response.headers().contains_key(header::CONTENT_ENCODING) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match coding {
+        Coding::Gzip => {
+            parts.headers.remove(header::CONTENT_LENGTH);
+            gzip(body)
+        }
+        Coding::Brotli => {
+            let data = match axum::body::to_bytes(body, MAX_BROTLI_BYTES as usize).await {
+                Ok(data) => data,
+                Err(e) => {
+                    // The body is gone; all that is left is to fail the request.
+                    warn!(error = %e, "reading response body to compress failed");
+                    return Response::builder().status(StatusCode::BAD_GATEWAY).body(Body::empty()).unwrap();
+                }
+            };
+            let encode = move || (brotli::compress(&data, BROTLI_LEVEL), data);
+            let encoded = match tokio::task::spawn_blocking(encode).await {
+                Ok((encoded, data)) if encoded.len() >= data.len() => {
+                    return Response::from_parts(parts, Body::from(data))
+                }
+                Ok((encoded, _)) => encoded,
+                Err(e) => {
+                    warn!(error = %e, "response compression failed");
+                    return Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()).unwrap();
+                }
+            };
+            parts.headers.insert(header::CONTENT_LENGTH, HeaderValue::from(encoded.len()));
+            Body::from(encoded)
+        }
+    };
+    let headers = &mut parts.headers;
+    headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(coding.name()));
+    // Ranges and `Digest` address the stored bytes, which this response does
+    // not carry; `x-content-sha256` still names the file once decoded.
+    headers.remove(header::ACCEPT_RANGES);
+    headers.remove("digest");
+    if let Some(etag) = headers.get(header::ETAG).and_then(|v| v.to_str().ok()).filter(|v| !v.starts_with("W/")) {
+        let weak = HeaderValue::from_str(&format!("W/{etag}")).expect("ETag was a valid header value");
+        headers.insert(header::ETAG, weak);
+    }
+    Response::from_parts(parts, body)
+}
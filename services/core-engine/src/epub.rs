@@ -0,0 +1,240 @@
+//! Font obfuscation for EPUB packaging.
+//!
+//! Implements the two algorithms reading systems understand:
+//!
+//! * `idpf` — EPUB OCF 3.0: XOR the first 1040 bytes with the SHA-1 of the
+//!   publication's unique identifier (whitespace removed).
+//! * `adobe` — Adobe's older scheme: XOR the first 1024 bytes with the 16
+//!   bytes of the publication's `urn:uuid:` identifier.
+//!
+//! Obfuscation is its own inverse, so the same call de-obfuscates. Besides
+//! `POST /api/v1/font/epub` for fonts at hand, a compress request with
+//! `epub` stores an obfuscated copy of its output next to the web file (see
+//! [`checked`]).
+
+use axum::{
+    body::Body,
+    extract::Query,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use font_api::EpubOptions;
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use tracing::info;
+
+use crate::spool;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    #[default]
+    Idpf,
+    Adobe,
+}
+
+impl Algorithm {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "idpf" => Ok(Self::Idpf),
+            "adobe" => Ok(Self::Adobe),
+            other => Err(format!("unknown EPUB obfuscation algorithm '{other}'; use idpf or adobe")),
+        }
+    }
+
+    /// Value for the `Algorithm` attribute in `META-INF/encryption.xml`.
+    pub fn uri(self) -> &'static str {
+        match self {
+            Self::Idpf => "http://www.idpf.org/2008/embedding",
+            Self::Adobe => "http://ns.adobe.com/pdf/enc#RC",
+        }
+    }
+
+    fn key(self, publication_id: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Idpf => {
+                let id: String = publication_id
+                    .chars()
+                    .filter(|c| !matches!(c, ' ' | '\t' | '\r' | '\n'))
+                    .collect();
+                Ok(Sha1::digest(id.as_bytes()).to_vec())
+            }
+            Self::Adobe => {
+                let hex: String = publication_id
+                    .trim()
+                    .trim_start_matches("urn:uuid:")
+                    .chars()
+                    .filter(|c| *c != '-')
+                    .collect();
+                if hex.len() != 32 || !hex.is_ascii() {
+                    return Err("adobe obfuscation needs a urn:uuid: publication id".to_string());
+                }
+                (0..16)
+                    .map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16))
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| "publication id is not a valid UUID".to_string())
+            }
+        }
+    }
+
+    fn span(self) -> usize {
+        match self {
+            Self::Idpf => 1040,
+            Self::Adobe => 1024,
+        }
+    }
+}
+
+/// XORs the leading bytes of `data` in place.
+pub fn obfuscate(data: &mut [u8], algorithm: Algorithm, publication_id: &str) -> Result<(), String> {
+    let key = algorithm.key(publication_id)?;
+    let span = algorithm.span().min(data.len());
+    for (i, b) in data[..span].iter_mut().enumerate() {
+        *b ^= key[i % key.len()];
+    }
+    Ok(())
+}
+
+/// The algorithm a compress request's `epub` asks for, once its
+/// publication id is known to key it; `format` is the output's.
+pub fn checked(options: &EpubOptions, format: &str) -> Result<Algorithm, (StatusCode, String)> {
+    let invalid = |e: String| (StatusCode::BAD_REQUEST, format!("epub: {e}"));
+    if format == "eot" {
+        return Err(invalid("EPUB fonts are woff2, woff, otf or ttf".to_string()));
+    }
+    if options.publication_id.trim().is_empty() {
+        return Err(invalid("publication_id is required".to_string()));
+    }
+    let algorithm = Algorithm::parse(&options.algorithm).map_err(invalid)?;
+    algorithm.key(&options.publication_id).map_err(invalid)?;
+    Ok(algorithm)
+}
+
+/// The transform steps of the obfuscated copy of an output with `steps`.
+pub fn steps(steps: &serde_json::Value, options: &EpubOptions, algorithm: Algorithm) -> serde_json::Value {
+    let mut steps = steps.clone();
+    steps["epub"] = serde_json::json!({ "publication_id": options.publication_id, "algorithm": algorithm.uri() });
+    steps
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EpubQuery {
+    publication_id: String,
+    #[serde(default)]
+    algorithm: Algorithm,
+}
+
+/// Takes a raw font body and returns it obfuscated for the publication,
+/// ready to store in the EPUB container. `X-Encryption-Algorithm` carries the
+/// URI to list for it in `META-INF/encryption.xml`.
+pub async fn package(
+    Query(query): Query<EpubQuery>,
+    body: Body,
+) -> Result<Response, (StatusCode, String)> {
+    if query.publication_id.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "publication_id is required".to_string()));
+    }
+    let (mut data, flavor) = spool::receive_font(body).await?;
+    obfuscate(&mut data, query.algorithm, &query.publication_id)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    info!(size = data.len(), ?flavor, algorithm = ?query.algorithm, "font obfuscated for EPUB");
+    Ok((
+        [
+            (header::CONTENT_TYPE, flavor.media_type()),
+            (header::HeaderName::from_static("x-encryption-algorithm"), query.algorithm.uri()),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: &str = "urn:uuid:12345678-9abc-def0-1234-56789abcdef0";
+
+    fn options(publication_id: &str, algorithm: &str) -> EpubOptions {
+        EpubOptions { publication_id: publication_id.to_string(), algorithm: algorithm.to_string() }
+    }
+
+    /// `data` after obfuscating zeros: the key, repeated over the span.
+    fn keystream(len: usize, algorithm: Algorithm, publication_id: &str) -> Vec<u8> {
+        let mut data = vec![0; len];
+        obfuscate(&mut data, algorithm, publication_id).unwrap();
+        data
+    }
+
+    #[test]
+    fn idpf_xors_1040_bytes_with_the_sha1_of_the_trimmed_id() {
+        // SHA-1("abc"); whitespace anywhere in the id is left out.
+        let key = [
+            0xa9, 0x99, 0x3e, 0x36, 0x47, 0x06, 0x81, 0x6a, 0xba, 0x3e, 0x25, 0x71, 0x78, 0x50, 0xc2, 0x6c, 0x9c, 0xd0,
+            0xd8, 0x9d,
+        ];
+        let data = keystream(1100, Algorithm::Idpf, " a b\tc\r\n");
+        for block in data[..1040].chunks(20) {
+            assert_eq!(block, key);
+        }
+        assert!(data[1040..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn adobe_xors_1024_bytes_with_the_uuid() {
+        let key = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0];
+        let data = keystream(1100, Algorithm::Adobe, UUID);
+        for block in data[..1024].chunks(16) {
+            assert_eq!(block, key);
+        }
+        assert!(data[1024..].iter().all(|&b| b == 0));
+        assert_eq!(keystream(16, Algorithm::Adobe, "123456789ABCDEF0123456789abcdef0"), key, "bare and upper case");
+    }
+
+    #[test]
+    fn obfuscation_is_its_own_inverse() {
+        let font: Vec<u8> = (0..3000u32).map(|i| (i * 7 % 251) as u8).collect();
+        for (algorithm, id) in [(Algorithm::Idpf, "urn:isbn:9780000000002"), (Algorithm::Adobe, UUID)] {
+            let mut data = font.clone();
+            obfuscate(&mut data, algorithm, id).unwrap();
+            assert_ne!(data[..algorithm.span()], font[..algorithm.span()]);
+            assert_eq!(data[algorithm.span()..], font[algorithm.span()..]);
+            obfuscate(&mut data, algorithm, id).unwrap();
+            assert_eq!(data, font);
+        }
+        let mut short = vec![1, 2, 3];
+        obfuscate(&mut short, Algorithm::Adobe, UUID).unwrap();
+        assert_eq!(short, [1 ^ 0x12, 2 ^ 0x34, 3 ^ 0x56]);
+    }
+
+    #[test]
+    fn adobe_needs_a_uuid() {
+        let not_hex = "urn:uuid:zz345678-9abc-def0-1234-56789abcdef0";
+        // 32 bytes, but not 32 hex digits.
+        let multibyte = format!("a{}a", "é".repeat(15));
+        for id in ["urn:isbn:9780000000002", "urn:uuid:1234", not_hex, &multibyte] {
+            assert!(obfuscate(&mut [0; 4], Algorithm::Adobe, id).is_err(), "{id}");
+        }
+    }
+
+    #[test]
+    fn checks_compress_requests_up_front() {
+        assert!(matches!(checked(&options(UUID, "idpf"), "woff2"), Ok(Algorithm::Idpf)));
+        assert!(matches!(checked(&options(UUID, "adobe"), "ttf"), Ok(Algorithm::Adobe)));
+        for (options, format) in [
+            (options(UUID, "idpf"), "eot"),
+            (options(" ", "idpf"), "woff2"),
+            (options(UUID, "rot13"), "woff2"),
+            (options("urn:isbn:9780000000002", "adobe"), "otf"),
+        ] {
+            let (status, message) = checked(&options, format).unwrap_err();
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert!(message.starts_with("epub: "), "{message}");
+        }
+        let web = serde_json::json!({ "compress": 90 });
+        let obfuscated = steps(&web, &options(UUID, "adobe"), Algorithm::Adobe);
+        assert_eq!(obfuscated["epub"]["algorithm"], "http://ns.adobe.com/pdf/enc#RC");
+        assert_eq!(obfuscated["compress"], 90);
+    }
+}
@@ -0,0 +1,105 @@
+//! Dry runs: what compress or subset would produce.
+//!
+//! `"dry_run": true` runs the request's real pipeline (feature pruning,
+//! subsetting, hint stripping, encoding) and reports the exact output size,
+//! but stores nothing and returns no `download_url`. When the output is
+//! already in storage its recorded size is reported without running
+//! anything (`basis: "stored"`). Otherwise (`basis: "encoded"`) the report
+//! also says where the bytes go: each table's size in the source and in the
+//! processed font before compression, summed per group (outlines, layout,
+//! hinting, metrics, color, other).
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::sfnt::Font;
+
+const CATEGORIES: &[&str] = &["outlines", "layout", "hinting", "metrics", "color", "other"];
+
+/// Table groups by role.
+fn category(tag: &[u8; 4]) -> &'static str {
+    match tag {
+        b"glyf" | b"loca" | b"CFF " | b"CFF2" | b"gvar" => "outlines",
+        b"GSUB" | b"GPOS" | b"GDEF" | b"BASE" | b"JSTF" | b"MATH" | b"kern" | b"morx" => "layout",
+        b"fpgm" | b"prep" | b"cvt " | b"hdmx" | b"VDMX" | b"LTSH" | b"gasp" | b"cvar" => "hinting",
+        b"hmtx" | b"vmtx" | b"HVAR" | b"VVAR" | b"VORG" => "metrics",
+        b"CBDT" | b"CBLC" | b"sbix" | b"EBDT" | b"EBLC" | b"SVG " | b"COLR" | b"CPAL" => "color",
+        _ => "other",
+    }
+}
+
+fn known_category<'de, D: Deserializer<'de>>(deserializer: D) -> Result<&'static str, D::Error> {
+    crate::isolation::known_name(deserializer, CATEGORIES)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TableEstimate {
+    tag: String,
+    #[serde(deserialize_with = "known_category")]
+    category: crate::isolation::Known,
+    bytes: usize,
+    /// Share of the source's table bytes.
+    share: f64,
+    /// Uncompressed, after processing; 0 for tables the output drops.
+    output_bytes: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Estimate {
+    /// `encoded` when the pipeline ran, `stored` for output already in
+    /// storage.
+    basis: &'static str,
+    pub original_bytes: usize,
+    /// The output's exact size.
+    pub estimated_bytes: usize,
+    /// Uncompressed output bytes per table group.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    groups: Vec<(&'static str, usize)>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tables: Vec<TableEstimate>,
+}
+
+/// Table sizes of a font before processing, to compare with the output.
+pub struct Breakdown(Vec<([u8; 4], usize)>);
+
+impl Breakdown {
+    pub fn before(font: &Font) -> Self {
+        Self(font.tables.iter().map(|t| (t.tag, t.data.len())).collect())
+    }
+
+    /// Each source table next to its size in `font`, largest first.
+    pub fn after(self, font: &Font) -> Vec<TableEstimate> {
+        let total = self.0.iter().map(|(_, n)| n).sum::<usize>().max(1);
+        let mut tables: Vec<TableEstimate> = self
+            .0
+            .into_iter()
+            .map(|(tag, bytes)| TableEstimate {
+                tag: String::from_utf8_lossy(&tag).into_owned(),
+                category: category(&tag),
+                bytes,
+                share: (bytes as f64 / total as f64 * 1000.0).round() / 1000.0,
+                output_bytes: font.table(&tag).map_or(0, <[u8]>::len),
+            })
+            .collect();
+        tables.sort_by_key(|t| std::cmp::Reverse(t.bytes));
+        tables
+    }
+}
+
+impl Estimate {
+    /// The output of a dry run, with its table breakdown.
+    pub fn encoded(original_bytes: usize, output_bytes: usize, tables: Vec<TableEstimate>) -> Self {
+        let mut groups: Vec<(&'static str, usize)> = Vec::new();
+        for t in &tables {
+            match groups.iter_mut().find(|(c, _)| *c == t.category) {
+                Some((_, n)) => *n += t.output_bytes,
+                None => groups.push((t.category, t.output_bytes)),
+            }
+        }
+        Self { basis: "encoded", original_bytes, estimated_bytes: output_bytes, groups, tables }
+    }
+
+    /// Output that is already in storage.
+    pub fn stored(original_bytes: usize, output_bytes: usize) -> Self {
+        Self { basis: "stored", original_bytes, estimated_bytes: output_bytes, groups: Vec::new(), tables: Vec::new() }
+    }
+}
@@ -0,0 +1,78 @@
+//! Request extractors shared by the handlers.
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::{header, HeaderMap, StatusCode},
+};
+use serde::de::DeserializeOwned;
+use tracing::debug;
+
+use crate::limits;
+
+/// JSON body extractor with an opt-in strict mode.
+///
+/// Unknown fields are ignored by default, as with `axum::Json`. When the
+/// client sends `X-Strict-Json: true`, or `STRICT_JSON=true` is set for the
+/// whole deployment, any unknown field (e.g. a misspelled `qualty`) is a
+/// `400` naming the offending paths instead of silently falling back to
+/// defaults. Clients can send `X-Strict-Json: false` to opt out again.
+pub struct ApiJson<T>(pub T);
+
+fn strict_requested(headers: &HeaderMap) -> bool {
+    match headers.get("x-strict-json").and_then(|v| v.to_str().ok()) {
+        Some(v) => matches!(v, "1" | "true"),
+        None => std::env::var("STRICT_JSON").is_ok_and(|v| v == "1" || v == "true"),
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let is_json = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|ct| ct.starts_with("application/json") || ct.contains("+json"));
+        if !is_json {
+            return Err((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "expected request with `Content-Type: application/json`".to_string(),
+            ));
+        }
+        let strict = strict_requested(req.headers());
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| match e.status() {
+                StatusCode::PAYLOAD_TOO_LARGE => (
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!("JSON body exceeds the {}-byte limit", limits::max_json_bytes()),
+                ),
+                status => (status, e.body_text()),
+            })?;
+
+        let mut unknown = Vec::new();
+        let mut de = serde_json::Deserializer::from_slice(&bytes);
+        let value: T = serde_ignored::deserialize(&mut de, |path| unknown.push(path.to_string()))
+            .and_then(|v| de.end().map(|()| v))
+            .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, format!("invalid JSON body: {e}")))?;
+
+        if !unknown.is_empty() {
+            if strict {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("unknown field(s) in strict mode: {}", unknown.join(", ")),
+                ));
+            }
+            debug!(fields = ?unknown, "ignoring unknown JSON fields");
+        }
+        Ok(ApiJson(value))
+    }
+}
@@ -0,0 +1,143 @@
+//! Metric-matched local fallbacks for generated CSS.
+//!
+//! While a web font loads, text is set in a local font whose different
+//! widths and vertical metrics reflow the page when the web font arrives.
+//! `?fallback=arial,roboto` on the CSS endpoints adds, per family, an
+//! `@font-face` named `"<Family> Fallback <Local>"` over `local()` with
+//! `size-adjust` matching the family's average lowercase width (weighted by
+//! English letter frequency) and `ascent-override`, `descent-override` and
+//! `line-gap-override` matching its vertical metrics, so listing it right
+//! after the web font in `font-family` keeps the layout still. Metrics come
+//! from the family's Regular face in `CATALOG_FONT_DIR` (the first face
+//! otherwise); families without a binary get no fallback.
+
+use ttf_parser::Face;
+
+/// A local font: names tried in `local()`, and its metrics.
+pub struct LocalFont {
+    pub key: &'static str,
+    name: &'static str,
+    local: &'static [&'static str],
+    metrics: Metrics,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Metrics {
+    units_per_em: f64,
+    ascent: f64,
+    /// Positive, below the baseline.
+    descent: f64,
+    line_gap: f64,
+    /// Frequency-weighted average advance of `a`-`z` and the space.
+    average_width: f64,
+}
+
+pub const LOCAL_FONTS: &[LocalFont] = &[
+    LocalFont {
+        key: "arial",
+        name: "Arial",
+        local: &["Arial", "ArialMT"],
+        metrics: Metrics { units_per_em: 2048.0, ascent: 1854.0, descent: 434.0, line_gap: 67.0, average_width: 904.0 },
+    },
+    LocalFont {
+        key: "roboto",
+        name: "Roboto",
+        local: &["Roboto", "Roboto-Regular"],
+        metrics: Metrics { units_per_em: 2048.0, ascent: 1900.0, descent: 500.0, line_gap: 0.0, average_width: 911.0 },
+    },
+    LocalFont {
+        key: "times-new-roman",
+        name: "Times New Roman",
+        local: &["Times New Roman", "TimesNewRomanPSMT"],
+        metrics: Metrics { units_per_em: 2048.0, ascent: 1825.0, descent: 443.0, line_gap: 87.0, average_width: 819.0 },
+    },
+];
+
+/// English letter frequencies, the space included.
+const WEIGHTS: &[(char, f64)] = &[
+    ('a', 0.0668),
+    ('b', 0.0122),
+    ('c', 0.0228),
+    ('d', 0.0348),
+    ('e', 0.1039),
+    ('f', 0.0182),
+    ('g', 0.0165),
+    ('h', 0.0499),
+    ('i', 0.057),
+    ('j', 0.0013),
+    ('k', 0.0063),
+    ('l', 0.0329),
+    ('m', 0.0197),
+    ('n', 0.0552),
+    ('o', 0.0614),
+    ('p', 0.0158),
+    ('q', 0.0008),
+    ('r', 0.049),
+    ('s', 0.0518),
+    ('t', 0.0741),
+    ('u', 0.0226),
+    ('v', 0.008),
+    ('w', 0.0193),
+    ('x', 0.0012),
+    ('y', 0.0162),
+    ('z', 0.0006),
+    (' ', 0.1818),
+];
+
+/// `fallback=` values.
+pub fn parse(spec: &str) -> Result<Vec<&'static LocalFont>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .map(|f| {
+            LOCAL_FONTS.iter().find(|l| l.key.eq_ignore_ascii_case(f)).ok_or_else(|| {
+                let keys: Vec<_> = LOCAL_FONTS.iter().map(|l| l.key).collect();
+                format!("fallback '{f}' must be one of: {}", keys.join(", "))
+            })
+        })
+        .collect()
+}
+
+fn metrics(data: &[u8]) -> Option<Metrics> {
+    let face = Face::parse(data, 0).ok()?;
+    let mut width = 0.0;
+    let mut weight = 0.0;
+    for &(c, w) in WEIGHTS {
+        if let Some(advance) = face.glyph_index(c).and_then(|g| face.glyph_hor_advance(g)) {
+            width += advance as f64 * w;
+            weight += w;
+        }
+    }
+    (weight > 0.5).then(|| Metrics {
+        units_per_em: face.units_per_em() as f64,
+        ascent: face.ascender() as f64,
+        descent: -(face.descender() as f64),
+        line_gap: face.line_gap() as f64,
+        average_width: width / weight,
+    })
+}
+
+/// The fallback faces for a family whose binary is `data`, one per local
+/// font; none when its metrics cannot be read.
+pub fn faces(family: &str, data: &[u8], locals: &[&LocalFont]) -> Vec<String> {
+    let Some(font) = metrics(data) else { return Vec::new() };
+    locals
+        .iter()
+        .map(|local| {
+            let fallback = local.metrics;
+            let size = (font.average_width / font.units_per_em) / (fallback.average_width / fallback.units_per_em);
+            let percent = |v: f64| format!("{:.2}%", v / (font.units_per_em * size) * 100.0);
+            let src: Vec<String> = local.local.iter().map(|n| format!("local(\"{n}\")")).collect();
+            format!(
+                "@font-face {{\n  font-family: \"{family} Fallback {}\";\n  src: {};\n  size-adjust: {:.2}%;\n  \
+                 ascent-override: {};\n  descent-override: {};\n  line-gap-override: {};\n}}\n",
+                local.name,
+                src.join(", "),
+                size * 100.0,
+                percent(font.ascent),
+                percent(font.descent),
+                percent(font.line_gap),
+            )
+        })
+        .collect()
+}
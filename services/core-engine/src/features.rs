@@ -0,0 +1,293 @@
+//! Rendered OpenType feature demos.
+//!
+//! For each GSUB/GPOS feature in a font, a representative string is shaped
+//! with the feature off and on and both results are rasterized, so the
+//! specimen page and catalog UI can show what `liga`, `ss01` or `tnum`
+//! actually does. Features whose sample strings shape identically either
+//! way are still listed, with `changes: false`.
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use base64::Engine;
+use rustybuzz::{ttf_parser::Tag, Feature, UnicodeBuffer};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+
+use crate::{
+    cancel::CancelToken,
+    isolation, layout,
+    sfnt::Font,
+    spool,
+    sprite::{self, ImageFormat},
+    AppState,
+};
+
+const MAX_FEATURES: usize = 64;
+
+/// Sample strings for well-known features.
+const SAMPLES: &[(&str, &str)] = &[
+    ("liga", "fi fl ffi ffl"),
+    ("dlig", "ct st sp"),
+    ("hlig", "ct st"),
+    ("calt", "-> => != === www"),
+    ("kern", "AVATAR To Wa Ty"),
+    ("smcp", "Small Caps"),
+    ("c2sc", "SMALL CAPS"),
+    ("case", "(H-[O]) ¿Á?"),
+    ("tnum", "1111 2017"),
+    ("pnum", "1111 2017"),
+    ("onum", "0123456789"),
+    ("lnum", "0123456789"),
+    ("zero", "0O 100"),
+    ("frac", "1/2 3/4 7/8"),
+    ("sups", "x2 n3"),
+    ("subs", "H2O CO2"),
+    ("sinf", "H2O"),
+    ("ordn", "1a 2o No"),
+    ("swsh", "Quest Royal"),
+    ("salt", "ag &"),
+    ("titl", "TITLE"),
+];
+
+/// Tried in order for features without a specific sample (stylistic sets,
+/// character variants) until one visibly changes.
+const GENERIC_SAMPLES: &[&str] = &[
+    "Handgloves 0123456789 &?!",
+    "abcdefghijklmnopqrstuvwxyz",
+    "ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+];
+
+/// Shaping plumbing (composition, positioning, script forms) rather than
+/// something to demo.
+const SKIP: &[&str] = &[
+    "aalt", "abvm", "blwm", "ccmp", "curs", "dist", "fina", "init", "isol", "locl", "mark", "medi", "mkmk",
+    "rclt", "rlig", "rvrn",
+];
+
+#[derive(Debug, Deserialize)]
+pub struct DemoQuery {
+    /// Comma-separated feature tags; every demo-able feature when absent.
+    features: Option<String>,
+    /// Overrides the sample string for every feature.
+    text: Option<String>,
+    #[serde(default = "default_size")]
+    size: u16,
+    #[serde(default)]
+    format: ImageFormat,
+}
+
+fn default_size() -> u16 {
+    48
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FeatureDemo {
+    tag: String,
+    sample: String,
+    /// Whether the feature changed any glyph or position in `sample`.
+    changes: bool,
+    before: String,
+    after: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeatureDemos {
+    size: u16,
+    format: ImageFormat,
+    demos: Vec<FeatureDemo>,
+    /// Requested tags the font does not have.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unsupported: Vec<String>,
+}
+
+/// Every feature tag in the font's GSUB and GPOS feature lists.
+fn font_features(font: &Font) -> Vec<String> {
+    let mut tags: Vec<String> = [b"GSUB", b"GPOS"]
+        .into_iter()
+        .filter_map(|t| font.table(t))
+        .filter_map(layout::feature_tags)
+        .flatten()
+        .map(|t| String::from_utf8_lossy(&t).into_owned())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+struct Renderer<'a> {
+    shaper: rustybuzz::Face<'a>,
+    raster: fontdue::Font,
+    px: f32,
+    format: ImageFormat,
+}
+
+/// Shaped glyphs as (glyph, x advance, x offset, y offset).
+type Shaped = Vec<(u32, i32, i32, i32)>;
+
+impl<'a> Renderer<'a> {
+    fn new(data: &'a [u8], px: f32, format: ImageFormat) -> Result<Self, String> {
+        Ok(Self {
+            shaper: rustybuzz::Face::from_slice(data, 0).ok_or("font cannot be shaped")?,
+            raster: fontdue::Font::from_bytes(data, fontdue::FontSettings::default())
+                .map_err(|e| format!("font cannot be rasterized: {e}"))?,
+            px,
+            format,
+        })
+    }
+
+    fn shape(&self, text: &str, tag: &str, on: bool) -> Shaped {
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(text);
+        let feature = Feature::new(Tag::from_bytes_lossy(tag.as_bytes()), on as u32, ..);
+        let glyphs = rustybuzz::shape(&self.shaper, &[feature], buffer);
+        glyphs
+            .glyph_infos()
+            .iter()
+            .zip(glyphs.glyph_positions())
+            .map(|(i, p)| (i.glyph_id, p.x_advance, p.x_offset, p.y_offset))
+            .collect()
+    }
+
+    /// Draws shaped glyphs black on transparent and returns a data: URI.
+    fn draw(&self, shaped: &Shaped) -> Result<String, String> {
+        let scale = self.px / self.shaper.units_per_em().max(1) as f32;
+        let lines = self.raster.horizontal_line_metrics(self.px).ok_or("font has no horizontal metrics")?;
+        let pad = (self.px / 8.0).ceil() as i32;
+        let baseline = pad + lines.ascent.ceil() as i32;
+        let height = (baseline + (-lines.descent).ceil() as i32 + pad).max(1) as u32;
+        let advance: i32 = shaped.iter().map(|g| g.1).sum();
+        let width = ((advance as f32 * scale).ceil() as i32 + 2 * pad).max(1) as u32;
+        let mut pixels = vec![0u8; (width * height * 4) as usize];
+
+        let mut pen = 0i32;
+        for &(glyph, x_advance, x_offset, y_offset) in shaped {
+            let (m, coverage) = self.raster.rasterize_indexed(glyph as u16, self.px);
+            let x0 = pad + ((pen + x_offset) as f32 * scale).round() as i32 + m.xmin;
+            let y0 = baseline - (y_offset as f32 * scale).round() as i32 - m.ymin - m.height as i32;
+            for (i, &c) in coverage.iter().enumerate() {
+                let (x, y) = (x0 + (i % m.width.max(1)) as i32, y0 + (i / m.width.max(1)) as i32);
+                if c == 0 || x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                    continue;
+                }
+                let alpha = &mut pixels[4 * (y as u32 * width + x as u32) as usize + 3];
+                *alpha = alpha.saturating_add(c);
+            }
+            pen += x_advance;
+        }
+        let encoded = sprite::encode(self.format, &pixels, width, height)?;
+        let mime = match self.format {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Webp => "image/webp",
+        };
+        Ok(format!("data:{mime};base64,{}", base64::engine::general_purpose::STANDARD.encode(encoded)))
+    }
+
+    fn demo(&self, tag: &str, text: Option<&str>) -> Result<FeatureDemo, String> {
+        let candidates: Vec<&str> = match text {
+            Some(t) => vec![t],
+            None => SAMPLES
+                .iter()
+                .filter(|(t, _)| *t == tag)
+                .map(|(_, s)| *s)
+                .chain(GENERIC_SAMPLES.iter().copied())
+                .collect(),
+        };
+        let shaped = candidates.iter().map(|s| (s, self.shape(s, tag, false), self.shape(s, tag, true)));
+        let mut chosen = None;
+        for (sample, off, on) in shaped {
+            let changes = off != on;
+            if changes || chosen.is_none() {
+                chosen = Some((sample, off, on, changes));
+            }
+            if changes {
+                break;
+            }
+        }
+        let (sample, off, on, changes) = chosen.ok_or("no sample text")?;
+        Ok(FeatureDemo {
+            tag: tag.to_string(),
+            sample: sample.to_string(),
+            changes,
+            before: self.draw(&off)?,
+            after: self.draw(&on)?,
+        })
+    }
+}
+
+/// Picks the features and renders them in the parse worker. The inner `Err`
+/// is a request asking for too many.
+#[derive(Serialize, Deserialize)]
+pub struct Demos {
+    features: Option<String>,
+    text: Option<String>,
+    size: u16,
+    format: ImageFormat,
+}
+
+impl isolation::Task for Demos {
+    const NAME: &'static str = "demos";
+    /// The demos and the requested features the font lacks.
+    type Output = Result<(Vec<FeatureDemo>, Vec<String>), String>;
+
+    fn run(self, data: Vec<u8>, token: &CancelToken) -> Result<Self::Output, String> {
+        let available = font_features(&Font::parse(&data)?);
+        let (tags, unsupported): (Vec<String>, Vec<String>) = match &self.features {
+            Some(list) => list
+                .split(',')
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .partition(|t| available.contains(t)),
+            // Registered tags are lowercase; uppercase ones are private.
+            None => (
+                available
+                    .into_iter()
+                    .filter(|t| !SKIP.contains(&t.as_str()))
+                    .filter(|t| t.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit()))
+                    .collect(),
+                Vec::new(),
+            ),
+        };
+        if tags.len() > MAX_FEATURES {
+            return Ok(Err(format!("at most {MAX_FEATURES} features per request")));
+        }
+        let renderer = Renderer::new(&data, self.size as f32, self.format)?;
+        let mut demos = Vec::with_capacity(tags.len());
+        for tag in &tags {
+            token.check().map_err(|_| format!("{} was cancelled", Self::NAME))?;
+            demos.push(renderer.demo(tag, self.text.as_deref())?);
+        }
+        Ok(Ok((demos, unsupported)))
+    }
+}
+
+/// Before/after renderings for the font's features (or `?features=`).
+pub async fn demos(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<DemoQuery>,
+    body: Body,
+) -> Result<Json<FeatureDemos>, (StatusCode, String)> {
+    state.flags.ensure("demos", &headers)?;
+    state.flags.ensure("render", &headers)?;
+    if !(12..=128).contains(&query.size) {
+        return Err((StatusCode::BAD_REQUEST, "size must be 12-128".to_string()));
+    }
+    let (data, _) = spool::receive_font(body).await?;
+    let task = Demos { features: query.features, text: query.text, size: query.size, format: query.format };
+    let (demos, unsupported) = isolation::run(&state, task, data)
+        .await?
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    info!(
+        demos = demos.len(),
+        changing = demos.iter().filter(|d| d.changes).count(),
+        "feature demos rendered"
+    );
+    Ok(Json(FeatureDemos { size: query.size, format: query.format, demos, unsupported }))
+}
@@ -0,0 +1,111 @@
+//! Runtime feature flags for expensive capabilities.
+//!
+//! Every capability is on unless switched off globally or for a tenant
+//! (the `X-Font-Tenant` header set by the gateway). Flags start from
+//! `FEATURE_FLAGS` (e.g. `subset=off,analyze=on`) and can be flipped at
+//! runtime through the admin API to mitigate incidents without a redeploy.
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{FlagSet, FlagUpdate};
+use serde_json::json;
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+use crate::{audit, extract::ApiJson, AppState};
+
+/// Capabilities that can be toggled. `render` covers every endpoint that
+/// rasterizes glyphs (waterfalls, feature demos, sprite sheets), on top of
+/// their own switch; `crawl` covers fetching pages for `subset-from-url`.
+pub const CAPABILITIES: &[&str] = &["compress", "subset", "analyze", "instances", "sprite", "demos", "render", "crawl"];
+
+#[derive(Default)]
+pub struct FeatureFlags {
+    set: RwLock<FlagSet>,
+}
+
+impl FeatureFlags {
+    pub fn from_env() -> Self {
+        let flags = Self::default();
+        let spec = std::env::var("FEATURE_FLAGS").unwrap_or_default();
+        for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match item.split_once('=') {
+                Some((name, value)) if CAPABILITIES.contains(&name) => {
+                    let enabled = !matches!(value, "off" | "false" | "0");
+                    flags.set.write().unwrap().global.insert(name.to_string(), enabled);
+                }
+                _ => warn!("ignoring unknown FEATURE_FLAGS entry '{item}'"),
+            }
+        }
+        flags
+    }
+
+    pub fn is_enabled(&self, capability: &str, tenant: Option<&str>) -> bool {
+        let set = self.set.read().unwrap();
+        tenant
+            .and_then(|t| set.tenants.get(t))
+            .and_then(|m| m.get(capability))
+            .or_else(|| set.global.get(capability))
+            .copied()
+            .unwrap_or(true)
+    }
+
+    /// Rejects the request with `503` when `capability` is switched off for
+    /// the calling tenant.
+    pub fn ensure(&self, capability: &str, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+        let tenant = headers.get("x-font-tenant").and_then(|v| v.to_str().ok());
+        if self.is_enabled(capability, tenant) {
+            Ok(())
+        } else {
+            Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("{capability} is temporarily disabled"),
+            ))
+        }
+    }
+}
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<FlagSet>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    Ok(Json(state.flags.set.read().unwrap().clone()))
+}
+
+pub async fn update(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(capability): Path<String>,
+    ApiJson(update): ApiJson<FlagUpdate>,
+) -> Result<Json<FlagSet>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    if !CAPABILITIES.contains(&capability.as_str()) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("unknown capability '{capability}'; valid: {}", CAPABILITIES.join(", ")),
+        ));
+    }
+    let (before, set) = {
+        let mut set = state.flags.set.write().unwrap();
+        let before = set.clone();
+        match &update.tenant {
+            Some(tenant) => {
+                set.tenants
+                    .entry(tenant.clone())
+                    .or_default()
+                    .insert(capability.clone(), update.enabled);
+            }
+            None => {
+                set.global.insert(capability.clone(), update.enabled);
+            }
+        }
+        (before, set.clone())
+    };
+    audit::record(&state, &headers, "flag.update", &capability, json!(before), json!(set)).await;
+    info!(capability = %capability, tenant = ?update.tenant, enabled = update.enabled, "feature flag updated");
+    Ok(Json(set))
+}
@@ -0,0 +1,144 @@
+//! `POST /api/v1/font/compare-formats`: one font encoded as several formats
+//! at several qualities, to weigh which to ship.
+//!
+//! Every combination is really encoded, as `/api/v1/font/compress` would
+//! (with `strip_hints` applied first when asked), but nothing is stored.
+//! WOFF2 and WOFF are encoded once per distinct Brotli or zlib level the
+//! qualities map to; `ttf`, `otf` and `eot` once, as quality does not change
+//! them. Results come smallest first, with the encoding time, so the size
+//! gained by a higher quality can be set against its cost.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{CompareFormatsRequest, CompareFormatsResponse, FormatSize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::Instant,
+};
+use tracing::info;
+
+use crate::{cancel, compress, duplicates, extract::ApiJson, AppState};
+
+const FORMATS: &[&str] = &["woff2", "woff", "ttf", "otf", "eot"];
+const DEFAULT_QUALITIES: &[u8] = &[25, 50, 75, 100];
+const MAX_QUALITIES: usize = 12;
+
+fn bad_request(message: impl Into<String>) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message.into())
+}
+
+fn validate(req: &CompareFormatsRequest) -> Result<(), (StatusCode, String)> {
+    if let Some(format) = req.formats.iter().find(|f| !FORMATS.contains(&f.as_str())) {
+        return Err(bad_request(format!("unsupported format '{format}'; valid: {}", FORMATS.join(", "))));
+    }
+    if req.qualities.iter().any(|&q| q > 100) {
+        return Err(bad_request("qualities must be 0-100"));
+    }
+    if req.qualities.len() > MAX_QUALITIES {
+        return Err(bad_request(format!("at most {MAX_QUALITIES} qualities")));
+    }
+    Ok(())
+}
+
+/// The encoder level `quality` maps to in `format`; `None` where quality
+/// has no effect.
+fn level(format: &str, quality: u8) -> Option<u32> {
+    match format {
+        "woff2" => Some(compress::brotli_level(quality) as u32),
+        "woff" => Some(compress::zlib_level(quality)),
+        _ => None,
+    }
+}
+
+pub async fn compare(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(mut req): ApiJson<CompareFormatsRequest>,
+) -> Result<Json<CompareFormatsResponse>, (StatusCode, String)> {
+    state.flags.ensure("compress", &headers)?;
+    state.quotas.charge_operation(&headers)?;
+    validate(&req)?;
+    let (font_name, upload) = state.uploads.resolve(&headers, req.font_id.as_deref(), &req.font_name).await?;
+    req.font_name = font_name;
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &req.font_name)?;
+    if req.qualities.is_empty() {
+        req.qualities = DEFAULT_QUALITIES.to_vec();
+    }
+
+    let uploaded = match &upload {
+        Some(upload) => Some(state.uploads.read(upload).await?),
+        None => None,
+    };
+    let (job_state, job_req) = (Arc::clone(&state), req.clone());
+    let (original_bytes, mut results, unsupported) = cancel::run(&state.jobs, "compare-formats", move |token| {
+        let data = match uploaded {
+            Some(data) => data,
+            None => match duplicates::catalog_binary(&job_state, &job_req.font_name) {
+                Ok(data) => data,
+                Err(e) => return Ok(Err(e)),
+            },
+        };
+        let mut font = match compress::load(&data) {
+            Ok(font) => font,
+            Err(e) => return Ok(Err(e)),
+        };
+        if job_req.strip_hints {
+            if let Err(e) = compress::strip_hints(&mut font) {
+                return Ok(Err(e));
+            }
+        }
+        let formats: Vec<String> = match &job_req.formats {
+            formats if formats.is_empty() => {
+                let raw = if font.table(b"CFF ").is_some() || font.table(b"CFF2").is_some() { "otf" } else { "ttf" };
+                ["woff2", "woff", raw].map(String::from).to_vec()
+            }
+            formats => formats.iter().cloned().collect::<BTreeSet<_>>().into_iter().collect(),
+        };
+        let (mut results, mut unsupported) = (Vec::new(), BTreeMap::new());
+        for format in formats {
+            // One encode per distinct level, reported at the lowest quality
+            // reaching it.
+            let mut levels = BTreeMap::new();
+            for &quality in &job_req.qualities {
+                levels.entry(level(&format, quality)).or_insert(quality);
+            }
+            for (level, quality) in levels {
+                token.check()?;
+                let started = Instant::now();
+                match compress::encode(&font, &format, quality) {
+                    Ok(encoded) => results.push(FormatSize {
+                        format: format.clone(),
+                        quality: level.map(|_| quality),
+                        level,
+                        bytes: encoded.len(),
+                        size_kb: encoded.len() as f64 / 1024.0,
+                        ratio: data.len() as f64 / encoded.len().max(1) as f64,
+                        encode_ms: started.elapsed().as_millis() as u64,
+                    }),
+                    Err(e) => {
+                        unsupported.insert(format.clone(), e);
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(Ok((data.len(), results, unsupported)))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    results.sort_by_key(|r| (r.bytes, r.encode_ms));
+
+    let smallest = results.first().map(|r| r.format.as_str());
+    info!(font = %req.font_name, results = results.len(), smallest = ?smallest, "format comparison");
+    Ok(Json(CompareFormatsResponse {
+        font_name: req.font_name,
+        original_bytes,
+        strip_hints: req.strip_hints,
+        results,
+        unsupported,
+    }))
+}
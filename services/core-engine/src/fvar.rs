@@ -0,0 +1,170 @@
+//! Variable font axes and named instances (`fvar`), and localizing their
+//! names.
+//!
+//! `POST /api/v1/font/localize-names?language=ja&axis.wght=太さ&instance.Bold=太字`
+//! takes a raw font and writes a Windows Unicode `name` record in the given
+//! language for each listed axis (by tag) and named instance (by its English
+//! subfamily name). Only the `name` table changes; every other table is
+//! copied through untouched.
+
+use axum::{
+    body::Body,
+    extract::Query,
+    http::{header, HeaderName, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tracing::info;
+
+use crate::{
+    name::{self, NameTable},
+    render,
+    sfnt::{be_u16, Font},
+    spool,
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Axis {
+    pub tag: String,
+    pub min: f64,
+    pub default: f64,
+    pub max: f64,
+    pub hidden: bool,
+    pub name_id: u16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Instance {
+    pub subfamily_name_id: u16,
+    pub postscript_name_id: Option<u16>,
+    pub coordinates: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Fvar {
+    pub axes: Vec<Axis>,
+    pub instances: Vec<Instance>,
+}
+
+fn fixed(data: &[u8], at: usize) -> Option<f64> {
+    let raw = data.get(at..at + 4)?;
+    Some(i32::from_be_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64 / 65536.0)
+}
+
+impl Fvar {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let truncated = || "fvar table truncated".to_string();
+        let axes_offset = be_u16(data, 4).ok_or_else(truncated)? as usize;
+        let axis_count = be_u16(data, 8).ok_or_else(truncated)? as usize;
+        let axis_size = be_u16(data, 10).ok_or_else(truncated)? as usize;
+        let instance_count = be_u16(data, 12).ok_or_else(truncated)? as usize;
+        let instance_size = be_u16(data, 14).ok_or_else(truncated)? as usize;
+        if axis_size < 20 || instance_size < 4 + 4 * axis_count {
+            return Err("fvar record sizes are inconsistent".to_string());
+        }
+
+        let mut axes = Vec::with_capacity(axis_count);
+        for i in 0..axis_count {
+            let at = axes_offset + i * axis_size;
+            let tag = data.get(at..at + 4).ok_or_else(truncated)?;
+            axes.push(Axis {
+                tag: String::from_utf8_lossy(tag).trim_end().to_string(),
+                min: fixed(data, at + 4).ok_or_else(truncated)?,
+                default: fixed(data, at + 8).ok_or_else(truncated)?,
+                max: fixed(data, at + 12).ok_or_else(truncated)?,
+                hidden: be_u16(data, at + 16).ok_or_else(truncated)? & 1 != 0,
+                name_id: be_u16(data, at + 18).ok_or_else(truncated)?,
+            });
+        }
+        let instances_offset = axes_offset + axis_count * axis_size;
+        let mut instances = Vec::with_capacity(instance_count);
+        for i in 0..instance_count {
+            let at = instances_offset + i * instance_size;
+            let coordinates = (0..axis_count)
+                .map(|a| fixed(data, at + 4 + 4 * a).ok_or_else(truncated))
+                .collect::<Result<_, _>>()?;
+            let ps_at = at + 4 + 4 * axis_count;
+            instances.push(Instance {
+                subfamily_name_id: be_u16(data, at).ok_or_else(truncated)?,
+                postscript_name_id: (instance_size >= 6 + 4 * axis_count)
+                    .then(|| be_u16(data, ps_at))
+                    .flatten()
+                    .filter(|&id| id != 0xFFFF),
+                coordinates,
+            });
+        }
+        Ok(Self { axes, instances })
+    }
+}
+
+/// Applies `axis.<tag>` / `instance.<name>` overrides; returns the number
+/// of records written.
+fn localize(font: &mut Font, language: u16, overrides: &BTreeMap<String, String>) -> Result<usize, String> {
+    let fvar = Fvar::parse(font.table(b"fvar").ok_or("font has no fvar table; not a variable font")?)?;
+    let mut names = NameTable::parse(font.table(b"name").ok_or("font has no name table")?)?;
+
+    let mut updates = Vec::new();
+    for (key, text) in overrides {
+        if text.trim().is_empty() {
+            return Err(format!("{key}: name must not be empty"));
+        }
+        let name_id = if let Some(tag) = key.strip_prefix("axis.") {
+            fvar.axes.iter().find(|a| a.tag == tag).map(|a| a.name_id).ok_or_else(|| {
+                let tags: Vec<&str> = fvar.axes.iter().map(|a| a.tag.as_str()).collect();
+                format!("no axis '{tag}'; font has: {}", tags.join(", "))
+            })?
+        } else if let Some(wanted) = key.strip_prefix("instance.") {
+            fvar.instances
+                .iter()
+                .map(|i| i.subfamily_name_id)
+                .find(|&id| names.get(id).as_deref() == Some(wanted))
+                .ok_or_else(|| {
+                    let known: Vec<String> =
+                        fvar.instances.iter().filter_map(|i| names.get(i.subfamily_name_id)).collect();
+                    format!("no named instance '{wanted}'; font has: {}", known.join(", "))
+                })?
+        } else {
+            return Err(format!("unknown parameter '{key}'; use axis.<tag> or instance.<name>"));
+        };
+        updates.push((name_id, text.as_str()));
+    }
+    if updates.is_empty() {
+        return Err("nothing to localize; pass axis.<tag>=… or instance.<name>=…".to_string());
+    }
+    for &(name_id, text) in &updates {
+        names.set(name_id, language, text);
+    }
+    font.set_table(*b"name", names.to_bytes());
+    Ok(updates.len())
+}
+
+pub async fn localize_names(
+    Query(mut params): Query<BTreeMap<String, String>>,
+    Query(verify): Query<render::VerifyQuery>,
+    body: Body,
+) -> Result<Response, (StatusCode, String)> {
+    params.retain(|key, _| !key.starts_with("verify"));
+    let tag = params
+        .remove("language")
+        .ok_or((StatusCode::BAD_REQUEST, "language is required (e.g. ja)".to_string()))?;
+    let language = name::windows_language(&tag)
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("unsupported language '{tag}'")))?;
+
+    let (data, flavor) = spool::receive_font(body).await?;
+    let mut font = Font::parse(&data).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let updated = localize(&mut font, language, &params).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let out = font.to_bytes();
+    let checked = render::verify(&verify, &data, &out, None)?;
+
+    info!(language = %tag, updated, size = out.len(), "localized fvar names");
+    Ok((
+        checked,
+        [
+            (header::CONTENT_TYPE, flavor.media_type().to_string()),
+            (HeaderName::from_static("x-names-updated"), updated.to_string()),
+        ],
+        out,
+    )
+        .into_response())
+}
@@ -0,0 +1,332 @@
+//! Garbage collection of generated files.
+//!
+//! Generated files are kept until their class has a TTL in
+//! `ARTIFACT_TTL_SECS`, e.g. `subset=604800,recolor=86400`. The classes are
+//! `compress`, `subset`, `recolor`, `rename`, `line_metrics` and `slice`
+//! after the transform that made a file, and `other` for files stored before
+//! tracking began, which join the index when next downloaded. Storing a
+//! file, answering a request from its stored record and downloading it all
+//! count as access; accesses are merged every minute into an index kept in
+//! artifact storage (`.meta/access.json`) and shared by replicas. Every
+//! `GC_INTERVAL_SECS` (3600 by default) a sweep deletes the files whose
+//! class's TTL has passed since their last access, with their records and
+//! digests, and drops them from the subset cache. Version pins and the files
+//! of a current slicing are never collected.
+//!
+//! `POST /api/v1/admin/gc` (admin) sweeps at once (`?dry_run=true` only
+//! reports what would go); `GET` shows the TTLs, what is tracked per class
+//! and the last runs. Collected files and bytes are counted in
+//! `artifact_gc_deleted_total` and `artifact_gc_reclaimed_bytes_total` by
+//! class, and `artifact_tracked_bytes` gauges each class.
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+use crate::{audit, storage, AppState};
+
+/// Artifact classes, by the transform key that names them.
+pub const CLASSES: &[&str] = &["compress", "subset", "recolor", "rename", "line_metrics", "slice"];
+/// Files whose transform is unknown.
+pub const OTHER: &str = "other";
+
+const INDEX_KEY: &str = ".meta/access.json";
+const FLUSH_SECS: u64 = 60;
+const KEPT_RUNS: usize = 10;
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// A tracked file.
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+    class: String,
+    bytes: u64,
+    /// Unix seconds.
+    accessed: u64,
+}
+
+struct Touch {
+    class: Option<&'static str>,
+    bytes: Option<u64>,
+    at: u64,
+}
+
+/// Accesses not yet merged into the index.
+#[derive(Default)]
+pub struct Access {
+    touched: Mutex<HashMap<String, Touch>>,
+}
+
+impl Access {
+    /// Notes an access to the file at `key`; `bytes` adds an untracked file
+    /// to the index.
+    pub fn touch(&self, key: &str, class: Option<&'static str>, bytes: Option<u64>) {
+        // Version pins sit a level deeper and are never collected.
+        if key.matches('/').count() != 1 {
+            return;
+        }
+        let mut touched = self.touched.lock().unwrap();
+        let touch = touched.entry(key.to_string()).or_insert(Touch { class: None, bytes: None, at: 0 });
+        touch.class = class.or(touch.class);
+        touch.bytes = bytes.or(touch.bytes);
+        touch.at = now();
+    }
+
+    fn pending(&self) -> bool {
+        !self.touched.lock().unwrap().is_empty()
+    }
+
+    fn take(&self) -> HashMap<String, Touch> {
+        std::mem::take(&mut *self.touched.lock().unwrap())
+    }
+}
+
+/// `ARTIFACT_TTL_SECS` as class → seconds.
+pub fn parse_ttls(spec: &str) -> Result<BTreeMap<String, u64>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|item| {
+            let (class, secs) = item
+                .split_once('=')
+                .ok_or_else(|| format!("ARTIFACT_TTL_SECS: '{item}' should look like subset=604800"))?;
+            let class = class.trim();
+            if !CLASSES.contains(&class) && class != OTHER {
+                let known = CLASSES.join(", ");
+                return Err(format!("ARTIFACT_TTL_SECS: unknown class '{class}' (known: {known}, {OTHER})"));
+            }
+            let secs = secs.trim().parse().map_err(|_| format!("ARTIFACT_TTL_SECS: '{item}' needs whole seconds"))?;
+            Ok((class.to_string(), secs))
+        })
+        .collect()
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct Files {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// One sweep.
+#[derive(Clone, Serialize)]
+pub struct Run {
+    pub started_unix: u64,
+    /// `schedule` or `admin`.
+    pub trigger: &'static str,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+    pub tracked: usize,
+    /// Files deleted (or, in a dry run, that would be), by class.
+    pub collected: BTreeMap<String, Files>,
+    pub reclaimed_bytes: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+    pub duration_ms: u64,
+}
+
+#[derive(Serialize)]
+pub struct Status {
+    pub ttls: BTreeMap<String, u64>,
+    pub interval_secs: u64,
+    pub tracked: BTreeMap<String, Files>,
+    /// Newest first.
+    pub runs: Vec<Run>,
+}
+
+pub struct Gc {
+    ttls: BTreeMap<String, u64>,
+    interval: Duration,
+    runs: Mutex<VecDeque<Run>>,
+    /// One sweep at a time per replica.
+    sweeping: tokio::sync::Mutex<()>,
+}
+
+impl Gc {
+    pub fn from_env() -> Self {
+        let ttls = parse_ttls(&std::env::var("ARTIFACT_TTL_SECS").unwrap_or_default()).unwrap_or_else(|e| {
+            warn!("{e}; keeping every artifact");
+            BTreeMap::new()
+        });
+        let interval = std::env::var("GC_INTERVAL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(3600);
+        Self {
+            ttls,
+            interval: Duration::from_secs(interval),
+            runs: Mutex::default(),
+            sweeping: tokio::sync::Mutex::new(()),
+        }
+    }
+}
+
+async fn read_index(state: &AppState) -> Result<BTreeMap<String, Entry>, String> {
+    match state.artifacts.storage().get(INDEX_KEY).await? {
+        Some(raw) => serde_json::from_slice(&raw).map_err(|e| format!("{INDEX_KEY}: {e}")),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+async fn write_index(state: &AppState, index: &BTreeMap<String, Entry>) -> Result<(), String> {
+    let mut tracked: BTreeMap<&str, u64> = CLASSES.iter().chain([&OTHER]).map(|c| (*c, 0)).collect();
+    for entry in index.values() {
+        *tracked.entry(&entry.class).or_default() += entry.bytes;
+    }
+    for (class, bytes) in tracked {
+        metrics::gauge!("artifact_tracked_bytes", "class" => class.to_string()).set(bytes as f64);
+    }
+    let raw = serde_json::to_vec(index).map_err(|e| e.to_string())?;
+    state.artifacts.storage().put(INDEX_KEY, raw).await
+}
+
+/// Merges the accesses noted since the last flush into the index.
+async fn flush(state: &AppState) -> Result<BTreeMap<String, Entry>, String> {
+    let mut index = read_index(state).await?;
+    let touched = state.artifacts.access().take();
+    if touched.is_empty() {
+        return Ok(index);
+    }
+    for (key, touch) in touched {
+        match index.get_mut(&key) {
+            Some(entry) => {
+                entry.accessed = entry.accessed.max(touch.at);
+                if let Some(class) = touch.class {
+                    entry.class = class.to_string();
+                }
+                entry.bytes = touch.bytes.unwrap_or(entry.bytes);
+            }
+            None => {
+                if let Some(bytes) = touch.bytes {
+                    let class = touch.class.unwrap_or(OTHER).to_string();
+                    index.insert(key, Entry { class, bytes, accessed: touch.at });
+                }
+            }
+        }
+    }
+    write_index(state, &index).await?;
+    Ok(index)
+}
+
+async fn sweep(state: &AppState, trigger: &'static str, dry_run: bool) -> Result<Run, String> {
+    let _sweeping = state.gc.sweeping.lock().await;
+    let started = Instant::now();
+    let mut index = flush(state).await?;
+    let mut run = Run {
+        started_unix: now(),
+        trigger,
+        dry_run,
+        tracked: index.len(),
+        collected: BTreeMap::new(),
+        reclaimed_bytes: 0,
+        errors: Vec::new(),
+        duration_ms: 0,
+    };
+    let sliced = state.slices.keys();
+    let expired: Vec<String> = index
+        .iter()
+        .filter(|(key, entry)| {
+            let ttl = state.gc.ttls.get(&entry.class);
+            ttl.is_some_and(|ttl| run.started_unix.saturating_sub(entry.accessed) >= *ttl) && !sliced.contains(*key)
+        })
+        .map(|(key, _)| key.clone())
+        .collect();
+    let mut deleted = Vec::new();
+    for key in expired {
+        if !dry_run {
+            if let Err(e) = state.artifacts.delete(&key).await {
+                run.errors.push(format!("{key}: {e}"));
+                continue;
+            }
+        }
+        let entry = if dry_run { index[&key].clone() } else { index.remove(&key).expect("expired keys are indexed") };
+        let files = run.collected.entry(entry.class.clone()).or_default();
+        files.files += 1;
+        files.bytes += entry.bytes;
+        run.reclaimed_bytes += entry.bytes;
+        if !dry_run {
+            metrics::counter!("artifact_gc_deleted_total", "class" => entry.class.clone()).increment(1);
+            metrics::counter!("artifact_gc_reclaimed_bytes_total", "class" => entry.class).increment(entry.bytes);
+        }
+        deleted.push(key);
+    }
+    if !dry_run && !deleted.is_empty() {
+        state.subsets.evict(|key| deleted.binary_search_by(|d| d.as_str().cmp(key)).is_ok());
+        write_index(state, &index).await?;
+    }
+    run.duration_ms = started.elapsed().as_millis() as u64;
+    info!(trigger, dry_run, files = deleted.len(), bytes = run.reclaimed_bytes, "artifact GC finished");
+    let mut runs = state.gc.runs.lock().unwrap();
+    runs.push_front(run.clone());
+    runs.truncate(KEPT_RUNS);
+    Ok(run)
+}
+
+/// Flushes accesses every minute and sweeps every `GC_INTERVAL_SECS` while
+/// a class has a TTL, until the process exits.
+pub async fn run(state: Arc<AppState>) {
+    let mut tick = tokio::time::interval(Duration::from_secs(FLUSH_SECS));
+    tick.tick().await;
+    let mut last_sweep = Instant::now();
+    loop {
+        tick.tick().await;
+        let outcome = if !state.gc.ttls.is_empty() && last_sweep.elapsed() >= state.gc.interval {
+            last_sweep = Instant::now();
+            sweep(&state, "schedule", false).await.map(drop)
+        } else if state.artifacts.access().pending() {
+            flush(&state).await.map(drop)
+        } else {
+            Ok(())
+        };
+        if let Err(e) = outcome {
+            warn!("artifact GC: {e}");
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SweepQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+pub async fn collect(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<SweepQuery>,
+) -> Result<Json<Run>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let run = sweep(&state, "admin", query.dry_run).await.map_err(storage::error("collecting artifacts"))?;
+    if !query.dry_run {
+        audit::record(&state, &headers, "artifacts.gc", "artifacts", Value::Null, json!(run)).await;
+    }
+    Ok(Json(run))
+}
+
+pub async fn status(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Status>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let index = flush(&state).await.map_err(storage::error("reading artifact index"))?;
+    let mut tracked: BTreeMap<String, Files> = BTreeMap::new();
+    for entry in index.values() {
+        let files = tracked.entry(entry.class.clone()).or_default();
+        files.files += 1;
+        files.bytes += entry.bytes;
+    }
+    Ok(Json(Status {
+        ttls: state.gc.ttls.clone(),
+        interval_secs: state.gc.interval.as_secs(),
+        tracked,
+        runs: state.gc.runs.lock().unwrap().iter().cloned().collect(),
+    }))
+}
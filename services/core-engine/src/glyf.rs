@@ -0,0 +1,515 @@
+//! TrueType outlines: `loca` offsets into `glyf`, composite glyph
+//! references, and rewriting both.
+
+use std::collections::BTreeSet;
+
+use crate::sfnt::{be_u16, be_u32, Font};
+
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+const ON_CURVE_POINT: u8 = 0x01;
+const X_SHORT_VECTOR: u8 = 0x02;
+const Y_SHORT_VECTOR: u8 = 0x04;
+const REPEAT_FLAG: u8 = 0x08;
+const X_IS_SAME_OR_POSITIVE: u8 = 0x10;
+const Y_IS_SAME_OR_POSITIVE: u8 = 0x20;
+const OVERLAP_SIMPLE: u8 = 0x40;
+
+/// One component of a composite glyph; `at` is the offset of its flags.
+struct Component {
+    at: usize,
+    flags: u16,
+    glyph: u16,
+    end: usize,
+}
+
+fn component_records(data: &[u8]) -> Vec<Component> {
+    let mut out = Vec::new();
+    if data.len() < 10 || be_u16(data, 0).is_none_or(|contours| (contours as i16) >= 0) {
+        return out;
+    }
+    let mut at = 10;
+    while let (Some(flags), Some(glyph)) = (be_u16(data, at), be_u16(data, at + 2)) {
+        let mut end = at + 4 + if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+        end += if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            8
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            4
+        } else if flags & WE_HAVE_A_SCALE != 0 {
+            2
+        } else {
+            0
+        };
+        out.push(Component { at, flags, glyph, end: end.min(data.len()) });
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+        at = end;
+    }
+    out
+}
+
+/// Glyphs the composite glyph `data` references directly.
+pub fn components_of(data: &[u8]) -> Vec<u16> {
+    component_records(data).into_iter().map(|c| c.glyph).collect()
+}
+
+pub struct Glyf<'a> {
+    glyf: &'a [u8],
+    /// `numGlyphs + 1` offsets into `glyf`.
+    offsets: Vec<usize>,
+}
+
+impl<'a> Glyf<'a> {
+    /// `None` when the font has no TrueType outlines.
+    pub fn parse(font: &'a Font) -> Option<Result<Self, String>> {
+        let glyf = font.table(b"glyf")?;
+        Some((|| {
+            let loca = font.table(b"loca").ok_or("font has glyf but no loca table")?;
+            let long = be_u16(font.table(b"head").ok_or("font has no head table")?, 50).ok_or("head table truncated")? != 0;
+            let glyphs = be_u16(font.table(b"maxp").ok_or("font has no maxp table")?, 4).ok_or("maxp table truncated")?;
+            let offsets = (0..=glyphs as usize)
+                .map(|i| if long { be_u32(loca, 4 * i).map(|o| o as usize) } else { be_u16(loca, 2 * i).map(|o| 2 * o as usize) })
+                .collect::<Option<Vec<usize>>>()
+                .ok_or("loca table truncated")?;
+            if offsets.windows(2).any(|w| w[0] > w[1]) || offsets.last().is_some_and(|&end| end > glyf.len()) {
+                return Err("loca offsets are out of order or past the end of glyf".to_string());
+            }
+            Ok(Self { glyf, offsets })
+        })())
+    }
+
+    pub fn num_glyphs(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    pub fn data(&self, glyph: u16) -> &'a [u8] {
+        let g = glyph as usize;
+        if g < self.num_glyphs() {
+            &self.glyf[self.offsets[g]..self.offsets[g + 1]]
+        } else {
+            &[]
+        }
+    }
+
+    /// Glyphs a composite glyph references directly.
+    pub fn components(&self, glyph: u16) -> Vec<u16> {
+        components_of(self.data(glyph))
+    }
+
+    /// Checks that `glyph`'s record stays within its bytes and that its
+    /// components exist.
+    pub fn check(&self, glyph: u16) -> Result<(), String> {
+        let data = self.data(glyph);
+        if data.is_empty() {
+            return Ok(());
+        }
+        if data.len() < 10 {
+            return Err("truncated header".to_string());
+        }
+        let contours = be_u16(data, 0).unwrap_or_default() as i16;
+        if contours < 0 {
+            let mut at = 10;
+            loop {
+                let (Some(flags), Some(component)) = (be_u16(data, at), be_u16(data, at + 2)) else {
+                    return Err("truncated component record".to_string());
+                };
+                if component as usize >= self.num_glyphs() {
+                    return Err(format!("component references missing glyph {component}"));
+                }
+                at += 4 + if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+                at += if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                    8
+                } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                    4
+                } else if flags & WE_HAVE_A_SCALE != 0 {
+                    2
+                } else {
+                    0
+                };
+                if at > data.len() {
+                    return Err("truncated component record".to_string());
+                }
+                if flags & MORE_COMPONENTS == 0 {
+                    return Ok(());
+                }
+            }
+        }
+        let ends = (0..contours as usize)
+            .map(|i| be_u16(data, 10 + 2 * i))
+            .collect::<Option<Vec<u16>>>()
+            .ok_or("truncated endPtsOfContours")?;
+        if ends.windows(2).any(|w| w[0] >= w[1]) {
+            return Err("endPtsOfContours are not increasing".to_string());
+        }
+        let points = ends.last().map_or(0, |&e| e as usize + 1);
+        let at = 10 + 2 * contours as usize;
+        let instructions = be_u16(data, at).ok_or("truncated instruction length")? as usize;
+        let mut at = at + 2 + instructions;
+        // Flags, with repeats, then the coordinate bytes they imply.
+        let (mut seen, mut coordinates) = (0, 0);
+        while seen < points {
+            let flag = *data.get(at).ok_or("truncated flags")?;
+            at += 1;
+            let repeat = if flag & 0x08 != 0 {
+                at += 1;
+                *data.get(at - 1).ok_or("truncated flags")? as usize + 1
+            } else {
+                1
+            };
+            let x = if flag & 0x02 != 0 { 1 } else if flag & 0x10 != 0 { 0 } else { 2 };
+            let y = if flag & 0x04 != 0 { 1 } else if flag & 0x20 != 0 { 0 } else { 2 };
+            coordinates += (x + y) * repeat;
+            seen += repeat;
+        }
+        if seen > points {
+            return Err(format!("flags describe {seen} points but contours end at {points}"));
+        }
+        if at + coordinates > data.len() {
+            return Err("coordinates run past the end of the glyph".to_string());
+        }
+        Ok(())
+    }
+
+    /// `glyphs` plus every glyph their composites reference, transitively.
+    pub fn closure(&self, glyphs: &BTreeSet<u16>) -> BTreeSet<u16> {
+        let mut out = glyphs.clone();
+        let mut pending: Vec<u16> = glyphs.iter().copied().collect();
+        while let Some(g) = pending.pop() {
+            for c in self.components(g) {
+                if out.insert(c) {
+                    pending.push(c);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// A point of a simple glyph, in font units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+    pub on_curve: bool,
+}
+
+/// A simple glyph, decoded.
+#[derive(Debug, Clone)]
+pub struct Outline {
+    /// As stored: xMin, yMin, xMax, yMax.
+    pub bbox: [i16; 4],
+    /// Index of each contour's last point.
+    pub ends: Vec<u16>,
+    pub instructions: Vec<u8>,
+    pub points: Vec<Point>,
+    pub overlap: bool,
+}
+
+/// Absolute coordinates from one axis' deltas.
+fn coordinates(data: &[u8], at: &mut usize, flags: &[u8], short: u8, same: u8) -> Option<Vec<i32>> {
+    let mut value = 0;
+    let mut out = Vec::with_capacity(flags.len());
+    for &flag in flags {
+        if flag & short != 0 {
+            let delta = *data.get(*at)? as i32;
+            *at += 1;
+            value += if flag & same != 0 { delta } else { -delta };
+        } else if flag & same == 0 {
+            value += be_u16(data, *at)? as i16 as i32;
+            *at += 2;
+        }
+        out.push(value);
+    }
+    Some(out)
+}
+
+/// One axis' delta: its flag bits and bytes.
+fn delta(delta: i32, short: u8, same: u8, out: &mut Vec<u8>) -> u8 {
+    match delta {
+        0 => same,
+        1..=255 => {
+            out.push(delta as u8);
+            short | same
+        }
+        -255..=-1 => {
+            out.push(-delta as u8);
+            short
+        }
+        _ => {
+            out.extend_from_slice(&(delta as i16).to_be_bytes());
+            0
+        }
+    }
+}
+
+impl Outline {
+    /// `None` for composite, empty or malformed glyphs.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let contours = be_u16(data, 0)? as i16;
+        if contours <= 0 {
+            return None;
+        }
+        let bbox = [2, 4, 6, 8].map(|at| be_u16(data, at).unwrap_or_default() as i16);
+        let ends = (0..contours as usize).map(|i| be_u16(data, 10 + 2 * i)).collect::<Option<Vec<u16>>>()?;
+        if ends.windows(2).any(|w| w[0] >= w[1]) {
+            return None;
+        }
+        let count = *ends.last()? as usize + 1;
+        let at = 10 + 2 * contours as usize;
+        let length = be_u16(data, at)? as usize;
+        let instructions = data.get(at + 2..at + 2 + length)?.to_vec();
+        let mut at = at + 2 + length;
+        let mut flags = Vec::with_capacity(count);
+        while flags.len() < count {
+            let flag = *data.get(at)?;
+            at += 1;
+            let repeat = if flag & REPEAT_FLAG != 0 {
+                at += 1;
+                *data.get(at - 1)? as usize + 1
+            } else {
+                1
+            };
+            flags.extend(std::iter::repeat_n(flag, repeat));
+        }
+        if flags.len() != count {
+            return None;
+        }
+        let xs = coordinates(data, &mut at, &flags, X_SHORT_VECTOR, X_IS_SAME_OR_POSITIVE)?;
+        let ys = coordinates(data, &mut at, &flags, Y_SHORT_VECTOR, Y_IS_SAME_OR_POSITIVE)?;
+        let points =
+            flags.iter().zip(xs).zip(ys).map(|((f, x), y)| Point { x, y, on_curve: f & ON_CURVE_POINT != 0 }).collect();
+        Some(Self { bbox, ends, instructions, points, overlap: flags[0] & OVERLAP_SIMPLE != 0 })
+    }
+
+    /// The bounding box of the points.
+    pub fn bounds(&self) -> [i16; 4] {
+        let xs = self.points.iter().map(|p| p.x);
+        let ys = self.points.iter().map(|p| p.y);
+        let (x_min, x_max) = (xs.clone().min().unwrap_or(0), xs.max().unwrap_or(0));
+        let (y_min, y_max) = (ys.clone().min().unwrap_or(0), ys.max().unwrap_or(0));
+        [x_min, y_min, x_max, y_max].map(|v| v as i16)
+    }
+
+    /// Encodes the glyph with the shortest coordinates and repeated flags
+    /// folded; the bounding box is written as stored in `bbox`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&(self.ends.len() as i16).to_be_bytes());
+        for v in self.bbox {
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        for end in &self.ends {
+            out.extend_from_slice(&end.to_be_bytes());
+        }
+        out.extend_from_slice(&(self.instructions.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.instructions);
+
+        let (mut flags, mut xs, mut ys) = (Vec::with_capacity(self.points.len()), Vec::new(), Vec::new());
+        let (mut x, mut y) = (0, 0);
+        for (i, p) in self.points.iter().enumerate() {
+            let mut flag = delta(p.x - x, X_SHORT_VECTOR, X_IS_SAME_OR_POSITIVE, &mut xs)
+                | delta(p.y - y, Y_SHORT_VECTOR, Y_IS_SAME_OR_POSITIVE, &mut ys);
+            if p.on_curve {
+                flag |= ON_CURVE_POINT;
+            }
+            if i == 0 && self.overlap {
+                flag |= OVERLAP_SIMPLE;
+            }
+            flags.push(flag);
+            (x, y) = (p.x, p.y);
+        }
+        // A run of three or more is cheaper as flag + repeat count.
+        let mut i = 0;
+        while i < flags.len() {
+            let run = flags[i..].iter().take(256).take_while(|&&f| f == flags[i]).count();
+            if run >= 3 {
+                out.extend_from_slice(&[flags[i] | REPEAT_FLAG, (run - 1) as u8]);
+                i += run;
+            } else {
+                out.push(flags[i]);
+                i += 1;
+            }
+        }
+        out.extend_from_slice(&xs);
+        out.extend_from_slice(&ys);
+        out
+    }
+}
+
+/// A composite glyph of `component` moved by `dx`, `dy`.
+pub fn offset_composite(component: u16, dx: i32, dy: i32, bbox: [i16; 4]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(18);
+    out.extend_from_slice(&(-1i16).to_be_bytes());
+    for v in bbox {
+        out.extend_from_slice(&v.to_be_bytes());
+    }
+    let bytes = (-128..=127).contains(&dx) && (-128..=127).contains(&dy);
+    let flags = if bytes { ARGS_ARE_XY_VALUES } else { ARGS_ARE_XY_VALUES | ARG_1_AND_2_ARE_WORDS };
+    out.extend_from_slice(&flags.to_be_bytes());
+    out.extend_from_slice(&component.to_be_bytes());
+    if bytes {
+        out.extend_from_slice(&[dx as i8 as u8, dy as i8 as u8]);
+    } else {
+        out.extend_from_slice(&(dx as i16).to_be_bytes());
+        out.extend_from_slice(&(dy as i16).to_be_bytes());
+    }
+    out
+}
+
+/// The components of a composite glyph with their offsets, when every one
+/// is only moved (no scale, no point matching).
+pub fn component_offsets(glyph: &[u8]) -> Option<Vec<(u16, i32, i32)>> {
+    let components = component_records(glyph);
+    if components.is_empty() {
+        return None;
+    }
+    components
+        .iter()
+        .map(|c| {
+            let transformed = WE_HAVE_A_SCALE | WE_HAVE_AN_X_AND_Y_SCALE | WE_HAVE_A_TWO_BY_TWO;
+            if c.flags & ARGS_ARE_XY_VALUES == 0 || c.flags & transformed != 0 {
+                return None;
+            }
+            let args = c.at + 4;
+            if c.flags & ARG_1_AND_2_ARE_WORDS != 0 {
+                let arg = |at| be_u16(glyph, at).map(|v| v as i16 as i32);
+                Some((c.glyph, arg(args)?, arg(args + 2)?))
+            } else {
+                Some((c.glyph, *glyph.get(args)? as i8 as i32, *glyph.get(args + 1)? as i8 as i32))
+            }
+        })
+        .collect()
+}
+
+/// `glyph` (one glyph's outline data) without its TrueType instructions.
+pub fn strip_instructions(glyph: &[u8]) -> Vec<u8> {
+    let Some(contours) = be_u16(glyph, 0).map(|c| c as i16) else {
+        return glyph.to_vec();
+    };
+    if contours >= 0 {
+        let at = 10 + 2 * contours as usize;
+        return match be_u16(glyph, at) {
+            Some(len) if at + 2 + len as usize <= glyph.len() => {
+                let mut out = glyph[..at].to_vec();
+                out.extend_from_slice(&[0, 0]);
+                out.extend_from_slice(&glyph[at + 2 + len as usize..]);
+                out
+            }
+            _ => glyph.to_vec(),
+        };
+    }
+    let components = component_records(glyph);
+    let Some(last) = components.last() else {
+        return glyph.to_vec();
+    };
+    let mut out = glyph[..last.end].to_vec();
+    for c in &components {
+        let flags = c.flags & !WE_HAVE_INSTRUCTIONS;
+        out[c.at..c.at + 2].copy_from_slice(&flags.to_be_bytes());
+    }
+    out
+}
+
+/// Replaces `glyf` with `glyphs` (indexed by glyph ID) and rebuilds `loca`,
+/// switching `head.indexToLocFormat` to long offsets when short ones no
+/// longer reach.
+pub fn rebuild(font: &mut Font, glyphs: &[Vec<u8>]) -> Result<(), String> {
+    let mut glyf = Vec::new();
+    let mut offsets = Vec::with_capacity(glyphs.len() + 1);
+    for g in glyphs {
+        offsets.push(glyf.len());
+        glyf.extend_from_slice(g);
+        glyf.resize(glyf.len().next_multiple_of(2), 0);
+    }
+    offsets.push(glyf.len());
+    let long = glyf.len() > 0x1FFFE;
+    let loca: Vec<u8> = if long {
+        offsets.iter().flat_map(|&o| (o as u32).to_be_bytes()).collect()
+    } else {
+        offsets.iter().flat_map(|&o| ((o / 2) as u16).to_be_bytes()).collect()
+    };
+    let mut head = font.table(b"head").ok_or("font has no head table")?.to_vec();
+    head.get_mut(50..52).ok_or("head table truncated")?.copy_from_slice(&(long as u16).to_be_bytes());
+    font.set_table(*b"head", head);
+    font.set_table(*b"glyf", glyf);
+    font.set_table(*b"loca", loca);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sfnt::Table;
+
+    /// A closed square of `size` units with a one-byte instruction program.
+    fn square(size: i32) -> Vec<u8> {
+        let corners = [(0, 0), (size, 0), (size, size), (0, size)];
+        let points = corners.into_iter().map(|(x, y)| Point { x, y, on_curve: true }).collect();
+        let s = size as i16;
+        Outline { bbox: [0, 0, s, s], ends: vec![3], instructions: vec![0xB0], points, overlap: false }.to_bytes()
+    }
+
+    /// `glyphs` as a font with `glyf`, `loca`, `head` and `maxp`.
+    fn font(glyphs: &[Vec<u8>]) -> Font {
+        let maxp = [&0x0000_5000u32.to_be_bytes()[..], &(glyphs.len() as u16).to_be_bytes()].concat();
+        let tables = vec![Table { tag: *b"head", data: vec![0; 54] }, Table { tag: *b"maxp", data: maxp }];
+        let mut font = Font::new(0x0001_0000, tables);
+        rebuild(&mut font, glyphs).unwrap();
+        font
+    }
+
+    #[test]
+    fn outlines_round_trip() {
+        let data = square(500);
+        let outline = Outline::parse(&data).unwrap();
+        assert_eq!(outline.points.len(), 4);
+        assert_eq!(outline.bounds(), [0, 0, 500, 500]);
+        assert_eq!(Outline::parse(&outline.to_bytes()).unwrap().points, outline.points);
+        let stripped = Outline::parse(&strip_instructions(&data)).unwrap();
+        assert!(stripped.instructions.is_empty());
+        assert_eq!(stripped.points, outline.points);
+    }
+
+    #[test]
+    fn closure_follows_nested_composites() {
+        let box_ = [0, 0, 500, 500];
+        let mut accent = offset_composite(1, 0, 100, box_);
+        // A second component: the base letter, unmoved.
+        accent[10..12].copy_from_slice(&(ARGS_ARE_XY_VALUES | MORE_COMPONENTS).to_be_bytes());
+        accent.extend_from_slice(&[0, ARGS_ARE_XY_VALUES as u8, 0, 2, 0, 0]);
+        let glyphs = [square(500), square(100), square(400), accent, offset_composite(3, 1000, 0, box_)];
+        let font = font(&glyphs);
+        let glyf = Glyf::parse(&font).unwrap().unwrap();
+        assert_eq!(glyf.num_glyphs(), 5);
+        assert_eq!(glyf.components(3), [1, 2]);
+        assert_eq!(component_offsets(glyf.data(4)), Some(vec![(3, 1000, 0)]));
+        assert_eq!(glyf.closure(&BTreeSet::from([4])), BTreeSet::from([1, 2, 3, 4]));
+        assert_eq!(glyf.closure(&BTreeSet::from([2])), BTreeSet::from([2]));
+        (0..5).for_each(|g| glyf.check(g).unwrap());
+    }
+
+    #[test]
+    fn rebuild_switches_to_long_offsets() {
+        let short = font(&[square(500), Vec::new(), square(100)]);
+        assert_eq!(be_u16(short.table(b"head").unwrap(), 50), Some(0));
+        assert_eq!(short.table(b"loca").unwrap().len(), 2 * 4);
+        let glyf = Glyf::parse(&short).unwrap().unwrap();
+        assert!(glyf.data(1).is_empty());
+        assert_eq!(Outline::parse(glyf.data(2)).unwrap().bounds(), [0, 0, 100, 100]);
+
+        let big = vec![0x55; 0x10000];
+        let long = font(&[square(500), big.clone(), big.clone(), square(100)]);
+        assert_eq!(be_u16(long.table(b"head").unwrap(), 50), Some(1));
+        assert_eq!(long.table(b"loca").unwrap().len(), 4 * 5);
+        let glyf = Glyf::parse(&long).unwrap().unwrap();
+        assert_eq!(glyf.data(2), &big[..]);
+        assert_eq!(Outline::parse(glyf.data(3)).unwrap().bounds(), [0, 0, 100, 100]);
+    }
+}
@@ -0,0 +1,239 @@
+//! Catalog import from the Google Fonts API.
+//!
+//! `POST /api/v1/admin/catalog/import/google?families=Inter,Roboto` looks
+//! each family up in the Developer API (`GOOGLE_FONTS_API_KEY`, at
+//! `GOOGLE_FONTS_API_URL`) and registers every variant, or only those in
+//! `?variants=regular,700italic`, as a catalog entry: `inter` for Regular,
+//! `inter-bold-italic` for 700 italic and so on. Each binary is downloaded
+//! (at most `GOOGLE_FONTS_MAX_BYTES`, default 32 MiB, within
+//! `GOOGLE_FONTS_TIMEOUT_SECS`, default 60), validated, and written to
+//! `CATALOG_FONT_DIR` as `<id>.ttf`, where every catalog binary is kept.
+//!
+//! Glyph count and unicode ranges are read from the binary. The license is
+//! the SPDX ID of the one its `name` table declares (OFL, Apache or Ubuntu
+//! Font License, the ones Google Fonts ships), with the copyright notice as
+//! `attribution`; a face declaring none of them is not imported. IDs already
+//! in the catalog are skipped, and a face colliding with another entry's
+//! name fails unless `?resolution=` says how to proceed, as in
+//! [`crate::catalog`]; either way the rest are imported.
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{CatalogImport, FontCatalogEntry};
+use reqwest::Url;
+use serde::Deserialize;
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tracing::{info, warn};
+
+use crate::{cancel, collision::ResolutionParams, duplicates, ingest, AppState};
+
+const DEFAULT_API_URL: &str = "https://www.googleapis.com/webfonts/v1/webfonts";
+/// Families one request imports.
+const MAX_FAMILIES: usize = 20;
+
+const WEIGHTS: &[(&str, &str)] = &[
+    ("100", "Thin"),
+    ("200", "ExtraLight"),
+    ("300", "Light"),
+    ("400", "Regular"),
+    ("500", "Medium"),
+    ("600", "SemiBold"),
+    ("700", "Bold"),
+    ("800", "ExtraBold"),
+    ("900", "Black"),
+];
+
+fn env_u64(k: &str, d: u64) -> u64 {
+    std::env::var(k).ok().and_then(|v| v.parse().ok()).unwrap_or(d)
+}
+
+pub struct GoogleFonts {
+    http: reqwest::Client,
+    api_url: String,
+    api_key: Option<String>,
+    max_bytes: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct Webfonts {
+    #[serde(default)]
+    items: Vec<Webfont>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Webfont {
+    family: String,
+    #[serde(default)]
+    variants: Vec<String>,
+    /// Variant to TTF URL.
+    #[serde(default)]
+    files: BTreeMap<String, String>,
+}
+
+impl GoogleFonts {
+    pub fn from_env() -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(env_u64("GOOGLE_FONTS_TIMEOUT_SECS", 60).max(1)))
+                .user_agent(concat!("font-engine/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .expect("failed to build HTTP client"),
+            api_url: std::env::var("GOOGLE_FONTS_API_URL")
+                .ok()
+                .filter(|u| !u.is_empty())
+                .unwrap_or_else(|| DEFAULT_API_URL.to_string()),
+            api_key: std::env::var("GOOGLE_FONTS_API_KEY").ok().filter(|k| !k.is_empty()),
+            max_bytes: env_u64("GOOGLE_FONTS_MAX_BYTES", 32 * 1024 * 1024) as usize,
+        }
+    }
+
+    async fn get(&self, url: Url) -> Result<Vec<u8>, String> {
+        let mut response = self.http.get(url.clone()).send().await.map_err(|e| format!("{url}: {e}"))?;
+        if !response.status().is_success() {
+            return Err(format!("{url}: {}", response.status()));
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| format!("{url}: {e}"))? {
+            body.extend_from_slice(&chunk);
+            if body.len() > self.max_bytes {
+                return Err(format!("{url}: larger than {} bytes", self.max_bytes));
+            }
+        }
+        Ok(body)
+    }
+
+    /// The API's listing of `family`.
+    async fn family(&self, key: &str, family: &str) -> Result<Webfont, String> {
+        let mut url = Url::parse(&self.api_url).map_err(|e| format!("GOOGLE_FONTS_API_URL: {e}"))?;
+        url.query_pairs_mut().append_pair("key", key).append_pair("family", family);
+        // The key is in the URL; keep it out of errors.
+        let body = self.get(url).await.map_err(|e| e.replace(key, "***"))?;
+        let listing: Webfonts =
+            serde_json::from_slice(&body).map_err(|e| format!("unexpected Google Fonts API response: {e}"))?;
+        listing
+            .items
+            .into_iter()
+            .find(|f| f.family.eq_ignore_ascii_case(family))
+            .ok_or_else(|| format!("'{family}' is not on Google Fonts"))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    families: String,
+    variants: Option<String>,
+    #[serde(flatten)]
+    resolution: ResolutionParams,
+}
+
+fn list(spec: &str) -> Vec<String> {
+    spec.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect()
+}
+
+/// `Bold Italic` for `700italic`; `None` for names the API does not use.
+fn variant_name(variant: &str) -> Option<String> {
+    let (weight, italic) = match variant {
+        "regular" => ("400", false),
+        "italic" => ("400", true),
+        v => v.strip_suffix("italic").map_or((v, false), |w| (w, true)),
+    };
+    let name = WEIGHTS.iter().find(|(w, _)| *w == weight)?.1;
+    Some(match (name, italic) {
+        ("Regular", true) => "Italic".to_string(),
+        (name, true) => format!("{name} Italic"),
+        (name, false) => name.to_string(),
+    })
+}
+
+/// Downloads, checks, stores and registers one face.
+async fn import_face(
+    state: &Arc<AppState>,
+    headers: &HeaderMap,
+    id: String,
+    family: &str,
+    variant: &str,
+    url: &str,
+    params: &ResolutionParams,
+) -> Result<FontCatalogEntry, String> {
+    let url = Url::parse(url).map_err(|e| format!("{url}: {e}"))?;
+    let data = state.google_fonts.get(url).await?;
+    let (sfnt, mut entry) = cancel::run(&state.jobs, "google-import", move |_| Ok(ingest::describe(&data)))
+        .await
+        .map_err(|(_, e)| e)??;
+    if entry.license.is_empty() {
+        return Err("declares no OFL, Apache or Ubuntu Font License in its name table".to_string());
+    }
+    (entry.id, entry.family, entry.variant) = (id, family.to_string(), variant.to_string());
+    ingest::register(state, Some(headers), entry, &sfnt, params).await
+}
+
+pub async fn import(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<ImportQuery>,
+) -> Result<Json<CatalogImport>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let families = list(&query.families);
+    if families.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "families is required, e.g. ?families=Inter,Roboto".to_string()));
+    }
+    if families.len() > MAX_FAMILIES {
+        return Err((StatusCode::BAD_REQUEST, format!("at most {MAX_FAMILIES} families per import")));
+    }
+    let wanted = query.variants.as_deref().map(list);
+    if let Some(v) = wanted.iter().flatten().find(|v| variant_name(v).is_none()) {
+        let message = format!("unknown variant '{v}'; use e.g. regular, italic, 700, 700italic");
+        return Err((StatusCode::BAD_REQUEST, message));
+    }
+    let key = state.google_fonts.api_key.clone().ok_or_else(|| {
+        (StatusCode::SERVICE_UNAVAILABLE, "Google Fonts import disabled; set GOOGLE_FONTS_API_KEY".to_string())
+    })?;
+    if duplicates::catalog_font_dir().is_none() {
+        let message = "CATALOG_FONT_DIR is not configured, so binaries cannot be stored";
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, message.to_string()));
+    }
+
+    let mut report = CatalogImport::default();
+    for requested in families {
+        let listing = match state.google_fonts.family(&key, &requested).await {
+            Ok(listing) => listing,
+            Err(e) => {
+                report.failed.insert(requested, e);
+                continue;
+            }
+        };
+        for api_variant in &listing.variants {
+            if wanted.as_ref().is_some_and(|w| !w.contains(api_variant)) {
+                continue;
+            }
+            let face = format!("{} {api_variant}", listing.family);
+            let (Some(variant), Some(url)) = (variant_name(api_variant), listing.files.get(api_variant)) else {
+                report.failed.insert(face, "no downloadable file".to_string());
+                continue;
+            };
+            let id = ingest::entry_id(&listing.family, &variant);
+            if state.catalog.read().unwrap().iter().any(|e| e.id == id) {
+                report.skipped.push(id);
+                continue;
+            }
+            match import_face(&state, &headers, id, &listing.family, &variant, url, &query.resolution).await {
+                Ok(entry) => report.imported.push(entry),
+                Err(e) => {
+                    warn!(face = %face, error = %e, "Google Fonts import failed");
+                    report.failed.insert(face, e);
+                }
+            }
+        }
+    }
+    info!(
+        imported = report.imported.len(),
+        skipped = report.skipped.len(),
+        failed = report.failed.len(),
+        "Google Fonts import"
+    );
+    Ok(Json(report))
+}
@@ -0,0 +1,987 @@
+//! GraphQL queries over the catalog.
+//!
+//! `POST /api/v1/graphql` takes `{"query", "variables", "operationName"}`
+//! and answers `{"data"}` or `{"errors"}`, so one request can fetch entries
+//! with their sibling variants and generated files, only the fields it
+//! selects. `GET` returns the schema ([`SCHEMA`]). Entries are those
+//! `GET /api/v1/font/catalog` would list for the same headers (tenant
+//! sandbox, preview channel).
+//!
+//! The executor is deliberately small: queries only (no mutations or
+//! subscriptions), with aliases, arguments, variables, fragments and
+//! `@include`/`@skip`, but no introspection beyond `__typename`, and keys in
+//! `data` come back sorted rather than in selection order.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    artifacts,
+    catalog::{self, ListQuery},
+    extract::ApiJson,
+    signing, tenants, AppState, FontCatalogEntry, ProcessingDefaults,
+};
+
+pub const SCHEMA: &str = "\
+type Query {
+  fonts(family: String, license: String, format: String, max_size_kb: Float): [Font!]!
+  font(id: String!): Font
+}
+
+type Font {
+  id: String!
+  family: String!
+  variant: String!
+  formats: [String!]!
+  size_kb: Float!
+  glyph_count: Int!
+  unicode_ranges: [String!]!
+  license: String!
+  foundry: String
+  postscript_name: String
+  private: Boolean!
+  \"Binary version, from 1.\"
+  version: Int!
+  \"Credit to write into generated CSS.\"
+  attribution: String
+  \"Owning tenant; absent for shared fonts.\"
+  tenant: String
+  defaults: Defaults
+  \"Other entries of the same family.\"
+  variants: [Font!]!
+  \"Files generated from this font.\"
+  artifacts: [Artifact!]!
+}
+
+type Defaults {
+  quality: Int
+  strip_hints: Boolean
+  subset_preset: String
+}
+
+type Artifact {
+  \"CDN path.\"
+  path: String!
+  \"Edge URL, signed for private fonts.\"
+  url: String!
+  format: String!
+}
+";
+
+/// Nesting allowed in a query; `variants` can otherwise recurse forever.
+const MAX_DEPTH: usize = 12;
+/// Brackets, and fragments spread within fragments, allowed in a query's
+/// text, so parsing and expanding it cannot run out of stack.
+const MAX_NESTING: usize = 32;
+/// Objects one query may resolve; nested `variants` of a large family
+/// multiply quickly.
+const MAX_OBJECTS: usize = 10_000;
+
+// ── Lexer ──────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Punct(char),
+    Spread,
+    Name(String),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+fn lex(src: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while let Some(&c) = chars.get(i) {
+        match c {
+            ' ' | '\t' | '\n' | '\r' | ',' | '\u{feff}' => i += 1,
+            '#' => {
+                while chars.get(i).is_some_and(|&c| c != '\n') {
+                    i += 1;
+                }
+            }
+            '!' | '$' | '&' | '(' | ')' | ':' | '=' | '@' | '[' | ']' | '{' | '|' | '}' => {
+                tokens.push(Token::Punct(c));
+                i += 1;
+            }
+            '.' if chars.get(i..i + 3) == Some(&['.', '.', '.']) => {
+                tokens.push(Token::Spread);
+                i += 3;
+            }
+            '"' => {
+                if chars.get(i..i + 3) == Some(&['"', '"', '"']) {
+                    return Err("block strings are not supported".to_string());
+                }
+                i += 1;
+                let mut s = String::new();
+                loop {
+                    match chars.get(i) {
+                        None | Some('\n') => return Err("unterminated string".to_string()),
+                        Some('"') => break,
+                        Some('\\') => {
+                            let escaped = match chars.get(i + 1) {
+                                Some('u') => {
+                                    let hex: String = chars.get(i + 2..i + 6).unwrap_or_default().iter().collect();
+                                    i += 4;
+                                    u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+                                }
+                                Some('n') => Some('\n'),
+                                Some('t') => Some('\t'),
+                                Some('r') => Some('\r'),
+                                Some('b') => Some('\u{8}'),
+                                Some('f') => Some('\u{c}'),
+                                Some(&c @ ('"' | '\\' | '/')) => Some(c),
+                                _ => None,
+                            };
+                            s.push(escaped.ok_or("invalid escape in string")?);
+                            i += 2;
+                        }
+                        Some(&c) => {
+                            s.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push(Token::Str(s));
+                i += 1;
+            }
+            c if c == '_' || c.is_ascii_alphabetic() => {
+                let start = i;
+                while chars.get(i).is_some_and(|&c| c == '_' || c.is_ascii_alphanumeric()) {
+                    i += 1;
+                }
+                tokens.push(Token::Name(chars[start..i].iter().collect()));
+            }
+            c if c == '-' || c.is_ascii_digit() => {
+                let start = i;
+                i += 1;
+                while chars.get(i).is_some_and(|&c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-')) {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let invalid = || format!("invalid number '{text}'");
+                tokens.push(if text.contains(['.', 'e', 'E']) {
+                    Token::Float(text.parse().map_err(|_| invalid())?)
+                } else {
+                    Token::Int(text.parse().map_err(|_| invalid())?)
+                });
+            }
+            c => return Err(format!("unexpected character '{c}'")),
+        }
+    }
+    Ok(tokens)
+}
+
+// ── Parser ─────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+enum Literal {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Enum(String),
+    List(Vec<Literal>),
+    Object(Vec<(String, Literal)>),
+    Var(String),
+}
+
+type Arguments = Vec<(String, Literal)>;
+
+struct Directive {
+    name: String,
+    args: Arguments,
+}
+
+struct Field {
+    alias: Option<String>,
+    name: String,
+    args: Arguments,
+    directives: Vec<Directive>,
+    selection: Vec<Selection>,
+}
+
+enum Selection {
+    Field(Field),
+    Spread(String, Vec<Directive>),
+    Inline(Option<String>, Vec<Directive>, Vec<Selection>),
+}
+
+struct Variable {
+    name: String,
+    required: bool,
+    default: Option<Literal>,
+}
+
+struct Operation {
+    name: Option<String>,
+    variables: Vec<Variable>,
+    selection: Vec<Selection>,
+}
+
+struct Fragment {
+    on: String,
+    selection: Vec<Selection>,
+}
+
+#[derive(Default)]
+struct Document {
+    operations: Vec<Operation>,
+    fragments: HashMap<String, Fragment>,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Brackets open at `pos`.
+    depth: usize,
+}
+
+fn parse(query: &str) -> Result<Document, String> {
+    Parser { tokens: lex(query)?, pos: 0, depth: 0 }.document()
+}
+
+impl Parser {
+    fn nest(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(format!("query is nested deeper than {MAX_NESTING} levels"));
+        }
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or("unexpected end of query")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(&Token::Punct(c));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.next()? {
+            Token::Punct(p) if p == c => Ok(()),
+            other => Err(format!("expected '{c}', found {other:?}")),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Name(name) => Ok(name),
+            other => Err(format!("expected a name, found {other:?}")),
+        }
+    }
+
+    fn document(mut self) -> Result<Document, String> {
+        let mut doc = Document::default();
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Punct('{') => {
+                    let selection = self.selection_set()?;
+                    doc.operations.push(Operation { name: None, variables: Vec::new(), selection });
+                }
+                Token::Name(n) if n == "query" => {
+                    self.pos += 1;
+                    let operation = self.operation()?;
+                    doc.operations.push(operation);
+                }
+                Token::Name(n) if n == "mutation" || n == "subscription" => {
+                    return Err(format!("{n} operations are not supported; the catalog API is read-only"));
+                }
+                Token::Name(n) if n == "fragment" => {
+                    self.pos += 1;
+                    let name = self.name()?;
+                    if self.name()? != "on" {
+                        return Err(format!("fragment {name} needs a type condition"));
+                    }
+                    let on = self.name()?;
+                    self.directives()?;
+                    let selection = self.selection_set()?;
+                    doc.fragments.insert(name, Fragment { on, selection });
+                }
+                other => return Err(format!("expected an operation or fragment, found {other:?}")),
+            }
+        }
+        Ok(doc)
+    }
+
+    fn operation(&mut self) -> Result<Operation, String> {
+        let name = match self.peek() {
+            Some(Token::Name(_)) => Some(self.name()?),
+            _ => None,
+        };
+        let mut variables = Vec::new();
+        if self.eat('(') {
+            while !self.eat(')') {
+                self.expect('$')?;
+                let name = self.name()?;
+                self.expect(':')?;
+                let required = self.type_ref()?;
+                let default = if self.eat('=') { Some(self.value()?) } else { None };
+                self.directives()?;
+                variables.push(Variable { name, required, default });
+            }
+        }
+        self.directives()?;
+        Ok(Operation { name, variables, selection: self.selection_set()? })
+    }
+
+    /// Skips a type reference; whether it is non-null.
+    fn type_ref(&mut self) -> Result<bool, String> {
+        if self.eat('[') {
+            self.type_ref()?;
+            self.expect(']')?;
+        } else {
+            self.name()?;
+        }
+        Ok(self.eat('!'))
+    }
+
+    fn selection_set(&mut self) -> Result<Vec<Selection>, String> {
+        self.expect('{')?;
+        self.nest()?;
+        let mut selection = Vec::new();
+        while !self.eat('}') {
+            selection.push(self.selection()?);
+        }
+        self.depth -= 1;
+        if selection.is_empty() {
+            return Err("empty selection set".to_string());
+        }
+        Ok(selection)
+    }
+
+    fn selection(&mut self) -> Result<Selection, String> {
+        if self.peek() == Some(&Token::Spread) {
+            self.pos += 1;
+            return match self.peek() {
+                Some(Token::Name(n)) if n != "on" => {
+                    let name = self.name()?;
+                    Ok(Selection::Spread(name, self.directives()?))
+                }
+                _ => {
+                    let on = match self.peek() {
+                        Some(Token::Name(_)) => {
+                            self.pos += 1;
+                            Some(self.name()?)
+                        }
+                        _ => None,
+                    };
+                    let directives = self.directives()?;
+                    Ok(Selection::Inline(on, directives, self.selection_set()?))
+                }
+            };
+        }
+        let first = self.name()?;
+        let (alias, name) = if self.eat(':') { (Some(first), self.name()?) } else { (None, first) };
+        let args = self.arguments()?;
+        let directives = self.directives()?;
+        let selection =
+            if self.peek() == Some(&Token::Punct('{')) { self.selection_set()? } else { Vec::new() };
+        Ok(Selection::Field(Field { alias, name, args, directives, selection }))
+    }
+
+    fn arguments(&mut self) -> Result<Arguments, String> {
+        let mut args = Vec::new();
+        if self.eat('(') {
+            while !self.eat(')') {
+                let name = self.name()?;
+                self.expect(':')?;
+                args.push((name, self.value()?));
+            }
+        }
+        Ok(args)
+    }
+
+    fn directives(&mut self) -> Result<Vec<Directive>, String> {
+        let mut directives = Vec::new();
+        while self.eat('@') {
+            let name = self.name()?;
+            directives.push(Directive { name, args: self.arguments()? });
+        }
+        Ok(directives)
+    }
+
+    fn value(&mut self) -> Result<Literal, String> {
+        Ok(match self.next()? {
+            Token::Punct('$') => Literal::Var(self.name()?),
+            Token::Int(i) => Literal::Int(i),
+            Token::Float(f) => Literal::Float(f),
+            Token::Str(s) => Literal::Str(s),
+            Token::Name(n) => match n.as_str() {
+                "true" => Literal::Bool(true),
+                "false" => Literal::Bool(false),
+                "null" => Literal::Null,
+                _ => Literal::Enum(n),
+            },
+            Token::Punct('[') => {
+                self.nest()?;
+                let mut items = Vec::new();
+                while !self.eat(']') {
+                    items.push(self.value()?);
+                }
+                self.depth -= 1;
+                Literal::List(items)
+            }
+            Token::Punct('{') => {
+                self.nest()?;
+                let mut fields = Vec::new();
+                while !self.eat('}') {
+                    let name = self.name()?;
+                    self.expect(':')?;
+                    fields.push((name, self.value()?));
+                }
+                self.depth -= 1;
+                Literal::Object(fields)
+            }
+            other => return Err(format!("expected a value, found {other:?}")),
+        })
+    }
+}
+
+// ── Execution ──────────────────────────────────────────────────────────────
+
+#[derive(Clone, Copy)]
+enum Object<'a> {
+    Query,
+    Font(&'a FontCatalogEntry),
+    Defaults(&'a ProcessingDefaults),
+    Artifact(&'a str),
+}
+
+impl Object<'_> {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::Query => "Query",
+            Self::Font(_) => "Font",
+            Self::Defaults(_) => "Defaults",
+            Self::Artifact(_) => "Artifact",
+        }
+    }
+}
+
+/// A document and the variables of the operation being run: what
+/// arguments, directives and fragments are resolved against.
+struct Scope<'a> {
+    doc: &'a Document,
+    variables: Map<String, Value>,
+}
+
+struct Executor<'a> {
+    state: &'a AppState,
+    headers: &'a HeaderMap,
+    scope: Scope<'a>,
+    /// Every entry the caller may see.
+    entries: Vec<FontCatalogEntry>,
+    /// Generated files by slug, listed up front when `artifacts` is selected.
+    artifacts: HashMap<String, Vec<String>>,
+    resolved: Cell<usize>,
+}
+
+/// Whether a field named `name` is selected anywhere in `selection`.
+fn selects<'d>(
+    doc: &'d Document,
+    selection: &'d [Selection],
+    name: &str,
+    seen: &mut HashSet<&'d str>,
+    depth: usize,
+) -> Result<bool, String> {
+    if depth > MAX_NESTING {
+        return Err(format!("query is nested deeper than {MAX_NESTING} levels"));
+    }
+    for s in selection {
+        let found = match s {
+            Selection::Field(f) => f.name == name || selects(doc, &f.selection, name, seen, depth + 1)?,
+            Selection::Inline(_, _, inner) => selects(doc, inner, name, seen, depth + 1)?,
+            Selection::Spread(fragment, _) => match doc.fragments.get(fragment) {
+                Some(f) if seen.insert(fragment) => selects(doc, &f.selection, name, seen, depth + 1)?,
+                _ => false,
+            },
+        };
+        if found {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+impl<'a> Scope<'a> {
+    /// The variables `operation` declares, from those `provided` or their
+    /// defaults.
+    fn bind(doc: &'a Document, operation: &Operation, provided: &Map<String, Value>) -> Result<Self, String> {
+        let mut scope = Self { doc, variables: Map::new() };
+        for v in &operation.variables {
+            let value = match (provided.get(&v.name), &v.default) {
+                (Some(value), _) => value.clone(),
+                (None, Some(default)) => scope.literal(default)?,
+                (None, None) => Value::Null,
+            };
+            if v.required && value.is_null() {
+                return Err(format!("variable ${} is required", v.name));
+            }
+            scope.variables.insert(v.name.clone(), value);
+        }
+        Ok(scope)
+    }
+
+    fn literal(&self, value: &Literal) -> Result<Value, String> {
+        Ok(match value {
+            Literal::Null => Value::Null,
+            Literal::Bool(b) => json!(b),
+            Literal::Int(i) => json!(i),
+            Literal::Float(f) => json!(f),
+            Literal::Str(s) | Literal::Enum(s) => json!(s),
+            Literal::List(items) => Value::Array(items.iter().map(|v| self.literal(v)).collect::<Result<_, _>>()?),
+            Literal::Object(fields) => {
+                let fields = fields.iter().map(|(k, v)| Ok((k.clone(), self.literal(v)?)));
+                Value::Object(fields.collect::<Result<_, String>>()?)
+            }
+            Literal::Var(name) => {
+                self.variables.get(name).cloned().ok_or_else(|| format!("variable ${name} is not declared"))?
+            }
+        })
+    }
+
+    fn arguments(&self, args: &Arguments, allowed: &[&str]) -> Result<HashMap<String, Value>, String> {
+        let mut out = HashMap::new();
+        for (name, value) in args {
+            if !allowed.contains(&name.as_str()) {
+                return Err(format!("unknown argument '{name}'"));
+            }
+            out.insert(name.clone(), self.literal(value)?);
+        }
+        Ok(out)
+    }
+
+    /// Whether `@include`/`@skip` keep a selection.
+    fn included(&self, directives: &[Directive]) -> Result<bool, String> {
+        for d in directives {
+            let condition = self.arguments(&d.args, &["if"])?.remove("if");
+            let condition =
+                condition.and_then(|v| v.as_bool()).ok_or_else(|| format!("@{} needs if: Boolean!", d.name))?;
+            match d.name.as_str() {
+                "include" if !condition => return Ok(false),
+                "skip" if condition => return Ok(false),
+                "include" | "skip" => {}
+                other => return Err(format!("unknown directive @{other}")),
+            }
+        }
+        Ok(true)
+    }
+
+    /// The fields `selection` asks of a `type_name`, fragments expanded.
+    fn collect<'s>(
+        &'s self,
+        selection: &'s [Selection],
+        type_name: &str,
+        seen: &mut HashSet<&'s str>,
+        out: &mut Vec<&'s Field>,
+    ) -> Result<(), String> {
+        for s in selection {
+            match s {
+                Selection::Field(f) => {
+                    if self.included(&f.directives)? {
+                        out.push(f);
+                    }
+                }
+                Selection::Inline(on, directives, inner) => {
+                    if on.as_deref().is_none_or(|on| on == type_name) && self.included(directives)? {
+                        self.collect(inner, type_name, seen, out)?;
+                    }
+                }
+                Selection::Spread(name, directives) => {
+                    let fragment = self.doc.fragments.get(name).ok_or_else(|| format!("unknown fragment '{name}'"))?;
+                    if !seen.insert(name) {
+                        return Err(format!("fragment '{name}' spreads itself"));
+                    }
+                    if seen.len() > MAX_NESTING {
+                        return Err(format!("fragments are spread more than {MAX_NESTING} deep"));
+                    }
+                    if fragment.on == type_name && self.included(directives)? {
+                        self.collect(&fragment.selection, type_name, seen, out)?;
+                    }
+                    seen.remove(name.as_str());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Executor<'_> {
+    fn object(&self, object: Object<'_>, selection: &[Selection], depth: usize) -> Result<Value, String> {
+        if depth > MAX_DEPTH {
+            return Err(format!("query is nested deeper than {MAX_DEPTH} levels"));
+        }
+        self.resolved.set(self.resolved.get() + 1);
+        if self.resolved.get() > MAX_OBJECTS {
+            return Err(format!("query resolves more than {MAX_OBJECTS} objects; select fewer nested variants"));
+        }
+        let mut fields = Vec::new();
+        self.scope.collect(selection, object.type_name(), &mut HashSet::new(), &mut fields)?;
+        let mut out = Map::new();
+        for field in fields {
+            let value = self.field(object, field, depth)?;
+            out.insert(field.alias.clone().unwrap_or_else(|| field.name.clone()), value);
+        }
+        Ok(Value::Object(out))
+    }
+
+    fn fonts<'e>(
+        &self,
+        fonts: impl Iterator<Item = &'e FontCatalogEntry>,
+        field: &Field,
+        depth: usize,
+    ) -> Result<Value, String> {
+        fonts.map(|e| self.object(Object::Font(e), &field.selection, depth + 1)).collect()
+    }
+
+    fn field(&self, object: Object<'_>, field: &Field, depth: usize) -> Result<Value, String> {
+        let name = field.name.as_str();
+        let on = object.type_name();
+        let composite =
+            matches!((object, name), (Object::Query, _) | (Object::Font(_), "defaults" | "variants" | "artifacts"));
+        if name == "__typename" {
+            return Ok(json!(on));
+        }
+        if composite && field.selection.is_empty() {
+            return Err(format!("field '{name}' on {on} needs a selection of subfields"));
+        }
+        if !composite && !field.selection.is_empty() {
+            return Err(format!("field '{name}' on {on} is a scalar and takes no subfields"));
+        }
+        let no_args = || self.scope.arguments(&field.args, &[]);
+        let unknown = || Err(format!("cannot query field '{name}' on type {on}"));
+        Ok(match object {
+            Object::Query => match name {
+                "fonts" => {
+                    let args = self.scope.arguments(&field.args, &["family", "license", "format", "max_size_kb"])?;
+                    let text = |key: &str| -> Result<Option<String>, String> {
+                        match args.get(key) {
+                            None | Some(Value::Null) => Ok(None),
+                            Some(Value::String(s)) => Ok(Some(s.clone())),
+                            Some(_) => Err(format!("argument '{key}' must be a String")),
+                        }
+                    };
+                    let max_size_kb = match args.get("max_size_kb") {
+                        None | Some(Value::Null) => None,
+                        Some(v) => Some(v.as_f64().ok_or("argument 'max_size_kb' must be a Float")?),
+                    };
+                    let query = ListQuery::filters(text("family")?, text("license")?, text("format")?, max_size_kb);
+                    let matching = catalog::matching(self.state, self.headers, &query);
+                    let ids: HashSet<&str> = matching.iter().map(|e| e.id.as_str()).collect();
+                    self.fonts(self.entries.iter().filter(|e| ids.contains(e.id.as_str())), field, depth)?
+                }
+                "font" => {
+                    let args = self.scope.arguments(&field.args, &["id"])?;
+                    let id = args.get("id").and_then(Value::as_str).ok_or("argument 'id' must be a String!")?;
+                    match self.entries.iter().find(|e| e.id == id) {
+                        Some(e) => self.object(Object::Font(e), &field.selection, depth + 1)?,
+                        None => Value::Null,
+                    }
+                }
+                _ => return unknown(),
+            },
+            Object::Font(e) => {
+                no_args()?;
+                match name {
+                    "id" => json!(e.id),
+                    "family" => json!(e.family),
+                    "variant" => json!(e.variant),
+                    "formats" => json!(e.formats),
+                    "size_kb" => json!(e.size_kb),
+                    "glyph_count" => json!(e.glyph_count),
+                    "unicode_ranges" => json!(e.unicode_ranges),
+                    "license" => json!(e.license),
+                    "foundry" => json!(e.foundry),
+                    "postscript_name" => json!(e.postscript_name),
+                    "private" => json!(e.private),
+                    "version" => json!(e.version),
+                    "attribution" => json!(e.attribution),
+                    "tenant" => json!(Some(&e.tenant).filter(|t| !t.is_empty())),
+                    "defaults" => match &e.defaults {
+                        Some(d) => self.object(Object::Defaults(d), &field.selection, depth + 1)?,
+                        None => Value::Null,
+                    },
+                    "variants" => {
+                        let siblings = self.entries.iter().filter(|o| o.family == e.family && o.id != e.id);
+                        self.fonts(siblings, field, depth)?
+                    }
+                    "artifacts" => {
+                        let mut keys: Vec<&String> = [artifacts::slug(&e.id), artifacts::slug(&e.family)]
+                            .iter()
+                            .filter_map(|slug| self.artifacts.get(slug))
+                            .flatten()
+                            .collect();
+                        keys.sort();
+                        keys.dedup();
+                        keys.into_iter()
+                            .map(|k| self.object(Object::Artifact(k), &field.selection, depth + 1))
+                            .collect::<Result<_, _>>()?
+                    }
+                    _ => return unknown(),
+                }
+            }
+            Object::Defaults(d) => {
+                no_args()?;
+                match name {
+                    "quality" => json!(d.quality),
+                    "strip_hints" => json!(d.strip_hints),
+                    "subset_preset" => json!(d.subset_preset),
+                    _ => return unknown(),
+                }
+            }
+            Object::Artifact(key) => {
+                no_args()?;
+                let path = tenants::cdn_path(self.state, &format!("/cdn/fonts/{key}"));
+                match name {
+                    "path" => json!(path),
+                    "url" => json!(signing::download_url(self.state, &path)),
+                    "format" => json!(key.rsplit_once('.').map_or("", |(_, ext)| ext)),
+                    _ => return unknown(),
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphQlRequest {
+    query: String,
+    #[serde(default)]
+    variables: Option<Map<String, Value>>,
+    operation_name: Option<String>,
+}
+
+fn errors(message: String) -> Response {
+    (StatusCode::BAD_REQUEST, Json(json!({ "errors": [{ "message": message }] }))).into_response()
+}
+
+async fn execute(state: &AppState, headers: &HeaderMap, req: GraphQlRequest) -> Result<Value, String> {
+    let doc = parse(&req.query)?;
+    let operation = match &req.operation_name {
+        Some(name) => doc.operations.iter().find(|o| o.name.as_deref() == Some(name.as_str())),
+        None if doc.operations.len() == 1 => doc.operations.first(),
+        None => return Err("the query has several operations; pass operationName".to_string()),
+    };
+    let operation = operation.ok_or("no matching operation in the query")?;
+
+    let mut executor = Executor {
+        state,
+        headers,
+        scope: Scope::bind(&doc, operation, &req.variables.unwrap_or_default())?,
+        entries: catalog::matching(state, headers, &ListQuery::default()),
+        artifacts: HashMap::new(),
+        resolved: Cell::new(0),
+    };
+
+    if selects(&doc, &operation.selection, "artifacts", &mut HashSet::new(), 0)? {
+        let mut slugs: Vec<String> =
+            executor.entries.iter().flat_map(|e| [artifacts::slug(&e.id), artifacts::slug(&e.family)]).collect();
+        slugs.sort();
+        slugs.dedup();
+        for slug in slugs {
+            let keys = state.artifacts.list(&slug).await.map_err(|e| format!("listing artifacts: {e}"))?;
+            executor.artifacts.insert(slug, keys);
+        }
+    }
+    executor.object(Object::Query, &operation.selection, 0)
+}
+
+pub async fn query(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(req): ApiJson<GraphQlRequest>,
+) -> Response {
+    match execute(&state, &headers, req).await {
+        Ok(data) => Json(json!({ "data": data })).into_response(),
+        Err(message) => errors(message),
+    }
+}
+
+pub async fn schema() -> &'static str {
+    SCHEMA
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(selection: &[Selection], index: usize) -> &Field {
+        match &selection[index] {
+            Selection::Field(field) => field,
+            _ => panic!("selection {index} is not a field"),
+        }
+    }
+
+    /// Response keys of the fields `selection` asks of a `type_name`.
+    fn keys(scope: &Scope<'_>, selection: &[Selection], type_name: &str) -> Result<Vec<String>, String> {
+        let mut fields = Vec::new();
+        scope.collect(selection, type_name, &mut HashSet::new(), &mut fields)?;
+        Ok(fields.iter().map(|f| f.alias.clone().unwrap_or_else(|| f.name.clone())).collect())
+    }
+
+    #[test]
+    fn parses_nested_selections_with_aliases_and_arguments() {
+        let doc = parse(
+            r#"
+            # every Inter variant under 50 KB
+            query Small {
+              fonts(family: "Inter", max_size_kb: 50.5) {
+                id
+                siblings: variants { family defaults { quality } }
+              }
+            }"#,
+        )
+        .unwrap();
+        let operation = &doc.operations[0];
+        assert_eq!(operation.name.as_deref(), Some("Small"));
+        let fonts = field(&operation.selection, 0);
+        assert_eq!(fonts.name, "fonts");
+        assert!(matches!(&fonts.args[..], [(f, Literal::Str(family)), (m, Literal::Float(max))]
+            if f == "family" && family == "Inter" && m == "max_size_kb" && *max == 50.5));
+        let siblings = field(&fonts.selection, 1);
+        assert_eq!((siblings.alias.as_deref(), siblings.name.as_str()), (Some("siblings"), "variants"));
+        assert_eq!(field(&field(&siblings.selection, 1).selection, 0).name, "quality");
+    }
+
+    #[test]
+    fn binds_variables_from_the_request_and_defaults() {
+        let doc = parse(r#"query($family: String = "Inter", $id: String!, $wide: [Int!]) { font(id: $id) { id } }"#);
+        let doc = doc.unwrap();
+        let operation = &doc.operations[0];
+        let provided = json!({ "id": "roboto-regular" }).as_object().cloned().unwrap();
+        let scope = Scope::bind(&doc, operation, &provided).unwrap();
+        let bound = json!({ "family": "Inter", "id": "roboto-regular", "wide": null });
+        assert_eq!(Value::Object(scope.variables.clone()), bound);
+        let args = scope.arguments(&field(&operation.selection, 0).args, &["id"]).unwrap();
+        assert_eq!(args["id"], "roboto-regular");
+        assert_eq!(scope.arguments(&field(&operation.selection, 0).args, &[]).unwrap_err(), "unknown argument 'id'");
+
+        let missing = Scope::bind(&doc, operation, &Map::new()).err().unwrap();
+        assert_eq!(missing, "variable $id is required");
+        let doc = parse(r#"{ font(id: $id) { id } }"#).unwrap();
+        let scope = Scope::bind(&doc, &doc.operations[0], &provided).unwrap();
+        let undeclared = scope.arguments(&field(&doc.operations[0].selection, 0).args, &["id"]).unwrap_err();
+        assert_eq!(undeclared, "variable $id is not declared");
+    }
+
+    #[test]
+    fn expands_fragments_and_directives() {
+        let doc = parse(
+            r#"
+            query($full: Boolean!) {
+              font(id: "inter") {
+                ...names
+                ... on Font @include(if: $full) { license }
+                ... on Defaults { quality }
+                glyphs: glyph_count @skip(if: $full)
+              }
+            }
+            fragment names on Font { id family ...more }
+            fragment more on Font { variant }"#,
+        )
+        .unwrap();
+        let operation = &doc.operations[0];
+        let selection = &field(&operation.selection, 0).selection;
+        let full = Scope::bind(&doc, operation, json!({ "full": true }).as_object().unwrap()).unwrap();
+        assert_eq!(keys(&full, selection, "Font").unwrap(), ["id", "family", "variant", "license"]);
+        let brief = Scope::bind(&doc, operation, json!({ "full": false }).as_object().unwrap()).unwrap();
+        assert_eq!(keys(&brief, selection, "Font").unwrap(), ["id", "family", "variant", "glyphs"]);
+        assert_eq!(keys(&brief, selection, "Defaults").unwrap(), ["quality", "glyphs"]);
+        assert!(selects(&doc, &operation.selection, "variant", &mut HashSet::new(), 0).unwrap());
+        assert!(!selects(&doc, &operation.selection, "artifacts", &mut HashSet::new(), 0).unwrap());
+    }
+
+    #[test]
+    fn refuses_fragments_that_spread_themselves_or_are_unknown() {
+        let doc = parse("{ font(id: \"x\") { ...a } } fragment a on Font { id ...b } fragment b on Font { ...a }");
+        let doc = doc.unwrap();
+        let operation = &doc.operations[0];
+        let scope = Scope::bind(&doc, operation, &Map::new()).unwrap();
+        let selection = &field(&operation.selection, 0).selection;
+        assert_eq!(keys(&scope, selection, "Font").unwrap_err(), "fragment 'a' spreads itself");
+        assert!(!selects(&doc, &operation.selection, "artifacts", &mut HashSet::new(), 0).unwrap());
+
+        let doc = parse("{ font(id: \"x\") { ...missing } }").unwrap();
+        let scope = Scope::bind(&doc, &doc.operations[0], &Map::new()).unwrap();
+        let selection = &field(&doc.operations[0].selection, 0).selection;
+        assert_eq!(keys(&scope, selection, "Font").unwrap_err(), "unknown fragment 'missing'");
+    }
+
+    #[test]
+    fn malformed_queries_are_errors() {
+        for query in [
+            "{",
+            "{ }",
+            "{ id } }",
+            "{ ... }",
+            "{ font(id: ) { id } }",
+            "{ font(id: \"inter) { id } }",
+            "{ font(id: \"\\u12\") { id } }",
+            "{ font(id: \"\\q\") { id } }",
+            "{ fonts(max_size_kb: 1.2.3) { id } }",
+            "{ fonts(max_size_kb: -) { id } }",
+            "{ fonts(family: [\"a\" ) { id } }",
+            "{ font(id: \"\"\"inter\"\"\") { id } }",
+            "query($id) { font(id: $id) { id } }",
+            "query($id: ) { font(id: $id) { id } }",
+            "query { font(id: \"inter\") { id }",
+            "fragment f { id }",
+            "fragment f on Font",
+            "mutation { upload }",
+            "subscription { fonts { id } }",
+            "{ font(id: \"inter\") { % } }",
+            "{ alias: }",
+        ] {
+            assert!(parse(query).is_err(), "{query}");
+        }
+    }
+
+    #[test]
+    fn bounds_nesting() {
+        let nested = |levels: usize| format!("{}id{}", "{ a ".repeat(levels), " }".repeat(levels));
+        assert!(parse(&nested(MAX_NESTING)).is_ok());
+        assert_eq!(parse(&nested(MAX_NESTING + 1)).err().unwrap(), "query is nested deeper than 32 levels");
+        assert!(parse(&nested(100_000)).is_err());
+        let list = format!("{{ fonts(family: {}) {{ id }} }}", "[".repeat(100_000));
+        assert_eq!(parse(&list).err().unwrap(), "query is nested deeper than 32 levels");
+
+        // Fragments spreading the next one, 40 deep.
+        let mut query = "{ font(id: \"x\") { ...f0 } }".to_string();
+        for i in 0..40 {
+            query.push_str(&format!(" fragment f{i} on Font {{ id ...f{} }}", i + 1));
+        }
+        query.push_str(" fragment f40 on Font { id }");
+        let doc = parse(&query).unwrap();
+        let operation = &doc.operations[0];
+        let scope = Scope::bind(&doc, operation, &Map::new()).unwrap();
+        let selection = &field(&operation.selection, 0).selection;
+        assert_eq!(keys(&scope, selection, "Font").unwrap_err(), "fragments are spread more than 32 deep");
+        let artifacts = selects(&doc, &operation.selection, "artifacts", &mut HashSet::new(), 0);
+        assert_eq!(artifacts.unwrap_err(), "query is nested deeper than 32 levels");
+    }
+}
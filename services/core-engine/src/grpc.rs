@@ -0,0 +1,676 @@
+//! gRPC API on a second port.
+//!
+//! With `GRPC_ADDR` set, the engine also serves `alice.font.v1.FontService`
+//! (`proto/font.proto`) over HTTP/2 cleartext: `Compress`, `Subset`,
+//! `Analyze` and `Catalog`. The RPCs run through the same handlers as the
+//! REST routes, with the same `AppState`, API keys, rate limits and metrics;
+//! request metadata is passed on as headers, so `x-api-key` and
+//! `x-font-tenant` work as they do over REST. Compress and Subset stream the
+//! generated font back in chunks after a `FontInfo` message rather than
+//! returning a `download_url` only. Messages are encoded by hand; the subset
+//! of protobuf used here (varints, doubles, floats and length-delimited
+//! fields) is small, and compressed gRPC messages are refused.
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{Json, Response},
+};
+use font_api::TransformOptions;
+use futures_util::{
+    stream::{self, BoxStream},
+    Stream, StreamExt,
+};
+use http_body::Frame;
+use std::{
+    convert::Infallible,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use crate::{
+    catalog::{self, ListQuery},
+    extract::ApiJson,
+    tenants, AnalyzeMode, AnalyzeRequest, AnalyzeResponse, AppState, CompressRequest, CompressResponse, FontCatalogEntry,
+    SubsetRequest, SubsetResponse,
+};
+
+pub const COMPRESS: &str = "/alice.font.v1.FontService/Compress";
+pub const SUBSET: &str = "/alice.font.v1.FontService/Subset";
+pub const ANALYZE: &str = "/alice.font.v1.FontService/Analyze";
+pub const CATALOG: &str = "/alice.font.v1.FontService/Catalog";
+
+/// Largest `data` chunk of a streamed font.
+const CHUNK_BYTES: usize = 64 * 1024;
+
+type Error = (StatusCode, String);
+
+// ── Protobuf wire format ───────────────────────────────────────────────────
+
+enum Field<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    /// Skipped: no request field here is a `float` or `fixed32`.
+    Fixed32,
+    Len(&'a [u8]),
+}
+
+fn varint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *buf.get(*pos)?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// The fields of a message as `(number, value)`, in wire order.
+fn fields(buf: &[u8]) -> Result<Vec<(u64, Field<'_>)>, String> {
+    let malformed = || "malformed protobuf message".to_string();
+    let mut pos = 0;
+    let mut out = Vec::new();
+    while pos < buf.len() {
+        let key = varint(buf, &mut pos).ok_or_else(malformed)?;
+        let field = match key & 7 {
+            0 => Field::Varint(varint(buf, &mut pos).ok_or_else(malformed)?),
+            1 => {
+                let raw = buf.get(pos..pos + 8).ok_or_else(malformed)?;
+                pos += 8;
+                Field::Fixed64(u64::from_le_bytes(raw.try_into().unwrap()))
+            }
+            2 => {
+                let len = usize::try_from(varint(buf, &mut pos).ok_or_else(malformed)?).map_err(|_| malformed())?;
+                let raw = buf.get(pos..pos.checked_add(len).ok_or_else(malformed)?).ok_or_else(malformed)?;
+                pos += len;
+                Field::Len(raw)
+            }
+            5 => {
+                buf.get(pos..pos + 4).ok_or_else(malformed)?;
+                pos += 4;
+                Field::Fixed32
+            }
+            wire => return Err(format!("unsupported protobuf wire type {wire}")),
+        };
+        out.push((key >> 3, field));
+    }
+    Ok(out)
+}
+
+impl Field<'_> {
+    fn text(&self, name: &str) -> Result<String, String> {
+        match self {
+            Self::Len(raw) => String::from_utf8(raw.to_vec()).map_err(|_| format!("{name} is not UTF-8")),
+            _ => Err(format!("{name} must be a string")),
+        }
+    }
+
+    fn uint(&self, name: &str) -> Result<u64, String> {
+        match self {
+            Self::Varint(v) => Ok(*v),
+            _ => Err(format!("{name} must be a varint")),
+        }
+    }
+
+    fn double(&self, name: &str) -> Result<f64, String> {
+        match self {
+            Self::Fixed64(v) => Ok(f64::from_bits(*v)),
+            _ => Err(format!("{name} must be a double")),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.0.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.0.push(v as u8);
+    }
+
+    fn key(&mut self, field: u64, wire: u64) {
+        self.varint(field << 3 | wire);
+    }
+
+    /// Zero values are left out, as proto3 does.
+    fn uint(&mut self, field: u64, v: u64) -> &mut Self {
+        if v != 0 {
+            self.key(field, 0);
+            self.varint(v);
+        }
+        self
+    }
+
+    fn boolean(&mut self, field: u64, v: bool) -> &mut Self {
+        self.uint(field, u64::from(v))
+    }
+
+    fn double(&mut self, field: u64, v: f64) -> &mut Self {
+        if v != 0.0 {
+            self.key(field, 1);
+            self.0.extend_from_slice(&v.to_le_bytes());
+        }
+        self
+    }
+
+    fn float(&mut self, field: u64, v: f32) -> &mut Self {
+        if v != 0.0 {
+            self.key(field, 5);
+            self.0.extend_from_slice(&v.to_le_bytes());
+        }
+        self
+    }
+
+    /// Always written, so a message field or `oneof` member is present even
+    /// when empty.
+    fn bytes(&mut self, field: u64, v: &[u8]) -> &mut Self {
+        self.key(field, 2);
+        self.varint(v.len() as u64);
+        self.0.extend_from_slice(v);
+        self
+    }
+
+    fn string(&mut self, field: u64, v: &str) -> &mut Self {
+        if v.is_empty() {
+            self
+        } else {
+            self.bytes(field, v.as_bytes())
+        }
+    }
+
+    fn strings(&mut self, field: u64, values: &[String]) -> &mut Self {
+        for v in values {
+            self.bytes(field, v.as_bytes());
+        }
+        self
+    }
+
+    fn message(&mut self, field: u64, message: Writer) -> &mut Self {
+        self.bytes(field, &message.0)
+    }
+
+    fn done(&mut self) -> Bytes {
+        Bytes::from(std::mem::take(&mut self.0))
+    }
+}
+
+// ── gRPC framing ───────────────────────────────────────────────────────────
+
+/// The single message of a unary request body.
+fn unframe(body: &[u8]) -> Result<&[u8], Error> {
+    let invalid = |m: &str| (StatusCode::BAD_REQUEST, m.to_string());
+    let (prefix, message) = body.split_at_checked(5).ok_or_else(|| invalid("request is not a gRPC message"))?;
+    if prefix[0] != 0 {
+        return Err((StatusCode::NOT_IMPLEMENTED, "compressed gRPC messages are not supported".to_string()));
+    }
+    let len = u32::from_be_bytes(prefix[1..].try_into().unwrap()) as usize;
+    if len != message.len() {
+        return Err(invalid("request must hold exactly one gRPC message"));
+    }
+    Ok(message)
+}
+
+fn frame(message: Bytes) -> Bytes {
+    let mut out = Vec::with_capacity(5 + message.len());
+    out.push(0);
+    out.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    out.extend_from_slice(&message);
+    Bytes::from(out)
+}
+
+/// The gRPC status code closest to what the REST handler answered.
+fn code(status: StatusCode) -> u16 {
+    match status.as_u16() {
+        400 | 422 => 3,
+        401 => 16,
+        403 => 7,
+        404 => 5,
+        409 => 6,
+        412 => 9,
+        413 | 429 => 8,
+        499 => 1,
+        501 => 12,
+        503 => 14,
+        504 => 4,
+        _ => 13,
+    }
+}
+
+/// `grpc-message` is percent-encoded.
+fn percent_encode(message: &str) -> String {
+    message
+        .bytes()
+        .map(|b| match b {
+            b' '..=b'~' if b != b'%' => char::from(b).to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// A response body of framed messages, closed with the call's status in the
+/// trailers; a failure mid-stream ends it with that failure's status.
+struct Reply {
+    messages: BoxStream<'static, Result<Bytes, Error>>,
+    done: bool,
+}
+
+fn trailers(error: Option<Error>) -> HeaderMap {
+    let mut trailers = HeaderMap::new();
+    let (status, message) = error.map_or((0, None), |(s, m)| (code(s), Some(m)));
+    trailers.insert("grpc-status", HeaderValue::from(status));
+    if let Some(message) = message.and_then(|m| HeaderValue::from_str(&percent_encode(&m)).ok()) {
+        trailers.insert("grpc-message", message);
+    }
+    trailers
+}
+
+impl http_body::Body for Reply {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        let frame = match self.messages.poll_next_unpin(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Some(Ok(message))) => return Poll::Ready(Some(Ok(Frame::data(frame(message))))),
+            Poll::Ready(Some(Err(error))) => Frame::trailers(trailers(Some(error))),
+            Poll::Ready(None) => Frame::trailers(trailers(None)),
+        };
+        self.done = true;
+        Poll::Ready(Some(Ok(frame)))
+    }
+}
+
+fn reply(messages: impl Stream<Item = Result<Bytes, Error>> + Send + 'static) -> Response {
+    let mut response = Response::new(Body::new(Reply { messages: messages.boxed(), done: false }));
+    response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+    response
+}
+
+fn fail(error: Error) -> Response {
+    reply(stream::once(async { Err(error) }))
+}
+
+fn decode<T>(body: &[u8], parse: impl FnOnce(&[u8]) -> Result<T, String>) -> Result<T, Error> {
+    parse(unframe(body)?).map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+// ── Font streams ───────────────────────────────────────────────────────────
+
+/// `FontInfo` followed by the artifact behind `download_url` in chunks.
+async fn font_stream(state: Arc<AppState>, info: Writer, download_url: Option<String>) -> Response {
+    let mut first = Writer::default();
+    let info = stream::once(async move { Ok(first.message(1, info).done()) });
+    let Some(url) = download_url else { return reply(info) };
+    let path = url.split('?').next().unwrap_or_default();
+    let key = tenants::artifact_key(path);
+    let object = match state.artifacts.open(key).await {
+        Ok(Some(object)) => object,
+        Ok(None) => return fail((StatusCode::NOT_FOUND, format!("generated font {key} is gone"))),
+        Err(e) => return fail((StatusCode::INTERNAL_SERVER_ERROR, format!("reading generated font: {e}"))),
+    };
+    let data = object.body.into_data_stream().flat_map(|chunk| {
+        let pieces: Vec<Result<Bytes, Error>> = match chunk {
+            Ok(chunk) => (0..chunk.len())
+                .step_by(CHUNK_BYTES)
+                .map(|at| Ok(Writer::default().bytes(2, &chunk[at..chunk.len().min(at + CHUNK_BYTES)]).done()))
+                .collect(),
+            Err(e) => vec![Err((StatusCode::INTERNAL_SERVER_ERROR, format!("reading generated font: {e}")))],
+        };
+        stream::iter(pieces)
+    });
+    reply(info.chain(data))
+}
+
+fn compress_request(buf: &[u8]) -> Result<CompressRequest, String> {
+    let mut req = CompressRequest {
+        font_name: String::new(),
+        font_id: None,
+        transform: TransformOptions::default(),
+        dry_run: false,
+        inline: false,
+        optimize_outlines: false,
+        epub: None,
+    };
+    for (number, field) in fields(buf)? {
+        match number {
+            1 => req.font_name = field.text("font_name")?,
+            2 => req.font_id = Some(field.text("font_id")?).filter(|id| !id.is_empty()),
+            3 => req.transform.format = field.text("format")?,
+            4 => {
+                let quality = u8::try_from(field.uint("quality")?).map_err(|_| "quality must be 0-100")?;
+                req.transform.quality = Some(quality);
+            }
+            5 => req.transform.strip_hints = Some(field.uint("strip_hints")? != 0),
+            6 => req.dry_run = field.uint("dry_run")? != 0,
+            7 => req.optimize_outlines = field.uint("optimize_outlines")? != 0,
+            _ => {}
+        }
+    }
+    Ok(req)
+}
+
+fn compress_info(resp: &CompressResponse) -> Writer {
+    let mut info = Writer::default();
+    info.string(1, &resp.font_name)
+        .string(2, &resp.format)
+        .double(3, resp.original_size_kb)
+        .double(4, resp.compressed_size_kb)
+        .string(5, resp.download_url.as_deref().unwrap_or_default())
+        .uint(6, u64::from(resp.quality));
+    info
+}
+
+pub async fn compress(State(state): State<Arc<AppState>>, headers: HeaderMap, body: Bytes) -> Response {
+    let req = match decode(&body, compress_request) {
+        Ok(req) => req,
+        Err(e) => return fail(e),
+    };
+    match crate::compress(State(Arc::clone(&state)), headers, ApiJson(req)).await {
+        Ok(Json(resp)) => font_stream(state, compress_info(&resp), resp.download_url).await,
+        Err(e) => fail(e),
+    }
+}
+
+fn subset_request(buf: &[u8]) -> Result<SubsetRequest, String> {
+    let mut req = SubsetRequest {
+        font_name: String::new(),
+        font_id: None,
+        characters: String::new(),
+        decompose: false,
+        unicode_range: String::new(),
+        preset: None,
+        transform: TransformOptions::default(),
+        vertical: false,
+        verify_shaping: None,
+        profile: None,
+        dry_run: false,
+        inline: false,
+    };
+    for (number, field) in fields(buf)? {
+        match number {
+            1 => req.font_name = field.text("font_name")?,
+            2 => req.font_id = Some(field.text("font_id")?).filter(|id| !id.is_empty()),
+            3 => req.characters = field.text("characters")?,
+            4 => req.preset = Some(field.text("preset")?).filter(|p| !p.is_empty()),
+            5 => req.transform.format = field.text("format")?,
+            6 => req.profile = Some(field.text("profile")?).filter(|p| !p.is_empty()),
+            7 => req.transform.strip_hints = Some(field.uint("strip_hints")? != 0),
+            8 => req.dry_run = field.uint("dry_run")? != 0,
+            9 => req.transform.layout_closure = Some(field.uint("layout_closure")? != 0),
+            10 => req.decompose = field.uint("decompose")? != 0,
+            11 => req.unicode_range = field.text("unicode_range")?,
+            12 => req.vertical = field.uint("vertical")? != 0,
+            _ => {}
+        }
+    }
+    Ok(req)
+}
+
+fn subset_info(resp: &SubsetResponse) -> Writer {
+    let mut info = Writer::default();
+    info.string(1, &resp.font_name)
+        .string(2, &resp.format)
+        .double(3, resp.original_size_kb)
+        .double(4, resp.subset_size_kb)
+        .string(5, resp.download_url.as_deref().unwrap_or_default())
+        .uint(7, resp.character_count as u64)
+        .uint(8, resp.original_glyph_count as u64)
+        .uint(9, resp.subset_glyph_count as u64);
+    info
+}
+
+pub async fn subset(State(state): State<Arc<AppState>>, headers: HeaderMap, body: Bytes) -> Response {
+    let req = match decode(&body, subset_request) {
+        Ok(req) => req,
+        Err(e) => return fail(e),
+    };
+    match crate::subset(State(Arc::clone(&state)), headers, ApiJson(req)).await {
+        Ok(Json(resp)) => font_stream(state, subset_info(&resp), resp.download_url).await,
+        Err(e) => fail(e),
+    }
+}
+
+// ── Analyze and Catalog ────────────────────────────────────────────────────
+
+fn analyze_request(buf: &[u8]) -> Result<AnalyzeRequest, String> {
+    let mut req = AnalyzeRequest { mode: AnalyzeMode::Summary, ..Default::default() };
+    for (number, field) in fields(buf)? {
+        match number {
+            1 => req.font_name = field.text("font_name")?,
+            2 => req.font_id = Some(field.text("font_id")?).filter(|id| !id.is_empty()),
+            _ => {}
+        }
+    }
+    Ok(req)
+}
+
+fn analyze_response(resp: &AnalyzeResponse) -> Bytes {
+    let mut out = Writer::default();
+    out.string(1, &resp.font_name)
+        .uint(2, resp.glyph_count as u64)
+        .string(3, &resp.format)
+        .double(4, resp.size_kb)
+        .strings(5, &resp.tables)
+        .strings(6, &resp.unicode_ranges)
+        .boolean(7, resp.has_variable_axes);
+    for axis in &resp.variation_axes {
+        let mut a = Writer::default();
+        a.string(1, &axis.tag).float(2, axis.min).float(3, axis.default).float(4, axis.max);
+        a.string(5, axis.name.as_deref().unwrap_or_default()).boolean(6, axis.hidden);
+        out.message(8, a);
+    }
+    out.boolean(9, resp.has_math_table)
+        .uint(10, resp.color_palettes as u64)
+        .strings(11, &resp.opentype_features);
+    for instance in &resp.named_instances {
+        let mut i = Writer::default();
+        i.string(1, &instance.name).string(2, instance.postscript_name.as_deref().unwrap_or_default());
+        for (tag, value) in &instance.coordinates {
+            // A map field is a repeated key/value message.
+            let mut entry = Writer::default();
+            entry.string(1, tag).float(2, *value);
+            i.message(3, entry);
+        }
+        out.message(12, i);
+    }
+    out.done()
+}
+
+pub async fn analyze(State(state): State<Arc<AppState>>, headers: HeaderMap, body: Bytes) -> Response {
+    let req = match decode(&body, analyze_request) {
+        Ok(req) => req,
+        Err(e) => return fail(e),
+    };
+    let resp = crate::analyze(State(state), headers, ApiJson(req)).await;
+    reply(stream::once(async move { resp.map(|Json(resp)| analyze_response(&resp)) }))
+}
+
+fn catalog_request(buf: &[u8]) -> Result<ListQuery, String> {
+    let (mut family, mut license, mut format, mut max_size_kb) = (None, None, None, None);
+    for (number, field) in fields(buf)? {
+        match number {
+            1 => family = Some(field.text("family")?),
+            2 => license = Some(field.text("license")?),
+            3 => format = Some(field.text("format")?),
+            4 => max_size_kb = Some(field.double("max_size_kb")?),
+            _ => {}
+        }
+    }
+    let set = |v: Option<String>| v.filter(|v| !v.is_empty());
+    Ok(ListQuery::filters(set(family), set(license), set(format), max_size_kb.filter(|&max| max > 0.0)))
+}
+
+fn catalog_entry(entry: &FontCatalogEntry) -> Bytes {
+    Writer::default()
+        .string(1, &entry.id)
+        .string(2, &entry.family)
+        .string(3, &entry.variant)
+        .strings(4, &entry.formats)
+        .double(5, entry.size_kb)
+        .uint(6, entry.glyph_count as u64)
+        .strings(7, &entry.unicode_ranges)
+        .string(8, &entry.license)
+        .string(9, entry.foundry.as_deref().unwrap_or_default())
+        .string(10, entry.postscript_name.as_deref().unwrap_or_default())
+        .boolean(11, entry.private)
+        .uint(12, entry.version as u64)
+        .string(13, entry.attribution.as_deref().unwrap_or_default())
+        .string(14, &entry.tenant)
+        .done()
+}
+
+pub async fn catalog(State(state): State<Arc<AppState>>, headers: HeaderMap, body: Bytes) -> Response {
+    let query = match decode(&body, catalog_request) {
+        Ok(query) => query,
+        Err(e) => return fail(e),
+    };
+    let entries = catalog::matching(&state, &headers, &query);
+    reply(stream::iter(entries.into_iter().map(|e| Ok(catalog_entry(&e)))))
+}
+
+pub async fn unimplemented() -> Response {
+    fail((StatusCode::NOT_IMPLEMENTED, "unknown gRPC method".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_varints() {
+        for (bytes, value) in [(&[0x01][..], 1), (&[0xac, 0x02], 300), (&[0x96, 0x01], 150), (&[0x7f], 127)] {
+            let mut pos = 0;
+            assert_eq!(varint(bytes, &mut pos), Some(value));
+            assert_eq!(pos, bytes.len());
+        }
+        let max = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!(varint(&max, &mut 0), Some(u64::MAX));
+        assert_eq!(varint(&[0x80], &mut 0), None, "truncated");
+        assert_eq!(varint(&[0x80; 11], &mut 0), None, "longer than ten bytes");
+    }
+
+    #[test]
+    fn encodes_fields_as_protobuf_does() {
+        assert_eq!(Writer::default().uint(1, 150).done()[..], [0x08, 0x96, 0x01]);
+        assert_eq!(Writer::default().string(2, "testing").done()[..], *b"\x12\x07testing");
+        assert_eq!(Writer::default().double(5, 1.5).done()[..], [0x29, 0, 0, 0, 0, 0, 0, 0xf8, 0x3f]);
+        assert_eq!(Writer::default().float(3, 1.5).done()[..], [0x1d, 0, 0, 0xc0, 0x3f]);
+        assert_eq!(Writer::default().boolean(11, true).done()[..], [0x58, 0x01]);
+        let strings = ["a".to_string(), "bc".to_string()];
+        assert_eq!(Writer::default().strings(4, &strings).done()[..], *b"\x22\x01a\x22\x02bc");
+        // Zero scalars are left out; bytes and messages never are.
+        assert!(Writer::default().uint(1, 0).double(2, 0.0).string(3, "").boolean(4, false).done().is_empty());
+        assert_eq!(Writer::default().bytes(4, b"").done()[..], [0x22, 0x00]);
+        let mut inner = Writer::default();
+        inner.uint(1, 1);
+        assert_eq!(Writer::default().message(2, inner).done()[..], [0x12, 0x02, 0x08, 0x01]);
+    }
+
+    #[test]
+    fn reads_fields_back_in_wire_order() {
+        let message = Writer::default().string(1, "inter").uint(300, 7).double(4, 12.5).float(9, 2.0).done();
+        let read = fields(&message).unwrap();
+        assert_eq!(read.iter().map(|(n, _)| *n).collect::<Vec<_>>(), [1, 300, 4, 9]);
+        assert_eq!(read[0].1.text("family").unwrap(), "inter");
+        assert_eq!(read[1].1.uint("n").unwrap(), 7);
+        assert_eq!(read[2].1.double("max_size_kb").unwrap(), 12.5);
+        assert!(matches!(read[3].1, Field::Fixed32));
+        assert_eq!(read[0].1.uint("quality").unwrap_err(), "quality must be a varint");
+        assert_eq!(read[1].1.text("family").unwrap_err(), "family must be a string");
+        assert_eq!(fields(&[0x0a, 0x02, 0xff, 0xfe]).unwrap()[0].1.text("family").unwrap_err(), "family is not UTF-8");
+    }
+
+    #[test]
+    fn refuses_malformed_messages() {
+        for message in [
+            &b"\x0a\x05int"[..],
+            b"\x0a",
+            b"\x08",
+            b"\x08\x80",
+            b"\x21\x00\x00",
+            b"\x25\x00",
+            b"\x0a\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01",
+        ] {
+            assert_eq!(fields(message).err().unwrap(), "malformed protobuf message", "{message:?}");
+        }
+        assert_eq!(fields(b"\x0b").err().unwrap(), "unsupported protobuf wire type 3");
+    }
+
+    #[test]
+    fn decodes_a_compress_request() {
+        let message = b"\x0a\x05inter\x1a\x05woff2\x20\x50\x30\x01\x78\x07";
+        let req = compress_request(message).unwrap();
+        assert_eq!((req.font_name.as_str(), req.transform.format.as_str()), ("inter", "woff2"));
+        assert_eq!(req.transform.quality, Some(80));
+        assert!(req.dry_run && !req.optimize_outlines && req.font_id.is_none());
+        assert_eq!(compress_request(b"\x20\xac\x02").err().unwrap(), "quality must be 0-100");
+    }
+
+    #[test]
+    fn frames_and_unframes_one_message() {
+        let framed = frame(Bytes::from_static(b"abc"));
+        assert_eq!(framed[..], [0, 0, 0, 0, 3, b'a', b'b', b'c']);
+        assert_eq!(unframe(&framed).unwrap(), b"abc");
+        assert_eq!(unframe(&frame(Bytes::new())).unwrap(), b"");
+
+        assert_eq!(unframe(&[1, 0, 0, 0, 0]).unwrap_err().0, StatusCode::NOT_IMPLEMENTED, "compressed");
+        for body in [&[0, 0, 0][..], &[0, 0, 0, 0, 4, 1, 2, 3], &[0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 1, 2]] {
+            assert_eq!(unframe(body).unwrap_err().0, StatusCode::BAD_REQUEST, "{body:?}");
+        }
+    }
+
+    #[test]
+    fn trailers_carry_the_status_and_an_encoded_message() {
+        let ok = trailers(None);
+        assert_eq!(ok["grpc-status"], "0");
+        assert!(!ok.contains_key("grpc-message"));
+        let failed = trailers(Some((StatusCode::NOT_FOUND, "no font 'Noto Sans—JP' (100%)".to_string())));
+        assert_eq!(failed["grpc-status"], "5");
+        assert_eq!(failed["grpc-message"], "no font 'Noto Sans%E2%80%94JP' (100%25)");
+        assert_eq!(trailers(Some((StatusCode::TOO_MANY_REQUESTS, String::new())))["grpc-status"], "8");
+    }
+
+    async fn next_frame(body: &mut Reply) -> Option<Frame<Bytes>> {
+        let frame = std::future::poll_fn(|cx| http_body::Body::poll_frame(Pin::new(&mut *body), cx)).await;
+        frame.map(|frame| frame.unwrap())
+    }
+
+    async fn frames(messages: Vec<Result<Bytes, Error>>) -> (Vec<Bytes>, HeaderMap) {
+        let mut body = Reply { messages: stream::iter(messages).boxed(), done: false };
+        let mut data = Vec::new();
+        loop {
+            match next_frame(&mut body).await.unwrap().into_data() {
+                Ok(bytes) => data.push(bytes),
+                Err(frame) => {
+                    assert!(next_frame(&mut body).await.is_none());
+                    return (data, frame.into_trailers().unwrap());
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn replies_end_with_the_status_in_trailers() {
+        let (data, trailers) = frames(vec![Ok(Bytes::from_static(b"a")), Ok(Bytes::from_static(b"bc"))]).await;
+        assert_eq!(data, [&b"\0\0\0\0\x01a"[..], b"\0\0\0\0\x02bc"]);
+        assert_eq!(trailers["grpc-status"], "0");
+
+        let failure = Err((StatusCode::SERVICE_UNAVAILABLE, "storage down".to_string()));
+        let (data, trailers) = frames(vec![Ok(Bytes::from_static(b"a")), failure, Ok(Bytes::new())]).await;
+        assert_eq!(data.len(), 1, "nothing is sent after a failure");
+        assert_eq!(trailers["grpc-status"], "14");
+        assert_eq!(trailers["grpc-message"], "storage down");
+    }
+}
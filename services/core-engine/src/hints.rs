@@ -0,0 +1,257 @@
+//! Resource hints (`preconnect`, `dns-prefetch`) for font stylesheets.
+//!
+//! Hints are configured globally and per kit (the tenant in
+//! `X-Font-Tenant`, set by the gateway); a kit's own list replaces the
+//! global one. Global hints start from `RESOURCE_HINTS` (e.g.
+//! `preconnect=https://fonts.example.com,dns-prefetch=https://cdn.example.net`)
+//! and can be changed through the admin API. The primary edge host is always
+//! preconnected, since every generated font URL points there. CSS responses
+//! carry the hints as `Link` headers and `/* hint */` comments, with
+//! `rel=preload` for their first few fonts (see [`crate::stylesheet`]), with
+//! the font's `integrity` once it is stored.
+//! `GET /api/v1/font/hints` returns them as `<link>` tags, and with
+//! `?family=` the family's preloads too, also as a `Link` header that a
+//! site can copy onto its HTML responses.
+//!
+//! The engine cannot send `103 Early Hints` itself (its HTTP server has no
+//! informational responses); CDNs that offer Early Hints build them from
+//! these `Link` headers.
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::Json,
+};
+use font_api::{HintConfig, HintSet, HintUpdate};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+use tracing::{info, warn};
+
+use crate::{artifacts, audit, extract::ApiJson, stylesheet, AppState};
+
+const MAX_HINTS: usize = 16;
+
+#[derive(Default)]
+pub struct ResourceHints {
+    set: RwLock<HintSet>,
+}
+
+/// `scheme://host[:port]` of an http(s) URL without path, query or
+/// credentials; hints are per origin.
+fn origin(url: &str) -> Option<String> {
+    let url = url.trim().trim_end_matches('/');
+    let (scheme, rest) = url.split_once("://")?;
+    let valid = matches!(scheme, "http" | "https")
+        && !rest.is_empty()
+        && rest.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'));
+    valid.then(|| url.to_ascii_lowercase())
+}
+
+fn validate(hints: &HintConfig) -> Result<(), String> {
+    if hints.preconnect.len() + hints.dns_prefetch.len() > MAX_HINTS {
+        return Err(format!("at most {MAX_HINTS} hints"));
+    }
+    for url in hints.preconnect.iter().chain(&hints.dns_prefetch) {
+        if origin(url).is_none() {
+            return Err(format!("'{url}' must be an http(s) origin like https://fonts.example.com"));
+        }
+    }
+    Ok(())
+}
+
+/// Origins of `hints`, deduplicated.
+fn normalized(hints: &HintConfig) -> HintConfig {
+    let clean = |list: &[String]| {
+        let mut out: Vec<String> = Vec::new();
+        for o in list.iter().filter_map(|u| origin(u)) {
+            if !out.contains(&o) {
+                out.push(o);
+            }
+        }
+        out
+    };
+    HintConfig { preconnect: clean(&hints.preconnect), dns_prefetch: clean(&hints.dns_prefetch) }
+}
+
+/// One resolved hint.
+#[derive(Debug, Clone, Serialize)]
+pub struct Hint {
+    rel: &'static str,
+    href: String,
+    /// Media type of a preloaded font.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    media_type: Option<&'static str>,
+    /// Subresource Integrity value of a preloaded font.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    integrity: Option<String>,
+}
+
+impl Hint {
+    /// A font to fetch before the stylesheet asks for it.
+    pub fn preload(href: String, media_type: &'static str) -> Self {
+        Self { rel: "preload", href, media_type: Some(media_type), integrity: None }
+    }
+
+    /// Adds the `integrity` of a preload whose file is stored (see
+    /// [`artifacts::integrity`]).
+    pub async fn add_integrity(&mut self, state: &AppState) {
+        let Some(key) = (self.rel == "preload").then(|| artifacts::key_of(&self.href)).flatten() else { return };
+        match state.artifacts.digest(key).await {
+            Ok(digest) => self.integrity = digest.as_deref().and_then(artifacts::integrity),
+            Err(e) => warn!(key, error = %e, "cannot read artifact digest"),
+        }
+    }
+
+    /// Fonts are fetched in CORS mode, so a preconnect or preload must be
+    /// `crossorigin` or the browser fetches the font again.
+    fn crossorigin(&self) -> bool {
+        matches!(self.rel, "preconnect" | "preload")
+    }
+
+    fn link_header(&self) -> String {
+        let font = self.media_type.map(|t| format!("; as=font; type=\"{t}\"")).unwrap_or_default();
+        let cors = if self.crossorigin() { "; crossorigin" } else { "" };
+        let integrity = self.integrity.as_ref().map(|i| format!("; integrity=\"{i}\"")).unwrap_or_default();
+        format!("<{}>; rel={}{font}{cors}{integrity}", self.href, self.rel)
+    }
+
+    fn link_tag(&self) -> String {
+        let font = self.media_type.map(|t| format!(" as=\"font\" type=\"{t}\"")).unwrap_or_default();
+        let cors = if self.crossorigin() { " crossorigin" } else { "" };
+        let integrity = self.integrity.as_ref().map(|i| format!(" integrity=\"{i}\"")).unwrap_or_default();
+        format!("<link rel=\"{}\" href=\"{}\"{font}{cors}{integrity}>", self.rel, self.href)
+    }
+}
+
+impl ResourceHints {
+    pub fn from_env() -> Self {
+        let mut global = HintConfig::default();
+        let spec = std::env::var("RESOURCE_HINTS").unwrap_or_default();
+        for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match item.split_once('=').map(|(rel, url)| (rel, origin(url))) {
+                Some(("preconnect", Some(o))) => global.preconnect.push(o),
+                Some(("dns-prefetch", Some(o))) => global.dns_prefetch.push(o),
+                _ => warn!("ignoring invalid RESOURCE_HINTS entry '{item}'"),
+            }
+        }
+        Self { set: RwLock::new(HintSet { global: normalized(&global), tenants: BTreeMap::new() }) }
+    }
+
+    /// Hints for the calling kit, with `edge_url` (any generated font URL)
+    /// preconnected first.
+    pub fn resolve(&self, headers: &HeaderMap, edge_url: &str) -> Vec<Hint> {
+        let tenant = headers.get("x-font-tenant").and_then(|v| v.to_str().ok());
+        let set = self.set.read().unwrap();
+        let config = tenant.and_then(|t| set.tenants.get(t)).unwrap_or(&set.global);
+        let edge = edge_url.split_once("://").and_then(|(scheme, rest)| {
+            origin(&format!("{scheme}://{}", rest.split('/').next().unwrap_or_default()))
+        });
+        let mut hints: Vec<Hint> = Vec::new();
+        for href in edge.iter().chain(&config.preconnect) {
+            if !hints.iter().any(|h| &h.href == href) {
+                hints.push(Hint { rel: "preconnect", href: href.clone(), media_type: None, integrity: None });
+            }
+        }
+        for href in &config.dns_prefetch {
+            if !hints.iter().any(|h| &h.href == href) {
+                hints.push(Hint { rel: "dns-prefetch", href: href.clone(), media_type: None, integrity: None });
+            }
+        }
+        hints
+    }
+}
+
+/// `Link` header value for `hints`, if any.
+pub fn link_header(hints: &[Hint]) -> Option<HeaderValue> {
+    if hints.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(&hints.iter().map(Hint::link_header).collect::<Vec<_>>().join(", ")).ok()
+}
+
+/// `/* hint: ... */` lines to prepend to a stylesheet.
+pub fn css_comments(hints: &[Hint]) -> String {
+    hints
+        .iter()
+        .map(|h| match &h.integrity {
+            Some(integrity) => format!("/* hint: {} {} integrity={integrity} */\n", h.rel, h.href),
+            None => format!("/* hint: {} {} */\n", h.rel, h.href),
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HintsQuery {
+    /// Adds the family's preloads and stylesheet link after the hints.
+    family: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HintsResponse {
+    hints: Vec<Hint>,
+    /// Ready to paste into `<head>`, hints first.
+    links: Vec<String>,
+}
+
+/// Recommended `<link>` tags for the calling kit, and the same as a `Link`
+/// header.
+pub async fn recommended(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<HintsQuery>,
+) -> (HeaderMap, Json<HintsResponse>) {
+    let family = query.family.as_deref().map(|family| family.trim().to_lowercase().replace(' ', "-"));
+    let mut hints = state.hints.resolve(&headers, &state.edges.url_for("/"));
+    // An unknown family just has nothing to preload.
+    if let Some(family) = &family {
+        hints.extend(stylesheet::preloads(&state, &headers, family).await);
+    }
+    let mut links: Vec<String> = hints.iter().map(Hint::link_tag).collect();
+    links.extend(family.map(|family| format!("<link rel=\"stylesheet\" href=\"/api/v1/font/css/{family}\">")));
+    let mut response_headers = HeaderMap::new();
+    if let Some(link) = link_header(&hints) {
+        response_headers.insert(header::LINK, link);
+    }
+    (response_headers, Json(HintsResponse { hints, links }))
+}
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<HintSet>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    Ok(Json(state.hints.set.read().unwrap().clone()))
+}
+
+pub async fn update(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(update): ApiJson<HintUpdate>,
+) -> Result<Json<HintSet>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    validate(&update.hints).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let hints = normalized(&update.hints);
+    let (before, set) = {
+        let mut set = state.hints.set.write().unwrap();
+        let before = set.clone();
+        match &update.tenant {
+            // An empty list drops the kit override and falls back to global.
+            Some(tenant) if hints == HintConfig::default() => {
+                set.tenants.remove(tenant);
+            }
+            Some(tenant) => {
+                set.tenants.insert(tenant.clone(), hints);
+            }
+            None => set.global = hints,
+        }
+        (before, set.clone())
+    };
+    let target = update.tenant.as_deref().unwrap_or("global");
+    audit::record(&state, &headers, "hints.update", target, json!(before), json!(set)).await;
+    info!(tenant = ?update.tenant, "resource hints updated");
+    Ok(Json(set))
+}
@@ -0,0 +1,145 @@
+//! Incremental font transfer in the patch-subset model.
+//!
+//! A client first asks for a minimal subset (`have` empty), then, as a page
+//! needs more characters, posts the code points it holds and the ones it
+//! needs and gets back a VCDIFF patch (see [`crate::vcdiff`]) turning its
+//! cached font into the subset of both, rather than that whole subset
+//! again. The server keeps no per-client state: it rebuilds the held subset
+//! from `have`, which is exact as subsets are deterministic and keep glyph
+//! IDs, and checks it against `base_checksum`. When the two differ (the
+//! font changed since, or the client lost track) the patch is made against
+//! an empty base and the response says `replacement`.
+//!
+//! Fonts stay in their sfnt flavor (ttf or otf): a patch against compressed
+//! bytes would be no smaller than the font.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use base64::Engine;
+use font_api::PatchSubsetRequest;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeSet, ops::RangeInclusive, sync::Arc};
+use tracing::info;
+
+use crate::{
+    artifacts, cancel, compress, duplicates, extract::ApiJson, subset, telemetry, tenants, unicode, usage_events,
+    vcdiff, AppState,
+};
+
+#[derive(Debug, Serialize)]
+pub struct PatchSubsetResponse {
+    font_name: String,
+    format: &'static str,
+    /// The patch applies to an empty base: the client's font is replaced.
+    replacement: bool,
+    patch_format: &'static str,
+    /// Base64.
+    patch: String,
+    patch_bytes: usize,
+    /// What the patched font covers: `have` for the next request.
+    codepoints: String,
+    /// SHA-256 of the patched font: `base_checksum` for the next request.
+    checksum: String,
+    subset_bytes: usize,
+    glyph_count: usize,
+}
+
+/// The subset of `data` for `ranges`, in its sfnt flavor.
+fn build(data: &[u8], ranges: &[RangeInclusive<u32>]) -> Result<(Vec<u8>, subset::Report), String> {
+    let mut font = compress::load(data)?;
+    let wanted: BTreeSet<u32> = ranges.iter().flat_map(|r| r.clone()).collect();
+    let report = subset::subset(&mut font, &wanted, true, false)?;
+    Ok((font.to_bytes(), report))
+}
+
+pub async fn patch_subset(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(mut req): ApiJson<PatchSubsetRequest>,
+) -> Result<Json<PatchSubsetResponse>, (StatusCode, String)> {
+    state.flags.ensure("subset", &headers)?;
+    state.quotas.charge_operation(&headers)?;
+
+    let (font_name, upload) = state.uploads.resolve(&headers, req.font_id.as_deref(), &req.font_name).await?;
+    req.font_name = font_name;
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &req.font_name)?;
+    let have = unicode::parse_list("have", &req.have)?;
+    let needed = unicode::parse_list("needed", &req.needed)?;
+    if needed.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "needed is required".to_string()));
+    }
+    let codepoints = unicode::merge(have.iter().chain(&needed).cloned().collect());
+    let count = codepoints.iter().map(|r| (r.end() - r.start() + 1) as usize).sum();
+    state.sandbox.ensure_characters(&headers, count)?;
+
+    let upload = match upload {
+        Some(u) => Some(state.uploads.read(&u).await?),
+        None => None,
+    };
+    let (job_state, font_name, base_checksum) = (Arc::clone(&state), req.font_name.clone(), req.base_checksum.clone());
+    let job_codepoints = codepoints.clone();
+    let (original_bytes, format, replacement, patch, subset, report) =
+        cancel::run(&state.jobs, "patch-subset", move |token| {
+            let data = match upload {
+                Some(data) => Ok(data),
+                None => duplicates::catalog_binary(&job_state, &font_name),
+            };
+            token.check()?;
+            Ok(data.and_then(|data| {
+                let format = match compress::load(&data)?.table(b"CFF ") {
+                    Some(_) => "otf",
+                    None => "ttf",
+                };
+                let base = match &base_checksum {
+                    Some(expected) if !have.is_empty() => {
+                        let (base, _) = build(&data, &have)?;
+                        let matches = format!("{:x}", Sha256::digest(&base)).eq_ignore_ascii_case(expected.trim());
+                        matches.then_some(base)
+                    }
+                    _ => None,
+                };
+                let (subset, report) = build(&data, &job_codepoints)?;
+                let patch = vcdiff::encode(base.as_deref().unwrap_or_default(), &subset);
+                Ok((data.len(), format, base.is_none(), patch, subset, report))
+            }))
+        })
+        .await?
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    telemetry::record_output("patch-subset", format, original_bytes, patch.len());
+    state.analytics.record_subset(&req.font_name, format, "progressive", original_bytes, patch.len());
+    let usage = usage_events::Usage {
+        kind: "patch-subset",
+        tenant: tenants::of(&headers),
+        font: &artifacts::slug(&req.font_name),
+        format,
+        bytes: patch.len() as u64,
+        cache: "miss",
+    };
+    state.usage_events.record(&headers, usage);
+    info!(
+        font = %req.font_name,
+        replacement,
+        glyphs = report.glyphs,
+        subset_bytes = subset.len(),
+        patch_bytes = patch.len(),
+        "font subset patched"
+    );
+
+    Ok(Json(PatchSubsetResponse {
+        font_name: req.font_name,
+        format,
+        replacement,
+        patch_format: "vcdiff",
+        patch_bytes: patch.len(),
+        patch: base64::engine::general_purpose::STANDARD.encode(&patch),
+        codepoints: unicode::css_unicode_range(&codepoints),
+        checksum: format!("{:x}", Sha256::digest(&subset)),
+        subset_bytes: subset.len(),
+        glyph_count: report.glyphs,
+    }))
+}
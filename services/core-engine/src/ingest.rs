@@ -0,0 +1,336 @@
+//! Bulk catalog ingestion, for migrating an existing font library.
+//!
+//! `POST /api/v1/admin/ingest` takes a ZIP archive as the body (at most
+//! `MAX_UPLOAD_BYTES`); at startup, `INGEST_DIR` is scanned the same way,
+//! subdirectories included. Every `.ttf`, `.otf`, `.woff`, `.ttc` and `.otc`
+//! file found is validated and becomes a catalog entry per face, its binary
+//! written to `CATALOG_FONT_DIR` as `<id>.ttf|otf`; `.woff2` files are
+//! reported as failed, as they cannot be decoded back to the original:
+//!
+//! - family and variant are the typographic names in its `name` table
+//!   (IDs 16 and 17, else 1 and 2), the ID their slug (`inter` for Inter
+//!   Regular, `inter-bold-italic` for Inter Bold Italic);
+//! - formats are `woff2`, `woff` and `ttf` or `otf` by its outlines;
+//! - glyph count and unicode ranges are read from it;
+//! - the license is the SPDX ID of the OFL, Apache or Ubuntu Font License it
+//!   declares (see [`licensing::spdx`]), else `?license=` (`INGEST_LICENSE`
+//!   for the scan); without either the face is not ingested;
+//! - the foundry is its manufacturer name, else `?foundry=`, and its
+//!   copyright notice is the `attribution`.
+//!
+//! IDs already in the catalog are skipped, so a library can be ingested
+//! again after adding to it. A face colliding with another entry's name
+//! fails unless `?resolution=` says how to proceed, as in [`crate::catalog`].
+//! Failures are reported by file (`<file>#<face>` in collections) without
+//! stopping the rest. `INGEST_DIR` must not be `CATALOG_FONT_DIR` itself.
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{CatalogImport, FontCatalogEntry};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::{info, warn};
+
+use crate::{
+    analysis, artifacts, audit, cancel, catalog,
+    collision::{self, ResolutionParams},
+    compress, duplicates, licensing,
+    name::NameTable,
+    sfnt::{self, Font},
+    spool, validation, zip, AppState,
+};
+
+const EXTENSIONS: &[&str] = &["ttf", "otf", "woff", "woff2", "ttc", "otc"];
+/// Font files one archive or scan ingests.
+const MAX_FILES: usize = 2_000;
+const FAMILY: u16 = 1;
+const SUBFAMILY: u16 = 2;
+const POSTSCRIPT_NAME: u16 = 6;
+const MANUFACTURER: u16 = 8;
+const TYPOGRAPHIC_FAMILY: u16 = 16;
+const TYPOGRAPHIC_SUBFAMILY: u16 = 17;
+
+/// `inter` for Inter Regular, `inter-bold-italic` for Inter Bold Italic.
+pub fn entry_id(family: &str, variant: &str) -> String {
+    match variant {
+        "Regular" => artifacts::slug(family),
+        v => artifacts::slug(&format!("{family} {v}")),
+    }
+}
+
+/// A font binary as an sfnt, with the catalog entry its tables describe.
+/// `license` is empty when the font declares none [`licensing::spdx`]
+/// knows.
+pub fn describe(data: &[u8]) -> Result<(Vec<u8>, FontCatalogEntry), String> {
+    let font = compress::load(data)?;
+    let sfnt = font.to_bytes();
+    let problems = validation::validate(&sfnt).problems();
+    if !problems.is_empty() {
+        return Err(format!("fails validation: {}", problems.join("; ")));
+    }
+    let facts = analysis::facts(&sfnt)?;
+    let embedded = licensing::read(&font);
+    let names = font.table(b"name").and_then(|n| NameTable::parse(n).ok());
+    let name = |ids: &[u16]| {
+        let names = names.as_ref()?;
+        ids.iter().find_map(|&id| names.get(id).filter(|s| !s.trim().is_empty()))
+    };
+    let family = name(&[TYPOGRAPHIC_FAMILY, FAMILY]).ok_or("no family name")?;
+    let variant = name(&[TYPOGRAPHIC_SUBFAMILY, SUBFAMILY]).unwrap_or_else(|| "Regular".to_string());
+    let entry = FontCatalogEntry {
+        id: entry_id(&family, &variant),
+        family,
+        variant,
+        formats: ["woff2", "woff", if sfnt.starts_with(b"OTTO") { "otf" } else { "ttf" }].map(String::from).to_vec(),
+        size_kb: sfnt.len() as f64 / 1024.0,
+        glyph_count: facts.glyph_count,
+        unicode_ranges: facts.unicode_ranges,
+        license: licensing::spdx(&embedded).unwrap_or_default().to_string(),
+        foundry: name(&[MANUFACTURER]),
+        postscript_name: name(&[POSTSCRIPT_NAME]),
+        defaults: None,
+        private: false,
+        version: 1,
+        attribution: embedded.copyright,
+        tenant: String::new(),
+        cache_control: None,
+    };
+    Ok((sfnt, entry))
+}
+
+/// Writes `sfnt` to `CATALOG_FONT_DIR` and registers `entry` once
+/// `params` resolves its collisions. Audited as `catalog.import` when
+/// `headers` are a request's.
+pub async fn register(
+    state: &AppState,
+    headers: Option<&HeaderMap>,
+    entry: FontCatalogEntry,
+    sfnt: &[u8],
+    params: &ResolutionParams,
+) -> Result<FontCatalogEntry, String> {
+    let entry = collision::resolve(entry, &state.catalog.read().unwrap(), params).map_err(|(_, e)| e)?;
+    let dir = duplicates::catalog_font_dir().ok_or("CATALOG_FONT_DIR is not configured")?;
+    let path = dir.join(format!("{}.{}", entry.id, entry.formats[2]));
+    tokio::fs::write(&path, sfnt).await.map_err(|e| format!("{}: {e}", path.display()))?;
+    let registered = match catalog::validate(&entry.id, &entry) {
+        Ok(()) => catalog::persist(state, &entry).await,
+        Err(e) => Err(e),
+    };
+    if let Err((_, e)) = registered {
+        let _ = tokio::fs::remove_file(&path).await;
+        return Err(e);
+    }
+    // new_version replaces the colliding entry.
+    let before = {
+        let mut catalog = state.catalog.write().unwrap();
+        match catalog.iter_mut().find(|e| e.id == entry.id) {
+            Some(existing) => Some(std::mem::replace(existing, entry.clone())),
+            None => {
+                catalog.push(entry.clone());
+                None
+            }
+        }
+    };
+    if let Some(headers) = headers {
+        audit::record(state, headers, "catalog.import", &entry.id, json!(before), json!(entry)).await;
+    }
+    Ok(entry)
+}
+
+fn is_font(path: &str) -> bool {
+    let file = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    let ext = file.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+    !file.starts_with('.') && ext.is_some_and(|ext| EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// A face found, or why a file or face could not be read, by where it was
+/// found.
+type Described = Vec<(String, Result<(Vec<u8>, FontCatalogEntry), String>)>;
+
+/// The faces of the font file `data` found at `path`.
+fn describe_file(path: &str, data: &[u8]) -> Described {
+    if let Err(problems) = sfnt::check_structure(data, data.len() as u64) {
+        return vec![(path.to_string(), Err(format!("not a usable font: {}", problems.join("; "))))];
+    }
+    let Some(count) = sfnt::collection_len(data) else { return vec![(path.to_string(), describe(data))] };
+    (0..count)
+        .map(|index| {
+            let face = Font::parse_face(data, index).and_then(|font| describe(&font.to_bytes()));
+            (format!("{path}#{index}"), face)
+        })
+        .collect()
+}
+
+fn too_many(found: usize) -> String {
+    format!("{found} font files; at most {MAX_FILES} are ingested at once")
+}
+
+/// Registers what was found: license and foundry fall back to the given
+/// ones, IDs already taken are skipped.
+async fn ingest(
+    state: &AppState,
+    headers: Option<&HeaderMap>,
+    found: Described,
+    query: &IngestQuery,
+) -> CatalogImport {
+    let mut report = CatalogImport::default();
+    let mut seen: HashMap<String, String> = HashMap::new();
+    for (path, described) in found {
+        let (sfnt, mut entry) = match described {
+            Ok(described) => described,
+            Err(e) => {
+                report.failed.insert(path, e);
+                continue;
+            }
+        };
+        if let Some(first) = seen.get(&entry.id) {
+            report.failed.insert(path, format!("same ID '{}' as {first}", entry.id));
+            continue;
+        }
+        seen.insert(entry.id.clone(), path.clone());
+        if state.catalog.read().unwrap().iter().any(|e| e.id == entry.id) {
+            report.skipped.push(entry.id);
+            continue;
+        }
+        if entry.license.is_empty() {
+            let Some(license) = query.license.clone().filter(|l| !l.trim().is_empty()) else {
+                report.failed.insert(path, "declares no OFL, Apache or Ubuntu Font License; pass license".to_string());
+                continue;
+            };
+            entry.license = license;
+        }
+        entry.foundry = entry.foundry.or_else(|| query.foundry.clone());
+        match register(state, headers, entry, &sfnt, &query.resolution).await {
+            Ok(entry) => report.imported.push(entry),
+            Err(e) => {
+                report.failed.insert(path, e);
+            }
+        }
+    }
+    report
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct IngestQuery {
+    /// SPDX ID for faces that declare no known license.
+    license: Option<String>,
+    foundry: Option<String>,
+    #[serde(flatten)]
+    resolution: ResolutionParams,
+}
+
+pub async fn archive(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<IngestQuery>,
+    body: Body,
+) -> Result<Json<CatalogImport>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    if duplicates::catalog_font_dir().is_none() {
+        let message = "CATALOG_FONT_DIR is not configured, so binaries cannot be stored";
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, message.to_string()));
+    }
+    let limit = spool::max_upload_bytes();
+    let file = spool::spool(body, limit).await?;
+    let data = tokio::fs::read(file.path())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("reading spooled body: {e}")))?;
+
+    let found = cancel::run(&state.jobs, "ingest", move |token| {
+        let entries = match zip::read(&data) {
+            Ok(entries) => entries,
+            Err(e) => return Ok(Err(e)),
+        };
+        let fonts: Vec<&zip::Entry> = entries.iter().filter(|e| is_font(&e.name)).collect();
+        if fonts.len() > MAX_FILES {
+            return Ok(Err(too_many(fonts.len())));
+        }
+        let mut found = Vec::new();
+        for entry in fonts {
+            token.check()?;
+            match entry.extract(limit) {
+                Ok(font) => found.extend(describe_file(&entry.name, &font)),
+                Err(e) => found.push((entry.name.clone(), Err(e))),
+            }
+        }
+        Ok(Ok(found))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let report = ingest(&state, Some(&headers), found, &query).await;
+    info!(
+        imported = report.imported.len(),
+        skipped = report.skipped.len(),
+        failed = report.failed.len(),
+        "archive ingested"
+    );
+    Ok(Json(report))
+}
+
+/// Font files under `dir`, sorted.
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else if is_font(&path.to_string_lossy()) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Ingests `INGEST_DIR`, once at startup.
+pub async fn run(state: Arc<AppState>) {
+    let Some(dir) = std::env::var("INGEST_DIR").ok().filter(|d| !d.is_empty()).map(PathBuf::from) else { return };
+    if duplicates::catalog_font_dir().is_none() {
+        warn!(dir = %dir.display(), "INGEST_DIR is set but CATALOG_FONT_DIR is not; nothing ingested");
+        return;
+    }
+    let scan_dir = dir.clone();
+    let found = tokio::task::spawn_blocking(move || {
+        let mut files = Vec::new();
+        walk(&scan_dir, &mut files).map_err(|e| format!("{}: {e}", scan_dir.display()))?;
+        if files.len() > MAX_FILES {
+            return Err(too_many(files.len()));
+        }
+        files.sort();
+        let mut found = Vec::new();
+        for path in files {
+            let name = path.strip_prefix(&scan_dir).unwrap_or(&path).to_string_lossy().into_owned();
+            match std::fs::read(&path) {
+                Ok(data) => found.extend(describe_file(&name, &data)),
+                Err(e) => found.push((name, Err(e.to_string()))),
+            }
+        }
+        Ok(found)
+    })
+    .await;
+    let found = match found {
+        Ok(Ok(found)) => found,
+        Ok(Err(e)) => return warn!("INGEST_DIR scan failed: {e}"),
+        Err(e) => return warn!("INGEST_DIR scan failed: {e}"),
+    };
+    let query = IngestQuery { license: std::env::var("INGEST_LICENSE").ok(), ..Default::default() };
+    let report = ingest(&state, None, found, &query).await;
+    for (path, e) in &report.failed {
+        warn!(file = %path, "not ingested: {e}");
+    }
+    info!(
+        dir = %dir.display(),
+        imported = report.imported.len(),
+        skipped = report.skipped.len(),
+        failed = report.failed.len(),
+        "INGEST_DIR ingested"
+    );
+}
@@ -0,0 +1,522 @@
+//! `POST /api/v1/font/catalog/:id/instances`: static fonts cut from a
+//! variable catalog entry, for targets that cannot use variable fonts.
+//!
+//! The body lists named instances by subfamily name (`"Light"`, `"Bold"`)
+//! or positions on the axes (`{"axes": {"wght": 550, "wdth": 87.5}}`, with
+//! an optional `name`); axes left out stay at their default, and an empty
+//! list takes every named instance. All of them are generated in one
+//! background job and registered (admin only) as variants of the entry's
+//! family, with IDs `<family>-<variant>`, copying its license and settings;
+//! either every instance is registered or none is.
+//!
+//! Each instance gets its outlines and advance widths at that position
+//! (`avar` applied, composite glyphs flattened), `OS/2` weight and width
+//! classes and style bits to match, and its names rewritten as
+//! [`crate::rename`] does. The variation tables go, and so do hinting
+//! instructions, which no longer fit the moved points. Kerning, other `GPOS`
+//! values and font-wide metrics keep the default instance's values. Only
+//! TrueType (`glyf`) fonts are supported; CFF2 fonts return `422`.
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{FontCatalogEntry, InstanceLocation, RenameRequest, StaticInstancesRequest};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+use tracing::info;
+use ttf_parser::{Face, GlyphId, OutlineBuilder, Tag};
+
+use crate::{
+    analysis, artifacts, audit, cancel::CancelToken, catalog, collision, compress, duplicates,
+    extract::ApiJson,
+    fvar::{Axis, Fvar},
+    glyf::{self, Outline, Point},
+    isolation::{self, Blob},
+    name::NameTable,
+    os2, rename,
+    sfnt::Font,
+    AppState,
+};
+
+const MAX_INSTANCES: usize = 32;
+
+/// The variation machinery, meaningless in a static font.
+const VARIATION_TABLES: [&[u8; 4]; 8] = [b"fvar", b"gvar", b"avar", b"cvar", b"HVAR", b"VVAR", b"MVAR", b"STAT"];
+
+const WEIGHT_NAMES: [(f64, &str); 9] = [
+    (100.0, "Thin"),
+    (200.0, "ExtraLight"),
+    (300.0, "Light"),
+    (400.0, "Regular"),
+    (500.0, "Medium"),
+    (600.0, "SemiBold"),
+    (700.0, "Bold"),
+    (800.0, "ExtraBold"),
+    (900.0, "Black"),
+];
+
+/// `usWidthClass` 1-9 by `wdth` percentage.
+const WIDTH_CLASSES: [f64; 9] = [50.0, 62.5, 75.0, 87.5, 100.0, 112.5, 125.0, 150.0, 200.0];
+
+fn bad_request(message: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message)
+}
+
+fn unprocessable(message: String) -> (StatusCode, String) {
+    (StatusCode::UNPROCESSABLE_ENTITY, message)
+}
+
+/// One static font to generate.
+#[derive(Serialize, Deserialize)]
+struct Planned {
+    /// The variant name.
+    name: String,
+    /// A value for every axis, in `fvar` order.
+    values: Vec<(String, f64)>,
+}
+
+/// `Bold`, `Weight 550 Italic`, `SemiBold wdth 87.5`: the weight, other axes
+/// off their default, then `Italic`.
+fn derived_name(axes: &[Axis], values: &[(String, f64)]) -> String {
+    let (mut weight, mut others, mut italic) = (None, Vec::new(), false);
+    for (axis, (tag, value)) in axes.iter().zip(values) {
+        match tag.as_str() {
+            "wght" => {
+                let named = WEIGHT_NAMES.iter().find(|(w, _)| w == value);
+                weight = Some(named.map_or_else(|| format!("Weight {value}"), |(_, name)| name.to_string()));
+            }
+            "ital" => italic = *value >= 0.5,
+            "slnt" => italic = *value != 0.0,
+            _ if *value != axis.default => others.push(format!("{tag} {value}")),
+            _ => {}
+        }
+    }
+    let weight = weight.filter(|w| w != "Regular" || (others.is_empty() && !italic));
+    let mut parts: Vec<String> = weight.into_iter().chain(others).collect();
+    if italic {
+        parts.push("Italic".to_string());
+    }
+    if parts.is_empty() {
+        "Regular".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+/// Resolves the request against the font's `fvar`.
+fn plan(font: &Font, locations: &[InstanceLocation]) -> Result<Vec<Planned>, (StatusCode, String)> {
+    let fvar = font.table(b"fvar").ok_or_else(|| unprocessable("not a variable font: it has no fvar table".into()))?;
+    let fvar = Fvar::parse(fvar).map_err(unprocessable)?;
+    let names = font.table(b"name").and_then(|t| NameTable::parse(t).ok());
+    let tags: Vec<&str> = fvar.axes.iter().map(|a| a.tag.as_str()).collect();
+    let named: Vec<Planned> = fvar
+        .instances
+        .iter()
+        .filter_map(|i| {
+            let name = names.as_ref()?.get(i.subfamily_name_id)?.trim().to_string();
+            let values = tags.iter().map(|t| t.to_string()).zip(i.coordinates.iter().copied()).collect();
+            Some(Planned { name, values })
+        })
+        .collect();
+    if locations.is_empty() {
+        if named.is_empty() {
+            return Err(bad_request("the font has no named instances; list axis positions".to_string()));
+        }
+        return Ok(named);
+    }
+
+    let mut planned: Vec<Planned> = Vec::with_capacity(locations.len());
+    for location in locations {
+        let instance = match location {
+            InstanceLocation::Named(wanted) => {
+                let found = named.iter().find(|p| p.name.eq_ignore_ascii_case(wanted.trim())).ok_or_else(|| {
+                    let known: Vec<&str> = named.iter().map(|p| p.name.as_str()).collect();
+                    bad_request(format!("no named instance '{wanted}'; the font has: {}", known.join(", ")))
+                })?;
+                Planned { name: found.name.clone(), values: found.values.clone() }
+            }
+            InstanceLocation::Axes { axes, name } => {
+                if let Some(tag) = axes.keys().find(|t| !tags.contains(&t.as_str())) {
+                    return Err(bad_request(format!("no axis '{tag}'; the font has: {}", tags.join(", "))));
+                }
+                let mut values = Vec::with_capacity(fvar.axes.len());
+                for axis in &fvar.axes {
+                    let value = axes.get(&axis.tag).copied().unwrap_or(axis.default);
+                    if !(axis.min..=axis.max).contains(&value) {
+                        return Err(bad_request(format!(
+                            "{} {value} is outside the font's range {}-{}",
+                            axis.tag, axis.min, axis.max
+                        )));
+                    }
+                    values.push((axis.tag.clone(), value));
+                }
+                let name = match name.as_deref().map(str::trim) {
+                    Some(name) if !name.is_empty() => name.to_string(),
+                    _ => derived_name(&fvar.axes, &values),
+                };
+                Planned { name, values }
+            }
+        };
+        if planned.iter().any(|p| p.name.eq_ignore_ascii_case(&instance.name)) {
+            return Err(bad_request(format!("instance '{}' is listed twice", instance.name)));
+        }
+        planned.push(instance);
+    }
+    Ok(planned)
+}
+
+/// A glyph's contours as TrueType points, as the varied outline draws them,
+/// multiplied by `scale`.
+#[derive(Default)]
+struct Contours {
+    scale: f32,
+    ends: Vec<u16>,
+    points: Vec<Point>,
+    start: usize,
+}
+
+impl Contours {
+    fn push(&mut self, x: f32, y: f32, on_curve: bool) {
+        let (x, y) = ((x * self.scale).round() as i32, (y * self.scale).round() as i32);
+        self.points.push(Point { x, y, on_curve });
+    }
+}
+
+impl OutlineBuilder for Contours {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.start = self.points.len();
+        self.push(x, y, true);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push(x, y, true);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.push(x1, y1, false);
+        self.push(x, y, true);
+    }
+
+    fn curve_to(&mut self, _: f32, _: f32, _: f32, _: f32, x: f32, y: f32) {
+        // Only CFF outlines are cubic, and those are turned away first.
+        self.push(x, y, true);
+    }
+
+    /// Drops the point that returns to the start, and on-curve points
+    /// halfway between two off-curve ones, which TrueType implies.
+    fn close(&mut self) {
+        let mut contour = self.points.split_off(self.start);
+        if contour.len() > 1 && contour.first() == contour.last() {
+            contour.pop();
+        }
+        let n = contour.len();
+        let implied = |i: usize| {
+            let (p, prev, next) = (contour[i], contour[(i + n - 1) % n], contour[(i + 1) % n]);
+            n > 2
+                && p.on_curve
+                && !prev.on_curve
+                && !next.on_curve
+                && prev.x + next.x == 2 * p.x
+                && prev.y + next.y == 2 * p.y
+        };
+        let kept: Vec<Point> = (0..n).filter(|&i| !implied(i)).map(|i| contour[i]).collect();
+        if !kept.is_empty() {
+            self.points.extend(kept);
+            self.ends.push((self.points.len() - 1) as u16);
+        }
+    }
+}
+
+fn put(table: &mut [u8], at: usize, value: [u8; 2]) -> Result<(), String> {
+    table.get_mut(at..at + 2).ok_or("table truncated")?.copy_from_slice(&value);
+    Ok(())
+}
+
+/// Rewrites `tag` with `edit`, when the font has it.
+fn edit(font: &mut Font, tag: &[u8; 4], edit: impl FnOnce(&mut Vec<u8>) -> Result<(), String>) -> Result<(), String> {
+    let Some(mut table) = font.table(tag).map(<[u8]>::to_vec) else { return Ok(()) };
+    edit(&mut table).map_err(|e| format!("{}: {e}", String::from_utf8_lossy(tag)))?;
+    font.set_table(*tag, table);
+    Ok(())
+}
+
+/// Glyph `id` of `face` as a TrueType outline, coordinates multiplied by
+/// `scale`; `None` for a glyph without contours.
+pub fn outline(face: &Face, id: GlyphId, scale: f32) -> Option<Outline> {
+    let mut contours = Contours { scale, ..Default::default() };
+    face.outline_glyph(id, &mut contours)?;
+    if contours.points.is_empty() {
+        return None;
+    }
+    let mut outline =
+        Outline { bbox: [0; 4], ends: contours.ends, instructions: Vec::new(), points: contours.points, overlap: true };
+    outline.bbox = outline.bounds();
+    Some(outline)
+}
+
+/// Replaces the glyphs of `font` with `glyphs` (advance width and outline,
+/// by glyph ID), without hinting, and updates `hmtx` and the glyph counts,
+/// bounds and limits in `hhea`, `head` and `maxp` to match.
+pub fn replace_glyphs(font: &mut Font, glyphs: Vec<(u16, Option<Outline>)>) -> Result<(), String> {
+    let count = u16::try_from(glyphs.len()).map_err(|_| "more than 65535 glyphs")?;
+    let max_points = glyphs.iter().flat_map(|(_, o)| o.as_ref().map(|o| o.points.len())).max().unwrap_or(0);
+    let max_contours = glyphs.iter().flat_map(|(_, o)| o.as_ref().map(|o| o.ends.len())).max().unwrap_or(0);
+    let data: Vec<Vec<u8>> =
+        glyphs.iter().map(|(_, o)| o.as_ref().map(Outline::to_bytes).unwrap_or_default()).collect();
+    let metrics: Vec<(u16, Option<[i16; 4]>)> = glyphs.into_iter().map(|(a, o)| (a, o.map(|o| o.bbox))).collect();
+    glyf::rebuild(font, &data)?;
+
+    let hmtx: Vec<u8> = metrics
+        .iter()
+        .flat_map(|(advance, bbox)| [advance.to_be_bytes(), bbox.map_or(0, |b| b[0]).to_be_bytes()])
+        .flatten()
+        .collect();
+    font.set_table(*b"hmtx", hmtx);
+    let inked = || metrics.iter().filter_map(|&(advance, bbox)| bbox.map(|b| (advance as i32, b.map(i32::from))));
+    let extreme = |f: fn(i32, i32) -> i32, v: &dyn Fn(i32, [i32; 4]) -> i32| inked().map(|(a, b)| v(a, b)).reduce(f);
+    let bounds = [
+        extreme(i32::min, &|_, b| b[0]),
+        extreme(i32::min, &|_, b| b[1]),
+        extreme(i32::max, &|_, b| b[2]),
+        extreme(i32::max, &|_, b| b[3]),
+    ];
+    edit(font, b"hhea", |hhea| {
+        let advance_max = metrics.iter().map(|(a, _)| *a).max().unwrap_or(0);
+        put(hhea, 10, advance_max.to_be_bytes())?;
+        put(hhea, 12, (bounds[0].unwrap_or(0) as i16).to_be_bytes())?;
+        put(hhea, 14, (extreme(i32::min, &|a, b| a - b[2]).unwrap_or(0) as i16).to_be_bytes())?;
+        put(hhea, 16, (bounds[2].unwrap_or(0) as i16).to_be_bytes())?;
+        put(hhea, 34, count.to_be_bytes())
+    })?;
+    edit(font, b"head", |head| {
+        for (i, v) in bounds.iter().enumerate() {
+            put(head, 36 + 2 * i, (v.unwrap_or(0) as i16).to_be_bytes())?;
+        }
+        Ok(())
+    })?;
+    edit(font, b"maxp", |maxp| {
+        put(maxp, 4, count.to_be_bytes())?;
+        if maxp.len() < 32 {
+            return Ok(());
+        }
+        put(maxp, 6, (max_points as u16).to_be_bytes())?;
+        put(maxp, 8, (max_contours as u16).to_be_bytes())?;
+        // Composite limits and instruction sizes: there are none left.
+        for at in [10, 12, 26, 28, 30] {
+            put(maxp, at, [0, 0])?;
+        }
+        Ok(())
+    })?;
+    compress::strip_hints(font)
+}
+/// `data` (a variable TrueType font) as a static font at `values`, named
+/// `name`; returns it with its names.
+fn instantiate(data: &[u8], name: &str, values: &[(String, f64)]) -> Result<(Font, BTreeMap<String, String>), String> {
+    let mut font = Font::parse(data)?;
+    if font.table(b"glyf").is_none() {
+        return Err("only TrueType (glyf) variable fonts can be instanced".to_string());
+    }
+    let mut face = Face::parse(data, 0).map_err(|e| format!("not a parseable font: {e}"))?;
+    for (tag, value) in values {
+        face.set_variation(Tag::from_bytes_lossy(tag.as_bytes()), *value as f32);
+    }
+
+    let glyphs = (0..face.number_of_glyphs())
+        .map(GlyphId)
+        .map(|id| (face.glyph_hor_advance(id).unwrap_or(0), outline(&face, id, 1.0)))
+        .collect();
+    font.tables.retain(|t| !VARIATION_TABLES.contains(&&t.tag));
+    replace_glyphs(&mut font, glyphs)?;
+    style(&mut font, name, values)?;
+    os2::refresh(&mut font)?;
+
+    let names = rename::apply(&mut font, &RenameRequest { subfamily: Some(name.to_string()), ..Default::default() })?;
+    Ok((font, names))
+}
+
+/// `font` as a static instance at `axes` (see [`crate::transform`]).
+pub fn at(font: &Font, axes: &BTreeMap<String, f64>) -> Result<Font, String> {
+    let location = InstanceLocation::Axes { axes: axes.clone(), name: None };
+    let planned = plan(font, &[location]).map_err(|(_, e)| e)?;
+    let instance = planned.first().expect("one location plans one instance");
+    Ok(instantiate(&font.to_bytes(), &instance.name, &instance.values)?.0)
+}
+
+/// `OS/2` weight and width classes, and the italic, bold and regular bits
+/// of `OS/2` and `head`, for the instance; `post`'s italic angle from
+/// `slnt`.
+fn style(font: &mut Font, name: &str, values: &[(String, f64)]) -> Result<(), String> {
+    let value = |tag: &str| values.iter().find(|(t, _)| t == tag).map(|(_, v)| *v);
+    let lower = name.to_lowercase();
+    let italic = value("ital").is_some_and(|v| v >= 0.5)
+        || value("slnt").is_some_and(|v| v != 0.0)
+        || lower.contains("italic")
+        || lower.contains("oblique");
+    let weight = value("wght").map(|w| w.round().clamp(1.0, 1000.0) as u16);
+    let bold = weight == Some(700);
+    edit(font, b"OS/2", |os2| {
+        if let Some(weight) = weight {
+            put(os2, 4, weight.to_be_bytes())?;
+        }
+        if let Some(width) = value("wdth") {
+            let class = WIDTH_CLASSES
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| (*a - width).abs().total_cmp(&(*b - width).abs()))
+                .map_or(5, |(i, _)| i as u16 + 1);
+            put(os2, 6, class.to_be_bytes())?;
+        }
+        let selection = crate::sfnt::be_u16(os2, 62).ok_or("table truncated")? & !0b110_0001;
+        let bits = u16::from(italic) | u16::from(bold) << 5 | u16::from(!italic && !bold) << 6;
+        put(os2, 62, (selection | bits).to_be_bytes())
+    })?;
+    edit(font, b"head", |head| {
+        let style = crate::sfnt::be_u16(head, 44).ok_or("table truncated")? & !0b11;
+        put(head, 44, (style | u16::from(bold) | u16::from(italic) << 1).to_be_bytes())
+    })?;
+    if let Some(slant) = value("slnt") {
+        edit(font, b"post", |post| {
+            let angle = ((slant * 65536.0).round() as i32).to_be_bytes();
+            post.get_mut(4..8).ok_or("table truncated")?.copy_from_slice(&angle);
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// A static instance and what the catalog entry needs from it.
+#[derive(Serialize, Deserialize)]
+pub struct Made {
+    name: String,
+    postscript_name: Option<String>,
+    glyph_count: usize,
+    unicode_ranges: Vec<String>,
+    font: Blob,
+}
+
+/// Plans and cuts the requested instances in the parse worker. The inner
+/// `Err` is a request the font cannot satisfy.
+#[derive(Serialize, Deserialize)]
+pub struct Instantiate {
+    instances: Vec<InstanceLocation>,
+}
+
+impl isolation::Task for Instantiate {
+    const NAME: &'static str = "instantiate";
+    type Output = Result<Vec<Made>, String>;
+
+    fn run(self, data: Vec<u8>, token: &CancelToken) -> Result<Self::Output, String> {
+        let planned = match plan(&Font::parse(&data)?, &self.instances) {
+            Ok(planned) => planned,
+            Err((StatusCode::BAD_REQUEST, e)) => return Ok(Err(e)),
+            Err((_, e)) => return Err(e),
+        };
+        if planned.len() > MAX_INSTANCES {
+            let count = planned.len();
+            return Ok(Err(format!("the font has {count} named instances; list at most {MAX_INSTANCES}")));
+        }
+        let mut made = Vec::with_capacity(planned.len());
+        for instance in planned {
+            token.check().map_err(|_| format!("{} was cancelled", Self::NAME))?;
+            let (font, names) =
+                instantiate(&data, &instance.name, &instance.values).map_err(|e| format!("{}: {e}", instance.name))?;
+            let bytes = font.to_bytes();
+            let facts = analysis::facts(&bytes).map_err(|e| format!("{}: {e}", instance.name))?;
+            made.push(Made {
+                name: instance.name,
+                postscript_name: names.get("postscript_name").cloned(),
+                glyph_count: facts.glyph_count,
+                unicode_ranges: facts.unicode_ranges,
+                font: Blob(bytes),
+            });
+        }
+        Ok(Ok(made))
+    }
+}
+
+pub async fn generate(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ApiJson(req): ApiJson<StaticInstancesRequest>,
+) -> Result<(StatusCode, Json<Vec<FontCatalogEntry>>), (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    if req.instances.len() > MAX_INSTANCES {
+        return Err(bad_request(format!("instances must list at most {MAX_INSTANCES} instances")));
+    }
+    let base = state.catalog.read().unwrap().iter().find(|e| e.id == id).cloned();
+    let base = base.ok_or_else(|| (StatusCode::NOT_FOUND, format!("no catalog entry '{id}'")))?;
+    let dir = duplicates::catalog_font_dir()
+        .ok_or_else(|| unprocessable("CATALOG_FONT_DIR is not configured".to_string()))?;
+    let path = duplicates::font_path(&dir, &id)
+        .ok_or_else(|| unprocessable(format!("no binary for '{id}' in CATALOG_FONT_DIR")))?;
+    let data = tokio::fs::read(&path)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{}: {e}", path.display())))?;
+    let made = isolation::run(&state, Instantiate { instances: req.instances.clone() }, data)
+        .await?
+        .map_err(unprocessable)?
+        .map_err(bad_request)?;
+    let prepared: Vec<(FontCatalogEntry, Vec<u8>)> = made
+        .into_iter()
+        .map(|made| {
+            let entry = FontCatalogEntry {
+                id: artifacts::slug(&format!("{} {}", base.family, made.name)),
+                formats: vec!["woff2".to_string(), "woff".to_string(), "ttf".to_string()],
+                variant: made.name,
+                size_kb: made.font.0.len() as f64 / 1024.0,
+                glyph_count: made.glyph_count,
+                unicode_ranges: made.unicode_ranges,
+                postscript_name: made.postscript_name,
+                version: 1,
+                ..base.clone()
+            };
+            (entry, made.font.0)
+        })
+        .collect();
+
+    // Every entry is checked against the catalog and the others before
+    // anything is written.
+    let mut entries: Vec<FontCatalogEntry> = Vec::new();
+    for (entry, _) in &prepared {
+        let taken = |e: &FontCatalogEntry| e.id == entry.id;
+        if state.catalog.read().unwrap().iter().any(taken) || entries.iter().any(taken) {
+            return Err((StatusCode::CONFLICT, format!("'{}' is already in the catalog", entry.id)));
+        }
+        let mut existing = state.catalog.read().unwrap().clone();
+        existing.extend(entries.iter().cloned());
+        entries.push(collision::resolve(entry.clone(), &existing, &Default::default())?);
+    }
+    let mut written: Vec<PathBuf> = Vec::new();
+    let stored = async {
+        for (entry, bytes) in &prepared {
+            let path = dir.join(format!("{}.ttf", entry.id));
+            tokio::fs::write(&path, bytes)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{}: {e}", path.display())))?;
+            written.push(path);
+            catalog::validate(&entry.id, entry)?;
+        }
+        for entry in &entries {
+            catalog::persist(&state, entry).await?;
+        }
+        Ok(())
+    };
+    if let Err(e) = stored.await {
+        for path in &written {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+        return Err(e);
+    }
+
+    state.catalog.write().unwrap().extend(entries.iter().cloned());
+    for entry in &entries {
+        audit::record(&state, &headers, "catalog.create", &entry.id, Value::Null, json!(entry)).await;
+    }
+    let ids: Vec<&str> = entries.iter().map(|e| e.id.as_str()).collect();
+    info!(source = %id, ids = ?ids, "static instances registered");
+    Ok((StatusCode::CREATED, Json(entries)))
+}
@@ -0,0 +1,231 @@
+//! Static instances of a variable font, for browsers without variable font
+//! support.
+//!
+//! One request names the weights/styles wanted and gets every instance plus
+//! a CSS bundle with one `@font-face` per instance, so a client can switch
+//! wholesale to the static set. Each instance is cut from the catalog binary
+//! as [`crate::instancer`] does (TrueType outlines only), at `wght` for the
+//! weight and, for italics, `ital` 1 or the steepest `slnt`, and stored as a
+//! content-addressed artifact. `legacy: true` adds EOT sources (with the
+//! `?#iefix` hack) ahead of the modern format for IE-era browsers. Of the
+//! shared [`crate::transform`] options, `format`, `quality` (100 unless
+//! given), `drop_features`/`keep_features` and `output_name` (the file
+//! names, before the weight) apply; `instance` is what `instances` lists,
+//! and hinting never survives instancing.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{InstanceSpec, InstancesRequest};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{collections::BTreeMap, sync::Arc};
+use tracing::info;
+
+use crate::{
+    artifacts,
+    cancel::CancelToken,
+    compress, duplicates,
+    extract::ApiJson,
+    fvar::Fvar,
+    instancer,
+    isolation::{self, Blob},
+    prune, signing, transform, AppState,
+};
+
+const MAX_INSTANCES: usize = 32;
+
+#[derive(Debug, Serialize)]
+pub struct StaticInstance {
+    weight: u16,
+    style: String,
+    size_kb: f64,
+    download_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eot_url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InstancesResponse {
+    font_name: String,
+    family: String,
+    format: String,
+    instances: Vec<StaticInstance>,
+    css: String,
+}
+
+/// The axis positions of `spec`: `wght` at its weight and, for italics,
+/// `ital` at 1 or else `slnt` at its steepest.
+fn axes(fvar: &Fvar, spec: &InstanceSpec) -> Result<BTreeMap<String, f64>, String> {
+    let axis = |tag: &str| fvar.axes.iter().find(|a| a.tag == tag);
+    if axis("wght").is_none() {
+        return Err("the font has no wght axis".to_string());
+    }
+    let mut axes = BTreeMap::from([("wght".to_string(), f64::from(spec.weight))]);
+    if spec.style == "italic" {
+        match (axis("ital"), axis("slnt")) {
+            (Some(_), _) => axes.insert("ital".to_string(), 1.0),
+            (None, Some(slnt)) => axes.insert("slnt".to_string(), slnt.min),
+            (None, None) => return Err("the font has no ital or slnt axis for italic instances".to_string()),
+        };
+    }
+    Ok(axes)
+}
+
+/// Cuts and encodes the instances in the parse worker.
+#[derive(Serialize, Deserialize)]
+pub struct Generate {
+    id: String,
+    specs: Vec<InstanceSpec>,
+    options: transform::Transform,
+    legacy: bool,
+}
+
+impl isolation::Task for Generate {
+    const NAME: &'static str = "instances";
+    type Output = Vec<(InstanceSpec, BTreeMap<String, f64>, Blob, Option<Blob>)>;
+
+    fn run(self, data: Vec<u8>, token: &CancelToken) -> Result<Self::Output, String> {
+        let font = compress::load(&data)?;
+        let fvar = match font.table(b"fvar").map(Fvar::parse) {
+            Some(fvar) => fvar?,
+            None => return Err(format!("'{}' is not a variable font: it has no fvar table", self.id)),
+        };
+        let quality = self.options.quality.unwrap_or(100);
+        let mut out = Vec::with_capacity(self.specs.len());
+        for spec in self.specs {
+            token.check().map_err(|_| format!("{} was cancelled", Self::NAME))?;
+            let axes = axes(&fvar, &spec)?;
+            let mut instance = instancer::at(&font, &axes)?;
+            if let Some(features) = &self.options.features {
+                prune::prune(&mut instance, features)?;
+            }
+            let eot = if self.legacy { Some(Blob(compress::encode(&instance, "eot", quality)?)) } else { None };
+            let encoded = Blob(compress::encode(&instance, &self.options.format, quality)?);
+            out.push((spec, axes, encoded, eot));
+        }
+        Ok(out)
+    }
+}
+
+pub async fn instances(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(req): ApiJson<InstancesRequest>,
+) -> Result<Json<InstancesResponse>, (StatusCode, String)> {
+    state.flags.ensure("instances", &headers)?;
+
+    let valid_formats = ["woff2", "woff", "ttf"];
+    let options = transform::resolve(&req.transform, &valid_formats, &Default::default(), &mut Vec::new())?;
+    if options.instance.is_some() {
+        return Err((StatusCode::BAD_REQUEST, "list the weights and styles in instances, not instance".to_string()));
+    }
+    if req.font_name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "font_name is required".to_string()));
+    }
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &req.font_name)?;
+    if req.instances.is_empty() || req.instances.len() > MAX_INSTANCES {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("instances must list 1-{MAX_INSTANCES} weight/style pairs"),
+        ));
+    }
+    let mut specs: Vec<InstanceSpec> = Vec::with_capacity(req.instances.len());
+    for spec in req.instances {
+        if !(1..=1000).contains(&spec.weight) {
+            return Err((StatusCode::BAD_REQUEST, format!("weight {} must be 1-1000", spec.weight)));
+        }
+        if !matches!(spec.style.as_str(), "normal" | "italic") {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("style '{}' must be normal or italic", spec.style),
+            ));
+        }
+        if !specs.contains(&spec) {
+            specs.push(spec);
+        }
+    }
+
+    let key = req.font_name.to_lowercase();
+    let (id, family) = state
+        .catalog
+        .read()
+        .unwrap()
+        .iter()
+        .find(|e| e.id == key || e.family.to_lowercase() == key)
+        .map(|e| (e.id.clone(), e.family.clone()))
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("font '{}' is not in the catalog", req.font_name)))?;
+    let source = state.artifacts.source(&state, &id, None).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let data = duplicates::read_catalog_binary(&state, &id).await.map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let task = Generate { id: id.clone(), specs, options: options.clone(), legacy: req.legacy };
+    let generated = isolation::run(&state, task, data).await?.map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let stem = options.output_name.clone().unwrap_or_else(|| artifacts::slug(&family));
+    let mut instances: Vec<StaticInstance> = Vec::with_capacity(generated.len());
+    for (spec, axes, Blob(encoded), eot) in generated {
+        let suffix = if spec.style == "italic" { "-italic" } else { "" };
+        let name = format!("{stem}-{}{suffix}", spec.weight);
+        let mut transform = json!({ "instance": axes });
+        if let Some(quality) = options.quality {
+            transform["compress"] = json!(quality);
+        }
+        if let Some(features) = &options.features {
+            transform["features"] = json!(features);
+        }
+        let size_kb = encoded.len() as f64 / 1024.0;
+        let mut urls = Vec::new();
+        for (format, data) in [(options.format.as_str(), encoded)].into_iter().chain(eot.map(|eot| ("eot", eot.0))) {
+            let address = state.artifacts.named_address(&family, Some(&name), &source, &transform, format);
+            state.artifacts.put(&address, &data, &json!({ "output_bytes": data.len() })).await?;
+            urls.push(signing::download_url(&state, &address.path()));
+        }
+        let mut urls = urls.into_iter();
+        instances.push(StaticInstance {
+            download_url: urls.next().expect("the modern format is always stored"),
+            eot_url: urls.next(),
+            weight: spec.weight,
+            style: spec.style,
+            size_kb,
+        });
+    }
+    let css = instances
+        .iter()
+        .map(|i| {
+            let modern = format!("url(\"{}\") format(\"{}\")", i.download_url, css_format(&options.format));
+            let src = match &i.eot_url {
+                Some(eot) => format!(
+                    "src: url(\"{eot}\");\n  src: url(\"{eot}?#iefix\") format(\"embedded-opentype\"),\n       {modern};"
+                ),
+                None => format!("src: {modern};"),
+            };
+            format!(
+                "@font-face {{\n  font-family: \"{family}\";\n  font-style: {};\n  font-weight: {};\n  \
+                 font-display: swap;\n  {src}\n}}\n",
+                i.style, i.weight
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    info!(font = %req.font_name, instances = instances.len(), format = %options.format, "static instance bundle");
+
+    Ok(Json(InstancesResponse {
+        font_name: req.font_name,
+        family,
+        format: options.format,
+        instances,
+        css,
+    }))
+}
+
+pub fn css_format(format: &str) -> &'static str {
+    match format {
+        "woff2" => "woff2",
+        "woff" => "woff",
+        "otf" => "opentype",
+        _ => "truetype",
+    }
+}
@@ -0,0 +1,301 @@
+//! Working on untrusted fonts in a separate process.
+//!
+//! Everything that parses a font it did not write itself runs as a
+//! [`Task`] in a child `font-engine --parse-worker <task>` process instead
+//! of the server: the first parse of an upload (the `/font/validate`
+//! checks, and the family and license read from its first face), and the
+//! transforms that follow — subset, compress, analyze, the instancers and
+//! rendering. A font that makes a parser panic (release builds abort on
+//! panic), loop or exhaust memory takes down only the worker. The worker
+//! gets the task and the font on stdin and nothing else: an empty
+//! environment, and on Linux no new files, no file writes, no core dumps,
+//! `PARSE_WORKER_MEMORY_MB` of address space (1024 by default) and the
+//! job's time limit of CPU time, set before it reads the task. It writes
+//! the task's output to stdout and the steps it completes to stderr, and is
+//! killed once the time limit passes or the client goes away. A worker
+//! that crashes or is killed fails the task with the reason, and an upload
+//! that did so goes into quarantine. `PARSE_ISOLATION=off` runs tasks in
+//! the server's blocking pool instead.
+
+use axum::http::StatusCode;
+use base64::Engine;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    io::{Read, Write},
+    process::Stdio,
+    sync::Arc,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    process::Command,
+};
+use tracing::warn;
+
+use crate::{
+    cancel::{self, CancelToken},
+    uploads::Details,
+    validation, AppState,
+};
+
+/// The argument that starts the engine binary as a worker.
+pub const WORKER_FLAG: &str = "--parse-worker";
+const DEFAULT_MEMORY_MB: u64 = 1024;
+/// Steps workers report, as [`cancel::report`] takes them.
+const STEPS: &[&str] = &["parse", "subset", "encode"];
+
+/// Work on font bytes that runs in the worker.
+pub trait Task: Serialize + DeserializeOwned + Send + 'static {
+    /// Names the task to the worker and the job to [`cancel::run`].
+    const NAME: &'static str;
+    type Output: Serialize + DeserializeOwned + Send + 'static;
+
+    fn run(self, data: Vec<u8>, token: &CancelToken) -> Result<Self::Output, String>;
+}
+
+/// Bytes that travel to and from the worker, as base64.
+#[derive(Debug, Default)]
+pub struct Blob(pub Vec<u8>);
+
+impl Serialize for Blob {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Blob {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        base64::engine::general_purpose::STANDARD.decode(text).map(Blob).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A `&'static str` field the derive would otherwise try to borrow; read it
+/// back with [`known_name`].
+pub type Known = &'static str;
+
+/// A name kept as `&'static str`, read back from a worker as the one of
+/// `known` it equals.
+pub fn known_name<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    known: &[&'static str],
+) -> Result<&'static str, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    let found = known.iter().find(|k| **k == name).copied();
+    found.ok_or_else(|| serde::de::Error::custom(format!("unknown name '{name}'")))
+}
+
+/// What a worker reads from stdin.
+#[derive(Serialize, Deserialize)]
+struct Request<T> {
+    task: T,
+    data: Blob,
+}
+
+/// What parsing an upload found.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Parsed {
+    /// Validation errors; the upload is quarantined unless empty.
+    pub problems: Vec<String>,
+    pub details: Details,
+}
+
+/// The first parse of an upload.
+#[derive(Serialize, Deserialize)]
+struct Inspect;
+
+impl Task for Inspect {
+    const NAME: &'static str = "validate";
+    type Output = Parsed;
+
+    fn run(self, data: Vec<u8>, _: &CancelToken) -> Result<Parsed, String> {
+        Ok(Parsed { problems: validation::validate(&data).problems(), details: Details::read(&data) })
+    }
+}
+
+pub fn isolated() -> bool {
+    std::env::var("PARSE_ISOLATION").map_or(true, |v| v != "off")
+}
+
+fn memory_mb() -> u64 {
+    std::env::var("PARSE_WORKER_MEMORY_MB").ok().and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_MEMORY_MB)
+}
+
+/// Parses an upload, handing it back with what was found. A font that
+/// crashes or stalls the worker is reported as a problem.
+pub async fn parse(state: &AppState, data: Vec<u8>) -> Result<(Vec<u8>, Parsed), (StatusCode, String)> {
+    let parsed = match run(state, Inspect, data.clone()).await? {
+        Ok(parsed) => parsed,
+        Err(problem) => Parsed { problems: vec![problem], details: Details::default() },
+    };
+    Ok((data, parsed))
+}
+
+/// Runs `task` on `data` in a worker. The inner `Err` is the task's own
+/// error, or why the worker crashed or was stopped.
+pub async fn run<T: Task>(
+    state: &AppState,
+    task: T,
+    data: Vec<u8>,
+) -> Result<Result<T::Output, String>, (StatusCode, String)> {
+    if !isolated() {
+        return cancel::run(&state.jobs, T::NAME, move |token| {
+            token.check()?;
+            Ok(task.run(data, token))
+        })
+        .await;
+    }
+    let internal = |e: std::io::Error| (StatusCode::INTERNAL_SERVER_ERROR, format!("starting {} worker: {e}", T::NAME));
+    let request = serde_json::to_vec(&Request { task, data: Blob(data) })
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("writing {} task: {e}", T::NAME)))?;
+    let timeout = cancel::limit();
+    let mut child = Command::new(std::env::current_exe().map_err(internal)?)
+        .args([WORKER_FLAG, T::NAME, &memory_mb().to_string(), &timeout.as_secs().to_string()])
+        .env_clear()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(internal)?;
+    let (mut stdin, mut stdout) = (child.stdin.take().expect("stdin is piped"), child.stdout.take().expect("piped"));
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let run = async {
+        let write = async {
+            // A worker that dies early closes the pipe; its exit status says why.
+            let _ = stdin.write_all(&request).await;
+            drop(stdin);
+        };
+        let mut output = Vec::new();
+        let read = stdout.read_to_end(&mut output);
+        let (_, read, messages) = tokio::join!(write, read, follow(stderr));
+        read?;
+        Ok::<_, std::io::Error>((child.wait().await?, output, messages))
+    };
+    let failure = match tokio::time::timeout(timeout, run).await {
+        Ok(Ok((status, output, _))) if status.success() => {
+            return serde_json::from_slice(&output)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("reading {} worker output: {e}", T::NAME)));
+        }
+        Ok(Ok((status, _, messages))) => {
+            warn!(task = T::NAME, status = %status, stderr = %messages.trim(), "worker crashed");
+            format!("the font crashed the {} worker ({status})", T::NAME)
+        }
+        Ok(Err(e)) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("running {} worker: {e}", T::NAME))),
+        Err(_) => {
+            warn!(task = T::NAME, timeout_secs = timeout.as_secs(), "worker timed out");
+            format!("{} did not finish within {}s", T::NAME, timeout.as_secs())
+        }
+    };
+    Ok(Err(failure))
+}
+
+/// Passes the steps a worker reports on to the job, returning the rest of
+/// what it wrote.
+async fn follow(stderr: tokio::process::ChildStderr) -> String {
+    let mut lines = BufReader::new(stderr).lines();
+    let mut messages = String::new();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let step = line.strip_prefix("step ").and_then(|s| s.split_once(' '));
+        match step.and_then(|(step, percent)| Some((STEPS.iter().find(|s| **s == step)?, percent.parse().ok()?))) {
+            Some((step, percent)) => cancel::report(step, percent),
+            None => {
+                messages.push_str(&line);
+                messages.push('\n');
+            }
+        }
+    }
+    messages
+}
+
+/// The worker: reads a task and a font from stdin and writes the task's
+/// output to stdout as JSON, under the memory and CPU limits in its
+/// arguments.
+pub fn worker() -> ! {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let limits: Vec<u64> = args.iter().skip(1).filter_map(|a| a.parse().ok()).collect();
+    let (memory_mb, cpu_secs) = match limits[..] {
+        [memory_mb, cpu_secs] => (memory_mb, cpu_secs),
+        _ => (DEFAULT_MEMORY_MB, cancel::processing_timeout().as_secs()),
+    };
+    let serve: fn(Vec<u8>) -> Vec<u8> = match args.first().map(String::as_str) {
+        Some(Inspect::NAME) => serve::<Inspect>,
+        Some(crate::SubsetTask::NAME) => serve::<crate::SubsetTask>,
+        Some(crate::CompressTask::NAME) => serve::<crate::CompressTask>,
+        Some(crate::AnalyzeTask::NAME) => serve::<crate::AnalyzeTask>,
+        Some(crate::instancer::Instantiate::NAME) => serve::<crate::instancer::Instantiate>,
+        Some(crate::instances::Generate::NAME) => serve::<crate::instances::Generate>,
+        Some(crate::preview::Render::NAME) => serve::<crate::preview::Render>,
+        Some(crate::waterfall::Draw::NAME) => serve::<crate::waterfall::Draw>,
+        Some(crate::sprite::Pack::NAME) => serve::<crate::sprite::Pack>,
+        Some(crate::features::Demos::NAME) => serve::<crate::features::Demos>,
+        other => {
+            eprintln!("unknown worker task {other:?}");
+            std::process::exit(2);
+        }
+    };
+    let mut input = Vec::new();
+    if let Err(e) = std::io::stdin().read_to_end(&mut input) {
+        eprintln!("reading task: {e}");
+        std::process::exit(2);
+    }
+    confine(memory_mb << 20, cpu_secs);
+    let out = serve(input);
+    let mut stdout = std::io::stdout();
+    if let Err(e) = stdout.write_all(&out).and_then(|()| stdout.flush()) {
+        eprintln!("writing result: {e}");
+        std::process::exit(2);
+    }
+    std::process::exit(0)
+}
+
+/// Runs the task in `input`, its output as JSON.
+fn serve<T: Task>(input: Vec<u8>) -> Vec<u8> {
+    let request: Request<T> = match serde_json::from_slice(&input) {
+        Ok(request) => request,
+        Err(e) => {
+            eprintln!("reading task: {e}");
+            std::process::exit(2);
+        }
+    };
+    drop(input);
+    let token = CancelToken::reporting(Arc::new(|step, percent| eprintln!("step {step} {percent}")));
+    let output = request.task.run(request.data.0, &token);
+    serde_json::to_vec(&output).expect("task output serializes")
+}
+#[cfg(target_os = "linux")]
+fn confine(memory_bytes: u64, cpu_secs: u64) {
+    use std::ffi::{c_int, c_ulong};
+
+    #[repr(C)]
+    struct Rlimit {
+        current: c_ulong,
+        max: c_ulong,
+    }
+    extern "C" {
+        fn setrlimit(resource: c_int, limit: *const Rlimit) -> c_int;
+    }
+    const RLIMIT_CPU: c_int = 0;
+    const RLIMIT_FSIZE: c_int = 1;
+    const RLIMIT_CORE: c_int = 4;
+    const RLIMIT_NOFILE: c_int = 7;
+    const RLIMIT_AS: c_int = 9;
+
+    for (resource, value) in [
+        (RLIMIT_AS, memory_bytes),
+        (RLIMIT_CPU, cpu_secs.max(1)),
+        (RLIMIT_FSIZE, 0),
+        (RLIMIT_CORE, 0),
+        (RLIMIT_NOFILE, 0),
+    ] {
+        let limit = Rlimit { current: value as c_ulong, max: value as c_ulong };
+        // SAFETY: setrlimit only reads the struct, which outlives the call.
+        if unsafe { setrlimit(resource, &limit) } != 0 {
+            eprintln!("setrlimit({resource}): {}", std::io::Error::last_os_error());
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Elsewhere the worker relies on the server's timeout alone.
+#[cfg(not(target_os = "linux"))]
+fn confine(_memory_bytes: u64, _cpu_secs: u64) {}
@@ -0,0 +1,189 @@
+//! Kerning pairs, reported by analyze with `include_kerning`.
+//!
+//! Pairs come from the legacy `kern` table (format 0 subtables) and from the
+//! GPOS pair adjustment lookups the `kern` feature uses: glyph pairs
+//! (format 1) and class pairs (format 2). Values are horizontal advance
+//! adjustments in font units. The largest adjustments show which pairs a
+//! subset or a kerning-stripped build would visibly lose.
+
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use ttf_parser::{kern, Face, Tag};
+
+use crate::{analysis::GlyphLabels, layout, sfnt::be_u16};
+
+/// How many of the largest adjustments are listed.
+const LARGEST: usize = 20;
+const EXTENSION: u16 = 9;
+const PAIR_ADJUSTMENT: u16 = 2;
+const X_PLACEMENT: u16 = 0x0001;
+const X_ADVANCE: u16 = 0x0004;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Kerning {
+    /// Pairs in `kern` format 0 subtables.
+    pub kern_table_pairs: usize,
+    /// Distinct glyph pairs in GPOS format 1 subtables.
+    pub gpos_pairs: usize,
+    /// Non-zero class pairs in GPOS format 2 subtables.
+    pub gpos_class_pairs: usize,
+    /// By absolute value, largest first.
+    pub largest: Vec<KernPair>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KernPair {
+    /// The character the glyph maps to, else its glyph name or `gid<n>`.
+    /// Class pairs show the first glyph of each class.
+    pub left: String,
+    pub right: String,
+    pub value: i16,
+    /// `kern`, `GPOS` or `GPOS class`.
+    #[serde(deserialize_with = "source")]
+    pub source: crate::isolation::Known,
+}
+
+fn source<'de, D: Deserializer<'de>>(deserializer: D) -> Result<&'static str, D::Error> {
+    crate::isolation::known_name(deserializer, &["kern", "GPOS", "GPOS class"])
+}
+
+/// The horizontal adjustment of a ValueRecord with `format`: XAdvance, or
+/// XPlacement when the record has no advance.
+fn adjustment(data: &[u8], at: usize, format: u16) -> Option<i16> {
+    let field = if format & X_ADVANCE != 0 {
+        (format & 0x0003).count_ones() as usize
+    } else if format & X_PLACEMENT != 0 {
+        0
+    } else {
+        return Some(0);
+    };
+    be_u16(data, at + 2 * field).map(|v| v as i16)
+}
+
+/// ClassDef table at `at` as glyph → class.
+fn class_def(data: &[u8], at: usize) -> Option<BTreeMap<u16, u16>> {
+    let mut classes = BTreeMap::new();
+    match be_u16(data, at)? {
+        1 => {
+            let start = be_u16(data, at + 2)?;
+            for i in 0..be_u16(data, at + 4)? {
+                classes.insert(start.checked_add(i)?, be_u16(data, at + 6 + 2 * i as usize)?);
+            }
+        }
+        2 => {
+            for i in 0..be_u16(data, at + 2)? as usize {
+                let r = at + 4 + 6 * i;
+                let class = be_u16(data, r + 4)?;
+                classes.extend((be_u16(data, r)?..=be_u16(data, r + 2)?).map(|g| (g, class)));
+            }
+        }
+        _ => return None,
+    }
+    Some(classes)
+}
+
+#[derive(Default)]
+struct Pairs {
+    glyphs: BTreeMap<(u16, u16), i16>,
+    classes: Vec<(u16, u16, i16)>,
+}
+
+/// Pair adjustment subtable at `at`.
+fn pair_subtable(gpos: &[u8], at: usize, pairs: &mut Pairs) -> Option<()> {
+    let coverage = layout::coverage(gpos, at + be_u16(gpos, at + 2)? as usize)?;
+    let (format1, format2) = (be_u16(gpos, at + 4)?, be_u16(gpos, at + 6)?);
+    let (size1, size2) = (2 * format1.count_ones() as usize, 2 * format2.count_ones() as usize);
+    match be_u16(gpos, at)? {
+        1 => {
+            for (i, &first) in coverage.iter().enumerate().take(be_u16(gpos, at + 8)? as usize) {
+                let set = at + be_u16(gpos, at + 10 + 2 * i)? as usize;
+                for j in 0..be_u16(gpos, set)? as usize {
+                    let record = set + 2 + j * (2 + size1 + size2);
+                    let value = adjustment(gpos, record + 2, format1)?;
+                    if value != 0 {
+                        // The first lookup to adjust a pair wins, as in shaping.
+                        pairs.glyphs.entry((first, be_u16(gpos, record)?)).or_insert(value);
+                    }
+                }
+            }
+        }
+        2 => {
+            let first_classes = class_def(gpos, at + be_u16(gpos, at + 8)? as usize)?;
+            let second_classes = class_def(gpos, at + be_u16(gpos, at + 10)? as usize)?;
+            let (count1, count2) = (be_u16(gpos, at + 12)? as usize, be_u16(gpos, at + 14)? as usize);
+            // The first covered glyph of each first class, and the first
+            // glyph of each second class; class 0 ("everything else") is
+            // left out.
+            let mut firsts: BTreeMap<u16, u16> = BTreeMap::new();
+            for &glyph in &coverage {
+                let class = first_classes.get(&glyph).copied().unwrap_or(0);
+                firsts.entry(class).or_insert(glyph);
+            }
+            let mut seconds: BTreeMap<u16, u16> = BTreeMap::new();
+            for (&glyph, &class) in &second_classes {
+                seconds.entry(class).or_insert(glyph);
+            }
+            for (&class1, &left) in firsts.iter().filter(|(&c, _)| (c as usize) < count1) {
+                for (&class2, &right) in seconds.iter().filter(|(&c, _)| c != 0 && (c as usize) < count2) {
+                    let record = at + 16 + (class1 as usize * count2 + class2 as usize) * (size1 + size2);
+                    let value = adjustment(gpos, record, format1)?;
+                    if value != 0 {
+                        pairs.classes.push((left, right, value));
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    Some(())
+}
+
+pub fn report(data: &[u8]) -> Result<Kerning, String> {
+    let face = Face::parse(data, 0).map_err(|e| format!("not a parseable font: {e}"))?;
+    let mut found: Vec<(u16, u16, i16, &'static str)> = Vec::new();
+
+    let mut kern_table_pairs = 0;
+    for subtable in face.tables().kern.iter().flat_map(|k| k.subtables).filter(|s| s.horizontal) {
+        if let kern::Format::Format0(format0) = subtable.format {
+            kern_table_pairs += format0.pairs.len() as usize;
+            found.extend(format0.pairs.into_iter().map(|p| (p.left().0, p.right().0, p.value, "kern")));
+        }
+    }
+
+    let mut pairs = Pairs::default();
+    if let Some(gpos) = face.raw_face().table(Tag::from_bytes(b"GPOS")) {
+        let malformed = || "GPOS table is malformed".to_string();
+        let wanted = layout::feature_lookups(gpos).ok_or_else(malformed)?.remove(b"kern").unwrap_or_default();
+        let lookups = layout::lookups(gpos, EXTENSION).ok_or_else(malformed)?;
+        for (index, subtables) in lookups.iter().enumerate() {
+            if !wanted.contains(&(index as u16)) {
+                continue;
+            }
+            for &(kind, at) in subtables.iter().filter(|(kind, _)| *kind == PAIR_ADJUSTMENT) {
+                pair_subtable(gpos, at, &mut pairs)
+                    .ok_or_else(|| format!("GPOS lookup {index} type {kind} is malformed"))?;
+            }
+        }
+    }
+    found.extend(pairs.glyphs.iter().map(|(&(left, right), &value)| (left, right, value, "GPOS")));
+    found.extend(pairs.classes.iter().map(|&(left, right, value)| (left, right, value, "GPOS class")));
+    found.sort_by_key(|&(.., value, _)| std::cmp::Reverse(value.unsigned_abs()));
+
+    let labels = GlyphLabels::new(&face);
+    let largest = found
+        .into_iter()
+        .take(LARGEST)
+        .map(|(left, right, value, source)| KernPair {
+            left: labels.get(left),
+            right: labels.get(right),
+            value,
+            source,
+        })
+        .collect();
+    Ok(Kerning {
+        kern_table_pairs,
+        gpos_pairs: pairs.glyphs.len(),
+        gpos_class_pairs: pairs.classes.len(),
+        largest,
+    })
+}
@@ -0,0 +1,297 @@
+//! OpenType layout common tables (GSUB/GPOS): coverage, the lookup list,
+//! contextual lookup references, GPOS mark attachment and the GSUB glyph
+//! closure.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::sfnt::{be_u16, be_u32};
+
+/// Glyph IDs of a Coverage table, in coverage-index order.
+pub fn coverage(data: &[u8], at: usize) -> Option<Vec<u16>> {
+    match be_u16(data, at)? {
+        1 => {
+            let count = be_u16(data, at + 2)? as usize;
+            (0..count).map(|i| be_u16(data, at + 4 + 2 * i)).collect()
+        }
+        2 => {
+            let ranges = be_u16(data, at + 2)? as usize;
+            let mut glyphs = Vec::new();
+            for i in 0..ranges {
+                let r = at + 4 + 6 * i;
+                glyphs.extend(be_u16(data, r)?..=be_u16(data, r + 2)?);
+            }
+            Some(glyphs)
+        }
+        _ => None,
+    }
+}
+
+/// Feature tags in the FeatureList, in table order (a tag repeats once per
+/// script/language system that defines it).
+pub fn feature_tags(table: &[u8]) -> Option<Vec<[u8; 4]>> {
+    let list = be_u16(table, 6)? as usize;
+    (0..be_u16(table, list)? as usize)
+        .map(|i| table.get(list + 2 + 6 * i..list + 6 + 6 * i)?.try_into().ok())
+        .collect()
+}
+
+/// Lookup indices of each feature tag, merged across the script/language
+/// systems that define it.
+pub fn feature_lookups(table: &[u8]) -> Option<BTreeMap<[u8; 4], BTreeSet<u16>>> {
+    let list = be_u16(table, 6)? as usize;
+    let mut out: BTreeMap<[u8; 4], BTreeSet<u16>> = BTreeMap::new();
+    for i in 0..be_u16(table, list)? as usize {
+        let record = list + 2 + 6 * i;
+        let feature = list + be_u16(table, record + 4)? as usize;
+        let lookups = out.entry(table.get(record..record + 4)?.try_into().ok()?).or_default();
+        for j in 0..be_u16(table, feature + 2)? as usize {
+            lookups.insert(be_u16(table, feature + 4 + 2 * j)?);
+        }
+    }
+    Some(out)
+}
+
+/// The subtables of each lookup in the lookup list, by lookup index, as
+/// `(lookup type, absolute offset)`, with extension lookups
+/// (`extension_type`: 7 in GSUB, 9 in GPOS) resolved to the lookup type and
+/// subtable they wrap.
+pub fn lookups(table: &[u8], extension_type: u16) -> Option<Vec<Vec<(u16, usize)>>> {
+    let list = be_u16(table, 8)? as usize;
+    let mut out = Vec::new();
+    for i in 0..be_u16(table, list)? as usize {
+        let lookup = list + be_u16(table, list + 2 + 2 * i)? as usize;
+        let kind = be_u16(table, lookup)?;
+        let mut subtables = Vec::new();
+        for s in 0..be_u16(table, lookup + 4)? as usize {
+            let sub = lookup + be_u16(table, lookup + 6 + 2 * s)? as usize;
+            if kind == extension_type {
+                subtables.push((be_u16(table, sub + 2)?, sub + be_u32(table, sub + 4)? as usize));
+            } else {
+                subtables.push((kind, sub));
+            }
+        }
+        out.push(subtables);
+    }
+    Some(out)
+}
+
+/// Every subtable in the lookup list, as [`lookups`] resolves them.
+pub fn subtables(table: &[u8], extension_type: u16) -> Option<Vec<(u16, usize)>> {
+    Some(lookups(table, extension_type)?.into_iter().flatten().collect())
+}
+
+/// Lookup indices a contextual (`context` type: 5 in GSUB, 7 in GPOS) or
+/// chained contextual (`chained`: 6 and 8) subtable applies; empty for other
+/// lookup types.
+pub fn nested_lookups(table: &[u8], kind: u16, sub: usize, context: u16, chained: u16) -> Option<Vec<u16>> {
+    // SubstLookupRecord/PosLookupRecord: sequenceIndex, lookupListIndex.
+    let records =
+        |at: usize, count: usize| (0..count).map(|i| be_u16(table, at + 4 * i + 2)).collect::<Option<Vec<_>>>();
+    let format = be_u16(table, sub)?;
+    let mut out = Vec::new();
+    if kind == context && format == 3 {
+        let glyphs = be_u16(table, sub + 2)? as usize;
+        out.extend(records(sub + 6 + 2 * glyphs, be_u16(table, sub + 4)? as usize)?);
+    } else if kind == chained && format == 3 {
+        let mut at = sub + 2;
+        for _ in 0..3 {
+            at += 2 + 2 * be_u16(table, at)? as usize;
+        }
+        out.extend(records(at + 2, be_u16(table, at)? as usize)?);
+    } else if (kind == context || kind == chained) && matches!(format, 1 | 2) {
+        // Rule sets follow the coverage and, in format 2, the class
+        // definitions (one for context, three for chained context).
+        let sets = sub + match (kind == chained, format) {
+            (_, 1) => 4,
+            (false, _) => 6,
+            (true, _) => 10,
+        };
+        for i in 0..be_u16(table, sets)? as usize {
+            let offset = be_u16(table, sets + 2 + 2 * i)? as usize;
+            if offset == 0 {
+                continue;
+            }
+            let set = sub + offset;
+            for j in 0..be_u16(table, set)? as usize {
+                let rule = set + be_u16(table, set + 2 + 2 * j)? as usize;
+                if kind == context {
+                    let glyphs = be_u16(table, rule)? as usize;
+                    out.extend(records(rule + 4 + 2 * glyphs.saturating_sub(1), be_u16(table, rule + 2)? as usize)?);
+                } else {
+                    let mut at = rule + 2 + 2 * be_u16(table, rule)? as usize;
+                    at += 2 + 2 * (be_u16(table, at)? as usize).saturating_sub(1);
+                    at += 2 + 2 * be_u16(table, at)? as usize;
+                    out.extend(records(at + 2, be_u16(table, at)? as usize)?);
+                }
+            }
+        }
+    }
+    Some(out)
+}
+
+/// One MarkBasePos (type 4) or MarkMarkPos (type 6) subtable: each mark's
+/// class, and per base (or base mark) which classes it has an anchor for.
+struct MarkSubtable {
+    marks: HashMap<u16, u16>,
+    bases: HashMap<u16, Vec<bool>>,
+}
+
+impl MarkSubtable {
+    fn parse(gpos: &[u8], sub: usize) -> Option<Self> {
+        if be_u16(gpos, sub)? != 1 {
+            return None;
+        }
+        let mark_coverage = coverage(gpos, sub + be_u16(gpos, sub + 2)? as usize)?;
+        let base_coverage = coverage(gpos, sub + be_u16(gpos, sub + 4)? as usize)?;
+        let class_count = be_u16(gpos, sub + 6)? as usize;
+        let mark_array = sub + be_u16(gpos, sub + 8)? as usize;
+        let base_array = sub + be_u16(gpos, sub + 10)? as usize;
+        let marks = mark_coverage
+            .into_iter()
+            .enumerate()
+            .map(|(i, g)| Some((g, be_u16(gpos, mark_array + 2 + 4 * i)?)))
+            .collect::<Option<_>>()?;
+        let bases = base_coverage
+            .into_iter()
+            .enumerate()
+            .map(|(i, g)| {
+                let record = base_array + 2 + 2 * class_count * i;
+                let anchors = (0..class_count).map(|c| be_u16(gpos, record + 2 * c).map(|o| o != 0));
+                Some((g, anchors.collect::<Option<_>>()?))
+            })
+            .collect::<Option<_>>()?;
+        Some(Self { marks, bases })
+    }
+
+    fn anchors(&self, mark: u16, base: u16) -> bool {
+        let Some(&class) = self.marks.get(&mark) else { return false };
+        self.bases.get(&base).and_then(|a| a.get(class as usize)).copied().unwrap_or(false)
+    }
+}
+
+/// Which marks GPOS can position on which bases and on which other marks.
+pub struct MarkAttachment {
+    to_base: Vec<MarkSubtable>,
+    to_mark: Vec<MarkSubtable>,
+}
+
+impl MarkAttachment {
+    pub fn parse(gpos: &[u8]) -> Result<Self, String> {
+        let mut out = Self { to_base: Vec::new(), to_mark: Vec::new() };
+        for (kind, sub) in subtables(gpos, 9).ok_or("GPOS lookup list truncated")? {
+            let target = match kind {
+                4 => &mut out.to_base,
+                6 => &mut out.to_mark,
+                _ => continue,
+            };
+            target.push(MarkSubtable::parse(gpos, sub).ok_or("GPOS mark attachment subtable truncated")?);
+        }
+        Ok(out)
+    }
+
+    /// `mark` has an anchor matching one on `base`.
+    pub fn on_base(&self, mark: u16, base: u16) -> bool {
+        self.to_base.iter().any(|s| s.anchors(mark, base))
+    }
+
+    /// `mark` can stack on the preceding mark `on`.
+    pub fn on_mark(&self, mark: u16, on: u16) -> bool {
+        self.to_mark.iter().any(|s| s.anchors(mark, on))
+    }
+}
+
+/// Glyphs one GSUB subtable can produce from `glyphs`. Contextual lookups
+/// only point at other lookups, which are visited on their own, so this
+/// over-approximates but never misses an output.
+fn substitutes(gsub: &[u8], kind: u16, sub: usize, glyphs: &BTreeSet<u16>) -> Option<Vec<u16>> {
+    let format = be_u16(gsub, sub)?;
+    let covered = coverage(gsub, sub + be_u16(gsub, sub + 2)? as usize)?;
+    let mut out = Vec::new();
+    match (kind, format) {
+        (1, 1) => {
+            let delta = be_u16(gsub, sub + 4)?;
+            out.extend(covered.iter().filter(|g| glyphs.contains(g)).map(|g| g.wrapping_add(delta)));
+        }
+        (1, 2) | (8, 1) => {
+            let array = if kind == 1 {
+                sub + 4
+            } else {
+                // ReverseChainSingleSubst: skip backtrack and lookahead coverages.
+                let backtrack = be_u16(gsub, sub + 4)? as usize;
+                let lookahead = sub + 6 + 2 * backtrack;
+                lookahead + 2 + 2 * be_u16(gsub, lookahead)? as usize
+            };
+            for (i, g) in covered.iter().enumerate() {
+                if glyphs.contains(g) {
+                    out.push(be_u16(gsub, array + 2 + 2 * i)?);
+                }
+            }
+        }
+        // Multiple (Sequence) and Alternate (AlternateSet) share a layout.
+        (2 | 3, 1) => {
+            for (i, g) in covered.iter().enumerate() {
+                if glyphs.contains(g) {
+                    let set = sub + be_u16(gsub, sub + 6 + 2 * i)? as usize;
+                    for j in 0..be_u16(gsub, set)? as usize {
+                        out.push(be_u16(gsub, set + 2 + 2 * j)?);
+                    }
+                }
+            }
+        }
+        (4, 1) => {
+            for (i, g) in covered.iter().enumerate() {
+                if !glyphs.contains(g) {
+                    continue;
+                }
+                let set = sub + be_u16(gsub, sub + 6 + 2 * i)? as usize;
+                for j in 0..be_u16(gsub, set)? as usize {
+                    let lig = set + be_u16(gsub, set + 2 + 2 * j)? as usize;
+                    let components = be_u16(gsub, lig + 2)? as usize;
+                    let formed = (1..components)
+                        .map(|k| be_u16(gsub, lig + 2 + 2 * k))
+                        .collect::<Option<Vec<u16>>>()?
+                        .iter()
+                        .all(|c| glyphs.contains(c));
+                    if formed {
+                        out.push(be_u16(gsub, lig)?);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    Some(out)
+}
+
+/// `glyphs` plus every glyph GSUB can substitute them with, transitively.
+pub fn gsub_closure(gsub: &[u8], glyphs: &BTreeSet<u16>) -> Result<BTreeSet<u16>, String> {
+    let subtables = subtables(gsub, 7).ok_or("GSUB lookup list truncated")?;
+    close(gsub, &subtables, glyphs)
+}
+
+/// `glyphs` plus every glyph the lookups of `features` substitute them
+/// with, transitively.
+pub fn feature_closure(gsub: &[u8], glyphs: &BTreeSet<u16>, features: &[[u8; 4]]) -> Result<BTreeSet<u16>, String> {
+    let lookups = lookups(gsub, 7).ok_or("GSUB lookup list truncated")?;
+    let by_feature = feature_lookups(gsub).ok_or("GSUB feature list truncated")?;
+    let subtables: Vec<(u16, usize)> = by_feature
+        .iter()
+        .filter(|(tag, _)| features.contains(tag))
+        .flat_map(|(_, indices)| indices.iter().filter_map(|&i| lookups.get(i as usize)).flatten().copied())
+        .collect();
+    close(gsub, &subtables, glyphs)
+}
+
+fn close(gsub: &[u8], subtables: &[(u16, usize)], glyphs: &BTreeSet<u16>) -> Result<BTreeSet<u16>, String> {
+    let mut out = glyphs.clone();
+    loop {
+        let before = out.len();
+        for &(kind, sub) in subtables {
+            let produced = substitutes(gsub, kind, sub, &out).ok_or("GSUB subtable truncated")?;
+            out.extend(produced);
+        }
+        if out.len() == before {
+            return Ok(out);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! The license a font declares about itself.
+//!
+//! The catalog's `license` is whatever an admin typed; the binary says what
+//! it allows in the OS/2 `fsType` bits, with the license text and URL in
+//! `name` IDs 13 and 14. Uploads report this as `license` (see
+//! [`crate::uploads`]). [`crate::compress::encode`], the one place web fonts
+//! are produced, refuses fonts whose `fsType` is Restricted License
+//! embedding, and [`crate::subset::subset`] refuses fonts that set
+//! No Subsetting.
+
+use font_api::EmbeddedLicense;
+
+use crate::{
+    name::NameTable,
+    sfnt::{be_u16, Font},
+};
+
+const RESTRICTED: u16 = 0x0002;
+const PREVIEW_PRINT: u16 = 0x0004;
+const EDITABLE: u16 = 0x0008;
+const NO_SUBSETTING: u16 = 0x0100;
+const BITMAP_ONLY: u16 = 0x0200;
+const COPYRIGHT_ID: u16 = 0;
+const LICENSE_ID: u16 = 13;
+const LICENSE_URL_ID: u16 = 14;
+
+/// SPDX IDs by what a license description or URL says.
+const SPDX: &[(&str, &str)] = &[
+    ("open font license", "OFL-1.1"),
+    ("openfontlicense.org", "OFL-1.1"),
+    ("scripts.sil.org/ofl", "OFL-1.1"),
+    ("apache license", "Apache-2.0"),
+    ("apache.org/licenses", "Apache-2.0"),
+    ("ubuntu font licen", "UFL-1.0"),
+    ("font.ubuntu.com/licence", "UFL-1.0"),
+];
+
+/// OS/2 `fsType`; 0 (installable) when there is no OS/2 table.
+fn fs_type(font: &Font) -> u16 {
+    font.table(b"OS/2").and_then(|os2| be_u16(os2, 8)).unwrap_or(0)
+}
+
+/// The embedding level `fs_type` grants. Bits 1-3 should be exclusive;
+/// when several are set the least restrictive wins, as the spec asks.
+fn embedding(fs_type: u16) -> &'static str {
+    if fs_type & 0x000F == 0 {
+        "installable"
+    } else if fs_type & EDITABLE != 0 {
+        "editable"
+    } else if fs_type & PREVIEW_PRINT != 0 {
+        "preview-print"
+    } else if fs_type & RESTRICTED != 0 {
+        "restricted"
+    } else {
+        // Bit 0 alone is reserved; treat it as installable.
+        "installable"
+    }
+}
+
+pub fn read(font: &Font) -> EmbeddedLicense {
+    let fs_type = fs_type(font);
+    let names = font.table(b"name").and_then(|name| NameTable::parse(name).ok());
+    let name = |id| names.as_ref().and_then(|n| n.get(id)).filter(|s| !s.trim().is_empty());
+    EmbeddedLicense {
+        fs_type,
+        embedding: embedding(fs_type).to_string(),
+        no_subsetting: fs_type & NO_SUBSETTING != 0,
+        bitmap_only: fs_type & BITMAP_ONLY != 0,
+        description: name(LICENSE_ID),
+        url: name(LICENSE_URL_ID),
+        copyright: name(COPYRIGHT_ID),
+    }
+}
+
+/// The SPDX ID of the OFL, Apache or Ubuntu Font License (those Google
+/// Fonts ships) that `license` declares; `None` for any other.
+pub fn spdx(license: &EmbeddedLicense) -> Option<&'static str> {
+    [&license.description, &license.url].into_iter().flatten().find_map(|text| {
+        let text = text.to_lowercase();
+        SPDX.iter().find(|(marker, _)| text.contains(marker)).map(|(_, id)| *id)
+    })
+}
+
+/// Errors unless the font may be embedded in web pages.
+pub fn check_embedding(font: &Font) -> Result<(), String> {
+    let fs_type = fs_type(font);
+    if embedding(fs_type) == "restricted" {
+        return Err(format!("the font's license forbids embedding (OS/2 fsType {fs_type:#06x}, Restricted License)"));
+    }
+    Ok(())
+}
+
+/// Errors if the font's license forbids subsetting it.
+pub fn check_subsetting(font: &Font) -> Result<(), String> {
+    let fs_type = fs_type(font);
+    if fs_type & NO_SUBSETTING != 0 {
+        return Err(format!("the font's license forbids subsetting (OS/2 fsType {fs_type:#06x}, No Subsetting)"));
+    }
+    Ok(())
+}
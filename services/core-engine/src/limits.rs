@@ -0,0 +1,67 @@
+//! Request size and time limits.
+//!
+//! JSON bodies are capped at `MAX_JSON_BYTES` (default 1 MiB), catalog
+//! snapshots being restored at `MAX_UPLOAD_BYTES`. Endpoints that take a
+//! font as their body stream it to disk under `MAX_UPLOAD_BYTES` instead
+//! (see [`crate::spool`]). Either way, too large a body gets `413` without
+//! being buffered.
+//!
+//! Every request must be answered within its route's budget or gets `408`:
+//! `CATALOG_TIMEOUT_SECS` (default 10) for catalog, job status and other
+//! lookups, `TRANSFORM_TIMEOUT_SECS` (default 300) for the rest, which
+//! process fonts or take uploads. The budget ends with the response
+//! headers, so slow downloads and event streams are not cut off. Blocking
+//! work that overruns is cancelled with the request (see [`crate::cancel`]).
+
+use axum::{
+    extract::{DefaultBodyLimit, Request},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::time::Duration;
+
+/// Lookup routes, by path prefix, when read with `GET` or `HEAD`.
+const LOOKUPS: &[&str] = &[
+    "/health",
+    "/readyz",
+    "/metrics",
+    "/api/v1/openapi.json",
+    "/api/v1/font/catalog",
+    "/api/v1/font/subset-profiles",
+    "/api/v1/jobs/",
+    "/api/v1/analytics/",
+];
+
+fn setting(name: &str, default: u64) -> u64 {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Largest JSON body (`MAX_JSON_BYTES`).
+pub fn max_json_bytes() -> usize {
+    setting("MAX_JSON_BYTES", 1024 * 1024) as usize
+}
+
+/// The body limit for every route that does not stream its body.
+pub fn json_body_limit() -> DefaultBodyLimit {
+    DefaultBodyLimit::max(max_json_bytes())
+}
+
+fn budget(method: &Method, path: &str) -> Duration {
+    let lookup = matches!(*method, Method::GET | Method::HEAD) && LOOKUPS.iter().any(|p| path.starts_with(p));
+    let secs = if lookup { setting("CATALOG_TIMEOUT_SECS", 10) } else { setting("TRANSFORM_TIMEOUT_SECS", 300) };
+    Duration::from_secs(secs.max(1))
+}
+
+/// Answers `408` for requests that overrun their route's budget.
+pub async fn timeout(req: Request, next: Next) -> Response {
+    let budget = budget(req.method(), req.uri().path());
+    match tokio::time::timeout(budget, next.run(req)).await {
+        Ok(response) => response,
+        Err(_) => (
+            StatusCode::REQUEST_TIMEOUT,
+            format!("request was not answered within its {}s limit", budget.as_secs()),
+        )
+            .into_response(),
+    }
+}
@@ -3,83 +3,249 @@
 //! Axum-based HTTP engine for smart font delivery: compression,
 //! Unicode subsetting, catalog management, and font analytics.
 
+mod access;
+mod aliases;
+mod analysis;
+mod analytics;
+mod artifacts;
+mod audit;
+mod auth;
+mod backup;
+mod batch;
+mod bitmap;
+mod brotli;
+mod bundle;
+mod cache;
+mod cache_control;
+mod cancel;
+mod catalog;
+mod catalog_sync;
+mod cff;
+mod check;
+mod cjk;
+mod cmap;
+mod collection;
+mod collision;
+mod color;
+mod compress;
+mod config;
+mod cors;
+mod coverage;
+mod crawl;
+mod db;
+mod diacritics;
+mod diff;
+mod duplicates;
+mod edge;
+mod encoding;
+mod epub;
+mod estimate;
+mod extract;
+mod fallback;
+mod features;
+mod flags;
+mod formats;
+mod fvar;
+mod gc;
+mod glyf;
+mod google;
+mod graphql;
+mod grpc;
+mod hints;
+mod ift;
+mod ingest;
+mod instancer;
+mod instances;
+mod isolation;
+mod kerning;
+mod layout;
+mod licensing;
+mod limits;
+mod math;
+mod merged;
+mod metrics;
+mod multipart;
+mod name;
+mod negotiation;
+mod openapi;
+mod optimize;
+mod os2;
+mod outlines;
+mod pairings;
+mod pdf;
+mod preview;
+mod probes;
+mod profiles;
+mod progressive;
+mod prune;
+mod purge;
+mod quarantine;
+mod queue;
+mod quotas;
+mod ratelimit;
+mod reload;
+mod rename;
+mod render;
+mod resilience;
+mod samples;
+mod sandbox;
+mod scan;
+mod search;
+mod sfnt;
+mod shaping;
+mod shedding;
+mod signing;
+mod slices;
+mod slim;
+mod spool;
+mod sprite;
+mod staging;
+mod storage;
+mod stylesheet;
+mod subset;
+mod substitutions;
+mod system;
+#[cfg(unix)]
+mod systemd;
+mod telemetry;
+mod tenants;
+mod tls;
+mod transform;
+mod tuning;
+mod unicode;
+mod uploads;
+mod usage_events;
+mod validation;
+mod vcdiff;
+mod versions;
+mod warmup;
+mod waterfall;
+mod webhook;
+mod woff;
+mod zip;
+
 use axum::{
-    extract::State,
-    http::StatusCode,
+    extract::{DefaultBodyLimit, State},
+    http::{HeaderMap, StatusCode},
+    middleware,
     response::Json,
-    routing::{get, post},
+    routing::{delete, get, post, put},
     Router,
 };
+use extract::ApiJson;
+use font_api::{
+    AnalyzeMode, AnalyzeRequest, CompressRequest, EpubArtifact, FontCatalogEntry, OutlinePass, PdfSubset,
+    ProcessingDefaults, ShapingReport, SubsetProfile, SubsetRequest,
+};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::{BTreeMap, BTreeSet},
     net::SocketAddr,
-    sync::Arc,
+    sync::{Arc, RwLock},
     time::Instant,
 };
-use tracing::info;
+use tracing::{error, info};
 use tracing_subscriber::EnvFilter;
 
+use isolation::Blob;
+
 // ── State ──────────────────────────────────────────────────────────────────
 
-#[derive(Clone)]
 struct AppState {
     start_time: Instant,
+    admin_token: Option<String>,
+    keys: auth::ApiKeys,
+    rate_limits: ratelimit::RateLimits,
+    gc: gc::Gc,
+    shedding: shedding::Shedding,
+    catalog: RwLock<Vec<FontCatalogEntry>>,
+    aliases: aliases::Aliases,
+    staging: RwLock<BTreeMap<String, FontCatalogEntry>>,
+    db: Option<sqlx::PgPool>,
+    flags: flags::FeatureFlags,
+    cors: cors::CorsPolicy,
+    hints: hints::ResourceHints,
+    cache_policies: cache_control::CachePolicies,
+    profiles: profiles::SubsetProfiles,
+    pairings: pairings::ProfileCache,
+    cmaps: search::CmapCache,
+    scanner: scan::Scanner,
+    quarantine: quarantine::Quarantine,
+    sandbox: sandbox::Sandbox,
+    signer: signing::UrlSigner,
+    uploads: uploads::Uploads,
+    artifacts: artifacts::ArtifactStore,
+    slices: slices::Slices,
+    subsets: cache::SubsetCache,
+    jobs: Arc<cancel::JobStats>,
+    queue: queue::JobQueue,
+    webhooks: webhook::Webhooks,
+    crawler: crawl::Crawler,
+    google_fonts: google::GoogleFonts,
+    edges: Arc<edge::EdgeMonitor>,
+    telemetry: metrics_exporter_prometheus::PrometheusHandle,
+    analytics: analytics::Analytics,
+    usage_events: usage_events::UsageEvents,
+    quotas: quotas::Quotas,
+    audit: audit::AuditLog,
 }
 
-// ── Request / Response types ───────────────────────────────────────────────
-
-#[derive(Debug, Deserialize)]
-struct CompressRequest {
-    font_name: String,
-    format: String,
-    quality: u8,
-}
+impl AppState {
+    /// Processing defaults of the catalog entry `font_name` refers to.
+    fn defaults_for(&self, font_name: &str) -> ProcessingDefaults {
+        let key = font_name.to_lowercase();
+        self.catalog
+            .read()
+            .unwrap()
+            .iter()
+            .find(|e| e.id == key || e.family.to_lowercase() == key)
+            .and_then(|e| e.defaults.clone())
+            .unwrap_or_default()
+    }
 
-#[derive(Debug, Serialize)]
-struct CompressResponse {
-    font_name: String,
-    format: String,
-    quality: u8,
-    original_size_kb: f64,
-    compressed_size_kb: f64,
-    ratio: f64,
-    download_url: String,
+    /// Admin routes require `X-Admin-Token` to match `ADMIN_TOKEN`; they are
+    /// disabled entirely when no token is configured.
+    fn require_admin(&self, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+        let given = headers.get("x-admin-token").and_then(|v| v.to_str().ok());
+        match (&self.admin_token, given) {
+            (Some(want), Some(got)) if signing::same_secret(want, got) => Ok(()),
+            (None, _) => Err((StatusCode::FORBIDDEN, "admin API disabled; set ADMIN_TOKEN".to_string())),
+            _ => Err((StatusCode::UNAUTHORIZED, "invalid admin token".to_string())),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct SubsetRequest {
-    font_name: String,
-    characters: String,
-    format: String,
-}
+// ── Request / Response types ───────────────────────────────────────────────
 
-#[derive(Debug, Serialize)]
-struct SubsetResponse {
-    font_name: String,
-    format: String,
-    character_count: usize,
-    original_glyph_count: usize,
-    subset_glyph_count: usize,
-    original_size_kb: f64,
-    subset_size_kb: f64,
-    download_url: String,
-}
+type CompressResponse = font_api::CompressResponse<estimate::Estimate>;
+type SubsetResponse = font_api::SubsetResponse<estimate::Estimate>;
 
-#[derive(Debug, Serialize)]
-struct FontCatalogEntry {
-    id: String,
-    family: String,
-    variant: String,
-    formats: Vec<String>,
-    size_kb: f64,
-    glyph_count: usize,
-    unicode_ranges: Vec<String>,
-    license: String,
+/// Checks a catalog entry's processing defaults.
+fn validate_defaults(defaults: &ProcessingDefaults) -> Result<(), String> {
+    if defaults.quality.is_some_and(|q| q > 100) {
+        return Err("defaults.quality must be 0-100".to_string());
+    }
+    if let Some(preset) = &defaults.subset_preset {
+        if unicode::preset(preset).is_none() {
+            return Err(format!("defaults.subset_preset '{preset}' is unknown; valid: {}", unicode::preset_names()));
+        }
+    }
+    Ok(())
 }
 
-#[derive(Debug, Deserialize)]
-struct AnalyzeRequest {
-    font_name: String,
+/// Stored with a compressed font (see [`artifacts::ArtifactStore`]).
+#[derive(Serialize, Deserialize)]
+struct CompressRecord {
+    original_bytes: usize,
+    output_bytes: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    removed_features: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hinting_bytes_saved: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    outline_passes: Vec<OutlinePass>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    outline_bytes_saved: Option<usize>,
 }
 
 #[derive(Debug, Serialize)]
@@ -88,10 +254,58 @@ struct AnalyzeResponse {
     glyph_count: usize,
     format: String,
     size_kb: f64,
+    /// Tags of the tables in the font, in directory order.
+    tables: Vec<String>,
     unicode_ranges: Vec<String>,
+    /// Unicode variation sequences (`cmap` format 14), such as Ideographic
+    /// Variation Sequences; absent when the font has none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variation_sequences: Option<cmap::VariationSummary>,
     has_variable_axes: bool,
+    /// `fvar` axes with their names and ranges.
+    variation_axes: Vec<analysis::Axis>,
+    /// `fvar` named instances, as `/api/v1/font/catalog/:id/instances`
+    /// takes them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    named_instances: Vec<analysis::NamedInstance>,
+    /// `STAT` style names of axis values.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    axis_values: Vec<analysis::AxisValue>,
+    /// OpenType `MATH` table present; see [`math`] for the full report.
+    has_math_table: bool,
     color_palettes: usize,
+    /// `CPAL` palettes with their colors.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    palettes: Vec<color::Palette>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    colr: Option<color::Colr>,
+    /// Color glyph formats present (`COLRv0`, `COLRv1`, `SVG`, `sbix`,
+    /// `CBDT`).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    color_formats: Vec<String>,
+    /// `sbix` or `CBDT` strike sizes with their glyph counts.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bitmap_strikes: Vec<bitmap::StrikeSummary>,
     opentype_features: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blocks: Option<Vec<unicode::BlockCoverage>>,
+    /// With `include_kerning`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kerning: Option<kerning::Kerning>,
+    /// With `include_substitutions`, by GSUB feature tag.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    substitutions: Option<Vec<substitutions::FeatureSubstitutions>>,
+}
+
+#[derive(Debug, Serialize)]
+struct BuildInfo {
+    version: &'static str,
+    git_commit: Option<&'static str>,
+    profile: &'static str,
+    features: Vec<&'static str>,
+    database: bool,
+    schema_version: Option<i64>,
+    embedded_schema_version: Option<i64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -99,6 +313,163 @@ struct HealthResponse {
     status: String,
     uptime_secs: u64,
     version: String,
+    jobs: cancel::JobStatsSnapshot,
+}
+
+// ── Worker tasks ───────────────────────────────────────────────────────────
+
+/// The subset pipeline once the request is checked; runs in the parse
+/// worker (see [`isolation`]).
+#[derive(Serialize, Deserialize)]
+struct SubsetTask {
+    options: transform::Transform,
+    /// `options.features`, sparing the vertical ones for vertical subsets.
+    features: Option<prune::Filter>,
+    wanted: BTreeSet<u32>,
+    vertical: bool,
+    /// The characters to compare shaping of before and after.
+    verify: Option<String>,
+    /// For a PDF subset, the tables it keeps and the PostScript name to tag
+    /// should the font have none.
+    pdf: Option<(Vec<String>, String)>,
+    quality: u8,
+    dry_run: bool,
+}
+
+/// Source size, pruned features, the subset report and shaping check, the
+/// PDF tagging, the output and, for dry runs, its tables.
+type SubsetOutput = (
+    usize,
+    Vec<String>,
+    subset::Report,
+    Option<ShapingReport>,
+    Option<PdfSubset>,
+    Blob,
+    Option<Vec<estimate::TableEstimate>>,
+);
+
+impl isolation::Task for SubsetTask {
+    const NAME: &'static str = "subset";
+    type Output = SubsetOutput;
+
+    fn run(self, data: Vec<u8>, token: &cancel::CancelToken) -> Result<SubsetOutput, String> {
+        let mut font = self.options.load(&data)?;
+        token.report("parse", 10);
+        let breakdown = self.dry_run.then(|| estimate::Breakdown::before(&font));
+        // Before subsetting, so the closure skips the pruned lookups.
+        let removed = match &self.features {
+            Some(features) => prune::prune(&mut font, features)?,
+            None => Vec::new(),
+        };
+        // Pruned features change shaping on purpose; compare after.
+        let reference = self.verify.is_some().then(|| font.to_bytes());
+        let report = subset::subset(&mut font, &self.wanted, self.options.layout_closure, self.vertical)?;
+        token.report("subset", 60);
+        if self.options.strip_hints {
+            compress::strip_hints(&mut font)?;
+        }
+        let shaped = reference.zip(self.verify).and_then(|(reference, characters)| {
+            shaping::check(&reference, &font.to_bytes(), &self.wanted, &characters)
+        });
+        let pdf = self.pdf.map(|(tables, fallback)| {
+            font.tables.retain(|t| tables.iter().any(|r| r.as_bytes() == t.tag));
+            pdf::tag(&mut font, &report.kept, &fallback, tables)
+        });
+        let encoded = compress::encode(&font, &self.options.format, self.quality)?;
+        token.report("encode", 95);
+        let tables = breakdown.map(|b| b.after(&font));
+        Ok((data.len(), removed, report, shaped, pdf, Blob(encoded), tables))
+    }
+}
+
+/// The compress pipeline once the request is checked; runs in the parse
+/// worker.
+#[derive(Serialize, Deserialize)]
+struct CompressTask {
+    options: transform::Transform,
+    quality: u8,
+    optimize_outlines: bool,
+    dry_run: bool,
+}
+
+type CompressOutput = (
+    usize,
+    Vec<String>,
+    Option<usize>,
+    Vec<OutlinePass>,
+    Option<usize>,
+    Blob,
+    Option<Vec<estimate::TableEstimate>>,
+);
+
+impl isolation::Task for CompressTask {
+    const NAME: &'static str = "compress";
+    type Output = CompressOutput;
+
+    fn run(self, data: Vec<u8>, token: &cancel::CancelToken) -> Result<CompressOutput, String> {
+        let (format, quality) = (&self.options.format, self.quality);
+        let mut font = self.options.load(&data)?;
+        token.report("parse", 10);
+        let breakdown = self.dry_run.then(|| estimate::Breakdown::before(&font));
+        let removed = match &self.options.features {
+            Some(features) => prune::prune(&mut font, features)?,
+            None => Vec::new(),
+        };
+        let mut hinted = None;
+        if self.options.strip_hints {
+            // Encoded with hints as well, to report what stripping them saves.
+            hinted = Some(compress::encode(&font, format, quality)?.len());
+            compress::strip_hints(&mut font)?;
+        }
+        let (mut unoptimized, mut outline_passes) = (None, Vec::new());
+        if self.optimize_outlines {
+            unoptimized = Some(compress::encode(&font, format, quality)?.len());
+            outline_passes = optimize::optimize(&mut font)?;
+        }
+        let encoded = compress::encode(&font, format, quality)?;
+        token.report("encode", 95);
+        let unoptimized_len = unoptimized.unwrap_or(encoded.len());
+        let saved = hinted.map(|hinted| hinted.saturating_sub(unoptimized_len));
+        let outlines_saved = unoptimized.map(|u| u.saturating_sub(encoded.len()));
+        let tables = breakdown.map(|b| b.after(&font));
+        Ok((data.len(), removed, saved, outline_passes, outlines_saved, Blob(encoded), tables))
+    }
+}
+
+/// What analyze reads from a font; runs in the parse worker.
+#[derive(Serialize, Deserialize)]
+struct AnalyzeTask {
+    include_kerning: bool,
+    include_substitutions: bool,
+}
+
+type AnalyzeOutput = (
+    usize,
+    String,
+    analysis::Facts,
+    Option<kerning::Kerning>,
+    Option<Vec<substitutions::FeatureSubstitutions>>,
+);
+
+impl isolation::Task for AnalyzeTask {
+    const NAME: &'static str = "analyze";
+    type Output = AnalyzeOutput;
+
+    fn run(self, data: Vec<u8>, _: &cancel::CancelToken) -> Result<AnalyzeOutput, String> {
+        // A WOFF is analyzed as the font it wraps.
+        let data = match sfnt::sniff(&data) {
+            Some(sfnt::Flavor::Woff) => woff::decode_woff(&data)?.to_bytes(),
+            _ => data,
+        };
+        let format = match sfnt::sniff(&data) {
+            Some(sfnt::Flavor::Otf) => "otf",
+            _ => "ttf",
+        };
+        let facts = analysis::facts(&data)?;
+        let kerning = if self.include_kerning { Some(kerning::report(&data)?) } else { None };
+        let substitutions = if self.include_substitutions { Some(substitutions::report(&data)?) } else { None };
+        Ok((data.len(), format.to_string(), facts, kerning, substitutions))
+    }
 }
 
 // ── Handlers ───────────────────────────────────────────────────────────────
@@ -108,119 +479,553 @@ async fn health(State(state): State<Arc<AppState>>) -> Json<HealthResponse> {
         status: "ok".to_string(),
         uptime_secs: state.start_time.elapsed().as_secs(),
         version: env!("CARGO_PKG_VERSION").to_string(),
+        jobs: state.jobs.snapshot(),
     })
 }
 
+async fn debug_build(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<BuildInfo>, (StatusCode, String)> {
+    let schema_version = match &state.db {
+        Some(pool) => db::schema_version(pool)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("schema version query failed: {e}")))?,
+        None => None,
+    };
+    Ok(Json(BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_commit: option_env!("GIT_COMMIT"),
+        profile: if cfg!(debug_assertions) { "debug" } else { "release" },
+        features: system::features(),
+        database: state.db.is_some(),
+        schema_version,
+        embedded_schema_version: db::latest_migration(),
+    }))
+}
+
 async fn compress(
-    State(_state): State<Arc<AppState>>,
-    Json(req): Json<CompressRequest>,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(mut req): ApiJson<CompressRequest>,
 ) -> Result<Json<CompressResponse>, (StatusCode, String)> {
-    let valid_formats = ["woff2", "woff", "otf", "ttf"];
-    if !valid_formats.contains(&req.format.as_str()) {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            format!(
-                "unsupported format '{}'; valid: woff2, woff, otf, ttf",
-                req.format
-            ),
-        ));
-    }
-    if req.quality > 100 {
+    state.flags.ensure("compress", &headers)?;
+    state.quotas.charge_operation(&headers)?;
+
+    let (font_name, upload) = state.uploads.resolve(&headers, req.font_id.as_deref(), &req.font_name).await?;
+    req.font_name = font_name;
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &req.font_name)?;
+    let defaults = state.defaults_for(&req.font_name);
+    let mut defaults_applied = Vec::new();
+    // `eot` is EOT-lite (uncompressed, unobfuscated) for legacy IE kiosks.
+    let valid_formats = ["woff2", "woff", "otf", "ttf", "eot"];
+    let options = transform::resolve(&req.transform, &valid_formats, &defaults, &mut defaults_applied)?;
+    let quality = match (options.quality, defaults.quality) {
+        (Some(q), _) => q,
+        (None, Some(q)) => {
+            defaults_applied.push("quality".to_string());
+            q
+        }
+        (None, None) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("quality is required; '{}' has no catalog default", req.font_name),
+            ))
+        }
+    };
+    if quality > 100 {
         return Err((
             StatusCode::BAD_REQUEST,
             "quality must be 0-100".to_string(),
         ));
     }
-    if req.font_name.trim().is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "font_name is required".to_string()));
-    }
+    let (strip_hints, format) = (options.strip_hints, options.format.clone());
+    let epub = match &req.epub {
+        Some(e) => Some((epub::checked(e, &format)?, e)),
+        None => None,
+    };
 
-    // Simulated sizes based on format and quality
-    let original_size_kb = 280.0_f64;
-    let ratio_base = match req.format.as_str() {
-        "woff2" => 0.35,
-        "woff" => 0.55,
-        "otf" | "ttf" => 0.90,
-        _ => 0.80,
+    if req.dry_run && req.inline {
+        return Err((StatusCode::BAD_REQUEST, "inline needs the output; it cannot be combined with dry_run".to_string()));
+    }
+    let source = state
+        .artifacts
+        .source(&state, &req.font_name, upload.as_ref())
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let mut steps = serde_json::json!({ "compress": quality, "strip_hints": strip_hints });
+    if let Some(features) = &options.features {
+        steps["features"] = serde_json::json!(features);
+    }
+    if req.optimize_outlines {
+        steps["optimize_outlines"] = serde_json::json!(true);
+    }
+    // The obfuscated copy is a transform of its own, stored next to the output.
+    let epub = epub.map(|(algorithm, e)| {
+        let address = options.address(&state, &req.font_name, &source, epub::steps(&steps, e, algorithm));
+        (algorithm, e.publication_id.clone(), address)
+    });
+    let address = options.address(&state, &req.font_name, &source, steps);
+    let (record, cache, tables) = match state.artifacts.lookup::<CompressRecord>(&address).await? {
+        Some(record) => (record, "hit", None),
+        None => {
+            let data = match upload {
+                Some(u) => state.uploads.read(&u).await?,
+                None => duplicates::read_catalog_binary(&state, &req.font_name)
+                    .await
+                    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?,
+            };
+            let task = CompressTask {
+                options: options.clone(),
+                quality,
+                optimize_outlines: req.optimize_outlines,
+                dry_run: req.dry_run,
+            };
+            let (
+                original_bytes,
+                removed,
+                hinting_bytes_saved,
+                outline_passes,
+                outline_bytes_saved,
+                Blob(encoded),
+                tables,
+            ) = isolation::run(&state, task, data).await?.map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+            let output_bytes = encoded.len();
+            let record = CompressRecord {
+                original_bytes,
+                output_bytes,
+                removed_features: removed,
+                hinting_bytes_saved,
+                outline_passes,
+                outline_bytes_saved,
+            };
+            if !req.dry_run {
+                state.artifacts.put(&address, &encoded, &record).await?;
+            }
+            (record, "miss", tables)
+        }
     };
-    let quality_factor = 0.5 + (req.quality as f64 / 100.0) * 0.5;
-    let compressed_size_kb = original_size_kb * ratio_base * quality_factor;
-    let ratio = original_size_kb / compressed_size_kb;
+    let CompressRecord {
+        original_bytes,
+        output_bytes: compressed_bytes,
+        removed_features,
+        hinting_bytes_saved,
+        outline_passes,
+        outline_bytes_saved,
+    } = record;
+    let data_uri = if req.inline {
+        let encoded = state.artifacts.read(&address).await?;
+        Some(compress::data_uri(&format, &encoded).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?)
+    } else {
+        None
+    };
+    let original_size_kb = original_bytes as f64 / 1024.0;
+    let compressed_size_kb = compressed_bytes as f64 / 1024.0;
+    let ratio = original_size_kb / compressed_size_kb.max(f64::MIN_POSITIVE);
+    if req.dry_run {
+        let estimate = match tables {
+            Some(tables) => estimate::Estimate::encoded(original_bytes, compressed_bytes, tables),
+            None => estimate::Estimate::stored(original_bytes, compressed_bytes),
+        };
+        info!(font = %req.font_name, format = %format, compressed_bytes, "font compress dry run");
+        return Ok(Json(CompressResponse {
+            font_name: req.font_name,
+            format,
+            quality,
+            strip_hints,
+            original_size_kb,
+            compressed_size_kb,
+            ratio,
+            download_url: None,
+            estimate: Some(estimate),
+            defaults_applied,
+            removed_features,
+            hinting_bytes_saved,
+            outline_passes,
+            outline_bytes_saved,
+            data_uri: None,
+            epub: None,
+        }));
+    }
+    let epub = match epub {
+        Some((algorithm, publication_id, epub_address)) => {
+            if state.artifacts.lookup::<serde_json::Value>(&epub_address).await?.is_none() {
+                let mut data = state.artifacts.read(&address).await?;
+                epub::obfuscate(&mut data, algorithm, &publication_id).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+                let record = serde_json::json!({ "algorithm": algorithm.uri() });
+                state.artifacts.put(&epub_address, &data, &record).await?;
+            }
+            Some(EpubArtifact {
+                download_url: signing::download_url(&state, &epub_address.path()),
+                algorithm: algorithm.uri().to_string(),
+            })
+        }
+        None => None,
+    };
+    telemetry::record_output("compress", &format, original_bytes, compressed_bytes);
+    state.analytics.record_compress(&req.font_name, &format, original_bytes, compressed_bytes);
+    let usage = usage_events::Usage {
+        kind: "compress",
+        tenant: tenants::of(&headers),
+        font: &artifacts::slug(&req.font_name),
+        format: &format,
+        bytes: compressed_bytes as u64,
+        cache,
+    };
+    state.usage_events.record(&headers, usage);
+    let path = address.path();
 
     info!(
         font = %req.font_name,
-        format = %req.format,
-        quality = req.quality,
-        "font compress request"
+        format = %format,
+        quality,
+        strip_hints,
+        original_bytes,
+        compressed_bytes,
+        hinting_bytes_saved,
+        outline_bytes_saved,
+        defaults = ?defaults_applied,
+        "font compressed"
     );
 
     Ok(Json(CompressResponse {
         font_name: req.font_name.clone(),
-        format: req.format.clone(),
-        quality: req.quality,
+        format,
+        quality,
+        strip_hints,
+        defaults_applied,
         original_size_kb,
         compressed_size_kb,
         ratio,
-        download_url: format!(
-            "/cdn/fonts/{}/{}.{}",
-            req.font_name.to_lowercase().replace(' ', "-"),
-            req.font_name.to_lowercase().replace(' ', "-"),
-            req.format
-        ),
+        download_url: Some(signing::download_url(&state, &path)),
+        estimate: None,
+        removed_features,
+        hinting_bytes_saved,
+        outline_passes,
+        outline_bytes_saved,
+        data_uri,
+        epub,
     }))
 }
 
 async fn subset(
-    State(_state): State<Arc<AppState>>,
-    Json(req): Json<SubsetRequest>,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(mut req): ApiJson<SubsetRequest>,
 ) -> Result<Json<SubsetResponse>, (StatusCode, String)> {
+    state.flags.ensure("subset", &headers)?;
+    if !warmup::warming() {
+        state.quotas.charge_operation(&headers)?;
+    }
+
+    let (font_name, upload) = state.uploads.resolve(&headers, req.font_id.as_deref(), &req.font_name).await?;
+    req.font_name = font_name;
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &req.font_name)?;
+    req.characters = unicode::normalize_characters(&req.characters, req.decompose);
+    let (output, saved) = match req.profile.as_deref() {
+        None | Some("web") => (SubsetProfile::Web, None),
+        Some("pdf") => (SubsetProfile::Pdf, None),
+        Some(reference) => (SubsetProfile::Web, Some(state.profiles.resolve(&headers, reference)?)),
+    };
+    let defaults = state.defaults_for(&req.font_name);
+    let mut defaults_applied = Vec::new();
     let valid_formats = ["woff2", "woff", "otf", "ttf"];
-    if !valid_formats.contains(&req.format.as_str()) {
+    let options = transform::resolve(&req.transform, &valid_formats, &defaults, &mut defaults_applied)?;
+    let format = options.format.clone();
+    if output == SubsetProfile::Pdf && !matches!(format.as_str(), "ttf" | "otf") {
         return Err((
             StatusCode::BAD_REQUEST,
-            format!(
-                "unsupported format '{}'; valid: woff2, woff, otf, ttf",
-                req.format
-            ),
+            "pdf profile embeds raw sfnt; format must be ttf or otf".to_string(),
         ));
     }
-    if req.font_name.trim().is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "font_name is required".to_string()));
+    let explicit = !req.characters.is_empty() || !req.unicode_range.is_empty() || saved.is_some();
+    let preset = match (req.preset.clone(), defaults.subset_preset) {
+        (Some(p), _) => Some(p),
+        (None, Some(p)) if !explicit => {
+            defaults_applied.push("preset".to_string());
+            Some(p)
+        }
+        (None, _) => None,
+    };
+    let mut preset_ranges = match &preset {
+        Some(name) => unicode::preset(name).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("unknown preset '{name}'; valid: {}", unicode::preset_names()),
+            )
+        })?,
+        None if !explicit => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "characters, unicode_range, preset or a saved profile is required".to_string(),
+            ));
+        }
+        None => Vec::new(),
+    };
+    preset_ranges.extend(unicode::parse_list("unicode_range", &req.unicode_range)?);
+    if let Some(saved) = &saved {
+        preset_ranges.extend(profiles::code_points(&saved.spec));
     }
+    let preset_ranges = unicode::merge(preset_ranges);
+    let requested = preset_ranges.iter().map(|r| (r.end() - r.start() + 1) as usize).sum::<usize>()
+        + req.characters.chars().count();
+    state.sandbox.ensure_characters(&headers, requested)?;
+    let (strip_hints, layout_closure, vertical) = (options.strip_hints, options.layout_closure, req.vertical);
+    let features = options.features.clone().map(|f| if vertical { f.sparing(&subset::VERTICAL_FEATURES) } else { f });
+    let quality = options.quality.unwrap_or(100);
 
-    let character_count = req.characters.chars().count().max(1);
-    let original_glyph_count = 8_500_usize;
-    let subset_glyph_count = character_count.min(original_glyph_count);
-    let original_size_kb = 280.0_f64;
-    let subset_ratio = subset_glyph_count as f64 / original_glyph_count as f64;
-    let format_ratio = if req.format == "woff2" { 0.35 } else { 0.55 };
-    let subset_size_kb = original_size_kb * subset_ratio * format_ratio;
+    if req.dry_run && req.inline {
+        return Err((StatusCode::BAD_REQUEST, "inline needs the output; it cannot be combined with dry_run".to_string()));
+    }
+    let retained_tables = (output == SubsetProfile::Pdf).then(|| pdf::retained_tables(&format));
+
+    let wanted: BTreeSet<u32> =
+        preset_ranges.iter().flat_map(|r| r.clone()).chain(req.characters.chars().map(|c| c as u32)).collect();
+    let character_count = wanted.len();
+    let source = state
+        .artifacts
+        .source(&state, &req.font_name, upload.as_ref())
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let mut steps = serde_json::json!({ "subset": wanted, "strip_hints": strip_hints, "tables": retained_tables });
+    // Only added when off the default so existing artifact addresses stay valid.
+    if !layout_closure {
+        steps["layout_closure"] = false.into();
+    }
+    if vertical {
+        steps["vertical"] = true.into();
+    }
+    // PDF subsets from before tagging named the font but not the file.
+    if retained_tables.is_some() {
+        steps["tagged"] = true.into();
+    }
+    if let Some(features) = &features {
+        steps["features"] = serde_json::json!(features);
+    }
+    if quality != 100 {
+        steps["quality"] = quality.into();
+    }
+    let address = options.address(&state, &req.font_name, &source, steps);
+    // Requests another caller could make the same way, for warming.
+    let replayable = upload.is_none() && saved.is_none();
+    let (record, cache, tables) = if let Some(hit) = state.subsets.get(address.key()) {
+        if !req.dry_run {
+            // Rewrites the file should it have gone missing from storage.
+            state.artifacts.put(&address, &hit.encoded, &hit.record).await?;
+        }
+        (hit.record.clone(), "hit", None)
+    } else if let Some(record) = state.artifacts.lookup::<cache::SubsetRecord>(&address).await? {
+        (record, "hit", None)
+    } else {
+        let upload = match upload {
+            Some(u) => Some(state.uploads.read(&u).await?),
+            None => None,
+        };
+        let data = match upload {
+            Some(data) => data,
+            None => {
+                let data = duplicates::read_catalog_binary(&state, &req.font_name).await;
+                data.map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?
+            }
+        };
+        let verify = (output != SubsetProfile::Pdf && shaping::applies(&wanted, req.verify_shaping))
+            .then(|| req.characters.clone());
+        let task = SubsetTask {
+            options: options.clone(),
+            features,
+            wanted,
+            vertical,
+            verify,
+            pdf: retained_tables.map(|tables| (tables, req.font_name.clone())),
+            quality,
+            dry_run: req.dry_run,
+        };
+        let (original_bytes, removed, report, shaped, pdf, Blob(encoded), tables) =
+            isolation::run(&state, task, data).await?.map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+        let record = cache::SubsetRecord {
+            original_bytes,
+            output_bytes: encoded.len(),
+            report,
+            removed_features: removed,
+            shaping: shaped,
+            pdf,
+        };
+        shaping::enforce(req.verify_shaping, record.shaping.as_ref())?;
+        if !req.dry_run {
+            state.artifacts.put(&address, &encoded, &record).await?;
+            let generated = cache::Generated { record: record.clone(), encoded };
+            state.subsets.insert(address.key().to_string(), Arc::new(generated));
+        }
+        (record, "miss", tables)
+    };
+    if cache == "hit" {
+        shaping::enforce(req.verify_shaping, record.shaping.as_ref())?;
+    }
+    let cache::SubsetRecord { original_bytes, output_bytes, report, removed_features, shaping, pdf } = record;
+    if req.dry_run {
+        let estimate = match tables {
+            Some(tables) => estimate::Estimate::encoded(original_bytes, output_bytes, tables),
+            None => estimate::Estimate::stored(original_bytes, output_bytes),
+        };
+        info!(font = %req.font_name, format = %format, subset_bytes = output_bytes, "font subset dry run");
+        return Ok(Json(SubsetResponse {
+            font_name: req.font_name,
+            format,
+            character_count,
+            original_glyph_count: report.total_glyphs,
+            subset_glyph_count: report.glyphs,
+            original_size_kb: original_bytes as f64 / 1024.0,
+            subset_size_kb: output_bytes as f64 / 1024.0,
+            download_url: None,
+            estimate: Some(estimate),
+            profile: output,
+            features: saved.as_ref().map(|p| p.spec.features.clone()).unwrap_or_default(),
+            saved_profile: saved.map(|p| p.reference()),
+            preset,
+            strip_hints,
+            layout_closure,
+            vertical,
+            defaults_applied,
+            removed_features,
+            shaping,
+            data_uri: None,
+            pdf,
+        }));
+    }
+    let data_uri = if req.inline {
+        let encoded = state.artifacts.read(&address).await?;
+        Some(compress::data_uri(&format, &encoded).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?)
+    } else {
+        None
+    };
+    telemetry::record_output("subset", &format, original_bytes, output_bytes);
+    if !warmup::warming() {
+        let charset = analytics::charset(&req);
+        state.analytics.record_subset(&req.font_name, &format, &charset, original_bytes, output_bytes);
+        let usage = usage_events::Usage {
+            kind: "subset",
+            tenant: tenants::of(&headers),
+            font: &artifacts::slug(&req.font_name),
+            format: &format,
+            bytes: output_bytes as u64,
+            cache,
+        };
+        state.usage_events.record(&headers, usage);
+        if replayable && tenants::owner(&state, &artifacts::slug(&req.font_name)).is_empty() {
+            let replay = SubsetRequest { font_id: None, dry_run: false, inline: false, ..req.clone() };
+            state.analytics.record_subset_request(&replay);
+        }
+    }
+    let path = address.path();
 
     info!(
         font = %req.font_name,
         characters = character_count,
-        format = %req.format,
-        "font subset request"
+        missing = report.missing,
+        glyphs = report.glyphs,
+        format = %format,
+        profile = ?output,
+        saved_profile = ?saved.as_ref().map(|p| p.reference()),
+        preset = ?preset,
+        strip_hints,
+        layout_closure,
+        vertical,
+        subset_bytes = output_bytes,
+        "font subset"
     );
 
     Ok(Json(SubsetResponse {
         font_name: req.font_name.clone(),
-        format: req.format.clone(),
+        format,
         character_count,
-        original_glyph_count,
-        subset_glyph_count,
-        original_size_kb,
-        subset_size_kb,
-        download_url: format!(
-            "/cdn/fonts/{}/subset.{}",
-            req.font_name.to_lowercase().replace(' ', "-"),
-            req.format
-        ),
+        original_glyph_count: report.total_glyphs,
+        subset_glyph_count: report.glyphs,
+        original_size_kb: original_bytes as f64 / 1024.0,
+        subset_size_kb: output_bytes as f64 / 1024.0,
+        download_url: Some(signing::download_url(&state, &path)),
+        estimate: None,
+        profile: output,
+        features: saved.as_ref().map(|p| p.spec.features.clone()).unwrap_or_default(),
+        saved_profile: saved.map(|p| p.reference()),
+        preset,
+        strip_hints,
+        layout_closure,
+        vertical,
+        defaults_applied,
+        removed_features,
+        shaping,
+        data_uri,
+        pdf,
+    }))
+}
+
+async fn analyze(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(mut req): ApiJson<AnalyzeRequest>,
+) -> Result<Json<AnalyzeResponse>, (StatusCode, String)> {
+    state.flags.ensure("analyze", &headers)?;
+
+    let (font_name, upload) = state.uploads.resolve(&headers, req.font_id.as_deref(), &req.font_name).await?;
+    req.font_name = font_name;
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &req.font_name)?;
+
+    let uploaded = match &upload {
+        Some(upload) if upload.flavor == sfnt::Flavor::Ttc => {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("'{}' is a font collection; extract a face via /api/v1/font/uploads/{0}/faces", upload.id),
+            ));
+        }
+        Some(upload) if upload.flavor == sfnt::Flavor::Woff2 => {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("analysis reads sfnt tables; '{}' is WOFF2, upload the TTF/OTF or WOFF", upload.id),
+            ));
+        }
+        Some(upload) => Some(state.uploads.read(upload).await?),
+        None => None,
+    };
+    let data = match uploaded {
+        Some(data) => data,
+        None => duplicates::read_catalog_binary(&state, &req.font_name)
+            .await
+            .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?,
+    };
+    let task = AnalyzeTask { include_kerning: req.include_kerning, include_substitutions: req.include_substitutions };
+    let (size, format, facts, kerning, substitutions) =
+        isolation::run(&state, task, data).await?.map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    info!(font = %req.font_name, upload = ?upload.as_ref().map(|u| &u.id), mode = ?req.mode, "font analyze request");
+    let blocks = (req.mode == AnalyzeMode::Blocks)
+        .then(|| unicode::block_coverage(&unicode::parse_ranges(&facts.unicode_ranges)));
+    Ok(Json(AnalyzeResponse {
+        font_name: req.font_name,
+        glyph_count: facts.glyph_count,
+        format,
+        size_kb: size as f64 / 1024.0,
+        tables: facts.tables,
+        unicode_ranges: facts.unicode_ranges,
+        variation_sequences: facts.variation_sequences,
+        has_variable_axes: !facts.axes.is_empty(),
+        variation_axes: facts.axes,
+        named_instances: facts.instances,
+        axis_values: facts.axis_values,
+        has_math_table: facts.math,
+        color_palettes: facts.palettes.len(),
+        palettes: facts.palettes,
+        colr: facts.colr,
+        color_formats: facts.color_formats,
+        bitmap_strikes: facts.strikes,
+        opentype_features: facts.features,
+        blocks,
+        kerning,
+        substitutions,
     }))
 }
 
-async fn catalog(_state: State<Arc<AppState>>) -> Json<Vec<FontCatalogEntry>> {
-    Json(vec![
+// ── Catalog seed ───────────────────────────────────────────────────────────
+
+/// Built-in entries the catalog starts with.
+fn seed_catalog() -> Vec<FontCatalogEntry> {
+    vec![
         FontCatalogEntry {
             id: "inter".to_string(),
             family: "Inter".to_string(),
@@ -230,6 +1035,14 @@ async fn catalog(_state: State<Arc<AppState>>) -> Json<Vec<FontCatalogEntry>> {
             glyph_count: 3_990,
             unicode_ranges: vec!["U+0000-00FF".to_string(), "U+0100-024F".to_string()],
             license: "OFL-1.1".to_string(),
+            foundry: Some("Rasmus Andersson".to_string()),
+            postscript_name: Some("Inter-Regular".to_string()),
+            defaults: None,
+            private: false,
+            version: 1,
+            attribution: None,
+            tenant: String::new(),
+            cache_control: None,
         },
         FontCatalogEntry {
             id: "noto-sans-jp".to_string(),
@@ -240,6 +1053,14 @@ async fn catalog(_state: State<Arc<AppState>>) -> Json<Vec<FontCatalogEntry>> {
             glyph_count: 22_080,
             unicode_ranges: vec!["U+0020-007E".to_string(), "U+3000-9FFF".to_string()],
             license: "OFL-1.1".to_string(),
+            foundry: Some("Google".to_string()),
+            postscript_name: Some("NotoSansJP-Regular".to_string()),
+            defaults: None,
+            private: false,
+            version: 1,
+            attribution: None,
+            tenant: String::new(),
+            cache_control: None,
         },
         FontCatalogEntry {
             id: "roboto".to_string(),
@@ -250,6 +1071,14 @@ async fn catalog(_state: State<Arc<AppState>>) -> Json<Vec<FontCatalogEntry>> {
             glyph_count: 1_294,
             unicode_ranges: vec!["U+0000-00FF".to_string()],
             license: "Apache-2.0".to_string(),
+            foundry: Some("Google".to_string()),
+            postscript_name: Some("Roboto-Bold".to_string()),
+            defaults: None,
+            private: false,
+            version: 1,
+            attribution: None,
+            tenant: String::new(),
+            cache_control: None,
         },
         FontCatalogEntry {
             id: "fira-code".to_string(),
@@ -260,73 +1089,25 @@ async fn catalog(_state: State<Arc<AppState>>) -> Json<Vec<FontCatalogEntry>> {
             glyph_count: 1_617,
             unicode_ranges: vec!["U+0020-007E".to_string(), "U+FB00-FB06".to_string()],
             license: "OFL-1.1".to_string(),
+            foundry: Some("Nikita Prokopov".to_string()),
+            postscript_name: Some("FiraCode-Regular".to_string()),
+            defaults: None,
+            private: false,
+            version: 1,
+            attribution: None,
+            tenant: String::new(),
+            cache_control: None,
         },
-    ])
-}
-
-async fn analyze(
-    State(_state): State<Arc<AppState>>,
-    Json(req): Json<AnalyzeRequest>,
-) -> Result<Json<AnalyzeResponse>, (StatusCode, String)> {
-    if req.font_name.trim().is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "font_name is required".to_string()));
-    }
-
-    // Deterministic mock analysis keyed on font name
-    let (glyph_count, format, size_kb, variable, palettes, features) =
-        match req.font_name.to_lowercase().as_str() {
-            name if name.contains("noto") => (
-                22_080,
-                "otf",
-                4_200.0,
-                false,
-                0,
-                vec!["kern".to_string(), "liga".to_string(), "calt".to_string()],
-            ),
-            name if name.contains("fira") => (
-                1_617,
-                "ttf",
-                132.0,
-                false,
-                0,
-                vec!["kern".to_string(), "liga".to_string(), "dlig".to_string(), "calt".to_string()],
-            ),
-            name if name.contains("inter") => (
-                3_990,
-                "otf",
-                94.0,
-                true,
-                0,
-                vec!["kern".to_string(), "ss01".to_string(), "cv01".to_string()],
-            ),
-            _ => (
-                1_200,
-                "ttf",
-                80.0,
-                false,
-                0,
-                vec!["kern".to_string()],
-            ),
-        };
-
-    info!(font = %req.font_name, "font analyze request");
-
-    Ok(Json(AnalyzeResponse {
-        font_name: req.font_name,
-        glyph_count,
-        format: format.to_string(),
-        size_kb,
-        unicode_ranges: vec!["U+0000-00FF".to_string(), "U+0100-024F".to_string()],
-        has_variable_axes: variable,
-        color_palettes: palettes,
-        opentype_features: features,
-    }))
+    ]
 }
 
 // ── Main ───────────────────────────────────────────────────────────────────
 
 #[tokio::main]
 async fn main() {
+    if std::env::args().nth(1).as_deref() == Some(isolation::WORKER_FLAG) {
+        isolation::worker();
+    }
     tracing_subscriber::fmt()
         .with_env_filter(
             EnvFilter::try_from_default_env()
@@ -334,27 +1115,418 @@ async fn main() {
         )
         .init();
 
+    if std::env::args().any(|a| a == "--check-config") {
+        let with_storage = std::env::args().any(|a| a == "--check-storage");
+        std::process::exit(check::run(with_storage).await);
+    }
+    match config::EngineConfig::load() {
+        Ok(config) => config.export(),
+        Err(errors) => {
+            for e in &errors {
+                error!("configuration: {e}");
+            }
+            std::process::exit(1);
+        }
+    }
+
+    let migrate_only = std::env::args().any(|a| a == "--migrate-only");
+    let db = match std::env::var("DATABASE_URL").ok().filter(|u| !u.is_empty()) {
+        Some(url) => {
+            let pool = db::connect(&url)
+                .await
+                .expect("failed to connect to DATABASE_URL");
+            db::MIGRATOR
+                .run(&pool)
+                .await
+                .expect("database migration failed");
+            info!(schema_version = ?db::latest_migration(), "database migrations applied");
+            Some(pool)
+        }
+        None if migrate_only => {
+            eprintln!("--migrate-only requires DATABASE_URL");
+            std::process::exit(2);
+        }
+        None => None,
+    };
+    if migrate_only {
+        return;
+    }
+
+    let initial_catalog = match &db {
+        Some(pool) => {
+            let stored = db::load_catalog(pool).await.expect("failed to load catalog");
+            if stored.is_empty() {
+                let seed = seed_catalog();
+                db::replace_catalog(pool, &seed)
+                    .await
+                    .expect("failed to seed catalog");
+                seed
+            } else {
+                stored
+            }
+        }
+        None => seed_catalog(),
+    };
+    let initial_catalog = versions::restore(initial_catalog);
+
+    let initial_staging = match &db {
+        Some(pool) => db::load_staging(pool)
+            .await
+            .expect("failed to load staged catalog entries"),
+        None => Vec::new(),
+    };
+
+    let initial_profiles = match &db {
+        Some(pool) => db::load_subset_profiles(pool)
+            .await
+            .expect("failed to load subset profiles"),
+        None => Vec::new(),
+    };
+
+    let initial_keys = match &db {
+        Some(pool) => db::load_api_keys(pool)
+            .await
+            .expect("failed to load API keys"),
+        None => Vec::new(),
+    };
+
+    let initial_quotas = match &db {
+        Some(pool) => db::load_tenant_quotas(pool)
+            .await
+            .expect("failed to load tenant quotas"),
+        None => Vec::new(),
+    };
+
+    let upload_storage = storage::from_env("uploads", uploads::upload_dir()).expect("invalid storage configuration");
+    let job_queue = queue::JobQueue::from_env(db.clone()).expect("invalid job queue configuration");
     let state = Arc::new(AppState {
         start_time: Instant::now(),
+        admin_token: std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+        keys: auth::ApiKeys::load(initial_keys),
+        rate_limits: ratelimit::RateLimits::from_env(),
+        gc: gc::Gc::from_env(),
+        shedding: shedding::Shedding::from_env(),
+        catalog: RwLock::new(initial_catalog),
+        aliases: aliases::Aliases::load(
+            storage::from_env("aliases", artifacts::artifact_dir().join(".aliases"))
+                .expect("invalid storage configuration"),
+        )
+        .await,
+        staging: RwLock::new(
+            initial_staging
+                .into_iter()
+                .map(|e| (e.id.clone(), e))
+                .collect(),
+        ),
+        db,
+        flags: flags::FeatureFlags::from_env(),
+        cors: cors::CorsPolicy::from_env(),
+        hints: hints::ResourceHints::from_env(),
+        cache_policies: cache_control::CachePolicies::from_env(),
+        profiles: profiles::SubsetProfiles::load(initial_profiles),
+        pairings: pairings::ProfileCache::default(),
+        cmaps: search::CmapCache::default(),
+        scanner: scan::Scanner::from_env(),
+        quarantine: quarantine::Quarantine::default(),
+        sandbox: sandbox::Sandbox::from_env(),
+        signer: signing::UrlSigner::from_env(),
+        uploads: uploads::Uploads::load(upload_storage).await,
+        artifacts: artifacts::ArtifactStore::load(
+            storage::from_env("artifacts", artifacts::artifact_dir()).expect("invalid storage configuration"),
+        )
+        .await,
+        slices: slices::Slices::load(
+            storage::from_env("slices", artifacts::artifact_dir().join(".slices")).expect("invalid storage configuration"),
+        )
+        .await,
+        subsets: cache::SubsetCache::from_env(),
+        jobs: Arc::default(),
+        queue: job_queue,
+        webhooks: webhook::Webhooks::from_env(),
+        crawler: crawl::Crawler::from_env(),
+        google_fonts: google::GoogleFonts::from_env(),
+        edges: Arc::new(edge::EdgeMonitor::from_env()),
+        telemetry: telemetry::install(),
+        analytics: analytics::Analytics::default(),
+        usage_events: usage_events::UsageEvents::from_env(),
+        audit: audit::AuditLog::default(),
+        quotas: quotas::Quotas::load(initial_quotas),
     });
+    tokio::spawn(Arc::clone(&state.edges).run());
+    tokio::spawn(analytics::run(Arc::clone(&state)));
+    tokio::spawn(usage_events::run(Arc::clone(&state)));
+    tokio::spawn(quotas::run(Arc::clone(&state)));
+    tokio::spawn(purge::run(Arc::clone(&state)));
+    tokio::spawn(gc::run(Arc::clone(&state)));
+    tokio::spawn(warmup::run(Arc::clone(&state)));
+    tokio::spawn(ingest::run(Arc::clone(&state)));
+    tokio::spawn(queue::run(Arc::clone(&state)));
+    #[cfg(unix)]
+    tokio::spawn(reload::on_hangup(Arc::clone(&state)));
 
+    let grpc_state = Arc::clone(&state);
+    let cors = middleware::from_fn_with_state(Arc::clone(&state), cors::apply);
+    let aliased = middleware::from_fn_with_state(Arc::clone(&state), aliases::redirect);
     let app = Router::new()
         .route("/health", get(health))
+        .route("/readyz", get(probes::ready))
+        .route("/healthz/live", get(probes::live))
+        .route("/healthz/ready", get(probes::ready))
+        .route("/debug/build", get(debug_build))
+        .route("/metrics", get(telemetry::render))
+        .route("/api/v1/openapi.json", get(openapi::spec))
+        .route("/api/v1/docs", get(openapi::docs))
+        .route(
+            "/cdn/fonts/:slug/:file",
+            get(artifacts::serve)
+                .layer(middleware::from_fn(encoding::negotiate))
+                .layer(aliased.clone())
+                .layer(cors.clone()),
+        )
+        .route(
+            "/cdn/fonts/:slug/:version/:file",
+            get(versions::serve)
+                .layer(middleware::from_fn(encoding::negotiate))
+                .layer(aliased.clone())
+                .layer(cors.clone()),
+        )
+        .route(
+            "/cdn/:tenant/fonts/:slug/:file",
+            get(artifacts::serve_tenant)
+                .layer(middleware::from_fn(encoding::negotiate))
+                .layer(aliased.clone())
+                .layer(cors.clone()),
+        )
+        .route(
+            "/cdn/:tenant/fonts/:slug/:version/:file",
+            get(versions::serve_tenant)
+                .layer(middleware::from_fn(encoding::negotiate))
+                .layer(aliased.clone())
+                .layer(cors.clone()),
+        )
         .route("/api/v1/font/compress", post(compress))
+        .route("/api/v1/font/compress/batch", post(batch::compress))
+        .route("/api/v1/font/compare-formats", post(formats::compare))
         .route("/api/v1/font/subset", post(subset))
-        .route("/api/v1/font/catalog", get(catalog))
+        .route("/api/v1/font/subset/batch", post(batch::subset))
+        .route("/api/v1/font/subset/merged", post(merged::merged))
+        .route("/api/v1/font/subset-from-url", post(crawl::subset_from_url))
+        .route("/api/v1/font/subset/progressive", post(progressive::progressive))
+        .route("/api/v1/font/patch-subset", post(ift::patch_subset))
+        .route("/api/v1/jobs/compress", post(queue::compress))
+        .route("/api/v1/jobs/subset", post(queue::subset))
+        .route("/api/v1/jobs/slices", post(queue::slices))
+        .route("/api/v1/jobs/:id", get(queue::show).delete(queue::cancel))
+        .route("/api/v1/jobs/:id/events", get(queue::events))
+        .route("/api/v1/font/slim", get(slim::slim).layer(cors.clone()))
+        .route("/api/v1/font/:id/preview.svg", get(preview::preview).layer(cors.clone()))
+        .route("/api/v1/font/:id/waterfall.png", get(waterfall::waterfall))
+        .route("/api/v1/font/:id/pairings", get(pairings::pairings))
+        .route("/api/v1/font/:id/glyphs/:file", get(outlines::glyph))
+        .route("/api/v1/font/:id/glyphs.zip", get(outlines::export))
+        .route("/api/v1/font/:id/bundle.zip", get(bundle::bundle))
+        .route("/api/v1/font/slices", post(slices::slice))
+        .route("/api/v1/font/slices/:font", get(slices::show).delete(slices::delete))
+        .route("/api/v1/font/subset-profiles", get(profiles::list))
+        .route(
+            "/api/v1/font/subset-profiles/:name",
+            get(profiles::history).put(profiles::save).delete(profiles::delete),
+        )
+        .route("/api/v1/font/catalog", get(catalog::list))
+        .route("/api/v1/font/catalog/:id", post(catalog::create).put(catalog::update).delete(catalog::retire))
+        .route("/api/v1/font/catalog/:id/versions", get(versions::list).post(versions::add))
+        .route("/api/v1/font/catalog/:id/stats", get(analytics::stats))
+        .route("/api/v1/font/catalog/:id/instances", post(instancer::generate))
         .route("/api/v1/font/analyze", post(analyze))
-        .with_state(state);
+        .route("/api/v1/font/coverage", post(coverage::coverage))
+        .route("/api/v1/font/search/coverage", get(search::coverage))
+        .route("/api/v1/font/diff", post(diff::diff))
+        .route("/api/v1/font/rename", post(rename::rename))
+        .route("/api/v1/font/recolor", post(color::recolor_handler))
+        .route("/api/v1/analytics/fonts/:id", get(analytics::font))
+        .route("/api/v1/analytics/top", get(analytics::top))
+        .route("/api/v1/graphql", get(graphql::schema).post(graphql::query))
+        .route("/api/v1/font/:id/sign-url", post(signing::sign_url))
+        .route("/api/v1/font/instances", post(instances::instances))
+        .route(
+            "/api/v1/font/css",
+            get(stylesheet::css).layer(middleware::from_fn(encoding::negotiate)).layer(cors.clone()),
+        )
+        .route(
+            "/api/v1/font/css/:family",
+            get(stylesheet::family_css).layer(middleware::from_fn(encoding::negotiate)).layer(cors),
+        )
+        .route("/api/v1/font/hints", get(hints::recommended))
+        .route(
+            "/api/v1/font/localize-names",
+            post(fvar::localize_names).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/v1/font/metrics",
+            post(metrics::check).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/v1/font/metrics/repair",
+            post(metrics::repair_handler).layer(DefaultBodyLimit::disable()),
+        )
+        .route("/api/v1/font/metrics/normalize", post(metrics::normalize))
+        .route(
+            "/api/v1/font/diacritics",
+            post(diacritics::report).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/v1/font/unicode-range",
+            post(unicode::from_font).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/v1/font/cjk-widths",
+            post(cjk::report).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/v1/font/math",
+            post(math::report).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/v1/font/subset/bitmaps",
+            post(bitmap::prune_handler).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/v1/font/feature-demos",
+            post(features::demos).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/v1/font/emoji-sprite",
+            post(sprite::sprite_sheet).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/v1/font/epub",
+            post(epub::package).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/v1/font/upload",
+            post(uploads::upload).layer(DefaultBodyLimit::disable()),
+        )
+        .route("/api/v1/font/uploads/:id", get(uploads::show).delete(uploads::delete))
+        .route("/api/v1/font/uploads/:id/faces", get(collection::list).post(collection::register))
+        .route("/api/v1/font/uploads/:id/faces/:index", post(collection::extract))
+        .route(
+            "/api/v1/font/scan",
+            post(scan::scan_handler).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/v1/font/validate",
+            post(validation::validate_handler).layer(DefaultBodyLimit::disable()),
+        )
+        .route("/api/v1/admin/duplicates", post(duplicates::scan))
+        .route("/api/v1/admin/audit", get(audit::list))
+        .route("/api/v1/admin/backup", get(backup::export))
+        .route(
+            "/api/v1/admin/restore",
+            post(backup::restore).layer(DefaultBodyLimit::max(spool::max_upload_bytes() as usize)),
+        )
+        .route("/api/v1/admin/cache/purge", post(purge::purge))
+        .route("/api/v1/admin/gc", get(gc::status).post(gc::collect))
+        .route("/api/v1/admin/catalog/export", get(catalog_sync::export))
+        .route("/api/v1/admin/catalog/import", post(catalog_sync::import))
+        .route("/api/v1/admin/catalog/import/google", post(google::import))
+        .route("/api/v1/admin/ingest", post(ingest::archive).layer(DefaultBodyLimit::disable()))
+        .route("/api/v1/admin/cors", get(cors::list).put(cors::update))
+        .route("/api/v1/admin/flags", get(flags::list))
+        .route("/api/v1/system/info", get(system::info))
+        .route("/api/v1/admin/aliases", get(aliases::list))
+        .route("/api/v1/admin/aliases/:alias", put(aliases::update).delete(aliases::delete))
+        .route("/api/v1/admin/flags/:capability", put(flags::update))
+        .route("/api/v1/admin/hints", get(hints::list).put(hints::update))
+        .route("/api/v1/admin/keys", get(auth::list).post(auth::create))
+        .route("/api/v1/admin/keys/:id", delete(auth::revoke))
+        .route("/api/v1/admin/quarantine", get(quarantine::list))
+        .route("/api/v1/admin/quotas", get(quotas::list))
+        .route("/api/v1/admin/quotas/:tenant", put(quotas::update))
+        .route("/api/v1/admin/reload", post(reload::handler))
+        .route(
+            "/api/v1/admin/quarantine/:id",
+            get(quarantine::show).delete(quarantine::discard),
+        )
+        .route("/api/v1/admin/quarantine/:id/retry", post(quarantine::retry))
+        .route("/api/v1/admin/staging", get(staging::list))
+        .route("/api/v1/admin/staging/promote", post(staging::promote))
+        .route("/api/v1/admin/tuning/brotli", post(tuning::brotli))
+        .route("/api/v1/admin/usage/export", get(quotas::export))
+        .route(
+            "/api/v1/admin/staging/:id",
+            put(staging::stage).delete(staging::discard),
+        )
+        .route_layer(middleware::from_fn(limits::timeout))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&state), shedding::shed))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&state), ratelimit::limit))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&state), auth::authenticate))
+        .route_layer(middleware::from_fn(telemetry::track))
+        .layer(limits::json_body_limit())
+        .with_state(state)
+        .layer(middleware::from_fn(access::log));
+
+    if let Some(grpc_addr) = std::env::var("GRPC_ADDR").ok().filter(|v| !v.is_empty()) {
+        let grpc_addr: SocketAddr = grpc_addr.parse().expect("invalid GRPC_ADDR");
+        let grpc_app = Router::new()
+            .route(grpc::COMPRESS, post(grpc::compress))
+            .route(grpc::SUBSET, post(grpc::subset))
+            .route(grpc::ANALYZE, post(grpc::analyze))
+            .route(grpc::CATALOG, post(grpc::catalog))
+            .route_layer(middleware::from_fn_with_state(Arc::clone(&grpc_state), ratelimit::limit))
+            .route_layer(middleware::from_fn_with_state(Arc::clone(&grpc_state), auth::authenticate))
+            .route_layer(middleware::from_fn(telemetry::track))
+            .fallback(grpc::unimplemented)
+            .with_state(grpc_state)
+            .layer(middleware::from_fn(access::log));
+        let grpc_listener = tokio::net::TcpListener::bind(grpc_addr).await.expect("failed to bind GRPC_ADDR");
+        info!("gRPC API listening on {grpc_addr}");
+        tokio::spawn(async move {
+            axum::serve(grpc_listener, grpc_app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .expect("gRPC server error");
+        });
+    }
 
     let addr: SocketAddr = std::env::var("FONT_ADDR")
         .unwrap_or_else(|_| "0.0.0.0:8082".to_string())
         .parse()
         .expect("invalid FONT_ADDR");
 
-    info!("ALICE Font Engine listening on {addr}");
+    #[cfg(unix)]
+    let activated = systemd::activated_listener();
+    #[cfg(not(unix))]
+    let activated: Option<std::net::TcpListener> = None;
+
+    let listener = match activated {
+        Some(l) => tokio::net::TcpListener::from_std(l).expect("invalid activated socket"),
+        None => tokio::net::TcpListener::bind(addr)
+            .await
+            .expect("failed to bind"),
+    };
+    info!(
+        "ALICE Font Engine listening on {}",
+        listener.local_addr().map_or(addr, |a| a)
+    );
 
-    let listener = tokio::net::TcpListener::bind(addr)
-        .await
-        .expect("failed to bind");
-    axum::serve(listener, app).await.expect("server error");
+    let acceptor = tls::paths().map(|(cert, key)| {
+        tls::acceptor(cert, key).unwrap_or_else(|e| {
+            error!("TLS: {e}");
+            std::process::exit(1);
+        })
+    });
+    if let Some(redirect) = std::env::var("TLS_REDIRECT_ADDR").ok().filter(|_| acceptor.is_some()) {
+        let redirect: SocketAddr = redirect.parse().expect("invalid TLS_REDIRECT_ADDR");
+        let https_port = listener.local_addr().map_or(addr.port(), |a| a.port());
+        tokio::spawn(tls::redirect(redirect, https_port));
+    }
+
+    #[cfg(unix)]
+    systemd::notify_ready("serving");
+
+    match acceptor {
+        Some(acceptor) => tls::serve(listener, acceptor, app).await,
+        None => axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .expect("server error"),
+    }
 }
@@ -0,0 +1,311 @@
+//! OpenType `MATH` table: reporting and glyph retention.
+//!
+//! Math layout engines (MathML in browsers, LuaTeX, Word) read constants for
+//! script shifts, fraction gaps and radicals, per-glyph italic corrections
+//! and accent attachments, and size variants / glyph assemblies for
+//! stretchy operators. A subset that keeps `∑` but drops its display-size
+//! variant or the parts of a tall `(` lays out wrong without any error, so
+//! [`Math::closure`] extends a glyph set with everything the retained glyphs
+//! reference.
+
+use axum::{body::Body, extract::Query, http::StatusCode, response::Json};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use tracing::info;
+
+use crate::{
+    cmap::CharMap,
+    layout::coverage,
+    sfnt::{be_u16, Font},
+    spool,
+};
+
+/// MathValueRecord constants, in table order, after the four plain fields.
+const VALUE_CONSTANTS: [&str; 51] = [
+    "math_leading",
+    "axis_height",
+    "accent_base_height",
+    "flattened_accent_base_height",
+    "subscript_shift_down",
+    "subscript_top_max",
+    "subscript_baseline_drop_min",
+    "superscript_shift_up",
+    "superscript_shift_up_cramped",
+    "superscript_bottom_min",
+    "superscript_baseline_drop_max",
+    "sub_superscript_gap_min",
+    "superscript_bottom_max_with_subscript",
+    "space_after_script",
+    "upper_limit_gap_min",
+    "upper_limit_baseline_rise_min",
+    "lower_limit_gap_min",
+    "lower_limit_baseline_drop_min",
+    "stack_top_shift_up",
+    "stack_top_display_style_shift_up",
+    "stack_bottom_shift_down",
+    "stack_bottom_display_style_shift_down",
+    "stack_gap_min",
+    "stack_display_style_gap_min",
+    "stretch_stack_top_shift_up",
+    "stretch_stack_bottom_shift_down",
+    "stretch_stack_gap_above_min",
+    "stretch_stack_gap_below_min",
+    "fraction_numerator_shift_up",
+    "fraction_numerator_display_style_shift_up",
+    "fraction_denominator_shift_down",
+    "fraction_denominator_display_style_shift_down",
+    "fraction_numerator_gap_min",
+    "fraction_num_display_style_gap_min",
+    "fraction_rule_thickness",
+    "fraction_denominator_gap_min",
+    "fraction_denom_display_style_gap_min",
+    "skewed_fraction_horizontal_gap",
+    "skewed_fraction_vertical_gap",
+    "overbar_vertical_gap",
+    "overbar_rule_thickness",
+    "overbar_extra_ascender",
+    "underbar_vertical_gap",
+    "underbar_rule_thickness",
+    "underbar_extra_descender",
+    "radical_vertical_gap",
+    "radical_display_style_vertical_gap",
+    "radical_rule_thickness",
+    "radical_extra_ascender",
+    "radical_kern_before_degree",
+    "radical_kern_after_degree",
+];
+
+/// Stretchy-glyph data for one direction: size variants and assembly parts
+/// per covered glyph.
+#[derive(Debug, Default)]
+struct Constructions {
+    glyphs: BTreeMap<u16, Vec<u16>>,
+    variants: usize,
+    assemblies: usize,
+}
+
+impl Constructions {
+    fn parse(math: &[u8], base: usize, coverage_offset: u16, offsets_at: usize, count: usize) -> Option<Self> {
+        let mut out = Self::default();
+        if coverage_offset == 0 {
+            return Some(out);
+        }
+        let covered = coverage(math, base + coverage_offset as usize)?;
+        for (i, glyph) in covered.into_iter().enumerate().take(count) {
+            let construction = base + be_u16(math, offsets_at + 2 * i)? as usize;
+            let assembly = be_u16(math, construction)? as usize;
+            let variant_count = be_u16(math, construction + 2)? as usize;
+            let mut referenced = (0..variant_count)
+                .map(|v| be_u16(math, construction + 4 + 4 * v))
+                .collect::<Option<Vec<_>>>()?;
+            out.variants += variant_count;
+            if assembly != 0 {
+                // GlyphAssembly: italics MathValueRecord, partCount, 10-byte parts.
+                let assembly = construction + assembly;
+                let parts = be_u16(math, assembly + 4)? as usize;
+                for p in 0..parts {
+                    referenced.push(be_u16(math, assembly + 6 + 10 * p)?);
+                }
+                out.assemblies += 1;
+            }
+            out.glyphs.insert(glyph, referenced);
+        }
+        Some(out)
+    }
+}
+
+/// Parsed `MATH` table.
+pub struct Math {
+    version: String,
+    constants: BTreeMap<&'static str, i16>,
+    glyph_info: GlyphInfo,
+    min_connector_overlap: u16,
+    vertical: Constructions,
+    horizontal: Constructions,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct GlyphInfo {
+    italics_corrections: usize,
+    top_accent_attachments: usize,
+    extended_shapes: usize,
+    kern_infos: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct VariantsSummary {
+    min_connector_overlap: u16,
+    vertical_glyphs: usize,
+    horizontal_glyphs: usize,
+    size_variants: usize,
+    assemblies: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MathReport {
+    version: String,
+    constants: BTreeMap<&'static str, i16>,
+    glyph_info: GlyphInfo,
+    variants: VariantsSummary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retention: Option<Retention>,
+}
+
+/// What a subset for `text` has to keep for math layout to survive.
+#[derive(Debug, Serialize)]
+struct Retention {
+    text_glyphs: usize,
+    /// Variant and assembly-part glyphs pulled in by the text's glyphs.
+    added_glyphs: Vec<u16>,
+    unmapped: Vec<char>,
+}
+
+impl Math {
+    pub fn parse(math: &[u8]) -> Result<Self, String> {
+        let truncated = || "MATH table truncated".to_string();
+        let major = be_u16(math, 0).ok_or_else(truncated)?;
+        if major != 1 {
+            return Err(format!("unsupported MATH table version {major}"));
+        }
+        let minor = be_u16(math, 2).ok_or_else(truncated)?;
+        let constants_at = be_u16(math, 4).ok_or_else(truncated)? as usize;
+        let info_at = be_u16(math, 6).ok_or_else(truncated)? as usize;
+        let variants_at = be_u16(math, 8).ok_or_else(truncated)? as usize;
+
+        let mut constants = BTreeMap::new();
+        if constants_at != 0 {
+            let plain = [
+                ("script_percent_scale_down", 0),
+                ("script_script_percent_scale_down", 2),
+                ("delimited_sub_formula_min_height", 4),
+                ("display_operator_min_height", 6),
+                ("radical_degree_bottom_raise_percent", 8 + 4 * VALUE_CONSTANTS.len()),
+            ];
+            let values = VALUE_CONSTANTS.iter().enumerate().map(|(i, &name)| (name, 8 + 4 * i));
+            for (name, at) in plain.into_iter().chain(values) {
+                constants.insert(name, be_u16(math, constants_at + at).ok_or_else(truncated)? as i16);
+            }
+        }
+
+        let mut glyph_info = GlyphInfo::default();
+        if info_at != 0 {
+            let sub = |i: usize| be_u16(math, info_at + 2 * i).map(|o| info_at + o as usize);
+            let counted = |i: usize| -> Option<usize> {
+                match be_u16(math, info_at + 2 * i)? {
+                    0 => Some(0),
+                    _ => be_u16(math, sub(i)? + 2).map(usize::from),
+                }
+            };
+            glyph_info.italics_corrections = counted(0).ok_or_else(truncated)?;
+            glyph_info.top_accent_attachments = counted(1).ok_or_else(truncated)?;
+            glyph_info.extended_shapes = match be_u16(math, info_at + 4).ok_or_else(truncated)? {
+                0 => 0,
+                _ => coverage(math, sub(2).ok_or_else(truncated)?).ok_or_else(truncated)?.len(),
+            };
+            glyph_info.kern_infos = counted(3).ok_or_else(truncated)?;
+        }
+
+        let (mut min_connector_overlap, mut vertical, mut horizontal) = (0, Constructions::default(), Constructions::default());
+        if variants_at != 0 {
+            let field = |i: usize| be_u16(math, variants_at + 2 * i).ok_or_else(truncated);
+            min_connector_overlap = field(0)?;
+            let (vert_count, horiz_count) = (field(3)? as usize, field(4)? as usize);
+            let offsets_at = variants_at + 10;
+            vertical = Constructions::parse(math, variants_at, field(1)?, offsets_at, vert_count).ok_or_else(truncated)?;
+            horizontal =
+                Constructions::parse(math, variants_at, field(2)?, offsets_at + 2 * vert_count, horiz_count)
+                    .ok_or_else(truncated)?;
+        }
+
+        Ok(Self {
+            version: format!("{major}.{minor}"),
+            constants,
+            glyph_info,
+            min_connector_overlap,
+            vertical,
+            horizontal,
+        })
+    }
+
+    /// `glyphs` plus every size variant and assembly part they reference,
+    /// transitively.
+    pub fn closure(&self, glyphs: &BTreeSet<u16>) -> BTreeSet<u16> {
+        let mut out = glyphs.clone();
+        let mut pending: Vec<u16> = glyphs.iter().copied().collect();
+        while let Some(g) = pending.pop() {
+            let referenced = [&self.vertical, &self.horizontal].into_iter().filter_map(|c| c.glyphs.get(&g)).flatten();
+            for &r in referenced {
+                if out.insert(r) {
+                    pending.push(r);
+                }
+            }
+        }
+        out
+    }
+
+    fn report(self) -> MathReport {
+        MathReport {
+            version: self.version,
+            constants: self.constants,
+            glyph_info: self.glyph_info,
+            variants: VariantsSummary {
+                min_connector_overlap: self.min_connector_overlap,
+                vertical_glyphs: self.vertical.glyphs.len(),
+                horizontal_glyphs: self.horizontal.glyphs.len(),
+                size_variants: self.vertical.variants + self.horizontal.variants,
+                assemblies: self.vertical.assemblies + self.horizontal.assemblies,
+            },
+            retention: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MathQuery {
+    /// Characters a subset would keep; adds the glyphs math layout needs.
+    text: Option<String>,
+}
+
+/// Reports the MATH table (constants, glyph info, variants) and, with
+/// `?text=`, the extra glyphs a subset must retain.
+pub async fn report(Query(query): Query<MathQuery>, body: Body) -> Result<Json<MathReport>, (StatusCode, String)> {
+    let (data, _) = spool::receive_font(body).await?;
+    let unprocessable = |e: String| (StatusCode::UNPROCESSABLE_ENTITY, e);
+    let font = Font::parse(&data).map_err(unprocessable)?;
+    let math = Math::parse(font.table(b"MATH").ok_or_else(|| unprocessable("font has no MATH table".into()))?)
+        .map_err(unprocessable)?;
+
+    let retention = match &query.text {
+        Some(text) => {
+            let cmap = CharMap::parse(font.table(b"cmap").ok_or_else(|| unprocessable("font has no cmap table".into()))?)
+                .map_err(unprocessable)?;
+            let (mut glyphs, mut unmapped) = (BTreeSet::new(), Vec::new());
+            for c in text.chars() {
+                match cmap.glyph(c) {
+                    Some(g) => {
+                        glyphs.insert(g);
+                    }
+                    None if !unmapped.contains(&c) => unmapped.push(c),
+                    None => {}
+                }
+            }
+            let closure = math.closure(&glyphs);
+            Some(Retention {
+                text_glyphs: glyphs.len(),
+                added_glyphs: closure.difference(&glyphs).copied().collect(),
+                unmapped,
+            })
+        }
+        None => None,
+    };
+
+    let mut report = math.report();
+    info!(
+        version = %report.version,
+        assemblies = report.variants.assemblies,
+        added = retention.as_ref().map_or(0, |r| r.added_glyphs.len()),
+        "math table analyzed"
+    );
+    report.retention = retention;
+    Ok(Json(report))
+}
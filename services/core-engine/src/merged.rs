@@ -0,0 +1,320 @@
+//! Merged page subsets across a font stack.
+//!
+//! A page set in e.g. Inter + Noto Sans JP normally loads two subsets and
+//! flashes the fallback while the second arrives. This endpoint takes the
+//! page's characters and the ordered stack, draws each character from the
+//! first font whose `cmap` covers it, and builds one merged font behind a
+//! single `@font-face`, stored as a content-addressed artifact. Every font
+//! needs its binary in `CATALOG_FONT_DIR` and TrueType (`glyf`) outlines;
+//! CFF fonts return `422`.
+//!
+//! The merged font keeps the first font's names, vertical metrics and
+//! `OS/2`, renamed to `family`. Outlines and advance widths of later fonts
+//! are scaled to the first font's units-per-em; composite glyphs are
+//! flattened, hinting is dropped and variable fonts give their default
+//! instance. Layout tables (`GSUB`, `GPOS`, `kern`) go, since their glyph
+//! IDs no longer apply. Formats are `woff2`, `woff` and `ttf`.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{MergedRequest, RenameRequest};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::RangeInclusive,
+    sync::Arc,
+};
+use tracing::info;
+use ttf_parser::{Face, GlyphId};
+
+use crate::{
+    cancel::{self, CancelToken, Cancelled},
+    cmap::CharMap,
+    compress, duplicates,
+    extract::ApiJson,
+    instancer,
+    instances::css_format,
+    os2, rename,
+    sfnt::{be_u16, Font, Table},
+    signing, unicode, AppState,
+};
+
+const MAX_FONTS: usize = 8;
+
+/// The first font's tables the merged font keeps, `cmap`, `glyf`, `loca`
+/// and `hmtx` rebuilt.
+const KEPT_TABLES: [&[u8; 4]; 10] =
+    [b"head", b"hhea", b"maxp", b"OS/2", b"name", b"post", b"cmap", b"glyf", b"loca", b"hmtx"];
+
+#[derive(Debug, Serialize)]
+pub struct MergedSource {
+    font_name: String,
+    family: String,
+    units_per_em: u16,
+    /// Outline scale into the merged font, when it differs from 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale: Option<f64>,
+    character_count: usize,
+    /// Glyphs the font contributes.
+    glyph_count: usize,
+    characters: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    unicode_range: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MergedResponse {
+    family: String,
+    format: String,
+    character_count: usize,
+    /// Glyphs in the merged font, `.notdef` included.
+    glyph_count: usize,
+    sources: Vec<MergedSource>,
+    /// Characters no font in the stack covers.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    missing: String,
+    size_kb: f64,
+    download_url: String,
+    css: String,
+}
+
+/// A stack entry as resolved from the catalog.
+struct Source {
+    font_name: String,
+    id: String,
+    family: String,
+}
+
+/// What [`merge`] drew from each font of the stack.
+struct Merged {
+    font: Font,
+    /// Characters, glyphs and units-per-em per font.
+    used: Vec<(Vec<char>, usize, u16)>,
+    missing: String,
+}
+
+/// Draws each of `wanted` from the first of `binaries` that maps it, into a
+/// copy of the first font named `family`.
+fn merge(
+    binaries: &[Vec<u8>],
+    wanted: &BTreeSet<char>,
+    family: &str,
+    token: &CancelToken,
+) -> Result<Result<Merged, String>, Cancelled> {
+    let mut faces = Vec::new();
+    for data in binaries {
+        let font = match compress::load(data) {
+            Ok(font) if font.table(b"glyf").is_some() => font,
+            Ok(_) => return Ok(Err("only TrueType (glyf) fonts can be merged".to_string())),
+            Err(e) => return Ok(Err(e)),
+        };
+        let cmap = match font.table(b"cmap").map(CharMap::parse) {
+            Some(Ok(cmap)) => cmap,
+            Some(Err(e)) => return Ok(Err(e)),
+            None => return Ok(Err("font has no cmap table".to_string())),
+        };
+        let upm = font.table(b"head").and_then(|h| be_u16(h, 18)).filter(|&u| u > 0).unwrap_or(1000);
+        faces.push((font.to_bytes(), cmap, upm));
+    }
+
+    let mut used: Vec<(Vec<char>, usize, u16)> = faces.iter().map(|&(_, _, upm)| (Vec::new(), 0, upm)).collect();
+    let mut missing = String::new();
+    for (i, &c) in wanted.iter().enumerate() {
+        if i % 4096 == 0 {
+            token.check()?;
+        }
+        match faces.iter().position(|(_, cmap, _)| cmap.glyph(c).is_some()) {
+            Some(font) => used[font].0.push(c),
+            None => missing.push(c),
+        }
+    }
+
+    let target = f32::from(faces[0].2);
+    let mut glyphs = Vec::new();
+    let mut map = BTreeMap::new();
+    for (i, (data, cmap, upm)) in faces.iter().enumerate() {
+        token.check()?;
+        let face = match Face::parse(data, 0) {
+            Ok(face) => face,
+            Err(e) => return Ok(Err(format!("not a parseable font: {e}"))),
+        };
+        let scale = target / f32::from(*upm);
+        let mut add = |id: GlyphId| {
+            let advance = (f32::from(face.glyph_hor_advance(id).unwrap_or(0)) * scale).round() as u16;
+            glyphs.push((advance, instancer::outline(&face, id, scale)));
+            glyphs.len() as u16 - 1
+        };
+        if i == 0 {
+            add(GlyphId(0));
+        }
+        let mut drawn = BTreeMap::new();
+        for &c in &used[i].0 {
+            let original = cmap.glyph(c).expect("assigned characters are mapped");
+            let glyph = *drawn.entry(original).or_insert_with(|| add(GlyphId(original)));
+            map.insert(c as u32, glyph);
+        }
+        used[i].1 = drawn.len();
+        if glyphs.len() > usize::from(u16::MAX) {
+            return Ok(Err("the merged font would have more than 65535 glyphs".to_string()));
+        }
+    }
+
+    let first = Font::parse(&faces[0].0).expect("parsed once already");
+    let mut font = Font::new(
+        0x0001_0000,
+        first.tables.into_iter().filter(|t| KEPT_TABLES.contains(&&t.tag)).collect::<Vec<Table>>(),
+    );
+    // Glyph names would not match the new glyph order; format 3 has none.
+    if let Some(post) = font.table(b"post").filter(|p| p.len() >= 32) {
+        let mut post = post[..32].to_vec();
+        post[..4].copy_from_slice(&0x0003_0000u32.to_be_bytes());
+        font.set_table(*b"post", post);
+    }
+    font.set_table(*b"cmap", CharMap::new(map).to_bytes());
+    let rebuilt = instancer::replace_glyphs(&mut font, glyphs).and_then(|()| {
+        rename::apply(&mut font, &RenameRequest { family: Some(family.to_string()), ..Default::default() })?;
+        os2::refresh(&mut font)
+    });
+    Ok(rebuilt.map(|()| Merged { font, used, missing }))
+}
+
+pub async fn merged(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(req): ApiJson<MergedRequest>,
+) -> Result<Json<MergedResponse>, (StatusCode, String)> {
+    state.flags.ensure("subset", &headers)?;
+    state.quotas.charge_operation(&headers)?;
+
+    if !["woff2", "woff", "ttf"].contains(&req.format.as_str()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("unsupported format '{}'; valid: woff2, woff, ttf", req.format),
+        ));
+    }
+    if !(2..=MAX_FONTS).contains(&req.fonts.len()) {
+        return Err((StatusCode::BAD_REQUEST, format!("fonts must list 2-{MAX_FONTS} fonts")));
+    }
+    if req.characters.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "characters is required".to_string()));
+    }
+    state.sandbox.ensure_characters(&headers, req.characters.chars().count())?;
+    for name in &req.fonts {
+        state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), name)?;
+    }
+
+    let mut sources: Vec<Source> = Vec::new();
+    {
+        let catalog = state.catalog.read().unwrap();
+        for name in &req.fonts {
+            let key = name.trim().to_lowercase();
+            let entry = catalog
+                .iter()
+                .find(|e| e.id == key || e.family.to_lowercase() == key)
+                .ok_or_else(|| (StatusCode::NOT_FOUND, format!("font '{name}' is not in the catalog")))?;
+            if sources.iter().any(|s| s.id == entry.id) {
+                return Err((StatusCode::BAD_REQUEST, format!("font '{name}' is listed twice")));
+            }
+            sources.push(Source { font_name: name.clone(), id: entry.id.clone(), family: entry.family.clone() });
+        }
+    }
+    let family = req.family.clone().unwrap_or_else(|| sources[0].family.clone());
+
+    let ids: Vec<String> = sources.iter().map(|s| s.id.clone()).collect();
+    let wanted: BTreeSet<char> = req.characters.chars().filter(|c| !c.is_control()).collect();
+    let (job_state, job_ids) = (Arc::clone(&state), ids.clone());
+    let (job_family, format) = (family.clone(), req.format.clone());
+    let (used, missing, encoded) = cancel::run(&state.jobs, "merged-subset", move |token| {
+        let mut binaries = Vec::new();
+        for id in &job_ids {
+            token.check()?;
+            match duplicates::catalog_binary(&job_state, id) {
+                Ok(data) => binaries.push(data),
+                Err(e) => return Ok(Err(e)),
+            }
+        }
+        Ok(merge(&binaries, &wanted, &job_family, token)?.and_then(|merged| {
+            let encoded = compress::encode(&merged.font, &format, 100)?;
+            Ok((merged.used, merged.missing, encoded))
+        }))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let target_upm = used[0].2;
+    let glyph_count = 1 + used.iter().map(|(_, glyphs, _)| glyphs).sum::<usize>();
+    let sources: Vec<MergedSource> = sources
+        .into_iter()
+        .zip(used)
+        .map(|(s, (chars, glyph_count, upm))| {
+            let ranges: Vec<RangeInclusive<u32>> = chars.iter().map(|&c| c as u32..=c as u32).collect();
+            let scale = (upm != target_upm).then(|| (target_upm as f64 / upm as f64 * 10_000.0).round() / 10_000.0);
+            MergedSource {
+                font_name: s.font_name,
+                family: s.family,
+                units_per_em: upm,
+                scale,
+                character_count: chars.len(),
+                glyph_count,
+                unicode_range: unicode::css_unicode_range(&unicode::merge(ranges)),
+                characters: chars.into_iter().collect(),
+            }
+        })
+        .collect();
+    let character_count: usize = sources.iter().map(|s| s.character_count).sum();
+    if character_count == 0 {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "no font in the stack covers any of the characters".to_string(),
+        ));
+    }
+
+    let mut source = Sha256::new();
+    for id in &ids {
+        let digest = state.artifacts.source(&state, id, None).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+        source.update(digest.as_bytes());
+        source.update([0]);
+    }
+    let source = format!("{:x}", source.finalize());
+    let stack: Vec<_> =
+        ids.iter().zip(&sources).map(|(id, s)| serde_json::json!({ "font": id, "characters": s.characters })).collect();
+    let address = state.artifacts.address(&family, &source, &serde_json::json!({ "merge": stack }), &req.format);
+    state.artifacts.put(&address, &encoded, &serde_json::json!({ "output_bytes": encoded.len() })).await?;
+    let download_url = signing::download_url(&state, &address.path());
+
+    let all: Vec<RangeInclusive<u32>> = sources
+        .iter()
+        .flat_map(|s| s.characters.chars().map(|c| c as u32..=c as u32))
+        .collect();
+    let css = format!(
+        "@font-face {{\n  font-family: \"{family}\";\n  font-style: normal;\n  font-weight: 400;\n  \
+         font-display: swap;\n  src: url(\"{download_url}\") format(\"{}\");\n  unicode-range: {};\n}}\n",
+        css_format(&req.format),
+        unicode::css_unicode_range(&unicode::merge(all))
+    );
+
+    info!(
+        fonts = req.fonts.len(),
+        characters = character_count,
+        missing = missing.chars().count(),
+        format = %req.format,
+        bytes = encoded.len(),
+        "merged subset built"
+    );
+
+    Ok(Json(MergedResponse {
+        family,
+        format: req.format,
+        character_count,
+        glyph_count,
+        sources,
+        missing,
+        size_kb: encoded.len() as f64 / 1024.0,
+        download_url,
+        css,
+    }))
+}
@@ -0,0 +1,352 @@
+//! Vertical line metrics: consistency check and repair.
+//!
+//! macOS lays out lines from `hhea`, Windows GDI from `OS/2` win metrics, and
+//! DirectWrite/browsers from `OS/2` typo metrics when `USE_TYPO_METRICS`
+//! (fsSelection bit 7) is set, else win metrics. When these disagree the same
+//! CSS `line-height: normal` differs per platform — the usual cause of "line
+//! height differs on Mac vs Windows" reports.
+//!
+//! `/api/v1/font/metrics` and `/metrics/repair` take a raw font;
+//! `/metrics/normalize` repairs a catalog or uploaded font by name, stores
+//! the result as an artifact and reports the metrics before and after.
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderName, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use font_api::NormalizeMetricsRequest;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+
+use crate::{
+    cancel, compress, duplicates,
+    extract::ApiJson,
+    render,
+    sfnt::{be_u16, Font},
+    signing, spool, AppState,
+};
+
+const USE_TYPO_METRICS: u16 = 1 << 7;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LineMetrics {
+    units_per_em: u16,
+    y_max: i16,
+    y_min: i16,
+    hhea_ascender: i16,
+    hhea_descender: i16,
+    hhea_line_gap: i16,
+    typo_ascender: i16,
+    typo_descender: i16,
+    typo_line_gap: i16,
+    win_ascent: u16,
+    win_descent: u16,
+    os2_version: u16,
+    use_typo_metrics: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricsReport {
+    metrics: LineMetrics,
+    /// Default line height in em on each layout path.
+    line_height_em: LineHeights,
+    issues: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LineHeights {
+    mac: f64,
+    windows_gdi: f64,
+    directwrite_and_browsers: f64,
+}
+
+fn be_i16(data: &[u8], at: usize) -> Option<i16> {
+    be_u16(data, at).map(|v| v as i16)
+}
+
+fn put_u16(data: &mut [u8], at: usize, v: u16) {
+    data[at..at + 2].copy_from_slice(&v.to_be_bytes());
+}
+
+impl LineMetrics {
+    fn read(font: &Font) -> Result<Self, String> {
+        let head = font.table(b"head").ok_or("font has no head table")?;
+        let hhea = font.table(b"hhea").ok_or("font has no hhea table")?;
+        let os2 = font.table(b"OS/2").ok_or("font has no OS/2 table")?;
+        let short = |t: &str| format!("{t} table truncated");
+        Ok(Self {
+            units_per_em: be_u16(head, 18).ok_or_else(|| short("head"))?,
+            y_min: be_i16(head, 38).ok_or_else(|| short("head"))?,
+            y_max: be_i16(head, 42).ok_or_else(|| short("head"))?,
+            hhea_ascender: be_i16(hhea, 4).ok_or_else(|| short("hhea"))?,
+            hhea_descender: be_i16(hhea, 6).ok_or_else(|| short("hhea"))?,
+            hhea_line_gap: be_i16(hhea, 8).ok_or_else(|| short("hhea"))?,
+            os2_version: be_u16(os2, 0).ok_or_else(|| short("OS/2"))?,
+            use_typo_metrics: be_u16(os2, 62).ok_or_else(|| short("OS/2"))? & USE_TYPO_METRICS != 0,
+            typo_ascender: be_i16(os2, 68).ok_or_else(|| short("OS/2"))?,
+            typo_descender: be_i16(os2, 70).ok_or_else(|| short("OS/2"))?,
+            typo_line_gap: be_i16(os2, 72).ok_or_else(|| short("OS/2"))?,
+            win_ascent: be_u16(os2, 74).ok_or_else(|| short("OS/2"))?,
+            win_descent: be_u16(os2, 76).ok_or_else(|| short("OS/2"))?,
+        })
+    }
+
+    fn hhea_height(&self) -> i32 {
+        self.hhea_ascender as i32 - self.hhea_descender as i32 + self.hhea_line_gap as i32
+    }
+
+    fn typo_height(&self) -> i32 {
+        self.typo_ascender as i32 - self.typo_descender as i32 + self.typo_line_gap as i32
+    }
+
+    fn win_height(&self) -> i32 {
+        self.win_ascent as i32 + self.win_descent as i32
+    }
+
+    fn report(self) -> MetricsReport {
+        let mut issues = Vec::new();
+        let em = self.units_per_em.max(1) as f64;
+        let browser = if self.use_typo_metrics { self.typo_height() } else { self.win_height() };
+        if self.hhea_height() != browser {
+            issues.push(format!(
+                "hhea line height ({}) differs from the {} metrics browsers use on Windows ({})",
+                self.hhea_height(),
+                if self.use_typo_metrics { "typo" } else { "win" },
+                browser
+            ));
+        }
+        if self.hhea_height() != self.win_height() && self.hhea_height() == browser {
+            issues.push(format!(
+                "win metrics ({}) differ from hhea ({}): GDI applications will space lines differently",
+                self.win_height(),
+                self.hhea_height()
+            ));
+        }
+        if !self.use_typo_metrics && self.typo_height() != self.win_height() {
+            issues.push("USE_TYPO_METRICS is off and typo metrics disagree with win metrics".to_string());
+        }
+        if (self.win_ascent as i32) < self.y_max as i32 || (self.win_descent as i32) < -(self.y_min as i32) {
+            issues.push(format!(
+                "win metrics ({}/{}) do not cover the glyph bounding box ({}/{}); Windows will clip",
+                self.win_ascent, self.win_descent, self.y_max, -(self.y_min as i32)
+            ));
+        }
+        if self.hhea_descender > 0 || self.typo_descender > 0 {
+            issues.push("descender should be zero or negative".to_string());
+        }
+        MetricsReport {
+            line_height_em: LineHeights {
+                mac: self.hhea_height() as f64 / em,
+                windows_gdi: self.win_height() as f64 / em,
+                directwrite_and_browsers: browser as f64 / em,
+            },
+            metrics: self,
+            issues,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    /// Keep typo metrics; copy them to hhea.
+    Typo,
+    /// Keep hhea metrics; copy them to typo.
+    Hhea,
+    /// Use win metrics, grown to the bounding box, everywhere: identical
+    /// on every platform and never clips, at the cost of looser lines.
+    Win,
+}
+
+impl Strategy {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "typo" => Some(Self::Typo),
+            "hhea" => Some(Self::Hhea),
+            "win" => Some(Self::Win),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RepairQuery {
+    strategy: Strategy,
+}
+
+/// Makes hhea and typo metrics agree per `strategy`, sets USE_TYPO_METRICS,
+/// and grows win metrics to the bounding box where needed so nothing clips.
+fn repair(font: &mut Font, strategy: Strategy) -> Result<(), String> {
+    let m = LineMetrics::read(font)?;
+    let (asc, desc, gap) = match strategy {
+        Strategy::Typo => (m.typo_ascender, m.typo_descender, m.typo_line_gap),
+        Strategy::Hhea => (m.hhea_ascender, m.hhea_descender, m.hhea_line_gap),
+        Strategy::Win => (
+            (m.win_ascent as i32).max(m.y_max as i32) as i16,
+            -(m.win_descent as i32).max(-(m.y_min as i32)) as i16,
+            0,
+        ),
+    };
+    let mut os2 = font.table(b"OS/2").ok_or("font has no OS/2 table")?.to_vec();
+    let mut hhea = font.table(b"hhea").ok_or("font has no hhea table")?.to_vec();
+    if m.os2_version < 4 {
+        // fsSelection bit 7 is only defined from version 4. Version 1 lacks
+        // the five fields versions 2-4 added; append them with neutral
+        // values (unknown x/cap height, space as break char).
+        match os2.len() {
+            86..=95 => {
+                os2.truncate(86);
+                for v in [0u16, 0, 0, 0x20, 2] {
+                    os2.extend_from_slice(&v.to_be_bytes());
+                }
+            }
+            96.. => {}
+            _ => return Err(format!("OS/2 version {} is too old to carry USE_TYPO_METRICS", m.os2_version)),
+        }
+        put_u16(&mut os2, 0, 4);
+    }
+    put_u16(&mut hhea, 4, asc as u16);
+    put_u16(&mut hhea, 6, desc as u16);
+    put_u16(&mut hhea, 8, gap as u16);
+    put_u16(&mut os2, 68, asc as u16);
+    put_u16(&mut os2, 70, desc as u16);
+    put_u16(&mut os2, 72, gap as u16);
+    let win_ascent = (asc as i32 + gap as i32).max(m.y_max as i32).max(0) as u16;
+    let win_descent = (-(desc as i32)).max(-(m.y_min as i32)).max(0) as u16;
+    put_u16(&mut os2, 74, win_ascent);
+    put_u16(&mut os2, 76, win_descent);
+    let fs_selection = be_u16(&os2, 62).unwrap_or(0) | USE_TYPO_METRICS;
+    put_u16(&mut os2, 62, fs_selection);
+    font.set_table(*b"hhea", hhea);
+    font.set_table(*b"OS/2", os2);
+    Ok(())
+}
+
+/// Reports line metrics and their cross-platform inconsistencies.
+pub async fn check(body: Body) -> Result<Json<MetricsReport>, (StatusCode, String)> {
+    let (data, _) = spool::receive_font(body).await?;
+    let font = Font::parse(&data).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let metrics = LineMetrics::read(&font).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    Ok(Json(metrics.report()))
+}
+
+/// Returns the font with line metrics repaired per `?strategy=`; the
+/// remaining issues (if any) are listed in `X-Metrics-Issues`.
+pub async fn repair_handler(
+    Query(query): Query<RepairQuery>,
+    Query(verify): Query<render::VerifyQuery>,
+    body: Body,
+) -> Result<Response, (StatusCode, String)> {
+    let (data, flavor) = spool::receive_font(body).await?;
+    let mut font = Font::parse(&data).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    repair(&mut font, query.strategy).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let remaining = LineMetrics::read(&font)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .report()
+        .issues
+        .len();
+    let out = font.to_bytes();
+    let checked = render::verify(&verify, &data, &out, None)?;
+    info!(strategy = ?query.strategy, remaining, "line metrics repaired");
+    Ok((
+        checked,
+        [
+            (header::CONTENT_TYPE, flavor.media_type().to_string()),
+            (HeaderName::from_static("x-metrics-issues"), remaining.to_string()),
+        ],
+        out,
+    )
+        .into_response())
+}
+
+#[derive(Debug, Serialize)]
+pub struct NormalizeResponse {
+    font_name: String,
+    format: String,
+    strategy: String,
+    before: MetricsReport,
+    after: MetricsReport,
+    size_kb: f64,
+    download_url: String,
+}
+
+/// Stored with a normalized font (see [`artifacts::ArtifactStore`]).
+#[derive(Serialize, Deserialize)]
+struct NormalizeRecord {
+    output_bytes: usize,
+    after: MetricsReport,
+}
+
+/// Repairs a catalog or uploaded font's line metrics into an artifact.
+pub async fn normalize(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(mut req): ApiJson<NormalizeMetricsRequest>,
+) -> Result<Json<NormalizeResponse>, (StatusCode, String)> {
+    state.flags.ensure("compress", &headers)?;
+    state.quotas.charge_operation(&headers)?;
+    let strategy = Strategy::parse(&req.strategy).ok_or_else(|| {
+        (StatusCode::BAD_REQUEST, format!("strategy '{}' must be typo, hhea or win", req.strategy))
+    })?;
+    if !["woff2", "woff", "otf", "ttf"].contains(&req.format.as_str()) {
+        let message = format!("unsupported format '{}'; valid: woff2, woff, otf, ttf", req.format);
+        return Err((StatusCode::BAD_REQUEST, message));
+    }
+    let (font_name, upload) = state.uploads.resolve(&headers, req.font_id.as_deref(), &req.font_name).await?;
+    req.font_name = font_name;
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &req.font_name)?;
+
+    let uploaded = match &upload {
+        Some(upload) => Some(state.uploads.read(upload).await?),
+        None => None,
+    };
+    let (job_state, job_req) = (Arc::clone(&state), req.clone());
+    let (before, after, encoded) = cancel::run(&state.jobs, "metrics-normalize", move |token| {
+        let data = match uploaded {
+            Some(data) => data,
+            None => match duplicates::catalog_binary(&job_state, &job_req.font_name) {
+                Ok(data) => data,
+                Err(e) => return Ok(Err(e)),
+            },
+        };
+        token.check()?;
+        Ok(compress::load(&data).and_then(|mut font| {
+            let before = LineMetrics::read(&font)?.report();
+            repair(&mut font, strategy)?;
+            let after = LineMetrics::read(&font)?.report();
+            Ok((before, after, compress::encode(&font, &job_req.format, 100)?))
+        }))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let source = state
+        .artifacts
+        .source(&state, &req.font_name, upload.as_ref())
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let transform = serde_json::json!({ "line_metrics": req.strategy });
+    let address = state.artifacts.address(&req.font_name, &source, &transform, &req.format);
+    let size_kb = encoded.len() as f64 / 1024.0;
+    let after = match state.artifacts.lookup::<NormalizeRecord>(&address).await? {
+        Some(record) => record.after,
+        None => {
+            let record = NormalizeRecord { output_bytes: encoded.len(), after };
+            state.artifacts.put(&address, &encoded, &record).await?;
+            record.after
+        }
+    };
+
+    info!(font = %req.font_name, strategy = ?strategy, remaining = after.issues.len(), "line metrics normalized");
+    Ok(Json(NormalizeResponse {
+        font_name: req.font_name,
+        format: req.format,
+        strategy: req.strategy,
+        before,
+        after,
+        size_kb,
+        download_url: signing::download_url(&state, &address.path()),
+    }))
+}
@@ -0,0 +1,167 @@
+//! `multipart/form-data` bodies (RFC 7578), read from a fully spooled body
+//! a chunk at a time, so neither the body nor the part is ever held in
+//! memory whole.
+
+use axum::http::StatusCode;
+use std::path::Path;
+use tokio::{fs::File, io::AsyncReadExt};
+
+use crate::spool::{self, SpooledFile};
+
+/// Bytes read from the spooled body at a time.
+const CHUNK_BYTES: usize = 64 * 1024;
+/// Longest header block a part may have.
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+
+/// The boundary of a `multipart/form-data` content type.
+pub fn boundary(content_type: &str) -> Option<String> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .split(';')
+        .filter_map(|p| p.trim().split_once('='))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, v)| v.trim().trim_matches('"').to_string())
+        .filter(|b| (1..=70).contains(&b.len()))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// `name` and `filename` from a `Content-Disposition: form-data` header.
+fn disposition(value: &str) -> (Option<String>, Option<String>) {
+    let (mut name, mut filename) = (None, None);
+    for (k, v) in value.split(';').skip(1).filter_map(|p| p.trim().split_once('=')) {
+        let v = v.trim().trim_matches('"').to_string();
+        match k.trim().to_ascii_lowercase().as_str() {
+            "name" => name = Some(v),
+            "filename" => filename = Some(v),
+            _ => {}
+        }
+    }
+    (name, filename)
+}
+
+fn bad(message: &str) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message.to_string())
+}
+
+/// The unread rest of a spooled body.
+struct Reader {
+    file: File,
+    buf: Vec<u8>,
+}
+
+impl Reader {
+    /// Reads another chunk; `false` at the end of the body.
+    async fn fill(&mut self) -> Result<bool, (StatusCode, String)> {
+        let mut chunk = vec![0; CHUNK_BYTES];
+        let n = self.file.read(&mut chunk).await.map_err(|e| {
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("reading spooled body: {e}"))
+        })?;
+        self.buf.extend_from_slice(&chunk[..n]);
+        Ok(n > 0)
+    }
+
+    /// Whether at least `n` bytes are buffered, reading up to them.
+    async fn has(&mut self, n: usize) -> Result<bool, (StatusCode, String)> {
+        while self.buf.len() < n {
+            if !self.fill().await? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Consumes the body up to and including `needle`, writing what comes
+    /// before it to `out`; `false` if the body ends first.
+    async fn skip_to(
+        &mut self,
+        needle: &[u8],
+        mut out: Option<&mut spool::Writer>,
+    ) -> Result<bool, (StatusCode, String)> {
+        loop {
+            if let Some(at) = find(&self.buf, needle) {
+                if let Some(out) = out.as_deref_mut() {
+                    out.write(&self.buf[..at]).await?;
+                }
+                self.buf.drain(..at + needle.len());
+                return Ok(true);
+            }
+            // Keeps what may be the start of `needle`.
+            let done = self.buf.len().saturating_sub(needle.len() - 1);
+            if let Some(out) = out.as_deref_mut() {
+                out.write(&self.buf[..done]).await?;
+            }
+            self.buf.drain(..done);
+            if !self.fill().await? {
+                return Ok(false);
+            }
+        }
+    }
+}
+
+/// Copies the first part named `field` of the spooled body at `path` to a
+/// spool file of its own, with the part's `filename`; `None` when no part
+/// has that name.
+pub async fn extract(
+    path: &Path,
+    boundary: &str,
+    field: &str,
+) -> Result<Option<(Option<String>, SpooledFile)>, (StatusCode, String)> {
+    let file = File::open(path)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("reading spooled body: {e}")))?;
+    let mut body = Reader { file, buf: Vec::new() };
+    let delimiter = format!("--{boundary}");
+    let close = format!("\r\n--{boundary}");
+    if !body.skip_to(delimiter.as_bytes(), None).await? {
+        return Err(bad("no multipart boundary in body"));
+    }
+    loop {
+        if !body.has(2).await? {
+            return Err(bad("truncated multipart body"));
+        }
+        if body.buf.starts_with(b"--") {
+            return Ok(None);
+        }
+        // Rest of the delimiter line (transport padding), then the headers.
+        if !body.skip_to(b"\r\n", None).await? || !body.has(2).await? {
+            return Err(bad("truncated multipart body"));
+        }
+        let headers = if body.buf.starts_with(b"\r\n") {
+            body.buf.drain(..2);
+            String::new()
+        } else {
+            let end = loop {
+                if let Some(end) = find(&body.buf, b"\r\n\r\n") {
+                    break end;
+                }
+                if body.buf.len() > MAX_HEADER_BYTES || !body.fill().await? {
+                    return Err(bad("truncated multipart part headers"));
+                }
+            };
+            let headers = String::from_utf8_lossy(&body.buf[..end]).into_owned();
+            body.buf.drain(..end + 4);
+            headers
+        };
+        let (name, filename) = headers
+            .split("\r\n")
+            .filter_map(|line| line.split_once(':'))
+            .find(|(k, _)| k.trim().eq_ignore_ascii_case("content-disposition"))
+            .map_or((None, None), |(_, v)| disposition(v));
+        if name.as_deref() == Some(field) {
+            let mut part = spool::Writer::create(spool::max_upload_bytes()).await?;
+            if !body.skip_to(close.as_bytes(), Some(&mut part)).await? {
+                return Err(bad("multipart part is not terminated"));
+            }
+            return Ok(Some((filename, part.finish().await?)));
+        }
+        if !body.skip_to(close.as_bytes(), None).await? {
+            return Err(bad("multipart part is not terminated"));
+        }
+    }
+}
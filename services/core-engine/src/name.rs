@@ -0,0 +1,172 @@
+//! The OpenType `name` table: decoding, editing and re-encoding records.
+
+use crate::sfnt::be_u16;
+
+#[derive(Debug, Clone)]
+pub struct NameRecord {
+    pub platform: u16,
+    pub encoding: u16,
+    pub language: u16,
+    pub name_id: u16,
+    /// Raw string bytes in the platform's encoding.
+    pub value: Vec<u8>,
+}
+
+pub struct NameTable {
+    pub records: Vec<NameRecord>,
+    /// Format 1 language-tag strings (UTF-16BE), kept as-is.
+    lang_tags: Vec<Vec<u8>>,
+}
+
+/// Windows language IDs for the BCP 47 tags we accept.
+const WINDOWS_LANGUAGES: &[(&str, u16)] = &[
+    ("en", 0x0409),
+    ("ja", 0x0411),
+    ("ko", 0x0412),
+    ("zh-hans", 0x0804),
+    ("zh-cn", 0x0804),
+    ("zh-hant", 0x0404),
+    ("zh-tw", 0x0404),
+    ("zh-hk", 0x0C04),
+    ("de", 0x0407),
+    ("fr", 0x040C),
+    ("es", 0x0C0A),
+    ("it", 0x0410),
+    ("pt", 0x0416),
+    ("nl", 0x0413),
+    ("ru", 0x0419),
+    ("pl", 0x0415),
+    ("tr", 0x041F),
+    ("ar", 0x0401),
+    ("he", 0x040D),
+    ("th", 0x041E),
+    ("vi", 0x042A),
+];
+
+pub fn windows_language(tag: &str) -> Option<u16> {
+    let tag = tag.to_ascii_lowercase();
+    WINDOWS_LANGUAGES.iter().find(|(t, _)| *t == tag).map(|&(_, id)| id)
+}
+
+fn utf16be(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+}
+
+impl NameTable {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let truncated = || "name table truncated".to_string();
+        let format = be_u16(data, 0).ok_or_else(truncated)?;
+        let count = be_u16(data, 2).ok_or_else(truncated)? as usize;
+        let storage = be_u16(data, 4).ok_or_else(truncated)? as usize;
+        let string = |len: u16, off: u16| {
+            let start = storage + off as usize;
+            data.get(start..start + len as usize).map(<[u8]>::to_vec).ok_or_else(truncated)
+        };
+        let mut records = Vec::with_capacity(count);
+        for i in 0..count {
+            let at = 6 + 12 * i;
+            let field = |n: usize| be_u16(data, at + 2 * n).ok_or_else(truncated);
+            records.push(NameRecord {
+                platform: field(0)?,
+                encoding: field(1)?,
+                language: field(2)?,
+                name_id: field(3)?,
+                value: string(field(4)?, field(5)?)?,
+            });
+        }
+        let mut lang_tags = Vec::new();
+        if format == 1 {
+            let at = 6 + 12 * count;
+            let tag_count = be_u16(data, at).ok_or_else(truncated)? as usize;
+            for i in 0..tag_count {
+                let len = be_u16(data, at + 2 + 4 * i).ok_or_else(truncated)?;
+                let off = be_u16(data, at + 4 + 4 * i).ok_or_else(truncated)?;
+                lang_tags.push(string(len, off)?);
+            }
+        }
+        Ok(Self { records, lang_tags })
+    }
+
+    /// The English (or first Unicode) value of `name_id`.
+    pub fn get(&self, name_id: u16) -> Option<String> {
+        let rank = |r: &NameRecord| match (r.platform, r.encoding, r.language) {
+            (3, 1, 0x0409) => 0,
+            (3, 1, _) | (0, _, _) => 1,
+            (1, 0, 0) => 2,
+            _ => 3,
+        };
+        let record = self
+            .records
+            .iter()
+            .filter(|r| r.name_id == name_id && rank(r) < 3)
+            .min_by_key(|r| rank(r))?;
+        Some(match record.platform {
+            // Mac Roman; ASCII is all that matters for matching.
+            1 => record.value.iter().map(|&b| b as char).collect(),
+            _ => char::decode_utf16(record.value.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])))
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect(),
+        })
+    }
+
+    /// Sets the Windows Unicode record for `name_id` in `language`, replacing
+    /// any existing one.
+    pub fn set(&mut self, name_id: u16, language: u16, text: &str) {
+        let value = utf16be(text);
+        match self
+            .records
+            .iter_mut()
+            .find(|r| (r.platform, r.encoding, r.language, r.name_id) == (3, 1, language, name_id))
+        {
+            Some(r) => r.value = value,
+            None => self.records.push(NameRecord { platform: 3, encoding: 1, language, name_id, value }),
+        }
+    }
+
+    /// Replaces `name_id` in every platform and language with `text`. Mac
+    /// records are dropped when `text` is not ASCII, and a Windows English
+    /// record is added if there was none.
+    pub fn replace(&mut self, name_id: u16, text: &str) {
+        self.records.retain(|r| r.name_id != name_id || r.platform != 1 || text.is_ascii());
+        for r in self.records.iter_mut().filter(|r| r.name_id == name_id) {
+            r.value = if r.platform == 1 { text.as_bytes().to_vec() } else { utf16be(text) };
+        }
+        if !self.records.iter().any(|r| r.name_id == name_id && r.platform == 3) {
+            self.set(name_id, 0x0409, text);
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut records: Vec<&NameRecord> = self.records.iter().collect();
+        records.sort_by_key(|r| (r.platform, r.encoding, r.language, r.name_id));
+        let format: u16 = if self.lang_tags.is_empty() { 0 } else { 1 };
+        let header_len = 6 + 12 * records.len() + if format == 1 { 2 + 4 * self.lang_tags.len() } else { 0 };
+
+        let mut out = Vec::with_capacity(header_len);
+        let mut storage: Vec<u8> = Vec::new();
+        let mut put = |bytes: &[u8]| {
+            let off = storage.len() as u16;
+            storage.extend_from_slice(bytes);
+            (bytes.len() as u16, off)
+        };
+        for v in [format, records.len() as u16, header_len as u16] {
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        for r in &records {
+            let (len, off) = put(&r.value);
+            for v in [r.platform, r.encoding, r.language, r.name_id, len, off] {
+                out.extend_from_slice(&v.to_be_bytes());
+            }
+        }
+        if format == 1 {
+            out.extend_from_slice(&(self.lang_tags.len() as u16).to_be_bytes());
+            for tag in &self.lang_tags {
+                let (len, off) = put(tag);
+                out.extend_from_slice(&len.to_be_bytes());
+                out.extend_from_slice(&off.to_be_bytes());
+            }
+        }
+        out.extend_from_slice(&storage);
+        out
+    }
+}
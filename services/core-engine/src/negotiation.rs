@@ -0,0 +1,141 @@
+//! Font format negotiation for `auto` URLs and stylesheets.
+//!
+//! `/cdn/fonts/<slug>/<file>.auto` (and the version-pinned and tenant
+//! forms) serves the stored `<file>` in the best format the client can
+//! use, and `formats=auto` on the CSS routes lists only that format in
+//! each face's `src`, as Google Fonts' CSS API does. Both answer with
+//! `Vary: User-Agent, Accept`.
+//!
+//! An `Accept` naming `font/woff2` (or `application/font-woff2`) or WOFF
+//! decides first, the higher `q` winning and WOFF2 a tie; a format it gives
+//! `q=0` is never picked. Otherwise the `User-Agent` does: Chrome 36+, Firefox 39+,
+//! Edge 14+ and Safari 12+ get WOFF2; IE 9+, older Edge, Chrome 5+,
+//! Firefox 4+ and Safari 6+ get WOFF; anything else, bots and command-line
+//! tools included, gets the raw TTF or OTF.
+
+use axum::http::{header, HeaderMap};
+
+/// Web formats, best first.
+const FORMATS: [&str; 4] = ["woff2", "woff", "otf", "ttf"];
+
+/// The `Vary` value of negotiated responses.
+pub const VARY: &str = "user-agent, accept";
+
+/// The major version after `token` in `agent`, as in `Chrome/120.0`.
+fn version(agent: &str, token: &str) -> Option<u32> {
+    let (_, rest) = agent.split_once(token)?;
+    let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
+
+const WOFF2_TYPES: [&str; 2] = ["font/woff2", "application/font-woff2"];
+const WOFF_TYPES: [&str; 2] = ["font/woff", "application/font-woff"];
+
+/// The `q` `accept` gives the first of `types` it lists (1 when unstated).
+fn quality(accept: &str, types: &[&str]) -> Option<f32> {
+    accept.split(',').find_map(|item| {
+        let mut params = item.split(';');
+        let media = params.next()?.trim();
+        if !types.iter().any(|t| media.eq_ignore_ascii_case(t)) {
+            return None;
+        }
+        match params.find_map(|p| p.trim().strip_prefix("q=")) {
+            Some(q) => q.trim().parse().ok(),
+            None => Some(1.0),
+        }
+    })
+}
+
+/// Index into [`FORMATS`] of the best format the client supports.
+fn best(headers: &HeaderMap) -> usize {
+    let accept = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let (woff2, woff) = (quality(accept, &WOFF2_TYPES), quality(accept, &WOFF_TYPES));
+    if woff2.is_some_and(|q| q > 0.0 && q >= woff.unwrap_or(0.0)) {
+        return 0;
+    }
+    if woff.is_some_and(|q| q > 0.0) {
+        return 1;
+    }
+    let refused = |q: Option<f32>| q == Some(0.0);
+    match from_agent(headers) {
+        0 if refused(woff2) && refused(woff) => 2,
+        0 if refused(woff2) => 1,
+        1 if refused(woff) => 2,
+        best => best,
+    }
+}
+
+/// Index into [`FORMATS`] of the best format the `User-Agent` supports.
+fn from_agent(headers: &HeaderMap) -> usize {
+    let agent = headers.get(header::USER_AGENT).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    // (token, WOFF2 from, WOFF from); EdgeHTML also claims Chrome, and
+    // Chrome and Edge also claim Safari, so the order matters.
+    let browsers = [
+        ("Edge/", 14, 12),
+        ("Chrome/", 36, 5),
+        ("CriOS/", 36, 5),
+        ("Firefox/", 39, 4),
+        ("FxiOS/", 1, 1),
+        ("MSIE ", u32::MAX, 9),
+        ("Trident/", u32::MAX, 5),
+        ("Version/", 12, 6),
+    ];
+    for (token, woff2, woff) in browsers {
+        if let Some(v) = version(agent, token) {
+            return if v >= woff2 {
+                0
+            } else if v >= woff {
+                1
+            } else {
+                2
+            };
+        }
+    }
+    2
+}
+
+/// Formats the client behind `headers` can use, best first.
+pub fn formats(headers: &HeaderMap) -> &'static [&'static str] {
+    &FORMATS[best(headers)..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        pairs.iter().map(|(name, value)| (name.clone(), value.parse().unwrap())).collect()
+    }
+
+    fn accept(value: &str) -> &'static [&'static str] {
+        formats(&headers(&[(header::ACCEPT, value)]))
+    }
+
+    #[test]
+    fn accept_decides_by_q_value() {
+        assert_eq!(accept("font/woff2, font/woff"), FORMATS);
+        assert_eq!(accept("font/woff;q=1.0, font/woff2;q=0.5"), &FORMATS[1..]);
+        assert_eq!(accept("font/woff2;q=0.8, font/woff;q=0.8"), FORMATS);
+        assert_eq!(accept("application/font-woff2; q=0.9, */*;q=0.1"), FORMATS);
+        assert_eq!(accept("FONT/WOFF"), &FORMATS[1..]);
+    }
+
+    #[test]
+    fn a_refused_format_is_never_picked() {
+        assert_eq!(accept("font/woff2;q=0, font/woff"), &FORMATS[1..]);
+        assert_eq!(accept("font/woff2;q=0, font/woff;q=0"), &FORMATS[2..]);
+        let chrome = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 Chrome/120.0 Safari/537.36";
+        let refusing = headers(&[(header::ACCEPT, "font/woff2;q=0"), (header::USER_AGENT, chrome)]);
+        assert_eq!(formats(&refusing), &FORMATS[1..]);
+    }
+
+    #[test]
+    fn the_user_agent_decides_without_font_types() {
+        let agent = |ua: &str| formats(&headers(&[(header::ACCEPT, "*/*"), (header::USER_AGENT, ua)]));
+        assert_eq!(agent("Mozilla/5.0 (Windows NT 10.0) AppleWebKit/537.36 Chrome/120.0.0.0 Safari/537.36"), FORMATS);
+        assert_eq!(agent("Mozilla/5.0 (Windows NT 6.1; Trident/7.0; rv:11.0) like Gecko"), &FORMATS[1..]);
+        assert_eq!(agent("Mozilla/5.0 (Macintosh) AppleWebKit/605.1.15 Version/11.1 Safari/605.1.15"), &FORMATS[1..]);
+        assert_eq!(agent("Mozilla/5.0 (Windows NT 10.0) Edge/13.10586"), &FORMATS[1..]);
+        assert_eq!(agent("curl/8.5.0"), &FORMATS[2..]);
+    }
+}
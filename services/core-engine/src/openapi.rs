@@ -0,0 +1,789 @@
+//! OpenAPI description of the engine's HTTP API.
+//!
+//! `GET /api/v1/openapi.json` serves an OpenAPI 3.0 document built from
+//! [`OPERATIONS`], one row per route and method, and the request and response
+//! schemas in [`schemas`]; `GET /api/v1/docs` is a Swagger UI page reading
+//! it. A route added to the router needs its row here too. Bodies without a
+//! schema of their own are described as plain JSON objects, and errors are
+//! always the `text/plain` message the handlers return.
+
+use axum::response::{Html, Json};
+use serde_json::{json, Map, Value};
+
+use Access::{Admin, Key, Public};
+
+/// Who may call a route; see [`crate::auth`].
+#[derive(Clone, Copy)]
+enum Access {
+    Public,
+    /// An API key when `API_AUTH=required`.
+    Key,
+    /// `X-Admin-Token`.
+    Admin,
+}
+
+struct Operation {
+    method: &'static str,
+    path: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+    access: Access,
+    /// Component schema of the JSON body, if the route takes one.
+    request: Option<&'static str>,
+    /// Component schema of the `200` body; a JSON object when absent.
+    response: Option<&'static str>,
+}
+
+const fn op(
+    method: &'static str,
+    path: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+    access: Access,
+) -> Operation {
+    Operation { method, path, tag, summary, access, request: None, response: None }
+}
+
+impl Operation {
+    const fn body(self, request: &'static str, response: &'static str) -> Self {
+        Self { request: Some(request), response: Some(response), ..self }
+    }
+
+    const fn returns(self, response: &'static str) -> Self {
+        Self { response: Some(response), ..self }
+    }
+}
+
+const OPERATIONS: &[Operation] = &[
+    op("get", "/health", "service", "Liveness and job counters", Public).returns("HealthResponse"),
+    op("get", "/readyz", "service", "Readiness, including edge status", Public),
+    op("get", "/healthz/live", "service", "Liveness", Public),
+    op("get", "/healthz/ready", "service", "Readiness with per-dependency checks; 503 when a check fails", Public),
+    op("get", "/debug/build", "service", "Build information", Key),
+    op("get", "/api/v1/system/info", "service", "Replica configuration and recent error rates", Admin),
+    op("get", "/metrics", "service", "Prometheus metrics", Public),
+    op("get", "/cdn/fonts/{slug}/{file}", "delivery", "Download a generated font", Public),
+    op("get", "/cdn/fonts/{slug}/{version}/{file}", "delivery", "Download a version of a catalog font", Public),
+    op("get", "/cdn/{tenant}/fonts/{slug}/{file}", "delivery", "Download a generated font of a tenant", Public),
+    op("get", "/cdn/{tenant}/fonts/{slug}/{version}/{file}", "delivery", "Download a version of a tenant's font", Public),
+    op("post", "/api/v1/font/compress", "fonts", "Convert and compress a font", Key)
+        .body("CompressRequest", "CompressResponse"),
+    op("post", "/api/v1/font/compress/batch", "fonts", "Compress several fonts", Key)
+        .body("CompressBatch", "BatchResponse"),
+    op("post", "/api/v1/font/compare-formats", "fonts", "Compare formats and qualities for a font", Key),
+    op("post", "/api/v1/font/subset", "fonts", "Subset a font to given characters", Key)
+        .body("SubsetRequest", "SubsetResponse"),
+    op("post", "/api/v1/font/subset/batch", "fonts", "Subset several fonts", Key).body("SubsetBatch", "BatchResponse"),
+    op("post", "/api/v1/font/subset/merged", "fonts", "Subset several fonts into one", Key),
+    op("post", "/api/v1/font/subset-from-url", "fonts", "Subset the fonts of web pages to their text", Key),
+    op("post", "/api/v1/font/subset/progressive", "fonts", "Split a font into progressive chunks", Key),
+    op("post", "/api/v1/font/patch-subset", "fonts", "Patch a held subset to cover more code points", Key),
+    op("post", "/api/v1/font/subset/bitmaps", "fonts", "Drop bitmap strikes", Key),
+    op("post", "/api/v1/jobs/compress", "jobs", "Queue a compress job", Key),
+    op("post", "/api/v1/jobs/subset", "jobs", "Queue a subset job", Key),
+    op("post", "/api/v1/jobs/slices", "jobs", "Queue slicing a catalog font", Key),
+    op("get", "/api/v1/jobs/{id}", "jobs", "Job status", Key),
+    op("delete", "/api/v1/jobs/{id}", "jobs", "Cancel a job", Key),
+    op("get", "/api/v1/jobs/{id}/events", "jobs", "Follow a job as Server-Sent Events", Key),
+    op("get", "/api/v1/font/slim", "delivery", "Subset on the fly for a page's text", Public),
+    op("get", "/api/v1/font/{id}/preview.svg", "delivery", "Render text in the font as SVG", Public),
+    op("get", "/api/v1/font/{id}/pairings", "fonts", "Suggest heading/body pairings", Key),
+    op("get", "/api/v1/font/{id}/waterfall.png", "fonts", "Render a waterfall specimen as PNG", Key),
+    op("get", "/api/v1/font/{id}/glyphs/{codepoint}.svg", "fonts", "One glyph's outline as SVG", Key),
+    op("get", "/api/v1/font/{id}/glyphs.zip", "fonts", "Glyph outlines as a ZIP of SVGs", Key),
+    op("get", "/api/v1/font/{id}/bundle.zip", "fonts", "A family's fonts, stylesheet and license as a ZIP", Key),
+    op("post", "/api/v1/font/slices", "fonts", "Slice a font by Unicode range", Key),
+    op("get", "/api/v1/font/slices/{font}", "fonts", "Slices of a font", Key),
+    op("delete", "/api/v1/font/slices/{font}", "fonts", "Delete a font's slices", Key),
+    op("get", "/api/v1/font/subset-profiles", "profiles", "Saved subset profiles", Key),
+    op("get", "/api/v1/font/subset-profiles/{name}", "profiles", "Versions of a profile", Key),
+    op("put", "/api/v1/font/subset-profiles/{name}", "profiles", "Save a profile version", Key),
+    op("delete", "/api/v1/font/subset-profiles/{name}", "profiles", "Delete a profile", Key),
+    op("get", "/api/v1/font/catalog", "catalog", "List catalog entries", Key).returns("CatalogPage"),
+    op("post", "/api/v1/font/catalog/{id}", "catalog", "Add a catalog entry", Admin)
+        .body("FontCatalogEntry", "FontCatalogEntry"),
+    op("put", "/api/v1/font/catalog/{id}", "catalog", "Replace a catalog entry", Admin)
+        .body("FontCatalogEntry", "FontCatalogEntry"),
+    op("delete", "/api/v1/font/catalog/{id}", "catalog", "Retire a catalog entry", Admin),
+    op("get", "/api/v1/font/catalog/{id}/versions", "catalog", "Binary versions of an entry", Key)
+        .returns("FontVersions"),
+    op("post", "/api/v1/font/catalog/{id}/versions", "catalog", "Add a binary version from an upload", Admin)
+        .body("NewVersionRequest", "FontCatalogEntry"),
+    op("get", "/api/v1/font/catalog/{id}/stats", "analytics", "Usage of a catalog entry over time windows", Key),
+    op("post", "/api/v1/font/catalog/{id}/instances", "catalog", "Register static instances of a variable font", Admin)
+        .body("StaticInstancesRequest", "FontCatalogEntries"),
+    op("post", "/api/v1/font/analyze", "fonts", "Inspect a font", Key).body("AnalyzeRequest", "AnalyzeResponse"),
+    op("get", "/api/v1/graphql", "catalog", "GraphQL schema of the catalog", Key),
+    op("post", "/api/v1/graphql", "catalog", "Query the catalog with GraphQL", Key),
+    op("post", "/api/v1/font/coverage", "fonts", "Characters a font covers, with catalog fallbacks", Key)
+        .body("CoverageRequest", "CoverageResponse"),
+    op("get", "/api/v1/font/search/coverage", "catalog", "Catalog fonts covering given characters", Key),
+    op("post", "/api/v1/font/diff", "fonts", "Compare two versions of a font", Key).body("DiffRequest", "DiffResponse"),
+    op("post", "/api/v1/font/rename", "fonts", "Rewrite a font's names", Key).body("RenameRequest", "RenameResponse"),
+    op("post", "/api/v1/font/recolor", "fonts", "Override a color font's palette", Key)
+        .body("RecolorRequest", "RecolorResponse"),
+    op("get", "/api/v1/analytics/fonts/{id}", "analytics", "Daily usage of a font", Key),
+    op("get", "/api/v1/analytics/top", "analytics", "Fonts ranked by a usage counter", Key),
+    op("post", "/api/v1/font/{id}/sign-url", "delivery", "Sign a private font's download URL", Key)
+        .body("SignRequest", "SignedUrl"),
+    op("post", "/api/v1/font/instances", "fonts", "Static instances of a variable font", Key),
+    op("get", "/api/v1/font/css", "delivery", "@font-face stylesheet for several families", Public),
+    op("get", "/api/v1/font/css/{family}", "delivery", "@font-face stylesheet for a family", Public),
+    op("get", "/api/v1/font/hints", "delivery", "Recommended preload hints", Key),
+    op("post", "/api/v1/font/localize-names", "fonts", "Localize named instances", Key),
+    op("post", "/api/v1/font/metrics", "fonts", "Check vertical metrics", Key),
+    op("post", "/api/v1/font/metrics/repair", "fonts", "Repair vertical metrics", Key),
+    op("post", "/api/v1/font/metrics/normalize", "fonts", "Normalize a font's vertical metrics", Key),
+    op("post", "/api/v1/font/diacritics", "fonts", "Diacritic coverage", Key),
+    op("post", "/api/v1/font/unicode-range", "fonts", "unicode-range of a font", Key),
+    op("post", "/api/v1/font/cjk-widths", "fonts", "CJK width report", Key),
+    op("post", "/api/v1/font/math", "fonts", "MATH table report", Key),
+    op("post", "/api/v1/font/feature-demos", "fonts", "OpenType feature demos", Key),
+    op("post", "/api/v1/font/emoji-sprite", "fonts", "Render an emoji sprite sheet", Key),
+    op("post", "/api/v1/font/epub", "fonts", "Package fonts for EPUB", Key),
+    op("post", "/api/v1/font/upload", "uploads", "Upload a font (multipart)", Key),
+    op("get", "/api/v1/font/uploads/{id}", "uploads", "An uploaded font", Key),
+    op("delete", "/api/v1/font/uploads/{id}", "uploads", "Delete an upload", Key),
+    op("get", "/api/v1/font/uploads/{id}/faces", "uploads", "Faces of an uploaded collection", Key)
+        .returns("CollectionFaces"),
+    op("post", "/api/v1/font/uploads/{id}/faces", "uploads", "Register collection faces in the catalog", Admin)
+        .body("RegisterFacesRequest", "FontCatalogEntries"),
+    op("post", "/api/v1/font/uploads/{id}/faces/{index}", "uploads", "Extract a collection face as an upload", Key),
+    op("post", "/api/v1/font/scan", "uploads", "Scan a font for malformed tables", Key),
+    op("post", "/api/v1/font/validate", "uploads", "Validate a font", Key),
+    op("post", "/api/v1/admin/duplicates", "admin", "Find duplicate catalog binaries", Admin),
+    op("get", "/api/v1/admin/audit", "admin", "List audit log events", Admin),
+    op("get", "/api/v1/admin/backup", "admin", "Export a backup", Admin),
+    op("post", "/api/v1/admin/restore", "admin", "Restore a backup", Admin),
+    op("post", "/api/v1/admin/cache/purge", "admin", "Purge generated files", Admin)
+        .body("CachePurgeRequest", "CachePurgeResponse"),
+    op("get", "/api/v1/admin/gc", "admin", "Artifact garbage collection status", Admin),
+    op("post", "/api/v1/admin/gc", "admin", "Collect expired generated files", Admin),
+    op("get", "/api/v1/admin/catalog/export", "admin", "Export a signed catalog snapshot", Admin),
+    op("post", "/api/v1/admin/catalog/import", "admin", "Apply a signed catalog snapshot", Admin),
+    op("post", "/api/v1/admin/catalog/import/google", "admin", "Import families from Google Fonts", Admin)
+        .returns("CatalogImport"),
+    op("post", "/api/v1/admin/ingest", "admin", "Ingest a ZIP archive of fonts into the catalog", Admin)
+        .returns("CatalogImport"),
+    op("get", "/api/v1/admin/cors", "admin", "Allowed CORS origins", Admin).returns("OriginSet"),
+    op("put", "/api/v1/admin/cors", "admin", "Set allowed CORS origins", Admin).body("OriginUpdate", "OriginSet"),
+    op("get", "/api/v1/admin/aliases", "admin", "Catalog aliases", Admin),
+    op("put", "/api/v1/admin/aliases/{alias}", "admin", "Set a catalog alias", Admin),
+    op("delete", "/api/v1/admin/aliases/{alias}", "admin", "Remove a catalog alias", Admin),
+    op("get", "/api/v1/admin/flags", "admin", "Capability flags", Admin),
+    op("put", "/api/v1/admin/flags/{capability}", "admin", "Turn a capability on or off", Admin),
+    op("get", "/api/v1/admin/hints", "admin", "Preload hint lists", Admin),
+    op("put", "/api/v1/admin/hints", "admin", "Set preload hints", Admin),
+    op("get", "/api/v1/admin/keys", "admin", "API keys", Admin),
+    op("post", "/api/v1/admin/keys", "admin", "Create an API key", Admin),
+    op("delete", "/api/v1/admin/keys/{id}", "admin", "Revoke an API key", Admin),
+    op("get", "/api/v1/admin/quarantine", "admin", "Quarantined uploads", Admin),
+    op("get", "/api/v1/admin/quarantine/{id}", "admin", "A quarantined upload", Admin),
+    op("delete", "/api/v1/admin/quarantine/{id}", "admin", "Discard a quarantined upload", Admin),
+    op("post", "/api/v1/admin/quarantine/{id}/retry", "admin", "Retry a quarantined upload", Admin),
+    op("get", "/api/v1/admin/quotas", "admin", "Tenant quotas", Admin).returns("TenantQuotas"),
+    op("put", "/api/v1/admin/quotas/{tenant}", "admin", "Set a tenant's monthly quota", Admin)
+        .body("TenantQuota", "TenantQuotas"),
+    op("post", "/api/v1/admin/reload", "admin", "Reload the catalog and configuration", Admin)
+        .returns("ReloadReport"),
+    op("get", "/api/v1/admin/staging", "admin", "Staged catalog changes", Admin),
+    op("post", "/api/v1/admin/staging/promote", "admin", "Promote staged changes", Admin),
+    op("post", "/api/v1/admin/tuning/brotli", "admin", "WOFF2 size and encode time at each Brotli level", Admin),
+    op("put", "/api/v1/admin/staging/{id}", "admin", "Stage a catalog change", Admin),
+    op("delete", "/api/v1/admin/staging/{id}", "admin", "Discard a staged change", Admin),
+    op("get", "/api/v1/admin/usage/export", "admin", "Monthly usage per tenant (?month=, ?format=csv)", Admin),
+    op("get", "/api/v1/openapi.json", "service", "This document", Public),
+    op("get", "/api/v1/docs", "service", "Swagger UI for this document", Public),
+];
+
+fn reference(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{name}") })
+}
+
+fn object(required: &[&str], properties: Value) -> Value {
+    json!({ "type": "object", "required": required, "properties": properties })
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn strings() -> Value {
+    json!({ "type": "array", "items": { "type": "string" } })
+}
+
+fn integer() -> Value {
+    json!({ "type": "integer", "minimum": 0 })
+}
+
+fn number() -> Value {
+    json!({ "type": "number" })
+}
+
+fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+/// Adds the `font_name` / `font_id` pair the font endpoints accept.
+fn with_source(mut properties: Value) -> Value {
+    properties["font_name"] = json!({ "type": "string", "description": "Catalog id or family" });
+    properties["font_id"] = json!({ "type": "string", "description": "An uploaded font; font_name defaults to its family" });
+    properties
+}
+
+/// The shared transform options (see [`crate::transform`]).
+fn with_transform(mut properties: Value) -> Value {
+    properties["format"] = json!({ "type": "string", "enum": ["woff2", "woff", "ttf", "otf"], "default": "woff2" });
+    properties["quality"] = json!({ "type": "integer", "minimum": 0, "maximum": 100 });
+    properties["strip_hints"] = boolean();
+    properties["drop_features"] = json!({
+        "type": "array",
+        "items": string(),
+        "description": "Feature tags (ss01, or a prefix like ss*) to strip from GSUB and GPOS",
+    });
+    properties["keep_features"] = json!({
+        "type": "array",
+        "items": string(),
+        "description": "Strip every feature but these; exclusive with drop_features",
+    });
+    properties["layout_closure"] = json!({
+        "type": "boolean",
+        "default": true,
+        "description": "When subsetting, keep glyphs reachable through GSUB substitutions (ligatures, ccmp)",
+    });
+    properties["instance"] = json!({
+        "type": "object",
+        "additionalProperties": number(),
+        "description": "Axis positions such as {\"wght\": 600} to cut a static instance at first",
+    });
+    properties["output_name"] = json!({ "type": "string", "description": "File name of the output in its URL" });
+    properties
+}
+
+fn schemas() -> Value {
+    let format = json!({ "type": "string", "enum": ["woff2", "woff", "ttf", "otf"] });
+    let palette = object(&["index", "colors"], json!({
+        "index": integer(),
+        "colors": strings(),
+        "light_background": boolean(),
+        "dark_background": boolean(),
+        "label_name_id": integer(),
+    }));
+    let colr = object(&["version", "layered_glyphs", "layers", "painted_glyphs"], json!({
+        "version": integer(),
+        "layered_glyphs": integer(),
+        "layers": integer(),
+        "painted_glyphs": integer(),
+    }));
+    let strike = object(&["table", "ppem", "glyphs"], json!({
+        "table": { "type": "string", "enum": ["sbix", "CBDT"] },
+        "ppem": integer(),
+        "glyphs": integer(),
+    }));
+    let kerning = object(&["kern_table_pairs", "gpos_pairs", "gpos_class_pairs", "largest"], json!({
+        "kern_table_pairs": integer(),
+        "gpos_pairs": integer(),
+        "gpos_class_pairs": integer(),
+        "largest": { "type": "array", "items": object(&["left", "right", "value", "source"], json!({
+            "left": string(),
+            "right": string(),
+            "value": integer(),
+            "source": { "type": "string", "enum": ["kern", "GPOS", "GPOS class"] },
+        })) },
+    }));
+    let substitutions = object(&["tag", "lookups", "contextual"], json!({
+        "tag": string(),
+        "lookups": integer(),
+        "contextual": boolean(),
+        "ligatures": { "type": "array", "items": { "type": "object" } },
+        "singles": { "type": "array", "items": { "type": "object" } },
+        "alternates": { "type": "array", "items": { "type": "object" } },
+        "truncated": boolean(),
+    }));
+    let source = object(&[], with_source(json!({})));
+    let glyph_changes = object(&["from_count", "to_count", "added", "removed", "advance_changes"], json!({
+        "from_count": integer(),
+        "to_count": integer(),
+        "added_count": integer(),
+        "added": strings(),
+        "removed_count": integer(),
+        "removed": strings(),
+        "advance_change_count": integer(),
+        "advance_changes": { "type": "array", "items": object(&["glyph", "from", "to"], json!({
+            "glyph": string(),
+            "from": integer(),
+            "to": integer(),
+        })) },
+    }));
+    let coverage_changes = object(&["added", "removed"], json!({
+        "added_count": integer(),
+        "added": { "type": "array", "items": string(), "description": "U+ ranges gained" },
+        "removed_count": integer(),
+        "removed": { "type": "array", "items": string(), "description": "U+ ranges lost" },
+    }));
+    let diff_response = object(&["from", "to", "identical", "glyphs", "coverage", "tables", "metrics"], json!({
+        "from": string(),
+        "to": string(),
+        "identical": boolean(),
+        "glyphs": glyph_changes,
+        "coverage": coverage_changes,
+        "tables": { "type": "array", "items": object(&["tag", "delta"], json!({
+            "tag": string(),
+            "from_bytes": integer(),
+            "to_bytes": integer(),
+            "delta": { "type": "integer" },
+        })) },
+        "metrics": { "type": "array", "items": object(&["name"], json!({
+            "name": { "type": "string", "description": "table.field, e.g. OS/2.sTypoAscender" },
+            "from": number(),
+            "to": number(),
+        })) },
+    }));
+    let analyze_response = object(&["font_name", "glyph_count", "format", "size_kb", "tables"], json!({
+        "font_name": string(),
+        "glyph_count": integer(),
+        "format": string(),
+        "size_kb": number(),
+        "tables": strings(),
+        "unicode_ranges": strings(),
+        "variation_sequences": object(&["selectors", "sequences", "distinct_glyphs"], json!({
+            "selectors": strings(),
+            "sequences": integer(),
+            "distinct_glyphs": integer(),
+        })),
+        "has_variable_axes": boolean(),
+        "variation_axes": { "type": "array", "items": object(&["tag", "min", "default", "max"], json!({
+            "tag": string(),
+            "name": string(),
+            "min": number(),
+            "default": number(),
+            "max": number(),
+            "hidden": boolean(),
+        })) },
+        "named_instances": { "type": "array", "items": object(&["name", "coordinates"], json!({
+            "name": string(),
+            "postscript_name": string(),
+            "coordinates": { "type": "object", "additionalProperties": number() },
+        })) },
+        "axis_values": { "type": "array", "items": object(&["name", "location"], json!({
+            "name": string(),
+            "location": { "type": "object", "additionalProperties": number() },
+            "range": { "type": "array", "items": number() },
+            "linked_value": number(),
+            "elidable": boolean(),
+        })) },
+        "has_math_table": boolean(),
+        "color_palettes": integer(),
+        "palettes": { "type": "array", "items": palette },
+        "colr": colr,
+        "color_formats": strings(),
+        "bitmap_strikes": { "type": "array", "items": strike },
+        "opentype_features": strings(),
+        "blocks": { "type": "array", "items": object(&["block", "range", "covered", "total", "percent"], json!({
+            "block": string(),
+            "range": string(),
+            "covered": integer(),
+            "total": { "type": "integer", "description": "Characters Unicode assigns in the block" },
+            "percent": number(),
+        })) },
+        "kerning": kerning,
+        "substitutions": { "type": "array", "items": substitutions },
+    }));
+    let health_response = object(&["status", "uptime_secs", "version", "jobs"], json!({
+        "status": string(),
+        "uptime_secs": integer(),
+        "version": string(),
+        "jobs": { "type": "object" },
+    }));
+    let purge_request = object(&[], json!({
+        "font_id": string(),
+        "prefix": { "type": "string", "description": "A CDN path prefix such as /cdn/fonts/inter/" },
+        "all": boolean(),
+    }));
+    let purge_response = object(&["purged", "evicted", "generation", "urls"], json!({
+        "purged": strings(),
+        "evicted": integer(),
+        "generation": integer(),
+        "urls": strings(),
+    }));
+    let reload_report = object(&["catalog_entries"], json!({
+        "catalog_entries": integer(),
+        "added": strings(),
+        "removed": strings(),
+        "applied": { "type": "array", "items": string(), "description": "Changed settings now in effect" },
+        "restart_required": { "type": "array", "items": string(), "description": "Changed settings needing a restart" },
+    }));
+    let tenant_quota = object(&[], json!({
+        "bandwidth_bytes": { "type": "integer", "minimum": 0, "description": "CDN bytes a month; unlimited if absent" },
+        "operations": { "type": "integer", "minimum": 0, "description": "Compress/subset requests a month" },
+    }));
+    let tenant_usage = object(&["tenant", "month", "downloads", "bandwidth_bytes", "operations"], json!({
+        "tenant": string(),
+        "month": { "type": "string", "description": "YYYY-MM" },
+        "downloads": integer(),
+        "bandwidth_bytes": integer(),
+        "operations": integer(),
+        "bandwidth_quota": integer(),
+        "operations_quota": integer(),
+    }));
+    let inline = json!({ "type": "boolean", "description": "Also return the output as a base64 data: URI" });
+    let data_uri = json!({ "type": "string", "description": "data:font/...;base64,... for inline requests" });
+    json!({
+        "CompressRequest": object(&[], with_source(with_transform(json!({
+            "dry_run": boolean(),
+            "inline": inline,
+            "optimize_outlines": boolean(),
+            "epub": object(&["publication_id"], json!({
+                "publication_id": string(),
+                "algorithm": { "type": "string", "enum": ["idpf", "adobe"], "description": "Defaults to idpf" },
+            })),
+        })))),
+        "CompressResponse": object(
+            &["font_name", "format", "quality", "strip_hints", "original_size_kb", "compressed_size_kb", "ratio"],
+            json!({
+                "font_name": string(),
+                "format": string(),
+                "quality": integer(),
+                "strip_hints": boolean(),
+                "original_size_kb": number(),
+                "compressed_size_kb": number(),
+                "ratio": number(),
+                "download_url": { "type": "string", "description": "Absent for dry runs" },
+                "estimate": reference("Estimate"),
+                "defaults_applied": strings(),
+                "removed_features": strings(),
+                "hinting_bytes_saved": { "type": "integer", "description": "Present when strip_hints is set" },
+                "outline_passes": { "type": "array", "items": object(&["pass", "glyphs", "bytes_saved"], json!({
+                    "pass": string(),
+                    "glyphs": integer(),
+                    "bytes_saved": integer(),
+                })) },
+                "outline_bytes_saved": { "type": "integer", "description": "Present when optimize_outlines is set" },
+                "data_uri": data_uri,
+                "epub": object(&["download_url", "algorithm"], json!({
+                    "download_url": string(),
+                    "algorithm": { "type": "string", "description": "URI for META-INF/encryption.xml" },
+                })),
+            }),
+        ),
+        "SubsetRequest": object(&[], with_source(with_transform(json!({
+            "characters": string(),
+            "preset": { "type": "string", "description": "Named character sets, comma-separated, added to characters" },
+            "vertical": {
+                "type": "boolean",
+                "description": "Keep vertical writing: vert/vrt2 alternates and features, vhea/vmtx metrics",
+            },
+            "verify_shaping": {
+                "type": "string",
+                "enum": ["warn", "fail", "off"],
+                "default": "warn",
+                "description": "Shape complex-script text with source and subset; report or reject (422) differences",
+            },
+            "profile": { "type": "string", "description": "web, pdf, or a saved profile as name[@version]" },
+            "dry_run": boolean(),
+            "inline": inline,
+        })))),
+        "SubsetResponse": object(
+            &["font_name", "format", "character_count", "original_glyph_count", "subset_glyph_count", "profile"],
+            json!({
+                "font_name": string(),
+                "format": string(),
+                "character_count": integer(),
+                "original_glyph_count": integer(),
+                "subset_glyph_count": integer(),
+                "original_size_kb": number(),
+                "subset_size_kb": number(),
+                "download_url": { "type": "string", "description": "Absent for dry runs" },
+                "estimate": reference("Estimate"),
+                "profile": { "type": "string", "enum": ["web", "pdf"] },
+                "saved_profile": string(),
+                "features": strings(),
+                "preset": string(),
+                "strip_hints": boolean(),
+                "layout_closure": boolean(),
+                "vertical": boolean(),
+                "defaults_applied": strings(),
+                "removed_features": strings(),
+                "shaping": object(&["checked"], json!({
+                    "checked": integer(),
+                    "diverged": { "type": "array", "items": object(&["text", "glyphs", "reason"], json!({
+                        "text": string(),
+                        "glyphs": { "type": "array", "items": integer() },
+                        "reason": string(),
+                    })) },
+                })),
+                "data_uri": data_uri,
+            }),
+        ),
+        "Estimate": object(&["basis", "original_bytes", "estimated_bytes"], json!({
+            "basis": { "type": "string", "enum": ["encoded", "stored"] },
+            "original_bytes": integer(),
+            "estimated_bytes": integer(),
+            "groups": { "type": "array", "items": { "type": "array" } },
+            "tables": { "type": "array", "items": object(&["tag", "category", "bytes", "output_bytes"], json!({
+                "tag": string(),
+                "category": string(),
+                "bytes": integer(),
+                "share": number(),
+                "output_bytes": integer(),
+            })) },
+        })),
+        "CompressBatch": {
+            "type": "array", "minItems": 1, "maxItems": crate::batch::MAX_ITEMS, "items": reference("CompressRequest"),
+        },
+        "SubsetBatch": {
+            "type": "array", "minItems": 1, "maxItems": crate::batch::MAX_ITEMS, "items": reference("SubsetRequest"),
+        },
+        "BatchResponse": object(&["succeeded", "failed", "items"], json!({
+            "succeeded": integer(),
+            "failed": integer(),
+            "items": { "type": "array", "items": object(&["index", "status"], json!({
+                "index": integer(),
+                "status": { "type": "integer", "description": "What the single endpoint would have answered" },
+                "result": { "type": "object", "description": "The single endpoint's response" },
+                "error": string(),
+            })) },
+        })),
+        "FontCatalogEntry": object(
+            &["id", "family", "variant", "formats", "size_kb", "glyph_count", "unicode_ranges", "license"],
+            json!({
+                "id": string(),
+                "family": string(),
+                "variant": string(),
+                "formats": strings(),
+                "size_kb": number(),
+                "glyph_count": integer(),
+                "unicode_ranges": strings(),
+                "license": string(),
+                "foundry": string(),
+                "postscript_name": string(),
+                "defaults": object(&[], json!({
+                    "quality": { "type": "integer", "minimum": 0, "maximum": 100 },
+                    "strip_hints": boolean(),
+                    "subset_preset": string(),
+                })),
+                "private": { "type": "boolean", "description": "Generated files need signed URLs" },
+                "version": { "type": "integer", "minimum": 1, "description": "Set by the engine" },
+                "attribution": { "type": "string", "description": "Comment written above the family's CSS" },
+                "tenant": { "type": "string", "description": "Owning tenant; shared when absent" },
+                "cache_control": {
+                    "type": "string",
+                    "description": "Cache-Control for the entry's files and family stylesheet",
+                },
+            }),
+        ),
+        "NewVersionRequest": object(&["font_id"], json!({ "font_id": string() })),
+        "FontVersions": { "type": "array", "items": object(&["version", "size_kb", "sha256", "current"], json!({
+            "version": integer(),
+            "size_kb": number(),
+            "sha256": string(),
+            "current": boolean(),
+        })) },
+        "StaticInstancesRequest": object(&[], json!({
+            "instances": { "type": "array", "items": { "oneOf": [string(), object(&["axes"], json!({
+                "axes": { "type": "object", "additionalProperties": number() },
+                "name": string(),
+            }))] } },
+        })),
+        "FontCatalogEntries": { "type": "array", "items": reference("FontCatalogEntry") },
+        "CollectionFaces": object(&["id", "faces"], json!({
+            "id": string(),
+            "faces": { "type": "array", "items": object(
+                &["index", "format", "glyph_count", "tables", "size_kb"],
+                json!({
+                    "index": integer(),
+                    "family": string(),
+                    "subfamily": string(),
+                    "full_name": string(),
+                    "postscript_name": string(),
+                    "format": { "type": "string", "enum": ["ttf", "otf"] },
+                    "glyph_count": integer(),
+                    "tables": strings(),
+                    "size_kb": number(),
+                }),
+            ) },
+        })),
+        "RegisterFacesRequest": object(&["license"], json!({
+            "faces": { "type": "array", "items": integer(), "description": "Face indices; all when absent" },
+            "license": string(),
+            "foundry": string(),
+        })),
+        "CatalogPage": object(&["items", "total", "page", "per_page", "pages"], json!({
+            "items": { "type": "array", "items": { "type": "object" } },
+            "total": integer(),
+            "page": integer(),
+            "per_page": integer(),
+            "pages": integer(),
+        })),
+        "AnalyzeRequest": object(&[], with_source(json!({
+            "mode": { "type": "string", "enum": ["summary", "blocks"] },
+            "include_kerning": boolean(),
+            "include_substitutions": boolean(),
+        }))),
+        "CoverageRequest": object(&[], with_source(json!({
+            "characters": string(),
+            "preset": { "type": "string", "description": "Named character sets, comma-separated, added to characters" },
+        }))),
+        "CoverageResponse": object(
+            &["font_name", "requested", "covered", "coverage_percent", "missing", "fallbacks", "uncovered"],
+            json!({
+                "font_name": string(),
+                "requested": integer(),
+                "covered": integer(),
+                "coverage_percent": number(),
+                "missing": string(),
+                "missing_ranges": strings(),
+                "fallbacks": { "type": "array", "items": object(&["id", "family", "covers", "characters"], json!({
+                    "id": string(),
+                    "family": string(),
+                    "covers": integer(),
+                    "characters": string(),
+                })) },
+                "uncovered": { "type": "string", "description": "Missing characters no catalog font declares" },
+            }),
+        ),
+        "DiffRequest": object(&["from", "to"], json!({ "from": source.clone(), "to": source })),
+        "DiffResponse": diff_response,
+        "RenameRequest": object(&[], with_source(json!({
+            "family": string(),
+            "subfamily": string(),
+            "full_name": { "type": "string", "description": "Derived from family and subfamily when absent" },
+            "postscript_name": { "type": "string", "description": "Derived as Family-Subfamily when absent" },
+            "format": format,
+            "catalog_id": { "type": "string", "description": "Also register as this catalog entry (admin only)" },
+        }))),
+        "RenameResponse": object(&["font_name", "format", "names", "size_kb", "download_url"], json!({
+            "font_name": string(),
+            "format": string(),
+            "names": { "type": "object", "additionalProperties": string() },
+            "size_kb": number(),
+            "download_url": string(),
+            "catalog_entry": reference("FontCatalogEntry"),
+        })),
+        "RecolorRequest": object(&["colors"], with_source(json!({
+            "palette": { "type": "integer", "minimum": 0, "description": "0 when absent" },
+            "colors": {
+                "type": "object",
+                "additionalProperties": string(),
+                "description": "#RRGGBB or #RRGGBBAA by palette entry index",
+            },
+            "format": format,
+        }))),
+        "RecolorResponse": object(&["font_name", "format", "palette", "colors", "size_kb", "download_url"], json!({
+            "font_name": string(),
+            "format": string(),
+            "palette": integer(),
+            "colors": strings(),
+            "size_kb": number(),
+            "download_url": string(),
+        })),
+        "AnalyzeResponse": analyze_response,
+        "SignRequest": object(&["url"], json!({
+            "url": { "type": "string", "description": "A download_url or /cdn/fonts/ path of this font" },
+            "expires_in": { "type": "integer", "minimum": 1, "description": "Seconds; SIGNED_URL_TTL_SECS by default" },
+        })),
+        "SignedUrl": object(&["url", "expires_at"], json!({
+            "url": string(),
+            "expires_at": { "type": "integer", "description": "Unix seconds" },
+        })),
+        "OriginSet": object(&["global", "tenants"], json!({
+            "global": strings(),
+            "tenants": { "type": "object", "additionalProperties": strings() },
+        })),
+        "OriginUpdate": object(&["origins"], json!({
+            "tenant": { "type": "string", "description": "Global when absent" },
+            "origins": strings(),
+        })),
+        "CachePurgeRequest": purge_request,
+        "CachePurgeResponse": purge_response,
+        "CatalogImport": object(&["imported"], json!({
+            "imported": { "type": "array", "items": reference("FontCatalogEntry") },
+            "skipped": { "type": "array", "items": string(), "description": "IDs already in the catalog" },
+            "failed": {
+                "type": "object",
+                "additionalProperties": string(),
+                "description": "Why each file, family or face was not imported",
+            },
+        })),
+        "ReloadReport": reload_report,
+        "TenantQuota": tenant_quota,
+        "TenantQuotas": { "type": "object", "additionalProperties": reference("TenantQuota") },
+        "TenantUsage": tenant_usage,
+        "HealthResponse": health_response,
+    })
+}
+
+/// `{name}` segments of an OpenAPI path.
+fn path_parameters(path: &str) -> Vec<Value> {
+    path.split('/')
+        .filter_map(|s| s.strip_prefix('{')?.strip_suffix('}'))
+        .map(|name| json!({ "name": name, "in": "path", "required": true, "schema": string() }))
+        .collect()
+}
+
+fn operation(op: &Operation) -> Value {
+    let content = |schema: Value| json!({ "application/json": { "schema": schema } });
+    let ok = op.response.map_or_else(|| json!({ "type": "object" }), reference);
+    let error = json!({ "description": "Error", "content": { "text/plain": { "schema": string() } } });
+    let mut value = json!({
+        "tags": [op.tag],
+        "summary": op.summary,
+        "parameters": path_parameters(op.path),
+        "responses": { "200": { "description": "OK", "content": content(ok) }, "default": error },
+    });
+    if let Some(request) = op.request {
+        value["requestBody"] = json!({ "required": true, "content": content(reference(request)) });
+    }
+    match op.access {
+        Public => value["security"] = json!([]),
+        Key => {}
+        Admin => value["security"] = json!([{ "adminToken": [] }]),
+    }
+    value
+}
+
+fn document() -> Value {
+    let mut paths = Map::new();
+    for op in OPERATIONS {
+        let item = paths.entry(op.path).or_insert_with(|| json!({}));
+        item[op.method] = operation(op);
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": { "title": "ALICE Font Engine", "version": env!("CARGO_PKG_VERSION") },
+        "paths": paths,
+        "components": {
+            "schemas": schemas(),
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer" },
+                "apiKey": { "type": "apiKey", "in": "header", "name": "X-API-Key" },
+                "adminToken": { "type": "apiKey", "in": "header", "name": "X-Admin-Token" },
+            },
+        },
+        // Keys are only enforced with API_AUTH=required.
+        "security": [{ "bearer": [] }, { "apiKey": [] }, {}],
+    })
+}
+
+pub async fn spec() -> Json<Value> {
+    Json(document())
+}
+
+const SWAGGER_UI: &str = r##"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>ALICE Font Engine API</title>
+<link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+<div id="swagger-ui"></div>
+<script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+<script>SwaggerUIBundle({ url: "/api/v1/openapi.json", dom_id: "#swagger-ui" });</script>
+</body>
+</html>
+"##;
+
+pub async fn docs() -> Html<&'static str> {
+    Html(SWAGGER_UI)
+}
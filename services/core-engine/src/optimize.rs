@@ -0,0 +1,252 @@
+//! `optimize_outlines`: `glyf` optimization before encoding.
+//!
+//! Passes, in order, each reported with the glyphs it changed and the `glyf`
+//! bytes it saved:
+//!
+//! - `encoding`: simple glyphs re-encoded with the shortest coordinates and
+//!   folded flag runs, where that is smaller.
+//! - `points`: on-curve points that repeat their predecessor or lie on the
+//!   straight line between their on-curve neighbours are dropped, in glyphs
+//!   without instructions (instructions address points by index).
+//! - `duplicates`: a glyph identical to an earlier one becomes a composite of
+//!   it.
+//! - `composites`: a glyph that is an earlier one moved becomes a composite
+//!   of it, offset.
+//! - `bounds`: bounding boxes are recomputed from the points (and from moved
+//!   components), along with `head`'s and the left side bearings that
+//!   matched the old boxes.
+//!
+//! Glyph IDs are kept, as in subsetting, so layout, kerning and color tables
+//! stay valid untouched; glyphs are not renumbered. Variable fonts get only
+//! `encoding` and `bounds`, since `gvar` deltas address points. Fonts without
+//! `glyf` are left as they are. `maxp` composite limits are raised where the
+//! new composites need it.
+
+use font_api::OutlinePass;
+use std::collections::HashMap;
+
+use crate::{
+    glyf::{self, Glyf, Outline, Point},
+    sfnt::{be_u16, Font},
+};
+
+/// Composite nesting followed before giving up on a (cyclic) glyph.
+const MAX_DEPTH: usize = 16;
+
+fn size(glyphs: &[Vec<u8>]) -> usize {
+    glyphs.iter().map(|g| g.len().next_multiple_of(2)).sum()
+}
+
+struct Passes {
+    glyphs: Vec<Vec<u8>>,
+    report: Vec<OutlinePass>,
+}
+
+impl Passes {
+    /// Runs `pass` over every glyph; `change` returns a glyph's new bytes.
+    fn run(&mut self, pass: &str, mut change: impl FnMut(usize, &[Vec<u8>]) -> Option<Vec<u8>>) {
+        let before = size(&self.glyphs);
+        let mut glyphs = 0;
+        for g in 0..self.glyphs.len() {
+            if let Some(bytes) = change(g, &self.glyphs) {
+                self.glyphs[g] = bytes;
+                glyphs += 1;
+            }
+        }
+        let bytes_saved = before.saturating_sub(size(&self.glyphs));
+        self.report.push(OutlinePass { pass: pass.to_string(), glyphs, bytes_saved });
+    }
+}
+
+/// An instruction-free simple glyph.
+fn plain(data: &[u8]) -> Option<Outline> {
+    Outline::parse(data).filter(|o| o.instructions.is_empty())
+}
+
+/// `c` is on-curve between on-curve `p` and `q` on a straight line, or
+/// repeats `p`.
+fn redundant(p: Point, c: Point, q: Point) -> bool {
+    if !(p.on_curve && c.on_curve && q.on_curve) {
+        return false;
+    }
+    let (ax, ay, bx, by) = ((c.x - p.x) as i64, (c.y - p.y) as i64, (q.x - c.x) as i64, (q.y - c.y) as i64);
+    ax * by == ay * bx && ax * bx + ay * by >= 0
+}
+
+/// Drops redundant points, keeping at least three per contour; returns how
+/// many went.
+fn drop_redundant(outline: &mut Outline) -> usize {
+    let (mut points, mut ends) = (Vec::with_capacity(outline.points.len()), Vec::with_capacity(outline.ends.len()));
+    let mut start = 0;
+    for &end in &outline.ends {
+        let mut contour = outline.points[start..=end as usize].to_vec();
+        start = end as usize + 1;
+        let mut i = 0;
+        while contour.len() > 3 && i < contour.len() {
+            let n = contour.len();
+            if redundant(contour[(i + n - 1) % n], contour[i], contour[(i + 1) % n]) {
+                contour.remove(i);
+                i = i.saturating_sub(1);
+            } else {
+                i += 1;
+            }
+        }
+        points.extend(contour);
+        ends.push((points.len() - 1) as u16);
+    }
+    let removed = outline.points.len() - points.len();
+    (outline.points, outline.ends) = (points, ends);
+    removed
+}
+
+fn stored_bbox(data: &[u8]) -> Option<[i16; 4]> {
+    (data.len() >= 10).then(|| [2, 4, 6, 8].map(|at| be_u16(data, at).unwrap_or_default() as i16))
+}
+
+/// `glyph`'s bounding box from its points, or its moved components'; the
+/// stored one for transformed composites; `None` for empty glyphs.
+fn bbox(glyphs: &[Vec<u8>], glyph: usize, depth: usize) -> Option<[i16; 4]> {
+    let data = glyphs.get(glyph)?;
+    if let Some(outline) = Outline::parse(data) {
+        return Some(outline.bounds());
+    }
+    match glyf::component_offsets(data) {
+        Some(components) if depth < MAX_DEPTH => components
+            .into_iter()
+            .filter_map(|(c, dx, dy)| {
+                let [x0, y0, x1, y1] = bbox(glyphs, c as usize, depth + 1)?;
+                Some([x0 as i32 + dx, y0 as i32 + dy, x1 as i32 + dx, y1 as i32 + dy])
+            })
+            .reduce(|a, b| [a[0].min(b[0]), a[1].min(b[1]), a[2].max(b[2]), a[3].max(b[3])])
+            .map(|b| b.map(|v| v.clamp(i16::MIN as i32, i16::MAX as i32) as i16)),
+        _ => stored_bbox(data),
+    }
+}
+
+/// Points, contours and nesting depth of `glyph` with its components.
+fn extent(glyphs: &[Vec<u8>], glyph: usize, depth: usize) -> (usize, usize, usize) {
+    let data = glyphs.get(glyph).map_or(&[][..], Vec::as_slice);
+    let contours = be_u16(data, 0).unwrap_or_default() as i16;
+    if contours >= 0 {
+        let points = match contours {
+            0 => 0,
+            n => be_u16(data, 10 + 2 * (n as usize - 1)).map_or(0, |e| e as usize + 1),
+        };
+        return (points, contours as usize, 0);
+    }
+    if depth >= MAX_DEPTH {
+        return (0, 0, 0);
+    }
+    glyf::components_of(data).into_iter().fold((0, 0, 1), |(p, c, d), component| {
+        let (cp, cc, cd) = extent(glyphs, component as usize, depth + 1);
+        (p + cp, c + cc, d.max(cd + 1))
+    })
+}
+
+/// Raises `maxp`'s composite limits to what `glyphs` need.
+fn raise_maxp(font: &mut Font, glyphs: &[Vec<u8>]) {
+    let Some(mut maxp) = font.table(b"maxp").filter(|m| m.len() >= 32).map(<[u8]>::to_vec) else { return };
+    let mut needed = [0usize; 4];
+    for (g, data) in glyphs.iter().enumerate() {
+        if be_u16(data, 0).is_some_and(|c| (c as i16) < 0) {
+            let (points, contours, depth) = extent(glyphs, g, 0);
+            let elements = glyf::components_of(data).len();
+            for (n, v) in needed.iter_mut().zip([points, contours, elements, depth]) {
+                *n = (*n).max(v);
+            }
+        }
+    }
+    // maxCompositePoints, maxCompositeContours, maxComponentElements,
+    // maxComponentDepth.
+    for (at, v) in [10, 12, 28, 30].into_iter().zip(needed) {
+        let raised = be_u16(&maxp, at).unwrap_or_default().max(v.min(u16::MAX as usize) as u16);
+        maxp[at..at + 2].copy_from_slice(&raised.to_be_bytes());
+    }
+    font.set_table(*b"maxp", maxp);
+}
+
+/// Moves `head`'s box to the glyphs' and each left side bearing that matched
+/// its glyph's old `xMin` to the new one.
+fn sync_metrics(font: &mut Font, glyphs: &[Vec<u8>], moved: &[(usize, i16, i16)]) -> Result<(), String> {
+    let boxes = glyphs.iter().filter(|g| !g.is_empty()).filter_map(|g| stored_bbox(g));
+    if let Some(b) = boxes.reduce(|a, b| [a[0].min(b[0]), a[1].min(b[1]), a[2].max(b[2]), a[3].max(b[3])]) {
+        let mut head = font.table(b"head").ok_or("font has no head table")?.to_vec();
+        for (i, v) in b.into_iter().enumerate() {
+            head.get_mut(36 + 2 * i..38 + 2 * i).ok_or("head table truncated")?.copy_from_slice(&v.to_be_bytes());
+        }
+        font.set_table(*b"head", head);
+    }
+    let metrics = font.table(b"hhea").and_then(|h| be_u16(h, 34)).zip(font.table(b"hmtx"));
+    let Some((long, hmtx)) = metrics.map(|(n, t)| (n as usize, t.to_vec())) else { return Ok(()) };
+    let mut hmtx = hmtx;
+    for &(g, old, new) in moved {
+        let at = if g < long { 4 * g + 2 } else { 4 * long + 2 * (g - long) };
+        if be_u16(&hmtx, at).is_some_and(|lsb| lsb as i16 == old) {
+            hmtx[at..at + 2].copy_from_slice(&new.to_be_bytes());
+        }
+    }
+    font.set_table(*b"hmtx", hmtx);
+    Ok(())
+}
+
+/// Runs the passes on `font`'s outlines; none for fonts without `glyf`.
+pub fn optimize(font: &mut Font) -> Result<Vec<OutlinePass>, String> {
+    let Some(glyf) = Glyf::parse(font).transpose()? else { return Ok(Vec::new()) };
+    let glyphs = (0..glyf.num_glyphs() as u16).map(|g| glyf.data(g).to_vec()).collect();
+    let variable = font.table(b"gvar").is_some();
+    let mut passes = Passes { glyphs, report: Vec::new() };
+
+    passes.run("encoding", |g, glyphs| {
+        let bytes = Outline::parse(&glyphs[g])?.to_bytes();
+        (bytes.len() < glyphs[g].len()).then_some(bytes)
+    });
+    if !variable {
+        passes.run("points", |g, glyphs| {
+            let mut outline = plain(&glyphs[g])?;
+            (drop_redundant(&mut outline) > 0).then(|| outline.to_bytes())
+        });
+        let mut seen = HashMap::new();
+        passes.run("duplicates", |g, glyphs| {
+            let outline = plain(&glyphs[g])?;
+            let key = (outline.ends.clone(), outline.points.clone(), outline.overlap);
+            let Some(&original) = seen.get(&key) else {
+                seen.insert(key, g as u16);
+                return None;
+            };
+            let composite = glyf::offset_composite(original, 0, 0, outline.bbox);
+            (composite.len() < glyphs[g].len()).then_some(composite)
+        });
+        let mut seen = HashMap::new();
+        passes.run("composites", |g, glyphs| {
+            let outline = plain(&glyphs[g])?;
+            let first = outline.points[0];
+            let moved: Vec<(i32, i32, bool)> =
+                outline.points.iter().map(|p| (p.x - first.x, p.y - first.y, p.on_curve)).collect();
+            let key = (outline.ends.clone(), moved, outline.overlap);
+            let Some(&(original, origin)) = seen.get(&key) else {
+                seen.insert(key, (g as u16, first));
+                return None;
+            };
+            let composite = glyf::offset_composite(original, first.x - origin.x, first.y - origin.y, outline.bbox);
+            (composite.len() < glyphs[g].len()).then_some(composite)
+        });
+    }
+    let mut moved = Vec::new();
+    passes.run("bounds", |g, glyphs| {
+        let (old, new) = (stored_bbox(&glyphs[g])?, bbox(glyphs, g, 0)?);
+        if old == new {
+            return None;
+        }
+        moved.push((g, old[0], new[0]));
+        let mut bytes = glyphs[g].clone();
+        for (i, v) in new.into_iter().enumerate() {
+            bytes[2 + 2 * i..4 + 2 * i].copy_from_slice(&v.to_be_bytes());
+        }
+        Some(bytes)
+    });
+
+    glyf::rebuild(font, &passes.glyphs)?;
+    sync_metrics(font, &passes.glyphs, &moved)?;
+    raise_maxp(font, &passes.glyphs);
+    Ok(passes.report)
+}
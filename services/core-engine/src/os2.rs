@@ -0,0 +1,311 @@
+//! Coverage metadata of generated fonts, refreshed after subsetting and
+//! instancing.
+//!
+//! A subset inherits `OS/2` claims about characters it no longer has, which
+//! font menus, fallback and Office use to decide what a font supports.
+//! [`refresh`] clears the `ulUnicodeRange` bits whose blocks the new `cmap`
+//! maps nothing in, and the `ulCodePageRange` bits whose characteristic
+//! characters are gone (as fontTools decides them, falling back to Latin 1
+//! when none are left so Word still lists the font), and recomputes
+//! `usFirstCharIndex`/`usLastCharIndex`. Bits are only ever cleared: a block
+//! the source did not claim stays unclaimed. `head.modified` becomes the
+//! engine's `SOURCE_DATE_EPOCH` at compile time when that is later, never
+//! the wall clock, so the same request still gives the same bytes (IFT
+//! `base_checksum` and SRI digests depend on it). Table checksums and
+//! `checkSumAdjustment` are recomputed by [`Font::to_bytes`], and instances
+//! get their weight class and style bits from [`crate::instancer`].
+
+use std::ops::RangeInclusive;
+
+use crate::{
+    cmap::CharMap,
+    sfnt::{be_u16, be_u32, Font},
+};
+
+/// Seconds from 1904-01-01 (`LONGDATETIME`) to the Unix epoch.
+const MAC_EPOCH_OFFSET: u64 = 2_082_844_800;
+
+/// `ulUnicodeRange` bits and the blocks each stands for (OpenType `OS/2`).
+const UNICODE_RANGES: &[(u8, u32, u32)] = &[
+    (0, 0x0000, 0x007F),
+    (1, 0x0080, 0x00FF),
+    (2, 0x0100, 0x017F),
+    (3, 0x0180, 0x024F),
+    (4, 0x0250, 0x02AF),
+    (4, 0x1D00, 0x1DBF),
+    (5, 0x02B0, 0x02FF),
+    (5, 0xA700, 0xA71F),
+    (6, 0x0300, 0x036F),
+    (6, 0x1DC0, 0x1DFF),
+    (7, 0x0370, 0x03FF),
+    (8, 0x2C80, 0x2CFF),
+    (9, 0x0400, 0x052F),
+    (9, 0x2DE0, 0x2DFF),
+    (9, 0xA640, 0xA69F),
+    (10, 0x0530, 0x058F),
+    (11, 0x0590, 0x05FF),
+    (12, 0xA500, 0xA63F),
+    (13, 0x0600, 0x06FF),
+    (13, 0x0750, 0x077F),
+    (14, 0x07C0, 0x07FF),
+    (15, 0x0900, 0x097F),
+    (16, 0x0980, 0x09FF),
+    (17, 0x0A00, 0x0A7F),
+    (18, 0x0A80, 0x0AFF),
+    (19, 0x0B00, 0x0B7F),
+    (20, 0x0B80, 0x0BFF),
+    (21, 0x0C00, 0x0C7F),
+    (22, 0x0C80, 0x0CFF),
+    (23, 0x0D00, 0x0D7F),
+    (24, 0x0E00, 0x0E7F),
+    (25, 0x0E80, 0x0EFF),
+    (26, 0x10A0, 0x10FF),
+    (26, 0x2D00, 0x2D2F),
+    (27, 0x1B00, 0x1B7F),
+    (28, 0x1100, 0x11FF),
+    (29, 0x1E00, 0x1EFF),
+    (29, 0x2C60, 0x2C7F),
+    (29, 0xA720, 0xA7FF),
+    (30, 0x1F00, 0x1FFF),
+    (31, 0x2000, 0x206F),
+    (31, 0x2E00, 0x2E7F),
+    (32, 0x2070, 0x209F),
+    (33, 0x20A0, 0x20CF),
+    (34, 0x20D0, 0x20FF),
+    (35, 0x2100, 0x214F),
+    (36, 0x2150, 0x218F),
+    (37, 0x2190, 0x21FF),
+    (37, 0x27F0, 0x27FF),
+    (37, 0x2900, 0x297F),
+    (37, 0x2B00, 0x2BFF),
+    (38, 0x2200, 0x22FF),
+    (38, 0x27C0, 0x27EF),
+    (38, 0x2980, 0x2AFF),
+    (39, 0x2300, 0x23FF),
+    (40, 0x2400, 0x243F),
+    (41, 0x2440, 0x245F),
+    (42, 0x2460, 0x24FF),
+    (43, 0x2500, 0x257F),
+    (44, 0x2580, 0x259F),
+    (45, 0x25A0, 0x25FF),
+    (46, 0x2600, 0x26FF),
+    (47, 0x2700, 0x27BF),
+    (48, 0x3000, 0x303F),
+    (49, 0x3040, 0x309F),
+    (50, 0x30A0, 0x30FF),
+    (50, 0x31F0, 0x31FF),
+    (51, 0x3100, 0x312F),
+    (51, 0x31A0, 0x31BF),
+    (52, 0x3130, 0x318F),
+    (53, 0xA840, 0xA87F),
+    (54, 0x3200, 0x32FF),
+    (55, 0x3300, 0x33FF),
+    (56, 0xAC00, 0xD7AF),
+    (57, 0x10000, 0x10FFFF),
+    (58, 0x10900, 0x1091F),
+    (59, 0x2E80, 0x2FFF),
+    (59, 0x3190, 0x319F),
+    (59, 0x3400, 0x4DBF),
+    (59, 0x4E00, 0x9FFF),
+    (59, 0x20000, 0x2A6DF),
+    (60, 0xE000, 0xF8FF),
+    (61, 0x31C0, 0x31EF),
+    (61, 0xF900, 0xFAFF),
+    (61, 0x2F800, 0x2FA1F),
+    (62, 0xFB00, 0xFB4F),
+    (63, 0xFB50, 0xFDFF),
+    (64, 0xFE20, 0xFE2F),
+    (65, 0xFE10, 0xFE1F),
+    (65, 0xFE30, 0xFE4F),
+    (66, 0xFE50, 0xFE6F),
+    (67, 0xFE70, 0xFEFF),
+    (68, 0xFF00, 0xFFEF),
+    (69, 0xFFF0, 0xFFFF),
+    (70, 0x0F00, 0x0FFF),
+    (71, 0x0700, 0x074F),
+    (72, 0x0780, 0x07BF),
+    (73, 0x0D80, 0x0DFF),
+    (74, 0x1000, 0x109F),
+    (75, 0x1200, 0x139F),
+    (75, 0x2D80, 0x2DDF),
+    (76, 0x13A0, 0x13FF),
+    (77, 0x1400, 0x167F),
+    (78, 0x1680, 0x169F),
+    (79, 0x16A0, 0x16FF),
+    (80, 0x1780, 0x17FF),
+    (80, 0x19E0, 0x19FF),
+    (81, 0x1800, 0x18AF),
+    (82, 0x2800, 0x28FF),
+    (83, 0xA000, 0xA4CF),
+    (84, 0x1700, 0x177F),
+    (85, 0x10300, 0x1032F),
+    (86, 0x10330, 0x1034F),
+    (87, 0x10400, 0x1044F),
+    (88, 0x1D000, 0x1D24F),
+    (89, 0x1D400, 0x1D7FF),
+    (90, 0xF0000, 0x10FFFD),
+    (91, 0xFE00, 0xFE0F),
+    (91, 0xE0100, 0xE01EF),
+    (92, 0xE0000, 0xE007F),
+    (93, 0x1900, 0x194F),
+    (94, 0x1950, 0x197F),
+    (95, 0x1980, 0x19DF),
+    (96, 0x1A00, 0x1A1F),
+    (97, 0x2C00, 0x2C5F),
+    (98, 0x2D30, 0x2D7F),
+    (99, 0x4DC0, 0x4DFF),
+    (100, 0xA800, 0xA82F),
+    (101, 0x10000, 0x1013F),
+    (102, 0x10140, 0x1018F),
+    (103, 0x10380, 0x1039F),
+    (104, 0x103A0, 0x103DF),
+    (105, 0x10450, 0x1047F),
+    (106, 0x10480, 0x104AF),
+    (107, 0x10800, 0x1083F),
+    (108, 0x10A00, 0x10A5F),
+    (109, 0x1D300, 0x1D35F),
+    (110, 0x12000, 0x1247F),
+    (111, 0x1D360, 0x1D37F),
+    (112, 0x1B80, 0x1BBF),
+    (113, 0x1C00, 0x1C4F),
+    (114, 0x1C50, 0x1C7F),
+    (115, 0xA880, 0xA8DF),
+    (116, 0xA900, 0xA92F),
+    (117, 0xA930, 0xA95F),
+    (118, 0xAA00, 0xAA5F),
+    (119, 0x10190, 0x101CF),
+    (120, 0x101D0, 0x101FF),
+    (121, 0x10280, 0x102DF),
+    (121, 0x10920, 0x1093F),
+    (122, 0x1F000, 0x1F09F),
+];
+
+/// `ulCodePageRange` bits, the characters that must all be mapped for each
+/// and whether printable ASCII must be too (after fontTools'
+/// `calcCodePageRanges`; the DOS code pages also need box drawing).
+const CODE_PAGES: &[(u8, &[char], bool)] = &[
+    (0, &['Þ'], true),
+    (1, &['Ľ'], true),
+    (2, &['Б'], false),
+    (3, &['Ά'], false),
+    (4, &['İ'], true),
+    (5, &['א'], false),
+    (6, &['ر'], false),
+    (7, &['ŗ'], true),
+    (8, &['₫'], true),
+    (16, &['ๅ'], false),
+    (17, &['エ'], false),
+    (18, &['ㄅ'], false),
+    (19, &['ㄱ'], false),
+    (20, &['央'], false),
+    (21, &['곴'], false),
+    (29, &['‰', '∑'], true),
+    (30, &['♥'], true),
+    (48, &['Ά', '┤'], false),
+    (49, &['Б', '┤', '√'], true),
+    (50, &['ø', '┤', '√'], true),
+    (51, &['ر', '√'], false),
+    (52, &['È', '┤', '√'], true),
+    (53, &['א', '┤', '√'], false),
+    (54, &['þ', '┤'], true),
+    (55, &['Õ', '┤', '√'], true),
+    (56, &['İ', '┤'], true),
+    (57, &['Б', '┤'], true),
+    (58, &['Ľ', '┤'], true),
+    (59, &['ŗ', '┤'], true),
+    (60, &['Ά', '┤'], true),
+    (61, &['ر', '┤'], false),
+    (62, &['╚'], true),
+    (63, &['╚'], true),
+];
+
+/// Whether `cp` falls in the sorted `ranges`.
+fn maps(ranges: &[RangeInclusive<u32>], cp: u32) -> bool {
+    let at = ranges.partition_point(|r| *r.end() < cp);
+    ranges.get(at).is_some_and(|r| r.contains(&cp))
+}
+
+/// Bits of a 128-bit (`ulUnicodeRange`) or 64-bit (`ulCodePageRange`) field
+/// with `known` the bits a rule exists for: unknown bits are kept as they are.
+fn prune(old: u128, computed: u128, known: u128) -> u128 {
+    old & (computed | !known)
+}
+
+fn unicode_ranges(ranges: &[RangeInclusive<u32>]) -> (u128, u128) {
+    let (mut computed, mut known) = (0, 0);
+    for &(bit, start, end) in UNICODE_RANGES {
+        known |= 1 << bit;
+        let at = ranges.partition_point(|r| *r.end() < start);
+        if ranges.get(at).is_some_and(|r| *r.start() <= end) {
+            computed |= 1 << bit;
+        }
+    }
+    (computed, known)
+}
+
+fn code_pages(ranges: &[RangeInclusive<u32>]) -> (u128, u128) {
+    let ascii = ranges.iter().any(|r| r.contains(&0x20) && r.contains(&0x7E));
+    let (mut computed, mut known) = (0, 0);
+    for &(bit, characters, needs_ascii) in CODE_PAGES {
+        known |= 1 << bit;
+        if (ascii || !needs_ascii) && characters.iter().all(|&c| maps(ranges, c as u32)) {
+            computed |= 1 << bit;
+        }
+    }
+    (computed, known)
+}
+
+/// `count` big-endian `u32`s from `at` as one field, the first holding bits 0-31.
+fn read_bits(table: &[u8], at: usize, count: usize) -> Option<u128> {
+    (0..count).try_fold(0, |bits, i| Some(bits | u128::from(be_u32(table, at + 4 * i)?) << (32 * i)))
+}
+
+fn write_bits(table: &mut [u8], at: usize, count: usize, bits: u128) {
+    for i in 0..count {
+        table[at + 4 * i..at + 4 * i + 4].copy_from_slice(&((bits >> (32 * i)) as u32).to_be_bytes());
+    }
+}
+
+/// `head.modified`: the engine's build time, when set and later.
+fn modified(font: &mut Font) {
+    let Some(epoch) = option_env!("SOURCE_DATE_EPOCH").and_then(|s| s.trim().parse::<u64>().ok()) else { return };
+    let Some(mut head) = font.table(b"head").filter(|h| h.len() >= 36).map(<[u8]>::to_vec) else { return };
+    let built = epoch + MAC_EPOCH_OFFSET;
+    let before = u64::from_be_bytes(head[28..36].try_into().expect("8 bytes"));
+    if built > before {
+        head[28..36].copy_from_slice(&built.to_be_bytes());
+        font.set_table(*b"head", head);
+    }
+}
+
+/// Brings `OS/2` coverage bits and `head.modified` of `font` in line with
+/// its `cmap`.
+pub fn refresh(font: &mut Font) -> Result<(), String> {
+    modified(font);
+    let (Some(cmap), Some(os2)) = (font.table(b"cmap"), font.table(b"OS/2")) else { return Ok(()) };
+    let ranges = CharMap::parse(cmap)?.ranges();
+    let mut os2 = os2.to_vec();
+    if os2.len() < 68 {
+        return Err("OS/2 table truncated".to_string());
+    }
+
+    let (computed, known) = unicode_ranges(&ranges);
+    let old = read_bits(&os2, 42, 4).expect("length checked");
+    write_bits(&mut os2, 42, 4, prune(old, computed, known));
+    let first = ranges.first().map_or(0, |r| *r.start()).min(0xFFFF) as u16;
+    let last = ranges.last().map_or(0, |r| *r.end()).min(0xFFFF) as u16;
+    os2[64..66].copy_from_slice(&first.to_be_bytes());
+    os2[66..68].copy_from_slice(&last.to_be_bytes());
+
+    if be_u16(&os2, 0).is_some_and(|version| version >= 1) && os2.len() >= 86 {
+        let (computed, known) = code_pages(&ranges);
+        let old = read_bits(&os2, 78, 2).expect("length checked");
+        let pages = match prune(old, computed, known) {
+            0 => 1,
+            pages => pages,
+        };
+        write_bits(&mut os2, 78, 2, pages);
+    }
+    font.set_table(*b"OS/2", os2);
+    Ok(())
+}
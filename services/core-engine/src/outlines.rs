@@ -0,0 +1,163 @@
+//! Glyph outlines as SVG, for icon fonts and design tooling.
+//!
+//! `GET /api/v1/font/:id/glyphs/U+E001.svg` returns the glyph a code point
+//! maps to (the `U+` is optional; the code point is hex). The SVG is in font
+//! units with y pointing down: the viewBox spans the advance width and
+//! ascender to descender, so exported icons line up with each other.
+//! `GET /api/v1/font/:id/glyphs.zip` bundles every mapped code point, or
+//! those of `characters` and `preset` (named sets or `U+` ranges), as
+//! `U+XXXX.svg` files, with the glyph name appended when the font has one
+//! (`U+E001-home.svg`).
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+};
+use serde::Deserialize;
+use std::{collections::BTreeSet, sync::Arc};
+use tracing::info;
+use ttf_parser::{Face, GlyphId};
+
+use crate::{cancel, cmap::CharMap, duplicates, preview, unicode, zip, AppState};
+
+/// Most glyphs one ZIP export holds.
+const MAX_GLYPHS: usize = 10_000;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    characters: String,
+    preset: Option<String>,
+}
+
+/// The glyph for `cp` as a standalone SVG, or `None` when it is unmapped.
+fn glyph_svg(face: &Face, cp: u32) -> Option<String> {
+    let glyph = face.glyph_index(char::from_u32(cp)?)?;
+    let advance = face.glyph_hor_advance(glyph).unwrap_or(0);
+    let (ascender, descender) = (face.ascender(), face.descender());
+    let mut d = String::new();
+    face.outline_glyph(glyph, &mut preview::PathWriter::new(&mut d, 0.0, 0.0, 1.0));
+    Some(format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 {} {advance} {}\"><path d=\"{}\"/></svg>\n",
+        -ascender,
+        ascender as i32 - descender as i32,
+        d.trim_start()
+    ))
+}
+
+/// `U+XXXX`, plus `-name` when the glyph has a name that is safe in a file
+/// name.
+fn file_name(face: &Face, cp: u32, glyph: GlyphId) -> String {
+    let name = face
+        .glyph_name(glyph)
+        .filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_alphanumeric() || b"._-".contains(&b)));
+    match name {
+        Some(name) => format!("U+{cp:04X}-{name}.svg"),
+        None => format!("U+{cp:04X}.svg"),
+    }
+}
+
+/// `U+0041`, `u+0041` or `0041`.
+fn parse_code_point(text: &str) -> Option<u32> {
+    let hex = text.strip_prefix("U+").or_else(|| text.strip_prefix("u+")).unwrap_or(text);
+    u32::from_str_radix(hex, 16).ok().filter(|cp| char::from_u32(*cp).is_some())
+}
+
+async fn catalog_font(state: &Arc<AppState>, headers: &HeaderMap, id: &str) -> Result<Vec<u8>, (StatusCode, String)> {
+    state.flags.ensure("demos", headers)?;
+    state.sandbox.ensure_font(headers, &state.catalog.read().unwrap(), id)?;
+    if !state.catalog.read().unwrap().iter().any(|e| e.id == id) {
+        return Err((StatusCode::NOT_FOUND, format!("no catalog entry '{id}'")));
+    }
+    let (job_state, job_id) = (Arc::clone(state), id.to_string());
+    cancel::run(&state.jobs, "outlines", move |token| {
+        token.check()?;
+        Ok(duplicates::catalog_binary(&job_state, &job_id))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))
+}
+
+pub async fn glyph(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path((id, file)): Path<(String, String)>,
+) -> Result<Response, (StatusCode, String)> {
+    let cp = file
+        .strip_suffix(".svg")
+        .and_then(parse_code_point)
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("'{file}' should look like U+0041.svg")))?;
+    let data = catalog_font(&state, &headers, &id).await?;
+    let face = Face::parse(&data, 0).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, format!("unreadable font: {e}")))?;
+    let svg = glyph_svg(&face, cp)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("'{id}' has no glyph for U+{cp:04X}")))?;
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "image/svg+xml")
+        .header(header::CONTENT_LENGTH, svg.len())
+        .body(Body::from(svg))
+        .unwrap())
+}
+
+pub async fn export(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let mut wanted: BTreeSet<u32> = query.characters.chars().map(|c| c as u32).collect();
+    if let Some(name) = &query.preset {
+        let ranges = unicode::preset(name).ok_or_else(|| {
+            (StatusCode::BAD_REQUEST, format!("unknown preset '{name}'; valid: {}", unicode::preset_names()))
+        })?;
+        wanted.extend(ranges.into_iter().flatten());
+    }
+    let data = catalog_font(&state, &headers, &id).await?;
+
+    let zipped = cancel::run(&state.jobs, "outlines", move |token| {
+        let face = match Face::parse(&data, 0) {
+            Ok(face) => face,
+            Err(e) => return Ok(Err(format!("unreadable font: {e}"))),
+        };
+        let mapped = match face.raw_face().table(ttf_parser::Tag::from_bytes(b"cmap")).map(CharMap::parse) {
+            Some(Ok(map)) => map.ranges(),
+            Some(Err(e)) => return Ok(Err(e)),
+            None => return Ok(Err("font has no cmap table".to_string())),
+        };
+        let code_points: Vec<u32> = mapped
+            .into_iter()
+            .flatten()
+            .filter(|cp| wanted.is_empty() || wanted.contains(cp))
+            .collect();
+        if code_points.is_empty() {
+            return Ok(Err("none of the requested characters are in the font".to_string()));
+        }
+        if code_points.len() > MAX_GLYPHS {
+            return Ok(Err(format!(
+                "{} glyphs is over the {MAX_GLYPHS} per export; narrow it with characters or preset",
+                code_points.len()
+            )));
+        }
+        let mut files = Vec::with_capacity(code_points.len());
+        for cp in code_points {
+            token.check()?;
+            let Some(glyph) = char::from_u32(cp).and_then(|c| face.glyph_index(c)) else { continue };
+            if let Some(svg) = glyph_svg(&face, cp) {
+                files.push((file_name(&face, cp, glyph), svg.into_bytes()));
+            }
+        }
+        Ok(zip::write(&files).map(|zipped| (files.len(), zipped)))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let (glyphs, zipped) = zipped;
+
+    info!(id = %id, glyphs, bytes = zipped.len(), "glyph outlines exported");
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{id}-glyphs.zip\""))
+        .header(header::CONTENT_LENGTH, zipped.len())
+        .body(Body::from(zipped))
+        .unwrap())
+}
@@ -0,0 +1,337 @@
+//! Heading/body font pairing suggestions.
+//!
+//! `GET /api/v1/font/:id/pairings` profiles each catalog family (its Regular
+//! face, or the first) from the binary in `CATALOG_FONT_DIR`: a category
+//! (`serif`, `sans-serif`, `monospace`, `display` or `handwriting`, from the
+//! OS/2 PANOSE and family class, the fixed-pitch flag and finally the name),
+//! the x-height as a share of the em, and stroke contrast, how much thinner
+//! the horizontal strokes of `o` are than its vertical ones (0 monolinear, 1
+//! hairline). Each other family is then scored 0-100 as the body for this
+//! one as heading and the other way round: distinct categories pair,
+//! lookalikes do not, body faces want a generous x-height and low contrast,
+//! and close x-heights keep mixed lines even. Every point comes with a
+//! reason. `?role=heading` or `body` fixes this font's role; `?limit=`
+//! caps the list (default 10, at most 50).
+//!
+//! Profiles are cached per entry version.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::FontCatalogEntry;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tracing::info;
+use ttf_parser::{Face, OutlineBuilder, Rect};
+
+use crate::{cancel, duplicates, AppState};
+
+const MAX_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Category {
+    Serif,
+    SansSerif,
+    Monospace,
+    Display,
+    Handwriting,
+}
+
+impl Category {
+    fn name(self) -> &'static str {
+        match self {
+            Category::Serif => "serif",
+            Category::SansSerif => "sans-serif",
+            Category::Monospace => "monospace",
+            Category::Display => "display",
+            Category::Handwriting => "handwriting",
+        }
+    }
+
+    fn text(self) -> bool {
+        matches!(self, Category::Serif | Category::SansSerif)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Profile {
+    category: Category,
+    /// x-height over units per em.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    x_height: Option<f64>,
+    /// 1 - thinnest / thickest stroke of `o`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contrast: Option<f64>,
+}
+
+#[derive(Default)]
+pub struct ProfileCache {
+    profiles: Mutex<HashMap<(String, u32), Profile>>,
+}
+
+/// Bounding boxes of each contour.
+#[derive(Default)]
+struct Contours(Vec<Rect>);
+
+impl Contours {
+    fn point(&mut self, x: f32, y: f32) {
+        let (x, y) = (x as i16, y as i16);
+        if let Some(r) = self.0.last_mut() {
+            *r = Rect { x_min: r.x_min.min(x), y_min: r.y_min.min(y), x_max: r.x_max.max(x), y_max: r.y_max.max(y) };
+        }
+    }
+}
+
+impl OutlineBuilder for Contours {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let (x, y) = (x as i16, y as i16);
+        self.0.push(Rect { x_min: x, y_min: y, x_max: x, y_max: y });
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.point(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.point(x1, y1);
+        self.point(x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.point(x1, y1);
+        self.point(x2, y2);
+        self.point(x, y);
+    }
+
+    fn close(&mut self) {}
+}
+
+/// Stroke contrast of `o`, from the gaps between its outer and inner
+/// contours: the sides against the top and bottom.
+fn contrast(face: &Face) -> Option<f64> {
+    let mut contours = Contours::default();
+    face.outline_glyph(face.glyph_index('o')?, &mut contours)?;
+    let mut boxes = contours.0;
+    if boxes.len() < 2 {
+        return None;
+    }
+    boxes.sort_by_key(|r| std::cmp::Reverse(r.width() as i32 * r.height() as i32));
+    let (outer, inner) = (boxes[0], boxes[1]);
+    let sides = (outer.width() - inner.width()) as f64 / 2.0;
+    let ends = (outer.height() - inner.height()) as f64 / 2.0;
+    let (thin, thick) = (sides.min(ends), sides.max(ends));
+    (thin > 0.0).then(|| ((1.0 - thin / thick) * 100.0).round() / 100.0)
+}
+
+fn x_height(face: &Face) -> Option<f64> {
+    let height = face.x_height().filter(|&h| h > 0).map(f64::from).or_else(|| {
+        let x = face.glyph_bounding_box(face.glyph_index('x')?)?;
+        Some(x.y_max as f64)
+    })?;
+    Some((height / face.units_per_em() as f64 * 1000.0).round() / 1000.0)
+}
+
+fn category(face: &Face, family: &str) -> Category {
+    let os2 = face.raw_face().table(ttf_parser::Tag::from_bytes(b"OS/2"));
+    let class = os2.and_then(|t| t.get(30)).copied().unwrap_or(0);
+    let (kind, serif_style) = os2.and_then(|t| Some((*t.get(32)?, *t.get(33)?))).unwrap_or((0, 0));
+    let name = family.to_lowercase();
+    if face.is_monospaced() {
+        return Category::Monospace;
+    }
+    match (kind, serif_style, class) {
+        (3, ..) | (_, _, 10) => return Category::Handwriting,
+        (4 | 5, ..) | (_, _, 9 | 12) => return Category::Display,
+        (2, 11..=15, _) | (_, _, 8) => return Category::SansSerif,
+        (2, 2..=10, _) | (_, _, 1..=5 | 7) => return Category::Serif,
+        _ => {}
+    }
+    if name.contains("mono") || name.contains("code") {
+        Category::Monospace
+    } else if name.contains("serif") && !name.contains("sans") || name.contains("slab") {
+        Category::Serif
+    } else if ["script", "hand", "brush"].iter().any(|w| name.contains(w)) {
+        Category::Handwriting
+    } else {
+        Category::SansSerif
+    }
+}
+
+fn profile(data: &[u8], family: &str) -> Result<Profile, String> {
+    let face = Face::parse(data, 0).map_err(|e| format!("font cannot be parsed: {e}"))?;
+    Ok(Profile { category: category(&face, family), x_height: x_height(&face), contrast: contrast(&face) })
+}
+
+#[derive(Debug, Serialize)]
+pub struct Pairing {
+    heading: String,
+    body: String,
+    /// The other family's representative entry.
+    font_id: String,
+    family: String,
+    profile: Profile,
+    score: u8,
+    rationale: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PairingsResponse {
+    font_id: String,
+    family: String,
+    profile: Profile,
+    pairings: Vec<Pairing>,
+    /// Families without a binary to profile.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unprofiled: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PairingsQuery {
+    /// This font's role: `heading`, `body` or `any` (default).
+    #[serde(default)]
+    role: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_limit() -> usize {
+    10
+}
+
+/// Scores `heading` over `body`, with the reasons.
+fn score(heading: &Profile, body: &Profile) -> (u8, Vec<String>) {
+    let mut score = 50i32;
+    let mut why = Vec::new();
+    let mut add = |points: i32, reason: String| {
+        score += points;
+        why.push(format!("{points:+} {reason}"));
+    };
+    let (h, b) = (heading.category, body.category);
+    if h != b && (b.text() || h.text()) {
+        add(20, format!("{} heading over {} body contrasts in structure", h.name(), b.name()));
+    }
+    if !b.text() {
+        add(-25, format!("{} faces tire the eye in running text", b.name()));
+    }
+    let x = heading.x_height.zip(body.x_height);
+    let c = heading.contrast.zip(body.contrast);
+    let lookalike = x.is_some_and(|(a, b)| (a - b).abs() < 0.03) && c.is_some_and(|(a, b)| (a - b).abs() < 0.1);
+    if h == b && lookalike {
+        add(-20, "too alike: same category, x-height and contrast".to_string());
+    } else if h == b {
+        add(-5, format!("both {}; the pair relies on weight and size", h.name()));
+    }
+    if let Some((hx, bx)) = x {
+        let gap = (hx - bx).abs();
+        if gap <= 0.05 {
+            add(10, format!("x-heights within {:.0}% of the em keep mixed lines even", gap * 100.0));
+        }
+    }
+    match body.x_height {
+        Some(bx) if bx >= 0.5 => add(10, format!("body x-height {bx:.2} em reads well at small sizes")),
+        Some(bx) if bx < 0.44 => add(-5, format!("body x-height {bx:.2} em is small for text")),
+        _ => {}
+    }
+    match body.contrast {
+        Some(bc) if bc > 0.6 => add(-10, "high-contrast body strokes break up at small sizes".to_string()),
+        Some(bc) if bc <= 0.35 => add(5, "even body strokes hold up on screens".to_string()),
+        _ => {}
+    }
+    if let Some((hc, bc)) = c {
+        if hc > bc + 0.15 {
+            add(5, "the heading's stroke contrast gives it character".to_string());
+        }
+    }
+    (score.clamp(0, 100) as u8, why)
+}
+
+/// One entry per family: its Regular face, or the first.
+fn representatives(catalog: &[FontCatalogEntry]) -> Vec<&FontCatalogEntry> {
+    let mut families: Vec<&FontCatalogEntry> = Vec::new();
+    for entry in catalog {
+        match families.iter_mut().find(|e| e.family == entry.family) {
+            Some(kept) if entry.variant.eq_ignore_ascii_case("regular") => *kept = entry,
+            Some(_) => {}
+            None => families.push(entry),
+        }
+    }
+    families
+}
+
+pub async fn pairings(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(query): Query<PairingsQuery>,
+) -> Result<Json<PairingsResponse>, (StatusCode, String)> {
+    let (as_heading, as_body) = match query.role.as_deref() {
+        None | Some("any") => (true, true),
+        Some("heading") => (true, false),
+        Some("body") => (false, true),
+        Some(other) => return Err((StatusCode::BAD_REQUEST, format!("role '{other}' must be heading, body or any"))),
+    };
+    let catalog = state.sandbox.visible(&headers, state.catalog.read().unwrap().clone());
+    let font = catalog.iter().find(|e| e.id == id).cloned();
+    let font = font.ok_or_else(|| (StatusCode::NOT_FOUND, format!("no catalog entry '{id}'")))?;
+    let others: Vec<FontCatalogEntry> =
+        representatives(&catalog).into_iter().filter(|e| e.family != font.family).cloned().collect();
+
+    let job_state = Arc::clone(&state);
+    let wanted: Vec<FontCatalogEntry> = std::iter::once(font.clone()).chain(others).collect();
+    let profiles = cancel::run(&state.jobs, "pairings", move |token| {
+        let mut profiles = Vec::with_capacity(wanted.len());
+        for entry in wanted {
+            token.check()?;
+            let key = (entry.id.clone(), entry.version);
+            let cached = job_state.pairings.profiles.lock().unwrap().get(&key).cloned();
+            let profile = match cached {
+                Some(p) => Ok(p),
+                None => duplicates::catalog_binary(&job_state, &entry.id).and_then(|d| profile(&d, &entry.family)),
+            };
+            if let Ok(p) = &profile {
+                job_state.pairings.profiles.lock().unwrap().insert(key, p.clone());
+            }
+            profiles.push((entry, profile));
+        }
+        Ok(profiles)
+    })
+    .await?;
+
+    let mut profiles = profiles.into_iter();
+    let (font, own) = profiles.next().expect("the font itself is profiled first");
+    let own = own.map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, format!("'{}' cannot be profiled: {e}", font.id)))?;
+    let mut pairings = Vec::new();
+    let mut unprofiled = Vec::new();
+    for (other, profile) in profiles {
+        let Ok(profile) = profile else {
+            unprofiled.push(other.family);
+            continue;
+        };
+        let pair = |heading: &str, body: &str, (score, rationale): (u8, Vec<String>)| Pairing {
+            heading: heading.to_string(),
+            body: body.to_string(),
+            font_id: other.id.clone(),
+            family: other.family.clone(),
+            profile: profile.clone(),
+            score,
+            rationale,
+        };
+        if as_heading {
+            pairings.push(pair(&font.family, &other.family, score(&own, &profile)));
+        }
+        if as_body {
+            pairings.push(pair(&other.family, &font.family, score(&profile, &own)));
+        }
+    }
+    pairings.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.family.cmp(&b.family)));
+    pairings.truncate(query.limit.clamp(1, MAX_LIMIT));
+
+    info!(id = %font.id, pairings = pairings.len(), unprofiled = unprofiled.len(), "font pairings suggested");
+    Ok(Json(PairingsResponse { font_id: font.id, family: font.family, profile: own, pairings, unprofiled }))
+}
@@ -0,0 +1,122 @@
+//! Subsetting profile for PDF embedding.
+//!
+//! PDF embeds raw sfnt data (`FontFile2` for TrueType outlines, `FontFile3`
+//! for CFF), so the subset keeps its outline format, retains the tables PDF
+//! viewers rely on, and carries the six-letter subset tag that ISO 32000
+//! requires in front of the PostScript name (`ABCDEF+Inter-Regular`). The
+//! tag is hashed from the glyphs the subset keeps and written into the
+//! font's own `name` table, so the name a PDF writer reads matches the one
+//! reported.
+
+use font_api::PdfSubset;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+
+use crate::{name::NameTable, sfnt::Font};
+
+/// Tables retained for TrueType-outline subsets (hinting programs included;
+/// printers still use them).
+const TRUETYPE_TABLES: &[&str] = &[
+    "cmap", "cvt ", "fpgm", "glyf", "head", "hhea", "hmtx", "loca", "maxp", "name", "OS/2", "post", "prep",
+];
+
+/// Tables retained for CFF-outline subsets; CFF is kept rather than
+/// converted so the PDF can embed it as `FontFile3`.
+const CFF_TABLES: &[&str] = &["CFF ", "cmap", "head", "hhea", "hmtx", "maxp", "name", "OS/2", "post"];
+
+/// Deterministic tag for a font and the glyphs its subset keeps: the same
+/// glyph set always yields the same tag, different ones different tags.
+pub fn subset_tag(postscript_name: &str, glyphs: &BTreeSet<u16>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(postscript_name.as_bytes());
+    for glyph in glyphs {
+        hasher.update(glyph.to_be_bytes());
+    }
+    hasher
+        .finalize()
+        .iter()
+        .take(6)
+        .map(|b| char::from(b'A' + b % 26))
+        .collect()
+}
+
+pub fn retained_tables(format: &str) -> Vec<String> {
+    if format == "otf" { CFF_TABLES } else { TRUETYPE_TABLES }.iter().map(|t| t.to_string()).collect()
+}
+
+/// Tags `font`, a subset keeping `glyphs`, with the PostScript name from its
+/// own `name` table (`fallback` when it has none): `TAG+PSName` goes into
+/// name IDs 6 and 4, and 1 for CFF fonts, whose embedded name PDF readers
+/// take from the family.
+pub fn tag(font: &mut Font, glyphs: &BTreeSet<u16>, fallback: &str, retained_tables: Vec<String>) -> PdfSubset {
+    let mut names = font.table(b"name").and_then(|t| NameTable::parse(t).ok());
+    let postscript_name = names
+        .as_ref()
+        .and_then(|n| n.get(6))
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| fallback.replace(' ', ""));
+    let subset_tag = subset_tag(&postscript_name, glyphs);
+    let tagged = format!("{subset_tag}+{postscript_name}");
+    let names = names.get_or_insert_with(|| NameTable::parse(&[0, 0, 0, 0, 0, 6]).expect("an empty name table"));
+    let ids: &[u16] = if font.table(b"CFF ").is_some() { &[1, 4, 6] } else { &[4, 6] };
+    for &id in ids {
+        names.replace(id, &tagged);
+    }
+    font.set_table(*b"name", names.to_bytes());
+    PdfSubset { subset_tag, postscript_name: tagged, retained_tables }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sfnt::Table;
+
+    fn font(outlines: &[u8; 4]) -> Font {
+        let mut names = NameTable::parse(&[0, 0, 0, 0, 0, 6]).unwrap();
+        names.set(1, 0x0409, "Inter");
+        names.set(4, 0x0409, "Inter Regular");
+        names.set(6, 0x0409, "Inter-Regular");
+        let tables = [(*b"name", names.to_bytes()), (*outlines, vec![0; 4])];
+        Font::new(0x0001_0000, tables.into_iter().map(|(tag, data)| Table { tag, data }).collect())
+    }
+
+    fn name(font: &Font, id: u16) -> Option<String> {
+        NameTable::parse(font.table(b"name").unwrap()).unwrap().get(id)
+    }
+
+    #[test]
+    fn tag_follows_the_glyph_set() {
+        let glyphs = BTreeSet::from([0, 3, 36]);
+        let tag = subset_tag("Inter-Regular", &glyphs);
+        assert_eq!(tag.len(), 6);
+        assert!(tag.bytes().all(|b| b.is_ascii_uppercase()));
+        assert_eq!(tag, subset_tag("Inter-Regular", &BTreeSet::from([36, 3, 0])));
+        assert_ne!(tag, subset_tag("Inter-Regular", &BTreeSet::from([0, 3, 37])));
+        assert_ne!(tag, subset_tag("Inter-Bold", &glyphs));
+    }
+
+    #[test]
+    fn tags_the_names_in_the_font() {
+        let glyphs = BTreeSet::from([0, 3, 36]);
+        let mut truetype = font(b"glyf");
+        let pdf = tag(&mut truetype, &glyphs, "Catalog Name", retained_tables("ttf"));
+        let tagged = format!("{}+Inter-Regular", subset_tag("Inter-Regular", &glyphs));
+        assert_eq!(pdf.postscript_name, tagged);
+        assert_eq!(name(&truetype, 6).as_deref(), Some(tagged.as_str()));
+        assert_eq!(name(&truetype, 4).as_deref(), Some(tagged.as_str()));
+        assert_eq!(name(&truetype, 1).as_deref(), Some("Inter"));
+
+        let mut cff = font(b"CFF ");
+        tag(&mut cff, &glyphs, "Catalog Name", retained_tables("otf"));
+        assert_eq!(name(&cff, 1).as_deref(), Some(tagged.as_str()));
+    }
+
+    #[test]
+    fn falls_back_without_a_postscript_name() {
+        let mut bare = Font::new(0x0001_0000, vec![Table { tag: *b"glyf", data: vec![0; 4] }]);
+        let pdf = tag(&mut bare, &BTreeSet::from([0]), "Noto Sans", retained_tables("ttf"));
+        assert!(pdf.postscript_name.ends_with("+NotoSans"));
+        assert_eq!(name(&bare, 6), Some(pdf.postscript_name));
+    }
+}
@@ -0,0 +1,220 @@
+//! Live text previews as SVG.
+//!
+//! `GET /api/v1/font/:id/preview.svg?text=Hamburgefonstiv&size=48` shapes
+//! `text` with the catalog font (kerning, ligatures and marks included) and
+//! draws the glyph outlines as one SVG path, so the catalog UI and docs can
+//! show a specimen without the browser loading the font. Without `text` it
+//! draws the font's primary sample (see `samples`), or the sample for
+//! `script` (`japanese`, `arabic`, ...). `size` is the font size in pixels.
+//! Like slim fonts, previews are public and cacheable with a content-hash
+//! `ETag`; private fonts are not previewed.
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+};
+use rustybuzz::{ttf_parser::GlyphId, UnicodeBuffer};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fmt::Write, sync::Arc};
+use tracing::info;
+
+use crate::{
+    artifacts, cache_control::Policy, cancel::CancelToken, duplicates, isolation, samples, signing, AppState,
+};
+
+const MAX_CHARACTERS: usize = 256;
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewQuery {
+    text: Option<String>,
+    /// A sample script to draw instead of `text`.
+    script: Option<String>,
+    #[serde(default = "default_size")]
+    size: u16,
+}
+
+/// For fonts covering none of the samples.
+const FALLBACK_TEXT: &str = "Hamburgefonstiv";
+
+/// Checks `?script=`.
+pub fn sample_script(script: Option<&str>) -> Result<(), (StatusCode, String)> {
+    match script {
+        Some(s) if !samples::scripts().any(|known| known == s) => {
+            let known: Vec<_> = samples::scripts().collect();
+            Err((StatusCode::BAD_REQUEST, format!("unknown script '{s}' (known: {})", known.join(", "))))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The text to draw in a font: `text` if given, else its sample.
+pub fn text_for(data: &[u8], text: Option<String>, script: Option<&str>, fallback: &str) -> Result<String, String> {
+    if let Some(text) = text {
+        return Ok(text);
+    }
+    match (samples::for_font(data, script), script) {
+        (Some(sample), _) => Ok(sample.to_string()),
+        (None, Some(script)) => Err(format!("the font does not cover the {script} sample")),
+        (None, None) => Ok(fallback.to_string()),
+    }
+}
+
+fn default_size() -> u16 {
+    48
+}
+
+/// Glyph outlines appended to an SVG path, scaled and flipped to y down
+/// with the glyph origin at (`x`, `y`).
+pub struct PathWriter<'a> {
+    d: &'a mut String,
+    x: f32,
+    y: f32,
+    scale: f32,
+}
+
+impl<'a> PathWriter<'a> {
+    pub fn new(d: &'a mut String, x: f32, y: f32, scale: f32) -> Self {
+        Self { d, x, y, scale }
+    }
+
+    fn point(&mut self, x: f32, y: f32) {
+        let _ = write!(self.d, " {:.2} {:.2}", self.x + x * self.scale, self.y - y * self.scale);
+    }
+}
+
+impl rustybuzz::ttf_parser::OutlineBuilder for PathWriter<'_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.d.push('M');
+        self.point(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.d.push('L');
+        self.point(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.d.push('Q');
+        self.point(x1, y1);
+        self.point(x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.d.push('C');
+        self.point(x1, y1);
+        self.point(x2, y2);
+        self.point(x, y);
+    }
+
+    fn close(&mut self) {
+        self.d.push('Z');
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// `text` shaped and drawn in `data` at `px` pixels per em.
+fn render(data: &[u8], text: &str, px: f32) -> Result<String, String> {
+    let face = rustybuzz::Face::from_slice(data, 0).ok_or("font cannot be shaped")?;
+    let scale = px / face.units_per_em().max(1) as f32;
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.guess_segment_properties();
+    let glyphs = rustybuzz::shape(&face, &[], buffer);
+
+    let pad = (px / 8.0).ceil();
+    let baseline = pad + face.ascender() as f32 * scale;
+    let height = baseline + -face.descender() as f32 * scale + pad;
+    let mut d = String::new();
+    let mut pen = 0;
+    for (info, position) in glyphs.glyph_infos().iter().zip(glyphs.glyph_positions()) {
+        let x = pad + (pen + position.x_offset) as f32 * scale;
+        let mut writer = PathWriter::new(&mut d, x, baseline - position.y_offset as f32 * scale, scale);
+        face.outline_glyph(GlyphId(info.glyph_id as u16), &mut writer);
+        pen += position.x_advance;
+    }
+    let width = (pen.max(0) as f32 * scale + 2.0 * pad).ceil();
+    let height = height.ceil().max(1.0);
+    let label = escape(text);
+    Ok(format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"0 0 {width} {height}\" role=\"img\" aria-label=\"{label}\">\
+         <title>{label}</title><path d=\"{}\"/></svg>\n",
+        d.trim_start()
+    ))
+}
+
+/// Shapes and draws the preview in the parse worker.
+#[derive(Serialize, Deserialize)]
+pub struct Render {
+    text: Option<String>,
+    script: Option<String>,
+    px: f32,
+}
+
+impl isolation::Task for Render {
+    const NAME: &'static str = "preview";
+    /// The SVG and how many characters it draws.
+    type Output = (String, usize);
+
+    fn run(self, data: Vec<u8>, _: &CancelToken) -> Result<Self::Output, String> {
+        let text = text_for(&data, self.text, self.script.as_deref(), FALLBACK_TEXT)?;
+        Ok((render(&data, &text, self.px)?, text.chars().count()))
+    }
+}
+
+pub async fn preview(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(query): Query<PreviewQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    state.flags.ensure("demos", &headers)?;
+    if !(8..=512).contains(&query.size) {
+        return Err((StatusCode::BAD_REQUEST, "size must be 8-512".to_string()));
+    }
+    if let Some(text) = &query.text {
+        let characters = text.chars().count();
+        if text.trim().is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "text must not be empty".to_string()));
+        }
+        if characters > MAX_CHARACTERS {
+            let message = format!("text has {characters} characters; at most {MAX_CHARACTERS}");
+            return Err((StatusCode::BAD_REQUEST, message));
+        }
+    }
+    sample_script(query.script.as_deref())?;
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &id)?;
+    if !state.catalog.read().unwrap().iter().any(|e| e.id == id) {
+        return Err((StatusCode::NOT_FOUND, format!("no catalog entry '{id}'")));
+    }
+    if signing::is_private(&state, &artifacts::slug(&id)) {
+        return Err((StatusCode::FORBIDDEN, format!("'{id}' is private and has no public preview")));
+    }
+
+    let data = duplicates::read_catalog_binary(&state, &id).await.map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let task = Render { text: query.text, script: query.script, px: query.size as f32 };
+    let (svg, characters) =
+        isolation::run(&state, task, data).await?.map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let etag = format!("\"{}\"", &format!("{:x}", Sha256::digest(&svg))[..16]);
+    let cached = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').map(|t| t.trim().trim_start_matches("W/")).any(|t| t == "*" || t == etag));
+    info!(id = %id, characters, size = query.size, bytes = svg.len(), "preview rendered");
+
+    let response = Response::builder()
+        .header(header::CONTENT_TYPE, "image/svg+xml")
+        .header(header::CACHE_CONTROL, state.cache_policies.get(Policy::Generated))
+        .header(header::ETAG, &etag);
+    if cached {
+        return Ok(response.status(StatusCode::NOT_MODIFIED).body(Body::empty()).unwrap());
+    }
+    Ok(response.header(header::CONTENT_LENGTH, svg.len()).body(Body::from(svg)).unwrap())
+}
@@ -0,0 +1,89 @@
+//! Liveness and readiness probes for orchestrators.
+//!
+//! `/healthz/live` answers as long as the runtime serves requests; failing it
+//! means the process should be restarted. `/healthz/ready` (also `/readyz`)
+//! checks every dependency — upload and artifact storage, the database when
+//! `DATABASE_URL` is set, and the subset cache — within
+//! `READINESS_TIMEOUT_MS` each, and answers `503` when any fails so the
+//! replica is taken out of rotation. Edge status is reported alongside but
+//! does not make the engine unready.
+
+use axum::{extract::State, http::StatusCode, response::Json};
+use serde::Serialize;
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{db, edge, AppState};
+
+#[derive(Debug, Serialize)]
+pub struct LiveResponse {
+    status: &'static str,
+    uptime_secs: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    name: &'static str,
+    /// `ok` or `failed`.
+    status: &'static str,
+    latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadyResponse {
+    /// `ready` or `unready`.
+    status: &'static str,
+    checks: Vec<Check>,
+    edges: Vec<edge::EdgeStatus>,
+}
+
+fn timeout() -> Duration {
+    let ms = std::env::var("READINESS_TIMEOUT_MS").ok().and_then(|v| v.parse().ok()).unwrap_or(2000);
+    Duration::from_millis(ms)
+}
+
+async fn check(name: &'static str, limit: Duration, probe: impl Future<Output = Result<(), String>>) -> Check {
+    let started = Instant::now();
+    let result = tokio::time::timeout(limit, probe)
+        .await
+        .unwrap_or_else(|_| Err(format!("no answer within {} ms", limit.as_millis())));
+    Check {
+        name,
+        status: if result.is_ok() { "ok" } else { "failed" },
+        latency_ms: started.elapsed().as_micros() as f64 / 1000.0,
+        error: result.err(),
+    }
+}
+
+pub async fn live(State(state): State<Arc<AppState>>) -> Json<LiveResponse> {
+    Json(LiveResponse { status: "alive", uptime_secs: state.start_time.elapsed().as_secs() })
+}
+
+pub async fn ready(State(state): State<Arc<AppState>>) -> (StatusCode, Json<ReadyResponse>) {
+    let limit = timeout();
+    let database = async {
+        let pool = state.db.as_ref()?;
+        Some(check("database", limit, async { db::ping(pool).await.map_err(|e| e.to_string()) }).await)
+    };
+    let (uploads, artifacts, database) = tokio::join!(
+        check("uploads", limit, state.uploads.storage().ping()),
+        check("artifacts", limit, state.artifacts.storage().ping()),
+        database,
+    );
+    let cache = check("cache", limit, async { state.subsets.check() }).await;
+    let checks: Vec<Check> = [Some(uploads), Some(artifacts), database, Some(cache)].into_iter().flatten().collect();
+
+    let ready = checks.iter().all(|c| c.error.is_none());
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    let body = ReadyResponse {
+        status: if ready { "ready" } else { "unready" },
+        checks,
+        edges: state.edges.statuses(),
+    };
+    (status, Json(body))
+}
@@ -0,0 +1,201 @@
+//! Named, versioned subset profiles.
+//!
+//! A team saves a subset specification (characters, unicode ranges, a
+//! preset, layout features) under a name with
+//! `PUT /api/v1/font/subset-profiles/checkout-page-ja` and then references
+//! it as `"profile": "checkout-page-ja"` in subset requests or
+//! `?profile=checkout-page-ja` on family CSS. Every save creates a new
+//! version; `name@2` pins one. Profiles are scoped to the calling tenant
+//! (`X-Font-Tenant`) and persisted when a database is configured.
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{SavedProfile, SubsetSpec};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    ops::RangeInclusive,
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::info;
+
+use crate::{audit, db, extract::ApiJson, unicode, AppState};
+
+const MAX_NAME_LEN: usize = 64;
+/// Built-in output profiles of the subset endpoint.
+const RESERVED: &[&str] = &["web", "pdf"];
+
+fn validate(spec: &SubsetSpec) -> Result<(), String> {
+    if let Some(bad) = spec.ranges.iter().find(|r| unicode::parse_range(r).is_none()) {
+        return Err(format!("'{bad}' is not a unicode range like U+0000-00FF"));
+    }
+    if let Some(preset) = spec.preset.as_deref().filter(|p| unicode::preset(p).is_none()) {
+        return Err(format!("unknown preset '{preset}'; valid: {}", unicode::preset_names()));
+    }
+    if let Some(bad) = spec.features.iter().find(|f| f.len() != 4 || !f.is_ascii()) {
+        return Err(format!("feature '{bad}' must be a four-character OpenType tag"));
+    }
+    if code_points(spec).is_empty() {
+        return Err("profile must list characters, ranges or a preset".to_string());
+    }
+    Ok(())
+}
+
+/// Every code point `spec` selects, merged into ranges.
+pub fn code_points(spec: &SubsetSpec) -> Vec<RangeInclusive<u32>> {
+    let mut ranges = unicode::parse_ranges(&spec.ranges);
+    ranges.extend(spec.preset.as_deref().and_then(unicode::preset).unwrap_or_default());
+    ranges.extend(spec.characters.chars().map(|c| c as u32..=c as u32));
+    unicode::merge(ranges)
+}
+
+/// Every version of every profile, keyed by (tenant, name).
+#[derive(Default)]
+pub struct SubsetProfiles {
+    saved: RwLock<BTreeMap<(String, String), Vec<SavedProfile>>>,
+}
+
+fn tenant(headers: &HeaderMap) -> String {
+    headers.get("x-font-tenant").and_then(|v| v.to_str().ok()).unwrap_or_default().to_string()
+}
+
+fn validate_name(name: &str) -> Result<(), (StatusCode, String)> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !RESERVED.contains(&name);
+    if valid {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "profile name must be 1-{MAX_NAME_LEN} lowercase letters, digits or hyphens and not {}",
+                RESERVED.join("/")
+            ),
+        ))
+    }
+}
+
+impl SubsetProfiles {
+    pub fn load(profiles: Vec<SavedProfile>) -> Self {
+        let mut saved: BTreeMap<(String, String), Vec<SavedProfile>> = BTreeMap::new();
+        for p in profiles {
+            saved.entry((p.tenant.clone(), p.name.clone())).or_default().push(p);
+        }
+        Self { saved: RwLock::new(saved) }
+    }
+
+    /// Looks up `name` (latest version) or `name@version` for the caller.
+    pub fn resolve(&self, headers: &HeaderMap, reference: &str) -> Result<SavedProfile, (StatusCode, String)> {
+        let (name, version) = match reference.split_once('@') {
+            Some((name, v)) => {
+                let v = v
+                    .parse::<u32>()
+                    .map_err(|_| (StatusCode::BAD_REQUEST, format!("'{reference}': version must be a number")))?;
+                (name, Some(v))
+            }
+            None => (reference, None),
+        };
+        let saved = self.saved.read().unwrap();
+        let versions = saved
+            .get(&(tenant(headers), name.to_string()))
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("no subset profile '{name}'")))?;
+        match version {
+            Some(v) => versions.iter().find(|p| p.version == v),
+            None => versions.last(),
+        }
+        .cloned()
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("subset profile '{reference}' does not exist")))
+    }
+}
+
+pub async fn list(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Json<Vec<SavedProfile>> {
+    let tenant = tenant(&headers);
+    let saved = state.profiles.saved.read().unwrap();
+    Json(
+        saved
+            .iter()
+            .filter(|((t, _), _)| *t == tenant)
+            .filter_map(|(_, versions)| versions.last().cloned())
+            .collect(),
+    )
+}
+
+/// Every version of one profile, oldest first.
+pub async fn history(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<SavedProfile>>, (StatusCode, String)> {
+    let saved = state.profiles.saved.read().unwrap();
+    saved
+        .get(&(tenant(&headers), name.clone()))
+        .map(|versions| Json(versions.clone()))
+        .ok_or((StatusCode::NOT_FOUND, format!("no subset profile '{name}'")))
+}
+
+/// Saves `spec` as the next version of `name`.
+pub async fn save(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    ApiJson(spec): ApiJson<SubsetSpec>,
+) -> Result<Json<SavedProfile>, (StatusCode, String)> {
+    validate_name(&name)?;
+    validate(&spec).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let tenant = tenant(&headers);
+    let key = (tenant.clone(), name.clone());
+    let version = state.profiles.saved.read().unwrap().get(&key).and_then(|v| v.last()).map_or(1, |p| p.version + 1);
+    let profile = SavedProfile {
+        tenant,
+        name,
+        version,
+        saved_at_unix: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        spec,
+    };
+    if let Some(pool) = &state.db {
+        db::save_subset_profile(pool, &profile)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("profile persist failed: {e}")))?;
+    }
+    let before = {
+        let mut saved = state.profiles.saved.write().unwrap();
+        let versions = saved.entry(key).or_default();
+        // A concurrent save took this version number first.
+        if versions.last().is_some_and(|p| p.version >= profile.version) {
+            return Err((StatusCode::CONFLICT, format!("profile '{}' was saved concurrently; retry", profile.name)));
+        }
+        let before = versions.last().cloned();
+        versions.push(profile.clone());
+        before
+    };
+    audit::record(&state, &headers, "profile.save", &profile.name, json!(before), json!(profile)).await;
+    info!(tenant = %profile.tenant, name = %profile.name, version = profile.version, "subset profile saved");
+    Ok(Json(profile))
+}
+
+/// Deletes every version of `name`.
+pub async fn delete(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let key = (tenant(&headers), name.clone());
+    if !state.profiles.saved.read().unwrap().contains_key(&key) {
+        return Err((StatusCode::NOT_FOUND, format!("no subset profile '{name}'")));
+    }
+    if let Some(pool) = &state.db {
+        db::delete_subset_profile(pool, &key.0, &name)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("profile persist failed: {e}")))?;
+    }
+    let before = state.profiles.saved.write().unwrap().remove(&key);
+    audit::record(&state, &headers, "profile.delete", &name, json!(before), Value::Null).await;
+    info!(tenant = %key.0, name = %name, "subset profile deleted");
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,247 @@
+//! Frequency-ordered progressive subsets.
+//!
+//! A large (typically CJK) font is split into a small "core" chunk holding
+//! the most frequent characters of a language and a few "extended" chunks
+//! with the rest of the language's coverage. The generated CSS declares
+//! every chunk as the same face with disjoint `unicode-range`s, so first
+//! paint only fetches the core chunk and the browser pulls an extended one
+//! only when the page uses one of its characters. Chunks draw only on
+//! characters the catalog binary's `cmap` maps; each is subset from it (with
+//! its layout closure) and stored as a content-addressed artifact.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::ProgressiveRequest;
+use serde::Serialize;
+use serde_json::json;
+use std::{collections::BTreeSet, ops::RangeInclusive, sync::Arc};
+use tracing::info;
+
+use crate::{
+    artifacts, cancel, cmap::CharMap, compress, duplicates, extract::ApiJson, instances::css_format, signing, slices,
+    subset, unicode, AppState,
+};
+
+const MAX_CHUNKS: usize = 16;
+
+/// Japanese: kana and punctuation, then kanji by frequency.
+pub const JA_FREQUENT: &str = "、。「」・ーのにはをたがでてとしれさいるかもなっうこあんすらりまよくおけえせつどだちきほわやみねそゆろむめぬへふひ\
+ンスルトリクイラドタシアカレロッコフジマテプグメキオニ\
+日一人年大国十二本中長出三時行見月分後前生五間上東四今金九入学高円子外八六下来気小七山話女北午百書先名川千水半男西電校語土木聞食車何南万毎白天母火右読友左休父雨\
+会事自社者地業方新場員立開手力問代明動京目通言理体田主題意不作用度強公持野以思家世多正安院心界教文元重近考画海売知道集別物使品計死特私始朝運終台広住無真有口少町料工建空急止送切転研足究楽起着店病質待試族銀早映親験英医仕去味写字答夜音注帰古歌買悪図週室歩風紙黒花春赤青館屋色走秋夏習駅洋旅服夕借曜飲肉貸堂鳥飯勉冬昼茶弟牛魚兄犬妹姉漢";
+
+/// Simplified Chinese: hanzi by frequency.
+pub const ZH_FREQUENT: &str = "，。、的一是不了人我在有他这中大来上国个到说们为子和你地出道也时年得就那要下以生会自着去之过家学对可她里后小么心多天而能好都然没日于起还发成事只作当想看文无开手十用主行方又如前所本见经头面公同三已老从动两长知民样现分将外但身些与高意进把法此实回二理美点月明其种声全工己话儿者向情部正名定女问力机给等几很业最间新什打便位因重被走电四第门相次东政海口使教西再平真听世气信北少关并内加化由却代军产入先山五太水万市眼体别处总才场师书比住员九笑性通目华报立马命张活难神数件安表原车白应路期叫死常提感金何更反合放做系计或司利受光王果亲界及今京务制解各任至清物台象记边共风战干接它许八特觉望直服毛林题建南度统色字请交爱让认算论百吃义科怎元社术结六功指思非流每青管夫连远资队跟带花快条院变联言权往展该领传近留红治决周保达办运武半候七必城父强步完革深区即求品士转量空甚众技轻程告江语英基派满式李息写呢识极令黄德收脸钱党倒未持音跑";
+
+/// Korean: hangul syllables by frequency.
+pub const KO_FREQUENT: &str = "이다는의에가하고을를지기사서도자리한로어으나그대시수정인보있아해것게라일들제만과적주부여상면오전말중요우내위소국원생무성경장연거화동간니데비학없음방문계식실유회저공관발신개단당분마물세안운업점체치터통판표품합행현호후";
+
+/// Latin: letters by English frequency, then digits and punctuation. Leads
+/// every language's core chunk.
+pub const LATIN_FREQUENT: &str = "etaoinshrdlcumwfgypbvkjxqzETAOINSHRDLCUMWFGYPBVKJXQZ0123456789 .,'\"-:;!?()&/";
+
+struct Language {
+    code: &'static str,
+    frequent: &'static str,
+    /// Coverage the extended chunks draw from.
+    script: &'static [(u32, u32)],
+}
+
+const LANGUAGES: &[Language] = &[
+    Language {
+        code: "ja",
+        frequent: JA_FREQUENT,
+        script: &[(0x0020, 0x007E), (0x3000, 0x30FF), (0x4E00, 0x9FFF), (0xFF01, 0xFF9F)],
+    },
+    Language {
+        code: "zh",
+        frequent: ZH_FREQUENT,
+        script: &[(0x0020, 0x007E), (0x3000, 0x303F), (0x4E00, 0x9FFF), (0xFF01, 0xFF5E)],
+    },
+    Language {
+        code: "ko",
+        frequent: KO_FREQUENT,
+        script: &[(0x0020, 0x007E), (0x3000, 0x303F), (0x3130, 0x318F), (0xAC00, 0xD7A3)],
+    },
+    Language { code: "en", frequent: LATIN_FREQUENT, script: &[(0x0020, 0x007E), (0x00A0, 0x024F), (0x2000, 0x206F)] },
+];
+
+#[derive(Debug, Serialize)]
+pub struct Chunk {
+    name: String,
+    character_count: usize,
+    unicode_range: String,
+    size_kb: f64,
+    download_url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProgressiveResponse {
+    font_name: String,
+    family: String,
+    language: String,
+    format: String,
+    chunks: Vec<Chunk>,
+    total_size_kb: f64,
+    /// What a page using only core characters downloads.
+    first_paint_kb: f64,
+    css: String,
+}
+
+/// Ranges covering exactly `points` (sorted).
+fn ranges_of(points: &[u32]) -> Vec<RangeInclusive<u32>> {
+    let mut ranges: Vec<RangeInclusive<u32>> = Vec::new();
+    for &p in points {
+        match ranges.last_mut() {
+            Some(last) if *last.end() + 1 == p => *last = *last.start()..=p,
+            _ => ranges.push(p..=p),
+        }
+    }
+    ranges
+}
+
+pub async fn progressive(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(req): ApiJson<ProgressiveRequest>,
+) -> Result<Json<ProgressiveResponse>, (StatusCode, String)> {
+    state.flags.ensure("subset", &headers)?;
+    state.quotas.charge_operation(&headers)?;
+
+    if !["woff2", "woff", "otf", "ttf"].contains(&req.format.as_str()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("unsupported format '{}'; valid: woff2, woff, otf, ttf", req.format),
+        ));
+    }
+    if req.font_name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "font_name is required".to_string()));
+    }
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &req.font_name)?;
+    let Some(&Language { code: language, frequent, script }) = LANGUAGES.iter().find(|l| l.code == req.language) else {
+        let codes: Vec<&str> = LANGUAGES.iter().map(|l| l.code).collect();
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("unsupported language '{}'; valid: {}", req.language, codes.join(", ")),
+        ));
+    };
+    if req.core_size == Some(0) {
+        return Err((StatusCode::BAD_REQUEST, "core_size must be at least 1".to_string()));
+    }
+    if !(1..=MAX_CHUNKS).contains(&req.chunks) {
+        return Err((StatusCode::BAD_REQUEST, format!("chunks must be 1-{MAX_CHUNKS}")));
+    }
+
+    let (id, family) = slices::catalog_entry(&state, &req.font_name)?;
+    let source = state.artifacts.source(&state, &id, None).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let (job_state, job_id, format, core_size, chunks) =
+        (Arc::clone(&state), id.clone(), req.format.clone(), req.core_size, req.chunks);
+
+    let encoded = cancel::run(&state.jobs, "progressive-subset", move |token| {
+        let data = match duplicates::catalog_binary(&job_state, &job_id) {
+            Ok(data) => data,
+            Err(e) => return Ok(Err(e)),
+        };
+        let coverage = match compress::load(&data).and_then(|font| {
+            CharMap::parse(font.table(b"cmap").ok_or("font has no cmap table")?).map(|cmap| cmap.ranges())
+        }) {
+            Ok(coverage) => coverage,
+            Err(e) => return Ok(Err(e)),
+        };
+        let covered = |c: u32| char::from_u32(c).is_some_and(|c| unicode::covers(&coverage, c));
+        let mut seen = BTreeSet::new();
+        // Every page has digits, punctuation and Latin letters in URLs and
+        // numbers, so basic Latin leads the core chunk of every language.
+        let core: Vec<u32> = LATIN_FREQUENT
+            .chars()
+            .chain(frequent.chars())
+            .map(|c| c as u32)
+            .filter(|&c| covered(c) && seen.insert(c))
+            .take(core_size.unwrap_or(usize::MAX))
+            .collect();
+        if core.is_empty() {
+            return Ok(Err(format!("'{job_id}' covers none of the frequent {language} characters")));
+        }
+        token.check()?;
+        let core: BTreeSet<u32> = core.into_iter().collect();
+        let rest: Vec<u32> = script
+            .iter()
+            .flat_map(|&(start, end)| start..=end)
+            .filter(|c| covered(*c) && !core.contains(c))
+            .collect();
+        let per_chunk = rest.len().div_ceil(chunks).max(1);
+        let planned = std::iter::once(("core".to_string(), core.into_iter().collect::<Vec<u32>>()))
+            .chain(rest.chunks(per_chunk).enumerate().map(|(i, points)| (format!("ext-{}", i + 1), points.to_vec())));
+
+        let mut encoded = Vec::new();
+        for (name, points) in planned {
+            token.check()?;
+            let file = compress::load(&data).and_then(|mut font| {
+                subset::subset(&mut font, &points.iter().copied().collect(), true, false)?;
+                compress::encode(&font, &format, 100)
+            });
+            match file {
+                Ok(file) => encoded.push((name, points, file)),
+                Err(e) => return Ok(Err(e)),
+            }
+        }
+        Ok(Ok(encoded))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let slug = artifacts::slug(&family);
+    let mut chunks = Vec::with_capacity(encoded.len());
+    for (name, points, file) in encoded {
+        let stem = format!("{slug}-{language}-{name}");
+        let transform = json!({ "slice": points });
+        let address = state.artifacts.named_address(&family, Some(&stem), &source, &transform, &req.format);
+        state.artifacts.put(&address, &file, &json!({ "output_bytes": file.len() })).await?;
+        chunks.push(Chunk {
+            download_url: signing::download_url(&state, &address.path()),
+            unicode_range: unicode::css_unicode_range(&ranges_of(&points)),
+            size_kb: file.len() as f64 / 1024.0,
+            character_count: points.len(),
+            name,
+        });
+    }
+
+    let css = chunks
+        .iter()
+        .map(|c| {
+            format!(
+                "/* {} */\n@font-face {{\n  font-family: \"{family}\";\n  font-style: normal;\n  font-weight: 400;\n  \
+                 font-display: swap;\n  src: url(\"{}\") format(\"{}\");\n  unicode-range: {};\n}}\n",
+                c.name,
+                c.download_url,
+                css_format(&req.format),
+                c.unicode_range
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let total_size_kb = chunks.iter().map(|c| c.size_kb).sum::<f64>();
+    let first_paint_kb = chunks[0].size_kb;
+
+    info!(
+        font = %req.font_name,
+        language,
+        core = chunks[0].character_count,
+        chunks = chunks.len(),
+        "progressive subset built"
+    );
+
+    Ok(Json(ProgressiveResponse {
+        font_name: req.font_name,
+        family,
+        language: language.to_string(),
+        format: req.format,
+        chunks,
+        total_size_kb,
+        first_paint_kb,
+        css,
+    }))
+}
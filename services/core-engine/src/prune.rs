@@ -0,0 +1,312 @@
+//! OpenType feature pruning for `drop_features` / `keep_features`.
+//!
+//! Pruned features are taken out of every language system in GSUB and GPOS
+//! and their Feature tables emptied; FeatureList indices stay put, so
+//! `FeatureVariations` still point at the right features. Lookups no
+//! remaining feature reaches — directly, through a contextual lookup or a
+//! feature variation — lose their subtables, so a subset also drops the
+//! glyphs only they produce (stylistic sets, historical forms). `aalt` lists
+//! every alternate and keeps their lookups alive while it stays. The bytes of
+//! disabled lookups stay in place; a table left with no live lookup is
+//! dropped as a whole.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use crate::{
+    layout,
+    sfnt::{be_u16, be_u32, Font},
+};
+
+/// Which features a request strips.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Filter {
+    /// `true` for `drop_features`, `false` for `keep_features`.
+    drop: bool,
+    tags: Vec<String>,
+    /// Kept whatever `tags` say, such as the vertical features of a
+    /// `vertical` subset.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    spared: Vec<String>,
+}
+
+/// A four-character tag, or a shorter prefix ending in `*`.
+fn valid(tag: &str) -> bool {
+    match tag.strip_suffix('*') {
+        Some(prefix) => prefix.len() < 4 && prefix.is_ascii() && !prefix.contains('*'),
+        None => tag.len() == 4 && tag.is_ascii(),
+    }
+}
+
+impl Filter {
+    /// The filter a request asks for, if any.
+    pub fn new(drop: &[String], keep: Option<&[String]>) -> Result<Option<Self>, String> {
+        let (drop, tags) = match (drop.is_empty(), keep) {
+            (true, None) => return Ok(None),
+            (false, Some(_)) => return Err("give drop_features or keep_features, not both".to_string()),
+            (false, None) => (true, drop),
+            (true, Some(keep)) => (false, keep),
+        };
+        if let Some(bad) = tags.iter().find(|t| !valid(t)) {
+            return Err(format!("feature '{bad}' must be a four-character OpenType tag or a prefix like 'ss*'"));
+        }
+        let mut tags = tags.to_vec();
+        tags.sort();
+        tags.dedup();
+        Ok(Some(Self { drop, tags, spared: Vec::new() }))
+    }
+
+    /// The filter, keeping `tags` whatever it lists.
+    pub fn sparing(mut self, tags: &[[u8; 4]]) -> Self {
+        self.spared = tags.iter().map(|t| String::from_utf8_lossy(t).into_owned()).collect();
+        self
+    }
+
+    fn removes(&self, tag: &[u8; 4]) -> bool {
+        if self.spared.iter().any(|t| t.as_bytes() == tag) {
+            return false;
+        }
+        let listed = self.tags.iter().any(|t| match t.strip_suffix('*') {
+            Some(prefix) => tag.starts_with(prefix.as_bytes()),
+            None => t.as_bytes() == tag,
+        });
+        listed == self.drop
+    }
+}
+
+/// Strips the features `filter` removes from GSUB and GPOS; returns their
+/// tags.
+pub fn prune(font: &mut Font, filter: &Filter) -> Result<Vec<String>, String> {
+    let mut removed = BTreeSet::new();
+    for (tag, lookup_types) in [(*b"GSUB", [7, 5, 6]), (*b"GPOS", [9, 7, 8])] {
+        let Some(table) = font.table(&tag) else { continue };
+        let pruned = rebuild(table, filter, lookup_types, &mut removed).ok_or_else(|| {
+            format!("{} table is malformed or too large to prune", String::from_utf8_lossy(&tag))
+        })?;
+        match pruned {
+            Pruned::Unchanged => {}
+            Pruned::Empty => font.tables.retain(|t| t.tag != tag),
+            Pruned::Rebuilt(table) => font.set_table(tag, table),
+        }
+    }
+    Ok(removed.into_iter().collect())
+}
+
+fn push16(out: &mut Vec<u8>, value: usize) -> Option<()> {
+    out.extend(u16::try_from(value).ok()?.to_be_bytes());
+    Some(())
+}
+
+/// Lookup indices of the Feature table at `at`.
+fn feature_lookups(table: &[u8], at: usize) -> Option<Vec<u16>> {
+    (0..be_u16(table, at + 2)? as usize).map(|i| be_u16(table, at + 4 + 2 * i)).collect()
+}
+
+enum Pruned {
+    Unchanged,
+    /// No lookup is left to apply.
+    Empty,
+    Rebuilt(Vec<u8>),
+}
+
+/// `table` without the features `filter` removes. `lookup_types` are the
+/// extension, context and chained context lookup types.
+fn rebuild(
+    table: &[u8],
+    filter: &Filter,
+    [extension, context, chained]: [u16; 3],
+    removed: &mut BTreeSet<String>,
+) -> Option<Pruned> {
+    let minor = be_u16(table, 2)?;
+    let (scripts, features, lookup_list) =
+        (be_u16(table, 4)? as usize, be_u16(table, 6)? as usize, be_u16(table, 8)? as usize);
+    let variations = if minor >= 1 { be_u32(table, 10)? as usize } else { 0 };
+
+    let records: Vec<([u8; 4], usize)> = (0..be_u16(table, features)? as usize)
+        .map(|i| {
+            let at = features + 2 + 6 * i;
+            Some((table.get(at..at + 4)?.try_into().ok()?, features + be_u16(table, at + 4)? as usize))
+        })
+        .collect::<Option<_>>()?;
+    let pruned: Vec<bool> = records.iter().map(|(tag, _)| filter.removes(tag)).collect();
+    if !pruned.contains(&true) {
+        return Some(Pruned::Unchanged);
+    }
+    for ((tag, _), _) in records.iter().zip(&pruned).filter(|(_, &p)| p) {
+        removed.insert(String::from_utf8_lossy(tag).into_owned());
+    }
+    let is_pruned = |feature: u16| pruned.get(feature as usize).copied().unwrap_or(false);
+
+    // Lookups the remaining features reach.
+    let mut pending = Vec::new();
+    for ((_, feature), &p) in records.iter().zip(&pruned) {
+        if !p {
+            pending.extend(feature_lookups(table, *feature)?);
+        }
+    }
+    if variations != 0 {
+        for i in 0..be_u32(table, variations + 4)? as usize {
+            let offset = be_u32(table, variations + 8 + 8 * i + 4)? as usize;
+            if offset == 0 {
+                continue;
+            }
+            let substitution = variations + offset;
+            for j in 0..be_u16(table, substitution + 4)? as usize {
+                let at = substitution + 6 + 6 * j;
+                if !is_pruned(be_u16(table, at)?) {
+                    pending.extend(feature_lookups(table, substitution + be_u32(table, at + 2)? as usize)?);
+                }
+            }
+        }
+    }
+    let lookups = layout::lookups(table, extension)?;
+    let mut live = BTreeSet::new();
+    while let Some(index) = pending.pop() {
+        if !live.insert(index) {
+            continue;
+        }
+        for &(kind, sub) in lookups.get(index as usize).map_or(&[][..], Vec::as_slice) {
+            pending.extend(layout::nested_lookups(table, kind, sub, context, chained)?);
+        }
+    }
+    if live.is_empty() {
+        return Some(Pruned::Empty);
+    }
+
+    // New header, ScriptList and FeatureList; the lookup list and feature
+    // variations, with everything they point to, are copied unchanged.
+    let header = if minor >= 1 { 14 } else { 10 };
+    let script_list = script_list(table, scripts, &is_pruned)?;
+    let feature_list = feature_list(table, &records, &pruned)?;
+    let start = if variations != 0 { lookup_list.min(variations) } else { lookup_list };
+    let base = header + script_list.len() + feature_list.len();
+    let mut out = table.get(..4)?.to_vec();
+    push16(&mut out, header)?;
+    push16(&mut out, header + script_list.len())?;
+    push16(&mut out, base + lookup_list - start)?;
+    if minor >= 1 {
+        let moved = if variations != 0 { base + variations - start } else { 0 };
+        out.extend(u32::try_from(moved).ok()?.to_be_bytes());
+    }
+    out.extend(script_list);
+    out.extend(feature_list);
+    out.extend_from_slice(table.get(start..)?);
+    for i in (0..lookups.len()).filter(|&i| !live.contains(&(i as u16))) {
+        let count = base + lookup_list + be_u16(table, lookup_list + 2 + 2 * i)? as usize + 4 - start;
+        out.get_mut(count..count + 2)?.fill(0);
+    }
+    Some(Pruned::Rebuilt(out))
+}
+
+/// Tagged records (`tag`, offset) followed by the tables they point to.
+fn tagged(out: &mut Vec<u8>, header: usize, entries: Vec<(&[u8], Vec<u8>)>) -> Option<()> {
+    let mut offset = header + 6 * entries.len();
+    for (tag, body) in &entries {
+        out.extend_from_slice(tag);
+        push16(out, offset)?;
+        offset += body.len();
+    }
+    for (_, body) in entries {
+        out.extend(body);
+    }
+    Some(())
+}
+
+/// The ScriptList with pruned features left out of every language system.
+fn script_list(table: &[u8], at: usize, is_pruned: &dyn Fn(u16) -> bool) -> Option<Vec<u8>> {
+    let count = be_u16(table, at)? as usize;
+    let scripts = (0..count)
+        .map(|i| {
+            let record = at + 2 + 6 * i;
+            let script = at + be_u16(table, record + 4)? as usize;
+            Some((table.get(record..record + 4)?, script_table(table, script, is_pruned)?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let mut out = Vec::new();
+    push16(&mut out, count)?;
+    tagged(&mut out, 2, scripts)?;
+    Some(out)
+}
+
+fn script_table(table: &[u8], at: usize, is_pruned: &dyn Fn(u16) -> bool) -> Option<Vec<u8>> {
+    let default = match be_u16(table, at)? as usize {
+        0 => None,
+        offset => Some(lang_sys(table, at + offset, is_pruned)?),
+    };
+    let count = be_u16(table, at + 2)? as usize;
+    let systems = (0..count)
+        .map(|i| {
+            let record = at + 4 + 6 * i;
+            let system = at + be_u16(table, record + 4)? as usize;
+            Some((table.get(record..record + 4)?, lang_sys(table, system, is_pruned)?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let header = 4 + 6 * count;
+    let mut out = Vec::new();
+    push16(&mut out, if default.is_some() { header } else { 0 })?;
+    push16(&mut out, count)?;
+    let default_len = default.as_ref().map_or(0, Vec::len);
+    tagged(&mut out, 4 + default_len, systems)?;
+    // The default LangSys sits between the records and the others.
+    if let Some(default) = default {
+        out.splice(header..header, default);
+    }
+    Some(out)
+}
+
+fn lang_sys(table: &[u8], at: usize, is_pruned: &dyn Fn(u16) -> bool) -> Option<Vec<u8>> {
+    let required = be_u16(table, at + 2)?;
+    let indices = (0..be_u16(table, at + 4)? as usize)
+        .map(|i| be_u16(table, at + 6 + 2 * i))
+        .collect::<Option<Vec<u16>>>()?;
+    let indices: Vec<u16> = indices.into_iter().filter(|&f| !is_pruned(f)).collect();
+    // lookupOrderOffset (reserved), requiredFeatureIndex, features.
+    let mut out = vec![0, 0];
+    out.extend(if is_pruned(required) { 0xFFFF } else { required }.to_be_bytes());
+    push16(&mut out, indices.len())?;
+    for feature in indices {
+        out.extend(feature.to_be_bytes());
+    }
+    Some(out)
+}
+
+/// The FeatureList with pruned features' lookups emptied.
+fn feature_list(table: &[u8], records: &[([u8; 4], usize)], pruned: &[bool]) -> Option<Vec<u8>> {
+    let features = records
+        .iter()
+        .zip(pruned)
+        .map(|((tag, at), &p)| Some((&tag[..], if p { vec![0; 4] } else { feature_table(table, tag, *at)? })))
+        .collect::<Option<Vec<_>>>()?;
+    let mut out = Vec::new();
+    push16(&mut out, features.len())?;
+    tagged(&mut out, 2, features)?;
+    Some(out)
+}
+
+fn feature_table(table: &[u8], tag: &[u8; 4], at: usize) -> Option<Vec<u8>> {
+    let lookups = feature_lookups(table, at)?;
+    let params = match be_u16(table, at)? as usize {
+        0 => None,
+        offset => params(table, tag, at + offset),
+    };
+    let mut out = Vec::new();
+    push16(&mut out, if params.is_some() { 4 + 2 * lookups.len() } else { 0 })?;
+    push16(&mut out, lookups.len())?;
+    for lookup in lookups {
+        out.extend(lookup.to_be_bytes());
+    }
+    out.extend_from_slice(params.unwrap_or_default());
+    Some(out)
+}
+
+/// FeatureParams of `size`, `ssXX` and `cvXX`, the features that define
+/// them; others are dropped.
+fn params<'a>(table: &'a [u8], tag: &[u8; 4], at: usize) -> Option<&'a [u8]> {
+    let len = match tag {
+        b"size" => 10,
+        [b's', b's', ..] => 4,
+        [b'c', b'v', ..] => 14 + 3 * be_u16(table, at + 12)? as usize,
+        _ => return None,
+    };
+    table.get(at..at + len)
+}
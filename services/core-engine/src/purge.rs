@@ -0,0 +1,113 @@
+//! Purging generated files after a bad one ships.
+//!
+//! `POST /api/v1/admin/cache/purge` (admin) takes `{"font_id"}`,
+//! `{"prefix": "/cdn/fonts/inter/"}` or `{"all": true}`. Matching files are
+//! deleted from artifact storage with their records, and matching subsets
+//! leave the in-memory cache (see [`crate::cache`]), so the next request
+//! generates them again. As CDN files are cached as immutable, the purged
+//! fonts also move to a new purge generation first: regenerated files get
+//! new addresses, and family CSS adds `?v=<generation>` to the URLs it
+//! cannot rename (see [`crate::artifacts`]). Other replicas pick up the
+//! generation within half a minute.
+//!
+//! Files of a current slicing stay, as family CSS points at them; slicing
+//! the font again replaces them.
+
+use axum::{extract::State, http::HeaderMap, http::StatusCode, response::Json};
+use font_api::{CachePurgeRequest, CachePurgeResponse};
+use serde_json::{json, Value};
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+use tracing::{info, warn};
+
+use crate::{artifacts, audit, extract::ApiJson, storage, tenants, AppState};
+
+const SYNC_SECS: u64 = 30;
+
+/// Reloads purge generations until the process exits.
+pub async fn run(state: Arc<AppState>) {
+    let mut tick = tokio::time::interval(Duration::from_secs(SYNC_SECS));
+    tick.tick().await;
+    loop {
+        tick.tick().await;
+        if let Err(e) = state.artifacts.reload_purges().await {
+            warn!("reading purge generations failed: {e}");
+        }
+    }
+}
+
+/// Keys of the files stored under `slug`, with those of its versions.
+async fn stored(state: &AppState, slug: &str) -> Result<Vec<String>, (StatusCode, String)> {
+    let versions = state.catalog.read().unwrap().iter().find(|e| e.id == slug).map_or(0, |e| e.version);
+    let store = state.artifacts.storage();
+    let mut keys = store.list(slug).await.map_err(storage::error("listing artifacts"))?;
+    for n in 1..=versions {
+        keys.extend(store.list(&format!("{slug}/v{n}")).await.map_err(storage::error("listing artifacts"))?);
+    }
+    Ok(keys)
+}
+
+pub async fn purge(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(req): ApiJson<CachePurgeRequest>,
+) -> Result<Json<CachePurgeResponse>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let bad = |message: &str| (StatusCode::BAD_REQUEST, message.to_string());
+    // Slugs whose generation moves (none for all), the slugs to list and
+    // the key prefix a file must have.
+    let (bumped, slugs, key_prefix) = match (&req.font_id, &req.prefix, req.all) {
+        (Some(id), None, false) => {
+            let catalog = state.catalog.read().unwrap();
+            let entry = catalog
+                .iter()
+                .find(|e| e.id == *id)
+                .ok_or_else(|| (StatusCode::NOT_FOUND, format!("no catalog entry '{id}'")))?;
+            let slugs: BTreeSet<String> = [artifacts::slug(&entry.id), artifacts::slug(&entry.family)].into();
+            (slugs.iter().cloned().collect::<Vec<_>>(), slugs, String::new())
+        }
+        (None, Some(prefix), false) => {
+            let key = tenants::artifact_key(prefix).trim_start_matches('/');
+            let slug = key.split('/').next().unwrap_or_default().to_string();
+            if !prefix.starts_with("/cdn/") || slug.is_empty() || slug.starts_with('.') {
+                return Err(bad("prefix must be a CDN path naming at least a font, like /cdn/fonts/inter/"));
+            }
+            let key_prefix = if key.contains('/') { key.to_string() } else { format!("{key}/") };
+            (vec![slug.clone()], BTreeSet::from([slug]), key_prefix)
+        }
+        (None, None, true) => {
+            let catalog = state.catalog.read().unwrap();
+            let slugs = catalog.iter().flat_map(|e| [artifacts::slug(&e.id), artifacts::slug(&e.family)]).collect();
+            (Vec::new(), slugs, String::new())
+        }
+        _ => return Err(bad("give exactly one of font_id, prefix or \"all\": true")),
+    };
+
+    let generation = state.artifacts.bump(&bumped).await.map_err(storage::error("storing purge generation"))?;
+    let sliced = state.slices.keys();
+    let mut purged = Vec::new();
+    let mut urls = Vec::new();
+    for slug in &slugs {
+        for key in stored(&state, slug).await? {
+            if !key.starts_with(&key_prefix) || sliced.contains(&key) {
+                continue;
+            }
+            state.artifacts.delete(&key).await.map_err(storage::error("deleting artifact"))?;
+            let path = tenants::cdn_path(&state, &format!("/cdn/fonts/{key}"));
+            // Versions come back under the same path; other files under new
+            // addresses, from the next request for them.
+            if key.split('/').nth(1).is_some_and(|dir| dir.starts_with('v') && key.matches('/').count() == 2) {
+                urls.push(state.artifacts.bust(slug, state.edges.url_for(&path)));
+            }
+            purged.push(path);
+        }
+    }
+    let evicted = state.subsets.evict(|key| {
+        let slug = key.split('/').next().unwrap_or_default();
+        (req.all || slugs.contains(slug)) && key.starts_with(&key_prefix)
+    });
+    info!(files = purged.len(), evicted, generation, "artifacts purged");
+    let response = CachePurgeResponse { purged, evicted, generation, urls };
+    let target = req.font_id.or(req.prefix).unwrap_or_else(|| "all".to_string());
+    audit::record(&state, &headers, "cache.purge", &target, Value::Null, json!(response)).await;
+    Ok(Json(response))
+}
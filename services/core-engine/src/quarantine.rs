@@ -0,0 +1,206 @@
+//! Quarantine for fonts that failed intake checks.
+//!
+//! Files that are structurally broken or flagged by the virus scanner are
+//! held here with the failure report instead of being dropped, so operators
+//! can inspect them, retry the checks (e.g. after a scanner outage) or
+//! discard them through the admin API. Quarantined files live on disk under
+//! `QUARANTINE_DIR` (default `<SPOOL_DIR>/quarantine`), not in memory.
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    path::{Path as FsPath, PathBuf},
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+use crate::{audit, scan::Verdict, sfnt, spool::{self, SpooledFile}, AppState};
+
+const MAX_ENTRIES: usize = 256;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub flavor: Option<sfnt::Flavor>,
+    pub problems: Vec<String>,
+    pub verdict: Verdict,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.problems.is_empty() && !matches!(self.verdict, Verdict::Infected { .. })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuarantineEntry {
+    id: String,
+    sha256: String,
+    size_bytes: u64,
+    quarantined_at_unix: u64,
+    attempts: u32,
+    report: Report,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetryResponse {
+    released: bool,
+    entry: QuarantineEntry,
+}
+
+#[derive(Default)]
+pub struct Quarantine {
+    entries: RwLock<BTreeMap<String, QuarantineEntry>>,
+}
+
+pub fn quarantine_dir() -> PathBuf {
+    std::env::var("QUARANTINE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| spool::spool_dir().join("quarantine"))
+}
+
+/// Runs the structural check and the virus scan over the file at `path`.
+/// Only the leading [`sfnt::INSPECT_PREFIX`] bytes are read into memory.
+pub async fn inspect(state: &AppState, path: &FsPath, len: u64) -> Result<Report, (StatusCode, String)> {
+    let head = spool::read_head(path, sfnt::INSPECT_PREFIX)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("reading {}: {e}", path.display())))?;
+    let (flavor, problems) = match sfnt::check_structure(&head, len) {
+        Ok(flavor) => (Some(flavor), Vec::new()),
+        Err(problems) => (sfnt::sniff(&head), problems),
+    };
+    let verdict = state
+        .scanner
+        .scan(path)
+        .await
+        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("virus scanner unavailable: {e}")))?;
+    Ok(Report { flavor, problems, verdict })
+}
+
+impl Quarantine {
+    /// Moves `file` into the quarantine directory with its failing report and
+    /// returns the quarantine ID.
+    pub async fn admit(&self, file: SpooledFile, report: Report) -> Result<String, (StatusCode, String)> {
+        let existing = self
+            .entries
+            .write()
+            .unwrap()
+            .values_mut()
+            .find(|e| e.sha256 == file.sha256)
+            .map(|e| {
+                e.report = report.clone();
+                e.id.clone()
+            });
+        if let Some(id) = existing {
+            return Ok(id);
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let path = quarantine_dir().join(&id);
+        let (sha256, size_bytes) = (file.sha256.clone(), file.size);
+        file.persist(&path)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("quarantining file: {e}")))?;
+
+        let mut entries = self.entries.write().unwrap();
+        if entries.len() >= MAX_ENTRIES {
+            // Drop the oldest entry rather than refusing new evidence.
+            let oldest = entries
+                .values()
+                .min_by_key(|e| e.quarantined_at_unix)
+                .map(|e| e.id.clone());
+            if let Some(evicted) = oldest.and_then(|id| entries.remove(&id)) {
+                warn!(id = %evicted.id, "quarantine full; evicting oldest entry");
+                let _ = std::fs::remove_file(&evicted.path);
+            }
+        }
+        info!(id = %id, sha256 = %sha256, problems = ?report.problems, verdict = ?report.verdict, "font quarantined");
+        entries.insert(id.clone(), QuarantineEntry {
+            id: id.clone(),
+            sha256,
+            size_bytes,
+            quarantined_at_unix: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            attempts: 1,
+            report,
+            path,
+        });
+        Ok(id)
+    }
+
+    fn get(&self, id: &str) -> Result<QuarantineEntry, (StatusCode, String)> {
+        self.entries
+            .read()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("no quarantined font '{id}'")))
+    }
+}
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<QuarantineEntry>>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    Ok(Json(state.quarantine.entries.read().unwrap().values().cloned().collect()))
+}
+
+pub async fn show(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<QuarantineEntry>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    state.quarantine.get(&id).map(Json)
+}
+
+pub async fn retry(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<RetryResponse>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let mut entry = state.quarantine.get(&id)?;
+    let report = inspect(&state, &entry.path, entry.size_bytes).await?;
+    let released = report.passed();
+    entry.report = report;
+    entry.attempts += 1;
+
+    if released {
+        let before = state.quarantine.entries.write().unwrap().remove(&id);
+        let _ = std::fs::remove_file(&entry.path);
+        audit::record(&state, &headers, "quarantine.release", &id, json!(before), Value::Null).await;
+        info!(id = %id, "quarantined font passed re-check and was released");
+    } else {
+        state.quarantine.entries.write().unwrap().insert(id.clone(), entry.clone());
+    }
+    Ok(Json(RetryResponse { released, entry }))
+}
+
+pub async fn discard(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let removed = state.quarantine.entries.write().unwrap().remove(&id);
+    match removed {
+        Some(entry) => {
+            let _ = std::fs::remove_file(&entry.path);
+            audit::record(&state, &headers, "quarantine.discard", &id, json!(entry), Value::Null).await;
+            info!(id = %id, "quarantined font discarded");
+            Ok(StatusCode::NO_CONTENT)
+        }
+        None => Err((StatusCode::NOT_FOUND, format!("no quarantined font '{id}'"))),
+    }
+}
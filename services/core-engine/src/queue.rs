@@ -0,0 +1,819 @@
+//! Asynchronous jobs for work that outlives a request.
+//!
+//! `POST /api/v1/jobs/subset`, `/api/v1/jobs/compress` and
+//! `/api/v1/jobs/slices` take the same body as the synchronous endpoints and
+//! answer `202` with a job ID right away.
+//! Jobs wait in FIFO order for one of `JOB_WORKERS` slots and run under
+//! `JOB_TIMEOUT_SECS` instead of `PROCESSING_TIMEOUT_SECS`.
+//! `GET /api/v1/jobs/:id` reports the status, the queue position while
+//! waiting and, once done, the endpoint's response and download URL;
+//! `DELETE` cancels. `GET /api/v1/jobs/:id/events` follows the job as
+//! Server-Sent Events instead: a `job` event with the same body now and at
+//! every change, including each processing step as it completes (`parse`
+//! 10%, `subset` 60%, `encode` 95%), ending once the job has finished. Jobs
+//! are private to the tenant that queued them and are forgotten
+//! `JOB_RETENTION_SECS` after finishing. With `?callback_url=` the finished
+//! job is also POSTed to the caller (see [`crate::webhook`]).
+//!
+//! By default jobs belong to the replica that accepted them. Each change is
+//! written to `JOB_DIR` (default `<SPOOL_DIR>/jobs`), so after a restart the
+//! same job IDs answer again and jobs that were queued or running are queued
+//! again. With `JOB_QUEUE=postgres` they are kept in the database instead
+//! (see [`crate::db`]) and any replica's `JOB_WORKERS` can run them: a
+//! worker leases a job for `JOB_LEASE_SECS` and renews the lease while it
+//! runs, so when a replica dies its jobs are picked up again once their
+//! leases expire. A replica with a stable `JOB_REPLICA_ID` (say a
+//! StatefulSet pod name) takes its own back at once when it restarts. Idle
+//! workers look for jobs every `JOB_POLL_MS`; a replica following a job it
+//! does not run sees its changes at the same interval. Jobs naming uploads
+//! need storage every replica can read (see [`crate::storage`]). There is no
+//! Redis backend.
+//!
+//! Either way, a job interrupted `JOB_MAX_ATTEMPTS` times fails instead of
+//! running again; `attempts` counts its runs (see [`recovery`]). Jobs that
+//! work in several steps, like slicing, keep a checkpoint with the job after
+//! each (see [`cancel::checkpoint`]), and a run after a restart resumes from
+//! it.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{
+        sse::{self, KeepAlive, Sse},
+        Json,
+    },
+};
+use font_api::SliceRequest;
+use futures_util::{stream, Stream};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{types::Json as DbJson, PgPool};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        Notify, OwnedSemaphorePermit, Semaphore,
+    },
+    task::AbortHandle,
+};
+use tracing::{info, warn};
+
+use crate::{cancel, db, extract::ApiJson, slices, spool, webhook, AppState, CompressRequest, SubsetRequest};
+
+mod recovery;
+
+/// Request headers the endpoints read, kept with jobs; credentials are not.
+const FORWARDED: &[&str] = &["x-font-tenant", "x-font-channel", "user-agent"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        [Self::Queued, Self::Running, Self::Succeeded, Self::Cancelled]
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .unwrap_or(Self::Failed)
+    }
+}
+
+/// The last processing step a job completed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Progress {
+    step: Cow<'static, str>,
+    percent: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    id: String,
+    kind: Cow<'static, str>,
+    status: Status,
+    /// Runs started, including ones a restart or a lost replica cut short.
+    attempts: u32,
+    /// Jobs ahead of this one while queued.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    queue_position: Option<usize>,
+    created_at_unix: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    started_at_unix: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finished_at_unix: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    progress: Option<Progress>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    download_url: Option<String>,
+    /// The synchronous endpoint's response.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<serde_json::Value>,
+    /// HTTP status the synchronous endpoint would have answered with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    callback: Option<Callback>,
+    #[serde(skip)]
+    tenant: String,
+    #[serde(skip)]
+    seq: u64,
+    /// The endpoint's request body and [`FORWARDED`] headers.
+    #[serde(skip)]
+    request: Value,
+    #[serde(skip)]
+    headers: Value,
+    /// How far an interrupted run got (see [`cancel::checkpoint`]).
+    #[serde(skip)]
+    checkpoint: Option<Value>,
+    #[serde(skip)]
+    handle: Option<AbortHandle>,
+}
+
+/// A job as written to `JOB_DIR`.
+#[derive(Serialize, Deserialize)]
+struct Stored {
+    #[serde(flatten)]
+    job: Job,
+    tenant: String,
+    seq: u64,
+    request: Value,
+    headers: Value,
+    #[serde(default)]
+    checkpoint: Option<Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallbackStatus {
+    /// Waiting for the job, or being delivered.
+    Pending,
+    Delivered,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Callback {
+    url: String,
+    status: CallbackStatus,
+    attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobParams {
+    callback_url: Option<String>,
+}
+
+/// What a callback receives.
+#[derive(Serialize)]
+struct Event<'a> {
+    /// `job.succeeded` or `job.failed`.
+    event: &'static str,
+    job: &'a Job,
+}
+
+impl From<db::JobRow> for Job {
+    fn from(row: db::JobRow) -> Self {
+        let status = Status::parse(&row.status);
+        Self {
+            id: row.id,
+            kind: row.kind.into(),
+            status,
+            attempts: row.attempts as u32,
+            queue_position: (status == Status::Queued).then_some(row.queue_position as usize),
+            created_at_unix: row.created_at as u64,
+            started_at_unix: row.started_at.map(|t| t as u64),
+            finished_at_unix: row.finished_at.map(|t| t as u64),
+            progress: row.progress.and_then(|DbJson(p)| serde_json::from_value(p).ok()),
+            download_url: row.download_url,
+            result: row.result.map(|DbJson(r)| r),
+            error_status: row.error_status.map(|s| s as u16),
+            error: row.error,
+            callback: row.callback.and_then(|DbJson(c)| serde_json::from_value(c).ok()),
+            tenant: row.tenant,
+            seq: 0,
+            request: row.request.0,
+            headers: row.headers.0,
+            checkpoint: row.checkpoint.map(|DbJson(c)| c),
+            handle: None,
+        }
+    }
+}
+
+/// Jobs kept in Postgres for every replica (`JOB_QUEUE=postgres`).
+struct Shared {
+    pool: PgPool,
+    /// This replica's name on the leases it holds.
+    replica: String,
+    lease: Duration,
+    poll: Duration,
+    /// Wakes this replica's workers when it queues a job.
+    queued: Notify,
+    /// Jobs this replica's workers are running.
+    running: Mutex<HashMap<String, AbortHandle>>,
+}
+
+pub struct JobQueue {
+    jobs: RwLock<BTreeMap<String, Job>>,
+    workers: Arc<Semaphore>,
+    /// `JOB_WORKERS`.
+    worker_slots: usize,
+    next_seq: AtomicU64,
+    max_queued: usize,
+    timeout: Duration,
+    retention: Duration,
+    max_attempts: u32,
+    /// IDs of jobs as they change.
+    changes: broadcast::Sender<String>,
+    /// Where jobs of this replica are written; `None` with `shared`.
+    dir: Option<PathBuf>,
+    shared: Option<Shared>,
+}
+
+pub fn job_dir() -> PathBuf {
+    std::env::var("JOB_DIR").map(PathBuf::from).unwrap_or_else(|_| spool::spool_dir().join("jobs"))
+}
+
+/// The [`FORWARDED`] headers of a request.
+fn forwarded(headers: &HeaderMap) -> Value {
+    FORWARDED
+        .iter()
+        .filter_map(|&name| Some((name.to_string(), headers.get(name)?.to_str().ok()?.into())))
+        .collect::<serde_json::Map<String, Value>>()
+        .into()
+}
+
+fn restore(headers: &Value) -> HeaderMap {
+    let mut restored = HeaderMap::new();
+    for (name, value) in headers.as_object().into_iter().flatten() {
+        let value = value.as_str().and_then(|v| HeaderValue::from_str(v).ok());
+        if let (Ok(name), Some(value)) = (HeaderName::from_bytes(name.as_bytes()), value) {
+            restored.insert(name, value);
+        }
+    }
+    restored
+}
+
+fn now_unix() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn tenant(headers: &HeaderMap) -> String {
+    headers.get("x-font-tenant").and_then(|v| v.to_str().ok()).unwrap_or_default().to_string()
+}
+
+fn unavailable(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::SERVICE_UNAVAILABLE, format!("job queue unavailable: {e}"))
+}
+
+impl JobQueue {
+    /// `db` is required with `JOB_QUEUE=postgres`.
+    pub fn from_env(db: Option<PgPool>) -> Result<Self, String> {
+        let number = |k: &str, default: u64| std::env::var(k).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+        let shared = match std::env::var("JOB_QUEUE").ok().filter(|v| !v.is_empty()).as_deref() {
+            None | Some("memory") => None,
+            Some("postgres") => {
+                let pool = db.ok_or("JOB_QUEUE=postgres needs DATABASE_URL")?;
+                let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "engine".to_string());
+                let replica = std::env::var("JOB_REPLICA_ID").ok().filter(|v| !v.is_empty());
+                Some(Shared {
+                    pool,
+                    replica: replica
+                        .unwrap_or_else(|| format!("{host}-{}", &uuid::Uuid::new_v4().simple().to_string()[..8])),
+                    lease: Duration::from_secs(number("JOB_LEASE_SECS", 30).max(3)),
+                    poll: Duration::from_millis(number("JOB_POLL_MS", 1000).max(10)),
+                    queued: Notify::new(),
+                    running: Mutex::default(),
+                })
+            }
+            Some(other) => return Err(format!("JOB_QUEUE={other:?} must be memory or postgres")),
+        };
+        let retention = Duration::from_secs(number("JOB_RETENTION_SECS", 3600));
+        let dir = shared.is_none().then(job_dir);
+        if let Some(dir) = &dir {
+            std::fs::create_dir_all(dir).map_err(|e| format!("JOB_DIR {}: {e}", dir.display()))?;
+        }
+        let jobs = dir.as_deref().map(|dir| recovery::load(dir, retention)).unwrap_or_default();
+        let next_seq = jobs.values().map(|j| j.seq + 1).max().unwrap_or(0);
+        let worker_slots = number("JOB_WORKERS", 2).max(1) as usize;
+        Ok(Self {
+            jobs: RwLock::new(jobs),
+            workers: Arc::new(Semaphore::new(worker_slots)),
+            worker_slots,
+            next_seq: AtomicU64::new(next_seq),
+            max_queued: number("JOB_QUEUE_LIMIT", 100) as usize,
+            timeout: Duration::from_secs(number("JOB_TIMEOUT_SECS", 3600)),
+            retention,
+            max_attempts: number("JOB_MAX_ATTEMPTS", 3).max(1) as u32,
+            changes: broadcast::channel(256).0,
+            dir,
+            shared,
+        })
+    }
+
+    /// `memory` or `postgres`, this replica's worker slots, and its
+    /// replica ID in a shared queue.
+    pub fn describe(&self) -> (&'static str, usize, Option<&str>) {
+        let backend = if self.shared.is_some() { "postgres" } else { "memory" };
+        (backend, self.worker_slots, self.shared.as_ref().map(|s| s.replica.as_str()))
+    }
+
+    /// Writes `job` to `JOB_DIR`.
+    fn save(&self, job: &Job) {
+        let Some(dir) = &self.dir else { return };
+        let stored = Stored {
+            job: job.clone(),
+            tenant: job.tenant.clone(),
+            seq: job.seq,
+            request: job.request.clone(),
+            headers: job.headers.clone(),
+            checkpoint: job.checkpoint.clone(),
+        };
+        let path = dir.join(format!("{}.json", job.id));
+        let written = serde_json::to_vec(&stored).map_err(std::io::Error::other).and_then(|data| {
+            // Renamed into place, so a crash mid-write leaves the last state.
+            let partial = path.with_extension("json.partial");
+            std::fs::write(&partial, data).and_then(|()| std::fs::rename(&partial, &path))
+        });
+        if let Err(e) = written {
+            warn!(id = %job.id, "{}: saving job failed: {e}", path.display());
+        }
+    }
+
+    fn update(&self, id: &str, f: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.jobs.write().unwrap().get_mut(id) {
+            f(job);
+            self.save(job);
+        }
+        // No one following any job is not an error.
+        let _ = self.changes.send(id.to_string());
+    }
+
+    /// Like [`Self::update`] for progress, which is not worth saving.
+    fn progress(&self, id: &str, progress: Progress) {
+        if let Some(job) = self.jobs.write().unwrap().get_mut(id) {
+            job.progress = Some(progress);
+        }
+        let _ = self.changes.send(id.to_string());
+    }
+
+    /// A job of any tenant, with its current queue position.
+    async fn find(&self, id: &str, tenant: Option<&str>) -> Result<Option<Job>, (StatusCode, String)> {
+        if let Some(shared) = &self.shared {
+            return Ok(db::job(&shared.pool, id, tenant).await.map_err(unavailable)?.map(Job::from));
+        }
+        let jobs = self.jobs.read().unwrap();
+        let Some(mut job) = jobs.get(id).filter(|j| tenant.is_none_or(|t| j.tenant == t)).cloned() else {
+            return Ok(None);
+        };
+        if job.status == Status::Queued {
+            job.queue_position = Some(jobs.values().filter(|j| j.status == Status::Queued && j.seq < job.seq).count());
+        }
+        Ok(Some(job))
+    }
+
+    /// The caller's job.
+    async fn get(&self, headers: &HeaderMap, id: &str) -> Result<Job, (StatusCode, String)> {
+        self.find(id, Some(&tenant(headers))).await?.ok_or_else(|| (StatusCode::NOT_FOUND, format!("no job '{id}'")))
+    }
+}
+
+/// Runs the synchronous endpoint a job of `kind` stands for.
+async fn work(
+    state: Arc<AppState>,
+    kind: &str,
+    headers: HeaderMap,
+    request: Value,
+) -> Result<Value, (StatusCode, String)> {
+    let invalid = |e: serde_json::Error| (StatusCode::BAD_REQUEST, format!("invalid {kind} request: {e}"));
+    let response = match kind {
+        "subset" => {
+            let req: SubsetRequest = serde_json::from_value(request).map_err(invalid)?;
+            serde_json::to_value(crate::subset(State(state), headers, ApiJson(req)).await?.0)
+        }
+        "compress" => {
+            let req: CompressRequest = serde_json::from_value(request).map_err(invalid)?;
+            serde_json::to_value(crate::compress(State(state), headers, ApiJson(req)).await?.0)
+        }
+        "slices" => {
+            let req = serde_json::from_value(request).map_err(invalid)?;
+            serde_json::to_value(slices::slice(State(state), headers, ApiJson(req)).await?.0)
+        }
+        kind => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("unknown job kind '{kind}'"))),
+    };
+    response.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Queues `request` for the endpoint `kind` and answers with the new job.
+async fn enqueue(
+    state: Arc<AppState>,
+    headers: &HeaderMap,
+    kind: &'static str,
+    params: JobParams,
+    request: Value,
+) -> Result<(StatusCode, HeaderMap, Json<Job>), (StatusCode, String)> {
+    let queue = &state.queue;
+    if let Some(url) = &params.callback_url {
+        state.webhooks.validate(url)?;
+    }
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    let callback =
+        params.callback_url.map(|url| Callback { url, status: CallbackStatus::Pending, attempts: 0, error: None });
+    let full = || (StatusCode::SERVICE_UNAVAILABLE, "job queue is full; retry later".to_string());
+    if let Some(shared) = &queue.shared {
+        let callback = callback.map(|c| serde_json::to_value(c).unwrap_or_default());
+        let job = db::NewJob {
+            id: &id,
+            kind,
+            tenant: &tenant(headers),
+            request: &request,
+            headers: &forwarded(headers),
+            callback: callback.as_ref(),
+        };
+        if !db::insert_job(&shared.pool, &job, queue.max_queued).await.map_err(unavailable)? {
+            return Err(full());
+        }
+        shared.queued.notify_one();
+    } else {
+        {
+            let mut jobs = queue.jobs.write().unwrap();
+            let cutoff = now_unix().saturating_sub(queue.retention.as_secs());
+            jobs.retain(|id, j| {
+                let keep = j.finished_at_unix.is_none_or(|t| t > cutoff);
+                if let (false, Some(dir)) = (keep, &queue.dir) {
+                    let _ = std::fs::remove_file(dir.join(format!("{id}.json")));
+                }
+                keep
+            });
+            if jobs.values().filter(|j| j.status == Status::Queued).count() >= queue.max_queued {
+                return Err(full());
+            }
+            let job = Job {
+                id: id.clone(),
+                kind: kind.into(),
+                status: Status::Queued,
+                attempts: 0,
+                queue_position: None,
+                created_at_unix: now_unix(),
+                started_at_unix: None,
+                finished_at_unix: None,
+                progress: None,
+                download_url: None,
+                result: None,
+                error_status: None,
+                error: None,
+                callback,
+                tenant: tenant(headers),
+                seq: queue.next_seq.fetch_add(1, Ordering::Relaxed),
+                request,
+                headers: forwarded(headers),
+                checkpoint: None,
+                handle: None,
+            };
+            queue.save(&job);
+            jobs.insert(id.clone(), job);
+        }
+        start(&state, &id);
+    }
+
+    info!(id = %id, kind, "job queued");
+    let job = queue.get(headers, &id).await?;
+    let mut response_headers = HeaderMap::new();
+    if let Ok(location) = HeaderValue::from_str(&format!("/api/v1/jobs/{id}")) {
+        response_headers.insert(header::LOCATION, location);
+    }
+    Ok((StatusCode::ACCEPTED, response_headers, Json(job)))
+}
+
+/// Runs the queued job `id` of this replica once a worker is free.
+fn start(state: &Arc<AppState>, id: &str) {
+    let (job_state, job_id) = (Arc::clone(state), id.to_string());
+    let handle = tokio::spawn(async move {
+        let queue = &job_state.queue;
+        let permit = Arc::clone(&queue.workers).acquire_owned().await;
+        let mut job = None;
+        queue.update(&job_id, |j| {
+            j.status = Status::Running;
+            j.attempts += 1;
+            j.started_at_unix = Some(now_unix());
+            job = Some((j.kind.clone(), restore(&j.headers), j.request.clone(), j.checkpoint.clone()));
+        });
+        let Some((kind, headers, request, resumed)) = job else { return };
+        let progress_state = Arc::clone(&job_state);
+        let progress_id = job_id.clone();
+        let progress: cancel::Progress = Arc::new(move |step, percent| {
+            progress_state.queue.progress(&progress_id, Progress { step: step.into(), percent });
+        });
+        let (save_state, save_id) = (Arc::clone(&job_state), job_id.clone());
+        let save: cancel::Save = Arc::new(move |checkpoint| {
+            save_state.queue.update(&save_id, |j| j.checkpoint = Some(checkpoint));
+        });
+        let work = cancel::with_checkpoint(resumed, save, work(Arc::clone(&job_state), &kind, headers, request));
+        let outcome = cancel::with_progress(progress, cancel::with_timeout(queue.timeout, work)).await;
+        queue.update(&job_id, |j| {
+            j.finished_at_unix = Some(now_unix());
+            j.handle = None;
+            j.checkpoint = None;
+            match outcome {
+                Ok(result) => {
+                    j.download_url = result["download_url"].as_str().map(str::to_string);
+                    j.result = Some(result);
+                    j.progress = Some(Progress { step: "done".into(), percent: 100 });
+                    j.status = Status::Succeeded;
+                }
+                Err((status, message)) => {
+                    j.error_status = Some(status.as_u16());
+                    j.error = Some(message);
+                    j.status = Status::Failed;
+                }
+            }
+        });
+        info!(id = %job_id, "job finished");
+        drop(permit);
+        notify(&job_state, &job_id).await;
+    });
+    state.queue.update(id, |j| j.handle = Some(handle.abort_handle()));
+}
+
+/// Queues again the jobs a restart interrupted. With `JOB_QUEUE=postgres`,
+/// then claims shared jobs as this replica's workers free up, fails jobs
+/// that ran out of attempts and prunes old ones.
+pub async fn run(state: Arc<AppState>) {
+    let Some(shared) = &state.queue.shared else { return recovery::recover(&state) };
+    recovery::recover_shared(&state, shared).await;
+    let max_attempts = state.queue.max_attempts;
+    let mut next_prune = Instant::now();
+    loop {
+        let Ok(permit) = Arc::clone(&state.queue.workers).acquire_owned().await else { return };
+        match db::claim_job(&shared.pool, &shared.replica, shared.lease.as_secs(), max_attempts).await {
+            Ok(Some(row)) => {
+                tokio::spawn(execute(Arc::clone(&state), row, permit));
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => warn!("claiming a job failed: {e}"),
+        }
+        drop(permit);
+        match db::abandon_jobs(&shared.pool, max_attempts).await {
+            Ok(ids) => {
+                for id in ids {
+                    warn!(id = %id, attempts = max_attempts, "job abandoned");
+                    let state = Arc::clone(&state);
+                    tokio::spawn(async move { notify(&state, &id).await });
+                }
+            }
+            Err(e) => warn!("failing abandoned jobs failed: {e}"),
+        }
+        if Instant::now() >= next_prune {
+            if let Err(e) = db::prune_jobs(&shared.pool, state.queue.retention.as_secs()).await {
+                warn!("pruning finished jobs failed: {e}");
+            }
+            next_prune = Instant::now() + Duration::from_secs(60);
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(shared.poll) => {}
+            _ = shared.queued.notified() => {}
+        }
+    }
+}
+
+/// Runs a claimed shared job, renewing its lease until it ends. A job that
+/// was cancelled, or taken over after a lease lapsed, is stopped and left as
+/// it is.
+async fn execute(state: Arc<AppState>, row: db::JobRow, permit: OwnedSemaphorePermit) {
+    let Some(shared) = &state.queue.shared else { return };
+    let (id, kind, attempt, headers) = (row.id, row.kind, row.attempts, restore(&row.headers.0));
+    let (progress_state, progress_id) = (Arc::clone(&state), id.clone());
+    let progress: cancel::Progress = Arc::new(move |step, percent| {
+        let (state, id) = (Arc::clone(&progress_state), progress_id.clone());
+        tokio::spawn(async move {
+            let Some(shared) = &state.queue.shared else { return };
+            let progress = serde_json::to_value(Progress { step: step.into(), percent }).unwrap_or_default();
+            if let Err(e) = db::job_progress(&shared.pool, &id, &shared.replica, &progress).await {
+                warn!(id = %id, "recording job progress failed: {e}");
+            }
+        });
+    });
+    let (save_state, save_id) = (Arc::clone(&state), id.clone());
+    let save: cancel::Save = Arc::new(move |checkpoint| {
+        let (state, id) = (Arc::clone(&save_state), save_id.clone());
+        tokio::spawn(async move {
+            let Some(shared) = &state.queue.shared else { return };
+            if let Err(e) = db::job_checkpoint(&shared.pool, &id, &shared.replica, &checkpoint).await {
+                warn!(id = %id, "saving job checkpoint failed: {e}");
+            }
+        });
+    });
+    let (work_state, request, resumed) = (Arc::clone(&state), row.request.0, row.checkpoint.map(|DbJson(c)| c));
+    let work = cancel::with_checkpoint(resumed, save, async move { work(work_state, &kind, headers, request).await });
+    let mut task = tokio::spawn(cancel::with_progress(progress, cancel::with_timeout(state.queue.timeout, work)));
+    shared.running.lock().unwrap().insert(id.clone(), task.abort_handle());
+    info!(id = %id, attempt, replica = %shared.replica, "job started");
+
+    let mut heartbeat = tokio::time::interval(shared.lease / 3);
+    heartbeat.tick().await;
+    let lease_secs = shared.lease.as_secs();
+    let outcome = loop {
+        tokio::select! {
+            finished = &mut task => break finished.ok(),
+            _ = heartbeat.tick() => match db::renew_job(&shared.pool, &id, &shared.replica, lease_secs).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    task.abort();
+                    break None;
+                }
+                // The lease may still be renewed before it lapses.
+                Err(e) => warn!(id = %id, "renewing job lease failed: {e}"),
+            },
+        }
+    };
+    shared.running.lock().unwrap().remove(&id);
+    drop(permit);
+    let Some(outcome) = outcome else {
+        info!(id = %id, "job stopped; cancelled or taken over");
+        return;
+    };
+    let done = serde_json::to_value(Progress { step: "done".into(), percent: 100 }).ok();
+    let result = outcome.as_ref().ok();
+    let finished = db::JobOutcome {
+        status: if result.is_some() { Status::Succeeded } else { Status::Failed }.as_str(),
+        progress: result.and(done.as_ref()),
+        result,
+        download_url: result.and_then(|r| r["download_url"].as_str()),
+        error: outcome.as_ref().err().map(|(status, message)| (status.as_u16(), message.as_str())),
+    };
+    match db::finish_job(&shared.pool, &id, &shared.replica, &finished).await {
+        Ok(true) => {
+            info!(id = %id, "job finished");
+            notify(&state, &id).await;
+        }
+        Ok(false) => info!(id = %id, "job finished after it was cancelled or taken over"),
+        Err(e) => warn!(id = %id, "recording job outcome failed: {e}"),
+    }
+}
+
+/// Sends the finished job to its callback URL, if it has one.
+async fn notify(state: &AppState, id: &str) {
+    let job = state.queue.find(id, None).await.ok().flatten();
+    let Some(mut job) = job.filter(|j| matches!(j.status, Status::Succeeded | Status::Failed)) else {
+        return;
+    };
+    let Some(url) = job.callback.take().map(|c| c.url) else {
+        return;
+    };
+    let event = if job.status == Status::Succeeded { "job.succeeded" } else { "job.failed" };
+    let event = Event { event, job: &job };
+    let body = serde_json::to_vec(&event).unwrap_or_default();
+    let webhook::Delivery { attempts, error } = state.webhooks.deliver(&url, id, body).await;
+    let status = if error.is_none() { CallbackStatus::Delivered } else { CallbackStatus::Failed };
+    let callback = Callback { url, status, attempts, error };
+    match &state.queue.shared {
+        Some(shared) => {
+            let callback = serde_json::to_value(callback).unwrap_or_default();
+            if let Err(e) = db::set_job_callback(&shared.pool, id, &callback).await {
+                warn!(id = %id, "recording job callback failed: {e}");
+            }
+        }
+        None => state.queue.update(id, |j| j.callback = Some(callback)),
+    }
+}
+
+pub async fn subset(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<JobParams>,
+    ApiJson(req): ApiJson<SubsetRequest>,
+) -> Result<(StatusCode, HeaderMap, Json<Job>), (StatusCode, String)> {
+    let request = serde_json::to_value(req).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    enqueue(state, &headers, "subset", params, request).await
+}
+
+pub async fn compress(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<JobParams>,
+    ApiJson(req): ApiJson<CompressRequest>,
+) -> Result<(StatusCode, HeaderMap, Json<Job>), (StatusCode, String)> {
+    let request = serde_json::to_value(req).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    enqueue(state, &headers, "compress", params, request).await
+}
+
+pub async fn slices(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<JobParams>,
+    ApiJson(req): ApiJson<SliceRequest>,
+) -> Result<(StatusCode, HeaderMap, Json<Job>), (StatusCode, String)> {
+    let request = serde_json::to_value(req).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    enqueue(state, &headers, "slices", params, request).await
+}
+
+pub async fn show(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, (StatusCode, String)> {
+    state.queue.get(&headers, &id).await.map(Json)
+}
+
+/// The job as Server-Sent Events, until it has finished.
+pub async fn events(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<sse::Event, axum::Error>>>, (StatusCode, String)> {
+    // Subscribed first, so no change between the two is missed.
+    let changes = state.queue.changes.subscribe();
+    let job = state.queue.get(&headers, &id).await?;
+    // The pending event, if any, the changes to wait on and the last event
+    // sent; none once finished.
+    let events = stream::unfold(Some((Some(job), changes, String::new())), move |following| {
+        let (state, headers, id) = (Arc::clone(&state), headers.clone(), id.clone());
+        async move {
+            let (next, mut changes, last) = following?;
+            let job = match (next, &state.queue.shared) {
+                (Some(job), _) => job,
+                // Other replicas' changes are only seen in the database.
+                (None, Some(shared)) => loop {
+                    tokio::time::sleep(shared.poll).await;
+                    let job = state.queue.get(&headers, &id).await.ok()?;
+                    if serde_json::to_string(&job).is_ok_and(|json| json != last) {
+                        break job;
+                    }
+                },
+                (None, None) => loop {
+                    match changes.recv().await {
+                        Ok(changed) if changed != id => continue,
+                        // Having missed changes, the current state covers them.
+                        Ok(_) | Err(RecvError::Lagged(_)) => break state.queue.get(&headers, &id).await.ok()?,
+                        Err(RecvError::Closed) => return None,
+                    }
+                },
+            };
+            let finished = !matches!(job.status, Status::Queued | Status::Running);
+            let json = serde_json::to_string(&job).unwrap_or_default();
+            let event = sse::Event::default().event("job").json_data(&job);
+            Some((event, (!finished).then_some((None, changes, json))))
+        }
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Cancels a queued or running job; finished jobs are left as they are.
+pub async fn cancel(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, (StatusCode, String)> {
+    let queue = &state.queue;
+    queue.get(&headers, &id).await?;
+    match &queue.shared {
+        Some(shared) => {
+            if db::cancel_job(&shared.pool, &id, &tenant(&headers)).await.map_err(unavailable)? {
+                // Elsewhere, the replica running it stops at its next lease renewal.
+                if let Some(handle) = shared.running.lock().unwrap().remove(&id) {
+                    handle.abort();
+                }
+                info!(id = %id, "job cancelled");
+            }
+        }
+        None => queue.update(&id, |j| {
+            if matches!(j.status, Status::Queued | Status::Running) {
+                if let Some(handle) = j.handle.take() {
+                    handle.abort();
+                }
+                j.status = Status::Cancelled;
+                j.finished_at_unix = Some(now_unix());
+                info!(id = %id, "job cancelled");
+            }
+        }),
+    }
+    queue.get(&headers, &id).await.map(Json)
+}
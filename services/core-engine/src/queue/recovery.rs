@@ -0,0 +1,156 @@
+//! Restart recovery: jobs a deploy or crash interrupted are queued again.
+//!
+//! In memory mode every change to a job is written to `JOB_DIR`; at startup
+//! [`load`] reads them back and [`recover`] queues the ones that were queued
+//! or running, in their original order. With `JOB_QUEUE=postgres` the rows
+//! are already durable and [`recover_shared`] takes back this replica's own.
+//! Running a job again is safe: its output is stored under a content
+//! address, so a second run writes the same artifact. A job that saved a
+//! checkpoint gets it back and skips the steps it records. A job interrupted
+//! `JOB_MAX_ATTEMPTS` times fails instead, and its callback fires.
+
+use super::*;
+
+/// Jobs written to `dir` by an earlier run, less those past `retention`.
+pub(super) fn load(dir: &std::path::Path, retention: Duration) -> BTreeMap<String, Job> {
+    let Ok(files) = std::fs::read_dir(dir) else { return BTreeMap::new() };
+    let cutoff = now_unix().saturating_sub(retention.as_secs());
+    let mut jobs = BTreeMap::new();
+    for path in files.flatten().map(|f| f.path()).filter(|p| p.extension().is_some_and(|e| e == "json")) {
+        let stored = std::fs::read(&path).map_err(|e| e.to_string());
+        match stored.and_then(|data| serde_json::from_slice::<Stored>(&data).map_err(|e| e.to_string())) {
+            Ok(stored) if stored.job.finished_at_unix.is_none_or(|t| t > cutoff) => {
+                let Stored { mut job, tenant, seq, request, headers, checkpoint } = stored;
+                (job.tenant, job.seq, job.request, job.headers) = (tenant, seq, request, headers);
+                job.checkpoint = checkpoint;
+                jobs.insert(job.id.clone(), job);
+            }
+            Ok(_) => {
+                let _ = std::fs::remove_file(&path);
+            }
+            Err(e) => warn!("{}: unreadable job: {e}", path.display()),
+        }
+    }
+    jobs
+}
+
+/// Fails an interrupted job that ran `max_attempts` times; `false` for jobs
+/// that may run again.
+fn give_up(job: &mut Job, max_attempts: u32) -> bool {
+    if job.attempts < max_attempts {
+        return false;
+    }
+    job.status = Status::Failed;
+    job.finished_at_unix = Some(now_unix());
+    job.error_status = Some(500);
+    job.error = Some(format!("interrupted {} time(s); not retried", job.attempts));
+    true
+}
+
+/// Queues again the jobs a restart interrupted, in their original order.
+pub(super) fn recover(state: &Arc<AppState>) {
+    let queue = &state.queue;
+    let mut interrupted: Vec<(u64, String)> = queue
+        .jobs
+        .read()
+        .unwrap()
+        .values()
+        .filter(|j| matches!(j.status, Status::Queued | Status::Running))
+        .map(|j| (j.seq, j.id.clone()))
+        .collect();
+    interrupted.sort();
+    for (_, id) in interrupted {
+        let mut failed = false;
+        queue.update(&id, |j| {
+            failed = give_up(j, queue.max_attempts);
+            if !failed {
+                j.status = Status::Queued;
+            }
+        });
+        if failed {
+            warn!(id = %id, "job interrupted too often; failed");
+            let state = Arc::clone(state);
+            tokio::spawn(async move { notify(&state, &id).await });
+        } else {
+            info!(id = %id, "job queued again after a restart");
+            start(state, &id);
+        }
+    }
+}
+
+/// Queues again the shared jobs this replica was running when it stopped,
+/// failing those that ran out of attempts.
+pub(super) async fn recover_shared(state: &Arc<AppState>, shared: &Shared) {
+    match db::recover_jobs(&shared.pool, &shared.replica, state.queue.max_attempts).await {
+        Ok(recovered) => {
+            for (id, status) in recovered {
+                if status == Status::Failed.as_str() {
+                    warn!(id = %id, "job interrupted too often; failed");
+                    let state = Arc::clone(state);
+                    tokio::spawn(async move { notify(&state, &id).await });
+                } else {
+                    info!(id = %id, "job queued again after a restart");
+                }
+            }
+        }
+        Err(e) => warn!("recovering this replica's jobs failed: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(id: &str, status: &str, attempts: u32, finished_at_unix: Option<u64>) -> Value {
+        serde_json::json!({
+            "id": id,
+            "kind": "subset",
+            "status": status,
+            "attempts": attempts,
+            "created_at_unix": 1,
+            "finished_at_unix": finished_at_unix,
+            "tenant": "acme",
+            "seq": attempts,
+            "request": {"font_id": "inter"},
+            "headers": {"x-font-tenant": "acme"},
+        })
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let mut running: Job = serde_json::from_value(job("a", "running", 2, None)).unwrap();
+        assert!(!give_up(&mut running, 3));
+        assert_eq!(running.status, Status::Running);
+        running.attempts = 3;
+        assert!(give_up(&mut running, 3));
+        assert_eq!((running.status, running.error_status), (Status::Failed, Some(500)));
+        assert_eq!(running.error.as_deref(), Some("interrupted 3 time(s); not retried"));
+        assert!(running.finished_at_unix.is_some());
+    }
+
+    #[test]
+    fn loads_jobs_written_before_a_restart() {
+        let dir = std::env::temp_dir().join(format!("recovery-test-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, body: &[u8]| std::fs::write(dir.join(name), body).unwrap();
+        let mut running = job("running", "running", 1, None);
+        running["checkpoint"] = serde_json::json!({"slices": [1, 2]});
+        write("running.json", running.to_string().as_bytes());
+        write("recent.json", job("recent", "succeeded", 1, Some(now_unix())).to_string().as_bytes());
+        write("expired.json", job("expired", "failed", 1, Some(1)).to_string().as_bytes());
+        write("torn.json", b"{\"id\": \"tor");
+        write("notes.txt", b"not a job");
+
+        let jobs = load(&dir, Duration::from_secs(3600));
+        assert_eq!(jobs.keys().collect::<Vec<_>>(), ["recent", "running"]);
+        let running = &jobs["running"];
+        assert_eq!((running.status, running.tenant.as_str(), running.seq), (Status::Running, "acme", 1));
+        assert_eq!(running.request["font_id"], "inter");
+        assert_eq!(restore(&running.headers)["x-font-tenant"], "acme");
+        assert_eq!(running.checkpoint, Some(serde_json::json!({"slices": [1, 2]})));
+        assert_eq!(jobs["recent"].checkpoint, None);
+        assert!(!dir.join("expired.json").exists(), "expired jobs are removed");
+        assert!(dir.join("torn.json").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,306 @@
+//! Per-tenant monthly quotas and the billing export.
+//!
+//! Each tenant's CDN bandwidth and transform operations are counted per UTC
+//! month. Bandwidth is billed to the tenant owning the font (see
+//! [`tenants`]), or for shared fonts to the request's `X-Font-Tenant`.
+//! Operations are compress and subset requests of any kind (batch items,
+//! jobs, slices, slim, merged and progressive subsets, rename, recolor),
+//! counted as they arrive, like rate limits. Requests without a tenant are
+//! neither counted nor limited.
+//!
+//! Limits are set per tenant with `PUT /api/v1/admin/quotas/:tenant`. Past
+//! its bandwidth a tenant's downloads get `402 Payment Required`, and past
+//! its operations its transform requests get `429`, until the month ends.
+//! Counters are added to the `tenant_usage` table every
+//! `ANALYTICS_FLUSH_SECS` when `DATABASE_URL` is set, after which each
+//! replica reloads the month's totals, so limits hold across replicas to
+//! within one flush. `GET /api/v1/admin/usage/export?month=2025-06` reports
+//! a month per tenant as JSON or, with `format=csv`, CSV for billing.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use font_api::{TenantQuota, TenantUsage};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    ops::AddAssign,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+use crate::{audit, db, extract::ApiJson, storage, tenants, AppState};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Counters {
+    pub downloads: u64,
+    pub bandwidth_bytes: u64,
+    pub operations: u64,
+}
+
+impl AddAssign for Counters {
+    fn add_assign(&mut self, other: Self) {
+        self.downloads += other.downloads;
+        self.bandwidth_bytes += other.bandwidth_bytes;
+        self.operations += other.operations;
+    }
+}
+
+/// The current month, `YYYY-MM` (UTC).
+fn month() -> String {
+    let today = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) / 86_400;
+    let (year, month, _) = storage::civil_date(today);
+    format!("{year:04}-{month:02}")
+}
+
+#[derive(Default)]
+pub struct Quotas {
+    limits: RwLock<BTreeMap<String, TenantQuota>>,
+    /// Usage by tenant and month as this replica knows it: the stored
+    /// totals at the last flush plus everything counted since.
+    used: Mutex<BTreeMap<(String, String), Counters>>,
+    /// Counted since the last flush.
+    pending: Mutex<BTreeMap<(String, String), Counters>>,
+}
+
+impl Quotas {
+    pub fn load(limits: Vec<(String, TenantQuota)>) -> Self {
+        Self { limits: RwLock::new(limits.into_iter().collect()), ..Self::default() }
+    }
+
+    fn add(&self, tenant: &str, counters: Counters) {
+        let key = (tenant.to_string(), month());
+        *self.used.lock().unwrap().entry(key.clone()).or_default() += counters;
+        *self.pending.lock().unwrap().entry(key).or_default() += counters;
+    }
+
+    fn used(&self, tenant: &str) -> Counters {
+        self.used.lock().unwrap().get(&(tenant.to_string(), month())).copied().unwrap_or_default()
+    }
+
+    fn limit(&self, tenant: &str) -> TenantQuota {
+        self.limits.read().unwrap().get(tenant).cloned().unwrap_or_default()
+    }
+
+    /// Counts one transform operation for the calling tenant, or answers
+    /// `429` when its operations for the month are used up.
+    pub fn charge_operation(&self, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+        let tenant = tenants::of(headers);
+        if tenant.is_empty() {
+            return Ok(());
+        }
+        if let Some(limit) = self.limit(tenant).operations {
+            if self.used(tenant).operations >= limit {
+                let message = format!("tenant '{tenant}' has used its {limit} transform operations for {}", month());
+                return Err((StatusCode::TOO_MANY_REQUESTS, message));
+            }
+        }
+        self.add(tenant, Counters { operations: 1, ..Counters::default() });
+        Ok(())
+    }
+
+    /// `402` when `tenant` has used up its bandwidth for the month.
+    pub fn ensure_bandwidth(&self, tenant: &str) -> Result<(), (StatusCode, String)> {
+        let Some(limit) = self.limit(tenant).bandwidth_bytes.filter(|_| !tenant.is_empty()) else { return Ok(()) };
+        if self.used(tenant).bandwidth_bytes >= limit {
+            let message = format!("tenant '{tenant}' has used its {limit} bytes of bandwidth for {}", month());
+            return Err((StatusCode::PAYMENT_REQUIRED, message));
+        }
+        Ok(())
+    }
+
+    pub fn record_download(&self, tenant: &str, bytes: u64) {
+        if !tenant.is_empty() {
+            self.add(tenant, Counters { downloads: 1, bandwidth_bytes: bytes, ..Counters::default() });
+        }
+    }
+
+    /// Moves pending counters into the database (kept for the next attempt
+    /// on failure), then reloads the month's totals so other replicas'
+    /// usage counts against the limits too.
+    async fn flush(&self, pool: &sqlx::PgPool) {
+        let rows: Vec<_> = std::mem::take(&mut *self.pending.lock().unwrap()).into_iter().collect();
+        if let Err(e) = db::add_tenant_usage(pool, &rows).await {
+            warn!(rows = rows.len(), "tenant usage flush failed: {e}");
+            let mut pending = self.pending.lock().unwrap();
+            for (key, counters) in rows {
+                *pending.entry(key).or_default() += counters;
+            }
+            return;
+        }
+        let month = month();
+        let stored = match db::tenant_usage(pool, &month).await {
+            Ok(stored) => stored,
+            Err(e) => return warn!("reading tenant usage failed: {e}"),
+        };
+        let pending = self.pending.lock().unwrap();
+        let mut used = self.used.lock().unwrap();
+        used.retain(|(_, m), _| *m == month);
+        for (tenant, counters) in stored {
+            let key = (tenant, month.clone());
+            let mut total = counters;
+            total += pending.get(&key).copied().unwrap_or_default();
+            used.insert(key, total);
+        }
+    }
+
+    /// Every tenant's usage in `month`, with the tenants that have a quota.
+    async fn report(&self, pool: Option<&sqlx::PgPool>, month: &str) -> Result<Vec<TenantUsage>, (StatusCode, String)> {
+        let mut totals: BTreeMap<String, Counters> = BTreeMap::new();
+        let counted = match pool {
+            Some(pool) => {
+                let stored = db::tenant_usage(pool, month)
+                    .await
+                    .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("reading tenant usage failed: {e}")))?;
+                totals.extend(stored);
+                &self.pending
+            }
+            None => &self.used,
+        };
+        for ((tenant, m), counters) in counted.lock().unwrap().iter() {
+            if m == month {
+                *totals.entry(tenant.clone()).or_default() += *counters;
+            }
+        }
+        let limits = self.limits.read().unwrap();
+        for tenant in limits.keys() {
+            totals.entry(tenant.clone()).or_default();
+        }
+        Ok(totals
+            .into_iter()
+            .map(|(tenant, counters)| {
+                let quota = limits.get(&tenant).cloned().unwrap_or_default();
+                TenantUsage {
+                    tenant,
+                    month: month.to_string(),
+                    downloads: counters.downloads,
+                    bandwidth_bytes: counters.bandwidth_bytes,
+                    operations: counters.operations,
+                    bandwidth_quota: quota.bandwidth_bytes,
+                    operations_quota: quota.operations,
+                }
+            })
+            .collect())
+    }
+}
+
+/// Loads stored usage, then flushes to the database until the process exits.
+pub async fn run(state: Arc<AppState>) {
+    let Some(pool) = state.db.clone() else { return };
+    let secs = std::env::var("ANALYTICS_FLUSH_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(60u64);
+    let mut tick = tokio::time::interval(Duration::from_secs(secs.max(1)));
+    loop {
+        tick.tick().await;
+        state.quotas.flush(&pool).await;
+    }
+}
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<BTreeMap<String, TenantQuota>>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    Ok(Json(state.quotas.limits.read().unwrap().clone()))
+}
+
+/// Sets a tenant's limits; an empty object removes them.
+pub async fn update(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(tenant): Path<String>,
+    ApiJson(quota): ApiJson<TenantQuota>,
+) -> Result<Json<BTreeMap<String, TenantQuota>>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    if tenant.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "tenant is required".to_string()));
+    }
+    tenants::validate(&tenant).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let unlimited = quota == TenantQuota::default();
+    if let Some(pool) = &state.db {
+        let saved = if unlimited {
+            db::delete_tenant_quota(pool, &tenant).await
+        } else {
+            db::save_tenant_quota(pool, &tenant, &quota).await
+        };
+        saved.map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("quota persist failed: {e}")))?;
+    }
+    let (before, limits) = {
+        let mut limits = state.quotas.limits.write().unwrap();
+        let before = if unlimited { limits.remove(&tenant) } else { limits.insert(tenant.clone(), quota.clone()) };
+        (before, limits.clone())
+    };
+    let after = (!unlimited).then_some(&quota);
+    audit::record(&state, &headers, "quota.update", &tenant, json!(before), json!(after)).await;
+    info!(tenant = %tenant, bandwidth_bytes = ?quota.bandwidth_bytes, operations = ?quota.operations, "quota updated");
+    Ok(Json(limits))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    /// `YYYY-MM`; the current month when absent.
+    month: Option<String>,
+    /// `json` (default) or `csv`.
+    format: Option<String>,
+}
+
+fn valid_month(month: &str) -> bool {
+    let Some((year, number)) = month.split_once('-') else { return false };
+    year.len() == 4
+        && year.bytes().all(|b| b.is_ascii_digit())
+        && number.len() == 2
+        && number.parse::<u8>().is_ok_and(|n| (1..=12).contains(&n))
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv(rows: &[TenantUsage]) -> String {
+    let optional = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_default();
+    let mut out = "tenant,month,downloads,bandwidth_bytes,operations,bandwidth_quota,operations_quota\n".to_string();
+    for row in rows {
+        out.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            csv_field(&row.tenant),
+            row.month,
+            row.downloads,
+            row.bandwidth_bytes,
+            row.operations,
+            optional(row.bandwidth_quota),
+            optional(row.operations_quota),
+        ));
+    }
+    out
+}
+
+pub async fn export(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let month = query.month.unwrap_or_else(month);
+    if !valid_month(&month) {
+        return Err((StatusCode::BAD_REQUEST, format!("month '{month}' should look like 2025-06")));
+    }
+    let rows = state.quotas.report(state.db.as_ref(), &month).await?;
+    match query.format.as_deref().unwrap_or("json") {
+        "json" => Ok(Json(rows).into_response()),
+        "csv" => {
+            let headers = [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                (header::CONTENT_DISPOSITION, format!("attachment; filename=\"usage-{month}.csv\"")),
+            ];
+            Ok((headers, csv(&rows)).into_response())
+        }
+        other => Err((StatusCode::BAD_REQUEST, format!("unknown format '{other}'; valid: json, csv"))),
+    }
+}
@@ -0,0 +1,154 @@
+//! Per-client rate limiting.
+//!
+//! With `RATE_LIMIT_PER_MINUTE` set, each client gets a token bucket of
+//! `RATE_LIMIT_BURST` requests (default: one minute's worth) that refills at
+//! that rate; an empty bucket answers `429` with `Retry-After`. Clients are
+//! told apart by API key when the request carried a valid one (see
+//! [`auth`]), otherwise by peer address, or by the first `X-Forwarded-For`
+//! hop with `RATE_LIMIT_TRUST_FORWARDED=true` behind a proxy. Health,
+//! metrics and `/cdn/fonts/` (pulled by edges) are not limited. A reload
+//! (see [`crate::reload`]) changes the limits without touching buckets.
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex, RwLock},
+    time::Instant,
+};
+use tracing::warn;
+
+use crate::{auth, AppState};
+
+const EXEMPT: &[&str] = &[
+    "/health",
+    "/readyz",
+    "/healthz/live",
+    "/healthz/ready",
+    "/metrics",
+    "/cdn/fonts/:slug/:file",
+    "/cdn/fonts/:slug/:version/:file",
+    "/cdn/:tenant/fonts/:slug/:file",
+    "/cdn/:tenant/fonts/:slug/:version/:file",
+];
+
+/// Idle (full) buckets are dropped once this many clients are tracked.
+const MAX_CLIENTS: usize = 100_000;
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Limit {
+    burst: f64,
+    per_second: f64,
+}
+
+impl Limit {
+    /// Whether `bucket` has refilled completely, i.e. its client is idle.
+    fn refilled(&self, bucket: &TokenBucket, now: Instant) -> bool {
+        bucket.tokens + now.duration_since(bucket.last_refill).as_secs_f64() * self.per_second >= self.burst
+    }
+
+    /// Refills `bucket`, then takes a token or returns the seconds until one
+    /// is available.
+    fn take(&self, bucket: &mut TokenBucket, now: Instant) -> Result<(), f64> {
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.burst);
+        bucket.last_refill = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err((1.0 - bucket.tokens) / self.per_second)
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Settings {
+    /// Disabled when unset.
+    limit: Option<Limit>,
+    trust_forwarded: bool,
+}
+
+impl Settings {
+    fn new(per_minute: Option<f64>, burst: Option<f64>, trust_forwarded: bool) -> Self {
+        let limit = per_minute.filter(|n| *n > 0.0).map(|per_minute| Limit {
+            burst: burst.filter(|n| *n > 0.0).unwrap_or(per_minute).max(1.0),
+            per_second: per_minute / 60.0,
+        });
+        Self { limit, trust_forwarded }
+    }
+}
+
+#[derive(Default)]
+pub struct RateLimits {
+    settings: RwLock<Settings>,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimits {
+    pub fn from_env() -> Self {
+        let number = |k: &str| std::env::var(k).ok().and_then(|v| v.parse::<f64>().ok());
+        let trust_forwarded = std::env::var("RATE_LIMIT_TRUST_FORWARDED").is_ok_and(|v| v == "true" || v == "1");
+        let settings = Settings::new(number("RATE_LIMIT_PER_MINUTE"), number("RATE_LIMIT_BURST"), trust_forwarded);
+        Self { settings: RwLock::new(settings), buckets: Mutex::default() }
+    }
+
+    pub fn configure(&self, per_minute: Option<f64>, burst: Option<f64>, trust_forwarded: bool) {
+        *self.settings.write().unwrap() = Settings::new(per_minute, burst, trust_forwarded);
+    }
+
+    fn client(&self, request: &Request, trust_forwarded: bool) -> String {
+        if let Some(auth::KeyId(id)) = request.extensions().get::<auth::KeyId>() {
+            return format!("key:{id}");
+        }
+        let forwarded = trust_forwarded
+            .then(|| request.headers().get("x-forwarded-for")?.to_str().ok()?.split(',').next().map(str::trim))
+            .flatten()
+            .filter(|ip| !ip.is_empty());
+        let peer = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|c| c.0.ip().to_string());
+        format!("ip:{}", forwarded.map(str::to_string).or(peer).unwrap_or_default())
+    }
+}
+
+pub async fn limit(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let limits = &state.rate_limits;
+    let settings = *limits.settings.read().unwrap();
+    let Some(limit) = settings.limit else {
+        return next.run(request).await;
+    };
+    if request.extensions().get::<MatchedPath>().is_some_and(|p| EXEMPT.contains(&p.as_str())) {
+        return next.run(request).await;
+    }
+    let client = limits.client(&request, settings.trust_forwarded);
+    let now = Instant::now();
+    let taken = {
+        let mut buckets = limits.buckets.lock().unwrap();
+        if buckets.len() >= MAX_CLIENTS {
+            buckets.retain(|_, b| !limit.refilled(b, now));
+        }
+        let bucket = buckets.entry(client.clone()).or_insert(TokenBucket { tokens: limit.burst, last_refill: now });
+        limit.take(bucket, now)
+    };
+    match taken {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            warn!(client = %client, "rate limit exceeded");
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, (wait.ceil() as u64).max(1).to_string())],
+                "rate limit exceeded".to_string(),
+            )
+                .into_response()
+        }
+    }
+}
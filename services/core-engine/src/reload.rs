@@ -0,0 +1,104 @@
+//! Reloading the catalog and configuration without a restart.
+//!
+//! `POST /api/v1/admin/reload` (admin), or `SIGHUP`, reads the catalog
+//! again — from the database when `DATABASE_URL` is set, so entries written
+//! by other replicas or straight into the table appear, and in any case with
+//! the versions stored in `CATALOG_FONT_DIR` (see [`crate::versions`]) — and
+//! swaps it in whole. The configuration file and environment are read and
+//! validated again too (see [`crate::config`]): changed CORS origins and
+//! rate limits apply at once, while changes to anything else (addresses,
+//! storage, workers, TLS) are reported as needing a restart. An invalid file
+//! changes nothing. Requests in flight finish with what they started with.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::ReloadReport;
+use std::{collections::BTreeSet, sync::Arc};
+use tracing::{error, info};
+
+use crate::{config, cors, db, versions, AppState};
+
+/// Settings applied while running; see [`apply`].
+const LIVE: &[&str] = &["CORS_ORIGINS", "RATE_LIMIT_PER_MINUTE", "RATE_LIMIT_BURST", "RATE_LIMIT_TRUST_FORWARDED"];
+
+fn apply(state: &AppState, config: &config::EngineConfig, changed: &[&str]) {
+    if changed.contains(&"CORS_ORIGINS") {
+        // `load` has validated them.
+        let origins = match &config.cors.origins {
+            Some(origins) => cors::normalized(origins).unwrap_or_default(),
+            None => vec!["*".to_string()],
+        };
+        state.cors.set_global(origins);
+    }
+    if changed.iter().any(|name| name.starts_with("RATE_LIMIT_")) {
+        let limit = &config.rate_limit;
+        state.rate_limits.configure(limit.per_minute, limit.burst, limit.trust_forwarded.unwrap_or(false));
+    }
+}
+
+pub async fn reload(state: &AppState) -> Result<ReloadReport, (StatusCode, String)> {
+    let mut config = config::EngineConfig::load().map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.join("; ")))?;
+    let stored = match &state.db {
+        Some(pool) => Some(
+            db::load_catalog(pool)
+                .await
+                .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("reading catalog failed: {e}")))?,
+        ),
+        None => None,
+    };
+
+    let changed = config.changes();
+    apply(state, &config, &changed);
+    let (applied, restart_required): (Vec<&str>, Vec<&str>) = changed.into_iter().partition(|n| LIVE.contains(n));
+
+    let ids = |entries: &[font_api::FontCatalogEntry]| entries.iter().map(|e| e.id.clone()).collect::<BTreeSet<_>>();
+    let (old_ids, new_ids) = {
+        let mut catalog = state.catalog.write().unwrap();
+        let old_ids = ids(&catalog);
+        // Without a database the catalog in memory is the source.
+        *catalog = versions::restore(stored.unwrap_or_else(|| catalog.clone()));
+        (old_ids, ids(&catalog))
+    };
+    let report = ReloadReport {
+        catalog_entries: new_ids.len(),
+        added: new_ids.difference(&old_ids).cloned().collect(),
+        removed: old_ids.difference(&new_ids).cloned().collect(),
+        applied: applied.into_iter().map(String::from).collect(),
+        restart_required: restart_required.into_iter().map(String::from).collect(),
+    };
+    info!(
+        entries = report.catalog_entries,
+        added = ?report.added,
+        removed = ?report.removed,
+        applied = ?report.applied,
+        restart_required = ?report.restart_required,
+        "catalog and configuration reloaded"
+    );
+    Ok(report)
+}
+
+pub async fn handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<ReloadReport>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    reload(&state).await.map(Json)
+}
+
+/// Reloads on every `SIGHUP` until the process exits.
+#[cfg(unix)]
+pub async fn on_hangup(state: Arc<AppState>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => return error!("cannot listen for SIGHUP: {e}"),
+    };
+    while hangups.recv().await.is_some() {
+        if let Err((_, e)) = reload(&state).await {
+            error!("reload failed: {e}");
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! `POST /api/v1/font/rename`: rewrites a font's names for white-labelling.
+//!
+//! Family, subfamily, full and PostScript names (name IDs 1, 2, 4 and 6, and
+//! the typographic 16/17 when the font has them) are replaced in every
+//! platform and language; a changed family or subfamily also derives the full
+//! and PostScript names unless they are given. The renamed font is stored as
+//! an artifact under the new family. With `catalog_id` (admin only, catalog
+//! fonts only) it is also written to `CATALOG_FONT_DIR` and registered as a
+//! catalog entry copying the source entry's license and settings. A CFF
+//! font's internal name is left as it was.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{FontCatalogEntry, RenameRequest, RenameResponse};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+use tracing::info;
+
+use crate::{
+    cancel, catalog, collision, compress, duplicates,
+    extract::ApiJson,
+    name::NameTable,
+    sfnt::Font,
+    signing, AppState,
+};
+
+const FAMILY: u16 = 1;
+const SUBFAMILY: u16 = 2;
+const FULL_NAME: u16 = 4;
+const POSTSCRIPT_NAME: u16 = 6;
+const TYPOGRAPHIC_FAMILY: u16 = 16;
+const TYPOGRAPHIC_SUBFAMILY: u16 = 17;
+
+/// Stored with a renamed font (see [`artifacts::ArtifactStore`]).
+#[derive(Serialize, Deserialize)]
+struct RenameRecord {
+    output_bytes: usize,
+    names: BTreeMap<String, String>,
+}
+
+fn bad_request(message: impl Into<String>) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message.into())
+}
+
+/// Printable ASCII without the PostScript delimiters, at most 63 characters.
+fn valid_postscript(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 63
+        && name.bytes().all(|b| (33..=126).contains(&b) && !b"[](){}<>/%".contains(&b))
+}
+
+fn postscript_part(text: &str) -> String {
+    text.chars().filter(|c| c.is_ascii_graphic() && !"[](){}<>/%".contains(*c)).collect()
+}
+
+/// Applies the request to `font`'s name table; returns the resulting names.
+pub fn apply(font: &mut Font, req: &RenameRequest) -> Result<BTreeMap<String, String>, String> {
+    let mut names = NameTable::parse(font.table(b"name").ok_or("font has no name table")?)?;
+    let typographic = names.get(TYPOGRAPHIC_FAMILY).is_some();
+    let old_family = names.get(TYPOGRAPHIC_FAMILY).or_else(|| names.get(FAMILY)).unwrap_or_default();
+    let old_style =
+        names.get(TYPOGRAPHIC_SUBFAMILY).or_else(|| names.get(SUBFAMILY)).unwrap_or_else(|| "Regular".to_string());
+    let family = req.family.clone().unwrap_or_else(|| old_family.clone());
+    let style = req.subfamily.clone().unwrap_or_else(|| old_style.clone());
+    let restyled = family != old_family || style != old_style;
+
+    if family != old_family {
+        // `Inter Medium` in ID 1 beside `Inter` in ID 16 keeps its suffix.
+        let legacy = names.get(FAMILY).unwrap_or_default();
+        let suffix = legacy.strip_prefix(old_family.as_str()).filter(|_| typographic).unwrap_or_default();
+        names.replace(FAMILY, &format!("{family}{suffix}"));
+        if typographic {
+            names.replace(TYPOGRAPHIC_FAMILY, &family);
+        }
+    }
+    if style != old_style {
+        let id = if names.get(TYPOGRAPHIC_SUBFAMILY).is_some() { TYPOGRAPHIC_SUBFAMILY } else { SUBFAMILY };
+        names.replace(id, &style);
+    }
+    let full_name = match &req.full_name {
+        Some(full) => Some(full.clone()),
+        None if restyled && style == "Regular" => Some(family.clone()),
+        None if restyled => Some(format!("{family} {style}")),
+        None => None,
+    };
+    if let Some(full) = &full_name {
+        names.replace(FULL_NAME, full);
+    }
+    let postscript_name = match &req.postscript_name {
+        Some(ps) => Some(ps.clone()),
+        None if restyled => {
+            let mut ps = format!("{}-{}", postscript_part(&family), postscript_part(&style));
+            ps.truncate(63);
+            Some(ps)
+        }
+        None => None,
+    };
+    if let Some(ps) = &postscript_name {
+        names.replace(POSTSCRIPT_NAME, ps);
+    }
+    font.set_table(*b"name", names.to_bytes());
+
+    Ok(BTreeMap::from([
+        ("family".to_string(), family),
+        ("subfamily".to_string(), style),
+        ("full_name".to_string(), full_name.or_else(|| names.get(FULL_NAME)).unwrap_or_default()),
+        ("postscript_name".to_string(), postscript_name.or_else(|| names.get(POSTSCRIPT_NAME)).unwrap_or_default()),
+    ]))
+}
+
+fn validate(req: &RenameRequest) -> Result<(), (StatusCode, String)> {
+    let valid_formats = ["woff2", "woff", "otf", "ttf"];
+    if !valid_formats.contains(&req.format.as_str()) {
+        return Err(bad_request(format!("unsupported format '{}'; valid: woff2, woff, otf, ttf", req.format)));
+    }
+    let fields = [("family", &req.family), ("subfamily", &req.subfamily), ("full_name", &req.full_name)];
+    if fields.iter().all(|(_, v)| v.is_none()) && req.postscript_name.is_none() {
+        return Err(bad_request("give family, subfamily, full_name or postscript_name"));
+    }
+    for (field, value) in fields {
+        if value.as_deref().is_some_and(|v| v.trim().is_empty() || v.chars().count() > 255) {
+            return Err(bad_request(format!("{field} must be 1-255 characters")));
+        }
+    }
+    if let Some(ps) = req.postscript_name.as_deref().filter(|ps| !valid_postscript(ps)) {
+        return Err(bad_request(format!(
+            "postscript_name '{ps}' must be at most 63 printable ASCII characters without spaces or []{{}}()<>/%"
+        )));
+    }
+    Ok(())
+}
+
+pub async fn rename(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(mut req): ApiJson<RenameRequest>,
+) -> Result<Json<RenameResponse>, (StatusCode, String)> {
+    state.flags.ensure("compress", &headers)?;
+    state.quotas.charge_operation(&headers)?;
+    validate(&req)?;
+    let (font_name, upload) = state.uploads.resolve(&headers, req.font_id.as_deref(), &req.font_name).await?;
+    req.font_name = font_name;
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &req.font_name)?;
+
+    // The entry the variant copies, checked before any work is done.
+    let base_entry = match &req.catalog_id {
+        None => None,
+        Some(id) => {
+            state.require_admin(&headers)?;
+            if upload.is_some() {
+                return Err(bad_request("catalog_id needs a catalog font as the source, not an upload"));
+            }
+            if state.catalog.read().unwrap().iter().any(|e| &e.id == id) {
+                return Err((StatusCode::CONFLICT, format!("'{id}' is already in the catalog")));
+            }
+            let key = req.font_name.to_lowercase();
+            let entry =
+                state.catalog.read().unwrap().iter().find(|e| e.id == key || e.family.to_lowercase() == key).cloned();
+            Some(entry.ok_or_else(|| (StatusCode::NOT_FOUND, format!("'{}' is not in the catalog", req.font_name)))?)
+        }
+    };
+
+    let uploaded = match &upload {
+        Some(upload) => Some(state.uploads.read(upload).await?),
+        None => None,
+    };
+    let (job_state, job_req) = (Arc::clone(&state), req.clone());
+    let (names, sfnt, encoded) = cancel::run(&state.jobs, "rename", move |token| {
+        let data = match uploaded {
+            Some(data) => data,
+            None => match duplicates::catalog_binary(&job_state, &job_req.font_name) {
+                Ok(data) => data,
+                Err(e) => return Ok(Err(e)),
+            },
+        };
+        token.check()?;
+        Ok(compress::load(&data).and_then(|mut font| {
+            let names = apply(&mut font, &job_req)?;
+            Ok((names, font.to_bytes(), compress::encode(&font, &job_req.format, 100)?))
+        }))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let source = state
+        .artifacts
+        .source(&state, &req.font_name, upload.as_ref())
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let transform = serde_json::json!({ "rename": names });
+    let address = state.artifacts.address(&names["family"], &source, &transform, &req.format);
+    if state.artifacts.lookup::<RenameRecord>(&address).await?.is_none() {
+        let record = RenameRecord { output_bytes: encoded.len(), names: names.clone() };
+        state.artifacts.put(&address, &encoded, &record).await?;
+    }
+
+    let catalog_entry = match (base_entry, &req.catalog_id) {
+        (Some(base), Some(id)) => Some(register(&state, base, id, &names, &sfnt).await?),
+        _ => None,
+    };
+
+    info!(font = %req.font_name, family = %names["family"], catalog_id = ?req.catalog_id, "font renamed");
+    Ok(Json(RenameResponse {
+        font_name: req.font_name,
+        format: req.format,
+        names,
+        size_kb: encoded.len() as f64 / 1024.0,
+        download_url: signing::download_url(&state, &address.path()),
+        catalog_entry,
+    }))
+}
+
+/// Writes `sfnt` to `CATALOG_FONT_DIR` and adds it as entry `id`, a copy of
+/// `base` under the new names.
+async fn register(
+    state: &AppState,
+    base: FontCatalogEntry,
+    id: &str,
+    names: &BTreeMap<String, String>,
+    sfnt: &[u8],
+) -> Result<FontCatalogEntry, (StatusCode, String)> {
+    let dir = duplicates::catalog_font_dir()
+        .ok_or_else(|| (StatusCode::UNPROCESSABLE_ENTITY, "CATALOG_FONT_DIR is not configured".to_string()))?;
+    let cff = sfnt.starts_with(b"OTTO");
+    let path = dir.join(format!("{id}.{}", if cff { "otf" } else { "ttf" }));
+    let entry = FontCatalogEntry {
+        id: id.to_string(),
+        family: names["family"].clone(),
+        variant: names["subfamily"].clone(),
+        size_kb: sfnt.len() as f64 / 1024.0,
+        postscript_name: Some(names["postscript_name"].clone()),
+        version: 1,
+        ..base
+    };
+    let entry = collision::resolve(entry, &state.catalog.read().unwrap(), &Default::default())?;
+    tokio::fs::write(&path, sfnt)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{}: {e}", path.display())))?;
+    let registered = async {
+        catalog::validate(id, &entry)?;
+        catalog::persist(state, &entry).await
+    };
+    if let Err(e) = registered.await {
+        let _ = tokio::fs::remove_file(&path).await;
+        return Err(e);
+    }
+    state.catalog.write().unwrap().push(entry.clone());
+    info!(id = %entry.id, family = %entry.family, "renamed catalog variant registered");
+    Ok(entry)
+}
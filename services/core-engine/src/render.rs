@@ -0,0 +1,149 @@
+//! Visual regression check for processed fonts.
+//!
+//! Rasterizes sample text with the original and the processed font and
+//! compares the glyph bitmaps and advances, so a transform that touched
+//! outlines, cmap or hmtx by mistake is caught before the artifact ships.
+//! Transform endpoints opt in with `?verify=flag` (report in headers) or
+//! `?verify=fail` (422 when rendering changed).
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use fontdue::{Font, FontSettings};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use tracing::info;
+
+pub const DEFAULT_TEXT: &str = "The quick brown fox jumps over the lazy dog 0123456789";
+const SAMPLE_PX: f32 = 32.0;
+/// Per-pixel coverage difference treated as antialiasing noise.
+const PIXEL_TOLERANCE: u8 = 16;
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifyMode {
+    /// Report the comparison in `X-Render-*` headers.
+    Flag,
+    /// Additionally reject the job when rendering changed.
+    Fail,
+}
+
+/// Query parameters shared by every endpoint that can verify its output.
+#[derive(Debug, Default, Deserialize)]
+pub struct VerifyQuery {
+    verify: Option<VerifyMode>,
+    verify_text: Option<String>,
+    /// Largest tolerated fraction of differing pixels (default 0).
+    verify_threshold: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct RenderDiff {
+    glyphs: usize,
+    differing: Vec<char>,
+    missing: Vec<char>,
+    pixels: usize,
+    differing_pixels: usize,
+}
+
+impl RenderDiff {
+    fn ratio(&self) -> f64 {
+        if self.pixels == 0 {
+            0.0
+        } else {
+            self.differing_pixels as f64 / self.pixels as f64
+        }
+    }
+}
+
+fn load(data: &[u8], which: &str) -> Result<Font, String> {
+    Font::from_bytes(data, FontSettings::default()).map_err(|e| format!("cannot rasterize {which} font: {e}"))
+}
+
+/// Compares every distinct character of `text` the original font maps.
+fn compare(original: &[u8], processed: &[u8], text: &str) -> Result<RenderDiff, String> {
+    let (before, after) = (load(original, "original")?, load(processed, "processed")?);
+    let mut diff = RenderDiff::default();
+    for c in text.chars().filter(|c| !c.is_whitespace()).collect::<BTreeSet<_>>() {
+        let index = before.lookup_glyph_index(c);
+        if index == 0 {
+            continue;
+        }
+        diff.glyphs += 1;
+        let processed_index = after.lookup_glyph_index(c);
+        if processed_index == 0 {
+            diff.missing.push(c);
+            continue;
+        }
+        let (m1, a) = before.rasterize_indexed(index, SAMPLE_PX);
+        let (m2, b) = after.rasterize_indexed(processed_index, SAMPLE_PX);
+        let area = a.len().max(b.len()).max(1);
+        diff.pixels += area;
+        let same_box = (m1.xmin, m1.ymin, m1.width, m1.height) == (m2.xmin, m2.ymin, m2.width, m2.height)
+            && (m1.advance_width - m2.advance_width).abs() < 0.01;
+        let changed = if same_box {
+            a.iter().zip(&b).filter(|(x, y)| x.abs_diff(**y) > PIXEL_TOLERANCE).count()
+        } else {
+            area
+        };
+        if changed > 0 {
+            diff.differing.push(c);
+            diff.differing_pixels += changed;
+        }
+    }
+    Ok(diff)
+}
+
+fn code_points(chars: &[char]) -> String {
+    chars.iter().take(20).map(|c| format!("U+{:04X}", *c as u32)).collect::<Vec<_>>().join(" ")
+}
+
+/// Runs the check requested by `query` (if any) and returns the headers to
+/// add to the response. `default_text` is used without `verify_text`.
+pub fn verify(
+    query: &VerifyQuery,
+    original: &[u8],
+    processed: &[u8],
+    default_text: Option<&str>,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    let mut headers = HeaderMap::new();
+    let Some(mode) = query.verify else { return Ok(headers) };
+    let text = query.verify_text.as_deref().or(default_text).unwrap_or(DEFAULT_TEXT);
+    let threshold = query.verify_threshold.unwrap_or(0.0);
+    let mut set = |name: &'static str, value: String| {
+        if let Ok(v) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), v);
+        }
+    };
+    let diff = match compare(original, processed, text) {
+        Ok(diff) => diff,
+        Err(e) if mode == VerifyMode::Fail => return Err((StatusCode::UNPROCESSABLE_ENTITY, e)),
+        Err(e) => {
+            set("x-render-check", "unavailable".to_string());
+            set("x-render-error", e);
+            return Ok(headers);
+        }
+    };
+    let pass = diff.missing.is_empty() && diff.ratio() <= threshold;
+
+    info!(glyphs = diff.glyphs, differing = diff.differing.len(), ratio = diff.ratio(), pass, "render verification");
+    if !pass && mode == VerifyMode::Fail {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "rendering changed: {:.6} of pixels differ (threshold {threshold}); differing: [{}]; missing: [{}]",
+                diff.ratio(),
+                code_points(&diff.differing),
+                code_points(&diff.missing)
+            ),
+        ));
+    }
+    set("x-render-check", if pass { "pass" } else { "fail" }.to_string());
+    set("x-render-diff", format!("{:.6}", diff.ratio()));
+    set("x-render-glyphs", diff.glyphs.to_string());
+    if !diff.differing.is_empty() {
+        set("x-render-differing", code_points(&diff.differing));
+    }
+    if !diff.missing.is_empty() {
+        set("x-render-missing", code_points(&diff.missing));
+    }
+    Ok(headers)
+}
@@ -0,0 +1,176 @@
+//! Failure handling for calls to remote dependencies (S3 storage): a
+//! per-attempt timeout, jittered retries of reads and a consecutive-failure
+//! circuit breaker, so a backend that is down fails requests at once
+//! instead of holding each for a full timeout. Database calls are bounded
+//! by the pool's timeouts instead (see [`crate::db::connect`]).
+
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+fn env_u64(k: &str, d: u64) -> u64 {
+    std::env::var(k).ok().and_then(|v| v.parse().ok()).unwrap_or(d)
+}
+
+enum State {
+    Closed { failures: u32 },
+    Open { since: Instant },
+    HalfOpen,
+}
+
+/// Opens after `threshold` consecutive failures, then lets a single probe
+/// through once `cooldown` has elapsed.
+pub struct Breaker {
+    name: &'static str,
+    threshold: u32,
+    cooldown: Duration,
+    timeout: Duration,
+    retries: u32,
+    state: Mutex<State>,
+}
+
+impl Breaker {
+    /// Settings from `<PREFIX>_TIMEOUT_SECS`, `<PREFIX>_RETRIES`,
+    /// `<PREFIX>_BREAKER_THRESHOLD` and `<PREFIX>_BREAKER_COOLDOWN_SECS`.
+    pub fn from_env(name: &'static str, prefix: &str) -> Self {
+        let var = |suffix: &str, default| env_u64(&format!("{prefix}_{suffix}"), default);
+        Self {
+            name,
+            threshold: var("BREAKER_THRESHOLD", 5).max(1) as u32,
+            cooldown: Duration::from_secs(var("BREAKER_COOLDOWN_SECS", 30)),
+            timeout: Duration::from_secs(var("TIMEOUT_SECS", 30).max(1)),
+            retries: var("RETRIES", 2) as u32,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Closed { .. } => true,
+            State::Open { since } if since.elapsed() >= self.cooldown => {
+                *state = State::HalfOpen;
+                true
+            }
+            State::Open { .. } | State::HalfOpen => false,
+        }
+    }
+
+    fn record(&self, ok: bool) {
+        let mut state = self.state.lock().unwrap();
+        *state = match *state {
+            _ if ok => State::Closed { failures: 0 },
+            State::Closed { failures } if failures + 1 < self.threshold => State::Closed { failures: failures + 1 },
+            State::Closed { .. } | State::HalfOpen => {
+                tracing::warn!(dependency = self.name, "circuit breaker open");
+                State::Open { since: Instant::now() }
+            }
+            State::Open { since } => State::Open { since },
+        };
+    }
+
+    /// One attempt at `call`, failing at once while the breaker is open.
+    pub async fn call<T, F>(&self, call: F) -> Result<T, String>
+    where
+        F: Future<Output = Result<T, String>>,
+    {
+        if !self.allow() {
+            return Err(format!("{} unavailable: circuit breaker open", self.name));
+        }
+        let result = match tokio::time::timeout(self.timeout, call).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("{} timed out after {:?}", self.name, self.timeout)),
+        };
+        self.record(result.is_ok());
+        result
+    }
+
+    /// Like [`Breaker::call`], retrying failures with full-jitter backoff;
+    /// only for calls that are safe to repeat.
+    pub async fn retry<T, F, Fut>(&self, mut call: F) -> Result<T, String>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, String>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match self.call(call()).await {
+                Err(e) if attempt < self.retries && self.allow_retry() => {
+                    tokio::time::sleep(backoff(attempt)).await;
+                    attempt += 1;
+                    tracing::debug!(dependency = self.name, attempt, "retrying: {e}");
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn allow_retry(&self) -> bool {
+        matches!(*self.state.lock().unwrap(), State::Closed { .. })
+    }
+}
+
+/// Full-jitter exponential backoff for the zero-based retry `attempt`,
+/// from 50 ms up to one second.
+fn backoff(attempt: u32) -> Duration {
+    let cap = Duration::from_millis(50).saturating_mul(1 << attempt.min(16)).min(Duration::from_secs(1));
+    let roll = RandomState::new().build_hasher().finish() % 1_000;
+    cap.mul_f64(roll as f64 / 1_000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn breaker(cooldown: Duration) -> Breaker {
+        Breaker {
+            name: "test",
+            threshold: 2,
+            cooldown,
+            timeout: Duration::from_millis(200),
+            retries: 1,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    #[tokio::test]
+    async fn opens_after_consecutive_failures_and_probes_after_the_cooldown() {
+        let breaker = breaker(Duration::from_millis(100));
+        let calls = AtomicU32::new(0);
+        let fail = || async {
+            calls.fetch_add(1, Ordering::Relaxed);
+            Err::<(), _>("down".to_string())
+        };
+        // The first call fails and is retried once, which opens the breaker.
+        assert_eq!(breaker.retry(fail).await.unwrap_err(), "down");
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        assert!(breaker.retry(fail).await.unwrap_err().contains("circuit breaker open"));
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        // One probe, not retried: a failure opens the breaker again.
+        assert_eq!(breaker.retry(fail).await.unwrap_err(), "down");
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+        assert!(breaker.call(async { Ok(()) }).await.unwrap_err().contains("circuit breaker open"));
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(breaker.call(async { Ok(7) }).await, Ok(7));
+        assert_eq!(breaker.retry(fail).await.unwrap_err(), "down");
+        assert_eq!(calls.load(Ordering::Relaxed), 5);
+    }
+
+    #[tokio::test]
+    async fn times_out_slow_calls() {
+        let breaker = breaker(Duration::from_secs(60));
+        let slow = breaker.call(async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        });
+        assert_eq!(slow.await.unwrap_err(), "test timed out after 200ms");
+    }
+}
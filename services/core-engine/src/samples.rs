@@ -0,0 +1,62 @@
+//! Script-appropriate preview strings for catalog entries and previews.
+//!
+//! A sample is offered only when the font covers every character in it, so
+//! catalog UIs never render a Latin pangram in tofu for a CJK-only face (or
+//! the reverse). Catalog entries are matched against their registered
+//! `unicode_ranges`; the preview endpoints read the cmap of the font they
+//! draw, and default their text to its primary sample.
+
+use font_api::{CatalogItem, Sample};
+use rustybuzz::ttf_parser::Face;
+
+use crate::{unicode, FontCatalogEntry};
+
+/// Candidate samples in preference order: the first covered one is the
+/// entry's primary preview. Distinctive scripts come first, since their
+/// fonts usually carry Latin too; Latin comes before the scripts that Latin
+/// fonts often add.
+const SAMPLES: &[(&str, &str)] = &[
+    ("japanese", "いろはにほへと ちりぬるを わかよたれそ つねならむ"),
+    ("chinese", "天地玄黄 宇宙洪荒 日月盈昃 辰宿列张"),
+    ("korean", "다람쥐 헌 쳇바퀴에 타고파"),
+    ("arabic", "صِف خَلقَ خَودِ كَمِثلِ الشَمسِ إِذ بَزَغَت"),
+    ("hebrew", "דג סקרן שט בים מאוכזב ולפתע מצא חברה"),
+    ("devanagari", "सभी मनुष्यों को गौरव और अधिकारों के मामले में जन्मजात स्वतन्त्रता प्राप्त है"),
+    ("thai", "เป็นมนุษย์สุดประเสริฐเลิศคุณค่า"),
+    ("latin", "The quick brown fox jumps over the lazy dog"),
+    ("latin-ext", "Příliš žluťoučký kůň úpěl ďábelské ódy"),
+    ("cyrillic", "Съешь же ещё этих мягких французских булок, да выпей чаю"),
+    ("greek", "Ξεσκεπάζω την ψυχοφθόρα βδελυγμία"),
+];
+
+/// Script names `?script=` accepts.
+pub fn scripts() -> impl Iterator<Item = &'static str> {
+    SAMPLES.iter().map(|(script, _)| *script)
+}
+
+/// Samples whose every character `covers` accepts, in preference order.
+fn covered(covers: impl Fn(char) -> bool) -> impl Iterator<Item = &'static (&'static str, &'static str)> {
+    SAMPLES.iter().filter(move |(_, text)| text.chars().all(|c| c == ' ' || covers(c)))
+}
+
+pub fn for_entry(entry: &FontCatalogEntry) -> Vec<Sample> {
+    let ranges = unicode::parse_ranges(&entry.unicode_ranges);
+    covered(|c| unicode::covers(&ranges, c))
+        .map(|&(script, text)| Sample { script: script.to_string(), text: text.to_string() })
+        .collect()
+}
+
+/// The sample for `script` if given, else the font's primary sample;
+/// `None` when the font does not cover it (or any sample).
+pub fn for_font(data: &[u8], script: Option<&str>) -> Option<&'static str> {
+    let face = Face::parse(data, 0).ok()?;
+    let mut samples = covered(|c| face.glyph_index(c).is_some());
+    samples.find(|(name, _)| script.is_none_or(|s| s == *name)).map(|(_, text)| *text)
+}
+
+pub fn annotate(entries: Vec<FontCatalogEntry>) -> Vec<CatalogItem> {
+    entries
+        .into_iter()
+        .map(|entry| CatalogItem { samples: for_entry(&entry), entry })
+        .collect()
+}
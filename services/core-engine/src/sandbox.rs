@@ -0,0 +1,101 @@
+//! Public sandbox tenant.
+//!
+//! The gateway can admit unauthenticated callers as a demo tenant
+//! (`SANDBOX_MODE`); the engine recognises it by `SANDBOX_TENANT` as the
+//! authenticated tenant, vouched for by the gateway's `GATEWAY_SECRET` or
+//! bound to the caller's API key (see [`crate::auth`]; a bare
+//! `X-Font-Tenant` never gets this far). It narrows what the sandbox can
+//! touch: only the bundled OFL fonts in `SANDBOX_FONTS` are visible and
+//! processable, and subsets are capped at `SANDBOX_MAX_CHARACTERS`. Other
+//! tenants are unaffected.
+//!
+//! Every caller's view also goes through [`tenants`] first, which hides
+//! other tenants' catalog entries.
+
+use axum::http::{HeaderMap, StatusCode};
+
+use crate::{tenants, FontCatalogEntry};
+
+const DEFAULT_FONTS: &str = "inter,noto-sans-jp,fira-code";
+const DEFAULT_MAX_CHARACTERS: usize = 1_000;
+
+#[derive(Default)]
+pub struct Sandbox {
+    /// Disabled when unset.
+    tenant: Option<String>,
+    fonts: Vec<String>,
+    max_characters: usize,
+}
+
+impl Sandbox {
+    pub fn from_env() -> Self {
+        let Some(tenant) = std::env::var("SANDBOX_TENANT").ok().filter(|t| !t.is_empty()) else {
+            return Self::default();
+        };
+        let fonts = std::env::var("SANDBOX_FONTS").unwrap_or_else(|_| DEFAULT_FONTS.to_string());
+        Self {
+            tenant: Some(tenant),
+            fonts: fonts.split(',').map(|f| f.trim().to_lowercase()).filter(|f| !f.is_empty()).collect(),
+            max_characters: std::env::var("SANDBOX_MAX_CHARACTERS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_CHARACTERS),
+        }
+    }
+
+    pub fn is_sandbox(&self, headers: &HeaderMap) -> bool {
+        self.tenant.as_deref().is_some_and(|t| t == tenants::of(headers))
+    }
+
+    fn bundles(&self, entry: &FontCatalogEntry) -> bool {
+        self.fonts.contains(&entry.id)
+    }
+
+    /// The catalog as the caller may see it: without other tenants' entries
+    /// (see [`tenants`]) and, in the sandbox, bundled fonts only.
+    pub fn visible(&self, headers: &HeaderMap, entries: Vec<FontCatalogEntry>) -> Vec<FontCatalogEntry> {
+        let entries = tenants::visible(headers, entries);
+        if !self.is_sandbox(headers) {
+            return entries;
+        }
+        entries.into_iter().filter(|e| self.bundles(e)).collect()
+    }
+
+    /// Rejects requests for another tenant's font, and sandbox requests for
+    /// anything but a bundled font; `name` is an id, family name or family
+    /// slug.
+    pub fn ensure_font(
+        &self,
+        headers: &HeaderMap,
+        catalog: &[FontCatalogEntry],
+        name: &str,
+    ) -> Result<(), (StatusCode, String)> {
+        tenants::ensure_font(headers, catalog, name)?;
+        if !self.is_sandbox(headers) {
+            return Ok(());
+        }
+        let key = name.trim().to_lowercase();
+        let bundled = catalog.iter().filter(|e| self.bundles(e)).any(|e| {
+            let family = e.family.to_lowercase();
+            e.id == key || family == key || family.replace(' ', "-") == key
+        });
+        if bundled {
+            Ok(())
+        } else {
+            Err((
+                StatusCode::FORBIDDEN,
+                format!("the sandbox only serves its bundled fonts: {}", self.fonts.join(", ")),
+            ))
+        }
+    }
+
+    pub fn ensure_characters(&self, headers: &HeaderMap, count: usize) -> Result<(), (StatusCode, String)> {
+        if self.is_sandbox(headers) && count > self.max_characters {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("the sandbox subsets at most {} characters", self.max_characters),
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+//! Antivirus scanning hook for uploaded font binaries.
+//!
+//! `SCANNER` selects the backend: `clamd://host:3310` streams the file with
+//! clamd's `INSTREAM` command, `icap://host:1344/service` submits it as an
+//! ICAP `RESPMOD`. Unset means no scanning.
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::StatusCode,
+    response::Json,
+};
+use serde::Serialize;
+use std::{path::Path, sync::Arc, time::Duration};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tracing::{info, warn};
+
+use crate::{
+    quarantine::{self, Report},
+    spool, AppState,
+};
+
+const CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub enum Scanner {
+    Disabled,
+    Clamd { addr: String },
+    Icap { addr: String, host: String, service: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Verdict {
+    Clean,
+    Infected { signature: String },
+    NotScanned,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScanResponse {
+    scanner: &'static str,
+    size_bytes: u64,
+    sha256: String,
+    #[serde(flatten)]
+    report: Report,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quarantine_id: Option<String>,
+}
+
+impl Scanner {
+    pub fn from_env() -> Self {
+        let Ok(spec) = std::env::var("SCANNER") else {
+            return Self::Disabled;
+        };
+        if let Some(addr) = spec.strip_prefix("clamd://") {
+            return Self::Clamd { addr: addr.trim_end_matches('/').to_string() };
+        }
+        if let Some(rest) = spec.strip_prefix("icap://") {
+            let (addr, service) = rest.split_once('/').unwrap_or((rest, "avscan"));
+            let host = addr.split(':').next().unwrap_or(addr).to_string();
+            return Self::Icap { addr: addr.to_string(), host, service: service.to_string() };
+        }
+        if !spec.is_empty() {
+            warn!("unrecognised SCANNER '{spec}'; scanning disabled");
+        }
+        Self::Disabled
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Disabled => "none",
+            Self::Clamd { .. } => "clamd",
+            Self::Icap { .. } => "icap",
+        }
+    }
+
+    /// Scans the file at `path`, streaming it to the backend chunk by chunk.
+    pub async fn scan(&self, path: &Path) -> std::io::Result<Verdict> {
+        let timeout = Duration::from_secs(30);
+        let run = async {
+            match self {
+                Self::Disabled => Ok(Verdict::NotScanned),
+                Self::Clamd { addr } => clamd_instream(addr, File::open(path).await?).await,
+                Self::Icap { addr, host, service } => {
+                    let file = File::open(path).await?;
+                    let len = file.metadata().await?.len();
+                    icap_respmod(addr, host, service, file, len).await
+                }
+            }
+        };
+        tokio::time::timeout(timeout, run)
+            .await
+            .unwrap_or_else(|_| Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "scanner timed out")))
+    }
+}
+
+async fn clamd_instream(addr: &str, mut file: File) -> std::io::Result<Verdict> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(b"zINSTREAM\0").await?;
+    let mut chunk = vec![0u8; CHUNK];
+    loop {
+        let n = file.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        stream.write_all(&(n as u32).to_be_bytes()).await?;
+        stream.write_all(&chunk[..n]).await?;
+    }
+    stream.write_all(&0u32.to_be_bytes()).await?;
+
+    // Replies to z-prefixed commands are NUL-terminated.
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 256];
+    while !reply.contains(&0) {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        reply.extend_from_slice(&buf[..n]);
+    }
+    let reply = String::from_utf8_lossy(&reply);
+    let reply = reply.trim_end_matches(['\0', '\n']);
+    // "stream: OK" or "stream: <signature> FOUND"
+    match reply.strip_prefix("stream: ") {
+        Some("OK") => Ok(Verdict::Clean),
+        Some(rest) if rest.ends_with(" FOUND") => Ok(Verdict::Infected {
+            signature: rest.trim_end_matches(" FOUND").to_string(),
+        }),
+        _ => Err(std::io::Error::other(format!("unexpected clamd reply: {reply}"))),
+    }
+}
+
+async fn icap_respmod(
+    addr: &str,
+    host: &str,
+    service: &str,
+    mut file: File,
+    len: u64,
+) -> std::io::Result<Verdict> {
+    let http_head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {len}\r\n\r\n"
+    );
+    let icap_head = format!(
+        "RESPMOD icap://{addr}/{service} ICAP/1.0\r\nHost: {host}\r\nAllow: 204\r\n\
+         Encapsulated: res-hdr=0, res-body={}\r\n\r\n",
+        http_head.len()
+    );
+
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(icap_head.as_bytes()).await?;
+    stream.write_all(http_head.as_bytes()).await?;
+    let mut chunk = vec![0u8; CHUNK];
+    loop {
+        let n = file.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        stream.write_all(format!("{n:x}\r\n").as_bytes()).await?;
+        stream.write_all(&chunk[..n]).await?;
+        stream.write_all(b"\r\n").await?;
+    }
+    stream.write_all(b"0\r\n\r\n").await?;
+
+    // Only the ICAP status line and headers matter.
+    let mut head = Vec::new();
+    let mut buf = [0u8; 4096];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.lines();
+    let status = lines.next().unwrap_or_default();
+    let code = status.split_whitespace().nth(1).unwrap_or_default();
+    let signature = lines
+        .filter_map(|l| l.split_once(':'))
+        .find(|(k, _)| k.eq_ignore_ascii_case("X-Infection-Found") || k.eq_ignore_ascii_case("X-Virus-ID"))
+        .map(|(_, v)| v.trim().to_string());
+    match (code, signature) {
+        ("204", _) => Ok(Verdict::Clean),
+        (_, Some(signature)) => Ok(Verdict::Infected { signature }),
+        ("200", None) => Ok(Verdict::Infected { signature: "modified by ICAP service".to_string() }),
+        _ => Err(std::io::Error::other(format!("unexpected ICAP reply: {status}"))),
+    }
+}
+
+/// Checks a raw font body (structure and virus scan) without storing it.
+/// The body is spooled to disk as it arrives rather than buffered in memory.
+/// Failing files are held in quarantine and the response carries its ID.
+pub async fn scan_handler(
+    State(state): State<Arc<AppState>>,
+    body: Body,
+) -> Result<Json<ScanResponse>, (StatusCode, String)> {
+    let file = spool::spool(body, spool::max_upload_bytes()).await?;
+    if file.size == 0 {
+        return Err((StatusCode::BAD_REQUEST, "request body is empty".to_string()));
+    }
+    let report = quarantine::inspect(&state, file.path(), file.size).await?;
+    info!(scanner = state.scanner.name(), size = file.size, verdict = ?report.verdict, "font scanned");
+    let (size_bytes, sha256) = (file.size, file.sha256.clone());
+    let quarantine_id = if report.passed() {
+        None
+    } else {
+        Some(state.quarantine.admit(file, report.clone()).await?)
+    };
+    Ok(Json(ScanResponse {
+        scanner: state.scanner.name(),
+        size_bytes,
+        sha256,
+        report,
+        quarantine_id,
+    }))
+}
@@ -0,0 +1,164 @@
+//! Reverse coverage search: which catalog fonts render given characters.
+//!
+//! `GET /api/v1/font/search/coverage?chars=𝕬✓🎉` (and/or
+//! `unicode_range=U+2713,U+1F389`) checks every catalog entry the caller
+//! can see against the characters, using the `cmap` of its binary in
+//! `CATALOG_FONT_DIR` (the ranges the entry declares when it has no
+//! binary, marked `"source": "declared"`). Fonts covering any of them come
+//! back ranked by how many they cover, then smallest first, so picking a
+//! fallback for unusual symbols is one request. Characters no font covers
+//! are `uncovered`. Cmaps are cached per entry version.
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
+};
+
+use crate::{cancel, cmap::CharMap, compress, duplicates, unicode, AppState};
+
+/// Characters one search may ask about.
+const MAX_CHARACTERS: usize = 4096;
+
+type Ranges = Arc<Vec<RangeInclusive<u32>>>;
+
+#[derive(Default)]
+pub struct CmapCache {
+    ranges: Mutex<HashMap<(String, u32), Ranges>>,
+}
+
+fn cmap_ranges(data: &[u8]) -> Result<Vec<RangeInclusive<u32>>, String> {
+    let font = compress::load(data)?;
+    Ok(CharMap::parse(font.table(b"cmap").ok_or("font has no cmap table")?)?.ranges())
+}
+
+/// Whether sorted, disjoint `ranges` hold `c`.
+fn holds(ranges: &[RangeInclusive<u32>], c: char) -> bool {
+    let after = ranges.partition_point(|r| *r.end() < c as u32);
+    ranges.get(after).is_some_and(|r| r.contains(&(c as u32)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    chars: String,
+    /// Comma-separated `U+XXXX` or `U+XXXX-YYYY`.
+    #[serde(default)]
+    unicode_range: String,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Match {
+    id: String,
+    family: String,
+    variant: String,
+    covered: usize,
+    coverage_percent: f64,
+    complete: bool,
+    size_kb: f64,
+    /// `cmap`, or `declared` for an entry without a binary.
+    source: &'static str,
+    missing: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+    requested: usize,
+    fonts: Vec<Match>,
+    /// Characters no catalog font covers.
+    uncovered: String,
+}
+
+pub async fn coverage(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResponse>, (StatusCode, String)> {
+    let mut wanted: BTreeSet<char> = query.chars.chars().filter(|c| !c.is_control()).collect();
+    for item in query.unicode_range.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        // An unescaped `+` arrives as a space.
+        let item = item.replace(' ', "+");
+        let range = unicode::parse_range(&item).ok_or_else(|| {
+            (StatusCode::BAD_REQUEST, format!("unicode_range: '{item}' is not U+XXXX or U+XXXX-YYYY"))
+        })?;
+        if range.clone().count() > MAX_CHARACTERS {
+            let message = format!("unicode_range: '{item}' spans more than {MAX_CHARACTERS} characters");
+            return Err((StatusCode::BAD_REQUEST, message));
+        }
+        wanted.extend(range.filter_map(char::from_u32));
+    }
+    if wanted.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "chars or unicode_range is required".to_string()));
+    }
+    if wanted.len() > MAX_CHARACTERS {
+        return Err((StatusCode::BAD_REQUEST, format!("at most {MAX_CHARACTERS} characters per search")));
+    }
+
+    let catalog = state.sandbox.visible(&headers, state.catalog.read().unwrap().clone());
+    let job_state = Arc::clone(&state);
+    let coverage = cancel::run(&state.jobs, "coverage-search", move |token| {
+        // Retired entries and old versions leave the cache.
+        let current = |(id, version): &(String, u32)| catalog.iter().any(|e| &e.id == id && e.version == *version);
+        job_state.cmaps.ranges.lock().unwrap().retain(|key, _| current(key));
+        let mut found = Vec::with_capacity(catalog.len());
+        for entry in catalog {
+            token.check()?;
+            let key = (entry.id.clone(), entry.version);
+            let cached = job_state.cmaps.ranges.lock().unwrap().get(&key).cloned();
+            let ranges = match cached {
+                Some(ranges) => Some(ranges),
+                None => {
+                    let ranges = duplicates::catalog_binary(&job_state, &entry.id).and_then(|d| cmap_ranges(&d));
+                    let ranges = ranges.ok().map(Arc::new);
+                    if let Some(ranges) = &ranges {
+                        job_state.cmaps.ranges.lock().unwrap().insert(key, Arc::clone(ranges));
+                    }
+                    ranges
+                }
+            };
+            let (ranges, source) = match ranges {
+                Some(ranges) => (ranges, "cmap"),
+                None => (Arc::new(unicode::parse_ranges(&entry.unicode_ranges)), "declared"),
+            };
+            found.push((entry, ranges, source));
+        }
+        Ok(found)
+    })
+    .await?;
+
+    let mut uncovered = wanted.clone();
+    let mut fonts: Vec<Match> = coverage
+        .into_iter()
+        .filter_map(|(entry, ranges, source)| {
+            let (covered, missing): (BTreeSet<char>, BTreeSet<char>) =
+                wanted.iter().partition(|&&c| holds(&ranges, c));
+            if covered.is_empty() {
+                return None;
+            }
+            uncovered.retain(|c| !covered.contains(c));
+            Some(Match {
+                id: entry.id,
+                family: entry.family,
+                variant: entry.variant,
+                covered: covered.len(),
+                coverage_percent: (covered.len() as f64 * 1000.0 / wanted.len() as f64).round() / 10.0,
+                complete: missing.is_empty(),
+                size_kb: entry.size_kb,
+                source,
+                missing: missing.into_iter().collect(),
+            })
+        })
+        .collect();
+    fonts.sort_by(|a, b| {
+        b.covered.cmp(&a.covered).then_with(|| a.size_kb.total_cmp(&b.size_kb)).then_with(|| a.id.cmp(&b.id))
+    });
+    fonts.truncate(query.limit.unwrap_or(10).clamp(1, 50));
+    Ok(Json(SearchResponse { requested: wanted.len(), fonts, uncovered: uncovered.into_iter().collect() }))
+}
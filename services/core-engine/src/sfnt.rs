@@ -0,0 +1,251 @@
+//! Minimal structural inspection of font binaries: container sniffing and
+//! table-directory sanity checks, run before a file is accepted anywhere,
+//! plus a table-level reader/writer ([`Font`]) for transforms that rewrite
+//! individual tables and must leave every other table byte-identical.
+
+pub use font_api::Flavor;
+
+pub fn be_u16(data: &[u8], at: usize) -> Option<u16> {
+    data.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+pub fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Identifies the container from its magic number.
+pub fn sniff(data: &[u8]) -> Option<Flavor> {
+    match data.get(..4)? {
+        [0x00, 0x01, 0x00, 0x00] | b"true" => Some(Flavor::Ttf),
+        b"OTTO" => Some(Flavor::Otf),
+        b"wOFF" => Some(Flavor::Woff),
+        b"wOF2" => Some(Flavor::Woff2),
+        b"ttcf" => Some(Flavor::Ttc),
+        _ => None,
+    }
+}
+
+/// Number of faces in a TrueType collection.
+pub fn collection_len(data: &[u8]) -> Option<usize> {
+    (sniff(data)? == Flavor::Ttc).then(|| be_u32(data, 8).map(|n| n as usize))?
+}
+
+/// How much of a file [`check_structure`] needs to see; directories that
+/// start beyond this prefix are not inspected.
+pub const INSPECT_PREFIX: usize = 1024 * 1024;
+
+/// Checks that the file is a recognised font whose headers and table
+/// directory stay within the file. `head` is the start of the file (the whole
+/// file, or at least [`INSPECT_PREFIX`] bytes of it) and `total_len` its full
+/// size. Returns every problem found.
+pub fn check_structure(head: &[u8], total_len: u64) -> Result<Flavor, Vec<String>> {
+    let Some(flavor) = sniff(head) else {
+        return Err(vec!["unrecognised font signature".to_string()]);
+    };
+    let mut problems = Vec::new();
+    match flavor {
+        Flavor::Ttf | Flavor::Otf => check_table_directory(head, total_len, 0, &mut problems),
+        Flavor::Woff | Flavor::Woff2 => {
+            // Both headers store the total file length at offset 8.
+            match be_u32(head, 8) {
+                Some(len) if len as u64 == total_len => {}
+                Some(len) => problems.push(format!(
+                    "header declares {len} bytes but file is {total_len} bytes"
+                )),
+                None => problems.push("truncated header".to_string()),
+            }
+            if be_u16(head, 12) == Some(0) {
+                problems.push("numTables is zero".to_string());
+            }
+        }
+        Flavor::Ttc => match be_u32(head, 8) {
+            Some(0) | None => problems.push("collection has no fonts".to_string()),
+            Some(n) => {
+                for i in 0..n as usize {
+                    match be_u32(head, 12 + 4 * i) {
+                        Some(off) => check_table_directory(head, total_len, off as usize, &mut problems),
+                        None if beyond_prefix(head, total_len, 12 + 4 * (i + 1)) => break,
+                        None => {
+                            problems.push(format!("offset table entry {i} truncated"));
+                            break;
+                        }
+                    }
+                }
+            }
+        },
+    }
+    if problems.is_empty() {
+        Ok(flavor)
+    } else {
+        Err(problems)
+    }
+}
+
+/// True when `end` lies past the inspected prefix but still inside the file,
+/// i.e. a failed read means "not inspected" rather than "truncated".
+fn beyond_prefix(head: &[u8], total_len: u64, end: usize) -> bool {
+    end > head.len() && end as u64 <= total_len
+}
+
+fn check_table_directory(head: &[u8], total_len: u64, base: usize, problems: &mut Vec<String>) {
+    let Some(num_tables) = be_u16(head, base + 4) else {
+        if !beyond_prefix(head, total_len, base + 6) {
+            problems.push(format!("offset table at {base} truncated"));
+        }
+        return;
+    };
+    if num_tables == 0 {
+        problems.push("numTables is zero".to_string());
+    }
+    for i in 0..num_tables as usize {
+        let rec = base + 12 + 16 * i;
+        let (Some(tag), Some(offset), Some(length)) =
+            (head.get(rec..rec + 4), be_u32(head, rec + 8), be_u32(head, rec + 12))
+        else {
+            if !beyond_prefix(head, total_len, rec + 16) {
+                problems.push(format!("table record {i} truncated"));
+            }
+            return;
+        };
+        let tag = String::from_utf8_lossy(tag);
+        if offset as u64 + length as u64 > total_len {
+            problems.push(format!(
+                "table '{tag}' spans {offset}+{length}, beyond end of file ({total_len} bytes)"
+            ));
+        }
+    }
+}
+
+// ── Table-level reader/writer ──────────────────────────────────────────────
+
+pub struct Table {
+    pub tag: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+/// A single TrueType/CFF font split into its tables.
+pub struct Font {
+    sfnt_version: u32,
+    pub tables: Vec<Table>,
+}
+
+impl Font {
+    /// Parses an uncompressed single font (`ttf`/`otf`); WOFF must be
+    /// unpacked and a collection's face picked with [`Font::parse_face`].
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        match sniff(data) {
+            Some(Flavor::Ttf | Flavor::Otf) => Self::parse_at(data, 0),
+            Some(Flavor::Ttc) => Err("this is a font collection; extract one of its faces first".to_string()),
+            Some(other) => Err(format!("{other:?} input is not supported here; send ttf or otf")),
+            None => Err("unrecognised font signature".to_string()),
+        }
+    }
+
+    /// Face `index` of a TrueType collection (`ttc`), as a standalone font;
+    /// tables shared between faces are copied into each.
+    pub fn parse_face(data: &[u8], index: usize) -> Result<Self, String> {
+        if sniff(data) != Some(Flavor::Ttc) {
+            return Err("not a font collection".to_string());
+        }
+        let count = collection_len(data).ok_or("truncated collection header")?;
+        if index >= count {
+            return Err(format!("face {index} does not exist; the collection has {count}"));
+        }
+        let base = be_u32(data, 12 + 4 * index).ok_or("truncated collection header")? as usize;
+        match data.get(base..base + 4).and_then(sniff) {
+            Some(Flavor::Ttf | Flavor::Otf) => Self::parse_at(data, base),
+            _ => Err(format!("face {index} has no valid offset table")),
+        }
+    }
+
+    /// The font whose offset table starts at `base`; table offsets are from
+    /// the start of `data`, as in collections.
+    fn parse_at(data: &[u8], base: usize) -> Result<Self, String> {
+        let num_tables = be_u16(data, base + 4).ok_or("truncated offset table")? as usize;
+        let mut tables = Vec::with_capacity(num_tables);
+        for i in 0..num_tables {
+            let rec = base + 12 + 16 * i;
+            let tag = data.get(rec..rec + 4).ok_or("truncated table directory")?;
+            let offset = be_u32(data, rec + 8).ok_or("truncated table directory")? as usize;
+            let length = be_u32(data, rec + 12).ok_or("truncated table directory")? as usize;
+            let body = data
+                .get(offset..offset + length)
+                .ok_or_else(|| format!("table '{}' extends past end of file", String::from_utf8_lossy(tag)))?;
+            tables.push(Table { tag: tag.try_into().unwrap(), data: body.to_vec() });
+        }
+        Ok(Self { sfnt_version: be_u32(data, base).unwrap_or(0x0001_0000), tables })
+    }
+
+    pub fn new(sfnt_version: u32, tables: Vec<Table>) -> Self {
+        Self { sfnt_version, tables }
+    }
+
+    /// `0x00010000` for TrueType outlines, `OTTO` for CFF.
+    pub fn sfnt_version(&self) -> u32 {
+        self.sfnt_version
+    }
+
+    pub fn table(&self, tag: &[u8; 4]) -> Option<&[u8]> {
+        self.tables.iter().find(|t| &t.tag == tag).map(|t| t.data.as_slice())
+    }
+
+    /// Replaces (or adds) a table.
+    pub fn set_table(&mut self, tag: [u8; 4], data: Vec<u8>) {
+        match self.tables.iter_mut().find(|t| t.tag == tag) {
+            Some(t) => t.data = data,
+            None => self.tables.push(Table { tag, data }),
+        }
+    }
+
+    /// Serialises with a sorted directory, 4-byte aligned tables, fresh
+    /// checksums and `head.checkSumAdjustment`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut tables: Vec<&Table> = self.tables.iter().collect();
+        tables.sort_by_key(|t| t.tag);
+        let n = tables.len() as u16;
+        let entry_selector = 15 - n.max(1).leading_zeros() as u16;
+        let search_range = (1u16 << entry_selector) * 16;
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.sfnt_version.to_be_bytes());
+        for v in [n, search_range, entry_selector, n * 16 - search_range] {
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        let mut offset = 12 + 16 * tables.len();
+        let mut head_at = None;
+        for t in &tables {
+            let mut data = t.data.clone();
+            if &t.tag == b"head" && data.len() >= 12 {
+                data[8..12].fill(0);
+                head_at = Some(offset);
+            }
+            out.extend_from_slice(&t.tag);
+            out.extend_from_slice(&checksum(&data).to_be_bytes());
+            out.extend_from_slice(&(offset as u32).to_be_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            offset += data.len().next_multiple_of(4);
+        }
+        for t in &tables {
+            let start = out.len();
+            out.extend_from_slice(&t.data);
+            if &t.tag == b"head" && t.data.len() >= 12 {
+                out[start + 8..start + 12].fill(0);
+            }
+            out.resize(out.len().next_multiple_of(4), 0);
+        }
+        if let Some(at) = head_at {
+            let adjustment = 0xB1B0_AFBA_u32.wrapping_sub(checksum(&out));
+            out[at + 8..at + 12].copy_from_slice(&adjustment.to_be_bytes());
+        }
+        out
+    }
+}
+
+/// OpenType table checksum: sum of big-endian u32 words, zero padded.
+pub fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, c| {
+        let mut word = [0u8; 4];
+        word[..c.len()].copy_from_slice(c);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
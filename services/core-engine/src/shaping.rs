@@ -0,0 +1,163 @@
+//! Shaping check for subsets of complex-script text.
+//!
+//! Arabic joining, Indic conjuncts and reordering and the like come from
+//! GSUB and GPOS. Glyph IDs survive subsetting, so a subset whose closure
+//! missed a form still shapes to the same IDs, but the glyph it picks is
+//! empty and has no advance. When a subset keeps characters of such a
+//! script, reference words of those scripts made only of kept characters,
+//! and the request's own `characters`, are shaped with the source font
+//! (after feature pruning, which changes shaping on purpose) and with the
+//! subset; every glyph's ID, advance, offsets and outline must match.
+//! Divergences come back in the response's `shaping`, or fail the request
+//! with `422` under `"verify_shaping": "fail"`. With `layout_closure` off
+//! they are expected.
+
+use axum::http::StatusCode;
+use font_api::{ShapingDivergence, ShapingReport, VerifyShaping};
+use rustybuzz::{ttf_parser::GlyphId, Face, UnicodeBuffer};
+use std::collections::BTreeSet;
+
+/// Scripts that need shaping beyond one glyph per character.
+const COMPLEX: &[(u32, u32)] = &[
+    (0x0590, 0x08FF), // Hebrew, Arabic, Syriac, Thaana, NKo and Arabic supplements
+    (0x0900, 0x0DFF), // Indic scripts
+    (0x0E00, 0x0FFF), // Thai, Lao, Tibetan
+    (0x1000, 0x109F), // Myanmar
+    (0x1780, 0x18AF), // Khmer, Mongolian
+    (0xA8E0, 0xA8FF), // Devanagari Extended
+    (0xFB1D, 0xFDFF), // Hebrew and Arabic presentation forms
+    (0xFE70, 0xFEFF),
+];
+
+/// Words exercising joining, conjuncts, reordering and mark placement.
+const REFERENCE: &[&str] = &[
+    // Arabic, Persian
+    "العربية",
+    "بسم الله الرحمن الرحيم",
+    "لا إله",
+    "مرحبا بكم",
+    "فارسی",
+    // Hebrew
+    "שָׁלוֹם",
+    // Devanagari
+    "हिन्दी",
+    "क्षत्रिय",
+    "श्री",
+    "प्रेम",
+    "कृष्ण",
+    "द्विज",
+    // Bengali, Gurmukhi, Gujarati
+    "বাংলা",
+    "স্বপ্ন",
+    "ਪੰਜਾਬੀ",
+    "ગુજરાતી",
+    // Tamil, Telugu, Kannada, Malayalam, Sinhala
+    "தமிழ்",
+    "ஸ்ரீ",
+    "తెలుగు",
+    "ಕನ್ನಡ",
+    "മലയാളം",
+    "සිංහල",
+    // Thai, Lao, Tibetan, Myanmar, Khmer
+    "ภาษาไทย",
+    "น้ำ",
+    "ພາສາລາວ",
+    "བོད་ཡིག",
+    "မြန်မာ",
+    "ភាសាខ្មែរ",
+];
+
+/// Stretches of `characters` shaped as they are.
+const CHUNK: usize = 64;
+const MAX_CHUNKS: usize = 8;
+const MAX_REPORTED: usize = 20;
+
+fn complex(c: u32) -> bool {
+    COMPLEX.iter().any(|&(lo, hi)| (lo..=hi).contains(&c))
+}
+
+/// Whether a subset to `wanted` gets the check.
+pub fn applies(wanted: &BTreeSet<u32>, mode: Option<VerifyShaping>) -> bool {
+    mode != Some(VerifyShaping::Off) && wanted.iter().any(|&c| complex(c))
+}
+
+/// Per glyph: ID, advance, offsets and whether it has an outline.
+type Shaped = Vec<(u32, i32, i32, i32, bool)>;
+
+fn shape(face: &Face, text: &str) -> Shaped {
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.guess_segment_properties();
+    let glyphs = rustybuzz::shape(face, &[], buffer);
+    glyphs
+        .glyph_infos()
+        .iter()
+        .zip(glyphs.glyph_positions())
+        .map(|(i, p)| {
+            let inked = face.glyph_bounding_box(GlyphId(i.glyph_id as u16)).is_some();
+            (i.glyph_id, p.x_advance, p.x_offset, p.y_offset, inked)
+        })
+        .collect()
+}
+
+/// The first difference between two shapings of the same text.
+fn difference(before: &Shaped, after: &Shaped) -> Option<String> {
+    if before.len() != after.len() {
+        return Some(format!("{} glyphs instead of {}", after.len(), before.len()));
+    }
+    before.iter().zip(after).enumerate().find(|(_, (b, a))| b != a).map(|(n, (b, a))| {
+        if b.0 != a.0 {
+            format!("glyph {n} is ID {} instead of {}", a.0, b.0)
+        } else if b.4 && !a.4 {
+            format!("glyph {n} (ID {}) has no outline", b.0)
+        } else {
+            format!("glyph {n} (ID {}) is positioned differently", b.0)
+        }
+    })
+}
+
+/// Shapes the reference text with `source` and `subset`; `None` when
+/// either cannot be shaped.
+pub fn check(source: &[u8], subset: &[u8], wanted: &BTreeSet<u32>, characters: &str) -> Option<ShapingReport> {
+    let (source, subset) = (Face::from_slice(source, 0)?, Face::from_slice(subset, 0)?);
+    let kept = |text: &str| text.chars().all(|c| c == ' ' || wanted.contains(&(c as u32)));
+    let requested: Vec<char> = characters.chars().filter(|c| !c.is_control()).collect();
+    let texts: BTreeSet<String> = REFERENCE
+        .iter()
+        .map(|t| t.to_string())
+        .filter(|t| kept(t))
+        .chain(requested.chunks(CHUNK).take(MAX_CHUNKS).map(|c| c.iter().collect::<String>()))
+        .filter(|t| t.chars().any(|c| complex(c as u32)))
+        .collect();
+
+    let mut report = ShapingReport::default();
+    for text in texts {
+        report.checked += 1;
+        let before = shape(&source, &text);
+        if let Some(reason) = difference(&before, &shape(&subset, &text)) {
+            let glyphs = before.iter().map(|g| g.0).collect();
+            report.diverged.push(ShapingDivergence { text, glyphs, reason });
+        }
+    }
+    report.diverged.truncate(MAX_REPORTED);
+    Some(report)
+}
+
+/// Rejects the subset under `"verify_shaping": "fail"` when shaping
+/// diverged.
+pub fn enforce(mode: Option<VerifyShaping>, report: Option<&ShapingReport>) -> Result<(), (StatusCode, String)> {
+    let Some(report) = report.filter(|r| mode == Some(VerifyShaping::Fail) && !r.diverged.is_empty()) else {
+        return Ok(());
+    };
+    let first = &report.diverged[0];
+    Err((
+        StatusCode::UNPROCESSABLE_ENTITY,
+        format!(
+            "the subset shapes {} of {} reference strings differently, e.g. '{}': {}",
+            report.diverged.len(),
+            report.checked,
+            first.text,
+            first.reason
+        ),
+    ))
+}
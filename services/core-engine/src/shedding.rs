@@ -0,0 +1,166 @@
+//! Concurrency limits and load shedding for heavy routes.
+//!
+//! Routes that process fonts while the client waits (compress, subset and
+//! their variants, analyze, uploads) each run at most `CONCURRENCY_LIMIT`
+//! requests at once (default: the number of CPUs; `0` lifts the limit), or
+//! the route's own figure from `CONCURRENCY_LIMITS`
+//! (`subset=8,compress=2`). Up to `CONCURRENCY_QUEUE` more per route
+//! (default 16) wait for a slot, for at most `CONCURRENCY_WAIT_SECS`
+//! (default 10). A request finding the queue full, or still waiting when
+//! that runs out, gets `503` with `Retry-After` and is counted in
+//! `http_requests_shed_total`. Other routes (catalog, health, CDN files,
+//! job status) are never held back.
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+use crate::AppState;
+
+/// Limited routes, by the name `CONCURRENCY_LIMITS` knows them by.
+pub const ROUTES: &[(&str, &str)] = &[
+    ("compress", "/api/v1/font/compress"),
+    ("compress-batch", "/api/v1/font/compress/batch"),
+    ("compare-formats", "/api/v1/font/compare-formats"),
+    ("subset", "/api/v1/font/subset"),
+    ("subset-batch", "/api/v1/font/subset/batch"),
+    ("subset-merged", "/api/v1/font/subset/merged"),
+    ("subset-from-url", "/api/v1/font/subset-from-url"),
+    ("subset-progressive", "/api/v1/font/subset/progressive"),
+    ("patch-subset", "/api/v1/font/patch-subset"),
+    ("analyze", "/api/v1/font/analyze"),
+    ("upload", "/api/v1/font/upload"),
+];
+
+const DEFAULT_QUEUE: usize = 16;
+const DEFAULT_WAIT_SECS: u64 = 10;
+
+struct Gate {
+    name: &'static str,
+    limit: usize,
+    slots: Arc<Semaphore>,
+    waiting: AtomicUsize,
+    queue: usize,
+}
+
+/// Leaves the queue however the wait ends, including the client going away.
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub struct Shedding {
+    /// By route path; routes without a limit are absent.
+    gates: HashMap<&'static str, Gate>,
+    wait: Duration,
+}
+
+/// `CONCURRENCY_LIMITS` as route name → limit.
+pub fn parse_limits(spec: &str) -> Result<Vec<(&'static str, usize)>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|item| {
+            let (name, limit) = item
+                .split_once('=')
+                .ok_or_else(|| format!("CONCURRENCY_LIMITS: '{item}' should look like subset=4"))?;
+            let (name, _) = ROUTES.iter().find(|(n, _)| *n == name.trim()).ok_or_else(|| {
+                let known: Vec<_> = ROUTES.iter().map(|(n, _)| *n).collect();
+                format!("CONCURRENCY_LIMITS: unknown route '{}' (known: {})", name.trim(), known.join(", "))
+            })?;
+            let limit = limit
+                .trim()
+                .parse()
+                .map_err(|_| format!("CONCURRENCY_LIMITS: '{item}' needs a non-negative integer"))?;
+            Ok((*name, limit))
+        })
+        .collect()
+}
+
+impl Shedding {
+    pub fn from_env() -> Self {
+        let number = |k: &str| std::env::var(k).ok().and_then(|v| v.parse::<u64>().ok());
+        let default = number("CONCURRENCY_LIMIT")
+            .map(|n| n as usize)
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |n| n.get()));
+        let overrides = match parse_limits(&std::env::var("CONCURRENCY_LIMITS").unwrap_or_default()) {
+            Ok(overrides) => overrides,
+            Err(e) => {
+                warn!("{e}; ignoring CONCURRENCY_LIMITS");
+                Vec::new()
+            }
+        };
+        let queue = number("CONCURRENCY_QUEUE").map_or(DEFAULT_QUEUE, |n| n as usize);
+        let gates = ROUTES
+            .iter()
+            .filter_map(|&(name, path)| {
+                let limit = overrides.iter().rev().find(|(n, _)| *n == name).map_or(default, |&(_, l)| l);
+                let gate = Gate {
+                    name,
+                    limit,
+                    slots: Arc::new(Semaphore::new(limit)),
+                    waiting: AtomicUsize::new(0),
+                    queue,
+                };
+                (limit > 0).then_some((path, gate))
+            })
+            .collect();
+        Self { gates, wait: Duration::from_secs(number("CONCURRENCY_WAIT_SECS").unwrap_or(DEFAULT_WAIT_SECS)) }
+    }
+
+    /// Concurrency limit by route name; unlimited routes are absent.
+    pub fn limits(&self) -> BTreeMap<&'static str, usize> {
+        self.gates.values().map(|gate| (gate.name, gate.limit)).collect()
+    }
+}
+
+fn overloaded(gate: &Gate, reason: &'static str, retry_after: Duration) -> Response {
+    metrics::counter!("http_requests_shed_total", "route" => gate.name, "reason" => reason).increment(1);
+    warn!(route = gate.name, reason, "shedding request");
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, retry_after.as_secs().max(1).to_string())],
+        format!("too many {} requests in progress; retry later", gate.name),
+    )
+        .into_response()
+}
+
+pub async fn shed(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let shedding = &state.shedding;
+    let Some(gate) = request.extensions().get::<MatchedPath>().and_then(|p| shedding.gates.get(p.as_str())) else {
+        return next.run(request).await;
+    };
+    let permit = match Arc::clone(&gate.slots).try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(_) => {
+            if gate.waiting.fetch_add(1, Ordering::Relaxed) >= gate.queue {
+                gate.waiting.fetch_sub(1, Ordering::Relaxed);
+                return overloaded(gate, "queue_full", shedding.wait);
+            }
+            let _waiting = Waiting(&gate.waiting);
+            match tokio::time::timeout(shedding.wait, Arc::clone(&gate.slots).acquire_owned()).await {
+                Ok(Ok(permit)) => permit,
+                _ => return overloaded(gate, "wait_timeout", shedding.wait),
+            }
+        }
+    };
+    let response = next.run(request).await;
+    drop(permit);
+    response
+}
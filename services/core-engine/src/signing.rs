@@ -0,0 +1,164 @@
+//! Signed, expiring download URLs for private fonts.
+//!
+//! Generated files of a catalog entry marked `private` are only served from
+//! `/cdn/fonts/` with `?expires=<unix seconds>&signature=<hex>`, where the
+//! signature is an HMAC-SHA256 over `<path>:<expires>` keyed with
+//! `URL_SIGNING_SECRET`. Compress and subset sign the `download_url` of such
+//! fonts for `SIGNED_URL_TTL_SECS`; `POST /api/v1/font/:id/sign-url` mints
+//! URLs with other lifetimes. Without a secret, private files are not served
+//! at all.
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{SignRequest, SignedUrl};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::info;
+
+use crate::{artifacts, extract::ApiJson, storage, tenants, AppState};
+
+/// Longest lifetime a minted URL may have.
+const MAX_TTL_SECS: u64 = 7 * 24 * 3600;
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+pub struct UrlSigner {
+    secret: Option<String>,
+    ttl_secs: u64,
+}
+
+/// The signature part of a CDN request's query string.
+#[derive(Debug, Default, Deserialize)]
+pub struct Signature {
+    expires: Option<u64>,
+    signature: Option<String>,
+}
+
+fn forbidden(message: &str) -> (StatusCode, String) {
+    (StatusCode::FORBIDDEN, message.to_string())
+}
+
+pub fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+/// Whether `got` is the secret `want`, in time that does not depend on
+/// where they differ: each keys an HMAC of the same message and the tags are
+/// compared with [`Mac::verify_slice`].
+pub fn same_secret(want: &str, got: &str) -> bool {
+    let mac = |key: &str| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+        mac.update(b"font-engine secret");
+        mac
+    };
+    mac(got).verify_slice(&mac(want).finalize().into_bytes()).is_ok()
+}
+
+impl UrlSigner {
+    pub fn from_env() -> Self {
+        Self {
+            secret: std::env::var("URL_SIGNING_SECRET").ok().filter(|s| !s.is_empty()),
+            ttl_secs: std::env::var("SIGNED_URL_TTL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(3600),
+        }
+    }
+
+    fn mac(&self, path: &str, expires: u64) -> Option<Hmac<Sha256>> {
+        let secret = self.secret.as_deref()?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+        mac.update(format!("{path}:{expires}").as_bytes());
+        Some(mac)
+    }
+
+    /// `path` with the signature query appended; `None` while signing is
+    /// disabled.
+    pub fn sign(&self, path: &str, expires: u64) -> Option<String> {
+        let digest: String =
+            self.mac(path, expires)?.finalize().into_bytes().iter().map(|b| format!("{b:02x}")).collect();
+        Some(format!("{path}?expires={expires}&signature={digest}"))
+    }
+
+    /// Checks a request for `path` and returns the seconds it stays valid.
+    pub fn verify(&self, path: &str, query: &Signature) -> Result<u64, (StatusCode, String)> {
+        let (Some(expires), Some(signature)) = (query.expires, query.signature.as_deref()) else {
+            return Err(forbidden("this font needs a signed URL; ask the API for a fresh download_url"));
+        };
+        let mac = self.mac(path, expires).ok_or_else(|| forbidden("signed URLs are not configured"))?;
+        let valid = hex_decode(signature).is_some_and(|s| mac.verify_slice(&s).is_ok());
+        if !valid {
+            return Err(forbidden("invalid URL signature"));
+        }
+        expires.checked_sub(now()).filter(|&left| left > 0).ok_or_else(|| forbidden("signed URL has expired"))
+    }
+}
+
+/// Whether files under `/cdn/fonts/<slug>/` belong to a private catalog
+/// entry; artifacts are named after the font's id or family.
+pub fn is_private(state: &AppState, slug: &str) -> bool {
+    state
+        .catalog
+        .read()
+        .unwrap()
+        .iter()
+        .any(|e| e.private && (artifacts::slug(&e.id) == slug || artifacts::slug(&e.family) == slug))
+}
+
+/// The edge URL of the artifact at `path` (`/cdn/fonts/...`), under its
+/// tenant's prefix and signed for the default lifetime when its font is
+/// private.
+pub fn download_url(state: &AppState, path: &str) -> String {
+    let slug = path.trim_start_matches("/cdn/fonts/").split('/').next().unwrap_or_default();
+    let path = tenants::cdn_path(state, path);
+    let signed = is_private(state, slug)
+        .then(|| state.signer.sign(&path, now() + state.signer.ttl_secs))
+        .flatten();
+    state.edges.url_for(signed.as_deref().unwrap_or(&path))
+}
+
+pub async fn sign_url(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ApiJson(req): ApiJson<SignRequest>,
+) -> Result<Json<SignedUrl>, (StatusCode, String)> {
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &id)?;
+    let family = state.catalog.read().unwrap().iter().find(|e| e.id == id).map(|e| e.family.clone());
+    let family = family.ok_or_else(|| (StatusCode::NOT_FOUND, format!("no catalog entry '{id}'")))?;
+    let ttl = req.expires_in.unwrap_or(state.signer.ttl_secs);
+    if !(1..=MAX_TTL_SECS).contains(&ttl) {
+        return Err((StatusCode::BAD_REQUEST, format!("expires_in must be 1-{MAX_TTL_SECS} seconds")));
+    }
+
+    // Edge URLs differ by host; only the path is signed.
+    let path = req.url.split(['?', '#']).next().unwrap_or_default();
+    let key = tenants::artifact_key(path);
+    let path = tenants::cdn_path(&state, &format!("/cdn/fonts/{key}"));
+    let not_this_font =
+        || (StatusCode::BAD_REQUEST, format!("'{}' is not a /cdn/fonts/ URL of '{id}'", req.url));
+    let (slug, file) = key.split_once('/').ok_or_else(not_this_font)?;
+    if (slug != artifacts::slug(&id) && slug != artifacts::slug(&family)) || file.is_empty() || file.contains('/') {
+        return Err(not_this_font());
+    }
+    if !state.artifacts.exists(key).await.map_err(storage::error("looking up artifact"))? {
+        return Err((StatusCode::NOT_FOUND, format!("no font at {path}")));
+    }
+
+    let expires_at = now() + ttl;
+    let signed = state.signer.sign(&path, expires_at).ok_or_else(|| {
+        (StatusCode::SERVICE_UNAVAILABLE, "URL signing is disabled; URL_SIGNING_SECRET is not set".to_string())
+    })?;
+    info!(id = %id, path = %path, expires_at, "download URL signed");
+    Ok(Json(SignedUrl { url: state.edges.url_for(&signed), expires_at }))
+}
@@ -0,0 +1,446 @@
+//! Unicode-range slicing of large catalog fonts.
+//!
+//! `POST /api/v1/font/slices` cuts a catalog font into about a hundred
+//! subsets, Google Fonts style: the characters the font maps are ordered by
+//! frequency (see [`progressive`]) and then by code point, and split into
+//! equal runs. Every slice is stored as an artifact and the plan is kept as
+//! a manifest (see [`storage`]; `ARTIFACT_DIR/.slices` on local disk). Once
+//! a font is sliced, family CSS declares one face per slice with its
+//! `unicode-range`, so a page only downloads the slices its text touches.
+//! Large fonts are better sliced as a job (`POST /api/v1/jobs/slices`, see
+//! [`crate::queue`]): the job checkpoints after every stored slice, so one
+//! interrupted by a deploy resumes where it stopped instead of starting over.
+//!
+//! Operators can name other partitions in `SLICING_PROFILES`, `;`-separated:
+//! `jp-120-slices=120` cuts 120 runs the same way, and
+//! `latin-2-slices=U+0000-00FF U+0131|U+0100-024F` makes one slice per
+//! `|`-separated group of ranges plus one for the mapped characters outside
+//! them. `{"profile": "jp-120-slices"}` slices by a profile; a font keeps one
+//! slicing per profile beside the plain one, and family CSS picks one with
+//! `?slicing=` (see [`crate::stylesheet`]).
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{Manifest, Slice, SliceRequest};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::RangeInclusive,
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+use crate::{
+    audit, cancel,
+    cmap::CharMap,
+    compress, duplicates,
+    extract::ApiJson,
+    progressive,
+    storage::{self, FontStorage},
+    subset, tenants, unicode, AppState,
+};
+
+const MAX_SLICES: usize = 256;
+const DEFAULT_SLICES: usize = 100;
+
+/// The `?slicing=` value that serves whole fonts, even sliced ones.
+pub const NONE: &str = "none";
+
+/// How a slicing profile partitions a font.
+#[derive(Debug, Clone)]
+pub enum Strategy {
+    /// Frequent characters first, cut into this many equal runs.
+    Runs(usize),
+    /// One slice per group of ranges, then one for the rest.
+    Ranges(Vec<Vec<RangeInclusive<u32>>>),
+}
+
+/// `SLICING_PROFILES` as profile name → strategy.
+pub fn parse_profiles(spec: &str) -> Result<BTreeMap<String, Strategy>, String> {
+    let mut profiles = BTreeMap::new();
+    for item in spec.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+        let (name, plan) = item
+            .split_once('=')
+            .ok_or_else(|| format!("SLICING_PROFILES: '{item}' should look like jp-120-slices=120"))?;
+        let name = name.trim();
+        let valid = name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if name.is_empty() || name == NONE || !valid {
+            let message = format!("'{name}' must be lowercase letters, digits and '-', and not '{NONE}'");
+            return Err(format!("SLICING_PROFILES: {message}"));
+        }
+        let strategy = match plan.trim().parse::<usize>() {
+            Ok(count) if (1..=MAX_SLICES).contains(&count) => Strategy::Runs(count),
+            Ok(_) => return Err(format!("SLICING_PROFILES: {name} needs 1-{MAX_SLICES} slices")),
+            Err(_) => {
+                let groups = plan
+                    .split('|')
+                    .map(|group| {
+                        group
+                            .split_whitespace()
+                            .map(|r| {
+                                unicode::parse_range(r).ok_or_else(|| {
+                                    format!("SLICING_PROFILES: {name}: '{r}' is not a count or U+XXXX-YYYY range")
+                                })
+                            })
+                            .collect::<Result<Vec<_>, _>>()
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if groups.iter().any(Vec::is_empty) || groups.len() >= MAX_SLICES {
+                    return Err(format!("SLICING_PROFILES: {name} needs 1-{} non-empty groups", MAX_SLICES - 1));
+                }
+                Strategy::Ranges(groups)
+            }
+        };
+        if profiles.insert(name.to_string(), strategy).is_some() {
+            return Err(format!("SLICING_PROFILES: '{name}' is defined twice"));
+        }
+    }
+    Ok(profiles)
+}
+
+/// A manifest's catalog ID and profile.
+type Key = (String, Option<String>);
+
+/// Manifests of sliced catalog fonts, by catalog ID and profile, and the
+/// configured profiles.
+pub struct Slices {
+    manifests: RwLock<BTreeMap<Key, Manifest>>,
+    profiles: BTreeMap<String, Strategy>,
+    storage: Arc<dyn FontStorage>,
+}
+
+fn manifest_key(id: &str, profile: Option<&str>) -> String {
+    match profile {
+        Some(profile) => format!("{id}@{profile}.json"),
+        None => format!("{id}.json"),
+    }
+}
+
+impl Slices {
+    pub async fn load(storage: Arc<dyn FontStorage>) -> Self {
+        let mut manifests = BTreeMap::new();
+        let keys = storage.list("").await.unwrap_or_else(|e| {
+            warn!("{}: cannot list slice manifests: {e}", storage.location());
+            Vec::new()
+        });
+        for key in keys.iter().filter(|k| k.ends_with(".json")) {
+            let raw = match storage.get(key).await {
+                Ok(Some(raw)) => raw,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("{key}: cannot read slice manifest: {e}");
+                    continue;
+                }
+            };
+            match serde_json::from_slice::<Manifest>(&raw) {
+                Ok(m) => {
+                    manifests.insert((m.font_id.clone(), m.profile.clone()), m);
+                }
+                Err(e) => warn!("{key}: unreadable slice manifest: {e}"),
+            }
+        }
+        let profiles = parse_profiles(&std::env::var("SLICING_PROFILES").unwrap_or_default()).unwrap_or_else(|e| {
+            warn!("{e}; ignoring SLICING_PROFILES");
+            BTreeMap::new()
+        });
+        Self { manifests: RwLock::new(manifests), profiles, storage }
+    }
+
+    /// The manifest of a catalog entry sliced by `profile`, or without one.
+    pub fn get(&self, id: &str, profile: Option<&str>) -> Option<Manifest> {
+        self.manifests.read().unwrap().get(&(id.to_string(), profile.map(String::from))).cloned()
+    }
+
+    /// Checks that `profile` is configured.
+    pub fn ensure_profile(&self, profile: &str) -> Result<(), (StatusCode, String)> {
+        if self.profiles.contains_key(profile) {
+            return Ok(());
+        }
+        let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+        let known = if known.is_empty() { "none are configured".to_string() } else { known.join(", ") };
+        Err((StatusCode::BAD_REQUEST, format!("unknown slicing profile '{profile}' (known: {known})")))
+    }
+
+    /// Artifact keys of the files current slicings point at.
+    pub fn keys(&self) -> BTreeSet<String> {
+        let manifests = self.manifests.read().unwrap();
+        let paths = manifests.values().flat_map(|m| m.slices.iter().flat_map(|s| s.files.values()));
+        paths.map(|path| tenants::artifact_key(path).to_string()).collect()
+    }
+}
+
+/// Code points in slice order: frequent characters first, the rest by code
+/// point, cut into `count` runs of (nearly) equal length.
+fn runs(mapped: &BTreeSet<u32>, count: usize) -> Vec<Vec<u32>> {
+    let mut seen = BTreeSet::new();
+    let ordered: Vec<u32> = progressive::LATIN_FREQUENT
+        .chars()
+        .chain(progressive::JA_FREQUENT.chars())
+        .chain(progressive::ZH_FREQUENT.chars())
+        .chain(progressive::KO_FREQUENT.chars())
+        .map(|c| c as u32)
+        .chain(mapped.iter().copied())
+        .filter(|c| mapped.contains(c) && seen.insert(*c))
+        .collect();
+    let count = count.min(ordered.len());
+    (0..count)
+        .map(|i| {
+            let mut slice = ordered[i * ordered.len() / count..(i + 1) * ordered.len() / count].to_vec();
+            slice.sort_unstable();
+            slice
+        })
+        .collect()
+}
+
+/// The mapped code points of each slice under `strategy`; empty slices are
+/// left out.
+fn plan(mapped: &BTreeSet<u32>, strategy: &Strategy) -> Vec<Vec<u32>> {
+    let groups = match strategy {
+        Strategy::Runs(count) => return runs(mapped, *count),
+        Strategy::Ranges(groups) => groups,
+    };
+    let mut rest = mapped.clone();
+    let mut slices: Vec<Vec<u32>> = groups
+        .iter()
+        .map(|ranges| {
+            let slice: Vec<u32> = rest.iter().copied().filter(|c| ranges.iter().any(|r| r.contains(c))).collect();
+            slice.iter().for_each(|c| {
+                rest.remove(c);
+            });
+            slice
+        })
+        .collect();
+    slices.push(rest.into_iter().collect());
+    slices.retain(|slice| !slice.is_empty());
+    slices
+}
+
+/// The manifest with CDN paths turned into edge URLs.
+fn with_urls(state: &AppState, mut manifest: Manifest) -> Manifest {
+    for slice in &mut manifest.slices {
+        slice.files.values_mut().for_each(|path| *path = state.edges.url_for(&tenants::cdn_path(state, path)));
+    }
+    manifest
+}
+
+fn unicode_range(points: &[u32]) -> String {
+    unicode::css_unicode_range(&unicode::merge(points.iter().map(|&p| p..=p).collect()))
+}
+
+/// The slices done so far by an earlier run of the same slicing, from its
+/// checkpoint; none when that was of another source or other formats.
+fn resume(checkpoint: Option<Value>, source: &str, formats: &[String], planned: &[Vec<u32>]) -> Vec<Slice> {
+    let Some(checkpoint) = checkpoint.filter(|c| c["source"] == source && c["formats"] == json!(formats)) else {
+        return Vec::new();
+    };
+    let done: Vec<Slice> = serde_json::from_value(checkpoint["slices"].clone()).unwrap_or_default();
+    done.into_iter()
+        .zip(planned)
+        .take_while(|(slice, points)| slice.unicode_range == unicode_range(points))
+        .map(|(slice, _)| slice)
+        .collect()
+}
+
+/// Catalog ID and family of `font_name`.
+pub fn catalog_entry(state: &AppState, font_name: &str) -> Result<(String, String), (StatusCode, String)> {
+    let key = font_name.to_lowercase();
+    state
+        .catalog
+        .read()
+        .unwrap()
+        .iter()
+        .find(|e| e.id == key || e.family.to_lowercase() == key)
+        .map(|e| (e.id.clone(), e.family.clone()))
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("'{font_name}' is not in the catalog")))
+}
+
+pub async fn slice(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(req): ApiJson<SliceRequest>,
+) -> Result<Json<Manifest>, (StatusCode, String)> {
+    state.flags.ensure("subset", &headers)?;
+    state.quotas.charge_operation(&headers)?;
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &req.font_name)?;
+    let strategy = match (&req.profile, req.slices) {
+        (Some(_), Some(_)) => {
+            return Err((StatusCode::BAD_REQUEST, "give slices or profile, not both".to_string()));
+        }
+        (Some(profile), None) => {
+            state.slices.ensure_profile(profile)?;
+            state.slices.profiles[profile].clone()
+        }
+        (None, count) => {
+            let count = count.unwrap_or(DEFAULT_SLICES);
+            if !(1..=MAX_SLICES).contains(&count) {
+                return Err((StatusCode::BAD_REQUEST, format!("slices must be 1-{MAX_SLICES}")));
+            }
+            Strategy::Runs(count)
+        }
+    };
+    if req.formats.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "formats must name at least one format".to_string()));
+    }
+    if let Some(f) = req.formats.iter().find(|f| !["woff2", "woff", "otf", "ttf"].contains(&f.as_str())) {
+        return Err((StatusCode::BAD_REQUEST, format!("unsupported format '{f}'; valid: woff2, woff, otf, ttf")));
+    }
+    let (id, family) = catalog_entry(&state, &req.font_name)?;
+    let source = state.artifacts.source(&state, &id, None).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let (job_state, job_id) = (Arc::clone(&state), id.clone());
+    let (data, mapped) = cancel::run(&state.jobs, "slice", move |_| {
+        let data = match duplicates::catalog_binary(&job_state, &job_id) {
+            Ok(data) => data,
+            Err(e) => return Ok(Err(e)),
+        };
+        let mapped: Result<BTreeSet<u32>, String> = compress::load(&data).and_then(|font| {
+            CharMap::parse(font.table(b"cmap").ok_or("font has no cmap table")?)
+                .map(|cmap| cmap.ranges().into_iter().flatten().collect())
+        });
+        Ok(mapped.map(|mapped| (Arc::new(data), mapped)))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    // Each slice is stored as it is done, and a job started again after a
+    // restart goes on from the last one its checkpoint records.
+    let planned = plan(&mapped, &strategy);
+    let mut slices = resume(cancel::resumed(), &source, &req.formats, &planned);
+    if !slices.is_empty() {
+        info!(font = %id, done = slices.len(), planned = planned.len(), "resuming slicing from a checkpoint");
+    }
+    let slicing = async {
+        for points in &planned[slices.len()..] {
+            let (slice_data, slice_points, formats) = (Arc::clone(&data), points.clone(), req.formats.clone());
+            let files = cancel::run(&state.jobs, "slice", move |token| {
+                token.check()?;
+                Ok(compress::load(&slice_data).and_then(|mut font| {
+                    subset::subset(&mut font, &slice_points.into_iter().collect(), true, false)?;
+                    let encode = |f: &String| Ok((f.clone(), compress::encode(&font, f, 100)?));
+                    formats.iter().map(encode).collect::<Result<Vec<_>, String>>()
+                }))
+            })
+            .await?
+            .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+            let size_kb = files.first().map_or(0.0, |(_, data)| data.len() as f64 / 1024.0);
+            let mut paths = BTreeMap::new();
+            for (format, data) in files {
+                let address = state.artifacts.address(&family, &source, &json!({ "slice": points }), &format);
+                state.artifacts.put(&address, &data, &json!({ "output_bytes": data.len() })).await?;
+                paths.insert(format, address.path());
+            }
+            let unicode_range = unicode_range(points);
+            slices.push(Slice { unicode_range, code_points: points.len(), files: paths, size_kb });
+            cancel::checkpoint(json!({ "source": source, "formats": req.formats, "slices": slices }));
+        }
+        Ok::<_, (StatusCode, String)>(())
+    };
+    tokio::time::timeout(cancel::limit(), slicing)
+        .await
+        .map_err(|_| (StatusCode::SERVICE_UNAVAILABLE, "slice timed out".to_string()))??;
+
+    let manifest = Manifest {
+        font_id: id.clone(),
+        family,
+        profile: req.profile,
+        formats: req.formats,
+        original_size_kb: data.len() as f64 / 1024.0,
+        total_size_kb: slices.iter().map(|s| s.size_kb).sum(),
+        slices,
+        sliced_at_unix: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+    };
+    let raw = serde_json::to_vec_pretty(&manifest).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let key = manifest_key(&id, manifest.profile.as_deref());
+    state.slices.storage.put(&key, raw).await.map_err(storage::error("storing slice manifest"))?;
+    state.slices.manifests.write().unwrap().insert((id.clone(), manifest.profile.clone()), manifest.clone());
+
+    info!(
+        font = %id,
+        profile = ?manifest.profile,
+        slices = manifest.slices.len(),
+        original_kb = manifest.original_size_kb,
+        total_kb = manifest.total_size_kb,
+        "font sliced"
+    );
+    Ok(Json(with_urls(&state, manifest)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileQuery {
+    /// The slicing profile; the plain slicing when absent.
+    profile: Option<String>,
+}
+
+fn not_sliced(id: &str, profile: Option<&str>) -> (StatusCode, String) {
+    match profile {
+        Some(profile) => (StatusCode::NOT_FOUND, format!("'{id}' is not sliced by '{profile}'")),
+        None => (StatusCode::NOT_FOUND, format!("'{id}' is not sliced")),
+    }
+}
+
+pub async fn show(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(font): Path<String>,
+    Query(query): Query<ProfileQuery>,
+) -> Result<Json<Manifest>, (StatusCode, String)> {
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &font)?;
+    let (id, _) = catalog_entry(&state, &font)?;
+    let profile = query.profile.as_deref();
+    let manifest = state.slices.get(&id, profile).ok_or_else(|| not_sliced(&id, profile))?;
+    Ok(Json(with_urls(&state, manifest)))
+}
+
+/// Forgets the slicing, so family CSS points at the whole font again. Slice
+/// artifacts stay, as cached stylesheets may still reference them.
+pub async fn delete(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(font): Path<String>,
+    Query(query): Query<ProfileQuery>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &font)?;
+    let (id, _) = catalog_entry(&state, &font)?;
+    let profile = query.profile.as_deref();
+    let Some(manifest) = state.slices.manifests.write().unwrap().remove(&(id.clone(), query.profile.clone())) else {
+        return Err(not_sliced(&id, profile));
+    };
+    let key = manifest_key(&id, profile);
+    state.slices.storage.delete(&key).await.map_err(storage::error("deleting slice manifest"))?;
+    audit::record(&state, &headers, "slices.delete", &id, json!(manifest), Value::Null).await;
+    info!(font = %id, profile = ?profile, "font slices removed");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn done(points: &[u32]) -> Slice {
+        let files = BTreeMap::from([("woff2".to_string(), format!("/fonts/{}.woff2", points[0]))]);
+        Slice { unicode_range: unicode_range(points), code_points: points.len(), files, size_kb: 1.0 }
+    }
+
+    #[test]
+    fn resumes_from_the_slices_a_checkpoint_records() {
+        let planned = vec![vec![0x41, 0x42], vec![0x43], vec![0x3042, 0x3044]];
+        let formats = ["woff2".to_string()];
+        let checkpoint = json!({ "source": "abc", "formats": formats, "slices": [done(&[0x41, 0x42]), done(&[0x43])] });
+
+        let resumed = resume(Some(checkpoint.clone()), "abc", &formats, &planned);
+        assert_eq!(resumed.iter().map(|s| s.unicode_range.as_str()).collect::<Vec<_>>(), ["U+0041-0042", "U+0043"]);
+        assert_eq!(resumed[1].files["woff2"], "/fonts/67.woff2");
+
+        assert!(resume(None, "abc", &formats, &planned).is_empty());
+        assert!(resume(Some(checkpoint.clone()), "other", &formats, &planned).is_empty(), "the font changed");
+        let woff = ["woff".to_string()];
+        assert!(resume(Some(checkpoint.clone()), "abc", &woff, &planned).is_empty(), "other formats");
+        let replanned = vec![vec![0x41, 0x42], vec![0x43, 0x44]];
+        assert_eq!(resume(Some(checkpoint), "abc", &formats, &replanned).len(), 1, "only the slices still planned");
+    }
+}
@@ -0,0 +1,117 @@
+//! On-the-fly slimming for display text.
+//!
+//! `GET /api/v1/font/slim?family=Inter&text=Hello%20World` subsets a catalog
+//! font to exactly the characters of `text` and returns the font itself, so
+//! a banner or hero headline can point `@font-face` straight at the URL. The
+//! same query always yields the same bytes, so responses are cacheable, per
+//! the `generated` policy (see [`crate::cache_control`]), and carry a
+//! content-hash `ETag`. Private fonts are not slimmed: their files
+//! need signed URLs (see [`crate::signing`]). `format=data-uri` returns the
+//! WOFF2 font as a `data:font/woff2;base64,...` string instead, to paste
+//! into an email or a single-file HTML page.
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeSet, sync::Arc};
+use tracing::info;
+
+use crate::{artifacts, cache_control::Policy, cancel, compress, duplicates, signing, subset, AppState};
+
+/// Distinct characters a slim font may hold; longer texts belong in
+/// `/api/v1/font/subset`.
+const MAX_CHARACTERS: usize = 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct SlimQuery {
+    family: String,
+    text: String,
+    #[serde(default = "default_format")]
+    format: String,
+}
+
+fn default_format() -> String {
+    "woff2".to_string()
+}
+
+pub async fn slim(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<SlimQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    state.flags.ensure("subset", &headers)?;
+    state.quotas.charge_operation(&headers)?;
+    if !["woff2", "woff", "otf", "ttf", "data-uri"].contains(&query.format.as_str()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("unsupported format '{}'; valid: woff2, woff, otf, ttf, data-uri", query.format),
+        ));
+    }
+    let inline = query.format == "data-uri";
+    let wanted: BTreeSet<u32> = query.text.chars().map(|c| c as u32).collect();
+    if wanted.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "text is required".to_string()));
+    }
+    if wanted.len() > MAX_CHARACTERS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("text has {} distinct characters; at most {MAX_CHARACTERS}", wanted.len()),
+        ));
+    }
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &query.family)?;
+    if signing::is_private(&state, &artifacts::slug(&query.family)) {
+        let message = format!("'{}' is private; compress or subset it for a signed download_url", query.family);
+        return Err((StatusCode::FORBIDDEN, message));
+    }
+    state.sandbox.ensure_characters(&headers, wanted.len())?;
+
+    let format = if inline { "woff2".to_string() } else { query.format.clone() };
+    let (job_state, family, job_format) = (Arc::clone(&state), query.family.clone(), format.clone());
+    let (report, encoded) = cancel::run(&state.jobs, "slim", move |token| {
+        let data = duplicates::catalog_binary(&job_state, &family);
+        token.check()?;
+        Ok(data.and_then(|data| {
+            let mut font = compress::load(&data)?;
+            let report = subset::subset(&mut font, &wanted, true, false)?;
+            Ok((report, compress::encode(&font, &job_format, 100)?))
+        }))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let (content_type, body) = if inline {
+        let uri = compress::data_uri(&format, &encoded).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+        ("text/plain; charset=utf-8", uri.into_bytes())
+    } else {
+        (compress::media_type(&format), encoded)
+    };
+
+    let etag = format!("\"{}\"", &format!("{:x}", Sha256::digest(&body))[..16]);
+    let cached = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').map(|t| t.trim().trim_start_matches("W/")).any(|t| t == "*" || t == etag));
+
+    info!(
+        family = %query.family,
+        characters = report.characters,
+        missing = report.missing,
+        glyphs = report.glyphs,
+        format = %query.format,
+        bytes = body.len(),
+        "font slimmed"
+    );
+
+    let response = Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CACHE_CONTROL, state.cache_policies.get(Policy::Generated))
+        .header(header::ETAG, &etag);
+    if cached {
+        return Ok(response.status(StatusCode::NOT_MODIFIED).body(Body::empty()).unwrap());
+    }
+    Ok(response.header(header::CONTENT_LENGTH, body.len()).body(Body::from(body)).unwrap())
+}
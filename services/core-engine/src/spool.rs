@@ -0,0 +1,138 @@
+//! Streaming intake of large request bodies.
+//!
+//! Bodies are written to a spool file chunk by chunk as they arrive, with the
+//! SHA-256 computed on the fly, so a multi-megabyte font never sits in memory
+//! and a slow disk pushes back on the client through TCP flow control.
+
+use axum::{body::Body, http::StatusCode};
+use crate::sfnt;
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+
+/// Upper bound for a single uploaded body (`MAX_UPLOAD_BYTES`, default 50 MiB).
+pub fn max_upload_bytes() -> u64 {
+    std::env::var("MAX_UPLOAD_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(50 * 1024 * 1024)
+}
+
+pub fn spool_dir() -> PathBuf {
+    std::env::var("SPOOL_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| std::env::temp_dir().join("font-engine-spool"))
+}
+
+/// A fully received body on disk. The file is deleted on drop unless it was
+/// moved elsewhere with [`SpooledFile::persist`].
+#[derive(Debug)]
+pub struct SpooledFile {
+    path: Option<PathBuf>,
+    pub sha256: String,
+    pub size: u64,
+}
+
+impl SpooledFile {
+    pub fn path(&self) -> &Path {
+        self.path.as_deref().expect("spooled file already persisted")
+    }
+
+    /// Moves the file to `dest`; it is no longer deleted on drop.
+    pub async fn persist(mut self, dest: &Path) -> std::io::Result<()> {
+        let src = self.path.take().expect("spooled file already persisted");
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        if tokio::fs::rename(&src, dest).await.is_err() {
+            // Different filesystem: fall back to copy + delete.
+            tokio::fs::copy(&src, dest).await?;
+            tokio::fs::remove_file(&src).await?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SpooledFile {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Reads up to `max` bytes from the start of the file at `path`.
+pub async fn read_head(path: &Path, max: usize) -> std::io::Result<Vec<u8>> {
+    let file = File::open(path).await?;
+    let mut head = Vec::with_capacity(max.min(64 * 1024));
+    file.take(max as u64).read_to_end(&mut head).await?;
+    Ok(head)
+}
+
+/// A spool file being written, hashed as it grows.
+pub struct Writer {
+    file: File,
+    hasher: Sha256,
+    limit: u64,
+    // Removes the partial file on any early return.
+    spooled: SpooledFile,
+}
+
+impl Writer {
+    /// Starts a new spool file that takes at most `limit` bytes.
+    pub async fn create(limit: u64) -> Result<Self, (StatusCode, String)> {
+        let dir = spool_dir();
+        tokio::fs::create_dir_all(&dir).await.map_err(io_err)?;
+        let path = dir.join(format!("{}.part", uuid::Uuid::new_v4()));
+        let file = File::create(&path).await.map_err(io_err)?;
+        let spooled = SpooledFile { path: Some(path), sha256: String::new(), size: 0 };
+        Ok(Self { file, hasher: Sha256::new(), limit, spooled })
+    }
+
+    pub async fn write(&mut self, chunk: &[u8]) -> Result<(), (StatusCode, String)> {
+        self.spooled.size += chunk.len() as u64;
+        if self.spooled.size > self.limit {
+            return Err((StatusCode::PAYLOAD_TOO_LARGE, format!("body exceeds the {}-byte upload limit", self.limit)));
+        }
+        self.hasher.update(chunk);
+        self.file.write_all(chunk).await.map_err(io_err)
+    }
+
+    pub async fn finish(mut self) -> Result<SpooledFile, (StatusCode, String)> {
+        self.file.flush().await.map_err(io_err)?;
+        self.spooled.sha256 = format!("{:x}", self.hasher.finalize());
+        Ok(self.spooled)
+    }
+}
+
+fn io_err(e: std::io::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, format!("spool write failed: {e}"))
+}
+
+/// Streams `body` into a new spool file, enforcing `limit` bytes.
+pub async fn spool(body: Body, limit: u64) -> Result<SpooledFile, (StatusCode, String)> {
+    let mut writer = Writer::create(limit).await?;
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| (StatusCode::BAD_REQUEST, format!("body read failed: {e}")))?;
+        writer.write(&chunk).await?;
+    }
+    writer.finish().await
+}
+
+/// Spools a raw font upload and loads it for a transform, rejecting bodies
+/// that fail the structural check with `422`.
+pub async fn receive_font(body: Body) -> Result<(Vec<u8>, sfnt::Flavor), (StatusCode, String)> {
+    let file = spool(body, max_upload_bytes()).await?;
+    let data = tokio::fs::read(file.path())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("reading spooled body: {e}")))?;
+    let flavor = sfnt::check_structure(&data, file.size).map_err(|problems| {
+        (StatusCode::UNPROCESSABLE_ENTITY, format!("not a usable font: {}", problems.join("; ")))
+    })?;
+    Ok((data, flavor))
+}
@@ -0,0 +1,241 @@
+//! Color emoji sprite sheets.
+//!
+//! For platforms that cannot use a color font directly, selected emoji are
+//! cut from the font's embedded bitmaps (sbix or CBDT), scaled to a square
+//! cell and packed into one PNG or WebP image with a coordinate map. Vector
+//! color formats (COLR, SVG) would need a rasterizer and are rejected.
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+use tracing::info;
+
+use crate::{
+    bitmap::Bitmaps,
+    cancel::CancelToken,
+    cmap::CharMap,
+    isolation,
+    sfnt::Font,
+    spool, AppState,
+};
+
+const MAX_EMOJI: usize = 512;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Webp,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpriteQuery {
+    /// The emoji to include, as literal characters.
+    emoji: String,
+    #[serde(default = "default_size")]
+    size: u16,
+    #[serde(default)]
+    format: ImageFormat,
+}
+
+fn default_size() -> u16 {
+    64
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SpriteRect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SpriteSheet {
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    cell: u32,
+    strike_ppem: u16,
+    /// `data:` URI of the packed image.
+    image: String,
+    sprites: BTreeMap<String, SpriteRect>,
+    /// Requested emoji the font has no bitmap for.
+    missing: Vec<String>,
+}
+
+struct Rgba {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+fn decode_png(data: &[u8]) -> Result<Rgba, String> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+    buf.truncate(info.buffer_size());
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => buf.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return Err("indexed PNG was not expanded".to_string()),
+    };
+    Ok(Rgba { width: info.width, height: info.height, pixels })
+}
+
+/// Box-filters `src` into a `cell`×`cell` square, preserving aspect ratio
+/// and centering; alpha is premultiplied while averaging.
+fn blit_scaled(src: &Rgba, dst: &mut [u8], dst_width: u32, ox: u32, oy: u32, cell: u32) {
+    let scale = (src.width.max(src.height) as f64 / cell as f64).max(f64::MIN_POSITIVE);
+    let (w, h) = ((src.width as f64 / scale).round() as u32, (src.height as f64 / scale).round() as u32);
+    let (pad_x, pad_y) = ((cell - w.min(cell)) / 2, (cell - h.min(cell)) / 2);
+    for y in 0..h.min(cell) {
+        for x in 0..w.min(cell) {
+            let (x0, x1) = ((x as f64 * scale) as u32, (((x + 1) as f64 * scale).ceil() as u32).min(src.width));
+            let (y0, y1) = ((y as f64 * scale) as u32, (((y + 1) as f64 * scale).ceil() as u32).min(src.height));
+            let mut acc = [0f64; 4];
+            let mut n = 0f64;
+            for sy in y0..y1.max(y0 + 1).min(src.height) {
+                for sx in x0..x1.max(x0 + 1).min(src.width) {
+                    let p = &src.pixels[4 * (sy * src.width + sx) as usize..][..4];
+                    let a = p[3] as f64 / 255.0;
+                    acc[0] += p[0] as f64 * a;
+                    acc[1] += p[1] as f64 * a;
+                    acc[2] += p[2] as f64 * a;
+                    acc[3] += p[3] as f64;
+                    n += 1.0;
+                }
+            }
+            if n == 0.0 {
+                continue;
+            }
+            let alpha = acc[3] / n;
+            let un = |c: f64| if alpha > 0.0 { (c / n * 255.0 / alpha).round().min(255.0) as u8 } else { 0 };
+            let at = 4 * ((oy + pad_y + y) * dst_width + ox + pad_x + x) as usize;
+            dst[at..at + 4].copy_from_slice(&[un(acc[0]), un(acc[1]), un(acc[2]), alpha.round() as u8]);
+        }
+    }
+}
+
+pub fn encode(format: ImageFormat, pixels: &[u8], width: u32, height: u32) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    match format {
+        ImageFormat::Png => {
+            let mut encoder = png::Encoder::new(&mut out, width, height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+            writer.write_image_data(pixels).map_err(|e| e.to_string())?;
+        }
+        ImageFormat::Webp => {
+            image_webp::WebPEncoder::new(&mut out)
+                .encode(pixels, width, height, image_webp::ColorType::Rgba8)
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(out)
+}
+
+/// Cuts and packs the sheet in the parse worker.
+#[derive(Serialize, Deserialize)]
+pub struct Pack {
+    emoji: Vec<char>,
+    size: u16,
+    format: ImageFormat,
+}
+
+impl isolation::Task for Pack {
+    const NAME: &'static str = "sprite";
+    type Output = SpriteSheet;
+
+    fn run(self, data: Vec<u8>, _: &CancelToken) -> Result<SpriteSheet, String> {
+        let (cell, format) = (self.size as u32, self.format);
+        let font = Font::parse(&data)?;
+        let bitmaps = Bitmaps::from_font(&font).ok_or_else(|| {
+            if font.table(b"COLR").is_some() || font.table(b"SVG ").is_some() {
+                "vector color fonts (COLR/SVG) are not supported; use a bitmap (sbix/CBDT) emoji font".to_string()
+            } else {
+                "font has no color bitmaps (sbix or CBDT)".to_string()
+            }
+        })?;
+        let strike = bitmaps.best_strike(self.size).ok_or("font has no bitmap strikes")?;
+        let cmap = CharMap::parse(font.table(b"cmap").ok_or("font has no cmap table")?)?;
+
+        let mut images = Vec::new();
+        let mut missing = Vec::new();
+        for c in self.emoji {
+            let png = cmap.glyph(c).and_then(|g| bitmaps.png(strike, g));
+            match png.map(decode_png) {
+                Some(Ok(img)) => images.push((c, img)),
+                Some(Err(e)) => return Err(format!("bitmap for U+{:04X} is not a valid PNG: {e}", c as u32)),
+                None => missing.push(c.to_string()),
+            }
+        }
+        if images.is_empty() {
+            return Err("none of the requested emoji have bitmaps in this font".to_string());
+        }
+
+        let columns = (images.len() as f64).sqrt().ceil() as u32;
+        let rows = (images.len() as u32).div_ceil(columns);
+        let (width, height) = (columns * cell, rows * cell);
+        let mut pixels = vec![0u8; (width * height * 4) as usize];
+        let mut sprites = BTreeMap::new();
+        for (i, (c, img)) in images.iter().enumerate() {
+            let (x, y) = ((i as u32 % columns) * cell, (i as u32 / columns) * cell);
+            blit_scaled(img, &mut pixels, width, x, y, cell);
+            sprites.insert(c.to_string(), SpriteRect { x, y, width: cell, height: cell });
+        }
+        let encoded = encode(format, &pixels, width, height)?;
+        let mime = match format {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Webp => "image/webp",
+        };
+        Ok(SpriteSheet {
+            format,
+            width,
+            height,
+            cell,
+            strike_ppem: strike.ppem,
+            image: format!("data:{mime};base64,{}", base64::engine::general_purpose::STANDARD.encode(encoded)),
+            sprites,
+            missing,
+        })
+    }
+}
+
+pub async fn sprite_sheet(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<SpriteQuery>,
+    body: Body,
+) -> Result<Json<SpriteSheet>, (StatusCode, String)> {
+    state.flags.ensure("sprite", &headers)?;
+    state.flags.ensure("render", &headers)?;
+    if !(8..=512).contains(&query.size) {
+        return Err((StatusCode::BAD_REQUEST, "size must be 8-512".to_string()));
+    }
+    // Variation selectors only pick emoji presentation; they have no glyph.
+    let mut emoji: Vec<char> = query.emoji.chars().filter(|c| !matches!(*c as u32, 0xFE0E | 0xFE0F | 0x200D)).collect();
+    emoji.dedup();
+    if emoji.is_empty() || emoji.len() > MAX_EMOJI {
+        return Err((StatusCode::BAD_REQUEST, format!("emoji must list 1-{MAX_EMOJI} characters")));
+    }
+
+    let (data, _) = spool::receive_font(body).await?;
+    let task = Pack { emoji, size: query.size, format: query.format };
+    let sheet = isolation::run(&state, task, data).await?.map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    info!(sprites = sheet.sprites.len(), missing = sheet.missing.len(), "emoji sprite sheet built");
+    Ok(Json(sheet))
+}
@@ -0,0 +1,153 @@
+//! Staging channel for catalog changes.
+//!
+//! Admins stage new or updated entries without affecting production. Clients
+//! preview the staged view with `X-Font-Channel: staging` or
+//! `?channel=staging`; `POST /api/v1/admin/staging/promote` then moves every
+//! staged entry into production in one step. Staging a new entry runs name
+//! collision detection first (see [`collision`]).
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::info;
+
+use crate::{
+    audit, catalog,
+    collision::{self, ResolutionParams},
+    db,
+    extract::ApiJson,
+    AppState, FontCatalogEntry,
+};
+
+#[derive(Debug, Serialize)]
+pub struct PromoteResponse {
+    promoted: Vec<String>,
+}
+
+/// Whether the request asked for the staging view of the catalog.
+pub fn is_preview(headers: &HeaderMap, channel: Option<&str>) -> bool {
+    let header = headers.get("x-font-channel").and_then(|v| v.to_str().ok());
+    header.or(channel).is_some_and(|c| c.eq_ignore_ascii_case("staging"))
+}
+
+/// Production catalog with staged entries layered on top.
+pub fn overlay(state: &AppState) -> Vec<FontCatalogEntry> {
+    let staged = state.staging.read().unwrap();
+    let mut entries: Vec<FontCatalogEntry> = state
+        .catalog
+        .read()
+        .unwrap()
+        .iter()
+        .map(|e| staged.get(&e.id).unwrap_or(e).clone())
+        .collect();
+    for entry in staged.values() {
+        if !entries.iter().any(|e| e.id == entry.id) {
+            entries.push(entry.clone());
+        }
+    }
+    entries
+}
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<FontCatalogEntry>>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    Ok(Json(state.staging.read().unwrap().values().cloned().collect()))
+}
+
+pub async fn stage(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(params): Query<ResolutionParams>,
+    ApiJson(entry): ApiJson<FontCatalogEntry>,
+) -> Result<Json<FontCatalogEntry>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    if entry.id != id {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("entry id '{}' does not match path id '{id}'", entry.id),
+        ));
+    }
+    if entry.family.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "family is required".to_string()));
+    }
+    if let Some(defaults) = &entry.defaults {
+        crate::validate_defaults(defaults).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    }
+    // Names must be unique across production and everything already staged.
+    let mut entry = collision::resolve(entry, &overlay(&state), &params)?;
+    entry.version = catalog::current_version(&state, &entry.id);
+    let id = entry.id.clone();
+    if let Some(pool) = &state.db {
+        db::stage_entry(pool, &entry)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("staging persist failed: {e}")))?;
+    }
+    let before = state.staging.write().unwrap().insert(id.clone(), entry.clone());
+    audit::record(&state, &headers, "staging.stage", &id, json!(before), json!(entry)).await;
+    info!(id = %id, "catalog entry staged");
+    Ok(Json(entry))
+}
+
+pub async fn discard(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    if !state.staging.read().unwrap().contains_key(&id) {
+        return Err((StatusCode::NOT_FOUND, format!("no staged entry '{id}'")));
+    }
+    if let Some(pool) = &state.db {
+        db::unstage_entry(pool, &id)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("staging persist failed: {e}")))?;
+    }
+    let before = state.staging.write().unwrap().remove(&id);
+    audit::record(&state, &headers, "staging.discard", &id, json!(before), Value::Null).await;
+    info!(id = %id, "staged catalog entry discarded");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn promote(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<PromoteResponse>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    if let Some(pool) = &state.db {
+        db::promote_staging(pool)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("promotion failed: {e}")))?;
+    }
+    let mut changes = Vec::new();
+    {
+        // Take both locks so readers see either the old or the new catalog.
+        let mut staged = state.staging.write().unwrap();
+        let mut catalog = state.catalog.write().unwrap();
+        for (id, entry) in std::mem::take(&mut *staged) {
+            let after = json!(entry);
+            let before = match catalog.iter_mut().find(|e| e.id == id) {
+                Some(existing) => Some(std::mem::replace(existing, entry)),
+                None => {
+                    catalog.push(entry);
+                    None
+                }
+            };
+            changes.push((id, json!(before), after));
+        }
+    }
+    let mut promoted = Vec::new();
+    for (id, before, after) in changes {
+        audit::record(&state, &headers, "staging.promote", &id, before, after).await;
+        promoted.push(id);
+    }
+    info!(count = promoted.len(), "staged catalog entries promoted");
+    Ok(Json(PromoteResponse { promoted }))
+}
@@ -0,0 +1,716 @@
+//! Family-wide CSS: one stylesheet with an `@font-face` for every weight and
+//! style of a family, so a page links one URL per family instead of one per
+//! variant.
+//!
+//! Descriptors come from the catalog variant name (`Bold Italic` → 700
+//! italic). Variable entries (`Variable`, `Variable Italic`) get a weight
+//! range, read from the binary's `fvar` when it is in `CATALOG_FONT_DIR` and
+//! the full CSS range otherwise. `?split=true` emits one face per catalog
+//! unicode range, each pointing at its slice, so browsers only fetch the
+//! slices a page uses. Fonts sliced with `/api/v1/font/slices` (see
+//! [`crate::slices`]) get one face per stored slice: of their plain slicing,
+//! or of a configured slicing profile with `?slicing=jp-120-slices`, or with
+//! `slicing=Noto Sans JP:jp-120-slices,Inter:latin-2-slices` per family
+//! (`none` serves the whole font). Variants not sliced by the chosen profile
+//! fall back to the whole font. `?profile=` (a
+//! saved subset profile, see [`profiles`]) narrows every face to the
+//! profile's characters and points it at the matching subset. Configured
+//! resource hints (see [`hints`]) are sent as `Link` headers and noted at the
+//! top of the stylesheet, followed by `rel=preload` for the first font of up
+//! to four variants (weight and style); of a split or sliced variant only
+//! the first face, the one most pages need, is preloaded, with its
+//! `integrity` when the file is stored (see [`crate::artifacts`]).
+//! `?preload=false` leaves preloads out.
+//!
+//! Stylesheets float by default: URLs follow the catalog's current binaries.
+//! `?version=3` pins every face to version 3 of its entry (see
+//! [`crate::versions`]), served from `/cdn/fonts/<id>/v3/`, so a page keeps
+//! its glyphs when a new binary is released. Pins cannot be combined with
+//! `split`, `slicing` or `profile`, whose files are cut from the current
+//! binary.
+//!
+//! After a purge (see [`crate::purge`]) these URLs carry `?v=<generation>`,
+//! so edges fetch the files again. Stylesheets are cached per the `css`
+//! policy, or a family's own `cache_control` (see [`crate::cache_control`]).
+//!
+//! An entry's `attribution`, when its license asks for credit, is written as
+//! a comment above its family's faces.
+//!
+//! `?fallback=arial,roboto` follows each family's faces with local fallback
+//! faces whose metrics are overridden to match it (see [`fallback`]).
+//!
+//! Family names may be catalog aliases (see [`crate::aliases`]); the
+//! stylesheet is the canonical family's.
+//!
+//! `/api/v1/font/css` serves several families in one stylesheet, Google Fonts
+//! style: `family=Inter|Roboto:400,700` picks families (optionally with their
+//! own weights, and `Inter@3` pins a family's version), `weights=` filters the
+//! rest, and `formats=` limits and orders the `src` list. `formats=auto`, on
+//! either route, lists only the client's best format (see [`negotiation`]).
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::IntoResponse,
+};
+use font_api::SavedProfile;
+use serde::Deserialize;
+use std::{ops::RangeInclusive, sync::Arc};
+use tracing::info;
+
+use crate::{
+    artifacts, cache_control::Policy, compress, duplicates, fallback, fvar::Fvar, hints, negotiation, profiles,
+    sfnt::Font, slices, staging, tenants, unicode, AppState, FontCatalogEntry,
+};
+
+/// A file extension and its CSS `format()` name.
+type Format = (&'static str, &'static str);
+
+/// Web formats in the order browsers should try them.
+const FORMATS: &[Format] = &[("woff2", "woff2"), ("woff", "woff"), ("otf", "opentype"), ("ttf", "truetype")];
+
+/// Weight keywords in variant names (after removing spaces and hyphens).
+const WEIGHTS: &[(&str, u16)] = &[
+    ("hairline", 100),
+    ("thin", 100),
+    ("extralight", 200),
+    ("ultralight", 200),
+    ("light", 300),
+    ("book", 400),
+    ("regular", 400),
+    ("normal", 400),
+    ("medium", 500),
+    ("semibold", 600),
+    ("demibold", 600),
+    ("extrabold", 800),
+    ("ultrabold", 800),
+    ("bold", 700),
+    ("black", 900),
+    ("heavy", 900),
+];
+
+const DISPLAYS: &[&str] = &["auto", "block", "swap", "fallback", "optional"];
+
+#[derive(Debug, Deserialize)]
+pub struct FamilyCssQuery {
+    /// One face per catalog unicode range instead of one per variant.
+    #[serde(default)]
+    split: bool,
+    #[serde(default = "default_display")]
+    display: String,
+    /// Saved subset profile, `name` or `name@version`.
+    profile: Option<String>,
+    channel: Option<String>,
+    /// A version number, or `latest` (the default).
+    version: Option<String>,
+    #[serde(default = "default_preload")]
+    preload: bool,
+    /// Local fonts to add metric-matched fallback faces for, comma-separated.
+    fallback: Option<String>,
+    /// `auto`, or formats as for [`CssQuery`].
+    formats: Option<String>,
+    /// Slicing profile, as for [`CssQuery`].
+    slicing: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CssQuery {
+    /// `|`-separated families, each optionally `Family@2:400,700`.
+    family: String,
+    /// Weights for families listed without their own.
+    weights: Option<String>,
+    /// Comma-separated subset of `woff2,woff,otf,ttf`, in preference order,
+    /// or `auto`.
+    formats: Option<String>,
+    #[serde(default)]
+    split: bool,
+    #[serde(default = "default_display")]
+    display: String,
+    profile: Option<String>,
+    /// Slicing profiles, comma-separated: `name` for every family, or
+    /// `Family:name`.
+    slicing: Option<String>,
+    channel: Option<String>,
+    #[serde(default = "default_preload")]
+    preload: bool,
+    fallback: Option<String>,
+}
+
+fn default_display() -> String {
+    "swap".to_string()
+}
+
+fn default_preload() -> bool {
+    true
+}
+
+/// Fonts preloaded per stylesheet; more would compete with the page's own
+/// resources.
+const MAX_PRELOADS: usize = 4;
+
+/// What to emit for each member of a family.
+struct Options<'a> {
+    formats: Vec<&'a (&'a str, &'a str)>,
+    /// Only the first of `formats` each file has, for `formats=auto`.
+    negotiated: bool,
+    /// Weights to keep; empty keeps every variant.
+    weights: Vec<u16>,
+    split: bool,
+    display: &'a str,
+    /// Version every face is pinned to; `None` floats.
+    version: Option<u32>,
+    /// Slicing profile to serve; `None` serves the plain slicing.
+    slicing: Option<&'a str>,
+}
+
+/// `font-weight` and `font-style` descriptor values for a catalog entry.
+#[derive(Debug)]
+struct Descriptors {
+    weight: String,
+    style: String,
+    /// Weight range covered, for `weights=` filtering.
+    range: (u16, u16),
+    /// Sort key: italic faces after upright ones, then by weight.
+    order: (bool, u16),
+}
+
+fn descriptors(entry: &FontCatalogEntry) -> Descriptors {
+    let variant = entry.variant.to_lowercase().replace([' ', '-', '_'], "");
+    let italic = variant.contains("italic") || variant.contains("oblique");
+    if variant.contains("variable") {
+        let axes = variable_axes(entry).unwrap_or(VariableAxes { weight: (100, 900), oblique: None });
+        let (min, max) = axes.weight;
+        let style = match axes.oblique {
+            Some((a, b)) if !italic => format!("oblique {a}deg {b}deg"),
+            _ => if italic { "italic" } else { "normal" }.to_string(),
+        };
+        return Descriptors { weight: format!("{min} {max}"), style, range: (min, max), order: (italic, min) };
+    }
+    let weight = WEIGHTS.iter().find(|(name, _)| variant.contains(name)).map_or(400, |&(_, w)| w);
+    Descriptors {
+        weight: weight.to_string(),
+        style: if italic { "italic" } else { "normal" }.to_string(),
+        range: (weight, weight),
+        order: (italic, weight),
+    }
+}
+
+/// Descriptor ranges of a variable entry.
+struct VariableAxes {
+    weight: (u16, u16),
+    /// `slnt` range as CSS oblique angles.
+    oblique: Option<(i32, i32)>,
+}
+
+/// Axis ranges from the entry's binary, when it is available.
+fn variable_axes(entry: &FontCatalogEntry) -> Option<VariableAxes> {
+    let dir = duplicates::catalog_font_dir()?;
+    let data = std::fs::read(duplicates::font_path(&dir, &entry.id)?).ok()?;
+    let font = Font::parse(&data).ok()?;
+    let fvar = Fvar::parse(font.table(b"fvar")?).ok()?;
+    let wght = fvar.axes.iter().find(|a| a.tag == "wght")?;
+    // `slnt` is counter-clockwise degrees; CSS oblique angles are clockwise.
+    let oblique = fvar
+        .axes
+        .iter()
+        .find(|a| a.tag == "slnt")
+        .map(|a| ((-a.max).round() as i32, (-a.min).round() as i32));
+    Some(VariableAxes { weight: (wght.min.round() as u16, wght.max.round() as u16), oblique })
+}
+
+fn face(family: &str, d: &Descriptors, display: &str, src: &str, range: Option<&str>) -> String {
+    let range = range.map(|r| format!("  unicode-range: {r};\n")).unwrap_or_default();
+    format!(
+        "@font-face {{\n  font-family: \"{family}\";\n  font-style: {};\n  font-weight: {};\n  \
+         font-display: {display};\n  src: {src};\n{range}}}\n",
+        d.style, d.weight
+    )
+}
+
+/// An `@font-face` for `entry` whose `src` lists `files`, relative URLs
+/// ending in their format's extension, for downloadable kits.
+pub fn kit_face(entry: &FontCatalogEntry, files: &[String], range: Option<&str>) -> String {
+    let src: Vec<String> = files
+        .iter()
+        .filter_map(|file| {
+            let (_, css) = FORMATS.iter().find(|(ext, _)| file.ends_with(&format!(".{ext}")))?;
+            Some(format!("url(\"{file}\") format(\"{css}\")"))
+        })
+        .collect();
+    face(&entry.family, &descriptors(entry), "swap", &src.join(",\n       "), range)
+}
+
+/// A `version` value: a number pins, `latest` floats.
+fn parse_version(spec: &str) -> Result<Option<u32>, (StatusCode, String)> {
+    match spec.trim() {
+        "" | "latest" => Ok(None),
+        n => n.parse().ok().filter(|&n| n > 0).map(Some).ok_or_else(|| {
+            (StatusCode::BAD_REQUEST, format!("version '{spec}' must be a positive number or 'latest'"))
+        }),
+    }
+}
+
+/// Pins serve whole version binaries, which have no slices or subsets.
+fn check_pin(version: Option<u32>, split: bool, profile: Option<&str>) -> Result<(), (StatusCode, String)> {
+    if version.is_some() && (split || profile.is_some()) {
+        return Err((StatusCode::BAD_REQUEST, "a version pin cannot be combined with split or profile".to_string()));
+    }
+    Ok(())
+}
+
+/// A `slicing=` item: a family slug, none for every family, and a profile.
+type Slicing<'a> = (Option<String>, &'a str);
+
+fn parse_slicing<'a>(state: &AppState, spec: Option<&'a str>) -> Result<Vec<Slicing<'a>>, (StatusCode, String)> {
+    let items = spec.unwrap_or_default().split(',').map(str::trim).filter(|s| !s.is_empty());
+    items
+        .map(|item| {
+            let (family, profile) = match item.rsplit_once(':') {
+                Some((family, profile)) => (Some(artifacts::slug(family.trim())), profile.trim()),
+                None => (None, item),
+            };
+            if profile != slices::NONE {
+                state.slices.ensure_profile(profile)?;
+            }
+            Ok((family, profile))
+        })
+        .collect()
+}
+
+/// The slicing profile chosen for `family`: its own, else the one for all.
+fn slicing_for<'a>(choices: &[Slicing<'a>], family: &str) -> Option<&'a str> {
+    let slug = artifacts::slug(family);
+    let own = choices.iter().find(|(f, _)| f.as_deref() == Some(slug.as_str()));
+    own.or_else(|| choices.iter().find(|(f, _)| f.is_none())).map(|&(_, profile)| profile)
+}
+
+/// Slicings are cut from the current binary and hold every character.
+fn check_slicing(
+    slicing: Option<&str>,
+    version: Option<u32>,
+    profile: Option<&str>,
+) -> Result<(), (StatusCode, String)> {
+    if slicing.is_some_and(|s| s != slices::NONE) && (version.is_some() || profile.is_some()) {
+        let message = "slicing cannot be combined with a version pin or profile";
+        return Err((StatusCode::BAD_REQUEST, message.to_string()));
+    }
+    Ok(())
+}
+
+fn check_display(display: &str) -> Result<(), (StatusCode, String)> {
+    if !DISPLAYS.contains(&display) {
+        return Err((StatusCode::BAD_REQUEST, format!("display '{display}' must be one of: {}", DISPLAYS.join(", "))));
+    }
+    Ok(())
+}
+
+/// `weights=` values, each a CSS weight from 1 to 1000.
+fn parse_weights(spec: &str) -> Result<Vec<u16>, (StatusCode, String)> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            w.parse().ok().filter(|w| (1..=1000).contains(w)).ok_or_else(|| {
+                (StatusCode::BAD_REQUEST, format!("weight '{w}' must be a number from 1 to 1000"))
+            })
+        })
+        .collect()
+}
+
+/// `formats=` values in the caller's order, every format by default, and
+/// whether they were negotiated with `auto` (see [`negotiation`]).
+fn parse_formats(
+    spec: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<(Vec<&'static Format>, bool), (StatusCode, String)> {
+    let Some(spec) = spec else { return Ok((FORMATS.iter().collect(), false)) };
+    if spec.trim().eq_ignore_ascii_case("auto") {
+        let usable = negotiation::formats(headers);
+        return Ok((FORMATS.iter().filter(|(ext, _)| usable.contains(ext)).collect(), true));
+    }
+    let names: Vec<_> = FORMATS.iter().map(|(ext, _)| *ext).collect();
+    let formats = spec
+        .split(',')
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .map(|f| {
+            FORMATS.iter().find(|(ext, _)| ext.eq_ignore_ascii_case(f)).ok_or_else(|| {
+                (StatusCode::BAD_REQUEST, format!("format '{f}' must be one of: {}, or auto", names.join(", ")))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if formats.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "formats must name at least one format".to_string()));
+    }
+    Ok((formats, false))
+}
+
+/// `options.formats` that `has` accepts, in order; only the first when
+/// negotiated.
+fn usable<'a>(options: &Options<'a>, has: impl Fn(&str) -> bool) -> Vec<&'a (&'a str, &'a str)> {
+    let usable = options.formats.iter().copied().filter(|(ext, _)| has(ext));
+    usable.take(if options.negotiated { 1 } else { FORMATS.len() }).collect()
+}
+
+/// Catalog entries to read faces from: the staging overlay for previews.
+fn entries(state: &AppState, headers: &HeaderMap, channel: Option<&str>) -> Vec<FontCatalogEntry> {
+    if staging::is_preview(headers, channel) {
+        staging::overlay(state)
+    } else {
+        state.catalog.read().unwrap().clone()
+    }
+}
+
+/// The `@font-face` rules of one family, and how many variants it has.
+fn family_faces(
+    state: &AppState,
+    entries: &[FontCatalogEntry],
+    family: &str,
+    options: &Options,
+    saved: Option<&SavedProfile>,
+) -> Result<(usize, Vec<String>), (StatusCode, String)> {
+    let slug = family.to_lowercase().replace(' ', "-");
+    let mut members: Vec<(Descriptors, &FontCatalogEntry)> = entries
+        .iter()
+        .filter(|e| e.family.to_lowercase().replace(' ', "-") == slug)
+        .map(|e| (descriptors(e), e))
+        .collect();
+    if members.is_empty() {
+        return Err((StatusCode::NOT_FOUND, format!("no catalog family '{family}'")));
+    }
+    members.sort_by_key(|(d, _)| d.order);
+
+    let mut faces = Vec::new();
+    for (d, entry) in &members {
+        let (min, max) = d.range;
+        if !options.weights.is_empty() && !options.weights.iter().any(|w| (min..=max).contains(w)) {
+            continue;
+        }
+        if let Some(version) = options.version {
+            if version > entry.version {
+                return Err((
+                    StatusCode::NOT_FOUND,
+                    format!("'{}' has no version {version}; the latest is {}", entry.id, entry.version),
+                ));
+            }
+            let src = usable(options, |ext| entry.formats.iter().any(|f| f == ext))
+                .into_iter()
+                .map(|(ext, css)| {
+                    let prefix = tenants::prefix(&entry.tenant);
+                    let url = state.edges.url_for(&format!("{prefix}/{0}/v{version}/{0}.{ext}", entry.id));
+                    let url = state.artifacts.bust(&entry.id, url);
+                    format!("url(\"{url}\") format(\"{css}\")")
+                })
+                .collect::<Vec<_>>();
+            if !src.is_empty() {
+                faces.push(face(&entry.family, d, options.display, &src.join(",\n       "), None));
+            }
+            continue;
+        }
+        let manifest = match options.slicing {
+            _ if saved.is_some() => None,
+            Some(slices::NONE) => None,
+            profile => state.slices.get(&entry.id, profile),
+        };
+        if let Some(manifest) = manifest {
+            let sliced = usable(options, |ext| manifest.formats.iter().any(|f| f == ext));
+            if !sliced.is_empty() {
+                for slice in &manifest.slices {
+                    let src = sliced
+                        .iter()
+                        .filter_map(|(ext, css)| {
+                            let url = state.edges.url_for(&tenants::cdn_path(state, slice.files.get(*ext)?));
+                            Some(format!("url(\"{url}\") format(\"{css}\")"))
+                        })
+                        .collect::<Vec<_>>()
+                        .join(",\n       ");
+                    faces.push(face(&entry.family, d, options.display, &src, Some(&slice.unicode_range)));
+                }
+                continue;
+            }
+        }
+        let formats = usable(options, |ext| entry.formats.iter().any(|f| f == ext));
+        if formats.is_empty() {
+            continue;
+        }
+        let src = |file: &str| {
+            formats
+                .iter()
+                .map(|(ext, css)| {
+                    let path = tenants::cdn_path(state, &format!("/cdn/fonts/{slug}/{file}.{ext}"));
+                    let url = state.artifacts.bust(&slug, state.edges.url_for(&path));
+                    format!("url(\"{url}\") format(\"{css}\")")
+                })
+                .collect::<Vec<_>>()
+                .join(",\n       ")
+        };
+        let mut ranges: Vec<RangeInclusive<u32>> = unicode::parse_ranges(&entry.unicode_ranges);
+        let mut base = entry.id.clone();
+        if let Some(saved) = saved {
+            let wanted = profiles::code_points(&saved.spec);
+            ranges = if ranges.is_empty() { wanted } else { unicode::intersect(&ranges, &wanted) };
+            if ranges.is_empty() {
+                continue;
+            }
+            base = format!("{}.{}-v{}", entry.id, saved.name, saved.version);
+        }
+        if options.split && ranges.len() > 1 {
+            for (i, range) in ranges.iter().enumerate() {
+                let file = format!("{base}-{i}");
+                faces.push(face(&entry.family, d, options.display, &src(&file), Some(&unicode::format_range(range))));
+            }
+        } else {
+            let range = (!ranges.is_empty()).then(|| unicode::css_unicode_range(&ranges));
+            faces.push(face(&entry.family, d, options.display, &src(&base), range.as_deref()));
+        }
+    }
+    if faces.is_empty() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("family '{family}' has no faces in the requested weights, formats and characters"),
+        ));
+    }
+    let mut credits: Vec<&str> = Vec::new();
+    for text in members.iter().filter_map(|(_, e)| e.attribution.as_deref()).map(str::trim) {
+        if !text.is_empty() && !credits.contains(&text) {
+            credits.push(text);
+        }
+    }
+    if !credits.is_empty() {
+        let family = &members[0].1.family;
+        let comments: String =
+            credits.iter().map(|text| format!("/* {family}: {} */\n", text.replace("*/", "* /"))).collect();
+        faces[0].insert_str(0, &comments);
+    }
+    Ok((members.len(), faces))
+}
+
+/// Metric-matched local fallback faces for a family, from its Regular
+/// member's binary (see [`fallback`]).
+fn fallback_faces(entries: &[FontCatalogEntry], family: &str, locals: &[&fallback::LocalFont]) -> Vec<String> {
+    let slug = family.to_lowercase().replace(' ', "-");
+    let members: Vec<&FontCatalogEntry> =
+        entries.iter().filter(|e| e.family.to_lowercase().replace(' ', "-") == slug).collect();
+    let regular = members.iter().find(|e| e.variant.eq_ignore_ascii_case("regular")).or(members.first());
+    let data = regular.zip(duplicates::catalog_font_dir()).and_then(|(entry, dir)| {
+        let data = std::fs::read(duplicates::font_path(&dir, &entry.id)?).ok()?;
+        Some((&entry.family, data))
+    });
+    match data {
+        Some((family, data)) if !locals.is_empty() => fallback::faces(family, &data, locals),
+        _ => Vec::new(),
+    }
+}
+
+fn parse_fallbacks(spec: Option<&str>) -> Result<Vec<&'static fallback::LocalFont>, (StatusCode, String)> {
+    fallback::parse(spec.unwrap_or_default()).map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+/// Preloads for the first `src` of the first face of each variant, up to
+/// [`MAX_PRELOADS`].
+fn face_preloads(faces: &[String]) -> Vec<hints::Hint> {
+    let descriptor = |face: &str, name: &str| {
+        face.split_once(&format!("{name}: ")).and_then(|(_, rest)| rest.split_once(';')).map(|(v, _)| v.to_string())
+    };
+    let mut seen = Vec::new();
+    let mut preloads = Vec::new();
+    for face in faces {
+        let variant = (descriptor(face, "font-style"), descriptor(face, "font-weight"));
+        if seen.contains(&variant) {
+            continue;
+        }
+        seen.push(variant);
+        let Some(url) = face.split_once("url(\"").and_then(|(_, rest)| rest.split_once('"')).map(|(url, _)| url) else {
+            continue;
+        };
+        let ext = url.split('?').next().and_then(|path| path.rsplit_once('.')).map_or("", |(_, ext)| ext);
+        preloads.push(hints::Hint::preload(url.to_string(), compress::media_type(ext)));
+        if preloads.len() == MAX_PRELOADS {
+            break;
+        }
+    }
+    preloads
+}
+
+/// [`face_preloads`] with the `integrity` of each stored font.
+async fn checked_preloads(state: &AppState, faces: &[String]) -> Vec<hints::Hint> {
+    let mut preloads = face_preloads(faces);
+    for preload in &mut preloads {
+        preload.add_integrity(state).await;
+    }
+    preloads
+}
+
+/// Preloads for a family's stylesheet as `/api/v1/font/css/:family` serves
+/// it, none when it has no faces.
+pub async fn preloads(state: &AppState, headers: &HeaderMap, family: &str) -> Vec<hints::Hint> {
+    let family = &state.aliases.family(&state.catalog.read().unwrap(), family);
+    if state.sandbox.ensure_font(headers, &state.catalog.read().unwrap(), family).is_err() {
+        return Vec::new();
+    }
+    let entries = entries(state, headers, None);
+    let formats = FORMATS.iter().collect();
+    let options = Options {
+        formats,
+        negotiated: false,
+        weights: Vec::new(),
+        split: false,
+        display: "swap",
+        version: None,
+        slicing: None,
+    };
+    match family_faces(state, &entries, family, &options, None) {
+        Ok((_, faces)) => checked_preloads(state, &faces).await,
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The stylesheet response: CSS content type, `cache_control`, resource
+/// hints and preloads as `Link` headers and as comments at the top, and
+/// `Vary` when the formats were `negotiated`.
+async fn stylesheet(
+    state: &AppState,
+    headers: &HeaderMap,
+    faces: &[String],
+    preload: bool,
+    cache_control: HeaderValue,
+    negotiated: bool,
+) -> (HeaderMap, String) {
+    let mut hints = state.hints.resolve(headers, &state.edges.url_for("/"));
+    if preload {
+        hints.extend(checked_preloads(state, faces).await);
+    }
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/css; charset=utf-8"));
+    response_headers.insert(header::CACHE_CONTROL, cache_control);
+    if let Some(link) = hints::link_header(&hints) {
+        response_headers.insert(header::LINK, link);
+    }
+    if negotiated {
+        response_headers.insert(header::VARY, HeaderValue::from_static(negotiation::VARY));
+    }
+    (response_headers, format!("{}{}", hints::css_comments(&hints), faces.join("\n")))
+}
+
+/// Every variant of a family as one stylesheet.
+pub async fn family_css(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(family): Path<String>,
+    Query(query): Query<FamilyCssQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let family = state.aliases.family(&state.catalog.read().unwrap(), &family);
+    check_display(&query.display)?;
+    let (formats, negotiated) = parse_formats(query.formats.as_deref(), &headers)?;
+    let version = query.version.as_deref().map(parse_version).transpose()?.flatten();
+    check_pin(version, query.split, query.profile.as_deref())?;
+    let choices = parse_slicing(&state, query.slicing.as_deref())?;
+    let slicing = slicing_for(&choices, &family);
+    check_slicing(slicing, version, query.profile.as_deref())?;
+    let fallbacks = parse_fallbacks(query.fallback.as_deref())?;
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &family)?;
+    let saved = query.profile.as_deref().map(|p| state.profiles.resolve(&headers, p)).transpose()?;
+    let entries = entries(&state, &headers, query.channel.as_deref());
+    let options = Options {
+        formats,
+        negotiated,
+        weights: Vec::new(),
+        split: query.split,
+        display: &query.display,
+        version,
+        slicing,
+    };
+    let (variants, mut faces) = family_faces(&state, &entries, &family, &options, saved.as_ref())?;
+    faces.extend(fallback_faces(&entries, &family, &fallbacks));
+
+    info!(
+        family = %family,
+        variants,
+        faces = faces.len(),
+        split = query.split,
+        profile = ?saved.as_ref().map(|p| p.reference()),
+        slicing = ?slicing,
+        version = ?version,
+        "family stylesheet"
+    );
+    let cache_control = state.cache_policies.for_font(&entries, &artifacts::slug(&family), Policy::Css);
+    Ok(stylesheet(&state, &headers, &faces, query.preload, cache_control, negotiated).await)
+}
+
+/// Several families, filtered by weight and format, as one stylesheet.
+pub async fn css(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<CssQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    check_display(&query.display)?;
+    let (formats, negotiated) = parse_formats(query.formats.as_deref(), &headers)?;
+    let weights = query.weights.as_deref().map(parse_weights).transpose()?.unwrap_or_default();
+    let fallbacks = parse_fallbacks(query.fallback.as_deref())?;
+    let families: Vec<(&str, Option<&str>, Option<u32>)> = query
+        .family
+        .split('|')
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .map(|f| {
+            let (name, weights) = match f.split_once(':') {
+                Some((name, weights)) => (name.trim(), Some(weights)),
+                None => (f, None),
+            };
+            match name.split_once('@') {
+                Some((name, version)) => Ok((name.trim(), weights, parse_version(version)?)),
+                None => Ok((name, weights, None)),
+            }
+        })
+        .collect::<Result<_, (StatusCode, String)>>()?;
+    if families.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "family must name at least one family".to_string()));
+    }
+    let families: Vec<(String, Option<&str>, Option<u32>)> = {
+        let catalog = state.catalog.read().unwrap();
+        families.into_iter().map(|(name, own, version)| (state.aliases.family(&catalog, name), own, version)).collect()
+    };
+    let choices = parse_slicing(&state, query.slicing.as_deref())?;
+    for (family, _, version) in &families {
+        check_pin(*version, query.split, query.profile.as_deref())?;
+        check_slicing(slicing_for(&choices, family), *version, query.profile.as_deref())?;
+    }
+    {
+        let catalog = state.catalog.read().unwrap();
+        for (family, ..) in &families {
+            state.sandbox.ensure_font(&headers, &catalog, family)?;
+        }
+    }
+    let saved = query.profile.as_deref().map(|p| state.profiles.resolve(&headers, p)).transpose()?;
+    let entries = entries(&state, &headers, query.channel.as_deref());
+
+    let mut faces = Vec::new();
+    for (family, own, version) in &families {
+        let weights = match own {
+            Some(spec) => parse_weights(spec)?,
+            None => weights.clone(),
+        };
+        let options = Options {
+            formats: formats.clone(),
+            negotiated,
+            weights,
+            split: query.split,
+            display: &query.display,
+            version: *version,
+            slicing: slicing_for(&choices, family),
+        };
+        faces.extend(family_faces(&state, &entries, family, &options, saved.as_ref())?.1);
+        faces.extend(fallback_faces(&entries, family, &fallbacks));
+    }
+
+    info!(
+        families = families.len(),
+        faces = faces.len(),
+        formats = formats.len(),
+        split = query.split,
+        profile = ?saved.as_ref().map(|p| p.reference()),
+        "css api stylesheet"
+    );
+    let cache_control = state.cache_policies.get(Policy::Css);
+    Ok(stylesheet(&state, &headers, &faces, query.preload, cache_control, negotiated).await)
+}
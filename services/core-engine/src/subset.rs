@@ -0,0 +1,445 @@
+//! Glyph subsetting for `/api/v1/font/subset`.
+//!
+//! The requested code points are looked up in `cmap`, together with the
+//! space and, for precomposed characters the font lacks, their base letters
+//! and combining marks. The glyph set (`.notdef` always included) is then
+//! closed over GSUB substitutions, MATH variants and composite components.
+//! The GSUB closure keeps ligatures, contextual alternates and `ccmp`
+//! compositions working; callers after the smallest file can skip it, at the
+//! cost of those features. GPOS only positions glyphs and adds none.
+//! Glyph IDs are kept (like `hb-subset --retain-gids`) so layout tables stay
+//! valid untouched: outlines (`glyf`/`loca`, `CFF `), `gvar` deltas, metrics,
+//! color bitmaps and SVG documents of every other glyph are dropped, `cmap` is
+//! rebuilt for the retained characters and `post` loses its glyph names.
+//! `OS/2` coverage bits follow the new `cmap` (see [`crate::os2`]).
+//! Variation sequences (format 14) are kept when both the base and the
+//! selector are requested, with the variant glyphs they select, so `葛󠄀`
+//! (U+845B U+E0100) still draws its registered variant.
+//!
+//! A `vertical` subset keeps CJK vertical writing working whatever else is
+//! asked: the vertical alternates of the kept glyphs (`vert`, `vrt2` and
+//! the other [`VERTICAL_FEATURES`]) even without the full closure, those
+//! features through `drop_features`/`keep_features`, and `vhea`/`vmtx`
+//! metrics of the kept glyphs, re-packed like `hmtx`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use unicode_normalization::char::decompose_canonical;
+
+use crate::{
+    bitmap, cff,
+    cmap::CharMap,
+    glyf::{self, Glyf},
+    layout, licensing,
+    math::Math,
+    os2,
+    sfnt::{be_u16, be_u32, Font},
+};
+
+/// GSUB and GPOS features of vertical text.
+pub const VERTICAL_FEATURES: [[u8; 4]; 8] =
+    [*b"vert", *b"vrt2", *b"vrtr", *b"vkna", *b"vkrn", *b"vpal", *b"vhal", *b"valt"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    /// Requested characters the font maps.
+    pub characters: usize,
+    /// Requested characters the font has no glyph for.
+    pub missing: usize,
+    pub glyphs: usize,
+    pub total_glyphs: usize,
+    /// The retained glyph IDs, for the worker's own use; not stored.
+    #[serde(skip)]
+    pub kept: BTreeSet<u16>,
+}
+
+/// Subsets `font` in place to the characters in `wanted`, with everything
+/// their GSUB substitutions reach when `layout_closure` is set, and their
+/// vertical forms and metrics when `vertical` is.
+pub fn subset(font: &mut Font, wanted: &BTreeSet<u32>, layout_closure: bool, vertical: bool) -> Result<Report, String> {
+    licensing::check_subsetting(font)?;
+    let total_glyphs = be_u16(font.table(b"maxp").ok_or("font has no maxp table")?, 4).ok_or("maxp table truncated")?;
+    let cmap = CharMap::parse(font.table(b"cmap").ok_or("font has no cmap table")?)?;
+    let wanted = &with_fallbacks(&cmap, wanted);
+    let cmap = cmap.retain(wanted);
+    let characters = cmap.glyphs().count();
+    // Selectors have no glyph of their own but are not missing either.
+    let selectors = cmap.selectors().filter(|&s| char::from_u32(s).is_some_and(|c| cmap.glyph(c).is_none())).count();
+
+    let mut keep: BTreeSet<u16> = cmap.glyphs().chain(cmap.variation_glyphs()).chain([0]).collect();
+    if let Some(gsub) = font.table(b"GSUB").filter(|_| layout_closure) {
+        keep = layout::gsub_closure(gsub, &keep)?;
+    } else if let Some(gsub) = font.table(b"GSUB").filter(|_| vertical) {
+        keep = layout::feature_closure(gsub, &keep, &VERTICAL_FEATURES)?;
+    }
+    if let Some(math) = font.table(b"MATH") {
+        keep = Math::parse(math)?.closure(&keep);
+    }
+    if let Some(glyf) = Glyf::parse(font).transpose()? {
+        keep = glyf.closure(&keep);
+    }
+    keep.retain(|&g| g < total_glyphs);
+
+    if let Some(glyf) = Glyf::parse(font).transpose()? {
+        let glyphs: Vec<Vec<u8>> = (0..glyf.num_glyphs() as u16)
+            .map(|g| if keep.contains(&g) { glyf.data(g).to_vec() } else { Vec::new() })
+            .collect();
+        glyf::rebuild(font, &glyphs)?;
+    }
+    if let Some(outlines) = font.table(b"CFF ") {
+        let outlines = cff::retain(outlines, &keep)?;
+        font.set_table(*b"CFF ", outlines);
+    }
+    if let Some(gvar) = font.table(b"gvar") {
+        let gvar = retain_gvar(gvar, &keep)?;
+        font.set_table(*b"gvar", gvar);
+    }
+    retain_metrics(font, [*b"hhea", *b"hmtx"], &keep, total_glyphs as usize)?;
+    if vertical {
+        retain_metrics(font, [*b"vhea", *b"vmtx"], &keep, total_glyphs as usize)?;
+    }
+    if font.table(b"sbix").is_some() || font.table(b"CBDT").is_some() {
+        bitmap::prune(font, &keep, &[])?;
+    }
+    if let Some(svg) = font.table(b"SVG ") {
+        match retain_svg(svg, &keep)? {
+            Some(svg) => font.set_table(*b"SVG ", svg),
+            None => font.tables.retain(|t| &t.tag != b"SVG "),
+        }
+    }
+    font.set_table(*b"cmap", cmap.to_bytes());
+    drop_glyph_names(font);
+    os2::refresh(font)?;
+
+    Ok(Report {
+        characters,
+        missing: wanted.len() - characters - selectors,
+        glyphs: keep.len(),
+        total_glyphs: total_glyphs as usize,
+        kept: keep,
+    })
+}
+
+/// `wanted` plus the space, and the canonical decomposition of every
+/// precomposed character the font does not map, so shapers can still draw
+/// it as a base and combining marks.
+fn with_fallbacks(cmap: &CharMap, wanted: &BTreeSet<u32>) -> BTreeSet<u32> {
+    let mut all = wanted.clone();
+    all.insert(0x20);
+    for c in wanted.iter().filter_map(|&cp| char::from_u32(cp)) {
+        if cmap.glyph(c).is_none_or(|g| g == 0) {
+            decompose_canonical(c, |d| {
+                all.insert(d as u32);
+            });
+        }
+    }
+    all
+}
+
+/// Zeroes the metrics of dropped glyphs and re-packs the trailing run of
+/// equal advances into side bearings, in `hhea`/`hmtx` or `vhea`/`vmtx`
+/// (which share a layout).
+fn retain_metrics(
+    font: &mut Font,
+    [header, table]: [[u8; 4]; 2],
+    keep: &BTreeSet<u16>,
+    glyphs: usize,
+) -> Result<(), String> {
+    let (Some(hhea), Some(hmtx)) = (font.table(&header), font.table(&table)) else {
+        return Ok(());
+    };
+    let truncated = |tag: &[u8; 4]| format!("{} table truncated", String::from_utf8_lossy(tag));
+    let long = be_u16(hhea, 34).ok_or_else(|| truncated(&header))? as usize;
+    let truncated = || truncated(&table);
+    let mut metrics = Vec::with_capacity(glyphs);
+    for g in 0..glyphs {
+        let (advance, lsb) = if g < long {
+            (be_u16(hmtx, 4 * g).ok_or_else(truncated)?, be_u16(hmtx, 4 * g + 2).ok_or_else(truncated)?)
+        } else {
+            let last = metrics.last().map_or(0, |&(a, _)| a);
+            (last, be_u16(hmtx, 4 * long + 2 * (g - long)).ok_or_else(truncated)?)
+        };
+        metrics.push(if keep.contains(&(g as u16)) { (advance, lsb) } else { (0, 0) });
+    }
+    let last_advance = metrics.last().map_or(0, |&(a, _)| a);
+    let long = metrics.iter().rposition(|&(a, _)| a != last_advance).map_or(1, |i| i + 2).min(glyphs.max(1));
+
+    let mut out = Vec::with_capacity(2 * long + 2 * glyphs);
+    for (g, (advance, lsb)) in metrics.into_iter().enumerate() {
+        if g < long {
+            out.extend_from_slice(&advance.to_be_bytes());
+        }
+        out.extend_from_slice(&lsb.to_be_bytes());
+    }
+    let mut hhea = hhea.to_vec();
+    hhea[34..36].copy_from_slice(&(long as u16).to_be_bytes());
+    font.set_table(header, hhea);
+    font.set_table(table, out);
+    Ok(())
+}
+
+/// Keeps the SVG documents of retained glyphs, each record narrowed to the
+/// retained glyphs in its range; `None` when none is left. A document is
+/// kept whole even if only some of its glyphs are retained.
+fn retain_svg(svg: &[u8], keep: &BTreeSet<u16>) -> Result<Option<Vec<u8>>, String> {
+    let truncated = || "SVG table truncated".to_string();
+    let list = be_u32(svg, 2).ok_or_else(truncated)? as usize;
+    let count = be_u16(svg, list).ok_or_else(truncated)? as usize;
+    let mut records = Vec::new();
+    for i in 0..count {
+        let at = list + 2 + 12 * i;
+        let (start, end) = (be_u16(svg, at).ok_or_else(truncated)?, be_u16(svg, at + 2).ok_or_else(truncated)?);
+        let Some(first) = keep.range(start..=end).next() else { continue };
+        let last = keep.range(start..=end).next_back().unwrap_or(first);
+        let offset = list + be_u32(svg, at + 4).ok_or_else(truncated)? as usize;
+        let len = be_u32(svg, at + 8).ok_or_else(truncated)? as usize;
+        records.push((*first, *last, svg.get(offset..offset + len).ok_or_else(truncated)?));
+    }
+    if records.is_empty() {
+        return Ok(None);
+    }
+    // Documents shared by several records are stored once.
+    let mut docs: Vec<&[u8]> = Vec::new();
+    let mut entries = Vec::with_capacity(records.len());
+    for (first, last, doc) in records {
+        let index = docs.iter().position(|d| std::ptr::eq(*d, doc)).unwrap_or_else(|| {
+            docs.push(doc);
+            docs.len() - 1
+        });
+        entries.push((first, last, index));
+    }
+    let mut offsets = Vec::with_capacity(docs.len());
+    let mut offset = 2 + 12 * entries.len();
+    for doc in &docs {
+        offsets.push(offset);
+        offset += doc.len();
+    }
+
+    let mut out = Vec::with_capacity(10 + offset);
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(&10u32.to_be_bytes());
+    out.extend_from_slice(&0u32.to_be_bytes());
+    out.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+    for (first, last, index) in entries {
+        out.extend_from_slice(&first.to_be_bytes());
+        out.extend_from_slice(&last.to_be_bytes());
+        out.extend_from_slice(&(offsets[index] as u32).to_be_bytes());
+        out.extend_from_slice(&(docs[index].len() as u32).to_be_bytes());
+    }
+    docs.iter().for_each(|doc| out.extend_from_slice(doc));
+    Ok(Some(out))
+}
+
+/// Keeps only the variation data of retained glyphs, always writing long
+/// offsets.
+fn retain_gvar(gvar: &[u8], keep: &BTreeSet<u16>) -> Result<Vec<u8>, String> {
+    let truncated = || "gvar table truncated".to_string();
+    let axes_and_tuples = gvar.get(4..8).ok_or_else(truncated)?;
+    let shared_count = be_u16(gvar, 6).ok_or_else(truncated)? as usize;
+    let shared_at = be_u32(gvar, 8).ok_or_else(truncated)? as usize;
+    let glyphs = be_u16(gvar, 12).ok_or_else(truncated)? as usize;
+    let flags = be_u16(gvar, 14).ok_or_else(truncated)?;
+    let data_at = be_u32(gvar, 16).ok_or_else(truncated)? as usize;
+    let axes = be_u16(gvar, 4).ok_or_else(truncated)? as usize;
+    let shared = gvar.get(shared_at..shared_at + 2 * axes * shared_count).ok_or_else(truncated)?;
+    let offset = |g: usize| match flags & 1 {
+        1 => be_u32(gvar, 20 + 4 * g).map(|o| o as usize),
+        _ => be_u16(gvar, 20 + 2 * g).map(|o| 2 * o as usize),
+    };
+
+    let mut data = Vec::new();
+    let mut offsets = Vec::with_capacity(glyphs + 1);
+    for g in 0..glyphs {
+        offsets.push(data.len() as u32);
+        if keep.contains(&(g as u16)) {
+            let (start, end) = (offset(g).ok_or_else(truncated)?, offset(g + 1).ok_or_else(truncated)?);
+            data.extend_from_slice(gvar.get(data_at + start..data_at + end).ok_or_else(truncated)?);
+        }
+    }
+    offsets.push(data.len() as u32);
+
+    let shared_at = 20 + 4 * offsets.len();
+    let mut out = Vec::with_capacity(shared_at + shared.len() + data.len());
+    out.extend_from_slice(&gvar[..4]);
+    out.extend_from_slice(axes_and_tuples);
+    out.extend_from_slice(&(shared_at as u32).to_be_bytes());
+    out.extend_from_slice(&(glyphs as u16).to_be_bytes());
+    out.extend_from_slice(&(flags | 1).to_be_bytes());
+    out.extend_from_slice(&((shared_at + shared.len()) as u32).to_be_bytes());
+    offsets.iter().for_each(|o| out.extend_from_slice(&o.to_be_bytes()));
+    out.extend_from_slice(shared);
+    out.extend(data);
+    Ok(out)
+}
+
+/// Switches `post` to version 3.0, which carries no glyph names.
+fn drop_glyph_names(font: &mut Font) {
+    if let Some(post) = font.table(b"post").filter(|p| p.len() >= 32 && be_u32(p, 0) != Some(0x0003_0000)) {
+        let mut post = post[..32].to_vec();
+        post[..4].copy_from_slice(&0x0003_0000u32.to_be_bytes());
+        font.set_table(*b"post", post);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        glyf::{offset_composite, Outline, Point},
+        sfnt::Table,
+    };
+
+    const GPOS: [u8; 16] = [0, 1, 0, 0, 0, 10, 0, 12, 0, 14, 0, 0, 0, 0, 0, 0];
+
+    fn words(words: &[u16]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_be_bytes()).collect()
+    }
+
+    fn square(size: i16) -> Vec<u8> {
+        let corners = [(0, 0), (size, 0), (size, size), (0, size)];
+        let points = corners.map(|(x, y)| Point { x: x.into(), y: y.into(), on_curve: true }).to_vec();
+        Outline { bbox: [0, 0, size, size], ends: vec![3], instructions: Vec::new(), points, overlap: false }.to_bytes()
+    }
+
+    /// Eight glyphs: `.notdef`, space, `f`, `i`, the `f_i` ligature GSUB forms
+    /// from them, a combining acute, `í` composed of `i` and the acute, and
+    /// `A`; glyph `g` advances `500 + 10 * g` and has `[g; 4]` as variation
+    /// data, and SVG documents cover glyphs 2–3 and 6–7.
+    fn font() -> Font {
+        let mut iacute = offset_composite(3, 0, 0, [0, 0, 300, 300]);
+        // ARGS_ARE_XY_VALUES | MORE_COMPONENTS, then the acute, unmoved.
+        iacute[10..12].copy_from_slice(&0x0022u16.to_be_bytes());
+        iacute.extend_from_slice(&[0, 0x02, 0, 5, 0, 0]);
+        let glyphs = [square(500), Vec::new(), square(300), square(300), square(400), square(100), iacute, square(600)];
+
+        let cmap = CharMap::new([(0x20, 1), (0x41, 7), (0x66, 2), (0x69, 3), (0xED, 6), (0x301, 5)].into());
+        // liga: f i -> f_i.
+        let gsub = [
+            &words(&[1, 0, 10, 12, 26, 0])[..],
+            &words(&[1, u16::from_be_bytes(*b"li"), u16::from_be_bytes(*b"ga"), 8, 0, 1, 0])[..],
+            &words(&[1, 4, 4, 0, 1, 8, 1, 8, 1, 14, 1, 1, 2, 1, 4, 4, 2, 3])[..],
+        ]
+        .concat();
+        let mut hhea = vec![0; 36];
+        hhea[..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        hhea[34..36].copy_from_slice(&8u16.to_be_bytes());
+        let hmtx = words(&(0..8).flat_map(|g| [500 + 10 * g, 0]).collect::<Vec<_>>());
+        let mut head = vec![0; 54];
+        head[..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        head[12..16].copy_from_slice(&0x5F0F_3CF5u32.to_be_bytes());
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        let mut post = vec![0; 32];
+        post[..4].copy_from_slice(&0x0002_0000u32.to_be_bytes());
+        // Version 2.0 names: every glyph is `.notdef`.
+        post.extend(words(&[8, 0, 0, 0, 0, 0, 0, 0, 0]));
+
+        let data: Vec<Vec<u8>> = (0..8).map(|g| if g == 1 { Vec::new() } else { vec![g; 4] }).collect();
+        let mut gvar = words(&[1, 0, 1, 0, 0, 56, 8, 1, 0, 56]);
+        let mut offset = 0u32;
+        for d in data.iter().chain([&Vec::new()]) {
+            gvar.extend(offset.to_be_bytes());
+            offset += d.len() as u32;
+        }
+        gvar.extend(data.concat());
+
+        let docs = [&b"<svg id=\"glyph2\"/>"[..], b"<svg id=\"glyph6\"/>"];
+        let mut svg = [words(&[0, 0, 10, 0, 0]), words(&[2])].concat();
+        let mut offset = 2 + 12 * docs.len();
+        for (first, doc) in [2, 6].into_iter().zip(docs) {
+            svg.extend(words(&[first, first + 1]));
+            svg.extend((offset as u32).to_be_bytes());
+            svg.extend((doc.len() as u32).to_be_bytes());
+            offset += doc.len();
+        }
+        svg.extend(docs.concat());
+
+        let tables = [
+            (*b"GPOS", GPOS.to_vec()),
+            (*b"GSUB", gsub),
+            (*b"SVG ", svg),
+            (*b"cmap", cmap.to_bytes()),
+            (*b"gvar", gvar),
+            (*b"head", head),
+            (*b"hhea", hhea),
+            (*b"hmtx", hmtx),
+            (*b"maxp", vec![0, 0, 0x50, 0, 0, 8]),
+            (*b"post", post),
+        ];
+        let mut font = Font::new(0x0001_0000, tables.into_iter().map(|(tag, data)| Table { tag, data }).collect());
+        glyf::rebuild(&mut font, &glyphs).unwrap();
+        font
+    }
+
+    fn subset_of(text: &str, layout_closure: bool) -> (Font, Report) {
+        let mut font = font();
+        let report = subset(&mut font, &text.chars().map(u32::from).collect(), layout_closure, false).unwrap();
+        (font, report)
+    }
+
+    /// The glyph IDs that still have an outline.
+    fn outlines(font: &Font) -> BTreeSet<u16> {
+        let glyf = Glyf::parse(font).unwrap().unwrap();
+        (0..glyf.num_glyphs() as u16).filter(|&g| !glyf.data(g).is_empty()).collect()
+    }
+
+    /// The variation data of glyph `g`.
+    fn variations(font: &Font, g: usize) -> &[u8] {
+        let gvar = font.table(b"gvar").unwrap();
+        assert_eq!(be_u16(gvar, 14), Some(1));
+        let offset = |g: usize| be_u32(gvar, 20 + 4 * g).unwrap() as usize;
+        let data = be_u32(gvar, 16).unwrap() as usize;
+        &gvar[data + offset(g)..data + offset(g + 1)]
+    }
+
+    #[test]
+    fn keeps_requested_glyphs_and_their_closure() {
+        let (font, report) = subset_of("fií", true);
+        assert_eq!((report.characters, report.missing, report.glyphs, report.total_glyphs), (4, 0, 7, 8));
+        assert_eq!(report.kept, BTreeSet::from([0, 1, 2, 3, 4, 5, 6]));
+        assert_eq!(outlines(&font), BTreeSet::from([0, 2, 3, 4, 5, 6]));
+        assert_eq!(font.table(b"GPOS"), Some(&GPOS[..]));
+        assert_eq!(be_u32(font.table(b"post").unwrap(), 0), Some(0x0003_0000));
+        assert_eq!(variations(&font, 4), [4; 4]);
+        assert!(variations(&font, 7).is_empty());
+
+        let bytes = font.to_bytes();
+        let face = ttf_parser::Face::parse(&bytes, 0).unwrap();
+        assert_eq!(face.number_of_glyphs(), 8);
+        let glyph = |c| face.glyph_index(c).map(|g| g.0);
+        assert_eq!(['f', 'i', 'í', ' ', 'A', '\u{301}'].map(glyph), [Some(2), Some(3), Some(6), Some(1), None, None]);
+        let advance = |g| face.glyph_hor_advance(ttf_parser::GlyphId(g));
+        assert_eq!([4, 5, 6, 7].map(advance), [Some(540), Some(550), Some(560), Some(0)]);
+    }
+
+    #[test]
+    fn ligatures_need_the_layout_closure_and_all_components() {
+        let (font, report) = subset_of("fi", false);
+        assert_eq!(report.kept, BTreeSet::from([0, 1, 2, 3]));
+        assert_eq!(outlines(&font), BTreeSet::from([0, 2, 3]));
+        assert!(variations(&font, 4).is_empty());
+        assert_eq!(subset_of("fi", true).1.kept, BTreeSet::from([0, 1, 2, 3, 4]));
+        assert_eq!(subset_of("f", true).1.kept, BTreeSet::from([0, 1, 2]));
+    }
+
+    #[test]
+    fn falls_back_to_the_decomposition_of_unmapped_characters() {
+        let (font, report) = subset_of("ì", true);
+        assert_eq!(report.kept, BTreeSet::from([0, 1, 3]));
+        let cmap = CharMap::parse(font.table(b"cmap").unwrap()).unwrap();
+        assert_eq!(['i', 'ì', 'í'].map(|c| cmap.glyph(c)), [Some(3), None, None]);
+    }
+
+    #[test]
+    fn narrows_svg_records_to_retained_glyphs() {
+        let records = |text| {
+            let svg = subset_of(text, false).0.table(b"SVG ").map(<[u8]>::to_vec)?;
+            let count = be_u16(&svg, 10).unwrap() as usize;
+            Some((0..count).map(|i| [be_u16(&svg, 12 + 12 * i).unwrap(), be_u16(&svg, 14 + 12 * i).unwrap()]).collect())
+        };
+        assert_eq!(records("fi"), Some(vec![[2, 3]]));
+        assert_eq!(records("A"), Some(vec![[7, 7]]));
+        assert_eq!(records("fA"), Some(vec![[2, 2], [7, 7]]));
+        assert_eq!(records("í"), Some(vec![[3, 3], [6, 6]]));
+        assert_eq!(records(" "), None);
+    }
+}
@@ -0,0 +1,164 @@
+//! GSUB inventory, reported by analyze with `include_substitutions`.
+//!
+//! For each GSUB feature, what it actually substitutes: ligatures
+//! (components → glyph), single substitutions (the contents of a stylistic
+//! set, small caps, old-style figures) and alternates. Lookups a feature
+//! reaches through contextual rules are followed, so programming ligatures
+//! built from `calt` rules over single substitutions (Fira Code's `=>`) show
+//! up too, under the feature that triggers them. Glyphs are labelled as in
+//! [`crate::analysis::GlyphLabels`]. Each list stops at [`MAX_ENTRIES`].
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use ttf_parser::{Face, Tag};
+
+use crate::{analysis::GlyphLabels, layout, sfnt::be_u16};
+
+pub const MAX_ENTRIES: usize = 500;
+const SINGLE: u16 = 1;
+const ALTERNATE: u16 = 3;
+const LIGATURE: u16 = 4;
+const CONTEXT: u16 = 5;
+const CHAINED: u16 = 6;
+const EXTENSION: u16 = 7;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FeatureSubstitutions {
+    pub tag: String,
+    /// Lookups applied, including those reached through contextual rules.
+    pub lookups: usize,
+    /// Whether any of them are contextual, so the substitutions below only
+    /// happen in some surroundings.
+    pub contextual: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ligatures: Vec<Ligature>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub singles: Vec<Single>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternates: Vec<Alternates>,
+    /// A list was cut at [`MAX_ENTRIES`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Ligature {
+    pub components: Vec<String>,
+    pub glyph: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Single {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Alternates {
+    pub glyph: String,
+    pub alternates: Vec<String>,
+}
+
+/// Glyph IDs gathered from a feature's subtables before labelling.
+#[derive(Default)]
+struct Found {
+    ligatures: Vec<(Vec<u16>, u16)>,
+    singles: Vec<(u16, u16)>,
+    alternates: Vec<(u16, Vec<u16>)>,
+}
+
+fn glyphs(gsub: &[u8], at: usize, count: usize) -> Option<Vec<u16>> {
+    (0..count).map(|i| be_u16(gsub, at + 2 * i)).collect()
+}
+
+/// Adds what the single, alternate or ligature subtable at `sub` does.
+fn collect(gsub: &[u8], kind: u16, sub: usize, found: &mut Found) -> Option<()> {
+    let coverage = layout::coverage(gsub, sub + be_u16(gsub, sub + 2)? as usize)?;
+    match (kind, be_u16(gsub, sub)?) {
+        (SINGLE, 1) => {
+            let delta = be_u16(gsub, sub + 4)?;
+            found.singles.extend(coverage.iter().map(|&g| (g, g.wrapping_add(delta))));
+        }
+        (SINGLE, 2) => {
+            let to = glyphs(gsub, sub + 6, be_u16(gsub, sub + 4)? as usize)?;
+            found.singles.extend(coverage.iter().copied().zip(to));
+        }
+        (ALTERNATE, 1) => {
+            for (i, &glyph) in coverage.iter().enumerate().take(be_u16(gsub, sub + 4)? as usize) {
+                let set = sub + be_u16(gsub, sub + 6 + 2 * i)? as usize;
+                found.alternates.push((glyph, glyphs(gsub, set + 2, be_u16(gsub, set)? as usize)?));
+            }
+        }
+        (LIGATURE, 1) => {
+            for (i, &first) in coverage.iter().enumerate().take(be_u16(gsub, sub + 4)? as usize) {
+                let set = sub + be_u16(gsub, sub + 6 + 2 * i)? as usize;
+                for j in 0..be_u16(gsub, set)? as usize {
+                    let ligature = set + be_u16(gsub, set + 2 + 2 * j)? as usize;
+                    let count = be_u16(gsub, ligature + 2)? as usize;
+                    let mut components = vec![first];
+                    components.extend(glyphs(gsub, ligature + 4, count.saturating_sub(1))?);
+                    found.ligatures.push((components, be_u16(gsub, ligature)?));
+                }
+            }
+        }
+        _ => {}
+    }
+    Some(())
+}
+
+/// Labels up to [`MAX_ENTRIES`] of `items`; the flag says whether any were
+/// left out.
+fn labelled<T, U>(items: Vec<T>, label: impl Fn(T) -> U) -> (Vec<U>, bool) {
+    let truncated = items.len() > MAX_ENTRIES;
+    (items.into_iter().take(MAX_ENTRIES).map(label).collect(), truncated)
+}
+
+pub fn report(data: &[u8]) -> Result<Vec<FeatureSubstitutions>, String> {
+    let face = Face::parse(data, 0).map_err(|e| format!("not a parseable font: {e}"))?;
+    let Some(gsub) = face.raw_face().table(Tag::from_bytes(b"GSUB")) else { return Ok(Vec::new()) };
+    let malformed = || "GSUB table is malformed".to_string();
+    let features = layout::feature_lookups(gsub).ok_or_else(malformed)?;
+    let lookups = layout::lookups(gsub, EXTENSION).ok_or_else(malformed)?;
+    let labels = GlyphLabels::new(&face);
+
+    let mut out = Vec::with_capacity(features.len());
+    for (tag, direct) in features {
+        let mut pending: Vec<u16> = direct.into_iter().collect();
+        let mut reached = BTreeSet::new();
+        let mut contextual = false;
+        let mut found = Found::default();
+        while let Some(index) = pending.pop() {
+            if !reached.insert(index) {
+                continue;
+            }
+            for &(kind, sub) in lookups.get(index as usize).map_or(&[][..], Vec::as_slice) {
+                if matches!(kind, CONTEXT | CHAINED) {
+                    contextual = true;
+                    pending.extend(layout::nested_lookups(gsub, kind, sub, CONTEXT, CHAINED).ok_or_else(malformed)?);
+                } else {
+                    collect(gsub, kind, sub, &mut found).ok_or_else(malformed)?;
+                }
+            }
+        }
+        let (ligatures, cut_ligatures) = labelled(found.ligatures, |(components, glyph)| Ligature {
+            components: components.into_iter().map(|g| labels.get(g)).collect(),
+            glyph: labels.get(glyph),
+        });
+        let (singles, cut_singles) =
+            labelled(found.singles, |(from, to)| Single { from: labels.get(from), to: labels.get(to) });
+        let (alternates, cut_alternates) = labelled(found.alternates, |(glyph, alternates)| Alternates {
+            glyph: labels.get(glyph),
+            alternates: alternates.into_iter().map(|g| labels.get(g)).collect(),
+        });
+        out.push(FeatureSubstitutions {
+            tag: String::from_utf8_lossy(&tag).into_owned(),
+            lookups: reached.len(),
+            contextual,
+            ligatures,
+            singles,
+            alternates,
+            truncated: cut_ligatures || cut_singles || cut_alternates,
+        });
+    }
+    Ok(out)
+}
@@ -0,0 +1,184 @@
+//! `GET /api/v1/system/info`: how this replica is built, configured and
+//! behaving, at a glance.
+//!
+//! For operators comparing replicas (admin only): the build (version,
+//! `GIT_COMMIT` and `BUILD_TIMESTAMP` from the environment it was compiled
+//! in, profile, features), uptime, backends (storage, database, job queue,
+//! scanner, parse isolation, TLS, edges), the subset cache tier, worker
+//! pools and per-route concurrency limits, and the error rates of the
+//! requests it answered over the last 1, 5, 15 and 60 minutes. `/health`
+//! stays a cheap liveness check.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{batch, cancel, isolation, tls, AppState};
+
+const WINDOWS_MINUTES: &[u64] = &[1, 5, 15, 60];
+
+/// Requests answered in one minute.
+#[derive(Default)]
+struct Minute {
+    minute: u64,
+    requests: u64,
+    client_errors: u64,
+    server_errors: u64,
+}
+
+/// The last hour, a minute per entry, oldest first.
+static RECENT: Mutex<VecDeque<Minute>> = Mutex::new(VecDeque::new());
+
+fn now_minute() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() / 60)
+}
+
+/// Counts an answered request (see [`crate::telemetry::track`]).
+pub fn record(status: StatusCode) {
+    let minute = now_minute();
+    let mut recent = RECENT.lock().unwrap();
+    if recent.back().is_none_or(|m| m.minute != minute) {
+        recent.push_back(Minute { minute, ..Minute::default() });
+    }
+    while recent.front().is_some_and(|m| m.minute + WINDOWS_MINUTES[WINDOWS_MINUTES.len() - 1] <= minute) {
+        recent.pop_front();
+    }
+    let current = recent.back_mut().expect("pushed above");
+    current.requests += 1;
+    current.client_errors += u64::from(status.is_client_error());
+    current.server_errors += u64::from(status.is_server_error());
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorWindow {
+    minutes: u64,
+    requests: u64,
+    client_errors: u64,
+    server_errors: u64,
+    /// Server errors over requests.
+    error_rate: f64,
+}
+
+fn error_windows() -> Vec<ErrorWindow> {
+    let minute = now_minute();
+    let recent = RECENT.lock().unwrap();
+    WINDOWS_MINUTES
+        .iter()
+        .map(|&minutes| {
+            let window = recent.iter().filter(|m| m.minute + minutes > minute);
+            let (requests, client_errors, server_errors) =
+                window.fold((0, 0, 0), |(r, c, s), m| (r + m.requests, c + m.client_errors, s + m.server_errors));
+            let error_rate = if requests == 0 { 0.0 } else { server_errors as f64 / requests as f64 };
+            ErrorWindow { minutes, requests, client_errors, server_errors, error_rate }
+        })
+        .collect()
+}
+
+/// Cargo features the engine was compiled with.
+pub fn features() -> Vec<&'static str> {
+    [("alice-core", cfg!(feature = "alice-core"))].into_iter().filter_map(|(name, on)| on.then_some(name)).collect()
+}
+
+#[derive(Debug, Serialize)]
+pub struct Build {
+    version: &'static str,
+    git_commit: Option<&'static str>,
+    build_timestamp: Option<&'static str>,
+    profile: &'static str,
+    features: Vec<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Backends {
+    /// `local` or `s3`.
+    storage: String,
+    artifacts: String,
+    database: bool,
+    /// `memory` or `postgres`.
+    job_queue: &'static str,
+    scanner: &'static str,
+    parse_isolation: bool,
+    tls: bool,
+    edges: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheTier {
+    subset_cache_budget_bytes: usize,
+    subset_cache_bytes: usize,
+    subset_cache_entries: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Workers {
+    job_workers: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replica: Option<String>,
+    batch_concurrency: usize,
+    processing_timeout_secs: u64,
+    /// By route; unlimited routes are absent.
+    concurrency_limits: BTreeMap<&'static str, usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SystemInfo {
+    build: Build,
+    uptime_secs: u64,
+    started_at_unix: u64,
+    backends: Backends,
+    cache: CacheTier,
+    workers: Workers,
+    jobs: cancel::JobStatsSnapshot,
+    errors: Vec<ErrorWindow>,
+}
+
+pub async fn info(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<SystemInfo>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let uptime = state.start_time.elapsed();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (job_queue, job_workers, replica) = state.queue.describe();
+    let (budget, bytes, entries) = state.subsets.usage();
+    let storage = std::env::var("FONT_STORAGE").ok().filter(|v| !v.is_empty()).unwrap_or_else(|| "local".to_string());
+    Ok(Json(SystemInfo {
+        build: Build {
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: option_env!("GIT_COMMIT"),
+            build_timestamp: option_env!("BUILD_TIMESTAMP"),
+            profile: if cfg!(debug_assertions) { "debug" } else { "release" },
+            features: features(),
+        },
+        uptime_secs: uptime.as_secs(),
+        started_at_unix: now.saturating_sub(uptime).as_secs(),
+        backends: Backends {
+            storage,
+            artifacts: state.artifacts.location(),
+            database: state.db.is_some(),
+            job_queue,
+            scanner: state.scanner.name(),
+            parse_isolation: isolation::isolated(),
+            tls: tls::paths().is_some(),
+            edges: state.edges.statuses().len(),
+        },
+        cache: CacheTier { subset_cache_budget_bytes: budget, subset_cache_bytes: bytes, subset_cache_entries: entries },
+        workers: Workers {
+            job_workers,
+            replica: replica.map(String::from),
+            batch_concurrency: batch::concurrency(),
+            processing_timeout_secs: cancel::processing_timeout().as_secs(),
+            concurrency_limits: state.shedding.limits(),
+        },
+        jobs: state.jobs.snapshot(),
+        errors: error_windows(),
+    }))
+}
@@ -0,0 +1,43 @@
+//! systemd integration: `sd_notify` readiness and watchdog keep-alives, and
+//! socket activation via `LISTEN_FDS`. Every function is a no-op when the
+//! process was not started by systemd.
+
+use sd_notify::NotifyState;
+use std::os::fd::FromRawFd;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Takes over the first socket passed by systemd socket activation, if any.
+pub fn activated_listener() -> Option<std::net::TcpListener> {
+    let fd = sd_notify::listen_fds().ok()?.next()?;
+    // SAFETY: systemd hands us ownership of the descriptors it passes in
+    // LISTEN_FDS, and nothing else in the process refers to this one.
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+    if let Err(e) = listener.set_nonblocking(true) {
+        warn!("activated socket unusable: {e}");
+        return None;
+    }
+    info!("using socket-activated listener (fd {fd})");
+    Some(listener)
+}
+
+/// Reports READY=1 and, when `WatchdogSec=` is set, starts pinging the
+/// watchdog at half its interval from the async runtime, so a wedged event
+/// loop stops the pings and systemd restarts the service.
+pub fn notify_ready(status: &str) {
+    if let Err(e) = sd_notify::notify(&[NotifyState::Ready, NotifyState::Status(status)]) {
+        warn!("sd_notify READY failed: {e}");
+    }
+    let Some(timeout) = sd_notify::watchdog_enabled() else {
+        return;
+    };
+    let period = (timeout / 2).max(Duration::from_millis(100));
+    info!(?period, "systemd watchdog enabled");
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(period);
+        loop {
+            tick.tick().await;
+            let _ = sd_notify::notify(&[NotifyState::Watchdog]);
+        }
+    });
+}
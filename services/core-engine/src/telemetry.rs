@@ -0,0 +1,98 @@
+//! Prometheus metrics at `/metrics`.
+//!
+//! [`track`] runs as a route layer around every handler and records request
+//! counts, latency, response bytes and conditional-request (`If-None-Match`,
+//! `If-Modified-Since`) hits keyed by the matched route pattern, so label cardinality stays
+//! bounded and new handlers are covered without instrumentation.
+//! Compression and subset output adds the size ratios achieved (see
+//! [`record_output`]), and in-memory caches their hit rate and size (see
+//! [`record_cache`]).
+
+use axum::{
+    body::HttpBody,
+    extract::{MatchedPath, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::{sync::Arc, time::Instant};
+
+use crate::{system, AppState};
+
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+const RATIO_BUCKETS: &[f64] = &[1.0, 1.25, 1.5, 2.0, 3.0, 5.0, 10.0, 20.0, 50.0, 100.0];
+
+/// Installs the global recorder; call once at startup.
+pub fn install() -> PrometheusHandle {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Full("http_request_duration_seconds".to_string()), LATENCY_BUCKETS)
+        .and_then(|b| b.set_buckets_for_metric(Matcher::Full("font_output_ratio".to_string()), RATIO_BUCKETS))
+        .expect("invalid metric buckets")
+        .install_recorder()
+        .expect("failed to install metrics recorder")
+}
+
+pub async fn track(request: Request, next: Next) -> Response {
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => return next.run(request).await,
+    };
+    let method = request.method().to_string();
+    let headers = request.headers();
+    let conditional = headers.contains_key(header::IF_NONE_MATCH) || headers.contains_key(header::IF_MODIFIED_SINCE);
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let status = response.status();
+    system::record(status);
+
+    metrics::counter!(
+        "http_requests_total",
+        "method" => method.clone(),
+        "route" => route.clone(),
+        "status" => status.as_u16().to_string()
+    )
+    .increment(1);
+    metrics::histogram!("http_request_duration_seconds", "method" => method, "route" => route.clone())
+        .record(start.elapsed().as_secs_f64());
+    if let Some(length) = content_length(&response) {
+        metrics::counter!("http_response_bytes_total", "route" => route.clone()).increment(length);
+    }
+    if conditional {
+        let result = if status == StatusCode::NOT_MODIFIED { "hit" } else { "miss" };
+        metrics::counter!("http_conditional_requests_total", "route" => route, "result" => result).increment(1);
+    }
+    response
+}
+
+/// Body size from `Content-Length`, or from the body itself when it is
+/// fully buffered (handlers returning JSON or CSS).
+pub fn content_length(response: &Response) -> Option<u64> {
+    match response.headers().get(header::CONTENT_LENGTH) {
+        Some(length) => length.to_str().ok()?.parse().ok(),
+        None => response.body().size_hint().exact(),
+    }
+}
+
+/// Records how much smaller generated output is than its source.
+pub fn record_output(operation: &'static str, format: &str, original: usize, output: usize) {
+    let ratio = original as f64 / output.max(1) as f64;
+    metrics::histogram!("font_output_ratio", "operation" => operation, "format" => format.to_string()).record(ratio);
+    metrics::counter!("font_output_bytes_total", "operation" => operation, "format" => format.to_string())
+        .increment(output as u64);
+}
+
+/// Counts a lookup in one of the in-memory caches.
+pub fn record_cache(cache: &'static str, hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    metrics::counter!("cache_requests_total", "cache" => cache, "result" => result).increment(1);
+}
+
+pub fn record_cache_size(cache: &'static str, bytes: usize, entries: usize) {
+    metrics::gauge!("cache_bytes", "cache" => cache).set(bytes as f64);
+    metrics::gauge!("cache_entries", "cache" => cache).set(entries as f64);
+}
+
+pub async fn render(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], state.telemetry.render())
+}
@@ -0,0 +1,95 @@
+//! Tenant-scoped catalogs.
+//!
+//! A catalog entry with a `tenant` belongs to that tenant; entries without
+//! one are shared. Callers are identified by `X-Font-Tenant`, which an API
+//! key bound to a tenant or the gateway sets; [`crate::auth`] drops it from
+//! anyone else's requests. Callers see the shared entries and their own:
+//! other tenants' entries are left out of listings, and a request naming one
+//! is answered as if it did not exist. Faces registered from an uploaded
+//! collection belong to the upload's tenant.
+//!
+//! Generated files of a tenant's fonts are served from
+//! `/cdn/<tenant>/fonts/<slug>/...` and those of shared fonts from
+//! `/cdn/fonts/<slug>/...`; each path answers `404` for the other's files.
+//! Private fonts still need signed URLs (see [`crate::signing`]).
+
+use axum::http::{HeaderMap, StatusCode};
+
+use crate::{artifacts, AppState, FontCatalogEntry};
+
+/// The calling tenant; empty for none.
+pub fn of(headers: &HeaderMap) -> &str {
+    headers.get("x-font-tenant").and_then(|v| v.to_str().ok()).unwrap_or_default()
+}
+
+fn sees(tenant: &str, entry: &FontCatalogEntry) -> bool {
+    entry.tenant.is_empty() || entry.tenant == tenant
+}
+
+/// Tenants name a CDN path segment: lowercase letters, digits and `-`.
+pub fn validate(tenant: &str) -> Result<(), String> {
+    let valid = tenant.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !valid || tenant == "fonts" {
+        return Err(format!("tenant '{tenant}' must be lowercase letters, digits and '-', and not 'fonts'"));
+    }
+    Ok(())
+}
+
+/// The catalog without other tenants' entries.
+pub fn visible(headers: &HeaderMap, entries: Vec<FontCatalogEntry>) -> Vec<FontCatalogEntry> {
+    let tenant = of(headers);
+    entries.into_iter().filter(|e| sees(tenant, e)).collect()
+}
+
+/// `404` when `name` (an id, family name or family slug) only matches other
+/// tenants' entries; names outside the catalog are left to the caller.
+pub fn ensure_font(headers: &HeaderMap, catalog: &[FontCatalogEntry], name: &str) -> Result<(), (StatusCode, String)> {
+    let key = name.trim().to_lowercase();
+    let matching: Vec<&FontCatalogEntry> = catalog
+        .iter()
+        .filter(|e| {
+            let family = e.family.to_lowercase();
+            e.id == key || family == key || family.replace(' ', "-") == key
+        })
+        .collect();
+    let tenant = of(headers);
+    if !matching.is_empty() && !matching.iter().any(|e| sees(tenant, e)) {
+        return Err((StatusCode::NOT_FOUND, format!("no catalog font '{name}'")));
+    }
+    Ok(())
+}
+
+/// The tenant owning the files under `/cdn/.../<slug>/`, empty for shared
+/// fonts; artifacts are named after the font's id or family.
+pub fn owner(state: &AppState, slug: &str) -> String {
+    let catalog = state.catalog.read().unwrap();
+    let entry = catalog.iter().find(|e| artifacts::slug(&e.id) == slug || artifacts::slug(&e.family) == slug);
+    entry.map(|e| e.tenant.clone()).unwrap_or_default()
+}
+
+/// `/cdn/fonts` or `/cdn/<tenant>/fonts`.
+pub fn prefix(tenant: &str) -> String {
+    if tenant.is_empty() {
+        "/cdn/fonts".to_string()
+    } else {
+        format!("/cdn/{tenant}/fonts")
+    }
+}
+
+/// `path` (`/cdn/fonts/<slug>/...`) moved under its owner's prefix.
+pub fn cdn_path(state: &AppState, path: &str) -> String {
+    let Some(rest) = path.strip_prefix("/cdn/fonts/") else { return path.to_string() };
+    let slug = rest.split('/').next().unwrap_or_default();
+    format!("{}/{rest}", prefix(&owner(state, slug)))
+}
+
+/// The storage key (`<slug>/<file>`) in a CDN path or URL under either
+/// prefix; empty when there is none.
+pub fn artifact_key(path: &str) -> &str {
+    let Some(at) = path.find("/cdn/") else { return "" };
+    let rest = &path[at + "/cdn/".len()..];
+    match rest.strip_prefix("fonts/") {
+        Some(key) => key,
+        None => rest.split_once('/').and_then(|(_, r)| r.strip_prefix("fonts/")).unwrap_or_default(),
+    }
+}
@@ -0,0 +1,183 @@
+//! HTTPS termination without a fronting proxy.
+//!
+//! With `TLS_CERT` and `TLS_KEY` (PEM files) set, the main listener speaks
+//! TLS, offering HTTP/2 and HTTP/1.1 over ALPN. Both files are polled every
+//! `TLS_RELOAD_SECS` (default 30) and a renewed certificate is picked up for
+//! new handshakes without a restart; one that fails to load is logged and the
+//! previous pair kept. `TLS_REDIRECT_ADDR` adds a plain-HTTP listener that
+//! answers every request with a 308 to the same path over HTTPS.
+
+use axum::{
+    http::{header, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    Router,
+};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use rustls::{
+    crypto::{ring, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig,
+};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower_service::Service;
+use tracing::{debug, info, warn};
+
+fn var(k: &str) -> Option<String> {
+    std::env::var(k).ok().filter(|v| !v.is_empty())
+}
+
+/// Certificate and key paths, when TLS is configured.
+pub fn paths() -> Option<(PathBuf, PathBuf)> {
+    Some((PathBuf::from(var("TLS_CERT")?), PathBuf::from(var("TLS_KEY")?)))
+}
+
+fn load(cert: &Path, key: &Path, provider: &CryptoProvider) -> Result<CertifiedKey, String> {
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("{}: {e}", cert.display()))?;
+    if chain.is_empty() {
+        return Err(format!("{}: no certificates found", cert.display()));
+    }
+    let private = PrivateKeyDer::from_pem_file(key).map_err(|e| format!("{}: {e}", key.display()))?;
+    CertifiedKey::from_der(chain, private, provider).map_err(|e| format!("{}: {e}", key.display()))
+}
+
+/// Parses the configured pair; used by `--check-config`.
+pub fn check(cert: &Path, key: &Path) -> Result<(), String> {
+    load(cert, key, &ring::default_provider()).map(|_| ())
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Hands out the current certificate, which [`Reloader::watch`] replaces when
+/// the files change.
+#[derive(Debug)]
+struct Reloader {
+    cert: PathBuf,
+    key: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for Reloader {
+    fn resolve(&self, _hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(&self.current.read().unwrap()))
+    }
+}
+
+impl Reloader {
+    async fn watch(self: Arc<Self>, provider: Arc<CryptoProvider>, interval: Duration) {
+        let stamp = || (modified(&self.cert), modified(&self.key));
+        let mut seen = stamp();
+        let mut tick = tokio::time::interval(interval);
+        tick.tick().await;
+        loop {
+            tick.tick().await;
+            let now = stamp();
+            if now == seen {
+                continue;
+            }
+            match load(&self.cert, &self.key, &provider) {
+                Ok(key) => {
+                    *self.current.write().unwrap() = Arc::new(key);
+                    seen = now;
+                    info!(cert = %self.cert.display(), "TLS certificate reloaded");
+                }
+                // A half-written renewal: keep serving the old pair and retry.
+                Err(e) => warn!("TLS certificate reload failed, keeping the previous one: {e}"),
+            }
+        }
+    }
+}
+
+/// Builds the acceptor and starts watching the certificate files.
+pub fn acceptor(cert: PathBuf, key: PathBuf) -> Result<TlsAcceptor, String> {
+    let provider = Arc::new(ring::default_provider());
+    let current = load(&cert, &key, &provider)?;
+    let reloader = Arc::new(Reloader { cert, key, current: RwLock::new(Arc::new(current)) });
+    let secs = var("TLS_RELOAD_SECS").and_then(|v| v.parse().ok()).unwrap_or(30u64).max(1);
+    tokio::spawn(Arc::clone(&reloader).watch(Arc::clone(&provider), Duration::from_secs(secs)));
+
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_no_client_auth()
+        .with_cert_resolver(reloader);
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Accepts connections on `listener`, completes the handshake and serves
+/// `app` on each; the peer address is available as `ConnectInfo` like under
+/// `axum::serve`.
+pub async fn serve(listener: TcpListener, acceptor: TlsAcceptor, app: Router) {
+    let mut make_service = app.into_make_service_with_connect_info::<SocketAddr>();
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("accept failed: {e}");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let service = match make_service.call(peer).await {
+            Ok(service) => service,
+            Err(never) => match never {},
+        };
+        let acceptor = acceptor.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!(%peer, "TLS handshake failed: {e}");
+                    return;
+                }
+            };
+            let served = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(service))
+                .await;
+            if let Err(e) = served {
+                debug!(%peer, "connection closed: {e}");
+            }
+        });
+    }
+}
+
+/// The plain-HTTP listener that points clients at `https_port`.
+pub async fn redirect(addr: SocketAddr, https_port: u16) {
+    let listener = TcpListener::bind(addr).await.expect("failed to bind TLS_REDIRECT_ADDR");
+    info!("redirecting HTTP on {addr} to HTTPS");
+    let app = Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move {
+        to_https(headers.get(header::HOST).and_then(|h| h.to_str().ok()), &uri, https_port)
+    });
+    axum::serve(listener, app).await.expect("redirect server error");
+}
+
+fn to_https(host: Option<&str>, uri: &Uri, https_port: u16) -> Response {
+    let Some(host) = host.or_else(|| uri.host()) else {
+        return (StatusCode::BAD_REQUEST, "Host header required").into_response();
+    };
+    // Drop the plain-HTTP port, keeping IPv6 brackets intact.
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    };
+    let port = if https_port == 443 { String::new() } else { format!(":{https_port}") };
+    let path = uri.path_and_query().map_or("/", |p| p.as_str());
+    Redirect::permanent(&format!("https://{name}{port}{path}")).into_response()
+}
@@ -0,0 +1,109 @@
+//! The transform options compress, subset and instances share
+//! ([`TransformOptions`], flattened into their requests).
+//!
+//! One request composes what took a chain of endpoints and an artifact per
+//! step: `{"font_name": "Inter", "preset": "latin", "instance": {"wght":
+//! 600}, "format": "woff2", "quality": 90}` to `/api/v1/font/subset` cuts
+//! the static instance, subsets it and encodes it into one file. [`resolve`]
+//! checks the options and applies the catalog entry's defaults the same way
+//! for every endpoint; [`Transform::load`] runs the `instance` step and the
+//! handlers the steps only they have. `instance` and `output_name` change
+//! the artifact address only when given, so existing addresses stay valid.
+
+use axum::http::StatusCode;
+use font_api::{ProcessingDefaults, TransformOptions};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use crate::{
+    artifacts::{self, Address},
+    compress, instancer, prune,
+    sfnt::Font,
+    AppState,
+};
+
+/// [`TransformOptions`] checked, with the catalog defaults applied.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Transform {
+    pub format: String,
+    /// As asked for; what it falls back to differs by endpoint.
+    pub quality: Option<u8>,
+    pub strip_hints: bool,
+    pub features: Option<prune::Filter>,
+    pub layout_closure: bool,
+    pub instance: Option<BTreeMap<String, f64>>,
+    /// Slugged.
+    pub output_name: Option<String>,
+}
+
+fn bad_request(message: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message)
+}
+
+/// Checks `options` against the `formats` an endpoint writes and fills in
+/// `strip_hints` from `defaults`, adding it to `applied` when it does.
+pub fn resolve(
+    options: &TransformOptions,
+    formats: &[&str],
+    defaults: &ProcessingDefaults,
+    applied: &mut Vec<String>,
+) -> Result<Transform, (StatusCode, String)> {
+    if !formats.contains(&options.format.as_str()) {
+        return Err(bad_request(format!("unsupported format '{}'; valid: {}", options.format, formats.join(", "))));
+    }
+    if options.quality.is_some_and(|q| q > 100) {
+        return Err(bad_request("quality must be 0-100".to_string()));
+    }
+    let strip_hints = match (options.strip_hints, defaults.strip_hints) {
+        (Some(s), _) => s,
+        (None, Some(s)) => {
+            applied.push("strip_hints".to_string());
+            s
+        }
+        (None, None) => false,
+    };
+    let features = prune::Filter::new(&options.drop_features, options.keep_features.as_deref()).map_err(bad_request)?;
+    if let Some(axes) = &options.instance {
+        if axes.is_empty() {
+            return Err(bad_request("instance must give at least one axis position".to_string()));
+        }
+        if let Some((tag, value)) = axes.iter().find(|(tag, value)| tag.len() != 4 || !value.is_finite()) {
+            return Err(bad_request(format!("instance: '{tag}': {value} is not an axis tag and position")));
+        }
+    }
+    let output_name = match &options.output_name {
+        Some(name) if artifacts::slug(name).is_empty() => {
+            return Err(bad_request(format!("output_name '{name}' has no usable characters")));
+        }
+        name => name.as_deref().map(artifacts::slug),
+    };
+    Ok(Transform {
+        format: options.format.clone(),
+        quality: options.quality,
+        strip_hints,
+        features,
+        layout_closure: options.layout_closure.unwrap_or(true),
+        instance: options.instance.clone(),
+        output_name,
+    })
+}
+
+impl Transform {
+    /// Parses `data`, cut to the static `instance` when there is one.
+    pub fn load(&self, data: &[u8]) -> Result<Font, String> {
+        let font = compress::load(data)?;
+        match &self.instance {
+            Some(axes) => instancer::at(&font, axes),
+            None => Ok(font),
+        }
+    }
+
+    /// Where the output goes; `steps` describes the endpoint's own steps.
+    pub fn address(&self, state: &AppState, font_name: &str, source: &str, mut steps: Value) -> Address {
+        if let Some(axes) = &self.instance {
+            steps["instance"] = json!(axes);
+        }
+        state.artifacts.named_address(font_name, self.output_name.as_deref(), source, &steps, &self.format)
+    }
+}
@@ -0,0 +1,160 @@
+//! `POST /api/v1/admin/tuning/brotli`: WOFF2 size against encode time at
+//! every Brotli level.
+//!
+//! `quality` 0-100 is mapped onto Brotli levels linearly (see
+//! [`compress::brotli_level`]). To choose catalog `defaults` on data rather
+//! than guesswork, this encodes one catalog or uploaded font as WOFF2 at
+//! levels 1-11 (after `strip_hints` when asked), each `repeats` times
+//! (1-5, default 3), and reports per level the output size, the median
+//! encode time, what the level saves over the one below, and the qualities
+//! that map to it. `recommended_level` is the lowest level within 1% of the
+//! smallest output. Nothing is stored.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Instant};
+use tracing::info;
+
+use crate::{cancel, compress, duplicates, extract::ApiJson, woff, AppState};
+
+const LEVELS: std::ops::RangeInclusive<u8> = 1..=11;
+const MAX_REPEATS: u32 = 5;
+
+fn default_repeats() -> u32 {
+    3
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BrotliSweepRequest {
+    #[serde(default)]
+    font_name: String,
+    font_id: Option<String>,
+    #[serde(default)]
+    strip_hints: bool,
+    #[serde(default = "default_repeats")]
+    repeats: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LevelResult {
+    level: u8,
+    bytes: usize,
+    /// Original size over this size.
+    ratio: f64,
+    /// Bytes smaller than the level below; negative when larger.
+    saved_over_previous: i64,
+    /// Median over the repeats.
+    encode_ms: f64,
+    /// The `quality` values mapping to this level, as `lo-hi`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    qualities: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BrotliSweepResponse {
+    font_name: String,
+    original_bytes: usize,
+    strip_hints: bool,
+    repeats: u32,
+    levels: Vec<LevelResult>,
+    recommended_level: u8,
+}
+
+fn qualities(level: u8) -> Option<String> {
+    let mapped: Vec<u8> = (0..=100).filter(|&q| compress::brotli_level(q) == level).collect();
+    match (mapped.first(), mapped.last()) {
+        (Some(lo), Some(hi)) if lo == hi => Some(lo.to_string()),
+        (Some(lo), Some(hi)) => Some(format!("{lo}-{hi}")),
+        _ => None,
+    }
+}
+
+pub async fn brotli(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ApiJson(mut req): ApiJson<BrotliSweepRequest>,
+) -> Result<Json<BrotliSweepResponse>, (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    if !(1..=MAX_REPEATS).contains(&req.repeats) {
+        return Err((StatusCode::BAD_REQUEST, format!("repeats must be 1-{MAX_REPEATS}")));
+    }
+    let (font_name, upload) = state.uploads.resolve(&headers, req.font_id.as_deref(), &req.font_name).await?;
+    req.font_name = font_name;
+    let uploaded = match &upload {
+        Some(upload) => Some(state.uploads.read(upload).await?),
+        None => None,
+    };
+
+    let (job_state, name) = (Arc::clone(&state), req.font_name.clone());
+    let (strip_hints, repeats) = (req.strip_hints, req.repeats);
+    let (original_bytes, sweep) = cancel::run(&state.jobs, "brotli-sweep", move |token| {
+        let data = match uploaded.map_or_else(|| duplicates::catalog_binary(&job_state, &name), Ok) {
+            Ok(data) => data,
+            Err(e) => return Ok(Err(e)),
+        };
+        let mut font = match compress::load(&data) {
+            Ok(font) => font,
+            Err(e) => return Ok(Err(e)),
+        };
+        if strip_hints {
+            if let Err(e) = compress::strip_hints(&mut font) {
+                return Ok(Err(e));
+            }
+        }
+        let sfnt = font.to_bytes();
+        let mut sweep = Vec::new();
+        for level in LEVELS {
+            let mut times = Vec::new();
+            let mut bytes = 0;
+            for _ in 0..repeats {
+                token.check()?;
+                let started = Instant::now();
+                match woff::encode_woff2(&sfnt, level) {
+                    Ok(encoded) => bytes = encoded.len(),
+                    Err(e) => return Ok(Err(e)),
+                }
+                times.push(started.elapsed().as_secs_f64() * 1000.0);
+            }
+            times.sort_by(f64::total_cmp);
+            sweep.push((level, bytes, times[times.len() / 2]));
+        }
+        Ok(Ok((data.len(), sweep)))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let smallest = sweep.iter().map(|&(_, bytes, _)| bytes).min().unwrap_or(0);
+    let recommended_level = sweep
+        .iter()
+        .find(|&&(_, bytes, _)| bytes as f64 <= smallest as f64 * 1.01)
+        .map_or(*LEVELS.end(), |&(level, ..)| level);
+    let mut previous = None;
+    let levels = sweep
+        .into_iter()
+        .map(|(level, bytes, encode_ms)| {
+            let saved_over_previous = previous.map_or(0, |p: usize| p as i64 - bytes as i64);
+            previous = Some(bytes);
+            LevelResult {
+                level,
+                bytes,
+                ratio: original_bytes as f64 / bytes.max(1) as f64,
+                saved_over_previous,
+                encode_ms: (encode_ms * 10.0).round() / 10.0,
+                qualities: qualities(level),
+            }
+        })
+        .collect();
+    info!(font = %req.font_name, recommended_level, "brotli sweep");
+    Ok(Json(BrotliSweepResponse {
+        font_name: req.font_name,
+        original_bytes,
+        strip_hints: req.strip_hints,
+        repeats: req.repeats,
+        levels,
+        recommended_level,
+    }))
+}
@@ -0,0 +1,561 @@
+//! Unicode range helpers shared by catalog previews, coverage reports and
+//! CSS `unicode-range` generation.
+
+use axum::{body::Body, http::StatusCode, response::Json};
+use serde::Serialize;
+use std::{collections::BTreeSet, ops::RangeInclusive};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{cmap::CharMap, sfnt::Font, spool};
+
+/// Parses a CSS `unicode-range` item: `U+0041`, `U+0000-00FF` or `U+4??`.
+pub fn parse_range(s: &str) -> Option<RangeInclusive<u32>> {
+    let hex = s.trim().strip_prefix("U+").or_else(|| s.trim().strip_prefix("u+"))?;
+    let (start, end) = match hex.split_once('-') {
+        Some((a, b)) => (u32::from_str_radix(a, 16).ok()?, u32::from_str_radix(b, 16).ok()?),
+        None if hex.contains('?') => (
+            u32::from_str_radix(&hex.replace('?', "0"), 16).ok()?,
+            u32::from_str_radix(&hex.replace('?', "F"), 16).ok()?,
+        ),
+        None => {
+            let cp = u32::from_str_radix(hex, 16).ok()?;
+            (cp, cp)
+        }
+    };
+    (start <= end && end <= 0x10FFFF).then_some(start..=end)
+}
+
+/// Subset input in canonical composed form (NFC), then, with `decompose`,
+/// the characters of its canonical decomposition (NFD) not already in it.
+pub fn normalize_characters(characters: &str, decompose: bool) -> String {
+    let composed: String = characters.nfc().collect();
+    if !decompose {
+        return composed;
+    }
+    let mut seen: BTreeSet<char> = composed.chars().collect();
+    let extra: String = composed.nfd().filter(|&c| seen.insert(c)).collect();
+    composed + &extra
+}
+
+/// Parses a comma-separated `unicode-range` value, rejecting malformed
+/// items, and merges the ranges.
+pub fn parse_list(field: &str, list: &str) -> Result<Vec<RangeInclusive<u32>>, (StatusCode, String)> {
+    let items = list.split(',').map(str::trim).filter(|s| !s.is_empty());
+    let ranges = items
+        .map(|item| {
+            parse_range(item).ok_or_else(|| (StatusCode::BAD_REQUEST, format!("{field}: '{item}' is not a U+ range")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(merge(ranges))
+}
+
+/// Parses every item, skipping malformed ones, and merges overlapping or
+/// adjacent ranges into sorted order.
+pub fn parse_ranges<S: AsRef<str>>(items: &[S]) -> Vec<RangeInclusive<u32>> {
+    merge(items.iter().filter_map(|s| parse_range(s.as_ref())).collect())
+}
+
+/// Sorts `ranges` and merges overlapping or adjacent ones.
+pub fn merge(mut ranges: Vec<RangeInclusive<u32>>) -> Vec<RangeInclusive<u32>> {
+    ranges.sort_by_key(|r| *r.start());
+    let mut merged: Vec<RangeInclusive<u32>> = Vec::with_capacity(ranges.len());
+    for r in ranges {
+        match merged.last_mut() {
+            Some(last) if *r.start() <= last.end().saturating_add(1) => {
+                *last = *last.start()..=(*last.end()).max(*r.end());
+            }
+            _ => merged.push(r),
+        }
+    }
+    merged
+}
+
+/// Code points in both `a` and `b` (each sorted and merged).
+pub fn intersect(a: &[RangeInclusive<u32>], b: &[RangeInclusive<u32>]) -> Vec<RangeInclusive<u32>> {
+    let mut out = Vec::new();
+    for x in a {
+        for y in b {
+            let (lo, hi) = ((*x.start()).max(*y.start()), (*x.end()).min(*y.end()));
+            if lo <= hi {
+                out.push(lo..=hi);
+            }
+        }
+    }
+    merge(out)
+}
+
+/// Formats a range as a CSS `unicode-range` item (`U+0041`, `U+0061-007A`).
+pub fn format_range(r: &RangeInclusive<u32>) -> String {
+    if r.start() == r.end() {
+        format!("U+{:04X}", r.start())
+    } else {
+        format!("U+{:04X}-{:04X}", r.start(), r.end())
+    }
+}
+
+/// The value of a `unicode-range` descriptor for `ranges`.
+pub fn css_unicode_range(ranges: &[RangeInclusive<u32>]) -> String {
+    ranges.iter().map(format_range).collect::<Vec<_>>().join(", ")
+}
+
+pub fn covers(ranges: &[RangeInclusive<u32>], c: char) -> bool {
+    ranges.iter().any(|r| r.contains(&(c as u32)))
+}
+
+/// Inclusive code point ranges.
+type Ranges = &'static [(u32, u32)];
+
+/// Named character sets a subset can start from instead of (or on top of)
+/// an explicit character list.
+pub const PRESETS: &[(&str, Ranges)] = &[
+    ("latin", &[(0x0020, 0x007E), (0x00A0, 0x00FF), (0x2013, 0x2014), (0x2018, 0x201E), (0x2026, 0x2026), (0x20AC, 0x20AC)]),
+    ("latin-ext", &[(0x0100, 0x024F), (0x1E00, 0x1EFF), (0x20A0, 0x20CF)]),
+    ("vietnamese", &[(0x0102, 0x0103), (0x0110, 0x0111), (0x0128, 0x0129), (0x0168, 0x0169), (0x01A0, 0x01A1), (0x01AF, 0x01B0), (0x1EA0, 0x1EF9)]),
+    ("greek", &[(0x0370, 0x03FF)]),
+    ("greek-ext", &[(0x1F00, 0x1FFF)]),
+    ("cyrillic", &[(0x0400, 0x045F), (0x0490, 0x0491), (0x2116, 0x2116)]),
+    ("cyrillic-ext", &[(0x0460, 0x052F), (0x1C80, 0x1C88), (0x2DE0, 0x2DFF), (0xA640, 0xA69F)]),
+    ("arabic", &[(0x0600, 0x06FF), (0x0750, 0x077F), (0x200C, 0x200F), (0xFB50, 0xFDFF), (0xFE70, 0xFEFF)]),
+    ("hebrew", &[(0x0590, 0x05FF), (0x200C, 0x200F), (0xFB1D, 0xFB4F)]),
+    ("thai", &[(0x0E01, 0x0E5B), (0x200C, 0x200D)]),
+    ("devanagari", &[(0x0900, 0x097F), (0x200C, 0x200D), (0x20B9, 0x20B9), (0x25CC, 0x25CC), (0xA8E0, 0xA8FF)]),
+    ("kana", KANA),
+    ("emoji", EMOJI),
+    // The Emoticons block with what its sequences need.
+    ("emoji-smileys", &[(0x200D, 0x200D), (0xFE0F, 0xFE0F), (0x1F3FB, 0x1F3FF), (0x1F600, 0x1F64F)]),
+];
+
+/// Emoji with keycap bases, joiners, variation selectors and tag
+/// characters, so sequences keep working.
+const EMOJI: Ranges = &[
+    (0x0023, 0x0023), (0x002A, 0x002A), (0x0030, 0x0039), (0x00A9, 0x00A9), (0x00AE, 0x00AE), (0x200D, 0x200D),
+    (0x203C, 0x203C), (0x2049, 0x2049), (0x20E3, 0x20E3), (0x2122, 0x2122), (0x2139, 0x2139), (0x2194, 0x21AA),
+    (0x231A, 0x23FF), (0x24C2, 0x24C2), (0x25AA, 0x25FE), (0x2600, 0x27BF), (0x2934, 0x2935), (0x2B05, 0x2B55),
+    (0x3030, 0x3030), (0x303D, 0x303D), (0x3297, 0x3299), (0xFE0E, 0xFE0F), (0x1F000, 0x1FAFF), (0xE0020, 0xE007F),
+];
+
+const KANA: Ranges = &[(0x3000, 0x303F), (0x3041, 0x309F), (0x30A0, 0x30FF), (0xFF01, 0xFF9F)];
+
+/// Presets built from a curated character list (`presets/*.txt`, `#` lines
+/// are comments) plus ranges.
+const LISTED: &[(&str, &str, Ranges)] = &[
+    ("japanese-joyo", include_str!("presets/joyo.txt"), KANA),
+    // With Hangul compatibility jamo and CJK punctuation.
+    ("korean-2350", include_str!("presets/ksx1001-hangul.txt"), &[(0x3000, 0x303F), (0x3131, 0x318E)]),
+];
+
+fn listed(list: &str, ranges: &[(u32, u32)]) -> Vec<RangeInclusive<u32>> {
+    let chars = list.lines().filter(|l| !l.starts_with('#')).flat_map(str::chars).filter(|c| !c.is_whitespace());
+    merge(chars.map(|c| c as u32..=c as u32).chain(ranges.iter().map(|&(a, b)| a..=b)).collect())
+}
+
+/// Code point ranges of a named preset, or of several joined with `,`
+/// (`latin,japanese-joyo`); items may also be `unicode-range` values
+/// (`emoji,U+1F680-1F6FF`). `None` if any name is unknown.
+pub fn preset(name: &str) -> Option<Vec<RangeInclusive<u32>>> {
+    let mut ranges = Vec::new();
+    for name in name.split(',').map(str::trim) {
+        match PRESETS.iter().find(|(n, _)| *n == name) {
+            Some((_, r)) => ranges.extend(r.iter().map(|&(a, b)| a..=b)),
+            None if name.starts_with(['U', 'u']) => ranges.push(parse_range(name)?),
+            None => ranges.extend(LISTED.iter().find(|(n, ..)| *n == name).map(|(_, list, r)| listed(list, r))?),
+        }
+    }
+    Some(merge(ranges))
+}
+
+pub fn preset_names() -> String {
+    PRESETS.iter().map(|(n, _)| *n).chain(LISTED.iter().map(|(n, ..)| *n)).collect::<Vec<_>>().join(", ")
+}
+
+/// Unicode 14 blocks, with the number of characters assigned in each
+/// (controls, noncharacters and unassigned code points left out); the
+/// surrogate blocks, which fonts cannot map, are not listed.
+pub const BLOCKS: &[(&str, u32, u32, u32)] = &[
+    ("Basic Latin", 0x0000, 0x007F, 95),
+    ("Latin-1 Supplement", 0x0080, 0x00FF, 96),
+    ("Latin Extended-A", 0x0100, 0x017F, 128),
+    ("Latin Extended-B", 0x0180, 0x024F, 208),
+    ("IPA Extensions", 0x0250, 0x02AF, 96),
+    ("Spacing Modifier Letters", 0x02B0, 0x02FF, 80),
+    ("Combining Diacritical Marks", 0x0300, 0x036F, 112),
+    ("Greek and Coptic", 0x0370, 0x03FF, 135),
+    ("Cyrillic", 0x0400, 0x04FF, 256),
+    ("Cyrillic Supplement", 0x0500, 0x052F, 48),
+    ("Armenian", 0x0530, 0x058F, 91),
+    ("Hebrew", 0x0590, 0x05FF, 88),
+    ("Arabic", 0x0600, 0x06FF, 256),
+    ("Syriac", 0x0700, 0x074F, 77),
+    ("Arabic Supplement", 0x0750, 0x077F, 48),
+    ("Thaana", 0x0780, 0x07BF, 50),
+    ("NKo", 0x07C0, 0x07FF, 62),
+    ("Samaritan", 0x0800, 0x083F, 61),
+    ("Mandaic", 0x0840, 0x085F, 29),
+    ("Syriac Supplement", 0x0860, 0x086F, 11),
+    ("Arabic Extended-B", 0x0870, 0x089F, 41),
+    ("Arabic Extended-A", 0x08A0, 0x08FF, 96),
+    ("Devanagari", 0x0900, 0x097F, 128),
+    ("Bengali", 0x0980, 0x09FF, 96),
+    ("Gurmukhi", 0x0A00, 0x0A7F, 80),
+    ("Gujarati", 0x0A80, 0x0AFF, 91),
+    ("Oriya", 0x0B00, 0x0B7F, 91),
+    ("Tamil", 0x0B80, 0x0BFF, 72),
+    ("Telugu", 0x0C00, 0x0C7F, 100),
+    ("Kannada", 0x0C80, 0x0CFF, 90),
+    ("Malayalam", 0x0D00, 0x0D7F, 118),
+    ("Sinhala", 0x0D80, 0x0DFF, 91),
+    ("Thai", 0x0E00, 0x0E7F, 87),
+    ("Lao", 0x0E80, 0x0EFF, 82),
+    ("Tibetan", 0x0F00, 0x0FFF, 211),
+    ("Myanmar", 0x1000, 0x109F, 160),
+    ("Georgian", 0x10A0, 0x10FF, 88),
+    ("Hangul Jamo", 0x1100, 0x11FF, 256),
+    ("Ethiopic", 0x1200, 0x137F, 358),
+    ("Ethiopic Supplement", 0x1380, 0x139F, 26),
+    ("Cherokee", 0x13A0, 0x13FF, 92),
+    ("Unified Canadian Aboriginal Syllabics", 0x1400, 0x167F, 640),
+    ("Ogham", 0x1680, 0x169F, 29),
+    ("Runic", 0x16A0, 0x16FF, 89),
+    ("Tagalog", 0x1700, 0x171F, 23),
+    ("Hanunoo", 0x1720, 0x173F, 23),
+    ("Buhid", 0x1740, 0x175F, 20),
+    ("Tagbanwa", 0x1760, 0x177F, 18),
+    ("Khmer", 0x1780, 0x17FF, 114),
+    ("Mongolian", 0x1800, 0x18AF, 158),
+    ("Unified Canadian Aboriginal Syllabics Extended", 0x18B0, 0x18FF, 70),
+    ("Limbu", 0x1900, 0x194F, 68),
+    ("Tai Le", 0x1950, 0x197F, 35),
+    ("New Tai Lue", 0x1980, 0x19DF, 83),
+    ("Khmer Symbols", 0x19E0, 0x19FF, 32),
+    ("Buginese", 0x1A00, 0x1A1F, 30),
+    ("Tai Tham", 0x1A20, 0x1AAF, 127),
+    ("Combining Diacritical Marks Extended", 0x1AB0, 0x1AFF, 31),
+    ("Balinese", 0x1B00, 0x1B7F, 124),
+    ("Sundanese", 0x1B80, 0x1BBF, 64),
+    ("Batak", 0x1BC0, 0x1BFF, 56),
+    ("Lepcha", 0x1C00, 0x1C4F, 74),
+    ("Ol Chiki", 0x1C50, 0x1C7F, 48),
+    ("Cyrillic Extended-C", 0x1C80, 0x1C8F, 9),
+    ("Georgian Extended", 0x1C90, 0x1CBF, 46),
+    ("Sundanese Supplement", 0x1CC0, 0x1CCF, 8),
+    ("Vedic Extensions", 0x1CD0, 0x1CFF, 43),
+    ("Phonetic Extensions", 0x1D00, 0x1D7F, 128),
+    ("Phonetic Extensions Supplement", 0x1D80, 0x1DBF, 64),
+    ("Combining Diacritical Marks Supplement", 0x1DC0, 0x1DFF, 64),
+    ("Latin Extended Additional", 0x1E00, 0x1EFF, 256),
+    ("Greek Extended", 0x1F00, 0x1FFF, 233),
+    ("General Punctuation", 0x2000, 0x206F, 111),
+    ("Superscripts and Subscripts", 0x2070, 0x209F, 42),
+    ("Currency Symbols", 0x20A0, 0x20CF, 33),
+    ("Combining Diacritical Marks for Symbols", 0x20D0, 0x20FF, 33),
+    ("Letterlike Symbols", 0x2100, 0x214F, 80),
+    ("Number Forms", 0x2150, 0x218F, 60),
+    ("Arrows", 0x2190, 0x21FF, 112),
+    ("Mathematical Operators", 0x2200, 0x22FF, 256),
+    ("Miscellaneous Technical", 0x2300, 0x23FF, 256),
+    ("Control Pictures", 0x2400, 0x243F, 39),
+    ("Optical Character Recognition", 0x2440, 0x245F, 11),
+    ("Enclosed Alphanumerics", 0x2460, 0x24FF, 160),
+    ("Box Drawing", 0x2500, 0x257F, 128),
+    ("Block Elements", 0x2580, 0x259F, 32),
+    ("Geometric Shapes", 0x25A0, 0x25FF, 96),
+    ("Miscellaneous Symbols", 0x2600, 0x26FF, 256),
+    ("Dingbats", 0x2700, 0x27BF, 192),
+    ("Miscellaneous Mathematical Symbols-A", 0x27C0, 0x27EF, 48),
+    ("Supplemental Arrows-A", 0x27F0, 0x27FF, 16),
+    ("Braille Patterns", 0x2800, 0x28FF, 256),
+    ("Supplemental Arrows-B", 0x2900, 0x297F, 128),
+    ("Miscellaneous Mathematical Symbols-B", 0x2980, 0x29FF, 128),
+    ("Supplemental Mathematical Operators", 0x2A00, 0x2AFF, 256),
+    ("Miscellaneous Symbols and Arrows", 0x2B00, 0x2BFF, 253),
+    ("Glagolitic", 0x2C00, 0x2C5F, 96),
+    ("Latin Extended-C", 0x2C60, 0x2C7F, 32),
+    ("Coptic", 0x2C80, 0x2CFF, 123),
+    ("Georgian Supplement", 0x2D00, 0x2D2F, 40),
+    ("Tifinagh", 0x2D30, 0x2D7F, 59),
+    ("Ethiopic Extended", 0x2D80, 0x2DDF, 79),
+    ("Cyrillic Extended-A", 0x2DE0, 0x2DFF, 32),
+    ("Supplemental Punctuation", 0x2E00, 0x2E7F, 94),
+    ("CJK Radicals Supplement", 0x2E80, 0x2EFF, 115),
+    ("Kangxi Radicals", 0x2F00, 0x2FDF, 214),
+    ("Ideographic Description Characters", 0x2FF0, 0x2FFF, 12),
+    ("CJK Symbols and Punctuation", 0x3000, 0x303F, 64),
+    ("Hiragana", 0x3040, 0x309F, 93),
+    ("Katakana", 0x30A0, 0x30FF, 96),
+    ("Bopomofo", 0x3100, 0x312F, 43),
+    ("Hangul Compatibility Jamo", 0x3130, 0x318F, 94),
+    ("Kanbun", 0x3190, 0x319F, 16),
+    ("Bopomofo Extended", 0x31A0, 0x31BF, 32),
+    ("CJK Strokes", 0x31C0, 0x31EF, 36),
+    ("Katakana Phonetic Extensions", 0x31F0, 0x31FF, 16),
+    ("Enclosed CJK Letters and Months", 0x3200, 0x32FF, 255),
+    ("CJK Compatibility", 0x3300, 0x33FF, 256),
+    ("CJK Unified Ideographs Extension A", 0x3400, 0x4DBF, 6592),
+    ("Yijing Hexagram Symbols", 0x4DC0, 0x4DFF, 64),
+    ("CJK Unified Ideographs", 0x4E00, 0x9FFF, 20992),
+    ("Yi Syllables", 0xA000, 0xA48F, 1165),
+    ("Yi Radicals", 0xA490, 0xA4CF, 55),
+    ("Lisu", 0xA4D0, 0xA4FF, 48),
+    ("Vai", 0xA500, 0xA63F, 300),
+    ("Cyrillic Extended-B", 0xA640, 0xA69F, 96),
+    ("Bamum", 0xA6A0, 0xA6FF, 88),
+    ("Modifier Tone Letters", 0xA700, 0xA71F, 32),
+    ("Latin Extended-D", 0xA720, 0xA7FF, 193),
+    ("Syloti Nagri", 0xA800, 0xA82F, 45),
+    ("Common Indic Number Forms", 0xA830, 0xA83F, 10),
+    ("Phags-pa", 0xA840, 0xA87F, 56),
+    ("Saurashtra", 0xA880, 0xA8DF, 82),
+    ("Devanagari Extended", 0xA8E0, 0xA8FF, 32),
+    ("Kayah Li", 0xA900, 0xA92F, 48),
+    ("Rejang", 0xA930, 0xA95F, 37),
+    ("Hangul Jamo Extended-A", 0xA960, 0xA97F, 29),
+    ("Javanese", 0xA980, 0xA9DF, 91),
+    ("Myanmar Extended-B", 0xA9E0, 0xA9FF, 31),
+    ("Cham", 0xAA00, 0xAA5F, 83),
+    ("Myanmar Extended-A", 0xAA60, 0xAA7F, 32),
+    ("Tai Viet", 0xAA80, 0xAADF, 72),
+    ("Meetei Mayek Extensions", 0xAAE0, 0xAAFF, 23),
+    ("Ethiopic Extended-A", 0xAB00, 0xAB2F, 32),
+    ("Latin Extended-E", 0xAB30, 0xAB6F, 60),
+    ("Cherokee Supplement", 0xAB70, 0xABBF, 80),
+    ("Meetei Mayek", 0xABC0, 0xABFF, 56),
+    ("Hangul Syllables", 0xAC00, 0xD7AF, 11172),
+    ("Hangul Jamo Extended-B", 0xD7B0, 0xD7FF, 72),
+    ("Private Use Area", 0xE000, 0xF8FF, 6400),
+    ("CJK Compatibility Ideographs", 0xF900, 0xFAFF, 472),
+    ("Alphabetic Presentation Forms", 0xFB00, 0xFB4F, 58),
+    ("Arabic Presentation Forms-A", 0xFB50, 0xFDFF, 631),
+    ("Variation Selectors", 0xFE00, 0xFE0F, 16),
+    ("Vertical Forms", 0xFE10, 0xFE1F, 10),
+    ("Combining Half Marks", 0xFE20, 0xFE2F, 16),
+    ("CJK Compatibility Forms", 0xFE30, 0xFE4F, 32),
+    ("Small Form Variants", 0xFE50, 0xFE6F, 26),
+    ("Arabic Presentation Forms-B", 0xFE70, 0xFEFF, 141),
+    ("Halfwidth and Fullwidth Forms", 0xFF00, 0xFFEF, 225),
+    ("Specials", 0xFFF0, 0xFFFF, 5),
+    ("Linear B Syllabary", 0x10000, 0x1007F, 88),
+    ("Linear B Ideograms", 0x10080, 0x100FF, 123),
+    ("Aegean Numbers", 0x10100, 0x1013F, 57),
+    ("Ancient Greek Numbers", 0x10140, 0x1018F, 79),
+    ("Ancient Symbols", 0x10190, 0x101CF, 14),
+    ("Phaistos Disc", 0x101D0, 0x101FF, 46),
+    ("Lycian", 0x10280, 0x1029F, 29),
+    ("Carian", 0x102A0, 0x102DF, 49),
+    ("Coptic Epact Numbers", 0x102E0, 0x102FF, 28),
+    ("Old Italic", 0x10300, 0x1032F, 39),
+    ("Gothic", 0x10330, 0x1034F, 27),
+    ("Old Permic", 0x10350, 0x1037F, 43),
+    ("Ugaritic", 0x10380, 0x1039F, 31),
+    ("Old Persian", 0x103A0, 0x103DF, 50),
+    ("Deseret", 0x10400, 0x1044F, 80),
+    ("Shavian", 0x10450, 0x1047F, 48),
+    ("Osmanya", 0x10480, 0x104AF, 40),
+    ("Osage", 0x104B0, 0x104FF, 72),
+    ("Elbasan", 0x10500, 0x1052F, 40),
+    ("Caucasian Albanian", 0x10530, 0x1056F, 53),
+    ("Vithkuqi", 0x10570, 0x105BF, 70),
+    ("Linear A", 0x10600, 0x1077F, 341),
+    ("Latin Extended-F", 0x10780, 0x107BF, 57),
+    ("Cypriot Syllabary", 0x10800, 0x1083F, 55),
+    ("Imperial Aramaic", 0x10840, 0x1085F, 31),
+    ("Palmyrene", 0x10860, 0x1087F, 32),
+    ("Nabataean", 0x10880, 0x108AF, 40),
+    ("Hatran", 0x108E0, 0x108FF, 26),
+    ("Phoenician", 0x10900, 0x1091F, 29),
+    ("Lydian", 0x10920, 0x1093F, 27),
+    ("Meroitic Hieroglyphs", 0x10980, 0x1099F, 32),
+    ("Meroitic Cursive", 0x109A0, 0x109FF, 90),
+    ("Kharoshthi", 0x10A00, 0x10A5F, 68),
+    ("Old South Arabian", 0x10A60, 0x10A7F, 32),
+    ("Old North Arabian", 0x10A80, 0x10A9F, 32),
+    ("Manichaean", 0x10AC0, 0x10AFF, 51),
+    ("Avestan", 0x10B00, 0x10B3F, 61),
+    ("Inscriptional Parthian", 0x10B40, 0x10B5F, 30),
+    ("Inscriptional Pahlavi", 0x10B60, 0x10B7F, 27),
+    ("Psalter Pahlavi", 0x10B80, 0x10BAF, 29),
+    ("Old Turkic", 0x10C00, 0x10C4F, 73),
+    ("Old Hungarian", 0x10C80, 0x10CFF, 108),
+    ("Hanifi Rohingya", 0x10D00, 0x10D3F, 50),
+    ("Rumi Numeral Symbols", 0x10E60, 0x10E7F, 31),
+    ("Yezidi", 0x10E80, 0x10EBF, 47),
+    ("Old Sogdian", 0x10F00, 0x10F2F, 40),
+    ("Sogdian", 0x10F30, 0x10F6F, 42),
+    ("Old Uyghur", 0x10F70, 0x10FAF, 26),
+    ("Chorasmian", 0x10FB0, 0x10FDF, 28),
+    ("Elymaic", 0x10FE0, 0x10FFF, 23),
+    ("Brahmi", 0x11000, 0x1107F, 115),
+    ("Kaithi", 0x11080, 0x110CF, 68),
+    ("Sora Sompeng", 0x110D0, 0x110FF, 35),
+    ("Chakma", 0x11100, 0x1114F, 71),
+    ("Mahajani", 0x11150, 0x1117F, 39),
+    ("Sharada", 0x11180, 0x111DF, 96),
+    ("Sinhala Archaic Numbers", 0x111E0, 0x111FF, 20),
+    ("Khojki", 0x11200, 0x1124F, 62),
+    ("Multani", 0x11280, 0x112AF, 38),
+    ("Khudawadi", 0x112B0, 0x112FF, 69),
+    ("Grantha", 0x11300, 0x1137F, 86),
+    ("Newa", 0x11400, 0x1147F, 97),
+    ("Tirhuta", 0x11480, 0x114DF, 82),
+    ("Siddham", 0x11580, 0x115FF, 92),
+    ("Modi", 0x11600, 0x1165F, 79),
+    ("Mongolian Supplement", 0x11660, 0x1167F, 13),
+    ("Takri", 0x11680, 0x116CF, 68),
+    ("Ahom", 0x11700, 0x1174F, 65),
+    ("Dogra", 0x11800, 0x1184F, 60),
+    ("Warang Citi", 0x118A0, 0x118FF, 84),
+    ("Dives Akuru", 0x11900, 0x1195F, 72),
+    ("Nandinagari", 0x119A0, 0x119FF, 65),
+    ("Zanabazar Square", 0x11A00, 0x11A4F, 72),
+    ("Soyombo", 0x11A50, 0x11AAF, 83),
+    ("Unified Canadian Aboriginal Syllabics Extended-A", 0x11AB0, 0x11ABF, 16),
+    ("Pau Cin Hau", 0x11AC0, 0x11AFF, 57),
+    ("Bhaiksuki", 0x11C00, 0x11C6F, 97),
+    ("Marchen", 0x11C70, 0x11CBF, 68),
+    ("Masaram Gondi", 0x11D00, 0x11D5F, 75),
+    ("Gunjala Gondi", 0x11D60, 0x11DAF, 63),
+    ("Makasar", 0x11EE0, 0x11EFF, 25),
+    ("Lisu Supplement", 0x11FB0, 0x11FBF, 1),
+    ("Tamil Supplement", 0x11FC0, 0x11FFF, 51),
+    ("Cuneiform", 0x12000, 0x123FF, 922),
+    ("Cuneiform Numbers and Punctuation", 0x12400, 0x1247F, 116),
+    ("Early Dynastic Cuneiform", 0x12480, 0x1254F, 196),
+    ("Cypro-Minoan", 0x12F90, 0x12FFF, 99),
+    ("Egyptian Hieroglyphs", 0x13000, 0x1342F, 1071),
+    ("Egyptian Hieroglyph Format Controls", 0x13430, 0x1343F, 9),
+    ("Anatolian Hieroglyphs", 0x14400, 0x1467F, 583),
+    ("Bamum Supplement", 0x16800, 0x16A3F, 569),
+    ("Mro", 0x16A40, 0x16A6F, 43),
+    ("Tangsa", 0x16A70, 0x16ACF, 89),
+    ("Bassa Vah", 0x16AD0, 0x16AFF, 36),
+    ("Pahawh Hmong", 0x16B00, 0x16B8F, 127),
+    ("Medefaidrin", 0x16E40, 0x16E9F, 91),
+    ("Miao", 0x16F00, 0x16F9F, 149),
+    ("Ideographic Symbols and Punctuation", 0x16FE0, 0x16FFF, 7),
+    ("Tangut", 0x17000, 0x187FF, 6136),
+    ("Tangut Components", 0x18800, 0x18AFF, 768),
+    ("Khitan Small Script", 0x18B00, 0x18CFF, 470),
+    ("Tangut Supplement", 0x18D00, 0x18D7F, 9),
+    ("Kana Extended-B", 0x1AFF0, 0x1AFFF, 13),
+    ("Kana Supplement", 0x1B000, 0x1B0FF, 256),
+    ("Kana Extended-A", 0x1B100, 0x1B12F, 35),
+    ("Small Kana Extension", 0x1B130, 0x1B16F, 7),
+    ("Nushu", 0x1B170, 0x1B2FF, 396),
+    ("Duployan", 0x1BC00, 0x1BC9F, 143),
+    ("Shorthand Format Controls", 0x1BCA0, 0x1BCAF, 4),
+    ("Znamenny Musical Notation", 0x1CF00, 0x1CFCF, 185),
+    ("Byzantine Musical Symbols", 0x1D000, 0x1D0FF, 246),
+    ("Musical Symbols", 0x1D100, 0x1D1FF, 233),
+    ("Ancient Greek Musical Notation", 0x1D200, 0x1D24F, 70),
+    ("Mayan Numerals", 0x1D2E0, 0x1D2FF, 20),
+    ("Tai Xuan Jing Symbols", 0x1D300, 0x1D35F, 87),
+    ("Counting Rod Numerals", 0x1D360, 0x1D37F, 25),
+    ("Mathematical Alphanumeric Symbols", 0x1D400, 0x1D7FF, 996),
+    ("Sutton SignWriting", 0x1D800, 0x1DAAF, 672),
+    ("Latin Extended-G", 0x1DF00, 0x1DFFF, 31),
+    ("Glagolitic Supplement", 0x1E000, 0x1E02F, 38),
+    ("Nyiakeng Puachue Hmong", 0x1E100, 0x1E14F, 71),
+    ("Toto", 0x1E290, 0x1E2BF, 31),
+    ("Wancho", 0x1E2C0, 0x1E2FF, 59),
+    ("Ethiopic Extended-B", 0x1E7E0, 0x1E7FF, 28),
+    ("Mende Kikakui", 0x1E800, 0x1E8DF, 213),
+    ("Adlam", 0x1E900, 0x1E95F, 88),
+    ("Indic Siyaq Numbers", 0x1EC70, 0x1ECBF, 68),
+    ("Ottoman Siyaq Numbers", 0x1ED00, 0x1ED4F, 61),
+    ("Arabic Mathematical Alphabetic Symbols", 0x1EE00, 0x1EEFF, 143),
+    ("Mahjong Tiles", 0x1F000, 0x1F02F, 44),
+    ("Domino Tiles", 0x1F030, 0x1F09F, 100),
+    ("Playing Cards", 0x1F0A0, 0x1F0FF, 82),
+    ("Enclosed Alphanumeric Supplement", 0x1F100, 0x1F1FF, 200),
+    ("Enclosed Ideographic Supplement", 0x1F200, 0x1F2FF, 64),
+    ("Miscellaneous Symbols and Pictographs", 0x1F300, 0x1F5FF, 768),
+    ("Emoticons", 0x1F600, 0x1F64F, 80),
+    ("Ornamental Dingbats", 0x1F650, 0x1F67F, 48),
+    ("Transport and Map Symbols", 0x1F680, 0x1F6FF, 117),
+    ("Alchemical Symbols", 0x1F700, 0x1F77F, 116),
+    ("Geometric Shapes Extended", 0x1F780, 0x1F7FF, 102),
+    ("Supplemental Arrows-C", 0x1F800, 0x1F8FF, 150),
+    ("Supplemental Symbols and Pictographs", 0x1F900, 0x1F9FF, 256),
+    ("Chess Symbols", 0x1FA00, 0x1FA6F, 98),
+    ("Symbols and Pictographs Extended-A", 0x1FA70, 0x1FAFF, 88),
+    ("Symbols for Legacy Computing", 0x1FB00, 0x1FBFF, 212),
+    ("CJK Unified Ideographs Extension B", 0x20000, 0x2A6DF, 42720),
+    ("CJK Unified Ideographs Extension C", 0x2A700, 0x2B73F, 4153),
+    ("CJK Unified Ideographs Extension D", 0x2B740, 0x2B81F, 222),
+    ("CJK Unified Ideographs Extension E", 0x2B820, 0x2CEAF, 5762),
+    ("CJK Unified Ideographs Extension F", 0x2CEB0, 0x2EBEF, 7473),
+    ("CJK Compatibility Ideographs Supplement", 0x2F800, 0x2FA1F, 542),
+    ("CJK Unified Ideographs Extension G", 0x30000, 0x3134F, 4939),
+    ("Tags", 0xE0000, 0xE007F, 97),
+    ("Variation Selectors Supplement", 0xE0100, 0xE01EF, 240),
+    ("Supplementary Private Use Area-A", 0xF0000, 0xFFFFF, 65534),
+    ("Supplementary Private Use Area-B", 0x100000, 0x10FFFF, 65534),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockCoverage {
+    pub block: &'static str,
+    pub range: String,
+    pub covered: u32,
+    pub total: u32,
+    pub percent: f64,
+}
+
+/// Code points a block's total leaves out: C0 and C1 controls and the
+/// noncharacters.
+fn uncounted() -> impl Iterator<Item = (u32, u32)> {
+    let plane_ends = (0..=0x10).map(|plane| (plane << 16 | 0xFFFE, plane << 16 | 0xFFFF));
+    [(0x0000, 0x001F), (0x007F, 0x009F), (0xFDD0, 0xFDEF)].into_iter().chain(plane_ends)
+}
+
+/// Coverage of each block the ranges touch, in code point order, counted
+/// against the block's assigned characters.
+pub fn block_coverage(ranges: &[RangeInclusive<u32>]) -> Vec<BlockCoverage> {
+    let overlap = |(start, end): (u32, u32), r: &RangeInclusive<u32>| {
+        let (lo, hi) = ((*r.start()).max(start), (*r.end()).min(end));
+        (lo <= hi).then_some(lo..=hi)
+    };
+    let len = |r: &RangeInclusive<u32>| r.end() - r.start() + 1;
+    BLOCKS
+        .iter()
+        .filter_map(|&(block, start, end, total)| {
+            let mapped: u32 = ranges
+                .iter()
+                .filter_map(|r| overlap((start, end), r))
+                .map(|r| len(&r) - uncounted().filter_map(|u| overlap(u, &r)).map(|u| len(&u)).sum::<u32>())
+                .sum();
+            // A font may map code points Unicode 14 leaves unassigned.
+            let covered = mapped.min(total);
+            (covered > 0).then(|| BlockCoverage {
+                block,
+                range: format!("U+{start:04X}-{end:04X}"),
+                covered,
+                total,
+                percent: (covered as f64 * 1000.0 / total as f64).round() / 10.0,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnicodeRangeResponse {
+    /// Ready to paste after `unicode-range:`.
+    unicode_range: String,
+    ranges: Vec<String>,
+    code_points: u32,
+}
+
+/// `unicode-range` computed from a raw font's own cmap, so the descriptor
+/// never claims a character the file cannot render.
+pub async fn from_font(body: Body) -> Result<Json<UnicodeRangeResponse>, (StatusCode, String)> {
+    let (data, _) = spool::receive_font(body).await?;
+    let unprocessable = |e: String| (StatusCode::UNPROCESSABLE_ENTITY, e);
+    let font = Font::parse(&data).map_err(unprocessable)?;
+    let cmap = CharMap::parse(font.table(b"cmap").ok_or_else(|| unprocessable("font has no cmap table".into()))?)
+        .map_err(unprocessable)?;
+    let ranges = cmap.ranges();
+    Ok(Json(UnicodeRangeResponse {
+        unicode_range: css_unicode_range(&ranges),
+        code_points: ranges.iter().map(|r| r.end() - r.start() + 1).sum(),
+        ranges: ranges.iter().map(format_range).collect(),
+    }))
+}
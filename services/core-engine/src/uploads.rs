@@ -0,0 +1,307 @@
+//! Font uploads.
+//!
+//! `POST /api/v1/font/upload` takes a `multipart/form-data` body with the
+//! font in a `font` field (TTF, OTF, WOFF, WOFF2, or a TTC collection whose
+//! faces are listed and extracted through [`crate::collection`]). The file is
+//! spooled, structurally checked and virus-scanned like `/font/scan` and
+//! validated like `/font/validate` in a separate process (see
+//! [`isolation`]; failures go to quarantine), then stored by content hash
+//! (see [`storage`]; `UPLOAD_DIR` on local disk). The returned ID is
+//! accepted as `font_id` by compress, subset and analyze. Uploads are private to the tenant that made them.
+//! Records uploaded through another replica are fetched from storage on
+//! first use.
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    response::Json,
+};
+use font_api::{EmbeddedLicense, UploadedFont};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+use crate::{
+    audit, isolation, licensing, multipart,
+    name::NameTable,
+    quarantine,
+    sfnt::{self, Flavor, Font},
+    spool,
+    storage::{self, FontStorage},
+    woff, AppState,
+};
+
+const FAMILY_NAME_ID: u16 = 1;
+
+pub fn upload_dir() -> PathBuf {
+    std::env::var("UPLOAD_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| spool::spool_dir().join("uploads"))
+}
+
+fn tenant(headers: &HeaderMap) -> String {
+    headers.get("x-font-tenant").and_then(|v| v.to_str().ok()).unwrap_or_default().to_string()
+}
+
+pub struct Uploads {
+    fonts: RwLock<BTreeMap<String, UploadedFont>>,
+    storage: Arc<dyn FontStorage>,
+}
+
+fn record_key(id: &str) -> String {
+    format!("{id}.json")
+}
+
+impl Uploads {
+    pub fn storage(&self) -> &dyn FontStorage {
+        &*self.storage
+    }
+
+    /// Reloads the `<id>.json` records stored next to each upload.
+    pub async fn load(storage: Arc<dyn FontStorage>) -> Self {
+        let mut fonts = BTreeMap::new();
+        let keys = storage.list("").await.unwrap_or_else(|e| {
+            warn!("{}: cannot list uploads: {e}", storage.location());
+            Vec::new()
+        });
+        for key in keys.iter().filter(|k| k.ends_with(".json")) {
+            match Self::fetch(&*storage, key).await {
+                Ok(Some(font)) => {
+                    fonts.insert(font.id.clone(), font);
+                }
+                Ok(None) => warn!("{key}: upload binary is missing"),
+                Err(e) => warn!("{key}: unreadable upload record: {e}"),
+            }
+        }
+        Self { fonts: RwLock::new(fonts), storage }
+    }
+
+    /// The record under `key`, if its binary is stored too.
+    async fn fetch(storage: &dyn FontStorage, key: &str) -> Result<Option<UploadedFont>, String> {
+        let Some(raw) = storage.get(key).await? else {
+            return Ok(None);
+        };
+        let font: UploadedFont = serde_json::from_slice(&raw).map_err(|e| e.to_string())?;
+        Ok(storage.exists(&font.id).await?.then_some(font))
+    }
+
+    /// The caller's upload `id`.
+    pub async fn get(&self, headers: &HeaderMap, id: &str) -> Result<UploadedFont, (StatusCode, String)> {
+        let cached = self.fonts.read().unwrap().get(id).cloned();
+        let font = match cached {
+            Some(font) => Some(font),
+            // Possibly uploaded through another replica.
+            None if !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') => {
+                let fetched = Self::fetch(&*self.storage, &record_key(id)).await.map_err(storage::error("reading upload record"))?;
+                if let Some(font) = &fetched {
+                    self.fonts.write().unwrap().insert(font.id.clone(), font.clone());
+                }
+                fetched
+            }
+            None => None,
+        };
+        font.filter(|f| f.tenant == tenant(headers))
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("no uploaded font '{id}'")))
+    }
+
+    /// The binary of an upload, checked again on read.
+    pub async fn read(&self, font: &UploadedFont) -> Result<Vec<u8>, (StatusCode, String)> {
+        let stored = self.storage.get(&font.id).await.map_err(storage::error(&format!("reading upload {}", font.id)))?;
+        let Some(data) = stored else {
+            // Deleted through another replica.
+            self.fonts.write().unwrap().remove(&font.id);
+            return Err((StatusCode::NOT_FOUND, format!("no uploaded font '{}'", font.id)));
+        };
+        sfnt::check_structure(&data, data.len() as u64)
+            .map_err(|p| (StatusCode::UNPROCESSABLE_ENTITY, format!("stored upload is damaged: {}", p.join("; "))))?;
+        Ok(data)
+    }
+
+    /// Stores checked font bytes, described by `details`, as an upload of
+    /// the caller's tenant, from the file `spooled` when they are on disk
+    /// already.
+    /// Content-addressed per tenant by SHA-256: storing the same file again,
+    /// under any filename, stores nothing and returns the existing record
+    /// with `200 OK` and `deduplicated` set.
+    pub async fn store(
+        &self,
+        headers: &HeaderMap,
+        data: &[u8],
+        spooled: Option<&std::path::Path>,
+        flavor: Flavor,
+        filename: Option<String>,
+        details: Details,
+    ) -> Result<(StatusCode, UploadedFont), (StatusCode, String)> {
+        let tenant = tenant(headers);
+        let sha256 = format!("{:x}", Sha256::digest(data));
+        let key = format!("{:x}", Sha256::digest(format!("{tenant}\0{sha256}")));
+        let id = format!("{}-{}", &key[..16], flavor_ext(flavor));
+        if let Ok(existing) = self.get(headers, &id).await {
+            info!(id = %existing.id, filename = ?filename, "upload deduplicated");
+            return Ok((StatusCode::OK, UploadedFont { deduplicated: true, ..existing }));
+        }
+        let font = UploadedFont {
+            id,
+            tenant,
+            filename,
+            flavor,
+            size_bytes: data.len() as u64,
+            sha256,
+            family: details.family,
+            faces: sfnt::collection_len(data),
+            uploaded_at_unix: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            deduplicated: false,
+            license: details.license,
+        };
+        let stored = match spooled {
+            Some(path) => self.storage.put_file(&font.id, path).await,
+            None => self.storage.put(&font.id, data.to_vec()).await,
+        };
+        stored.map_err(storage::error("storing upload"))?;
+        let record = serde_json::to_vec(&font).expect("upload record serializes");
+        self.storage.put(&record_key(&font.id), record).await.map_err(storage::error("storing upload record"))?;
+        self.fonts.write().unwrap().insert(font.id.clone(), font.clone());
+        info!(id = %font.id, flavor = ?font.flavor, size = font.size_bytes, "font uploaded");
+        Ok((StatusCode::CREATED, font))
+    }
+
+    /// The upload a request names with `font_id`, and the font name to use:
+    /// `font_name` if given, else the upload's family.
+    pub async fn resolve(
+        &self,
+        headers: &HeaderMap,
+        font_id: Option<&str>,
+        font_name: &str,
+    ) -> Result<(String, Option<UploadedFont>), (StatusCode, String)> {
+        let upload = match font_id {
+            Some(id) => Some(self.get(headers, id).await?),
+            None => None,
+        };
+        let name = match (&upload, font_name.trim()) {
+            (_, name) if !name.is_empty() => font_name.to_string(),
+            (Some(u), _) => u.display_name(),
+            (None, _) => return Err((StatusCode::BAD_REQUEST, "font_name or font_id is required".to_string())),
+        };
+        Ok((name, upload))
+    }
+}
+
+/// What an upload's first face says about itself; nothing for WOFF2.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Details {
+    pub family: Option<String>,
+    pub license: Option<EmbeddedLicense>,
+}
+
+impl Details {
+    pub fn read(data: &[u8]) -> Self {
+        let face = first_face(data);
+        Self { family: face.as_ref().and_then(family_name), license: face.as_ref().map(licensing::read) }
+    }
+}
+
+/// The font, a collection's first face, or a WOFF's unwrapped font.
+fn first_face(data: &[u8]) -> Option<Font> {
+    match sfnt::sniff(data)? {
+        Flavor::Ttc => Font::parse_face(data, 0).ok(),
+        Flavor::Woff => woff::decode_woff(data).ok(),
+        _ => Font::parse(data).ok(),
+    }
+}
+
+fn family_name(font: &Font) -> Option<String> {
+    NameTable::parse(font.table(b"name")?).ok()?.get(FAMILY_NAME_ID)
+}
+
+pub async fn upload(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<(StatusCode, Json<UploadedFont>), (StatusCode, String)> {
+    let boundary = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(multipart::boundary)
+        .ok_or((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "send the font as multipart/form-data in a 'font' field".to_string(),
+        ))?;
+    let raw = spool::spool(body, spool::max_upload_bytes()).await?;
+    let (filename, file) = multipart::extract(raw.path(), &boundary, "font")
+        .await?
+        .ok_or((StatusCode::BAD_REQUEST, "multipart body has no 'font' field".to_string()))?;
+    drop(raw);
+    if file.size == 0 {
+        return Err((StatusCode::BAD_REQUEST, "'font' field is empty".to_string()));
+    }
+
+    let mut report = quarantine::inspect(&state, file.path(), file.size).await?;
+    let data = tokio::fs::read(file.path())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("reading spooled upload: {e}")))?;
+    let (data, details) = if report.problems.is_empty() {
+        let (data, parsed) = isolation::parse(&state, data).await?;
+        report.problems = parsed.problems;
+        (data, parsed.details)
+    } else {
+        (data, Details::default())
+    };
+    if !report.passed() {
+        let reason = match report.problems.first() {
+            Some(problem) => problem.clone(),
+            None => "virus scan failed".to_string(),
+        };
+        let id = state.quarantine.admit(file, report).await?;
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, format!("upload rejected: {reason}; quarantined as {id}")));
+    }
+    let flavor = report.flavor.ok_or((StatusCode::UNPROCESSABLE_ENTITY, "unrecognised font".to_string()))?;
+    let (status, font) =
+        state.uploads.store(&headers, &data, Some(file.path()), flavor, filename, details).await?;
+    if status == StatusCode::CREATED {
+        audit::record(&state, &headers, "upload.create", &font.id, Value::Null, json!(font)).await;
+    }
+    Ok((status, Json(font)))
+}
+
+fn flavor_ext(flavor: Flavor) -> &'static str {
+    match flavor {
+        Flavor::Ttf => "ttf",
+        Flavor::Ttc => "ttc",
+        Flavor::Otf => "otf",
+        Flavor::Woff => "woff",
+        Flavor::Woff2 => "woff2",
+    }
+}
+
+pub async fn show(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<UploadedFont>, (StatusCode, String)> {
+    state.uploads.get(&headers, &id).await.map(Json)
+}
+
+pub async fn delete(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let font = state.uploads.get(&headers, &id).await?;
+    for key in [font.id.clone(), record_key(&id)] {
+        if let Err(e) = state.uploads.storage.delete(&key).await {
+            warn!("{key}: {e}");
+        }
+    }
+    state.uploads.fonts.write().unwrap().remove(&id);
+    audit::record(&state, &headers, "upload.delete", &id, json!(font), Value::Null).await;
+    info!(id = %id, "uploaded font deleted");
+    Ok(StatusCode::NO_CONTENT)
+}
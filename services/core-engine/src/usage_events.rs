@@ -0,0 +1,487 @@
+//! Usage events for the data platform.
+//!
+//! Besides the per-day counts of [`crate::analytics`], each download from
+//! `/cdn/` and each compress or subset request can be published as a JSON
+//! event: tenant, font, format, bytes, user agent class and cache status.
+//! `USAGE_EVENTS` names where to:
+//!
+//! - `nats://host:4222/<subject>` publishes each event on a NATS subject
+//!   (core NATS: fire and forget, no JetStream acknowledgements);
+//! - `kafka+http://proxy:8082/<topic>` (or `kafka+https://`) produces them
+//!   to a Kafka topic through a Kafka REST Proxy (v2 API), up to
+//!   [`BATCH`] per request.
+//!
+//! Unset means no events. Events wait in memory for a background sender; up
+//! to `USAGE_EVENTS_BUFFER` (default 10000) of them, past which new ones
+//! are dropped rather than holding up responses, as are batches the sink
+//! refuses or does not take within ten seconds. Both show in
+//! `usage_events_total{result}`.
+
+use axum::http::{header, HeaderMap};
+use serde::Serialize;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::mpsc,
+};
+use tracing::{info, warn};
+
+use crate::AppState;
+
+/// Events sent to the sink at once.
+pub const BATCH: usize = 100;
+/// Longest connecting to the sink or publishing one batch may take.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sink {
+    Nats { addr: String, subject: String },
+    /// `url` is the REST Proxy's `/topics/<topic>`.
+    Kafka { url: String },
+}
+
+impl Sink {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let split = |rest: &str| {
+            let (base, name) = rest.trim_end_matches('/').rsplit_once('/')?;
+            (!base.is_empty() && !name.is_empty()).then(|| (base.to_string(), name.to_string()))
+        };
+        if let Some(rest) = spec.strip_prefix("nats://") {
+            let (addr, subject) = split(rest)
+                .ok_or_else(|| format!("USAGE_EVENTS={spec:?} needs a subject: nats://host:port/<subject>"))?;
+            if addr.contains('/') || subject.contains(char::is_whitespace) {
+                return Err(format!("USAGE_EVENTS={spec:?} must be nats://host:port/<subject>"));
+            }
+            return Ok(Self::Nats { addr, subject });
+        }
+        for scheme in ["http", "https"] {
+            if let Some(rest) = spec.strip_prefix(&format!("kafka+{scheme}://")) {
+                let (base, topic) = split(rest)
+                    .ok_or_else(|| format!("USAGE_EVENTS={spec:?} needs a topic: kafka+{scheme}://host:port/<topic>"))?;
+                return Ok(Self::Kafka { url: format!("{scheme}://{base}/topics/{topic}") });
+            }
+        }
+        Err(format!("USAGE_EVENTS={spec:?} must be nats://host:port/<subject> or kafka+http(s)://host:port/<topic>"))
+    }
+}
+
+/// One use of a font, as the handler saw it.
+pub struct Usage<'a> {
+    /// `download`, `compress`, `subset` or `patch-subset`.
+    pub kind: &'static str,
+    pub tenant: &'a str,
+    /// The font's URL slug.
+    pub font: &'a str,
+    pub format: &'a str,
+    /// Bytes sent, or generated.
+    pub bytes: u64,
+    /// `hit` for output served from storage or a cache, `miss` when it was
+    /// generated for the request, `revalidated` for a `304`.
+    pub cache: &'static str,
+}
+
+#[derive(Serialize)]
+struct Event<'a> {
+    kind: &'static str,
+    timestamp_ms: u64,
+    tenant: &'a str,
+    font: &'a str,
+    format: &'a str,
+    bytes: u64,
+    user_agent: &'static str,
+    cache: &'static str,
+}
+
+/// `browser`, `mobile`, `bot`, `tool` or `unknown`.
+fn agent_class(headers: &HeaderMap) -> &'static str {
+    let Some(agent) = headers.get(header::USER_AGENT).and_then(|v| v.to_str().ok()) else {
+        return "unknown";
+    };
+    let agent = agent.to_ascii_lowercase();
+    if ["bot", "crawler", "spider", "lighthouse", "headless"].iter().any(|w| agent.contains(w)) {
+        "bot"
+    } else if !agent.starts_with("mozilla/") {
+        "tool"
+    } else if ["mobile", "android", "iphone", "ipad"].iter().any(|w| agent.contains(w)) {
+        "mobile"
+    } else {
+        "browser"
+    }
+}
+
+fn count(result: &'static str, events: usize) {
+    metrics::counter!("usage_events_total", "result" => result).increment(events as u64);
+}
+
+pub struct UsageEvents {
+    sink: Option<Sink>,
+    sender: mpsc::Sender<Vec<u8>>,
+    /// Taken by [`run`].
+    receiver: Mutex<Option<mpsc::Receiver<Vec<u8>>>>,
+}
+
+impl UsageEvents {
+    pub fn from_env() -> Self {
+        let sink = std::env::var("USAGE_EVENTS").ok().filter(|v| !v.is_empty()).and_then(|spec| {
+            Sink::parse(&spec).map_err(|e| warn!("{e}; usage events disabled")).ok()
+        });
+        let buffer = std::env::var("USAGE_EVENTS_BUFFER").ok().and_then(|v| v.parse().ok()).unwrap_or(10_000usize);
+        Self::new(sink, buffer)
+    }
+
+    fn new(sink: Option<Sink>, buffer: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(buffer.max(1));
+        Self { sink, sender, receiver: Mutex::new(Some(receiver)) }
+    }
+
+    pub fn record(&self, headers: &HeaderMap, usage: Usage<'_>) {
+        if self.sink.is_none() {
+            return;
+        }
+        let event = Event {
+            kind: usage.kind,
+            timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64),
+            tenant: usage.tenant,
+            font: usage.font,
+            format: usage.format,
+            bytes: usage.bytes,
+            user_agent: agent_class(headers),
+            cache: usage.cache,
+        };
+        let Ok(json) = serde_json::to_vec(&event) else { return };
+        if self.sender.try_send(json).is_err() {
+            count("dropped", 1);
+        }
+    }
+}
+
+/// A connection to the NATS server, answering its pings while idle.
+struct Nats {
+    addr: String,
+    subject: String,
+    timeout: Duration,
+    connection: Option<BufReader<TcpStream>>,
+    /// What the server sent of its current line, kept when a publish
+    /// interrupts reading it.
+    line: Vec<u8>,
+}
+
+impl Nats {
+    async fn connect(&mut self) -> std::io::Result<&mut BufReader<TcpStream>> {
+        if self.connection.is_none() {
+            let mut stream = BufReader::new(TcpStream::connect(&self.addr).await?);
+            // The server introduces itself with INFO before anything else.
+            let mut info = String::new();
+            stream.read_line(&mut info).await?;
+            if !info.starts_with("INFO ") {
+                return Err(std::io::Error::other(format!("not a NATS server: {}", info.trim())));
+            }
+            let connect = serde_json::json!({
+                "verbose": false,
+                "pedantic": false,
+                "name": "font-engine",
+                "lang": "rust",
+                "version": env!("CARGO_PKG_VERSION"),
+            });
+            stream.get_mut().write_all(format!("CONNECT {connect}\r\n").as_bytes()).await?;
+            info!(addr = %self.addr, "connected to NATS");
+            self.connection = Some(stream);
+            self.line.clear();
+        }
+        Ok(self.connection.as_mut().expect("connected above"))
+    }
+
+    /// Connects if need be and writes the batch, within the timeout: a
+    /// server that accepts connections but never answers or reads would
+    /// otherwise hold up every later batch.
+    async fn publish(&mut self, batch: &[Vec<u8>]) -> std::io::Result<()> {
+        let (message, timeout) = (nats_message(&self.subject, batch), self.timeout);
+        let write = async { self.connect().await?.get_mut().write_all(&message).await };
+        let written = tokio::time::timeout(timeout, write).await.unwrap_or_else(|_| {
+            let message = format!("{} did not answer in {timeout:?}", self.addr);
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, message))
+        });
+        if written.is_err() {
+            self.connection = None;
+        }
+        written
+    }
+
+    /// Handles what the server sends between publishes; pending while
+    /// disconnected.
+    async fn serve(&mut self) {
+        let Some(stream) = &mut self.connection else {
+            return std::future::pending().await;
+        };
+        let closed = match stream.read_until(b'\n', &mut self.line).await {
+            Ok(0) => Some("connection closed".to_string()),
+            Ok(_) if self.line.starts_with(b"PING") => {
+                stream.get_mut().write_all(b"PONG\r\n").await.err().map(|e| e.to_string())
+            }
+            Ok(_) if self.line.starts_with(b"-ERR") => Some(String::from_utf8_lossy(&self.line).trim().to_string()),
+            Ok(_) => None,
+            Err(e) => Some(e.to_string()),
+        };
+        self.line.clear();
+        if let Some(reason) = closed {
+            warn!(addr = %self.addr, "NATS: {reason}; reconnecting on the next event");
+            self.connection = None;
+        }
+    }
+}
+
+/// One core NATS `PUB` per event.
+fn nats_message(subject: &str, batch: &[Vec<u8>]) -> Vec<u8> {
+    let mut message = Vec::new();
+    for event in batch {
+        message.extend_from_slice(format!("PUB {subject} {}\r\n", event.len()).as_bytes());
+        message.extend_from_slice(event);
+        message.extend_from_slice(b"\r\n");
+    }
+    message
+}
+
+/// A REST Proxy v2 produce request, one JSON record per event.
+fn kafka_records(batch: &[Vec<u8>]) -> String {
+    let records: Vec<String> = batch.iter().map(|e| format!("{{\"value\":{}}}", String::from_utf8_lossy(e))).collect();
+    format!("{{\"records\":[{}]}}", records.join(","))
+}
+
+enum Publisher {
+    Nats(Nats),
+    Kafka { client: reqwest::Client, url: String },
+}
+
+impl Publisher {
+    fn new(sink: Sink, timeout: Duration) -> Self {
+        match sink {
+            Sink::Nats { addr, subject } => {
+                Self::Nats(Nats { addr, subject, timeout, connection: None, line: Vec::new() })
+            }
+            Sink::Kafka { url } => {
+                let client = reqwest::Client::builder().timeout(timeout).build().unwrap_or_default();
+                Self::Kafka { client, url }
+            }
+        }
+    }
+
+    async fn publish(&mut self, batch: &[Vec<u8>]) -> Result<(), String> {
+        match self {
+            Self::Nats(nats) => nats.publish(batch).await.map_err(|e| e.to_string()),
+            Self::Kafka { client, url } => {
+                let sent = client
+                    .post(url.as_str())
+                    .header(header::CONTENT_TYPE, "application/vnd.kafka.json.v2+json")
+                    .body(kafka_records(batch))
+                    .send()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                match sent.status() {
+                    status if status.is_success() => Ok(()),
+                    status => Err(format!("REST Proxy answered {status}")),
+                }
+            }
+        }
+    }
+
+    async fn serve(&mut self) {
+        match self {
+            Self::Nats(nats) => nats.serve().await,
+            Self::Kafka { .. } => std::future::pending().await,
+        }
+    }
+}
+
+/// Sends recorded events to `USAGE_EVENTS` as they come, in batches of up
+/// to [`BATCH`].
+pub async fn run(state: Arc<AppState>) {
+    let events = &state.usage_events;
+    let (Some(sink), Some(receiver)) = (events.sink.clone(), events.receiver.lock().unwrap().take()) else {
+        return;
+    };
+    forward(Publisher::new(sink, TIMEOUT), receiver).await;
+}
+
+async fn forward(mut publisher: Publisher, mut receiver: mpsc::Receiver<Vec<u8>>) {
+    loop {
+        let first = tokio::select! {
+            event = receiver.recv() => event,
+            _ = publisher.serve() => continue,
+        };
+        let Some(first) = first else { return };
+        let mut batch = vec![first];
+        while batch.len() < BATCH {
+            match receiver.try_recv() {
+                Ok(event) => batch.push(event),
+                Err(_) => break,
+            }
+        }
+        match publisher.publish(&batch).await {
+            Ok(()) => count("published", batch.len()),
+            Err(e) => {
+                warn!(events = batch.len(), "publishing usage events failed: {e}");
+                count("failed", batch.len());
+                // Later events wait in the buffer meanwhile.
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    fn batch() -> Vec<Vec<u8>> {
+        vec![br#"{"kind":"download","bytes":1024}"#.to_vec(), b"{}".to_vec()]
+    }
+
+    async fn listener() -> (TcpListener, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        (listener, addr)
+    }
+
+    /// Forwards `batch()` to `publisher`, then closes the buffer and waits
+    /// for the sender to finish.
+    async fn forward_batch(publisher: Publisher) {
+        let (sender, receiver) = mpsc::channel(16);
+        for event in batch() {
+            sender.try_send(event).unwrap();
+        }
+        let forwarder = tokio::spawn(forward(publisher, receiver));
+        drop(sender);
+        tokio::time::timeout(Duration::from_secs(5), forwarder).await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn parses_sinks() {
+        let nats = Sink::Nats { addr: "nats:4222".to_string(), subject: "usage.fonts".to_string() };
+        assert_eq!(Sink::parse("nats://nats:4222/usage.fonts/"), Ok(nats));
+        let kafka = Sink::Kafka { url: "https://proxy:8082/topics/usage".to_string() };
+        assert_eq!(Sink::parse("kafka+https://proxy:8082/usage"), Ok(kafka));
+        for spec in ["nats://nats:4222", "nats://nats:4222/a b", "kafka+http://proxy", "kafka://broker:9092/usage"] {
+            assert!(Sink::parse(spec).is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn writes_one_nats_pub_per_event() {
+        let expected = "PUB usage.fonts 32\r\n{\"kind\":\"download\",\"bytes\":1024}\r\nPUB usage.fonts 2\r\n{}\r\n";
+        assert_eq!(String::from_utf8(nats_message("usage.fonts", &batch())).unwrap(), expected);
+    }
+
+    #[test]
+    fn wraps_each_event_in_a_kafka_rest_record() {
+        let records = kafka_records(&batch());
+        assert_eq!(records, r#"{"records":[{"value":{"kind":"download","bytes":1024}},{"value":{}}]}"#);
+        assert!(serde_json::from_str::<serde_json::Value>(&records).is_ok());
+    }
+
+    #[tokio::test]
+    async fn publishes_to_a_nats_server_and_answers_its_pings() {
+        let (listener, addr) = listener().await;
+        let server = tokio::spawn(async move {
+            let mut stream = BufReader::new(listener.accept().await.unwrap().0);
+            stream.get_mut().write_all(b"INFO {\"server_id\":\"test\"}\r\n").await.unwrap();
+            let mut connect = String::new();
+            stream.read_line(&mut connect).await.unwrap();
+            let mut published = vec![0; nats_message("usage.fonts", &batch()).len()];
+            stream.read_exact(&mut published).await.unwrap();
+            stream.get_mut().write_all(b"PING\r\n").await.unwrap();
+            let mut pong = String::new();
+            stream.read_line(&mut pong).await.unwrap();
+            (connect, published, pong)
+        });
+        let (sender, receiver) = mpsc::channel(16);
+        let subject = "usage.fonts".to_string();
+        let forwarder = tokio::spawn(forward(Publisher::new(Sink::Nats { addr, subject }, TIMEOUT), receiver));
+        for event in batch() {
+            sender.send(event).await.unwrap();
+        }
+
+        let (connect, published, pong) = tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap();
+        assert!(connect.starts_with("CONNECT {\"") && connect.ends_with("}\r\n"), "{connect}");
+        assert_eq!(published, nats_message("usage.fonts", &batch()));
+        assert_eq!(pong, "PONG\r\n");
+        drop(sender);
+        tokio::time::timeout(Duration::from_secs(5), forwarder).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn produces_to_a_kafka_rest_proxy() {
+        let (listener, addr) = listener().await;
+        let server = tokio::spawn(async move {
+            let mut stream = listener.accept().await.unwrap().0;
+            let mut request = Vec::new();
+            let complete = |request: &[u8]| {
+                let text = String::from_utf8_lossy(request);
+                let Some((head, body)) = text.split_once("\r\n\r\n") else { return false };
+                let length = head.lines().find_map(|l| l.strip_prefix("content-length: ")?.parse::<usize>().ok());
+                length.is_some_and(|length| body.len() >= length)
+            };
+            while !complete(&request) {
+                let mut chunk = [0; 4096];
+                let read = stream.read(&mut chunk).await.unwrap();
+                assert_ne!(read, 0, "the request ended early");
+                request.extend_from_slice(&chunk[..read]);
+            }
+            stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{}").await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let sink = Sink::parse(&format!("kafka+http://{addr}/usage")).unwrap();
+        forward_batch(Publisher::new(sink, TIMEOUT)).await;
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /topics/usage HTTP/1.1\r\n"), "{request}");
+        assert!(request.contains("content-type: application/vnd.kafka.json.v2+json\r\n"), "{request}");
+        assert!(request.ends_with(&format!("\r\n\r\n{}", kafka_records(&batch()))), "{request}");
+    }
+
+    #[tokio::test]
+    async fn an_unreachable_broker_never_holds_up_recording() {
+        let (listener, addr) = listener().await;
+        drop(listener);
+        let events = UsageEvents::new(Some(Sink::Nats { addr, subject: "usage".to_string() }), 2);
+        let usage = || Usage { kind: "download", tenant: "", font: "inter", format: "woff2", bytes: 1, cache: "hit" };
+        // Nothing is sending yet: what does not fit the buffer is dropped.
+        for _ in 0..5 {
+            events.record(&HeaderMap::new(), usage());
+        }
+        assert_eq!(events.sender.capacity(), 0);
+
+        let receiver = events.receiver.lock().unwrap().take().unwrap();
+        let publisher = Publisher::new(events.sink.clone().unwrap(), TIMEOUT);
+        let forwarder = tokio::spawn(forward(publisher, receiver));
+        // Every publish fails, but the buffer still drains and takes new events.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while events.sender.capacity() < 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        events.record(&HeaderMap::new(), usage());
+        assert_eq!(events.sender.capacity(), 1);
+        assert!(!forwarder.is_finished());
+        forwarder.abort();
+    }
+
+    #[tokio::test]
+    async fn a_broker_that_never_answers_times_out() {
+        // Connections are accepted by the kernel but nothing is ever sent.
+        let (_listener, addr) = listener().await;
+        let sink = Sink::Nats { addr, subject: "usage".to_string() };
+        let mut publisher = Publisher::new(sink, Duration::from_millis(200));
+        let started = Instant::now();
+        let error = publisher.publish(&batch()).await.unwrap_err();
+        assert!(error.contains("did not answer in 200ms"), "{error}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,412 @@
+//! Deep font validation in the spirit of OTS.
+//!
+//! `POST /api/v1/font/validate` takes a raw font body and lists errors (the
+//! font is unsafe or unusable) and warnings (spec violations renderers
+//! tolerate). Beyond the container check every intake runs (see
+//! [`sfnt::check_structure`]) it verifies the table directory and checksums,
+//! the required tables, `head`, `hhea`/`hmtx`, each `loca`/`glyf` record,
+//! `cmap` subtables and `name` records. Uploads and catalog registrations
+//! with errors are rejected. WOFF is unpacked first; WOFF2 and collections
+//! only get the container check.
+
+use axum::{body::Body, extract::State, http::StatusCode, response::Json};
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::{
+    cancel,
+    glyf::Glyf,
+    sfnt::{self, be_u16, be_u32, Flavor, Font},
+    spool, woff, AppState,
+};
+
+/// Problems reported per check before the rest are summarised.
+const MAX_REPORTED: usize = 20;
+
+/// Longest accepted name record, except for the free-text IDs below.
+const MAX_NAME_BYTES: usize = 1024;
+
+/// Copyright, description, license and sample text may be long.
+const FREE_TEXT_NAME_IDS: &[u16] = &[0, 10, 13, 19];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub table: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Validation {
+    pub flavor: Option<Flavor>,
+    /// No errors; warnings are allowed.
+    pub valid: bool,
+    pub errors: Vec<Issue>,
+    pub warnings: Vec<Issue>,
+}
+
+impl Validation {
+    /// Errors as single-line messages, for rejections and quarantine reports.
+    pub fn problems(&self) -> Vec<String> {
+        self.errors
+            .iter()
+            .map(|i| match &i.table {
+                Some(table) => format!("{table}: {}", i.message),
+                None => i.message.clone(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct Collector {
+    errors: Vec<Issue>,
+    warnings: Vec<Issue>,
+}
+
+impl Collector {
+    fn error(&mut self, table: &str, message: impl Into<String>) {
+        let table = Some(table.to_string()).filter(|t| !t.is_empty());
+        self.errors.push(Issue { table, message: message.into() });
+    }
+
+    fn warn(&mut self, table: &str, message: impl Into<String>) {
+        let table = Some(table.to_string()).filter(|t| !t.is_empty());
+        self.warnings.push(Issue { table, message: message.into() });
+    }
+}
+
+pub fn validate(data: &[u8]) -> Validation {
+    let mut c = Collector::default();
+    let flavor = sfnt::sniff(data);
+    match sfnt::check_structure(data, data.len() as u64) {
+        Err(problems) => problems.into_iter().for_each(|p| c.error("", p)),
+        Ok(Flavor::Ttf | Flavor::Otf) => {
+            check_directory(data, &mut c);
+            match Font::parse(data) {
+                Ok(font) => check_tables(&font, &mut c),
+                Err(e) => c.error("", e),
+            }
+        }
+        Ok(Flavor::Woff) => {
+            match woff::decode_woff(data) {
+                Ok(font) => check_tables(&font, &mut c),
+                Err(e) => c.error("", e),
+            }
+            if let Err(e) = woff::woff_metadata(data) {
+                c.error("", e);
+            }
+        }
+        Ok(Flavor::Woff2) => c.warn("", "WOFF2 tables are not unpacked; only the container was checked"),
+        Ok(Flavor::Ttc) => c.warn("", "collections are not unpacked; only the container was checked"),
+    }
+    Validation { flavor, valid: c.errors.is_empty(), errors: c.errors, warnings: c.warnings }
+}
+
+fn check_directory(data: &[u8], c: &mut Collector) {
+    let n = be_u16(data, 4).unwrap_or_default();
+    let entry_selector = 15 - n.max(1).leading_zeros() as u16;
+    let search_range = (1u16 << entry_selector).wrapping_mul(16);
+    let expected = [search_range, entry_selector, n.wrapping_mul(16).wrapping_sub(search_range)];
+    if (0..3).map(|i| be_u16(data, 6 + 2 * i)).ne(expected.map(Some)) {
+        c.warn("", "searchRange/entrySelector/rangeShift do not match numTables");
+    }
+
+    let mut records: Vec<([u8; 4], usize, usize, u32)> = (0..n as usize)
+        .filter_map(|i| {
+            let rec = 12 + 16 * i;
+            let tag: [u8; 4] = data.get(rec..rec + 4)?.try_into().ok()?;
+            Some((tag, be_u32(data, rec + 8)? as usize, be_u32(data, rec + 12)? as usize, be_u32(data, rec + 4)?))
+        })
+        .collect();
+    if has_duplicates(&records) {
+        c.error("", "table directory lists a table twice");
+    } else if records.windows(2).any(|w| w[0].0 > w[1].0) {
+        c.warn("", "table directory is not sorted by tag");
+    }
+
+    let mut head_adjustment = None;
+    for &(tag, offset, length, recorded) in &records {
+        let name = String::from_utf8_lossy(&tag).into_owned();
+        if !offset.is_multiple_of(4) {
+            c.warn(&name, format!("offset {offset} is not 4-byte aligned"));
+        }
+        let Some(body) = data.get(offset..offset + length) else { continue };
+        let mut body = body.to_vec();
+        if &tag == b"head" && body.len() >= 12 {
+            head_adjustment = be_u32(&body, 8);
+            body[8..12].fill(0);
+        }
+        if sfnt::checksum(&body) != recorded {
+            c.warn(&name, "checksum does not match the table directory");
+        }
+    }
+    records.sort_by_key(|r| r.1);
+    for w in records.windows(2) {
+        if w[0].1 + w[0].2 > w[1].1 {
+            let (a, b) = (String::from_utf8_lossy(&w[0].0), String::from_utf8_lossy(&w[1].0));
+            c.error("", format!("tables '{a}' and '{b}' overlap"));
+        }
+    }
+    if let Some(adjustment) = head_adjustment {
+        let mut whole = data.to_vec();
+        if let Some(&(_, offset, _, _)) = records.iter().find(|r| &r.0 == b"head") {
+            whole[offset + 8..offset + 12].fill(0);
+        }
+        if 0xB1B0_AFBA_u32.wrapping_sub(sfnt::checksum(&whole)) != adjustment {
+            c.warn("head", "checkSumAdjustment does not match the file");
+        }
+    }
+}
+
+fn has_duplicates(records: &[([u8; 4], usize, usize, u32)]) -> bool {
+    let mut tags: Vec<[u8; 4]> = records.iter().map(|r| r.0).collect();
+    tags.sort();
+    tags.windows(2).any(|w| w[0] == w[1])
+}
+
+fn check_tables(font: &Font, c: &mut Collector) {
+    for tag in [b"head", b"maxp", b"cmap", b"hhea", b"hmtx"] {
+        if font.table(tag).is_none() {
+            c.error("", format!("required table '{}' is missing", String::from_utf8_lossy(tag)));
+        }
+    }
+    for tag in [b"name", b"post", b"OS/2"] {
+        if font.table(tag).is_none() {
+            c.warn("", format!("table '{}' is missing", String::from_utf8_lossy(tag)));
+        }
+    }
+    if ![b"glyf", b"CFF ", b"CFF2", b"CBDT", b"sbix", b"SVG "].iter().any(|t| font.table(t).is_some()) {
+        c.error("", "font has no outline or bitmap glyph tables");
+    }
+
+    if let Some(head) = font.table(b"head") {
+        if head.len() < 54 {
+            c.error("head", "table is truncated");
+        } else {
+            if be_u32(head, 12) != Some(0x5F0F_3CF5) {
+                c.error("head", "bad magic number");
+            }
+            if !be_u16(head, 18).is_some_and(|upm| (16..=16384).contains(&upm)) {
+                c.error("head", "unitsPerEm must be 16-16384");
+            }
+            if !matches!(be_u16(head, 50), Some(0 | 1)) {
+                c.error("head", "indexToLocFormat must be 0 or 1");
+            }
+        }
+    }
+    let num_glyphs = font.table(b"maxp").and_then(|m| be_u16(m, 4)).unwrap_or_default() as usize;
+    if font.table(b"maxp").is_some() && num_glyphs == 0 {
+        c.error("maxp", "numGlyphs is zero or the table is truncated");
+    }
+
+    if let (Some(hhea), Some(hmtx)) = (font.table(b"hhea"), font.table(b"hmtx")) {
+        match be_u16(hhea, 34).map(usize::from) {
+            Some(metrics) if metrics == 0 || metrics > num_glyphs.max(1) => {
+                c.error("hhea", format!("numberOfHMetrics {metrics} is not within 1-{num_glyphs}"));
+            }
+            Some(metrics) if hmtx.len() < 4 * metrics + 2 * num_glyphs.saturating_sub(metrics) => {
+                c.error("hmtx", format!("table is too short for {num_glyphs} glyphs"));
+            }
+            Some(_) => {}
+            None => c.error("hhea", "table is truncated"),
+        }
+    }
+
+    match Glyf::parse(font) {
+        Some(Ok(glyf)) => {
+            let broken: Vec<String> = (0..glyf.num_glyphs() as u16)
+                .filter_map(|g| glyf.check(g).err().map(|e| format!("glyph {g}: {e}")))
+                .collect();
+            report(c, "glyf", broken, "glyphs");
+        }
+        Some(Err(e)) => c.error("loca", e),
+        None => {}
+    }
+    if let Some(cmap) = font.table(b"cmap") {
+        check_cmap(cmap, num_glyphs, c);
+    }
+    if let Some(name) = font.table(b"name") {
+        check_name(name, c);
+    }
+}
+
+/// Records the first [`MAX_REPORTED`] errors and a count of the rest.
+fn report(c: &mut Collector, table: &str, mut errors: Vec<String>, what: &str) {
+    let more = errors.len().saturating_sub(MAX_REPORTED);
+    errors.truncate(MAX_REPORTED);
+    for e in errors {
+        c.error(table, e);
+    }
+    if more > 0 {
+        c.error(table, format!("... and {more} more {what}"));
+    }
+}
+
+fn check_cmap(cmap: &[u8], num_glyphs: usize, c: &mut Collector) {
+    let Some(count) = be_u16(cmap, 2) else {
+        c.error("cmap", "table is truncated");
+        return;
+    };
+    let mut unicode = false;
+    let mut missing_glyphs = 0;
+    for i in 0..count as usize {
+        let (Some(platform), Some(encoding), Some(offset)) =
+            (be_u16(cmap, 4 + 8 * i), be_u16(cmap, 6 + 8 * i), be_u32(cmap, 8 + 8 * i))
+        else {
+            c.error("cmap", format!("encoding record {i} is truncated"));
+            return;
+        };
+        unicode |= platform == 0 || (platform == 3 && matches!(encoding, 1 | 10));
+        let Some(sub) = cmap.get(offset as usize..) else {
+            c.error("cmap", format!("subtable {platform}/{encoding} starts past the end of the table"));
+            continue;
+        };
+        let checked = match be_u16(sub, 0) {
+            Some(4) => check_format4(sub, num_glyphs),
+            Some(12) => check_format12(sub, num_glyphs),
+            Some(0 | 2 | 6 | 10 | 13 | 14) => Ok(0),
+            Some(format) => Err(format!("unknown format {format}")),
+            None => Err("truncated".to_string()),
+        };
+        match checked {
+            Ok(missing) => missing_glyphs += missing,
+            Err(e) => c.error("cmap", format!("subtable {platform}/{encoding}: {e}")),
+        }
+    }
+    if !unicode {
+        c.warn("cmap", "no Unicode subtable");
+    }
+    if missing_glyphs > 0 {
+        c.warn("cmap", format!("{missing_glyphs} code points map to glyph IDs beyond numGlyphs"));
+    }
+}
+
+/// Checks a segment-mapping subtable; returns how many code points map past
+/// `num_glyphs`.
+fn check_format4(sub: &[u8], num_glyphs: usize) -> Result<usize, String> {
+    let length = be_u16(sub, 2).ok_or("truncated")? as usize;
+    let sub = sub.get(..length).ok_or("length runs past the end of the table")?;
+    let seg_x2 = be_u16(sub, 6).ok_or("truncated")? as usize;
+    if seg_x2 == 0 || !seg_x2.is_multiple_of(2) {
+        return Err(format!("segCountX2 {seg_x2} must be even and nonzero"));
+    }
+    let (ends, starts, deltas, range_offsets) = (14, 16 + seg_x2, 16 + 2 * seg_x2, 16 + 3 * seg_x2);
+    if range_offsets + seg_x2 > sub.len() {
+        return Err("segment arrays run past the subtable".to_string());
+    }
+    let mut missing = 0;
+    let mut previous_end = None;
+    for s in 0..seg_x2 / 2 {
+        let (end, start) = (be_u16(sub, ends + 2 * s).unwrap_or(0), be_u16(sub, starts + 2 * s).unwrap_or(0));
+        let delta = be_u16(sub, deltas + 2 * s).unwrap_or(0);
+        let range_offset = be_u16(sub, range_offsets + 2 * s).unwrap_or(0) as usize;
+        if start > end || previous_end.is_some_and(|p| start <= p) {
+            return Err(format!("segment {s} ({start:04X}-{end:04X}) is out of order"));
+        }
+        previous_end = Some(end);
+        if start == 0xFFFF {
+            continue;
+        }
+        for code in start..=end {
+            let glyph = if range_offset == 0 {
+                code.wrapping_add(delta)
+            } else {
+                let at = range_offsets + 2 * s + range_offset + 2 * (code - start) as usize;
+                match be_u16(sub, at).ok_or_else(|| format!("segment {s} glyph index runs past the subtable"))? {
+                    0 => 0,
+                    g => g.wrapping_add(delta),
+                }
+            };
+            if glyph as usize >= num_glyphs {
+                missing += 1;
+            }
+        }
+    }
+    if previous_end != Some(0xFFFF) {
+        return Err("last segment must end at FFFF".to_string());
+    }
+    Ok(missing)
+}
+
+/// Checks a segmented-coverage subtable; returns how many code points map
+/// past `num_glyphs`.
+fn check_format12(sub: &[u8], num_glyphs: usize) -> Result<usize, String> {
+    let length = be_u32(sub, 4).ok_or("truncated")? as usize;
+    let sub = sub.get(..length).ok_or("length runs past the end of the table")?;
+    let groups = be_u32(sub, 12).ok_or("truncated")? as usize;
+    if groups.saturating_mul(12).saturating_add(16) > sub.len() {
+        return Err(format!("{groups} groups do not fit in the subtable"));
+    }
+    let mut missing = 0;
+    let mut previous_end = None;
+    for g in 0..groups {
+        let at = 16 + 12 * g;
+        let [start, end, glyph] = [0, 4, 8].map(|i| be_u32(sub, at + i).unwrap_or(0));
+        if start > end || end > 0x10FFFF || previous_end.is_some_and(|p| start <= p) {
+            return Err(format!("group {g} (U+{start:04X}-{end:04X}) is out of order or out of range"));
+        }
+        previous_end = Some(end);
+        let last_glyph = glyph as u64 + (end - start) as u64;
+        if last_glyph >= num_glyphs as u64 {
+            missing += (last_glyph + 1 - (num_glyphs as u64).max(glyph as u64)) as usize;
+        }
+    }
+    Ok(missing)
+}
+
+fn check_name(name: &[u8], c: &mut Collector) {
+    let (Some(count), Some(storage)) = (be_u16(name, 2), be_u16(name, 4)) else {
+        c.error("name", "table is truncated");
+        return;
+    };
+    let (count, storage) = (count as usize, storage as usize);
+    if 6 + 12 * count > name.len() || storage > name.len() {
+        c.error("name", format!("{count} records or string storage run past the table"));
+        return;
+    }
+    let mut broken = Vec::new();
+    for i in 0..count {
+        let rec = 6 + 12 * i;
+        let [platform, name_id, length, offset] = [0, 6, 8, 10].map(|i| be_u16(name, rec + i).unwrap_or(0));
+        let (length, offset) = (length as usize, offset as usize);
+        if storage + offset + length > name.len() {
+            broken.push(format!("record {i} (name ID {name_id}) runs past the table"));
+        } else if length > MAX_NAME_BYTES && !FREE_TEXT_NAME_IDS.contains(&name_id) {
+            broken.push(format!("record {i} (name ID {name_id}) is overlong: {length} bytes"));
+        } else if name_id == 6 && length / if platform == 1 { 1 } else { 2 } > 63 {
+            c.warn("name", format!("record {i}: PostScript name is longer than 63 characters"));
+        }
+    }
+    report(c, "name", broken, "records");
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidateResponse {
+    size_bytes: u64,
+    sha256: String,
+    #[serde(flatten)]
+    validation: Validation,
+}
+
+/// Validates a raw font body without storing it.
+pub async fn validate_handler(
+    State(state): State<Arc<AppState>>,
+    body: Body,
+) -> Result<Json<ValidateResponse>, (StatusCode, String)> {
+    let file = spool::spool(body, spool::max_upload_bytes()).await?;
+    if file.size == 0 {
+        return Err((StatusCode::BAD_REQUEST, "request body is empty".to_string()));
+    }
+    let data = tokio::fs::read(file.path())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("reading spooled body: {e}")))?;
+    let validation = cancel::run(&state.jobs, "validate", move |token| {
+        token.check()?;
+        Ok(validate(&data))
+    })
+    .await?;
+    info!(size = file.size, errors = validation.errors.len(), warnings = validation.warnings.len(), "font validated");
+    Ok(Json(ValidateResponse { size_bytes: file.size, sha256: file.sha256.clone(), validation }))
+}
@@ -0,0 +1,156 @@
+//! VCDIFF (RFC 3284) delta encoder, for patches from one subset of a font
+//! to a larger one (see [`crate::ift`]).
+//!
+//! One window covers the whole target, with the whole source as its source
+//! segment. Matches are found through a hash of 8-byte blocks over the
+//! source and the target decoded so far, taken greedily. Instructions use
+//! the default code table and only the `VCD_SELF` address mode, so any
+//! conforming decoder (open-vcdiff, xdelta3) applies the patch. There is
+//! no secondary compression.
+
+const MAGIC: [u8; 4] = [0xD6, 0xC3, 0xC4, 0x00];
+const VCD_SOURCE: u8 = 0x01;
+
+const BLOCK: usize = 8;
+const HASH_BITS: u32 = 18;
+/// Candidates kept per hash.
+const WAYS: usize = 4;
+const EMPTY: u32 = u32::MAX;
+
+/// Default code table entries: ADD with its size in the instruction
+/// (`ADD_SIZED + size`, sizes 1-17) or after it, and COPY in mode 0 likewise
+/// (`COPY_SIZED + size`, sizes 4-18).
+const ADD: u8 = 1;
+const ADD_SIZED: u8 = 1;
+const COPY: u8 = 19;
+const COPY_SIZED: u8 = 16;
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    let mut bytes = [0u8; 10];
+    let mut at = bytes.len();
+    loop {
+        at -= 1;
+        bytes[at] = (value & 0x7F) as u8 | if at == bytes.len() - 1 { 0 } else { 0x80 };
+        value >>= 7;
+        if value == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(&bytes[at..]);
+}
+
+fn hash(block: &[u8]) -> usize {
+    let word = u64::from_le_bytes(block[..BLOCK].try_into().unwrap());
+    (word.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> (64 - HASH_BITS)) as usize
+}
+
+/// Positions in the source-then-target address space by block hash.
+struct Index {
+    slots: Vec<u32>,
+}
+
+impl Index {
+    fn insert(&mut self, hash: usize, address: usize) {
+        let bucket = &mut self.slots[hash * WAYS..(hash + 1) * WAYS];
+        bucket.rotate_right(1);
+        bucket[0] = address as u32;
+    }
+
+    fn candidates(&self, hash: usize) -> impl Iterator<Item = usize> + '_ {
+        self.slots[hash * WAYS..(hash + 1) * WAYS].iter().take_while(|&&a| a != EMPTY).map(|&a| a as usize)
+    }
+}
+
+#[derive(Default)]
+struct Sections {
+    data: Vec<u8>,
+    instructions: Vec<u8>,
+    addresses: Vec<u8>,
+}
+
+impl Sections {
+    fn add(&mut self, bytes: &[u8]) {
+        match bytes.len() {
+            0 => return,
+            n @ 1..=17 => self.instructions.push(ADD_SIZED + n as u8),
+            n => {
+                self.instructions.push(ADD);
+                varint(&mut self.instructions, n as u64);
+            }
+        }
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn copy(&mut self, length: usize, address: usize) {
+        match length {
+            4..=18 => self.instructions.push(COPY_SIZED + length as u8),
+            n => {
+                self.instructions.push(COPY);
+                varint(&mut self.instructions, n as u64);
+            }
+        }
+        varint(&mut self.addresses, address as u64);
+    }
+}
+
+/// A patch that turns `source` into `target`; with an empty `source` it
+/// carries `target` whole.
+pub fn encode(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut index = Index { slots: vec![EMPTY; (1 << HASH_BITS) * WAYS] };
+    for at in 0..source.len().saturating_sub(BLOCK - 1) {
+        index.insert(hash(&source[at..]), at);
+    }
+    let byte = |address: usize| if address < source.len() { source[address] } else { target[address - source.len()] };
+
+    let mut sections = Sections::default();
+    let (mut at, mut pending) = (0, 0);
+    while at + BLOCK <= target.len() {
+        let h = hash(&target[at..]);
+        let here = source.len() + at;
+        let mut best = (0, 0);
+        for candidate in index.candidates(h) {
+            // Copies stay within the source or within the target.
+            let limit = if candidate < source.len() { source.len() - candidate } else { usize::MAX };
+            let mut length = 0;
+            while at + length < target.len() && length < limit && byte(candidate + length) == target[at + length] {
+                length += 1;
+            }
+            if length > best.0 {
+                best = (length, candidate);
+            }
+        }
+        index.insert(h, here);
+        if best.0 >= BLOCK {
+            sections.add(&target[pending..at]);
+            sections.copy(best.0, best.1);
+            at += best.0;
+            pending = at;
+        } else {
+            at += 1;
+        }
+    }
+    sections.add(&target[pending..]);
+
+    let mut delta = Vec::new();
+    varint(&mut delta, target.len() as u64);
+    delta.push(0);
+    varint(&mut delta, sections.data.len() as u64);
+    varint(&mut delta, sections.instructions.len() as u64);
+    varint(&mut delta, sections.addresses.len() as u64);
+    delta.extend_from_slice(&sections.data);
+    delta.extend_from_slice(&sections.instructions);
+    delta.extend_from_slice(&sections.addresses);
+
+    let mut out = MAGIC.to_vec();
+    out.push(0);
+    if source.is_empty() {
+        out.push(0);
+    } else {
+        out.push(VCD_SOURCE);
+        varint(&mut out, source.len() as u64);
+        varint(&mut out, 0);
+    }
+    varint(&mut out, delta.len() as u64);
+    out.extend_from_slice(&delta);
+    out
+}
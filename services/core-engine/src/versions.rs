@@ -0,0 +1,278 @@
+//! Immutable versions of catalog binaries.
+//!
+//! `POST /api/v1/font/catalog/:id/versions` with `{"font_id"}` makes an
+//! uploaded TTF, OTF or WOFF the next version of the entry (admin only).
+//! The binary is validated first; each version is kept in
+//! `CATALOG_FONT_DIR/versions/<id>/v<n>.ttf|otf`, written once and never
+//! replaced, while `<id>.ttf|otf` always holds the current one. The entry's
+//! size, glyph count and unicode ranges follow the new binary. `GET
+//! .../versions` lists the stored versions.
+//!
+//! `/cdn/fonts/<id>/v<n>/<id>.<ext>` serves version `n` in any of its web
+//! formats, generated on first request and stored like other generated
+//! files, so pages pinned to a version (see [`crate::stylesheet`]) keep
+//! exactly the glyphs they were built with; `<id>.auto` picks the format
+//! for the client (see [`crate::negotiation`]). Binaries replaced outside this
+//! endpoint are not tracked.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{Json, Response},
+};
+use font_api::{FontVersion, NewVersionRequest};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    io::ErrorKind,
+    path::{Path as FsPath, PathBuf},
+    sync::Arc,
+};
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+use crate::{
+    analysis, artifacts, audit, cancel, catalog, compress, duplicates, extract::ApiJson, negotiation, signing, storage,
+    tenants, validation, AppState, FontCatalogEntry,
+};
+
+const FORMATS: &[&str] = &["woff2", "woff", "ttf", "otf"];
+
+fn version_dir(dir: &FsPath, id: &str) -> PathBuf {
+    dir.join("versions").join(id)
+}
+
+fn extension(sfnt: &[u8]) -> &'static str {
+    if sfnt.starts_with(b"OTTO") {
+        "otf"
+    } else {
+        "ttf"
+    }
+}
+
+/// The binary of version `n` of `entry`: its stored copy, or the current
+/// binary when `n` is the current version and was never stored apart.
+fn binary_path(dir: &FsPath, entry: &FontCatalogEntry, n: u32) -> Option<PathBuf> {
+    let stored = ["ttf", "otf"].iter().map(|ext| version_dir(dir, &entry.id).join(format!("v{n}.{ext}")));
+    match stored.into_iter().find(|p| p.is_file()) {
+        Some(path) => Some(path),
+        None if n == entry.version => duplicates::font_path(dir, &entry.id),
+        None => None,
+    }
+}
+
+/// Writes `data` to `path` unless it is there already; a different file
+/// under the same version is an error, never overwritten.
+async fn keep(path: &FsPath, data: &[u8]) -> Result<(), (StatusCode, String)> {
+    let failed = |e: std::io::Error| (StatusCode::INTERNAL_SERVER_ERROR, format!("{}: {e}", path.display()));
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(failed)?;
+    }
+    match tokio::fs::OpenOptions::new().write(true).create_new(true).open(path).await {
+        Ok(mut file) => file.write_all(data).await.map_err(failed),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            if tokio::fs::read(path).await.map_err(failed)? == data {
+                Ok(())
+            } else {
+                Err((StatusCode::CONFLICT, format!("{} already holds a different binary", path.display())))
+            }
+        }
+        Err(e) => Err(failed(e)),
+    }
+}
+
+/// Raises each entry's version to the newest one stored, so a catalog
+/// seeded without a database picks up versions added by earlier runs.
+pub fn restore(mut entries: Vec<FontCatalogEntry>) -> Vec<FontCatalogEntry> {
+    let Some(dir) = duplicates::catalog_font_dir() else { return entries };
+    for entry in &mut entries {
+        let Ok(files) = std::fs::read_dir(version_dir(&dir, &entry.id)) else { continue };
+        let stored = files.filter_map(|f| {
+            let name = f.ok()?.file_name().into_string().ok()?;
+            name.strip_prefix('v')?.split_once('.')?.0.parse::<u32>().ok()
+        });
+        entry.version = stored.fold(entry.version, u32::max);
+    }
+    entries
+}
+
+fn entry(state: &AppState, id: &str) -> Result<FontCatalogEntry, (StatusCode, String)> {
+    let found = state.catalog.read().unwrap().iter().find(|e| e.id == id).cloned();
+    found.ok_or_else(|| (StatusCode::NOT_FOUND, format!("no catalog entry '{id}'")))
+}
+
+fn font_dir(id: &str) -> Result<PathBuf, (StatusCode, String)> {
+    duplicates::catalog_font_dir().ok_or_else(|| {
+        (StatusCode::UNPROCESSABLE_ENTITY, format!("CATALOG_FONT_DIR is not configured, so '{id}' has no binary"))
+    })
+}
+
+pub async fn add(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ApiJson(req): ApiJson<NewVersionRequest>,
+) -> Result<(StatusCode, Json<FontCatalogEntry>), (StatusCode, String)> {
+    state.require_admin(&headers)?;
+    let entry = entry(&state, &id)?;
+    let dir = font_dir(&id)?;
+    let current = duplicates::font_path(&dir, &id)
+        .ok_or_else(|| (StatusCode::UNPROCESSABLE_ENTITY, format!("no binary for '{id}' in CATALOG_FONT_DIR")))?;
+    let upload = state.uploads.get(&headers, &req.font_id).await?;
+    let data = state.uploads.read(&upload).await?;
+
+    let (sfnt, facts) = cancel::run(&state.jobs, "versions", move |token| {
+        let sfnt = match compress::load(&data) {
+            Ok(font) => font.to_bytes(),
+            Err(e) => return Ok(Err(e)),
+        };
+        token.check()?;
+        let problems = validation::validate(&sfnt).problems();
+        if !problems.is_empty() {
+            return Ok(Err(format!("upload fails validation: {}", problems.join("; "))));
+        }
+        Ok(analysis::facts(&sfnt).map(|facts| (sfnt, facts)))
+    })
+    .await?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let previous = tokio::fs::read(&current)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{}: {e}", current.display())))?;
+    if previous == sfnt {
+        let message = format!("'{}' is already version {} of '{id}'", req.font_id, entry.version);
+        return Err((StatusCode::CONFLICT, message));
+    }
+    let versions = version_dir(&dir, &id);
+    let next = entry.version + 1;
+    keep(&versions.join(format!("v{}.{}", entry.version, extension(&previous))), &previous).await?;
+    keep(&versions.join(format!("v{next}.{}", extension(&sfnt))), &sfnt).await?;
+
+    let path = dir.join(format!("{id}.{}", extension(&sfnt)));
+    tokio::fs::write(&path, &sfnt)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{}: {e}", path.display())))?;
+    if path != current {
+        let _ = tokio::fs::remove_file(&current).await;
+    }
+    let before = json!(entry);
+    let updated = FontCatalogEntry {
+        version: next,
+        size_kb: sfnt.len() as f64 / 1024.0,
+        glyph_count: facts.glyph_count,
+        unicode_ranges: facts.unicode_ranges,
+        ..entry
+    };
+    catalog::persist(&state, &updated).await?;
+    if let Some(existing) = state.catalog.write().unwrap().iter_mut().find(|e| e.id == id) {
+        *existing = updated.clone();
+    }
+    audit::record(&state, &headers, "catalog.version", &id, before, json!(updated)).await;
+    info!(id = %id, version = next, upload = %req.font_id, "catalog font version added");
+    Ok((StatusCode::CREATED, Json(updated)))
+}
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<FontVersion>>, (StatusCode, String)> {
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &id)?;
+    let entry = entry(&state, &id)?;
+    let dir = font_dir(&id)?;
+    let mut versions = Vec::new();
+    for n in 1..=entry.version {
+        let Some(path) = binary_path(&dir, &entry, n) else { continue };
+        let data = tokio::fs::read(&path)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{}: {e}", path.display())))?;
+        versions.push(FontVersion {
+            version: n,
+            size_kb: data.len() as f64 / 1024.0,
+            sha256: format!("{:x}", Sha256::digest(&data)),
+            current: n == entry.version,
+        });
+    }
+    Ok(Json(versions))
+}
+
+/// `/cdn/fonts/:slug/:version/:file`: version `v<n>` of the entry `slug`
+/// as `<slug>.<ext>`.
+pub async fn serve(
+    State(state): State<Arc<AppState>>,
+    Path((slug, version, file)): Path<(String, String, String)>,
+    Query(signature): Query<signing::Signature>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    serve_version(&state, "", &slug, &version, &file, &signature, &headers).await
+}
+
+/// `/cdn/:tenant/fonts/:slug/:version/:file`, for a tenant's entries.
+pub async fn serve_tenant(
+    State(state): State<Arc<AppState>>,
+    Path((tenant, slug, version, file)): Path<(String, String, String, String)>,
+    Query(signature): Query<signing::Signature>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    serve_version(&state, &tenant, &slug, &version, &file, &signature, &headers).await
+}
+
+async fn serve_version(
+    state: &AppState,
+    tenant: &str,
+    slug: &str,
+    version: &str,
+    file: &str,
+    signature: &signing::Signature,
+    headers: &HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    // Paths under an alias serve the canonical entry (see [`crate::aliases`]).
+    let canonical = state.aliases.cdn(&state.catalog.read().unwrap(), slug, file);
+    let (slug, file) = canonical.as_ref().map_or((slug, file), |(slug, file, _)| (slug.as_str(), file.as_str()));
+    let not_found =
+        || (StatusCode::NOT_FOUND, format!("no font at {}/{slug}/{version}/{file}", tenants::prefix(tenant)));
+    let n: u32 = version.strip_prefix('v').and_then(|n| n.parse().ok()).filter(|&n| n > 0).ok_or_else(not_found)?;
+    let format = file.strip_prefix(slug).and_then(|f| f.strip_prefix('.')).ok_or_else(not_found)?;
+    let auto = format == "auto";
+    if !auto && !FORMATS.contains(&format) {
+        return Err(not_found());
+    }
+    let entry = state.catalog.read().unwrap().iter().find(|e| e.id == slug).cloned();
+    let entry = entry.filter(|e| e.tenant == tenant).ok_or_else(not_found)?;
+    let binary = || duplicates::catalog_font_dir().and_then(|dir| binary_path(&dir, &entry, n)).ok_or_else(not_found);
+    // ttf of a CFF font or otf of a TrueType one.
+    let other_outlines = |path: &FsPath, format: &str| {
+        matches!(format, "ttf" | "otf") && path.extension().is_some_and(|ext| ext != format)
+    };
+    let format = if auto {
+        let path = binary()?;
+        *negotiation::formats(headers).iter().find(|f| !other_outlines(&path, f)).ok_or_else(not_found)?
+    } else {
+        format
+    };
+    let key = format!("{slug}/v{n}/{slug}.{format}");
+    let stored = state.artifacts.exists(&key).await.map_err(storage::error("reading artifact"))?;
+    if !stored {
+        let path = binary()?;
+        if other_outlines(&path, format) {
+            return Err(not_found());
+        }
+        let data = tokio::fs::read(&path)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{}: {e}", path.display())))?;
+        let job_format = format.to_string();
+        let encoded = cancel::run(&state.jobs, "versions", move |token| {
+            token.check()?;
+            Ok(compress::load(&data).and_then(|font| compress::encode(&font, &job_format, 100)))
+        })
+        .await?
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+        state.artifacts.put_file(&key, encoded).await.map_err(storage::error("storing artifact"))?;
+        info!(id = %slug, version = n, format, "font version generated");
+    }
+    let mut response = artifacts::stream(state, tenant, slug, &key, signature, headers).await?;
+    if auto {
+        response.headers_mut().append(header::VARY, HeaderValue::from_static(negotiation::VARY));
+    }
+    Ok(response)
+}
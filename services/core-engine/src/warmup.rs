@@ -0,0 +1,68 @@
+//! Generating popular subsets ahead of their requests.
+//!
+//! Large subsets (CJK slices above all) take seconds to generate the first
+//! time they are asked for. At startup and then every
+//! `WARMUP_INTERVAL_SECS` (default 3600), the `WARMUP_TOP` (default 50)
+//! subset requests made most often over the last `WARMUP_DAYS` (default 7)
+//! days (see [`crate::analytics`]) are made again, one at a time, so their
+//! artifacts are in storage before anyone waits for them. Requests already
+//! stored cost a lookup; after a purge or a new font version they are
+//! generated again. Warming requests are not counted in analytics, quotas
+//! or popularity. `WARMUP_TOP=0` turns warming off.
+//!
+//! Only requests for shared catalog fonts without a saved profile are
+//! counted, as those are the same for every caller.
+
+use axum::{extract::State, http::HeaderMap};
+use std::{sync::Arc, time::Duration};
+use tracing::{debug, info, warn};
+
+use crate::{extract::ApiJson, AppState};
+
+tokio::task_local! {
+    static WARMING: ();
+}
+
+/// Whether the current request is one made by [`run`].
+pub fn warming() -> bool {
+    WARMING.try_with(|_| ()).is_ok()
+}
+
+fn setting(name: &str, default: u64) -> u64 {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Warms once per interval until the process exits.
+pub async fn run(state: Arc<AppState>) {
+    let top = setting("WARMUP_TOP", 50) as usize;
+    if top == 0 {
+        return;
+    }
+    let days = setting("WARMUP_DAYS", 7).max(1);
+    let mut tick = tokio::time::interval(Duration::from_secs(setting("WARMUP_INTERVAL_SECS", 3600).max(60)));
+    loop {
+        tick.tick().await;
+        let popular = match state.analytics.popular_subsets(state.db.as_ref(), days, top).await {
+            Ok(popular) => popular,
+            Err(e) => {
+                warn!("reading popular subsets failed: {e}");
+                continue;
+            }
+        };
+        let (mut warmed, mut failed) = (0, 0);
+        for (request, requests) in popular {
+            let font = request.font_name.clone();
+            let made = crate::subset(State(Arc::clone(&state)), HeaderMap::new(), ApiJson(request));
+            match WARMING.scope((), made).await {
+                Ok(_) => warmed += 1,
+                Err((status, e)) => {
+                    failed += 1;
+                    debug!(font = %font, requests, %status, "warming subset failed: {e}");
+                }
+            }
+        }
+        if warmed + failed > 0 {
+            info!(warmed, failed, "popular subsets warmed");
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! PNG waterfall specimens.
+//!
+//! `GET /api/v1/font/:id/waterfall.png` renders the same sample text at
+//! several sizes (12, 16, 24, 32 and 48 px unless `sizes` says otherwise),
+//! one row per size, black on white: `text`, else the font's primary sample
+//! or the one for `script`, as for `preview.svg`. With `artifact` set to a generated
+//! file's `download_url` it renders that file instead of the catalog
+//! binary, so a subset or instance can be eyeballed against the original
+//! for mangled outlines, lost kerning or missing glyphs. WOFF2 artifacts
+//! cannot be decoded here; generate ttf, otf or woff to preview them.
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+};
+use rustybuzz::UnicodeBuffer;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+
+use crate::{
+    artifacts,
+    cancel::CancelToken,
+    compress, duplicates,
+    isolation::{self, Blob},
+    preview, render,
+    sfnt::{sniff, Flavor},
+    sprite::{self, ImageFormat},
+    storage, tenants, AppState,
+};
+
+const DEFAULT_SIZES: [u16; 5] = [12, 16, 24, 32, 48];
+const MAX_SIZES: usize = 12;
+const MAX_CHARACTERS: usize = 256;
+
+#[derive(Debug, Deserialize)]
+pub struct WaterfallQuery {
+    text: Option<String>,
+    /// A sample script to draw instead of `text`.
+    script: Option<String>,
+    /// Comma-separated pixel sizes, top row first.
+    sizes: Option<String>,
+    /// A `download_url` of a file generated from this font.
+    artifact: Option<String>,
+}
+
+fn parse_sizes(list: Option<&str>) -> Result<Vec<u16>, String> {
+    let Some(list) = list else { return Ok(DEFAULT_SIZES.to_vec()) };
+    let sizes = list
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().ok().filter(|px| (6..=256).contains(px)).ok_or_else(|| format!("size '{s}' is not 6-256")))
+        .collect::<Result<Vec<u16>, String>>()?;
+    if sizes.is_empty() || sizes.len() > MAX_SIZES {
+        return Err(format!("sizes must list 1-{MAX_SIZES} sizes"));
+    }
+    Ok(sizes)
+}
+
+/// RGBA rows of `text` at each size in `sizes`, shaped and rasterized.
+fn draw(data: &[u8], text: &str, sizes: &[u16]) -> Result<(Vec<u8>, u32, u32), String> {
+    let shaper = rustybuzz::Face::from_slice(data, 0).ok_or("font cannot be shaped")?;
+    let raster = fontdue::Font::from_bytes(data, fontdue::FontSettings::default())
+        .map_err(|e| format!("font cannot be rasterized: {e}"))?;
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.guess_segment_properties();
+    let glyphs = rustybuzz::shape(&shaper, &[], buffer);
+    let shaped: Vec<_> = glyphs.glyph_infos().iter().zip(glyphs.glyph_positions()).collect();
+    let advance: i32 = shaped.iter().map(|(_, p)| p.x_advance).sum();
+    let upem = shaper.units_per_em().max(1) as f32;
+
+    // (px, baseline) per row.
+    let pad = 8;
+    let mut rows = Vec::with_capacity(sizes.len());
+    let mut height = pad;
+    for &size in sizes {
+        let px = size as f32;
+        let lines = raster.horizontal_line_metrics(px).ok_or("font has no horizontal metrics")?;
+        let baseline = height + lines.ascent.ceil() as i32;
+        rows.push((px, baseline));
+        height = baseline + (-lines.descent).ceil() as i32 + pad;
+    }
+    let largest = sizes.iter().copied().max().unwrap_or(1) as f32;
+    let width = ((advance.max(0) as f32 * largest / upem).ceil() as i32 + 2 * pad) as u32;
+    let height = height.max(1) as u32;
+    let mut coverage = vec![0u8; (width * height) as usize];
+    for (px, baseline) in rows {
+        let scale = px / upem;
+        let mut pen = 0i32;
+        for (info, position) in &shaped {
+            let (m, bitmap) = raster.rasterize_indexed(info.glyph_id as u16, px);
+            let x0 = pad + ((pen + position.x_offset) as f32 * scale).round() as i32 + m.xmin;
+            let y0 = baseline - (position.y_offset as f32 * scale).round() as i32 - m.ymin - m.height as i32;
+            for (i, &c) in bitmap.iter().enumerate() {
+                let (x, y) = (x0 + (i % m.width.max(1)) as i32, y0 + (i / m.width.max(1)) as i32);
+                if c == 0 || x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                    continue;
+                }
+                let at = &mut coverage[(y as u32 * width + x as u32) as usize];
+                *at = at.saturating_add(c);
+            }
+            pen += position.x_advance;
+        }
+    }
+    let pixels = coverage.iter().flat_map(|&c| [255 - c, 255 - c, 255 - c, 255]).collect();
+    Ok((pixels, width, height))
+}
+
+/// The storage key of `url`, which must be a generated file of `id`.
+fn artifact_key(url: &str, id: &str, family: &str) -> Result<String, (StatusCode, String)> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let key = tenants::artifact_key(path);
+    let not_this_font = || (StatusCode::BAD_REQUEST, format!("'{url}' is not a /cdn/fonts/ URL of '{id}'"));
+    let (slug, file) = key.split_once('/').ok_or_else(not_this_font)?;
+    let valid = !file.is_empty() && !file.starts_with('.') && !file.contains('/');
+    if !valid || (slug != artifacts::slug(id) && slug != artifacts::slug(family)) {
+        return Err(not_this_font());
+    }
+    Ok(key.to_string())
+}
+
+/// Draws the waterfall in the parse worker.
+#[derive(Serialize, Deserialize)]
+pub struct Draw {
+    text: Option<String>,
+    script: Option<String>,
+    sizes: Vec<u16>,
+}
+
+impl isolation::Task for Draw {
+    const NAME: &'static str = "waterfall";
+    type Output = Blob;
+
+    fn run(self, data: Vec<u8>, _: &CancelToken) -> Result<Blob, String> {
+        let sfnt = compress::load(&data)?.to_bytes();
+        let text = preview::text_for(&sfnt, self.text, self.script.as_deref(), render::DEFAULT_TEXT)?;
+        let (pixels, width, height) = draw(&sfnt, &text, &self.sizes)?;
+        sprite::encode(ImageFormat::Png, &pixels, width, height).map(Blob)
+    }
+}
+
+pub async fn waterfall(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(query): Query<WaterfallQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    state.flags.ensure("render", &headers)?;
+    let sizes = parse_sizes(query.sizes.as_deref()).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if query.text.as_ref().is_some_and(|t| t.trim().is_empty() || t.chars().count() > MAX_CHARACTERS) {
+        return Err((StatusCode::BAD_REQUEST, format!("text must be 1-{MAX_CHARACTERS} characters")));
+    }
+    preview::sample_script(query.script.as_deref())?;
+    state.sandbox.ensure_font(&headers, &state.catalog.read().unwrap(), &id)?;
+    let family = state.catalog.read().unwrap().iter().find(|e| e.id == id).map(|e| e.family.clone());
+    let family = family.ok_or_else(|| (StatusCode::NOT_FOUND, format!("no catalog entry '{id}'")))?;
+
+    let generated = match &query.artifact {
+        Some(url) => {
+            let key = artifact_key(url, &id, &family)?;
+            let data = state.artifacts.storage().get(&key).await.map_err(storage::error("reading artifact"))?;
+            Some(data.ok_or_else(|| (StatusCode::NOT_FOUND, format!("no font at /cdn/fonts/{key}")))?)
+        }
+        None => None,
+    };
+    if generated.as_deref().and_then(sniff) == Some(Flavor::Woff2) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "WOFF2 artifacts cannot be previewed; generate ttf, otf or woff output instead".to_string(),
+        ));
+    }
+
+    let data = match generated {
+        Some(data) => data,
+        None => duplicates::read_catalog_binary(&state, &id).await.map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?,
+    };
+    let task = Draw { text: query.text, script: query.script, sizes: sizes.clone() };
+    let Blob(png) = isolation::run(&state, task, data).await?.map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    info!(id = %id, sizes = ?sizes, artifact = query.artifact.is_some(), bytes = png.len(), "waterfall rendered");
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "image/png")
+        .header(header::CONTENT_LENGTH, png.len())
+        .body(Body::from(png))
+        .unwrap())
+}
@@ -0,0 +1,174 @@
+//! Signed callbacks when background jobs finish.
+//!
+//! A job queued with `?callback_url=` gets its final state POSTed there as
+//! JSON once it succeeds or fails. Each delivery carries `X-Webhook-Id` (the
+//! job ID), `X-Webhook-Timestamp` (Unix seconds) and `X-Webhook-Signature:
+//! sha256=<hex>`, an HMAC-SHA256 over `<timestamp>.<body>` keyed with
+//! `WEBHOOK_SECRET`; callbacks are refused while it is unset. Receivers
+//! should recompute the signature and reject stale timestamps. Network
+//! errors, `429` and `5xx` are retried up to `WEBHOOK_MAX_ATTEMPTS` times,
+//! waiting 1s, 2s, 4s, ... in between; other statuses are final.
+//!
+//! Like crawling, callbacks only go to public addresses unless
+//! `WEBHOOK_ALLOW_PRIVATE=true`: addresses in the URL are refused when the
+//! job is queued, host names as they are resolved (see
+//! [`crate::crawl::PublicResolver`]), and redirects are not followed. A job
+//! only records that its callback failed, not why, so callbacks cannot be
+//! used to probe the engine's network; the reason is logged.
+
+use axum::http::StatusCode;
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use sha2::Sha256;
+use std::{
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+use crate::crawl::{self, PublicResolver};
+
+fn env_u64(k: &str, d: u64) -> u64 {
+    std::env::var(k).ok().and_then(|v| v.parse().ok()).unwrap_or(d)
+}
+
+/// Longest wait between two attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// All a job records of a failed delivery.
+const NOT_DELIVERED: &str = "callback was not delivered";
+
+pub struct Webhooks {
+    secret: Option<String>,
+    max_attempts: u32,
+    allow_private: bool,
+    http: reqwest::Client,
+}
+
+/// How a delivery ended.
+pub struct Delivery {
+    pub attempts: u32,
+    pub error: Option<String>,
+}
+
+impl Webhooks {
+    pub fn from_env() -> Self {
+        let secret = std::env::var("WEBHOOK_SECRET").ok().filter(|s| !s.is_empty());
+        let allow_private = matches!(std::env::var("WEBHOOK_ALLOW_PRIVATE").as_deref(), Ok("true" | "1"));
+        Self::new(secret, env_u64("WEBHOOK_MAX_ATTEMPTS", 5).clamp(1, 20) as u32, allow_private)
+    }
+
+    fn new(secret: Option<String>, max_attempts: u32, allow_private: bool) -> Self {
+        let mut http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(env_u64("WEBHOOK_TIMEOUT_SECS", 10)))
+            .redirect(reqwest::redirect::Policy::none());
+        if !allow_private {
+            http = http.dns_resolver(Arc::new(PublicResolver)).no_proxy();
+        }
+        Self { secret, max_attempts, allow_private, http: http.build().expect("failed to build HTTP client") }
+    }
+
+    /// Checks a callback URL when the job is queued.
+    pub fn validate(&self, url: &str) -> Result<(), (StatusCode, String)> {
+        if self.secret.is_none() {
+            return Err((StatusCode::BAD_REQUEST, "callbacks are disabled; WEBHOOK_SECRET is not set".to_string()));
+        }
+        self.check(url).map_err(|e| (StatusCode::BAD_REQUEST, format!("callback_url {url:?} {e}")))
+    }
+
+    /// Host names are checked as they are resolved; addresses in the URL
+    /// never reach the resolver, so they are checked here.
+    fn check(&self, url: &str) -> Result<(), &'static str> {
+        let url = Url::parse(url).ok().filter(|u| matches!(u.scheme(), "http" | "https"));
+        let host = url.as_ref().and_then(Url::host_str).ok_or("must be an absolute http(s) URL")?;
+        let ip = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>();
+        match ip {
+            Ok(ip) if !self.allow_private && !crawl::public(ip) => Err("must not point at a non-public address"),
+            _ => Ok(()),
+        }
+    }
+
+    fn sign(&self, timestamp: u64, body: &[u8]) -> String {
+        let secret = self.secret.as_deref().unwrap_or_default();
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+        mac.update(format!("{timestamp}.").as_bytes());
+        mac.update(body);
+        let digest: String = mac.finalize().into_bytes().iter().map(|b| format!("{b:02x}")).collect();
+        format!("sha256={digest}")
+    }
+
+    /// POSTs `body` to `url`, retrying with exponential backoff.
+    pub async fn deliver(&self, url: &str, id: &str, body: Vec<u8>) -> Delivery {
+        if let Err(e) = self.check(url) {
+            warn!(id = %id, "webhook not delivered: callback_url {e}");
+            return Delivery { attempts: 0, error: Some(NOT_DELIVERED.to_string()) };
+        }
+        let mut backoff = Duration::from_secs(1);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            let sent = self
+                .http
+                .post(url)
+                .header("content-type", "application/json")
+                .header("x-webhook-id", id)
+                .header("x-webhook-timestamp", timestamp.to_string())
+                .header("x-webhook-signature", self.sign(timestamp, &body))
+                .body(body.clone())
+                .send()
+                .await;
+            let (error, retry) = match sent {
+                Ok(r) if r.status().is_success() => {
+                    info!(id = %id, attempts, "webhook delivered");
+                    return Delivery { attempts, error: None };
+                }
+                Ok(r) => {
+                    let status = r.status();
+                    (format!("callback answered {status}"), status.is_server_error() || status.as_u16() == 429)
+                }
+                Err(e) => (format!("callback failed: {}", crawl::describe(&e)), true),
+            };
+            if !retry || attempts >= self.max_attempts {
+                warn!(id = %id, attempts, error = %error, "webhook delivery gave up");
+                return Delivery { attempts, error: Some(NOT_DELIVERED.to_string()) };
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn webhooks(allow_private: bool) -> Webhooks {
+        Webhooks::new(Some("secret".to_string()), 1, allow_private)
+    }
+
+    #[test]
+    fn callbacks_to_non_public_addresses_are_refused() {
+        let hooks = webhooks(false);
+        let refused = ["http://127.0.0.1:8080/hook", "http://169.254.169.254/latest", "http://10.0.0.5/", "http://[::1]/"];
+        for url in refused {
+            let (status, message) = hooks.validate(url).unwrap_err();
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{url}");
+            assert!(message.contains("non-public address"), "{url}: {message}");
+        }
+        assert!(hooks.validate("https://hooks.example.com/fonts").is_ok());
+        assert!(hooks.validate("ftp://hooks.example.com/").is_err());
+        assert!(webhooks(true).validate("http://127.0.0.1:8080/hook").is_ok());
+    }
+
+    #[tokio::test]
+    async fn failed_deliveries_do_not_say_why() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let delivery = webhooks(false).deliver(&format!("http://localhost:{port}/hook"), "job", b"{}".to_vec()).await;
+        assert_eq!((delivery.attempts, delivery.error.as_deref()), (1, Some(NOT_DELIVERED)));
+        let delivery = webhooks(false).deliver(&format!("http://127.0.0.1:{port}/hook"), "job", b"{}".to_vec()).await;
+        assert_eq!((delivery.attempts, delivery.error.as_deref()), (0, Some(NOT_DELIVERED)));
+    }
+}
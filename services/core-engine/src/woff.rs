@@ -0,0 +1,406 @@
+//! WOFF 1.0 (zlib) and WOFF2 (Brotli) containers.
+//!
+//! Both encoders take a serialised sfnt (see [`Font::to_bytes`]) so table
+//! checksums and `head.checkSumAdjustment` are already final. WOFF2 tables
+//! are stored untransformed (`glyf`/`loca` use the null transform), which
+//! every conforming decoder accepts.
+//!
+//! WOFF 1.0 files carry an extended metadata block built from the font's
+//! `name` table (unique ID, vendor, designer, description, license,
+//! copyright and trademark), zlib-compressed after the tables; fonts without
+//! any of those names get none. [`woff_metadata`] reads the block back.
+
+use std::io::{Read, Write};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use crate::{
+    brotli,
+    name::NameTable,
+    sfnt::{be_u16, be_u32, Font, Table},
+    spool,
+};
+
+const WOFF_SIGNATURE: &[u8; 4] = b"wOFF";
+const WOFF2_SIGNATURE: &[u8; 4] = b"wOF2";
+const WOFF_HEADER_LEN: usize = 44;
+const WOFF2_HEADER_LEN: usize = 48;
+/// Transform version 3 is the null transform for `glyf` and `loca`.
+const NULL_GLYF_TRANSFORM: u8 = 3 << 6;
+
+/// Tags with a one-byte code in the WOFF2 table directory.
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm", b"glyf", b"loca",
+    b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern", b"LTSH", b"PCLT", b"VDMX", b"vhea",
+    b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC", b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL",
+    b"SVG ", b"sbix", b"acnt", b"avar", b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar",
+    b"gvar", b"hsty", b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+
+/// A table directory entry of a serialised sfnt.
+struct Entry<'a> {
+    tag: [u8; 4],
+    checksum: u32,
+    data: &'a [u8],
+}
+
+fn entries(sfnt: &[u8]) -> Result<(u32, Vec<Entry<'_>>), String> {
+    let version = be_u32(sfnt, 0).ok_or("truncated offset table")?;
+    let count = be_u16(sfnt, 4).ok_or("truncated offset table")? as usize;
+    let mut out = Vec::with_capacity(count);
+    for i in 0..count {
+        let rec = 12 + 16 * i;
+        let field = |at| be_u32(sfnt, rec + at).ok_or("truncated table directory");
+        let (offset, length) = (field(8)? as usize, field(12)? as usize);
+        out.push(Entry {
+            tag: sfnt[rec..rec + 4].try_into().unwrap(),
+            checksum: field(4)?,
+            data: sfnt.get(offset..offset + length).ok_or("table extends past end of font")?,
+        });
+    }
+    Ok((version, out))
+}
+
+fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn sfnt_size(entries: &[Entry]) -> u32 {
+    (12 + 16 * entries.len() + entries.iter().map(|e| e.data.len().next_multiple_of(4)).sum::<usize>()) as u32
+}
+
+fn zlib(data: &[u8], level: u32) -> Result<Vec<u8>, String> {
+    let mut z = ZlibEncoder::new(Vec::new(), Compression::new(level));
+    z.write_all(data).map_err(|e| e.to_string())?;
+    z.finish().map_err(|e| e.to_string())
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// WOFF extended metadata XML from `font`'s names; `None` when it has none
+/// worth carrying.
+pub fn metadata_xml(font: &Font) -> Option<String> {
+    let names = NameTable::parse(font.table(b"name")?).ok()?;
+    let get = |id: u16| names.get(id).filter(|v| !v.trim().is_empty()).map(|v| xml_escape(v.trim()));
+    let url = |id: u16| get(id).map(|u| format!(" url=\"{u}\"")).unwrap_or_default();
+    let text = |element: &str, id: u16, attributes: String| {
+        get(id).map(|t| format!("  <{element}{attributes}>\n    <text>{t}</text>\n  </{element}>\n"))
+    };
+    let mut elements = Vec::new();
+    elements.extend(get(3).map(|id| format!("  <uniqueid id=\"{id}\"/>\n")));
+    elements.extend(get(8).map(|vendor| format!("  <vendor name=\"{vendor}\"{}/>\n", url(11))));
+    elements.extend(get(9).map(|designer| {
+        format!("  <credits>\n    <credit name=\"{designer}\"{} role=\"Designer\"/>\n  </credits>\n", url(12))
+    }));
+    elements.extend(text("description", 10, String::new()));
+    elements.extend(text("license", 13, url(14)));
+    elements.extend(text("copyright", 0, String::new()));
+    elements.extend(text("trademark", 7, String::new()));
+    let header = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<metadata version=\"1.0\">\n";
+    (!elements.is_empty()).then(|| format!("{header}{}</metadata>\n", elements.concat()))
+}
+
+/// WOFF 1.0 at zlib `level` (0-9), with `metadata` XML if given. Tables
+/// that do not shrink are stored as they are, as the format requires.
+pub fn encode_woff(sfnt: &[u8], level: u32, metadata: Option<&str>) -> Result<Vec<u8>, String> {
+    let (flavor, mut entries) = entries(sfnt)?;
+    entries.sort_by_key(|e| e.tag);
+    let mut directory = Vec::with_capacity(20 * entries.len());
+    let mut body = Vec::new();
+    let tables_start = WOFF_HEADER_LEN + 20 * entries.len();
+    for e in &entries {
+        let compressed = zlib(e.data, level)?;
+        let stored = if compressed.len() < e.data.len() { compressed.as_slice() } else { e.data };
+        directory.extend_from_slice(&e.tag);
+        put_u32(&mut directory, (tables_start + body.len()) as u32);
+        put_u32(&mut directory, stored.len() as u32);
+        put_u32(&mut directory, e.data.len() as u32);
+        put_u32(&mut directory, e.checksum);
+        body.extend_from_slice(stored);
+        body.resize(body.len().next_multiple_of(4), 0);
+    }
+
+    // The metadata block follows the (4-byte padded) tables; it is always
+    // compressed. No private block.
+    let metadata = metadata.map(|xml| zlib(xml.as_bytes(), level).map(|z| (z, xml.len()))).transpose()?;
+    let meta_offset = tables_start + body.len();
+    let length = meta_offset + metadata.as_ref().map_or(0, |(z, _)| z.len());
+    let mut out = Vec::with_capacity(length);
+    out.extend_from_slice(WOFF_SIGNATURE);
+    put_u32(&mut out, flavor);
+    put_u32(&mut out, length as u32);
+    put_u16(&mut out, entries.len() as u16);
+    put_u16(&mut out, 0);
+    put_u32(&mut out, sfnt_size(&entries));
+    put_u16(&mut out, 1);
+    put_u16(&mut out, 0);
+    match &metadata {
+        Some((z, original)) => {
+            put_u32(&mut out, meta_offset as u32);
+            put_u32(&mut out, z.len() as u32);
+            put_u32(&mut out, *original as u32);
+        }
+        None => out.extend_from_slice(&[0; 12]),
+    }
+    out.extend_from_slice(&[0; 8]); // no private block
+    out.extend_from_slice(&directory);
+    out.extend_from_slice(&body);
+    if let Some((z, _)) = &metadata {
+        out.extend_from_slice(z);
+    }
+    Ok(out)
+}
+
+/// Unpacks a WOFF 1.0 file.
+pub fn decode_woff(data: &[u8]) -> Result<Font, String> {
+    if data.get(..4) != Some(WOFF_SIGNATURE) {
+        return Err("not a WOFF file".to_string());
+    }
+    let flavor = be_u32(data, 4).ok_or("truncated WOFF header")?;
+    let count = be_u16(data, 12).ok_or("truncated WOFF header")? as usize;
+    let mut tables = Vec::with_capacity(count);
+    for i in 0..count {
+        let rec = WOFF_HEADER_LEN + 20 * i;
+        let field = |at| be_u32(data, rec + at).map(|v| v as usize).ok_or("truncated WOFF table directory");
+        let (offset, comp_length, orig_length) = (field(4)?, field(8)?, field(12)?);
+        let tag: [u8; 4] = data[rec..rec + 4].try_into().unwrap();
+        let stored = data.get(offset..offset + comp_length).ok_or("WOFF table extends past end of file")?;
+        if orig_length as u64 > spool::max_upload_bytes() {
+            let tag = String::from_utf8_lossy(&tag);
+            return Err(format!("table '{tag}' declares more bytes than an upload may have"));
+        }
+        let table = if comp_length < orig_length {
+            let mut out = Vec::new();
+            ZlibDecoder::new(stored)
+                .take(orig_length as u64 + 1)
+                .read_to_end(&mut out)
+                .map_err(|e| format!("table '{}': {e}", String::from_utf8_lossy(&tag)))?;
+            out
+        } else {
+            stored.to_vec()
+        };
+        if table.len() != orig_length {
+            return Err(format!("table '{}' does not inflate to its declared length", String::from_utf8_lossy(&tag)));
+        }
+        tables.push(Table { tag, data: table });
+    }
+    Ok(Font::new(flavor, tables))
+}
+
+/// The extended metadata XML of a WOFF 1.0 file, if it has a block.
+pub fn woff_metadata(data: &[u8]) -> Result<Option<String>, String> {
+    if data.get(..4) != Some(WOFF_SIGNATURE) {
+        return Err("not a WOFF file".to_string());
+    }
+    let field = |at| be_u32(data, at).map(|v| v as usize).ok_or("truncated WOFF header");
+    let (offset, length, orig_length) = (field(24)?, field(28)?, field(32)?);
+    if length == 0 {
+        return Ok(None);
+    }
+    if offset % 4 != 0 {
+        return Err("WOFF metadata block is not 4-byte aligned".to_string());
+    }
+    let stored = data.get(offset..offset + length).ok_or("WOFF metadata block extends past end of file")?;
+    if orig_length as u64 > spool::max_upload_bytes() {
+        return Err("WOFF metadata declares more bytes than an upload may have".to_string());
+    }
+    let mut xml = Vec::new();
+    ZlibDecoder::new(stored)
+        .take(orig_length as u64 + 1)
+        .read_to_end(&mut xml)
+        .map_err(|e| format!("WOFF metadata: {e}"))?;
+    if xml.len() != orig_length {
+        return Err("WOFF metadata does not inflate to its declared length".to_string());
+    }
+    String::from_utf8(xml).map(Some).map_err(|_| "WOFF metadata is not UTF-8".to_string())
+}
+
+fn put_base128(out: &mut Vec<u8>, mut v: u32) {
+    let mut bytes = vec![(v & 0x7F) as u8];
+    v >>= 7;
+    while v > 0 {
+        bytes.push(0x80 | (v & 0x7F) as u8);
+        v >>= 7;
+    }
+    out.extend(bytes.into_iter().rev());
+}
+
+/// WOFF2 at Brotli `level` (0-11).
+pub fn encode_woff2(sfnt: &[u8], level: u8) -> Result<Vec<u8>, String> {
+    let (flavor, mut entries) = entries(sfnt)?;
+    // `loca` must directly follow `glyf`.
+    entries.sort_by_key(|e| if &e.tag == b"loca" { *b"glyg" } else { e.tag });
+    let mut directory = Vec::new();
+    let mut stream = Vec::new();
+    for e in &entries {
+        let known = KNOWN_TAGS.iter().position(|t| **t == e.tag);
+        let transform = if matches!(&e.tag, b"glyf" | b"loca") { NULL_GLYF_TRANSFORM } else { 0 };
+        match known {
+            Some(i) => directory.push(i as u8 | transform),
+            None => {
+                directory.push(63 | transform);
+                directory.extend_from_slice(&e.tag);
+            }
+        }
+        put_base128(&mut directory, e.data.len() as u32);
+        stream.extend_from_slice(e.data);
+    }
+    let compressed = brotli::compress(&stream, level);
+
+    let length = (WOFF2_HEADER_LEN + directory.len() + compressed.len()).next_multiple_of(4);
+    let mut out = Vec::with_capacity(length);
+    out.extend_from_slice(WOFF2_SIGNATURE);
+    put_u32(&mut out, flavor);
+    put_u32(&mut out, length as u32);
+    put_u16(&mut out, entries.len() as u16);
+    put_u16(&mut out, 0);
+    put_u32(&mut out, sfnt_size(&entries));
+    put_u32(&mut out, compressed.len() as u32);
+    put_u16(&mut out, 1);
+    put_u16(&mut out, 0);
+    out.extend_from_slice(&[0; 20]); // no metadata or private block
+    out.extend_from_slice(&directory);
+    out.extend_from_slice(&compressed);
+    out.resize(length, 0);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small TrueType font: a few compressible tables, one that does not
+    /// shrink and one tag without a WOFF2 code.
+    fn sample() -> Vec<u8> {
+        let mut head = vec![0; 54];
+        head[..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        head[12..16].copy_from_slice(&0x5F0F_3CF5u32.to_be_bytes());
+        let mut seed = 0x2545_F491_u32;
+        let noise: Vec<u8> = (0..301)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect();
+        let tables = [
+            (*b"head", head),
+            (*b"hhea", vec![0; 36]),
+            (*b"maxp", vec![0, 0, 0x50, 0, 0, 2]),
+            (*b"glyf", b"outline ".repeat(40)),
+            (*b"loca", vec![0, 0, 0, 80, 0, 160]),
+            (*b"name", noise),
+            (*b"TEST", vec![7; 3]),
+        ];
+        Font::new(0x0001_0000, tables.into_iter().map(|(tag, data)| Table { tag, data }).collect()).to_bytes()
+    }
+
+    #[test]
+    fn woff_round_trips() {
+        let sfnt = sample();
+        for level in [0, 6, 9] {
+            let woff = encode_woff(&sfnt, level, None).unwrap();
+            assert_eq!(&woff[..4], WOFF_SIGNATURE);
+            assert_eq!(be_u32(&woff, 8), Some(woff.len() as u32));
+            assert_eq!(be_u32(&woff, 16), Some(sfnt.len() as u32), "totalSfntSize at level {level}");
+            assert_eq!(decode_woff(&woff).unwrap().to_bytes(), sfnt, "level {level}");
+            assert_eq!(woff_metadata(&woff).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn woff_stores_tables_that_do_not_shrink() {
+        let woff = encode_woff(&sample(), 9, None).unwrap();
+        let records: Vec<&[u8]> = woff[WOFF_HEADER_LEN..].chunks(20).take(7).collect();
+        let record = |tag: &[u8]| *records.iter().find(|r| &r[..4] == tag).unwrap();
+        let (name, glyf) = (record(b"name"), record(b"glyf"));
+        assert_eq!(be_u32(name, 8), be_u32(name, 12));
+        assert!(be_u32(glyf, 8) < be_u32(glyf, 12));
+    }
+
+    #[test]
+    fn woff_metadata_round_trips() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<metadata version=\"1.0\">\n</metadata>\n";
+        let woff = encode_woff(&sample(), 6, Some(xml)).unwrap();
+        assert_eq!(woff_metadata(&woff).unwrap().as_deref(), Some(xml));
+        assert_eq!(decode_woff(&woff).unwrap().to_bytes(), sample());
+    }
+
+    #[test]
+    fn woff_declaring_huge_tables_is_refused_before_inflating() {
+        let mut woff = encode_woff(&sample(), 6, None).unwrap();
+        let glyf = (0..7).map(|i| WOFF_HEADER_LEN + 20 * i).find(|&at| &woff[at..at + 4] == b"glyf").unwrap();
+        woff[glyf + 12..glyf + 16].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(decode_woff(&woff).err().unwrap().contains("more bytes than an upload"));
+
+        let mut woff = encode_woff(&sample(), 6, Some("<metadata/>")).unwrap();
+        woff[32..36].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(woff_metadata(&woff).unwrap_err().contains("more bytes than an upload"));
+    }
+
+    #[test]
+    fn woff_rejects_other_files() {
+        assert!(decode_woff(&sample()).is_err());
+        assert!(decode_woff(b"wOFF").is_err());
+    }
+
+    /// Reads a UIntBase128 at `at`, advancing it.
+    fn base128(data: &[u8], at: &mut usize) -> u32 {
+        let mut value = 0;
+        loop {
+            let byte = data[*at];
+            *at += 1;
+            value = value << 7 | u32::from(byte & 0x7F);
+            if byte & 0x80 == 0 {
+                return value;
+            }
+        }
+    }
+
+    #[test]
+    fn woff2_directory_is_sorted_with_loca_after_glyf() {
+        let sfnt = sample();
+        let woff2 = encode_woff2(&sfnt, 0).unwrap();
+        assert_eq!(&woff2[..4], WOFF2_SIGNATURE);
+        assert_eq!(woff2.len() % 4, 0);
+        assert_eq!(be_u32(&woff2, 8), Some(woff2.len() as u32));
+        assert_eq!(be_u32(&woff2, 16), Some(sfnt.len() as u32), "totalSfntSize");
+
+        let count = be_u16(&woff2, 12).unwrap() as usize;
+        let mut at = WOFF2_HEADER_LEN;
+        let mut directory = Vec::new();
+        for _ in 0..count {
+            let flags = woff2[at];
+            at += 1;
+            let tag: [u8; 4] = match flags & 63 {
+                63 => {
+                    at += 4;
+                    woff2[at - 4..at].try_into().unwrap()
+                }
+                known => *KNOWN_TAGS[known as usize],
+            };
+            let transform = flags >> 6;
+            let expected = if matches!(&tag, b"glyf" | b"loca") { 3 } else { 0 };
+            assert_eq!(transform, expected, "null transform for {}", String::from_utf8_lossy(&tag));
+            directory.push((tag, base128(&woff2, &mut at)));
+        }
+        let tags: Vec<&[u8; 4]> = directory.iter().map(|(tag, _)| tag).collect();
+        assert_eq!(tags, [b"TEST", b"glyf", b"loca", b"head", b"hhea", b"maxp", b"name"]);
+
+        // Level 0 stores the stream, so the tables follow in directory order.
+        let font = Font::parse(&sfnt).unwrap();
+        let stream: Vec<u8> = tags.iter().flat_map(|tag| font.table(tag).unwrap().to_vec()).collect();
+        let lengths: Vec<u32> = tags.iter().map(|tag| font.table(tag).unwrap().len() as u32).collect();
+        assert_eq!(directory.iter().map(|(_, length)| *length).collect::<Vec<_>>(), lengths);
+        let compressed = be_u32(&woff2, 20).unwrap() as usize;
+        assert!(woff2[at..at + compressed].windows(stream.len()).any(|w| w == stream));
+    }
+}
@@ -0,0 +1,196 @@
+//! ZIP archives: written for downloads (glyph exports, family bundles) and
+//! read for bulk ingestion. Only what those need: stored and deflated
+//! entries, no ZIP64, no encryption.
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression, Crc};
+use std::io::{Read, Write};
+
+/// A ZIP archive of deflated `files`. Entries carry a fixed 1980-01-01
+/// timestamp, so the same files always produce the same bytes.
+pub fn write(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+    for (name, content) in files {
+        let mut crc = Crc::new();
+        crc.update(content);
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content).map_err(|e| e.to_string())?;
+        let deflated = encoder.finish().map_err(|e| e.to_string())?;
+        let offset = u32::try_from(out.len()).map_err(|_| "archive is over 4 GiB")?;
+        // version, flags, method 8 (deflate), time, date 1980-01-01, CRC,
+        // sizes, name length, extra length.
+        let fields = |out: &mut Vec<u8>| {
+            out.extend(20u16.to_le_bytes());
+            out.extend(0u16.to_le_bytes());
+            out.extend(8u16.to_le_bytes());
+            out.extend(0u16.to_le_bytes());
+            out.extend(0x21u16.to_le_bytes());
+            out.extend(crc.sum().to_le_bytes());
+            out.extend((deflated.len() as u32).to_le_bytes());
+            out.extend((content.len() as u32).to_le_bytes());
+            out.extend((name.len() as u16).to_le_bytes());
+            out.extend(0u16.to_le_bytes());
+        };
+        out.extend(0x0403_4B50u32.to_le_bytes());
+        fields(&mut out);
+        out.extend(name.as_bytes());
+        out.extend(&deflated);
+
+        central.extend(0x0201_4B50u32.to_le_bytes());
+        central.extend(20u16.to_le_bytes());
+        fields(&mut central);
+        // Comment length, disk, internal and external attributes, offset.
+        central.extend([0; 10]);
+        central.extend(offset.to_le_bytes());
+        central.extend(name.as_bytes());
+    }
+    let directory = out.len() as u32;
+    let count = files.len() as u16;
+    out.extend(&central);
+    out.extend(0x0605_4B50u32.to_le_bytes());
+    out.extend([0; 4]);
+    out.extend(count.to_le_bytes());
+    out.extend(count.to_le_bytes());
+    out.extend((central.len() as u32).to_le_bytes());
+    out.extend(directory.to_le_bytes());
+    out.extend(0u16.to_le_bytes());
+    Ok(out)
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<usize> {
+    data.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+}
+
+/// A file in a ZIP archive, still compressed.
+pub struct Entry<'a> {
+    pub name: String,
+    method: usize,
+    encrypted: bool,
+    crc: u32,
+    size: u64,
+    data: &'a [u8],
+}
+
+impl Entry<'_> {
+    /// The file's bytes; an error when it is encrypted, compressed other
+    /// than stored or deflated, over `limit` bytes or corrupt.
+    pub fn extract(&self, limit: u64) -> Result<Vec<u8>, String> {
+        if self.encrypted {
+            return Err("encrypted".to_string());
+        }
+        if self.size > limit {
+            return Err(format!("larger than {limit} bytes"));
+        }
+        let data = match self.method {
+            0 => self.data.to_vec(),
+            8 => {
+                let mut out = Vec::with_capacity(self.size as usize);
+                DeflateDecoder::new(self.data).take(self.size + 1).read_to_end(&mut out).map_err(|e| e.to_string())?;
+                out
+            }
+            m => return Err(format!("unsupported compression method {m}")),
+        };
+        let mut crc = Crc::new();
+        crc.update(&data);
+        if data.len() as u64 != self.size || crc.sum() != self.crc {
+            return Err("corrupt: size or CRC does not match".to_string());
+        }
+        Ok(data)
+    }
+}
+
+/// The files of a ZIP archive, read from its central directory.
+pub fn read(data: &[u8]) -> Result<Vec<Entry<'_>>, String> {
+    // The end of central directory record, before a comment of up to 64 KiB.
+    let search = data.len().saturating_sub(22 + 0xFFFF);
+    let end = (search..=data.len().saturating_sub(22))
+        .rev()
+        .find(|&at| u32_at(data, at) == Some(0x0605_4B50))
+        .ok_or("not a ZIP archive")?;
+    let count = u16_at(data, end + 10).unwrap_or_default();
+    let mut at = u32_at(data, end + 16).unwrap_or_default() as usize;
+    if at == 0xFFFF_FFFF {
+        return Err("ZIP64 archives are not supported".to_string());
+    }
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let truncated = || "truncated central directory".to_string();
+        if u32_at(data, at) != Some(0x0201_4B50) {
+            return Err(truncated());
+        }
+        let field = |offset| u16_at(data, at + offset).ok_or_else(truncated);
+        let (flags, method) = (field(8)?, field(10)?);
+        let (name_len, extra_len, comment_len) = (field(28)?, field(30)?, field(32)?);
+        let long = |offset| u32_at(data, at + offset).ok_or_else(truncated);
+        let (crc, compressed, size, local) = (long(16)?, long(20)?, long(24)?, long(42)? as usize);
+        let name = data.get(at + 46..at + 46 + name_len).ok_or_else(truncated)?;
+        at += 46 + name_len + extra_len + comment_len;
+
+        let start = (u32_at(data, local) == Some(0x0403_4B50))
+            .then(|| Some(local + 30 + u16_at(data, local + 26)? + u16_at(data, local + 28)?))
+            .flatten()
+            .ok_or("bad local file header")?;
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method,
+            encrypted: flags & 1 != 0,
+            crc,
+            size: size as u64,
+            data: data.get(start..start + compressed as usize).ok_or("truncated file data")?,
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files() -> Vec<(String, Vec<u8>)> {
+        vec![
+            ("fonts/inter.woff2".to_string(), b"wOF2".repeat(500)),
+            ("LICENSE.txt".to_string(), b"SIL Open Font License".to_vec()),
+            ("empty".to_string(), Vec::new()),
+        ]
+    }
+
+    #[test]
+    fn round_trips() {
+        let archive = write(&files()).unwrap();
+        assert_eq!(archive, write(&files()).unwrap(), "archives are deterministic");
+        let entries = read(&archive).unwrap();
+        assert_eq!(entries.len(), 3);
+        for (entry, (name, content)) in entries.iter().zip(files()) {
+            assert_eq!(entry.name, name);
+            assert_eq!(entry.extract(u64::MAX).unwrap(), content);
+        }
+    }
+
+    #[test]
+    fn refuses_entries_over_the_limit() {
+        let archive = write(&files()).unwrap();
+        let entries = read(&archive).unwrap();
+        assert_eq!(entries[0].extract(1999).unwrap_err(), "larger than 1999 bytes");
+        assert_eq!(entries[0].extract(2000).unwrap().len(), 2000);
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let mut archive = write(&files()).unwrap();
+        // The stored CRC of the first entry, in its central directory record.
+        let central = u32_at(&archive, archive.len() - 6).unwrap() as usize;
+        archive[central + 16] ^= 1;
+        assert!(read(&archive).unwrap()[0].extract(u64::MAX).unwrap_err().starts_with("corrupt"));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert_eq!(read(b"wOF2 is not a zip").err().unwrap(), "not a ZIP archive");
+        let archive = write(&files()).unwrap();
+        assert!(read(&archive[..archive.len() - 30]).is_err());
+    }
+}
@@ -0,0 +1,1178 @@
+//! Request and response types of the font engine's REST API.
+//!
+//! The engine (`services/core-engine`) serves these and `alice-font-client`
+//! sends and reads them, so both sides agree on the wire format by
+//! construction. The compress and subset responses are generic over their
+//! `estimate` report, which the engine fills with its own typed estimate and
+//! clients read as plain JSON.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// ── Transform options ──────────────────────────────────────────────────────
+
+/// What compress, subset and instances do to a font on the way out, flattened
+/// into each request so one request can instance, subset and encode a font
+/// into one artifact. The steps run in a fixed order: `instance`, the feature
+/// filter, the subset (subset only), `strip_hints`, then encoding as `format`
+/// at `quality`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransformOptions {
+    #[serde(default = "woff2")]
+    pub format: String,
+    /// Compress falls back to the catalog entry's default quality; subset
+    /// encodes at 100 without it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<u8>,
+    /// Drops `fpgm`, `prep`, `cvt ` and glyph instructions; also accepted as
+    /// `strip_hinting`.
+    #[serde(alias = "strip_hinting", skip_serializing_if = "Option::is_none")]
+    pub strip_hints: Option<bool>,
+    /// Feature tags (`ss01`, or a prefix like `ss*`) to strip from GSUB and
+    /// GPOS.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub drop_features: Vec<String>,
+    /// Strip every feature but these; exclusive with `drop_features`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_features: Option<Vec<String>>,
+    /// Keep glyphs reachable through GSUB substitutions (ligatures,
+    /// contextual alternates, `ccmp`) when subsetting; `false` drops them
+    /// for a smaller file. Defaults to `true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout_closure: Option<bool>,
+    /// Axis positions (`{"wght": 600}`) to cut a static instance of a
+    /// variable font at first; axes left out stay at their default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<BTreeMap<String, f64>>,
+    /// The output's file name in its download URL, in place of the font's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_name: Option<String>,
+}
+
+impl Default for TransformOptions {
+    fn default() -> Self {
+        Self {
+            format: woff2(),
+            quality: None,
+            strip_hints: None,
+            drop_features: Vec::new(),
+            keep_features: None,
+            layout_closure: None,
+            instance: None,
+            output_name: None,
+        }
+    }
+}
+
+// ── Compress ───────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompressRequest {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub font_name: String,
+    /// An uploaded font; `font_name` defaults to its family.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_id: Option<String>,
+    #[serde(flatten)]
+    pub transform: TransformOptions,
+    /// Produce the output to report its size, without storing it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+    /// Also return the output as a base64 `data:` URI (`data_uri`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub inline: bool,
+    /// Run the `glyf` optimization passes before encoding.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optimize_outlines: bool,
+    /// Also store the output obfuscated for an EPUB publication.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epub: Option<EpubOptions>,
+}
+
+/// Font obfuscation for an EPUB container, keyed by the publication's unique
+/// identifier: `idpf` (EPUB OCF 3.0, the default) or Adobe's older `adobe`,
+/// which needs a `urn:uuid:` identifier.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EpubOptions {
+    pub publication_id: String,
+    #[serde(default = "idpf")]
+    pub algorithm: String,
+}
+
+/// The obfuscated copy of an output, stored next to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EpubArtifact {
+    pub download_url: String,
+    /// The `Algorithm` URI to list for the font in `META-INF/encryption.xml`.
+    pub algorithm: String,
+}
+
+/// One `optimize_outlines` pass: the glyphs it changed and the `glyf` bytes
+/// it saved before encoding.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutlinePass {
+    pub pass: String,
+    pub glyphs: usize,
+    pub bytes_saved: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompressResponse<E = serde_json::Value> {
+    pub font_name: String,
+    pub format: String,
+    pub quality: u8,
+    pub strip_hints: bool,
+    pub original_size_kb: f64,
+    pub compressed_size_kb: f64,
+    pub ratio: f64,
+    /// Absent for dry runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_url: Option<String>,
+    #[serde(default = "none", skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<E>,
+    /// Settings taken from the catalog entry's defaults.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub defaults_applied: Vec<String>,
+    /// Feature tags `drop_features` / `keep_features` stripped.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_features: Vec<String>,
+    /// How much smaller `strip_hints` made the output; absent without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hinting_bytes_saved: Option<usize>,
+    /// What each `optimize_outlines` pass did; empty without it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outline_passes: Vec<OutlinePass>,
+    /// How much smaller `optimize_outlines` made the output; absent without
+    /// it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outline_bytes_saved: Option<usize>,
+    /// The output as `data:font/...;base64,...`, for `inline` requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_uri: Option<String>,
+    /// The output obfuscated for `epub` requests; absent for dry runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epub: Option<EpubArtifact>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompareFormatsRequest {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub font_name: String,
+    /// An uploaded font; `font_name` defaults to its family.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_id: Option<String>,
+    /// Defaults to `woff2`, `woff` and the raw format matching the outlines.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub formats: Vec<String>,
+    /// Defaults to 25, 50, 75 and 100.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub qualities: Vec<u8>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strip_hints: bool,
+}
+
+/// One format at one quality.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormatSize {
+    pub format: String,
+    /// Absent for formats quality does not change (`ttf`, `otf`, `eot`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quality: Option<u8>,
+    /// The Brotli or zlib level the quality maps to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<u32>,
+    pub bytes: usize,
+    pub size_kb: f64,
+    /// Original size over this size.
+    pub ratio: f64,
+    pub encode_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompareFormatsResponse {
+    pub font_name: String,
+    pub original_bytes: usize,
+    pub strip_hints: bool,
+    /// Smallest first.
+    pub results: Vec<FormatSize>,
+    /// Formats the font cannot be encoded as, with the reason.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub unsupported: BTreeMap<String, String>,
+}
+
+// ── Subset ─────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SubsetRequest {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub font_name: String,
+    /// An uploaded font; `font_name` defaults to its family.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_id: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub characters: String,
+    /// Also keep the canonical decomposition (NFD) of every character, for
+    /// text that may arrive decomposed. `characters` is always read as NFC.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub decompose: bool,
+    /// CSS `unicode-range` items (`U+0400-04FF, U+0500-052F`, `U+4??`),
+    /// comma-separated, added to `characters`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub unicode_range: String,
+    /// Named character sets or `U+` ranges, comma-separated, added to
+    /// `characters`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    #[serde(flatten)]
+    pub transform: TransformOptions,
+    /// Keep vertical writing: the `vert`/`vrt2` alternates of the kept
+    /// characters and the other vertical features, whatever
+    /// `layout_closure` and the feature filters say, and `vhea`/`vmtx`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub vertical: bool,
+    /// What a subset of complex-script characters does when shaping them
+    /// with it differs from the source font. Defaults to `warn`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_shaping: Option<VerifyShaping>,
+    /// `web` (default), `pdf`, or a saved subset profile (`name` or
+    /// `name@version`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Produce the subset to report its size, without storing it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+    /// Also return the output as a base64 `data:` URI (`data_uri`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub inline: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifyShaping {
+    /// Report divergences in `shaping`.
+    #[default]
+    Warn,
+    /// Reject the subset with `422`.
+    Fail,
+    /// Skip the check.
+    Off,
+}
+
+/// Reference text shaped with the source font and the subset.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShapingReport {
+    /// Strings shaped.
+    pub checked: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diverged: Vec<ShapingDivergence>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShapingDivergence {
+    pub text: String,
+    /// Glyph IDs the source font shapes `text` to.
+    pub glyphs: Vec<u32>,
+    /// The first difference, such as `glyph 3 (ID 812) has no outline`.
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SubsetProfile {
+    #[default]
+    Web,
+    /// Raw sfnt for PDF embedding.
+    Pdf,
+}
+
+/// What a `pdf` profile subset is embedded as.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PdfSubset {
+    pub subset_tag: String,
+    pub postscript_name: String,
+    pub retained_tables: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubsetResponse<E = serde_json::Value> {
+    pub font_name: String,
+    pub format: String,
+    pub character_count: usize,
+    pub original_glyph_count: usize,
+    pub subset_glyph_count: usize,
+    pub original_size_kb: f64,
+    pub subset_size_kb: f64,
+    /// Absent for dry runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_url: Option<String>,
+    #[serde(default = "none", skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<E>,
+    pub profile: SubsetProfile,
+    /// The saved profile used, as `name@version`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saved_profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    pub strip_hints: bool,
+    #[serde(default = "yes")]
+    pub layout_closure: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub vertical: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub defaults_applied: Vec<String>,
+    /// Feature tags `drop_features` / `keep_features` stripped.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_features: Vec<String>,
+    /// The shaping check, for subsets of complex-script characters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shaping: Option<ShapingReport>,
+    /// The output as `data:font/...;base64,...`, for `inline` requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_uri: Option<String>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub pdf: Option<PdfSubset>,
+}
+
+/// `#[serde(default)]` would require `E: Default`.
+fn none<E>() -> Option<E> {
+    None
+}
+
+fn woff2() -> String {
+    "woff2".to_string()
+}
+
+fn idpf() -> String {
+    "idpf".to_string()
+}
+
+fn yes() -> bool {
+    true
+}
+
+// ── Rename ─────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RenameRequest {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub font_name: String,
+    /// An uploaded font; `font_name` defaults to its family.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subfamily: Option<String>,
+    /// Derived from family and subfamily when they change and this is absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<String>,
+    /// Derived like `full_name`, as `Family-Subfamily` without spaces.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postscript_name: Option<String>,
+    #[serde(default = "woff2")]
+    pub format: String,
+    /// Also register the renamed font as this catalog entry (admin only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catalog_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenameResponse {
+    pub font_name: String,
+    pub format: String,
+    /// `family`, `subfamily`, `full_name` and `postscript_name` as written.
+    pub names: BTreeMap<String, String>,
+    pub size_kb: f64,
+    pub download_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catalog_entry: Option<FontCatalogEntry>,
+}
+
+// ── Vertical metrics ───────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NormalizeMetricsRequest {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub font_name: String,
+    /// An uploaded font; `font_name` defaults to its family.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_id: Option<String>,
+    /// Line metrics to keep: `typo`, `hhea` or `win`, as for
+    /// `/api/v1/font/metrics/repair`.
+    pub strategy: String,
+    #[serde(default = "woff2")]
+    pub format: String,
+}
+
+// ── Recolor ────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecolorRequest {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub font_name: String,
+    /// An uploaded font; `font_name` defaults to its family.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_id: Option<String>,
+    /// CPAL palette to override; 0, the one renderers use by default, when
+    /// absent.
+    #[serde(default)]
+    pub palette: usize,
+    /// New colors by palette entry index, as `#RRGGBB` or `#RRGGBBAA`.
+    pub colors: BTreeMap<usize, String>,
+    #[serde(default = "woff2")]
+    pub format: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecolorResponse {
+    pub font_name: String,
+    pub format: String,
+    pub palette: usize,
+    /// Every entry of the palette after the override, as `#RRGGBBAA`.
+    pub colors: Vec<String>,
+    pub size_kb: f64,
+    pub download_url: String,
+}
+
+// ── Batch ──────────────────────────────────────────────────────────────────
+
+/// One request of a batch, in request order.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchItem<T> {
+    pub index: usize,
+    /// What the single endpoint would have answered.
+    pub status: u16,
+    #[serde(default = "none", skip_serializing_if = "Option::is_none")]
+    pub result: Option<T>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchResponse<T> {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub items: Vec<BatchItem<T>>,
+}
+
+// ── Derived fonts ──────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergedRequest {
+    /// Font stack, most preferred first.
+    pub fonts: Vec<String>,
+    pub characters: String,
+    #[serde(default = "woff2")]
+    pub format: String,
+    /// `font-family` of the merged face; the first font's family when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgressiveRequest {
+    pub font_name: String,
+    pub language: String,
+    #[serde(default = "woff2")]
+    pub format: String,
+    /// Characters in the core chunk; the whole frequency list when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_size: Option<usize>,
+    /// Number of extended chunks.
+    #[serde(default = "default_chunks")]
+    pub chunks: usize,
+}
+
+fn default_chunks() -> usize {
+    4
+}
+
+/// Extends a subset the client already holds (incremental font transfer).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchSubsetRequest {
+    pub font_name: String,
+    /// An uploaded font; `font_name` defaults to its family.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_id: Option<String>,
+    /// Code points of the subset the client holds, as `U+` ranges,
+    /// comma-separated; empty for a first request.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub have: String,
+    /// `checksum` of the held subset, from the response that produced it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_checksum: Option<String>,
+    /// Code points to add, as `U+` ranges, comma-separated.
+    pub needed: String,
+}
+
+/// Subsets the catalog fonts a site uses to the text its pages show.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubsetFromUrlRequest {
+    /// Pages to fetch (http or https).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub urls: Vec<String>,
+    /// Markup to read as one more page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+    #[serde(default = "woff2")]
+    pub format: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstancesRequest {
+    pub font_name: String,
+    pub instances: Vec<InstanceSpec>,
+    /// `format` and `output_name` apply to the bundle.
+    #[serde(flatten)]
+    pub transform: TransformOptions,
+    #[serde(default)]
+    pub legacy: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstanceSpec {
+    pub weight: u16,
+    #[serde(default = "default_style")]
+    pub style: String,
+}
+
+fn default_style() -> String {
+    "normal".to_string()
+}
+
+/// `POST /api/v1/font/catalog/:id/instances`: static fonts to cut from a
+/// variable entry.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StaticInstancesRequest {
+    /// Empty generates every named instance of the font.
+    #[serde(default)]
+    pub instances: Vec<InstanceLocation>,
+}
+
+/// A named instance by its subfamily name (`"Bold"`), or a position on the
+/// axes (`{"axes": {"wght": 550}, "name": "Medium Plus"}`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum InstanceLocation {
+    Named(String),
+    Axes {
+        axes: BTreeMap<String, f64>,
+        /// The variant name; derived from the axes when absent.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+}
+
+// ── Slices ─────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SliceRequest {
+    pub font_name: String,
+    /// Equal runs to cut; 100 unless a `profile` decides.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slices: Option<usize>,
+    /// A configured slicing profile, e.g. `jp-120-slices`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default = "default_formats")]
+    pub formats: Vec<String>,
+}
+
+fn default_formats() -> Vec<String> {
+    vec![woff2()]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Slice {
+    pub unicode_range: String,
+    pub code_points: usize,
+    /// CDN path of the slice in each format.
+    pub files: BTreeMap<String, String>,
+    /// Size of the first format's file.
+    pub size_kb: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub font_id: String,
+    pub family: String,
+    /// The slicing profile; `None` for a plain `slices` count.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    pub formats: Vec<String>,
+    pub original_size_kb: f64,
+    pub total_size_kb: f64,
+    pub slices: Vec<Slice>,
+    pub sliced_at_unix: u64,
+}
+
+// ── Subset profiles ────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SubsetSpec {
+    #[serde(default)]
+    pub characters: String,
+    /// CSS `unicode-range` items (`U+0000-00FF`).
+    #[serde(default)]
+    pub ranges: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// OpenType feature tags to keep.
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedProfile {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub tenant: String,
+    pub name: String,
+    pub version: u32,
+    pub saved_at_unix: u64,
+    pub spec: SubsetSpec,
+}
+
+impl SavedProfile {
+    /// `name@version`, as used in artifact names.
+    pub fn reference(&self) -> String {
+        format!("{}@{}", self.name, self.version)
+    }
+}
+
+// ── Signed URLs ────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignRequest {
+    /// A `download_url` (or its `/cdn/fonts/...` path) of this font.
+    pub url: String,
+    /// Lifetime in seconds; `SIGNED_URL_TTL_SECS` when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedUrl {
+    pub url: String,
+    /// Unix seconds.
+    pub expires_at: u64,
+}
+
+// ── Analyze ────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnalyzeRequest {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub font_name: String,
+    /// An uploaded font; `font_name` defaults to its family.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_id: Option<String>,
+    #[serde(default)]
+    pub mode: AnalyzeMode,
+    /// Adds `kern` and GPOS kerning pair counts and the largest
+    /// adjustments.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_kerning: bool,
+    /// Adds each GSUB feature's ligatures, single substitutions and
+    /// alternates.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_substitutions: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalyzeMode {
+    #[default]
+    Summary,
+    /// Adds coverage of each Unicode block, out of its assigned characters.
+    Blocks,
+}
+
+// ── Coverage ───────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CoverageRequest {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub font_name: String,
+    /// An uploaded font; `font_name` defaults to its family.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_id: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub characters: String,
+    /// Named character sets, comma-separated, added to `characters`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+}
+
+// ── Diff ───────────────────────────────────────────────────────────────────
+
+/// One side of a diff: a catalog font or an upload.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FontSource {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub font_name: String,
+    /// An uploaded font; `font_name` defaults to its family.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiffRequest {
+    /// The current version.
+    pub from: FontSource,
+    /// The candidate replacing it.
+    pub to: FontSource,
+}
+
+// ── Uploads ────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Flavor {
+    /// TrueType outlines (`0x00010000` or `true`).
+    Ttf,
+    /// CFF outlines (`OTTO`).
+    Otf,
+    Woff,
+    Woff2,
+    /// TrueType/OpenType collection (`ttcf`).
+    Ttc,
+}
+
+impl Flavor {
+    /// Registered `font/*` media type (also the EPUB 3 core media type).
+    pub fn media_type(self) -> &'static str {
+        match self {
+            Self::Ttf | Self::Ttc => "font/ttf",
+            Self::Otf => "font/otf",
+            Self::Woff => "font/woff",
+            Self::Woff2 => "font/woff2",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadedFont {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub tenant: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    pub flavor: Flavor,
+    pub size_bytes: u64,
+    pub sha256: String,
+    /// From the `name` table (the first face's for a collection); absent
+    /// for WOFF2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    /// Number of fonts in a `ttc` upload.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub faces: Option<usize>,
+    pub uploaded_at_unix: u64,
+    /// Set in an upload response when the same bytes were already uploaded
+    /// (under any filename); the earlier upload is returned.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deduplicated: bool,
+    /// What the font says about its own license; absent for WOFF2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<EmbeddedLicense>,
+}
+
+impl UploadedFont {
+    /// The family name, or the upload ID when unknown.
+    pub fn display_name(&self) -> String {
+        self.family.clone().unwrap_or_else(|| self.id.clone())
+    }
+}
+
+/// A font's OS/2 `fsType` and the license strings in its `name` table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddedLicense {
+    pub fs_type: u16,
+    /// `installable`, `editable`, `preview-print` or `restricted`; the
+    /// engine will not produce web fonts from `restricted` ones.
+    pub embedding: String,
+    /// The engine will not subset these.
+    #[serde(default)]
+    pub no_subsetting: bool,
+    #[serde(default)]
+    pub bitmap_only: bool,
+    /// License description (name ID 13).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// License info URL (name ID 14).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Copyright notice (name ID 0).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub copyright: Option<String>,
+}
+
+/// One font inside an uploaded collection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionFace {
+    pub index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subfamily: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postscript_name: Option<String>,
+    /// `ttf` or `otf`, what the face extracts to.
+    pub format: String,
+    pub glyph_count: usize,
+    pub tables: Vec<String>,
+    /// Size of the face as a standalone font.
+    pub size_kb: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionFaces {
+    pub id: String,
+    pub faces: Vec<CollectionFace>,
+}
+
+/// Registers faces of an uploaded collection as catalog entries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisterFacesRequest {
+    /// Face indices; every face when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub faces: Vec<usize>,
+    pub license: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub foundry: Option<String>,
+}
+
+// ── Catalog ────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FontCatalogEntry {
+    pub id: String,
+    pub family: String,
+    pub variant: String,
+    pub formats: Vec<String>,
+    pub size_kb: f64,
+    pub glyph_count: usize,
+    pub unicode_ranges: Vec<String>,
+    pub license: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub foundry: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postscript_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<ProcessingDefaults>,
+    /// Generated files are only served through signed URLs.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub private: bool,
+    /// Version of the binary, from 1. Set by the engine: each binary added
+    /// through `/api/v1/font/catalog/:id/versions` is the next one.
+    #[serde(default = "first_version")]
+    pub version: u32,
+    /// Credit the license requires, written as a comment above the
+    /// family's `@font-face` rules in generated CSS.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<String>,    /// Owning tenant; only it sees the entry, whose files are served under
+    /// `/cdn/<tenant>/fonts/`. Shared with every tenant when empty.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub tenant: String,
+    /// `Cache-Control` for the entry's files and its family's stylesheet,
+    /// replacing the engine's policies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<String>,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+/// Foundry-approved processing settings for a catalog entry, applied when a
+/// compress or subset request omits them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProcessingDefaults {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quality: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strip_hints: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subset_preset: Option<String>,
+}
+
+/// Makes an uploaded TTF, OTF or WOFF the next version of a catalog entry.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NewVersionRequest {
+    pub font_id: String,
+}
+
+/// One stored binary of a catalog entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FontVersion {
+    pub version: u32,
+    pub size_kb: f64,
+    pub sha256: String,
+    pub current: bool,
+}
+
+/// What `POST /api/v1/admin/ingest` or `/api/v1/admin/catalog/import/google`
+/// did.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CatalogImport {
+    pub imported: Vec<FontCatalogEntry>,
+    /// IDs already in the catalog, left as they were.
+    #[serde(default)]
+    pub skipped: Vec<String>,
+    /// Why each file, family or face was not imported.
+    #[serde(default)]
+    pub failed: BTreeMap<String, String>,
+}
+
+/// A preview string the font covers entirely.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sample {
+    pub script: String,
+    pub text: String,
+}
+
+/// Catalog entry as served, with previews computed from its coverage.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CatalogItem {
+    #[serde(flatten)]
+    pub entry: FontCatalogEntry,
+    #[serde(default)]
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CatalogPage {
+    pub items: Vec<CatalogItem>,
+    /// Entries matching the filters, across all pages.
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+    pub pages: usize,
+}
+
+/// Another name for a catalog entry or family, such as a legacy ID.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alias {
+    /// Catalog ID or family slug the alias stands for.
+    pub target: String,
+    /// `301` or `308` to redirect CDN URLs under the alias instead of
+    /// serving them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redirect: Option<u16>,
+}
+
+// ── Admin ──────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub tenant: String,
+    pub scopes: Vec<String>,
+    /// First characters of the key, to tell keys apart in listings.
+    pub prefix: String,
+    pub sha256: String,
+    pub created_at_unix: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewKey {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub tenant: String,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatedKey {
+    /// Shown only in this response.
+    pub secret: String,
+    #[serde(flatten)]
+    pub key: ApiKey,
+}
+
+/// A tenant's monthly limits; unlimited where absent.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TenantQuota {
+    /// Bytes served from the CDN; past it downloads get `402`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth_bytes: Option<u64>,
+    /// Compress and subset requests; past it they get `429`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operations: Option<u64>,
+}
+
+/// One tenant's usage in one month, as exported for billing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TenantUsage {
+    pub tenant: String,
+    /// `YYYY-MM`, UTC.
+    pub month: String,
+    pub downloads: u64,
+    pub bandwidth_bytes: u64,
+    pub operations: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth_quota: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operations_quota: Option<u64>,
+}
+
+/// What to purge: exactly one of a catalog font, a CDN path prefix, or all.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CachePurgeRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_id: Option<String>,
+    /// `/cdn/fonts/<slug>/...` or `/cdn/<tenant>/fonts/<slug>/...`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub all: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CachePurgeResponse {
+    /// CDN paths of the deleted files.
+    pub purged: Vec<String>,
+    /// Entries dropped from the in-memory subset cache.
+    pub evicted: usize,
+    /// Purge generation now folded into new artifact addresses.
+    pub generation: u64,
+    /// Edge URLs, with `?v=<generation>` past any edge copy, of purged
+    /// catalog versions, which are generated again on first request. Other
+    /// files come back under new URLs from the next request for them.
+    pub urls: Vec<String>,
+}
+
+/// One recorded administrative or mutating operation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub at_unix: u64,
+    /// `admin`, `key:<id>` or `anonymous`.
+    pub actor: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub tenant: String,
+    /// What was done, e.g. `catalog.update` or `key.create`.
+    pub action: String,
+    /// What it was done to, e.g. a catalog or upload ID.
+    pub target: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub request_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<serde_json::Value>,
+}
+
+/// What `POST /api/v1/admin/reload` changed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReloadReport {
+    pub catalog_entries: usize,
+    /// Catalog ids that appeared or disappeared.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+    /// Changed settings now in effect, by environment name.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub applied: Vec<String>,
+    /// Changed settings that only take effect on restart.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub restart_required: Vec<String>,
+}
+
+/// A catalog backup; restore checks `catalog_sha256` before applying it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub format_version: u32,
+    pub engine_version: String,
+    pub created_at_unix: u64,
+    pub catalog: Vec<FontCatalogEntry>,
+    pub catalog_sha256: String,
+}
+
+/// The envelope of a catalog sync snapshot; the first line of its NDJSON
+/// form.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncManifest {
+    pub format_version: u32,
+    pub engine_version: String,
+    pub created_at_unix: u64,
+    pub entry_count: usize,
+    /// SHA-256 over the entries' canonical serialization.
+    pub catalog_sha256: String,
+    /// `sha256=<hex>` HMAC keyed with `CATALOG_SYNC_SECRET`.
+    pub signature: String,
+}
+
+/// A catalog entry with the SHA-256 of its binary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncEntry {
+    #[serde(flatten)]
+    pub entry: FontCatalogEntry,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary_sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncSnapshot {
+    #[serde(flatten)]
+    pub manifest: SyncManifest,
+    pub entries: Vec<SyncEntry>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OriginSet {
+    pub global: Vec<String>,
+    pub tenants: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OriginUpdate {
+    /// Applies to this kit only; global when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub origins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlagSet {
+    pub global: BTreeMap<String, bool>,
+    pub tenants: BTreeMap<String, BTreeMap<String, bool>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlagUpdate {
+    pub enabled: bool,
+    /// Applies to this tenant only; global when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HintConfig {
+    #[serde(default)]
+    pub preconnect: Vec<String>,
+    #[serde(default)]
+    pub dns_prefetch: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HintSet {
+    pub global: HintConfig,
+    pub tenants: BTreeMap<String, HintConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HintUpdate {
+    /// Applies to this kit only; global when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(flatten)]
+    pub hints: HintConfig,
+}
@@ -0,0 +1,284 @@
+//! `font-cli` — command-line client for the font engine.
+//!
+//! ```text
+//! font-cli upload ./Inter.ttf
+//! font-cli subset inter --text-file corpus.txt --format woff2 -o out/
+//! font-cli compress inter --format woff2 --quality 9 -o out/
+//! font-cli catalog list --license OFL-1.1
+//! ```
+//!
+//! The engine is reached at `--url` (or `FONT_ENGINE_URL`, default
+//! `http://localhost:8082`); `--api-key` / `FONT_API_KEY` and `--tenant` /
+//! `FONT_TENANT` are sent as `X-API-Key` and `X-Font-Tenant`. Requests go
+//! through `alice-font-client`.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use alice_font_client::{CompressRequest, FontCdnClient, SubsetRequest, TransformOptions};
+
+const USAGE: &str = "\
+usage: font-cli [--url URL] [--api-key KEY] [--tenant TENANT] <command>
+
+commands:
+  upload <file>
+  subset <font> (--text TEXT | --text-file FILE) [--preset NAME] [--profile P] [--font-id ID]
+         --format FMT [-o DIR]
+  compress <font> --format FMT [--quality N] [--font-id ID] [-o DIR]
+  catalog list [--family NAME] [--license ID] [--format FMT]";
+
+/// Failures reported to the user; usage errors exit with 2, the rest with 1.
+enum Error {
+    Usage(String),
+    Failed(String),
+}
+
+fn usage(msg: impl Into<String>) -> Error {
+    Error::Usage(msg.into())
+}
+
+fn failed(msg: impl Into<String>) -> Error {
+    Error::Failed(msg.into())
+}
+
+/// Command-line arguments split into positionals and `--flag value` pairs.
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl Args {
+    fn parse(raw: impl Iterator<Item = String>) -> Result<Self, Error> {
+        let mut args = Args { positional: Vec::new(), options: Vec::new() };
+        let mut raw = raw;
+        while let Some(arg) = raw.next() {
+            let Some(name) = arg.strip_prefix("--").or_else(|| arg.strip_prefix('-').filter(|n| n.len() == 1)) else {
+                args.positional.push(arg);
+                continue;
+            };
+            if name == "help" || name == "h" {
+                return Err(usage(""));
+            }
+            let (name, value) = match name.split_once('=') {
+                Some((name, value)) => (name.to_string(), value.to_string()),
+                None => {
+                    let value = raw.next().ok_or_else(|| usage(format!("{arg} needs a value")))?;
+                    (name.to_string(), value)
+                }
+            };
+            args.options.push((name, value));
+        }
+        Ok(args)
+    }
+
+    /// Removes and returns the last value given for `name` (or its alias).
+    fn take(&mut self, names: &[&str]) -> Option<String> {
+        let mut found = None;
+        self.options.retain(|(name, value)| {
+            let hit = names.contains(&name.as_str());
+            if hit {
+                found = Some(value.clone());
+            }
+            !hit
+        });
+        found
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        if let Some((name, _)) = self.options.first() {
+            return Err(usage(format!("unknown option --{name}")));
+        }
+        if let Some(extra) = self.positional.first() {
+            return Err(usage(format!("unexpected argument '{extra}'")));
+        }
+        Ok(())
+    }
+}
+
+impl From<alice_font_client::Error> for Error {
+    fn from(e: alice_font_client::Error) -> Self {
+        failed(e.to_string())
+    }
+}
+
+/// Fetches `url` into `dir`, named after the last path segment.
+async fn download(client: &FontCdnClient, url: &str, dir: &Path) -> Result<PathBuf, Error> {
+    let name = url.split('?').next().unwrap_or(url).rsplit('/').next().unwrap_or_default();
+    if name.is_empty() {
+        return Err(failed(format!("cannot name a file after '{url}'")));
+    }
+    let bytes = client.download(url).await?;
+    tokio::fs::create_dir_all(dir).await.map_err(|e| failed(format!("{}: {e}", dir.display())))?;
+    let path = dir.join(name);
+    tokio::fs::write(&path, &bytes).await.map_err(|e| failed(format!("{}: {e}", path.display())))?;
+    Ok(path)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Error::Usage(msg)) => {
+            if !msg.is_empty() {
+                eprintln!("font-cli: {msg}");
+            }
+            eprintln!("{USAGE}");
+            ExitCode::from(2)
+        }
+        Err(Error::Failed(msg)) => {
+            eprintln!("font-cli: {msg}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run() -> Result<(), Error> {
+    let mut args = Args::parse(std::env::args().skip(1))?;
+    let url = args
+        .take(&["url"])
+        .or_else(|| std::env::var("FONT_ENGINE_URL").ok())
+        .unwrap_or_else(|| "http://localhost:8082".to_string());
+    let mut client = FontCdnClient::new(url);
+    if let Some(key) = args.take(&["api-key"]).or_else(|| std::env::var("FONT_API_KEY").ok()) {
+        client = client.with_api_key(key);
+    }
+    if let Some(tenant) = args.take(&["tenant"]).or_else(|| std::env::var("FONT_TENANT").ok()) {
+        client = client.with_tenant(tenant);
+    }
+    if args.positional.is_empty() {
+        return Err(usage("missing command"));
+    }
+    let command = args.positional.remove(0);
+    match command.as_str() {
+        "upload" => upload(&client, args).await,
+        "subset" => subset(&client, args).await,
+        "compress" => compress(&client, args).await,
+        "catalog" => catalog(&client, args).await,
+        other => Err(usage(format!("unknown command '{other}'"))),
+    }
+}
+
+fn positional(args: &mut Args, what: &str) -> Result<String, Error> {
+    if args.positional.is_empty() {
+        return Err(usage(format!("missing {what}")));
+    }
+    Ok(args.positional.remove(0))
+}
+
+async fn upload(client: &FontCdnClient, mut args: Args) -> Result<(), Error> {
+    let file = PathBuf::from(positional(&mut args, "font file")?);
+    args.finish()?;
+    let data = tokio::fs::read(&file).await.map_err(|e| failed(format!("{}: {e}", file.display())))?;
+    let filename = file.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let font = client.upload(&filename, data).await?;
+    println!("{}", serde_json::to_string_pretty(&font).expect("upload record serializes"));
+    Ok(())
+}
+
+async fn subset(client: &FontCdnClient, mut args: Args) -> Result<(), Error> {
+    let font_name = positional(&mut args, "font name")?;
+    let characters = match (args.take(&["text"]), args.take(&["text-file"])) {
+        (Some(_), Some(_)) => return Err(usage("give --text or --text-file, not both")),
+        (Some(text), None) => text,
+        (None, Some(path)) => tokio::fs::read_to_string(&path).await.map_err(|e| failed(format!("{path}: {e}")))?,
+        (None, None) => String::new(),
+    };
+    let request = SubsetRequest {
+        font_name,
+        font_id: args.take(&["font-id"]),
+        characters,
+        preset: args.take(&["preset"]),
+        transform: TransformOptions {
+            format: args.take(&["format"]).ok_or_else(|| usage("--format is required"))?,
+            ..Default::default()
+        },
+        profile: args.take(&["profile"]),
+        ..Default::default()
+    };
+    if request.characters.is_empty() && request.preset.is_none() {
+        return Err(usage("give --text, --text-file or --preset"));
+    }
+    let out = PathBuf::from(args.take(&["o", "output"]).unwrap_or_else(|| ".".to_string()));
+    args.finish()?;
+
+    let response = client.subset(&request).await?;
+    let url = response.download_url.ok_or_else(|| failed("response has no download_url"))?;
+    let path = download(client, &url, &out).await?;
+    println!(
+        "{}: {} of {} glyphs, {:.1} KB -> {:.1} KB",
+        path.display(),
+        response.subset_glyph_count,
+        response.original_glyph_count,
+        response.original_size_kb,
+        response.subset_size_kb,
+    );
+    Ok(())
+}
+
+async fn compress(client: &FontCdnClient, mut args: Args) -> Result<(), Error> {
+    let quality = match args.take(&["quality"]) {
+        Some(q) => Some(q.parse().map_err(|_| usage(format!("--quality '{q}' is not 0-255")))?),
+        None => None,
+    };
+    let request = CompressRequest {
+        font_name: positional(&mut args, "font name")?,
+        font_id: args.take(&["font-id"]),
+        transform: TransformOptions {
+            format: args.take(&["format"]).ok_or_else(|| usage("--format is required"))?,
+            quality,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let out = PathBuf::from(args.take(&["o", "output"]).unwrap_or_else(|| ".".to_string()));
+    args.finish()?;
+
+    let response = client.compress(&request).await?;
+    let url = response.download_url.ok_or_else(|| failed("response has no download_url"))?;
+    let path = download(client, &url, &out).await?;
+    println!(
+        "{}: {:.1} KB -> {:.1} KB ({:.2}x)",
+        path.display(),
+        response.original_size_kb,
+        response.compressed_size_kb,
+        response.ratio,
+    );
+    Ok(())
+}
+
+async fn catalog(client: &FontCdnClient, mut args: Args) -> Result<(), Error> {
+    match positional(&mut args, "catalog subcommand")?.as_str() {
+        "list" => {}
+        other => return Err(usage(format!("unknown catalog subcommand '{other}'"))),
+    }
+    let mut query = Vec::new();
+    for name in ["family", "license", "format"] {
+        if let Some(value) = args.take(&[name]) {
+            query.push((name, value));
+        }
+    }
+    args.finish()?;
+
+    let mut page = 1;
+    loop {
+        let page_param = page.to_string();
+        let mut params: Vec<(&str, &str)> = query.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        params.push(("page", &page_param));
+        let listing = client.catalog(&params).await?;
+        for item in &listing.items {
+            let entry = &item.entry;
+            println!(
+                "{:<24} {:<32} {:<10} {:>8.1} KB  {}",
+                entry.id,
+                format!("{} {}", entry.family, entry.variant),
+                entry.license,
+                entry.size_kb,
+                entry.formats.join(","),
+            );
+        }
+        if page >= listing.pages {
+            return Ok(());
+        }
+        page += 1;
+    }
+}
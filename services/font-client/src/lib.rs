@@ -0,0 +1,624 @@
+//! Async client for the font engine's REST API.
+//!
+//! [`FontCdnClient`] has one method per endpoint and speaks the engine's own
+//! request and response types, re-exported from the `font-api` crate, so
+//! other Rust services don't hand-roll JSON against the API:
+//!
+//! ```no_run
+//! # async fn run() -> alice_font_client::Result<()> {
+//! use alice_font_client::{FontCdnClient, SubsetRequest};
+//!
+//! let client = FontCdnClient::new("http://localhost:8082").with_api_key("fk_...");
+//! let subset = client
+//!     .subset(&SubsetRequest {
+//!         font_name: "Inter".to_string(),
+//!         characters: "Hello".to_string(),
+//!         ..Default::default()
+//!     })
+//!     .await?;
+//! let woff2 = client.download(subset.download_url.as_deref().unwrap_or_default()).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Reports whose shape belongs to the engine's analysis modules (analyze,
+//! scan, validate, metrics and the like) and job records come back as
+//! [`serde_json::Value`]. Optional query parameters of an endpoint are passed
+//! as `params` pairs, named as in the README.
+
+use std::fmt;
+
+pub use font_api::*;
+use reqwest::{Method, RequestBuilder, Response};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+/// Query parameters, e.g. `&[("display", "optional")]`.
+pub type Params<'a> = &'a [(&'a str, &'a str)];
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request did not complete, or its body could not be decoded.
+    Http(reqwest::Error),
+    /// The engine answered with a non-success status.
+    Api { status: u16, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(e) => write!(f, "{e}"),
+            Self::Api { status, message } => write!(f, "{status}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Http(e) => Some(e),
+            Self::Api { .. } => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Http(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Percent-encodes one path segment.
+fn segment(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone)]
+pub struct FontCdnClient {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    tenant: Option<String>,
+    admin_token: Option<String>,
+}
+
+impl FontCdnClient {
+    /// A client for the engine (or gateway) at `base_url`.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: None,
+            tenant: None,
+            admin_token: None,
+        }
+    }
+
+    /// Uses `http`, e.g. one with timeouts or a proxy configured.
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Sent as `X-API-Key`.
+    pub fn with_api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    /// Sent as `X-Font-Tenant`; keys bound to a tenant override it.
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    /// Sent as `X-Admin-Token` for the catalog and admin endpoints.
+    pub fn with_admin_token(mut self, token: impl Into<String>) -> Self {
+        self.admin_token = Some(token.into());
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let url = if path.starts_with("http://") || path.starts_with("https://") {
+            path.to_string()
+        } else {
+            format!("{}{path}", self.base_url)
+        };
+        let mut builder = self.http.request(method, url);
+        if let Some(key) = &self.api_key {
+            builder = builder.header("x-api-key", key);
+        }
+        if let Some(tenant) = &self.tenant {
+            builder = builder.header("x-font-tenant", tenant);
+        }
+        if let Some(token) = &self.admin_token {
+            builder = builder.header("x-admin-token", token);
+        }
+        builder
+    }
+
+    async fn send(&self, builder: RequestBuilder) -> Result<Response> {
+        let response = builder.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let message = response.text().await.unwrap_or_default().trim().to_string();
+        Err(Error::Api { status: status.as_u16(), message })
+    }
+
+    async fn json<T: DeserializeOwned>(&self, builder: RequestBuilder) -> Result<T> {
+        Ok(self.send(builder).await?.json().await?)
+    }
+
+    async fn bytes(&self, builder: RequestBuilder) -> Result<Vec<u8>> {
+        Ok(self.send(builder).await?.bytes().await?.to_vec())
+    }
+
+    async fn text(&self, builder: RequestBuilder) -> Result<String> {
+        Ok(self.send(builder).await?.text().await?)
+    }
+
+    async fn empty(&self, builder: RequestBuilder) -> Result<()> {
+        self.send(builder).await.map(drop)
+    }
+
+    fn get(&self, path: &str) -> RequestBuilder {
+        self.request(Method::GET, path)
+    }
+
+    fn post<B: Serialize + ?Sized>(&self, path: &str, body: &B) -> RequestBuilder {
+        self.request(Method::POST, path).json(body)
+    }
+
+    fn put<B: Serialize + ?Sized>(&self, path: &str, body: &B) -> RequestBuilder {
+        self.request(Method::PUT, path).json(body)
+    }
+
+    fn delete(&self, path: &str) -> RequestBuilder {
+        self.request(Method::DELETE, path)
+    }
+
+    /// POSTs a font binary as the request body.
+    fn post_font(&self, path: &str, font: Vec<u8>) -> RequestBuilder {
+        self.request(Method::POST, path).header("content-type", "application/octet-stream").body(font)
+    }
+
+    // ── Service ────────────────────────────────────────────────────────────
+
+    pub async fn health(&self) -> Result<Value> {
+        self.json(self.get("/health")).await
+    }
+
+    pub async fn live(&self) -> Result<Value> {
+        self.json(self.get("/healthz/live")).await
+    }
+
+    /// Readiness; a not-ready engine answers `503` with its checks as the
+    /// error message.
+    pub async fn readyz(&self) -> Result<Value> {
+        self.json(self.get("/healthz/ready")).await
+    }
+
+    pub async fn build_info(&self) -> Result<Value> {
+        self.json(self.get("/debug/build")).await
+    }
+
+    /// Prometheus text exposition.
+    pub async fn metrics(&self) -> Result<String> {
+        self.text(self.get("/metrics")).await
+    }
+
+    pub async fn openapi(&self) -> Result<Value> {
+        self.json(self.get("/api/v1/openapi.json")).await
+    }
+
+    // ── Delivery ───────────────────────────────────────────────────────────
+
+    /// Fetches a generated font by its `download_url` (absolute, or a
+    /// `/cdn/fonts/...` path on this engine).
+    pub async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        self.bytes(self.get(url)).await
+    }
+
+    /// A subset of `family` for `text`, generated on the fly.
+    pub async fn slim(&self, family: &str, text: &str, format: &str) -> Result<Vec<u8>> {
+        let query = [("family", family), ("text", text), ("format", format)];
+        self.bytes(self.get("/api/v1/font/slim").query(&query)).await
+    }
+
+    /// `@font-face` rules for `family` (`Family` or `Family:400,700`, several
+    /// separated by `|`); `params` takes `weights`, `formats`, `split`,
+    /// `display`, `profile` and `channel`.
+    pub async fn css(&self, family: &str, params: Params<'_>) -> Result<String> {
+        self.text(self.get("/api/v1/font/css").query(&[("family", family)]).query(params)).await
+    }
+
+    /// `@font-face` rules for one family; `params` takes `split`, `display`,
+    /// `profile` and `channel`.
+    pub async fn family_css(&self, family: &str, params: Params<'_>) -> Result<String> {
+        self.text(self.get(&format!("/api/v1/font/css/{}", segment(family))).query(params)).await
+    }
+
+    /// Recommended preload hints; `params` takes `family`.
+    pub async fn hints(&self, params: Params<'_>) -> Result<Value> {
+        self.json(self.get("/api/v1/font/hints").query(params)).await
+    }
+
+    /// Signs the `download_url` of a private font.
+    pub async fn sign_url(&self, font_id: &str, req: &SignRequest) -> Result<SignedUrl> {
+        self.json(self.post(&format!("/api/v1/font/{}/sign-url", segment(font_id)), req)).await
+    }
+
+    // ── Fonts ──────────────────────────────────────────────────────────────
+
+    pub async fn compress(&self, req: &CompressRequest) -> Result<CompressResponse> {
+        self.json(self.post("/api/v1/font/compress", req)).await
+    }
+
+    pub async fn compress_batch(&self, reqs: &[CompressRequest]) -> Result<BatchResponse<CompressResponse>> {
+        self.json(self.post("/api/v1/font/compress/batch", reqs)).await
+    }
+
+    pub async fn subset(&self, req: &SubsetRequest) -> Result<SubsetResponse> {
+        self.json(self.post("/api/v1/font/subset", req)).await
+    }
+
+    pub async fn subset_batch(&self, reqs: &[SubsetRequest]) -> Result<BatchResponse<SubsetResponse>> {
+        self.json(self.post("/api/v1/font/subset/batch", reqs)).await
+    }
+
+    pub async fn subset_merged(&self, req: &MergedRequest) -> Result<Value> {
+        self.json(self.post("/api/v1/font/subset/merged", req)).await
+    }
+
+    pub async fn subset_progressive(&self, req: &ProgressiveRequest) -> Result<Value> {
+        self.json(self.post("/api/v1/font/subset/progressive", req)).await
+    }
+
+    pub async fn instances(&self, req: &InstancesRequest) -> Result<Value> {
+        self.json(self.post("/api/v1/font/instances", req)).await
+    }
+
+    pub async fn analyze(&self, req: &AnalyzeRequest) -> Result<Value> {
+        self.json(self.post("/api/v1/font/analyze", req)).await
+    }
+
+    pub async fn coverage(&self, req: &CoverageRequest) -> Result<Value> {
+        self.json(self.post("/api/v1/font/coverage", req)).await
+    }
+
+    /// Glyph, coverage, table and metric changes from `req.from` to `req.to`.
+    pub async fn diff(&self, req: &DiffRequest) -> Result<Value> {
+        self.json(self.post("/api/v1/font/diff", req)).await
+    }
+
+    /// Registering the result with `catalog_id` also needs [`Self::with_admin_token`].
+    pub async fn rename(&self, req: &RenameRequest) -> Result<RenameResponse> {
+        self.json(self.post("/api/v1/font/rename", req)).await
+    }
+
+    /// Overrides palette entries of a color font.
+    pub async fn recolor(&self, req: &RecolorRequest) -> Result<RecolorResponse> {
+        self.json(self.post("/api/v1/font/recolor", req)).await
+    }
+
+    /// Drops bitmaps outside `text`; `params` takes `drop_strikes` and the
+    /// `verify` options.
+    pub async fn prune_bitmaps(&self, font: Vec<u8>, text: &str, params: Params<'_>) -> Result<Vec<u8>> {
+        let builder = self.post_font("/api/v1/font/subset/bitmaps", font).query(&[("text", text)]).query(params);
+        self.bytes(builder).await
+    }
+
+    pub async fn check_metrics(&self, font: Vec<u8>) -> Result<Value> {
+        self.json(self.post_font("/api/v1/font/metrics", font)).await
+    }
+
+    /// Rewrites vertical metrics with `strategy`; `params` takes the
+    /// `verify` options.
+    pub async fn repair_metrics(&self, font: Vec<u8>, strategy: &str, params: Params<'_>) -> Result<Vec<u8>> {
+        let builder = self.post_font("/api/v1/font/metrics/repair", font).query(&[("strategy", strategy)]).query(params);
+        self.bytes(builder).await
+    }
+
+    /// Sets localized names; `params` takes `language`, `axis.<tag>` and
+    /// `instance.<name>`.
+    pub async fn localize_names(&self, font: Vec<u8>, params: Params<'_>) -> Result<Vec<u8>> {
+        self.bytes(self.post_font("/api/v1/font/localize-names", font).query(params)).await
+    }
+
+    pub async fn diacritics(&self, font: Vec<u8>, language: &str) -> Result<Value> {
+        self.json(self.post_font("/api/v1/font/diacritics", font).query(&[("language", language)])).await
+    }
+
+    pub async fn unicode_range(&self, font: Vec<u8>) -> Result<Value> {
+        self.json(self.post_font("/api/v1/font/unicode-range", font)).await
+    }
+
+    pub async fn cjk_widths(&self, font: Vec<u8>) -> Result<Value> {
+        self.json(self.post_font("/api/v1/font/cjk-widths", font)).await
+    }
+
+    /// `params` takes `text`.
+    pub async fn math(&self, font: Vec<u8>, params: Params<'_>) -> Result<Value> {
+        self.json(self.post_font("/api/v1/font/math", font).query(params)).await
+    }
+
+    /// `params` takes `features`, `text`, `size` and `format`.
+    pub async fn feature_demos(&self, font: Vec<u8>, params: Params<'_>) -> Result<Value> {
+        self.json(self.post_font("/api/v1/font/feature-demos", font).query(params)).await
+    }
+
+    /// `params` takes `size` and `format`.
+    pub async fn emoji_sprite(&self, font: Vec<u8>, emoji: &str, params: Params<'_>) -> Result<Value> {
+        self.json(self.post_font("/api/v1/font/emoji-sprite", font).query(&[("emoji", emoji)]).query(params)).await
+    }
+
+    /// Obfuscates `font` for `publication_id`; `params` takes `algorithm`.
+    pub async fn epub(&self, font: Vec<u8>, publication_id: &str, params: Params<'_>) -> Result<Vec<u8>> {
+        let builder = self.post_font("/api/v1/font/epub", font).query(&[("publication_id", publication_id)]);
+        self.bytes(builder.query(params)).await
+    }
+
+    // ── Jobs ───────────────────────────────────────────────────────────────
+
+    /// Queues a compress; the engine POSTs the finished job to
+    /// `callback_url` when given.
+    pub async fn queue_compress(&self, req: &CompressRequest, callback_url: Option<&str>) -> Result<Value> {
+        let query: Vec<_> = callback_url.map(|url| ("callback_url", url)).into_iter().collect();
+        self.json(self.post("/api/v1/jobs/compress", req).query(&query)).await
+    }
+
+    pub async fn queue_subset(&self, req: &SubsetRequest, callback_url: Option<&str>) -> Result<Value> {
+        let query: Vec<_> = callback_url.map(|url| ("callback_url", url)).into_iter().collect();
+        self.json(self.post("/api/v1/jobs/subset", req).query(&query)).await
+    }
+
+    pub async fn job(&self, id: &str) -> Result<Value> {
+        self.json(self.get(&format!("/api/v1/jobs/{}", segment(id)))).await
+    }
+
+    pub async fn cancel_job(&self, id: &str) -> Result<Value> {
+        self.json(self.delete(&format!("/api/v1/jobs/{}", segment(id)))).await
+    }
+
+    // ── Slices ─────────────────────────────────────────────────────────────
+
+    pub async fn create_slices(&self, req: &SliceRequest) -> Result<Manifest> {
+        self.json(self.post("/api/v1/font/slices", req)).await
+    }
+
+    pub async fn slices(&self, font_id: &str) -> Result<Manifest> {
+        self.json(self.get(&format!("/api/v1/font/slices/{}", segment(font_id)))).await
+    }
+
+    pub async fn delete_slices(&self, font_id: &str) -> Result<()> {
+        self.empty(self.delete(&format!("/api/v1/font/slices/{}", segment(font_id)))).await
+    }
+
+    // ── Subset profiles ────────────────────────────────────────────────────
+
+    /// The latest version of every profile.
+    pub async fn subset_profiles(&self) -> Result<Vec<SavedProfile>> {
+        self.json(self.get("/api/v1/font/subset-profiles")).await
+    }
+
+    /// Every version of `name`, oldest first.
+    pub async fn subset_profile(&self, name: &str) -> Result<Vec<SavedProfile>> {
+        self.json(self.get(&format!("/api/v1/font/subset-profiles/{}", segment(name)))).await
+    }
+
+    pub async fn save_subset_profile(&self, name: &str, spec: &SubsetSpec) -> Result<SavedProfile> {
+        self.json(self.put(&format!("/api/v1/font/subset-profiles/{}", segment(name)), spec)).await
+    }
+
+    pub async fn delete_subset_profile(&self, name: &str) -> Result<()> {
+        self.empty(self.delete(&format!("/api/v1/font/subset-profiles/{}", segment(name)))).await
+    }
+
+    // ── Catalog ────────────────────────────────────────────────────────────
+
+    /// One page of the catalog; `params` takes `family`, `license`,
+    /// `format`, `max_size_kb`, `channel`, `page` and `per_page`.
+    pub async fn catalog(&self, params: Params<'_>) -> Result<CatalogPage> {
+        self.json(self.get("/api/v1/font/catalog").query(params)).await
+    }
+
+    pub async fn create_catalog_entry(&self, entry: &FontCatalogEntry) -> Result<FontCatalogEntry> {
+        self.json(self.post(&format!("/api/v1/font/catalog/{}", segment(&entry.id)), entry)).await
+    }
+
+    pub async fn update_catalog_entry(&self, entry: &FontCatalogEntry) -> Result<FontCatalogEntry> {
+        self.json(self.put(&format!("/api/v1/font/catalog/{}", segment(&entry.id)), entry)).await
+    }
+
+    pub async fn retire_catalog_entry(&self, id: &str) -> Result<()> {
+        self.empty(self.delete(&format!("/api/v1/font/catalog/{}", segment(id)))).await
+    }
+
+    /// Makes the upload `req.font_id` the next version of entry `id`; needs
+    /// [`Self::with_admin_token`].
+    pub async fn add_font_version(&self, id: &str, req: &NewVersionRequest) -> Result<FontCatalogEntry> {
+        self.json(self.post(&format!("/api/v1/font/catalog/{}/versions", segment(id)), req)).await
+    }
+
+    pub async fn font_versions(&self, id: &str) -> Result<Vec<FontVersion>> {
+        self.json(self.get(&format!("/api/v1/font/catalog/{}/versions", segment(id)))).await
+    }
+
+    /// Runs a GraphQL query; the answer is `{"data": ...}`.
+    pub async fn graphql(&self, query: &str, variables: Option<&Value>) -> Result<Value> {
+        let body = serde_json::json!({ "query": query, "variables": variables });
+        self.json(self.post("/api/v1/graphql", &body)).await
+    }
+
+    /// The GraphQL schema, as SDL.
+    pub async fn graphql_schema(&self) -> Result<String> {
+        self.text(self.get("/api/v1/graphql")).await
+    }
+
+    // ── Analytics ──────────────────────────────────────────────────────────
+
+    /// Daily usage of a font; `params` takes `days`.
+    pub async fn font_analytics(&self, id: &str, params: Params<'_>) -> Result<Value> {
+        self.json(self.get(&format!("/api/v1/analytics/fonts/{}", segment(id))).query(params)).await
+    }
+
+    /// Fonts ranked by usage; `params` takes `by`, `limit` and `days`.
+    pub async fn top_fonts(&self, params: Params<'_>) -> Result<Value> {
+        self.json(self.get("/api/v1/analytics/top").query(params)).await
+    }
+
+    // ── Uploads ────────────────────────────────────────────────────────────
+
+    /// Uploads `font` as `filename`; the same file uploaded again returns
+    /// the existing record.
+    pub async fn upload(&self, filename: &str, font: Vec<u8>) -> Result<UploadedFont> {
+        const BOUNDARY: &str = "alice-font-client-8c1e5f27b4d9";
+        let filename = filename.replace(['"', '\r', '\n'], "");
+        let mut body = format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"font\"; filename=\"{filename}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend_from_slice(&font);
+        body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+        let builder = self
+            .request(Method::POST, "/api/v1/font/upload")
+            .header("content-type", format!("multipart/form-data; boundary={BOUNDARY}"))
+            .body(body);
+        self.json(builder).await
+    }
+
+    pub async fn uploaded_font(&self, id: &str) -> Result<UploadedFont> {
+        self.json(self.get(&format!("/api/v1/font/uploads/{}", segment(id)))).await
+    }
+
+    pub async fn delete_upload(&self, id: &str) -> Result<()> {
+        self.empty(self.delete(&format!("/api/v1/font/uploads/{}", segment(id)))).await
+    }
+
+    /// Faces of an uploaded TTC collection.
+    pub async fn collection_faces(&self, id: &str) -> Result<CollectionFaces> {
+        self.json(self.get(&format!("/api/v1/font/uploads/{}/faces", segment(id)))).await
+    }
+
+    /// Stores face `index` of collection `id` as an upload of its own.
+    pub async fn extract_face(&self, id: &str, index: usize) -> Result<UploadedFont> {
+        self.json(self.request(Method::POST, &format!("/api/v1/font/uploads/{}/faces/{index}", segment(id)))).await
+    }
+
+    /// Registers collection faces as catalog entries; needs [`Self::with_admin_token`].
+    pub async fn register_faces(&self, id: &str, req: &RegisterFacesRequest) -> Result<Vec<FontCatalogEntry>> {
+        self.json(self.post(&format!("/api/v1/font/uploads/{}/faces", segment(id)), req)).await
+    }
+
+    pub async fn scan(&self, font: Vec<u8>) -> Result<Value> {
+        self.json(self.post_font("/api/v1/font/scan", font)).await
+    }
+
+    pub async fn validate(&self, font: Vec<u8>) -> Result<Value> {
+        self.json(self.post_font("/api/v1/font/validate", font)).await
+    }
+
+    // ── Admin ──────────────────────────────────────────────────────────────
+
+    pub async fn find_duplicates(&self, threshold: Option<f64>) -> Result<Value> {
+        let query: Vec<_> = threshold.map(|t| ("threshold", t)).into_iter().collect();
+        self.json(self.request(Method::POST, "/api/v1/admin/duplicates").query(&query)).await
+    }
+
+    pub async fn backup(&self) -> Result<Snapshot> {
+        self.json(self.get("/api/v1/admin/backup")).await
+    }
+
+    /// Restores `snapshot`, or only checks it with `verify_only`.
+    pub async fn restore(&self, snapshot: &Snapshot, verify_only: bool) -> Result<Value> {
+        let builder = self.post("/api/v1/admin/restore", snapshot).query(&[("verify_only", verify_only)]);
+        self.json(builder).await
+    }
+
+    pub async fn cors_origins(&self) -> Result<OriginSet> {
+        self.json(self.get("/api/v1/admin/cors")).await
+    }
+
+    pub async fn set_cors_origins(&self, update: &OriginUpdate) -> Result<OriginSet> {
+        self.json(self.put("/api/v1/admin/cors", update)).await
+    }
+
+    pub async fn flags(&self) -> Result<FlagSet> {
+        self.json(self.get("/api/v1/admin/flags")).await
+    }
+
+    pub async fn set_flag(&self, capability: &str, update: &FlagUpdate) -> Result<FlagSet> {
+        self.json(self.put(&format!("/api/v1/admin/flags/{}", segment(capability)), update)).await
+    }
+
+    pub async fn hint_lists(&self) -> Result<HintSet> {
+        self.json(self.get("/api/v1/admin/hints")).await
+    }
+
+    pub async fn set_hints(&self, update: &HintUpdate) -> Result<HintSet> {
+        self.json(self.put("/api/v1/admin/hints", update)).await
+    }
+
+    pub async fn api_keys(&self) -> Result<Vec<ApiKey>> {
+        self.json(self.get("/api/v1/admin/keys")).await
+    }
+
+    pub async fn create_api_key(&self, key: &NewKey) -> Result<CreatedKey> {
+        self.json(self.post("/api/v1/admin/keys", key)).await
+    }
+
+    pub async fn revoke_api_key(&self, id: &str) -> Result<()> {
+        self.empty(self.delete(&format!("/api/v1/admin/keys/{}", segment(id)))).await
+    }
+
+    pub async fn quarantine(&self) -> Result<Value> {
+        self.json(self.get("/api/v1/admin/quarantine")).await
+    }
+
+    pub async fn quarantined(&self, id: &str) -> Result<Value> {
+        self.json(self.get(&format!("/api/v1/admin/quarantine/{}", segment(id)))).await
+    }
+
+    pub async fn discard_quarantined(&self, id: &str) -> Result<()> {
+        self.empty(self.delete(&format!("/api/v1/admin/quarantine/{}", segment(id)))).await
+    }
+
+    pub async fn retry_quarantined(&self, id: &str) -> Result<Value> {
+        self.json(self.request(Method::POST, &format!("/api/v1/admin/quarantine/{}/retry", segment(id)))).await
+    }
+
+    pub async fn staged(&self) -> Result<Vec<FontCatalogEntry>> {
+        self.json(self.get("/api/v1/admin/staging")).await
+    }
+
+    /// Stages `entry`; `params` takes `resolution` and `rename_to` for
+    /// family-name collisions.
+    pub async fn stage(&self, entry: &FontCatalogEntry, params: Params<'_>) -> Result<FontCatalogEntry> {
+        self.json(self.put(&format!("/api/v1/admin/staging/{}", segment(&entry.id)), entry).query(params)).await
+    }
+
+    pub async fn discard_staged(&self, id: &str) -> Result<()> {
+        self.empty(self.delete(&format!("/api/v1/admin/staging/{}", segment(id)))).await
+    }
+
+    pub async fn promote_staged(&self) -> Result<Value> {
+        self.json(self.request(Method::POST, "/api/v1/admin/staging/promote")).await
+    }
+}